@@ -0,0 +1,86 @@
+// Clustered forward lights, blocks must match lights::LightClusters.
+// The Light struct and SPOT_LIGHT must be declared before including, and the program needs
+// GL_ARB_shader_storage_buffer_object.
+
+// cluster light count shown as the hottest color of the heat map
+#define CLUSTER_HEATMAP_MAX 16.0
+
+layout (std140) uniform Clusters {
+    vec4 ClusterDepthRow;
+    vec2 ClusterViewport;
+    float ClusterNear;
+    float ClusterFar;
+    int ClusterTilesX;
+    int ClusterTilesY;
+    int ClusterSlices;
+    int ClustersEnabled;
+};
+
+// point and spot lights, referenced by the cluster indices
+layout (std430) readonly buffer ClusterLights {
+    Light clusterLights[];
+};
+
+// first index and light count of each cluster
+layout (std430) readonly buffer ClusterGrid {
+    uvec2 clusterGrid[];
+};
+
+layout (std430) readonly buffer ClusterIndices {
+    uint clusterIndices[];
+};
+
+// first index and light count of the cluster containing the fragment
+uvec2 FragmentCluster(vec3 worldPosition)
+{
+    ivec2 tile = ivec2(gl_FragCoord.xy / ClusterViewport * vec2(ClusterTilesX, ClusterTilesY));
+    tile = clamp(tile, ivec2(0), ivec2(ClusterTilesX, ClusterTilesY) - 1);
+
+    // depth slices grow exponentially from ClusterNear, the first one starts at the camera
+    float depth = dot(ClusterDepthRow, vec4(worldPosition, 1.0));
+    float slice = log(max(depth, ClusterNear) / ClusterNear) / log(ClusterFar / ClusterNear);
+    int z = clamp(int(slice * float(ClusterSlices)), 0, ClusterSlices - 1);
+
+    return clusterGrid[(z * ClusterTilesY + tile.y) * ClusterTilesX + tile.x];
+}
+
+Light ClusterLight(uvec2 cluster, uint i)
+{
+    return clusterLights[clusterIndices[cluster.x + i]];
+}
+
+// light color of a point or spot light reaching the position, and direction towards it in L
+vec3 ClusterLightRadiance(Light light, vec3 position, out vec3 L)
+{
+    vec3 toLight = light.PositionKind.xyz - position;
+    float distance = length(toLight);
+    L = toLight / max(distance, 0.0001);
+    vec3 radiance = light.ColorIntensity.rgb * light.ColorIntensity.a;
+    radiance *= 1.0 - smoothstep(0.0, light.DirectionRadius.w, distance);
+    if (int(light.PositionKind.w) == SPOT_LIGHT) {
+        radiance *= smoothstep(light.Params.y, light.Params.x, dot(-L, light.DirectionRadius.xyz));
+    }
+    return radiance;
+}
+
+// diffuse light of the cluster containing the fragment, for materials without PBR
+vec3 ClusterDiffuse(vec3 worldPosition, vec3 normal)
+{
+    uvec2 cluster = FragmentCluster(worldPosition);
+    vec3 diffuse = vec3(0.0);
+    for (uint i = 0u; i < cluster.y; i++) {
+        vec3 L;
+        vec3 radiance = ClusterLightRadiance(ClusterLight(cluster, i), worldPosition, L);
+        diffuse += radiance * max(dot(normal, L), 0.0);
+    }
+    return diffuse;
+}
+
+// blue for few lights over green and yellow to red at CLUSTER_HEATMAP_MAX
+vec3 ClusterHeat(uint lightCount)
+{
+    float t = clamp(float(lightCount) / CLUSTER_HEATMAP_MAX, 0.0, 1.0);
+    vec3 cold = mix(vec3(0.0, 0.0, 0.3), vec3(0.0, 0.8, 0.2), clamp(t * 2.0, 0.0, 1.0));
+    vec3 hot = mix(vec3(1.0, 0.9, 0.0), vec3(1.0, 0.0, 0.0), clamp(t * 2.0 - 1.0, 0.0, 1.0));
+    return lightCount == 0u ? vec3(0.0) : (t < 0.5 ? cold : hot);
+}
\ No newline at end of file
@@ -0,0 +1,38 @@
+// Distance fog shared by the scene shaders, blocks must match render::FogUniforms.
+// CameraPos of the Camera block must be declared before including.
+
+#define FOG_OFF 0
+#define FOG_LINEAR 1
+#define FOG_EXPONENTIAL 2
+#define FOG_EXPONENTIAL_SQUARED 3
+
+layout (std140) uniform Fog {
+    vec3 FogColor;
+    int FogMode;
+    // used by the exponential modes
+    float FogDensity;
+    // distances where linear fog starts and becomes opaque
+    float FogStart;
+    float FogEnd;
+};
+
+// fraction of the color replaced by fog at the distance from the camera
+float FogAmount(float distance)
+{
+    if (FogMode == FOG_LINEAR) {
+        return clamp((distance - FogStart) / max(FogEnd - FogStart, 0.0001), 0.0, 1.0);
+    }
+    if (FogMode == FOG_EXPONENTIAL) {
+        return 1.0 - exp(-FogDensity * distance);
+    }
+    if (FogMode == FOG_EXPONENTIAL_SQUARED) {
+        float d = FogDensity * distance;
+        return 1.0 - exp(-d * d);
+    }
+    return 0.0;
+}
+
+vec3 ApplyFog(vec3 color, vec3 worldPosition)
+{
+    return mix(color, FogColor, FogAmount(distance(CameraPos, worldPosition)));
+}
\ No newline at end of file
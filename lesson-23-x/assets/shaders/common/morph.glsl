@@ -0,0 +1,27 @@
+// Morph target blending shared by vertex shaders, data set by render_gl::MorphTargets.
+// Without MORPH_TARGETS positions and normals are left as they are.
+
+#define MAX_MORPH_TARGETS 8
+
+#ifdef MORPH_TARGETS
+// position delta followed by normal delta of each vertex, target after target
+uniform samplerBuffer MorphDeltas;
+uniform int MorphVertexCount;
+uniform int MorphTargetCount;
+uniform float MorphWeights[MAX_MORPH_TARGETS];
+#endif
+
+void ApplyMorphTargets(inout vec3 position, inout vec3 normal)
+{
+#ifdef MORPH_TARGETS
+    for (int target = 0; target < MorphTargetCount; target++) {
+        float weight = MorphWeights[target];
+        if (weight == 0.0) {
+            continue;
+        }
+        int texel = (target * MorphVertexCount + gl_VertexID) * 2;
+        position += weight * texelFetch(MorphDeltas, texel).xyz;
+        normal += weight * texelFetch(MorphDeltas, texel + 1).xyz;
+    }
+#endif
+}
\ No newline at end of file
@@ -0,0 +1,74 @@
+// Cascaded shadow lookups shared by the forward shaders.
+// Define SHADOW_BIAS before including to change the depth bias of the nearest cascade.
+
+#ifndef SHADOW_BIAS
+#define SHADOW_BIAS 0.001
+#endif
+
+// shadow cascades, nearest first, must match render_gl::MAX_SHADOW_CASCADES
+uniform sampler2DArray ShadowMap;
+uniform mat4 LightSpace[4];
+uniform int CascadeCount;
+uniform bool ShowCascades;
+
+// fraction of a cascade near its edge blended into the next one
+const float CascadeBlend = 0.1;
+
+vec3 CascadeCoords(int cascade, vec3 worldPosition)
+{
+    vec4 lightSpacePosition = LightSpace[cascade] * vec4(worldPosition, 1.0);
+    return lightSpacePosition.xyz / lightSpacePosition.w * 0.5 + 0.5;
+}
+
+// distance to the nearest edge in texture coordinates, negative outside the cascade
+float CascadeInside(vec3 coords)
+{
+    vec2 edge = min(coords.xy, 1.0 - coords.xy);
+    return coords.z > 1.0 ? -1.0 : min(edge.x, edge.y);
+}
+
+float CascadeLit(int cascade, vec3 coords)
+{
+    float closestDepth = texture(ShadowMap, vec3(coords.xy, float(cascade))).r;
+    return coords.z - SHADOW_BIAS * float(cascade + 1) > closestDepth ? 0.0 : 1.0;
+}
+
+float Lit(vec3 worldPosition)
+{
+    for (int i = 0; i < CascadeCount; i++) {
+        vec3 coords = CascadeCoords(i, worldPosition);
+        float inside = CascadeInside(coords);
+        if (inside < 0.0) {
+            continue;
+        }
+        float lit = CascadeLit(i, coords);
+        if (inside < CascadeBlend && i + 1 < CascadeCount) {
+            vec3 nextCoords = CascadeCoords(i + 1, worldPosition);
+            if (CascadeInside(nextCoords) >= 0.0) {
+                lit = mix(CascadeLit(i + 1, nextCoords), lit, inside / CascadeBlend);
+            }
+        }
+        return lit;
+    }
+    return 1.0;
+}
+
+// color of the cascade used for the position, white when cascades are not shown
+vec3 CascadeTint(vec3 worldPosition)
+{
+    if (!ShowCascades) {
+        return vec3(1.0);
+    }
+    vec3 tints[4] = vec3[4](
+        vec3(1.0, 0.5, 0.5),
+        vec3(0.5, 1.0, 0.5),
+        vec3(0.5, 0.5, 1.0),
+        vec3(1.0, 1.0, 0.5)
+    );
+    for (int i = 0; i < CascadeCount; i++) {
+        if (CascadeInside(CascadeCoords(i, worldPosition)) >= 0.0) {
+            return tints[i];
+        }
+    }
+    return vec3(1.0);
+}
\ No newline at end of file
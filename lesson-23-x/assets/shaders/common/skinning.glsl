@@ -0,0 +1,30 @@
+// Linear blend skinning shared by vertex shaders, bone matrices set by render::BoneUniforms.
+// Without SKINNED, and for vertices without weights, the vertex is left as it is.
+
+#define MAX_JOINTS 64
+
+#ifdef SKINNED
+layout (location = 11) in uvec4 BoneJoints;
+layout (location = 12) in vec4 BoneWeights;
+
+layout (std140) uniform Bones {
+    mat4 BoneMatrices[MAX_JOINTS];
+};
+#endif
+
+void ApplySkinning(inout vec3 position, inout vec3 normal, inout vec3 tangent)
+{
+#ifdef SKINNED
+    if (dot(BoneWeights, vec4(1.0)) <= 0.0) {
+        return;
+    }
+    mat4 Skin = BoneWeights.x * BoneMatrices[BoneJoints.x]
+        + BoneWeights.y * BoneMatrices[BoneJoints.y]
+        + BoneWeights.z * BoneMatrices[BoneJoints.z]
+        + BoneWeights.w * BoneMatrices[BoneJoints.w];
+    position = vec3(Skin * vec4(position, 1.0));
+    // joints are expected to scale uniformly, so directions move like positions
+    normal = mat3(Skin) * normal;
+    tangent = mat3(Skin) * tangent;
+#endif
+}
\ No newline at end of file
@@ -0,0 +1,19 @@
+#version 330 core
+
+uniform sampler2D Texture;
+uniform vec4 Color;
+
+in VS_OUTPUT {
+    vec2 Uv;
+} IN;
+
+out vec4 OutColor;
+
+void main()
+{
+    vec4 color = texture(Texture, IN.Uv) * Color;
+    if (color.a < 0.01) {
+        discard;
+    }
+    OutColor = color;
+}
\ No newline at end of file
@@ -0,0 +1,23 @@
+#version 330 core
+
+uniform sampler2D Texture;
+uniform sampler2D Normals;
+
+in VS_OUTPUT {
+    vec2 Uv;
+    vec3 WorldPosition;
+    mat3 TBN;
+} IN;
+
+layout (location = 0) out vec4 Albedo;
+layout (location = 1) out vec4 Normal;
+layout (location = 2) out vec4 Position;
+
+void main()
+{
+    vec3 normal = texture(Normals, IN.Uv).rgb * 2.0 - 1.0;
+
+    Albedo = vec4(texture(Texture, IN.Uv).rgb, 1.0);
+    Normal = vec4(normalize(IN.TBN * normal), 0.0);
+    Position = vec4(IN.WorldPosition, 1.0);
+}
\ No newline at end of file
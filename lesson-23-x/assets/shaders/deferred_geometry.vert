@@ -0,0 +1,46 @@
+#version 330 core
+
+layout (location = 0) in vec3 Position;
+layout (location = 1) in vec2 Uv;
+layout (location = 2) in vec3 T;
+layout (location = 3) in vec3 N;
+
+layout (std140) uniform Camera {
+    mat4 ViewProjection;
+    vec3 CameraPos;
+};
+
+uniform mat4 Model;
+
+#include "common/morph.glsl"
+#include "common/skinning.glsl"
+
+out VS_OUTPUT {
+    vec2 Uv;
+    vec3 WorldPosition;
+    mat3 TBN;
+} OUT;
+
+void main()
+{
+    vec3 DeformedPosition = Position;
+    vec3 DeformedN = N;
+    vec3 DeformedT = T;
+    ApplyMorphTargets(DeformedPosition, DeformedN);
+    ApplySkinning(DeformedPosition, DeformedN, DeformedT);
+
+    vec3 WorldPosition = vec3(Model * vec4(DeformedPosition, 1.0));
+    gl_Position = ViewProjection * vec4(WorldPosition, 1.0);
+
+    OUT.Uv = Uv;
+    OUT.WorldPosition = WorldPosition;
+
+    mat3 IntoModelMatrix = transpose(inverse(mat3(Model)));
+
+    vec3 ModelT = normalize(IntoModelMatrix * DeformedT);
+    vec3 ModelN = normalize(IntoModelMatrix * DeformedN);
+    ModelT = normalize(ModelT - dot(ModelT, ModelN) * ModelN);
+    vec3 ModelB = cross(ModelN, ModelT);
+
+    OUT.TBN = mat3(ModelT, ModelB, ModelN);
+}
\ No newline at end of file
@@ -0,0 +1,36 @@
+#version 330 core
+
+layout (location = 0) in vec3 Position;
+layout (location = 1) in vec2 Uv;
+layout (location = 2) in vec3 T;
+layout (location = 3) in vec3 N;
+layout (location = 4) in mat4 InstanceModel;
+
+layout (std140) uniform Camera {
+    mat4 ViewProjection;
+    vec3 CameraPos;
+};
+
+out VS_OUTPUT {
+    vec2 Uv;
+    vec3 WorldPosition;
+    mat3 TBN;
+} OUT;
+
+void main()
+{
+    vec3 WorldPosition = vec3(InstanceModel * vec4(Position, 1.0));
+    gl_Position = ViewProjection * vec4(WorldPosition, 1.0);
+
+    OUT.Uv = Uv;
+    OUT.WorldPosition = WorldPosition;
+
+    mat3 IntoModelMatrix = transpose(inverse(mat3(InstanceModel)));
+
+    vec3 ModelT = normalize(IntoModelMatrix * T);
+    vec3 ModelN = normalize(IntoModelMatrix * N);
+    ModelT = normalize(ModelT - dot(ModelT, ModelN) * ModelN);
+    vec3 ModelB = cross(ModelN, ModelT);
+
+    OUT.TBN = mat3(ModelT, ModelB, ModelN);
+}
\ No newline at end of file
@@ -0,0 +1,53 @@
+#version 330 core
+#ifdef CLUSTERED
+#extension GL_ARB_shader_storage_buffer_object : require
+#endif
+
+layout (std140) uniform Camera {
+    mat4 ViewProjection;
+    vec3 CameraPos;
+    int DebugView;
+    vec4 ClipPlane;
+};
+
+uniform vec3 Color;
+
+in VS_OUTPUT {
+    vec3 WorldPosition;
+} IN;
+
+out vec4 OutColor;
+
+#include "common/shadows.glsl"
+#include "common/fog.glsl"
+#ifdef CLUSTERED
+#define SPOT_LIGHT 2
+
+// must match lights::LightUniform
+struct Light {
+    vec4 PositionKind;
+    vec4 DirectionRadius;
+    vec4 ColorIntensity;
+    vec4 Params;
+};
+
+#include "common/clusters.glsl"
+#endif
+
+void main()
+{
+    float lit = Lit(IN.WorldPosition);
+    vec3 color = Color * CascadeTint(IN.WorldPosition) * (0.4 + 0.6 * lit);
+#ifdef CLUSTERED
+    if (ClustersEnabled != 0) {
+        if (DebugView == 6) {
+            OutColor = vec4(ClusterHeat(FragmentCluster(IN.WorldPosition).y), 1.0);
+            return;
+        }
+        // the floor is flat, its normal faces the camera
+        vec3 normal = normalize(cross(dFdx(IN.WorldPosition), dFdy(IN.WorldPosition)));
+        color += Color * ClusterDiffuse(IN.WorldPosition, normal);
+    }
+#endif
+    OutColor = vec4(ApplyFog(color, IN.WorldPosition), 1.0);
+}
\ No newline at end of file
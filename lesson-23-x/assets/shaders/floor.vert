@@ -0,0 +1,24 @@
+#version 330 core
+
+layout (location = 0) in vec3 Position;
+
+layout (std140) uniform Camera {
+    mat4 ViewProjection;
+    vec3 CameraPos;
+    int DebugView;
+    vec4 ClipPlane;
+};
+
+uniform mat4 Model;
+
+out VS_OUTPUT {
+    vec3 WorldPosition;
+} OUT;
+
+void main()
+{
+    vec4 WorldPosition = Model * vec4(Position, 1.0);
+    gl_Position = ViewProjection * WorldPosition;
+    gl_ClipDistance[0] = dot(WorldPosition, ClipPlane);
+    OUT.WorldPosition = WorldPosition.xyz;
+}
\ No newline at end of file
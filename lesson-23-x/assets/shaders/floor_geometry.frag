@@ -0,0 +1,19 @@
+#version 330 core
+
+uniform vec3 Color;
+
+in VS_OUTPUT {
+    vec3 WorldPosition;
+    vec3 Normal;
+} IN;
+
+layout (location = 0) out vec4 Albedo;
+layout (location = 1) out vec4 Normal;
+layout (location = 2) out vec4 Position;
+
+void main()
+{
+    Albedo = vec4(Color, 1.0);
+    Normal = vec4(IN.Normal, 0.0);
+    Position = vec4(IN.WorldPosition, 1.0);
+}
\ No newline at end of file
@@ -0,0 +1,23 @@
+#version 330 core
+
+layout (location = 0) in vec3 Position;
+
+layout (std140) uniform Camera {
+    mat4 ViewProjection;
+    vec3 CameraPos;
+};
+
+uniform mat4 Model;
+
+out VS_OUTPUT {
+    vec3 WorldPosition;
+    vec3 Normal;
+} OUT;
+
+void main()
+{
+    vec4 WorldPosition = Model * vec4(Position, 1.0);
+    gl_Position = ViewProjection * WorldPosition;
+    OUT.WorldPosition = WorldPosition.xyz;
+    OUT.Normal = normalize(mat3(Model) * vec3(0.0, 0.0, 1.0));
+}
\ No newline at end of file
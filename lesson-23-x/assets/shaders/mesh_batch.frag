@@ -0,0 +1,37 @@
+#version 430 core
+
+layout (std140) uniform Camera {
+    mat4 ViewProjection;
+    vec3 CameraPos;
+    int DebugView;
+    vec4 ClipPlane;
+};
+
+// direction the sun light travels in
+uniform vec3 LightDirection;
+
+// color of each material, indexed by the draw
+layout (std430) readonly buffer Materials {
+    vec4 colors[];
+};
+
+in VS_OUTPUT {
+    vec3 WorldPosition;
+    vec3 Normal;
+    flat uint Material;
+} IN;
+
+out vec4 OutColor;
+
+#define SHADOW_BIAS 0.002
+#include "common/shadows.glsl"
+#include "common/fog.glsl"
+
+void main()
+{
+    vec3 color = colors[IN.Material].rgb;
+    float diffuse = max(dot(normalize(IN.Normal), -LightDirection), 0.0);
+    float lit = Lit(IN.WorldPosition);
+    color *= CascadeTint(IN.WorldPosition);
+    OutColor = vec4(ApplyFog(color * (0.3 + 0.7 * diffuse * lit), IN.WorldPosition), 1.0);
+}
\ No newline at end of file
@@ -0,0 +1,39 @@
+#version 430 core
+#extension GL_ARB_shader_draw_parameters : require
+
+layout (location = 0) in vec3 Position;
+layout (location = 1) in vec3 Normal;
+
+layout (std140) uniform Camera {
+    mat4 ViewProjection;
+    vec3 CameraPos;
+    int DebugView;
+    vec4 ClipPlane;
+};
+
+// one element per indirect draw command, must match entity::MeshBatch
+struct Draw {
+    mat4 Model;
+    uint Material;
+};
+
+layout (std430) readonly buffer Draws {
+    Draw draws[];
+};
+
+out VS_OUTPUT {
+    vec3 WorldPosition;
+    vec3 Normal;
+    flat uint Material;
+} OUT;
+
+void main()
+{
+    Draw draw = draws[gl_DrawIDARB];
+    vec4 WorldPosition = draw.Model * vec4(Position, 1.0);
+    gl_Position = ViewProjection * WorldPosition;
+    gl_ClipDistance[0] = dot(WorldPosition, ClipPlane);
+    OUT.WorldPosition = WorldPosition.xyz;
+    OUT.Normal = normalize(transpose(inverse(mat3(draw.Model))) * Normal);
+    OUT.Material = draw.Material;
+}
\ No newline at end of file
@@ -0,0 +1,21 @@
+#version 430 core
+#extension GL_ARB_shader_draw_parameters : require
+
+layout (location = 0) in vec3 Position;
+
+uniform mat4 LightSpace;
+
+// one element per indirect draw command, must match entity::MeshBatch
+struct Draw {
+    mat4 Model;
+    uint Material;
+};
+
+layout (std430) readonly buffer Draws {
+    Draw draws[];
+};
+
+void main()
+{
+    gl_Position = LightSpace * draws[gl_DrawIDARB].Model * vec4(Position, 1.0);
+}
\ No newline at end of file
@@ -0,0 +1,39 @@
+#version 330 core
+
+uniform vec4 Color;
+uniform sampler2D SceneDepth;
+// projection matrix elements [2][2] and [3][2], to linearize depth
+uniform vec2 Projection;
+uniform vec2 ViewportSize;
+// distance over which particles fade out in front of geometry, 0 disables fading
+uniform float SoftDistance;
+
+in VS_OUTPUT {
+    vec2 Uv;
+    float Life;
+} IN;
+
+out vec4 OutColor;
+
+float LinearDepth(float depth)
+{
+    return Projection.y / (depth * 2.0 - 1.0 + Projection.x);
+}
+
+void main()
+{
+    float alpha = Color.a * smoothstep(0.5, 0.2, length(IN.Uv - vec2(0.5)));
+    alpha *= 1.0 - IN.Life;
+
+    if (SoftDistance > 0.0) {
+        float sceneDepth = texture(SceneDepth, gl_FragCoord.xy / ViewportSize).r;
+        float distance = LinearDepth(sceneDepth) - LinearDepth(gl_FragCoord.z);
+        alpha *= clamp(distance / SoftDistance, 0.0, 1.0);
+    }
+
+    if (alpha <= 0.0) {
+        discard;
+    }
+
+    OutColor = vec4(Color.rgb, alpha);
+}
\ No newline at end of file
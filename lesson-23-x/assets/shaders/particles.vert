@@ -0,0 +1,42 @@
+#version 330 core
+
+layout (std140) uniform Camera {
+    mat4 ViewProjection;
+    vec3 CameraPos;
+};
+
+layout (location = 0) in vec4 PositionAge;
+layout (location = 1) in vec4 VelocityLifetime;
+
+uniform float Size;
+
+out VS_OUTPUT {
+    vec2 Uv;
+    float Life;
+} OUT;
+
+void main()
+{
+    float life = PositionAge.w / max(VelocityLifetime.w, 0.0001);
+    if (life >= 1.0) {
+        // dead particle, place the quad outside of clip space
+        gl_Position = vec4(0.0, 0.0, -2.0, 1.0);
+        OUT.Uv = vec2(0.0);
+        OUT.Life = 1.0;
+        return;
+    }
+
+    // quad corners of the triangle strip come from vertex id
+    vec2 corner = vec2(gl_VertexID & 1, (gl_VertexID >> 1) & 1);
+
+    vec3 toCamera = normalize(CameraPos - PositionAge.xyz);
+    vec3 helper = abs(toCamera.z) < 0.99 ? vec3(0.0, 0.0, 1.0) : vec3(0.0, 1.0, 0.0);
+    vec3 right = normalize(cross(helper, toCamera));
+    vec3 up = cross(toCamera, right);
+
+    vec3 position = PositionAge.xyz + (right * (corner.x - 0.5) + up * (corner.y - 0.5)) * Size;
+
+    OUT.Uv = corner;
+    OUT.Life = life;
+    gl_Position = ViewProjection * vec4(position, 1.0);
+}
\ No newline at end of file
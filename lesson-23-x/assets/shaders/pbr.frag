@@ -0,0 +1,208 @@
+#version 330 core
+#ifdef CLUSTERED
+#extension GL_ARB_shader_storage_buffer_object : require
+#endif
+
+#define PI 3.1415926535897932384626433832795
+
+layout (std140) uniform Camera {
+    mat4 ViewProjection;
+    vec3 CameraPos;
+    int DebugView;
+    vec4 ClipPlane;
+};
+
+uniform sampler2D AlbedoMap;
+uniform sampler2D NormalMap;
+uniform sampler2D MetallicMap;
+uniform sampler2D RoughnessMap;
+uniform sampler2D OcclusionMap;
+
+uniform samplerCube IrradianceMap;
+uniform samplerCube SpecularMap;
+uniform sampler2D BrdfLut;
+uniform float SpecularMaxLod;
+
+uniform vec3 AlbedoFactor;
+uniform float MetallicFactor;
+uniform float RoughnessFactor;
+
+#define MAX_LIGHTS 32
+#define DIRECTIONAL_LIGHT 0
+#define POINT_LIGHT 1
+#define SPOT_LIGHT 2
+
+// must match lights::LightUniform
+struct Light {
+    vec4 PositionKind;
+    vec4 DirectionRadius;
+    vec4 ColorIntensity;
+    vec4 Params;
+};
+
+layout (std140) uniform Lights {
+    int LightCount;
+    Light AllLights[MAX_LIGHTS];
+};
+
+in VS_OUTPUT {
+    vec2 Uv;
+    vec3 WorldPosition;
+    mat3 TBN;
+} IN;
+
+out vec4 Color;
+
+#include "common/shadows.glsl"
+#include "common/fog.glsl"
+#ifdef CLUSTERED
+#include "common/clusters.glsl"
+#endif
+
+float DistributionGgx(float NdotH, float Roughness)
+{
+    float a = Roughness * Roughness;
+    float a2 = a * a;
+    float Denominator = NdotH * NdotH * (a2 - 1.0) + 1.0;
+    return a2 / (PI * Denominator * Denominator);
+}
+
+float GeometrySchlickGgx(float NdotV, float Roughness)
+{
+    // k for direct lighting
+    float r = Roughness + 1.0;
+    float k = (r * r) / 8.0;
+    return NdotV / (NdotV * (1.0 - k) + k);
+}
+
+vec3 FresnelSchlick(float CosTheta, vec3 F0)
+{
+    return F0 + (1.0 - F0) * pow(1.0 - CosTheta, 5.0);
+}
+
+vec3 FresnelSchlickRoughness(float CosTheta, vec3 F0, float Roughness)
+{
+    return F0 + (max(vec3(1.0 - Roughness), F0) - F0) * pow(1.0 - CosTheta, 5.0);
+}
+
+// light color reaching the position, and direction towards the light in L
+vec3 LightRadiance(Light light, vec3 position, out vec3 L)
+{
+    int kind = int(light.PositionKind.w);
+    vec3 radiance = light.ColorIntensity.rgb * light.ColorIntensity.a;
+
+    if (kind == DIRECTIONAL_LIGHT) {
+        L = -light.DirectionRadius.xyz;
+        return radiance;
+    }
+
+    vec3 toLight = light.PositionKind.xyz - position;
+    float distance = length(toLight);
+    L = toLight / max(distance, 0.0001);
+    radiance *= 1.0 - smoothstep(0.0, light.DirectionRadius.w, distance);
+
+    if (kind == SPOT_LIGHT) {
+        float cosAngle = dot(-L, light.DirectionRadius.xyz);
+        radiance *= smoothstep(light.Params.y, light.Params.x, cosAngle);
+    }
+    return radiance;
+}
+
+// reflected light of one light, only directional lights use the shadow map
+vec3 DirectLight(
+    Light light, vec3 N, vec3 V, vec3 Albedo, float Metallic, float Roughness, vec3 F0)
+{
+    vec3 L;
+    vec3 Radiance = LightRadiance(light, IN.WorldPosition, L);
+    if (light.Params.z > 0.5 && int(light.PositionKind.w) == DIRECTIONAL_LIGHT) {
+        Radiance *= Lit(IN.WorldPosition);
+    }
+
+    vec3 H = normalize(V + L);
+    float NdotV = max(dot(N, V), 0.0001);
+    float NdotL = max(dot(N, L), 0.0);
+    float NdotH = max(dot(N, H), 0.0);
+
+    float D = DistributionGgx(NdotH, Roughness);
+    float G = GeometrySchlickGgx(NdotV, Roughness) * GeometrySchlickGgx(NdotL, Roughness);
+    vec3 F = FresnelSchlick(max(dot(H, V), 0.0), F0);
+
+    vec3 Specular = D * G * F / (4.0 * NdotV * NdotL + 0.0001);
+    vec3 Kd = (vec3(1.0) - F) * (1.0 - Metallic);
+    return (Kd * Albedo / PI + Specular) * Radiance * NdotL;
+}
+
+// material debug views, must match render::MaterialDebugView
+vec3 DebugColor(mat3 WorldTBN, vec2 Uv, vec3 SampledNormal, vec3 MappedNormal)
+{
+    if (DebugView == 1) {
+        return normalize(WorldTBN[2]) * 0.5 + 0.5;
+    } else if (DebugView == 2) {
+        return normalize(WorldTBN[0]) * 0.5 + 0.5;
+    } else if (DebugView == 3) {
+        return vec3(fract(Uv), 0.0);
+    } else if (DebugView == 4) {
+        return SampledNormal;
+    }
+    return MappedNormal * 0.5 + 0.5;
+}
+
+void main()
+{
+    vec3 Albedo = texture(AlbedoMap, IN.Uv).rgb * AlbedoFactor;
+    float Metallic = texture(MetallicMap, IN.Uv).b * MetallicFactor;
+    float Roughness = clamp(texture(RoughnessMap, IN.Uv).g * RoughnessFactor, 0.04, 1.0);
+    float Occlusion = texture(OcclusionMap, IN.Uv).r;
+
+    vec3 SampledNormal = texture(NormalMap, IN.Uv).rgb;
+    vec3 N = normalize(IN.TBN * (SampledNormal * 2.0 - 1.0));
+#ifdef CLUSTERED
+    if (DebugView == 6 && ClustersEnabled != 0) {
+        Color = vec4(ClusterHeat(FragmentCluster(IN.WorldPosition).y), 1.0);
+        return;
+    }
+#endif
+    if (DebugView != 0) {
+        Color = vec4(DebugColor(IN.TBN, IN.Uv, SampledNormal, N), 1.0);
+        return;
+    }
+    vec3 V = normalize(CameraPos - IN.WorldPosition);
+    vec3 R = reflect(-V, N);
+    float NdotV = max(dot(N, V), 0.0001);
+
+    // dielectrics reflect about 4% at normal incidence
+    vec3 F0 = mix(vec3(0.04), Albedo, Metallic);
+
+    vec3 Direct = vec3(0.0);
+#ifdef CLUSTERED
+    if (ClustersEnabled != 0) {
+        // point and spot lights of the cluster, the block keeps the directional ones
+        for (int i = 0; i < LightCount; i++) {
+            if (int(AllLights[i].PositionKind.w) == DIRECTIONAL_LIGHT) {
+                Direct += DirectLight(AllLights[i], N, V, Albedo, Metallic, Roughness, F0);
+            }
+        }
+        uvec2 cluster = FragmentCluster(IN.WorldPosition);
+        for (uint i = 0u; i < cluster.y; i++) {
+            Direct += DirectLight(ClusterLight(cluster, i), N, V, Albedo, Metallic, Roughness, F0);
+        }
+    } else
+#endif
+    {
+        for (int i = 0; i < LightCount; i++) {
+            Direct += DirectLight(AllLights[i], N, V, Albedo, Metallic, Roughness, F0);
+        }
+    }
+
+    // image based ambient light
+    vec3 AmbientF = FresnelSchlickRoughness(NdotV, F0, Roughness);
+    vec3 AmbientKd = (vec3(1.0) - AmbientF) * (1.0 - Metallic);
+    vec3 Diffuse = texture(IrradianceMap, N).rgb * Albedo;
+    vec3 Prefiltered = textureLod(SpecularMap, R, Roughness * SpecularMaxLod).rgb;
+    vec2 Brdf = texture(BrdfLut, vec2(NdotV, Roughness)).rg;
+    vec3 AmbientSpecular = Prefiltered * (AmbientF * Brdf.x + Brdf.y);
+    vec3 Ambient = (AmbientKd * Diffuse + AmbientSpecular) * Occlusion;
+
+    vec3 Shaded = (Ambient + Direct) * CascadeTint(IN.WorldPosition);
+    Color = vec4(ApplyFog(Shaded, IN.WorldPosition), 1.0);
+}
\ No newline at end of file
@@ -0,0 +1,24 @@
+#version 330 core
+
+uniform sampler2D Input;
+// texel offset along the blur direction
+uniform vec2 Step;
+
+in VS_OUTPUT {
+    vec2 Uv;
+} IN;
+
+out vec4 Color;
+
+// 9 tap gaussian, folded into 5 weights
+const float Weights[5] = float[](0.227027, 0.1945946, 0.1216216, 0.054054, 0.016216);
+
+void main()
+{
+    vec3 Sum = texture(Input, IN.Uv).rgb * Weights[0];
+    for (int i = 1; i < 5; i++) {
+        Sum += texture(Input, IN.Uv + Step * float(i)).rgb * Weights[i];
+        Sum += texture(Input, IN.Uv - Step * float(i)).rgb * Weights[i];
+    }
+    Color = vec4(Sum, 1.0);
+}
\ No newline at end of file
@@ -0,0 +1,24 @@
+#version 330 core
+
+uniform sampler2D Input;
+uniform float Threshold;
+uniform float Knee;
+
+in VS_OUTPUT {
+    vec2 Uv;
+} IN;
+
+out vec4 Color;
+
+void main()
+{
+    vec3 Hdr = texture(Input, IN.Uv).rgb;
+    float Brightness = max(Hdr.r, max(Hdr.g, Hdr.b));
+
+    // quadratic soft knee around the threshold
+    float Soft = clamp(Brightness - Threshold + Knee, 0.0, 2.0 * Knee);
+    Soft = Soft * Soft / (4.0 * Knee + 0.00001);
+    float Contribution = max(Soft, Brightness - Threshold) / max(Brightness, 0.00001);
+
+    Color = vec4(Hdr * Contribution, 1.0);
+}
\ No newline at end of file
@@ -0,0 +1,18 @@
+#version 330 core
+
+uniform sampler2D Input;
+uniform sampler2D Bloom;
+uniform float Intensity;
+
+in VS_OUTPUT {
+    vec2 Uv;
+} IN;
+
+out vec4 Color;
+
+void main()
+{
+    vec3 Hdr = texture(Input, IN.Uv).rgb;
+    vec3 Glow = texture(Bloom, IN.Uv).rgb;
+    Color = vec4(Hdr + Glow * Intensity, 1.0);
+}
\ No newline at end of file
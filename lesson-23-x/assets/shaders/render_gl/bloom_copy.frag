@@ -0,0 +1,15 @@
+#version 330 core
+
+uniform sampler2D Input;
+
+in VS_OUTPUT {
+    vec2 Uv;
+} IN;
+
+out vec4 Color;
+
+void main()
+{
+    // linear filtering averages texels when the target size differs
+    Color = vec4(texture(Input, IN.Uv).rgb, 1.0);
+}
\ No newline at end of file
@@ -0,0 +1,27 @@
+#version 330 core
+
+layout (std140) uniform Camera {
+    mat4 ViewProjection;
+    vec3 CameraPos;
+};
+
+uniform mat4 Model;
+uniform vec3 BoundsMin;
+uniform vec3 BoundsMax;
+
+// box corner index for each vertex of the 12 triangles, bits are x, y and z
+const int Corners[36] = int[36](
+    0, 2, 1, 1, 2, 3,
+    4, 5, 6, 5, 7, 6,
+    0, 1, 4, 1, 5, 4,
+    2, 6, 3, 3, 6, 7,
+    0, 4, 2, 2, 4, 6,
+    1, 3, 5, 3, 7, 5
+);
+
+void main()
+{
+    int corner = Corners[gl_VertexID];
+    vec3 t = vec3(corner & 1, (corner >> 1) & 1, (corner >> 2) & 1);
+    gl_Position = ViewProjection * Model * vec4(mix(BoundsMin, BoundsMax, t), 1.0);
+}
\ No newline at end of file
@@ -0,0 +1,28 @@
+#version 330 core
+
+uniform int Face;
+
+out VS_OUTPUT {
+    vec3 Direction;
+} OUT;
+
+// direction at face coordinates in [-1, 1], in GL cube map face order
+vec3 FaceDirection(int face, vec2 st)
+{
+    if (face == 0) return vec3(1.0, -st.y, -st.x);
+    if (face == 1) return vec3(-1.0, -st.y, st.x);
+    if (face == 2) return vec3(st.x, 1.0, st.y);
+    if (face == 3) return vec3(st.x, -1.0, -st.y);
+    if (face == 4) return vec3(st.x, -st.y, 1.0);
+    return vec3(-st.x, -st.y, -1.0);
+}
+
+void main()
+{
+    // fullscreen triangle from vertex ids 0, 1, 2
+    vec2 Position = vec2(float((gl_VertexID << 1) & 2), float(gl_VertexID & 2));
+    vec2 Clip = Position * 2.0 - 1.0;
+    // direction is linear across the face, so it can be interpolated
+    OUT.Direction = FaceDirection(Face, Clip);
+    gl_Position = vec4(Clip, 0.0, 1.0);
+}
\ No newline at end of file
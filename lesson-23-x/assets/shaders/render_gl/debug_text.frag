@@ -0,0 +1,16 @@
+#version 330 core
+
+uniform sampler2D Font;
+
+in VS_OUTPUT {
+    vec2 Uv;
+    vec4 Color;
+} IN;
+
+out vec4 Color;
+
+void main()
+{
+    // glyph coverage is stored in alpha
+    Color = vec4(IN.Color.rgb, IN.Color.a * texture(Font, IN.Uv).a);
+}
\ No newline at end of file
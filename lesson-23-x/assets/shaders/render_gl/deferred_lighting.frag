@@ -0,0 +1,92 @@
+#version 330 core
+
+#define MAX_LIGHTS 32
+#define DIRECTIONAL_LIGHT 0
+#define POINT_LIGHT 1
+#define SPOT_LIGHT 2
+
+// must match lights::LightUniform
+struct Light {
+    vec4 PositionKind;
+    vec4 DirectionRadius;
+    vec4 ColorIntensity;
+    vec4 Params;
+};
+
+layout (std140) uniform Lights {
+    int LightCount;
+    Light AllLights[MAX_LIGHTS];
+};
+
+layout (std140) uniform Camera {
+    mat4 ViewProjection;
+    vec3 CameraPos;
+};
+
+uniform sampler2D Albedo;
+uniform sampler2D Normal;
+uniform sampler2D Position;
+// screen-space occlusion of the ambient light, 1 is unoccluded
+uniform sampler2D AmbientOcclusion;
+uniform bool UseAmbientOcclusion;
+
+in VS_OUTPUT {
+    vec2 Uv;
+} IN;
+
+out vec4 Color;
+
+#include "common/fog.glsl"
+
+// light color reaching the position, and direction towards the light in L
+vec3 LightRadiance(Light light, vec3 position, out vec3 L)
+{
+    int kind = int(light.PositionKind.w);
+    vec3 radiance = light.ColorIntensity.rgb * light.ColorIntensity.a;
+
+    if (kind == DIRECTIONAL_LIGHT) {
+        L = -light.DirectionRadius.xyz;
+        return radiance;
+    }
+
+    vec3 toLight = light.PositionKind.xyz - position;
+    float distance = length(toLight);
+    L = toLight / max(distance, 0.0001);
+    radiance *= 1.0 - smoothstep(0.0, light.DirectionRadius.w, distance);
+
+    if (kind == SPOT_LIGHT) {
+        float cosAngle = dot(-L, light.DirectionRadius.xyz);
+        radiance *= smoothstep(light.Params.y, light.Params.x, cosAngle);
+    }
+    return radiance;
+}
+
+void main()
+{
+    vec4 albedo = texture(Albedo, IN.Uv);
+    if (albedo.a == 0.0) {
+        discard;
+    }
+
+    vec3 normal = normalize(texture(Normal, IN.Uv).xyz);
+    vec3 position = texture(Position, IN.Uv).xyz;
+    vec3 viewDir = normalize(CameraPos - position);
+
+    // ambient
+    float occlusion = UseAmbientOcclusion ? texture(AmbientOcclusion, IN.Uv).r : 1.0;
+    vec3 result = 0.1 * albedo.rgb * occlusion;
+
+    // deferred path has no shadow map
+    for (int i = 0; i < LightCount; i++) {
+        vec3 lightDir;
+        vec3 radiance = LightRadiance(AllLights[i], position, lightDir);
+
+        float diff = max(dot(normal, lightDir), 0.0);
+        vec3 halfwayDir = normalize(lightDir + viewDir);
+        float spec = pow(max(dot(normal, halfwayDir), 0.0), 16.0);
+
+        result += radiance * (diff * albedo.rgb + vec3(0.2) * spec);
+    }
+
+    Color = vec4(ApplyFog(result, position), 1.0);
+}
\ No newline at end of file
@@ -0,0 +1,24 @@
+#version 330 core
+
+#define PI 3.1415926535897932384626433832795
+
+uniform sampler2D Equirectangular;
+
+in VS_OUTPUT {
+    vec3 Direction;
+} IN;
+
+out vec4 Color;
+
+void main()
+{
+    vec3 Direction = normalize(IN.Direction);
+
+    // Z is up, first image row is at the top
+    vec2 EquirectangularUv = vec2(
+        atan(Direction.y, Direction.x) / (2.0 * PI) + 0.5,
+        acos(clamp(Direction.z, -1.0, 1.0)) / PI
+    );
+
+    Color = vec4(texture(Equirectangular, EquirectangularUv).rgb, 1.0);
+}
\ No newline at end of file
@@ -0,0 +1,13 @@
+#version 330 core
+
+out VS_OUTPUT {
+    vec2 Uv;
+} OUT;
+
+void main()
+{
+    // fullscreen triangle from vertex ids 0, 1, 2
+    vec2 Position = vec2(float((gl_VertexID << 1) & 2), float(gl_VertexID & 2));
+    OUT.Uv = Position;
+    gl_Position = vec4(Position * 2.0 - 1.0, 0.0, 1.0);
+}
\ No newline at end of file
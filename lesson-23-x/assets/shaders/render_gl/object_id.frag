@@ -0,0 +1,10 @@
+#version 330 core
+
+uniform uint Id;
+
+out uint Color;
+
+void main()
+{
+    Color = Id;
+}
\ No newline at end of file
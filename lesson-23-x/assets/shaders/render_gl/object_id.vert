@@ -0,0 +1,11 @@
+#version 330 core
+
+layout (location = 0) in vec3 Position;
+
+uniform mat4 ViewProjection;
+uniform mat4 Model;
+
+void main()
+{
+    gl_Position = ViewProjection * Model * vec4(Position, 1.0);
+}
\ No newline at end of file
@@ -0,0 +1,10 @@
+#version 330 core
+
+uniform vec4 Color;
+
+out vec4 FragColor;
+
+void main()
+{
+    FragColor = Color;
+}
\ No newline at end of file
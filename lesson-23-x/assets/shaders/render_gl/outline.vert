@@ -0,0 +1,32 @@
+#version 330 core
+
+layout (location = 0) in vec3 Position;
+layout (location = 3) in vec3 Normal;
+
+uniform mat4 ViewProjection;
+uniform mat4 Model;
+uniform float Thickness;
+uniform vec2 ViewportSize;
+
+#include "common/morph.glsl"
+#include "common/skinning.glsl"
+
+void main()
+{
+    vec3 DeformedPosition = Position;
+    vec3 DeformedNormal = Normal;
+    vec3 DeformedTangent = vec3(0.0);
+    ApplyMorphTargets(DeformedPosition, DeformedNormal);
+    ApplySkinning(DeformedPosition, DeformedNormal, DeformedTangent);
+
+    vec4 clipPosition = ViewProjection * Model * vec4(DeformedPosition, 1.0);
+    vec3 worldNormal = transpose(inverse(mat3(Model))) * DeformedNormal;
+    vec2 clipNormal = (ViewProjection * vec4(worldNormal, 0.0)).xy;
+
+    // extrude in screen space, so that the outline is equally wide at any distance
+    if (length(clipNormal) > 0.0) {
+        clipPosition.xy += normalize(clipNormal) / ViewportSize * 2.0 * Thickness * clipPosition.w;
+    }
+
+    gl_Position = clipPosition;
+}
\ No newline at end of file
@@ -0,0 +1,88 @@
+#version 330 core
+
+#define PI 3.1415926535897932384626433832795
+#define SAMPLE_COUNT 1024u
+
+in VS_OUTPUT {
+    vec2 Uv;
+} IN;
+
+out vec4 Color;
+
+float RadicalInverse(uint Bits)
+{
+    Bits = (Bits << 16u) | (Bits >> 16u);
+    Bits = ((Bits & 0x55555555u) << 1u) | ((Bits & 0xAAAAAAAAu) >> 1u);
+    Bits = ((Bits & 0x33333333u) << 2u) | ((Bits & 0xCCCCCCCCu) >> 2u);
+    Bits = ((Bits & 0x0F0F0F0Fu) << 4u) | ((Bits & 0xF0F0F0F0u) >> 4u);
+    Bits = ((Bits & 0x00FF00FFu) << 8u) | ((Bits & 0xFF00FF00u) >> 8u);
+    return float(Bits) * 2.3283064365386963e-10;
+}
+
+vec2 Hammersley(uint i, uint n)
+{
+    return vec2(float(i) / float(n), RadicalInverse(i));
+}
+
+vec3 ImportanceSampleGgx(vec2 Xi, vec3 N, float Roughness)
+{
+    float a = Roughness * Roughness;
+
+    float Phi = 2.0 * PI * Xi.x;
+    float CosTheta = sqrt((1.0 - Xi.y) / (1.0 + (a * a - 1.0) * Xi.y));
+    float SinTheta = sqrt(1.0 - CosTheta * CosTheta);
+
+    vec3 H = vec3(cos(Phi) * SinTheta, sin(Phi) * SinTheta, CosTheta);
+
+    vec3 Up = abs(N.z) < 0.999 ? vec3(0.0, 0.0, 1.0) : vec3(1.0, 0.0, 0.0);
+    vec3 TangentX = normalize(cross(Up, N));
+    vec3 TangentY = cross(N, TangentX);
+
+    return normalize(TangentX * H.x + TangentY * H.y + N * H.z);
+}
+
+float GeometrySchlickGgx(float NdotV, float Roughness)
+{
+    // k for image based lighting
+    float k = (Roughness * Roughness) / 2.0;
+    return NdotV / (NdotV * (1.0 - k) + k);
+}
+
+float GeometrySmith(float NdotV, float NdotL, float Roughness)
+{
+    return GeometrySchlickGgx(NdotV, Roughness) * GeometrySchlickGgx(NdotL, Roughness);
+}
+
+// scale and bias to Fresnel reflectance at normal incidence, by view angle and roughness
+void main()
+{
+    float NdotV = max(IN.Uv.x, 0.0001);
+    float Roughness = IN.Uv.y;
+
+    vec3 V = vec3(sqrt(1.0 - NdotV * NdotV), 0.0, NdotV);
+    vec3 N = vec3(0.0, 0.0, 1.0);
+
+    float Scale = 0.0;
+    float Bias = 0.0;
+
+    for (uint i = 0u; i < SAMPLE_COUNT; ++i) {
+        vec2 Xi = Hammersley(i, SAMPLE_COUNT);
+        vec3 H = ImportanceSampleGgx(Xi, N, Roughness);
+        vec3 L = normalize(2.0 * dot(V, H) * H - V);
+
+        float NdotL = max(L.z, 0.0);
+        float NdotH = max(H.z, 0.0);
+        float VdotH = max(dot(V, H), 0.0);
+
+        if (NdotL > 0.0) {
+            float G = GeometrySmith(NdotV, NdotL, Roughness);
+            float GVis = (G * VdotH) / (NdotH * NdotV);
+            float Fc = pow(1.0 - VdotH, 5.0);
+
+            Scale += (1.0 - Fc) * GVis;
+            Bias += Fc * GVis;
+        }
+    }
+
+    Color = vec4(Scale / float(SAMPLE_COUNT), Bias / float(SAMPLE_COUNT), 0.0, 1.0);
+}
\ No newline at end of file
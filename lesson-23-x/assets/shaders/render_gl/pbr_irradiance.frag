@@ -0,0 +1,36 @@
+#version 330 core
+
+#define PI 3.1415926535897932384626433832795
+
+uniform samplerCube Environment;
+
+in VS_OUTPUT {
+    vec3 Direction;
+} IN;
+
+out vec4 Color;
+
+// cosine weighted average of incoming light over the hemisphere around the normal
+void main()
+{
+    vec3 N = normalize(IN.Direction);
+    vec3 Up = abs(N.z) < 0.999 ? vec3(0.0, 0.0, 1.0) : vec3(1.0, 0.0, 0.0);
+    vec3 Right = normalize(cross(Up, N));
+    Up = cross(N, Right);
+
+    float SampleDelta = 0.05;
+    float SampleCount = 0.0;
+    vec3 Irradiance = vec3(0.0);
+
+    for (float Phi = 0.0; Phi < 2.0 * PI; Phi += SampleDelta) {
+        for (float Theta = 0.0; Theta < 0.5 * PI; Theta += SampleDelta) {
+            vec3 TangentSample = vec3(sin(Theta) * cos(Phi), sin(Theta) * sin(Phi), cos(Theta));
+            vec3 SampleDirection = TangentSample.x * Right + TangentSample.y * Up + TangentSample.z * N;
+
+            Irradiance += textureLod(Environment, SampleDirection, 0.0).rgb * cos(Theta) * sin(Theta);
+            SampleCount += 1.0;
+        }
+    }
+
+    Color = vec4(PI * Irradiance / SampleCount, 1.0);
+}
\ No newline at end of file
@@ -0,0 +1,85 @@
+#version 330 core
+
+#define PI 3.1415926535897932384626433832795
+#define SAMPLE_COUNT 512u
+
+uniform samplerCube Environment;
+uniform float EnvironmentSize;
+uniform float Roughness;
+
+in VS_OUTPUT {
+    vec3 Direction;
+} IN;
+
+out vec4 Color;
+
+float RadicalInverse(uint Bits)
+{
+    Bits = (Bits << 16u) | (Bits >> 16u);
+    Bits = ((Bits & 0x55555555u) << 1u) | ((Bits & 0xAAAAAAAAu) >> 1u);
+    Bits = ((Bits & 0x33333333u) << 2u) | ((Bits & 0xCCCCCCCCu) >> 2u);
+    Bits = ((Bits & 0x0F0F0F0Fu) << 4u) | ((Bits & 0xF0F0F0F0u) >> 4u);
+    Bits = ((Bits & 0x00FF00FFu) << 8u) | ((Bits & 0xFF00FF00u) >> 8u);
+    return float(Bits) * 2.3283064365386963e-10;
+}
+
+vec2 Hammersley(uint i, uint n)
+{
+    return vec2(float(i) / float(n), RadicalInverse(i));
+}
+
+vec3 ImportanceSampleGgx(vec2 Xi, vec3 N, float Roughness)
+{
+    float a = Roughness * Roughness;
+
+    float Phi = 2.0 * PI * Xi.x;
+    float CosTheta = sqrt((1.0 - Xi.y) / (1.0 + (a * a - 1.0) * Xi.y));
+    float SinTheta = sqrt(1.0 - CosTheta * CosTheta);
+
+    vec3 H = vec3(cos(Phi) * SinTheta, sin(Phi) * SinTheta, CosTheta);
+
+    vec3 Up = abs(N.z) < 0.999 ? vec3(0.0, 0.0, 1.0) : vec3(1.0, 0.0, 0.0);
+    vec3 TangentX = normalize(cross(Up, N));
+    vec3 TangentY = cross(N, TangentX);
+
+    return normalize(TangentX * H.x + TangentY * H.y + N * H.z);
+}
+
+float DistributionGgx(float NdotH, float Roughness)
+{
+    float a = Roughness * Roughness;
+    float a2 = a * a;
+    float Denominator = NdotH * NdotH * (a2 - 1.0) + 1.0;
+    return a2 / (PI * Denominator * Denominator);
+}
+
+// specular lobe convolution, assuming view direction equals the normal
+void main()
+{
+    vec3 N = normalize(IN.Direction);
+    vec3 V = N;
+
+    float TotalWeight = 0.0;
+    vec3 Prefiltered = vec3(0.0);
+
+    for (uint i = 0u; i < SAMPLE_COUNT; ++i) {
+        vec2 Xi = Hammersley(i, SAMPLE_COUNT);
+        vec3 H = ImportanceSampleGgx(Xi, N, Roughness);
+        vec3 L = normalize(2.0 * dot(V, H) * H - V);
+
+        float NdotL = max(dot(N, L), 0.0);
+        if (NdotL > 0.0) {
+            // sample lower environment mip for low probability directions to avoid bright dots
+            float NdotH = max(dot(N, H), 0.0);
+            float Pdf = DistributionGgx(NdotH, Roughness) / 4.0 + 0.0001;
+            float SaTexel = 4.0 * PI / (6.0 * EnvironmentSize * EnvironmentSize);
+            float SaSample = 1.0 / (float(SAMPLE_COUNT) * Pdf + 0.0001);
+            float MipLevel = Roughness == 0.0 ? 0.0 : 0.5 * log2(SaSample / SaTexel);
+
+            Prefiltered += textureLod(Environment, L, MipLevel).rgb * NdotL;
+            TotalWeight += NdotL;
+        }
+    }
+
+    Color = vec4(Prefiltered / TotalWeight, 1.0);
+}
\ No newline at end of file
@@ -0,0 +1,21 @@
+#version 330 core
+
+layout (location = 0) in vec3 Position;
+
+uniform mat4 LightSpace;
+uniform mat4 Model;
+
+#include "common/morph.glsl"
+#include "common/skinning.glsl"
+
+void main()
+{
+    vec3 DeformedPosition = Position;
+    // directions are not needed for depth
+    vec3 DeformedN = vec3(0.0);
+    vec3 DeformedT = vec3(0.0);
+    ApplyMorphTargets(DeformedPosition, DeformedN);
+    ApplySkinning(DeformedPosition, DeformedN, DeformedT);
+
+    gl_Position = LightSpace * Model * vec4(DeformedPosition, 1.0);
+}
\ No newline at end of file
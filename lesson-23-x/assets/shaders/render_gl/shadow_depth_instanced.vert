@@ -0,0 +1,11 @@
+#version 330 core
+
+layout (location = 0) in vec3 Position;
+layout (location = 4) in mat4 InstanceModel;
+
+uniform mat4 LightSpace;
+
+void main()
+{
+    gl_Position = LightSpace * InstanceModel * vec4(Position, 1.0);
+}
\ No newline at end of file
@@ -0,0 +1,60 @@
+#version 330 core
+
+layout (std140) uniform Camera {
+    mat4 ViewProjection;
+    vec3 CameraPos;
+    int DebugView;
+    vec4 ClipPlane;
+};
+
+uniform sampler2D Normal;
+uniform sampler2D Position;
+uniform sampler2D Noise;
+// hemisphere around +Z, must match KERNEL_SIZE in render_gl::Ssao
+uniform vec3 Kernel[16];
+// screen size in noise tiles
+uniform vec2 NoiseScale;
+uniform float Radius;
+uniform float Bias;
+
+in VS_OUTPUT {
+    vec2 Uv;
+} IN;
+
+out vec4 Color;
+
+void main()
+{
+    // zero position alpha marks pixels without geometry
+    vec4 position = texture(Position, IN.Uv);
+    if (position.w == 0.0) {
+        Color = vec4(1.0);
+        return;
+    }
+
+    vec3 normal = normalize(texture(Normal, IN.Uv).xyz);
+    vec3 randomVec = vec3(texture(Noise, IN.Uv * NoiseScale).xy * 2.0 - 1.0, 0.0);
+    vec3 tangent = normalize(randomVec - normal * dot(randomVec, normal));
+    vec3 bitangent = cross(normal, tangent);
+    mat3 TBN = mat3(tangent, bitangent, normal);
+
+    float distance = length(position.xyz - CameraPos);
+    float occlusion = 0.0;
+    for (int i = 0; i < 16; i++) {
+        vec3 samplePosition = position.xyz + TBN * Kernel[i] * Radius;
+        vec4 clip = ViewProjection * vec4(samplePosition, 1.0);
+        vec2 uv = clip.xy / clip.w * 0.5 + 0.5;
+
+        vec4 scene = texture(Position, uv);
+        if (scene.w == 0.0) {
+            continue;
+        }
+        float sceneDistance = length(scene.xyz - CameraPos);
+        float sampleDistance = length(samplePosition - CameraPos);
+        // surfaces far in front of the sample do not occlude it
+        float range = smoothstep(0.0, 1.0, Radius / abs(distance - sceneDistance));
+        occlusion += (sceneDistance <= sampleDistance - Bias ? 1.0 : 0.0) * range;
+    }
+
+    Color = vec4(vec3(1.0 - occlusion / 16.0), 1.0);
+}
\ No newline at end of file
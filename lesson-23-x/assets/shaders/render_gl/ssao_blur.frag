@@ -0,0 +1,24 @@
+#version 330 core
+
+uniform sampler2D Input;
+// exponent applied to the blurred result
+uniform float Intensity;
+
+in VS_OUTPUT {
+    vec2 Uv;
+} IN;
+
+out vec4 Color;
+
+// box blur over the size of the noise texture, which hides its pattern
+void main()
+{
+    vec2 texel = 1.0 / vec2(textureSize(Input, 0));
+    float sum = 0.0;
+    for (int x = -2; x < 2; x++) {
+        for (int y = -2; y < 2; y++) {
+            sum += texture(Input, IN.Uv + (vec2(float(x), float(y)) + 0.5) * texel).r;
+        }
+    }
+    Color = vec4(vec3(pow(sum / 16.0, Intensity)), 1.0);
+}
\ No newline at end of file
@@ -0,0 +1,44 @@
+#version 330 core
+
+uniform sampler2D Input;
+uniform float Exposure;
+uniform int Operator;
+uniform float Gamma;
+
+in VS_OUTPUT {
+    vec2 Uv;
+} IN;
+
+out vec4 Color;
+
+vec3 Reinhard(vec3 x)
+{
+    return x / (x + vec3(1.0));
+}
+
+// Narkowicz 2015, "ACES Filmic Tone Mapping Curve"
+vec3 Aces(vec3 x)
+{
+    const float a = 2.51;
+    const float b = 0.03;
+    const float c = 2.43;
+    const float d = 0.59;
+    const float e = 0.14;
+    return clamp((x * (a * x + b)) / (x * (c * x + d) + e), 0.0, 1.0);
+}
+
+void main()
+{
+    vec3 Hdr = texture(Input, IN.Uv).rgb * Exposure;
+
+    vec3 Mapped;
+    if (Operator == 1) {
+        Mapped = Reinhard(Hdr);
+    } else if (Operator == 2) {
+        Mapped = Aces(Hdr);
+    } else {
+        Mapped = clamp(Hdr, 0.0, 1.0);
+    }
+
+    Color = vec4(pow(Mapped, vec3(1.0 / Gamma)), 1.0);
+}
\ No newline at end of file
@@ -1,23 +1,148 @@
 #version 330 core
+#ifdef CLUSTERED
+#extension GL_ARB_shader_storage_buffer_object : require
+#endif
+
+layout (std140) uniform Camera {
+    mat4 ViewProjection;
+    vec3 CameraPos;
+    int DebugView;
+    vec4 ClipPlane;
+};
 
 uniform sampler2D Texture;
 uniform sampler2D Normals;
+// height in red, white is the top of the surface
+uniform sampler2D Height;
+// depth of the height map in UV units, zero for plain normal mapping
+uniform float ParallaxScale;
+uniform int ParallaxSteps;
+uniform samplerCube Reflection;
+// zero without a reflection probe
+uniform float ReflectionStrength;
+// below 1 for glass, only used while blending
+uniform float Opacity;
+// features defined by the dice for each material: NORMAL_MAP, PARALLAX_MAP (with the normal
+// map), VERTEX_COLOR and LIGHTMAP, TEXTURE_ARRAY by instances with skins, and CLUSTERED
+// where storage buffers are supported
+// baked lighting, sampled with the second coordinates
+uniform sampler2D Lightmap;
+#ifdef TEXTURE_ARRAY
+// color of each instance, replaces Texture
+uniform sampler2DArray Skins;
+flat in uint MaterialLayer;
+#endif
 
 in VS_OUTPUT {
     vec2 Uv;
     vec3 TangentCameraPos;
     vec3 TangentPosition;
+    vec3 WorldPosition;
+    mat3 WorldTBN;
+    vec4 VertexColor;
+    vec2 Uv2;
 } IN;
 
 out vec4 Color;
 
+#include "common/shadows.glsl"
+#include "common/fog.glsl"
+#ifdef CLUSTERED
+#define SPOT_LIGHT 2
+
+// must match lights::LightUniform
+struct Light {
+    vec4 PositionKind;
+    vec4 DirectionRadius;
+    vec4 ColorIntensity;
+    vec4 Params;
+};
+
+#include "common/clusters.glsl"
+#endif
+
+// material debug views, must match render::MaterialDebugView
+vec3 DebugColor(mat3 WorldTBN, vec2 Uv, vec3 SampledNormal, vec3 MappedNormal)
+{
+    if (DebugView == 1) {
+        return normalize(WorldTBN[2]) * 0.5 + 0.5;
+    } else if (DebugView == 2) {
+        return normalize(WorldTBN[0]) * 0.5 + 0.5;
+    } else if (DebugView == 3) {
+        return vec3(fract(Uv), 0.0);
+    } else if (DebugView == 4) {
+        return SampledNormal;
+    }
+    return MappedNormal * 0.5 + 0.5;
+}
+
+// parallax occlusion mapping, steps along the view ray below the surface until it passes
+// under the height map and interpolates between the last two steps
+vec2 ParallaxUv(vec2 Uv, vec3 ViewDir)
+{
+#ifndef PARALLAX_MAP
+    return Uv;
+#else
+    if (ParallaxScale <= 0.0 || ParallaxSteps <= 0 || ViewDir.z <= 0.0) {
+        return Uv;
+    }
+    // grazing rays cross more texels and take more steps
+    float steps = mix(float(ParallaxSteps), max(float(ParallaxSteps) * 0.25, 1.0), ViewDir.z);
+    float stepDepth = 1.0 / steps;
+    vec2 stepUv = ViewDir.xy / ViewDir.z * ParallaxScale / steps;
+    // derivatives of the shifted coordinates are undefined inside the loop
+    vec2 dx = dFdx(Uv);
+    vec2 dy = dFdy(Uv);
+
+    vec2 uv = Uv;
+    float depth = 0.0;
+    float surfaceDepth = 1.0 - textureGrad(Height, uv, dx, dy).r;
+    for (int i = 0; float(i) < steps && depth < surfaceDepth; i++) {
+        uv -= stepUv;
+        depth += stepDepth;
+        surfaceDepth = 1.0 - textureGrad(Height, uv, dx, dy).r;
+    }
+
+    vec2 previousUv = uv + stepUv;
+    float after = surfaceDepth - depth;
+    float before = 1.0 - textureGrad(Height, previousUv, dx, dy).r - depth + stepDepth;
+    float weight = abs(after - before) > 1e-5 ? after / (after - before) : 0.0;
+    return mix(uv, previousUv, clamp(weight, 0.0, 1.0));
+#endif
+}
+
 void main()
 {
-    vec3 normal = texture(Normals, IN.Uv).rgb; // obtain normal from normal map in range [0,1]
-    normal = normalize(normal * 2.0 - 1.0); // transform normal vector to range [-1,1]
+    vec3 viewDir = normalize(IN.TangentCameraPos - IN.TangentPosition);
+    vec2 uv = ParallaxUv(IN.Uv, viewDir);
+
+#ifdef NORMAL_MAP
+    vec3 sampledNormal = texture(Normals, uv).rgb; // obtain normal from normal map in range [0,1]
+#else
+    vec3 sampledNormal = vec3(0.5, 0.5, 1.0); // flat surface without a normal map
+#endif
+    vec3 normal = normalize(sampledNormal * 2.0 - 1.0); // transform normal vector to range [-1,1]
+#ifdef CLUSTERED
+    if (DebugView == 6 && ClustersEnabled != 0) {
+        Color = vec4(ClusterHeat(FragmentCluster(IN.WorldPosition).y), 1.0);
+        return;
+    }
+#endif
+    if (DebugView != 0) {
+        vec3 mappedNormal = normalize(IN.WorldTBN * normal);
+        Color = vec4(DebugColor(IN.WorldTBN, uv, sampledNormal, mappedNormal), 1.0);
+        return;
+    }
 
     // get diffuse color
-    vec3 color = texture(Texture, IN.Uv).rgb;
+#ifdef TEXTURE_ARRAY
+    vec3 color = texture(Skins, vec3(uv, float(MaterialLayer))).rgb;
+#else
+    vec3 color = texture(Texture, uv).rgb;
+#endif
+#ifdef VERTEX_COLOR
+    color *= IN.VertexColor.rgb;
+#endif
     // ambient
     vec3 ambient = 0.3 * color;
     // diffuse
@@ -25,11 +150,25 @@ void main()
     float diff = max(dot(lightDir, normal), 0.0);
     vec3 diffuse = diff * color;
     // specular
-    vec3 viewDir = normalize(IN.TangentCameraPos - IN.TangentPosition);
     vec3 reflectDir = reflect(-lightDir, normal);
     vec3 halfwayDir = normalize(lightDir + viewDir);
     float spec = pow(max(dot(normal, halfwayDir), 0.0), 16.0);
 
     vec3 specular = vec3(0.2) * spec;
-    Color = vec4(ambient + diffuse + specular, 1.0);
+    // surroundings mirrored around the mapped normal
+    vec3 reflectedDir = IN.WorldTBN * reflect(-viewDir, normal);
+    vec3 reflection = texture(Reflection, reflectedDir).rgb * ReflectionStrength;
+
+    float lit = Lit(IN.WorldPosition);
+    vec3 shaded = ambient + lit * (diffuse + specular) + reflection;
+#ifdef CLUSTERED
+    if (ClustersEnabled != 0) {
+        // point and spot lights on top of the light at the camera
+        shaded += ClusterDiffuse(IN.WorldPosition, normalize(IN.WorldTBN * normal)) * color;
+    }
+#endif
+#ifdef LIGHTMAP
+    shaded *= texture(Lightmap, IN.Uv2).rgb;
+#endif
+    Color = vec4(ApplyFog(shaded * CascadeTint(IN.WorldPosition), IN.WorldPosition), Opacity);
 }
\ No newline at end of file
@@ -4,32 +4,57 @@ layout (location = 0) in vec3 Position;
 layout (location = 1) in vec2 Uv;
 layout (location = 2) in vec3 T;
 layout (location = 3) in vec3 N;
+layout (location = 8) in vec4 VertexColor;
+layout (location = 9) in vec2 Uv2;
+
+layout (std140) uniform Camera {
+    mat4 ViewProjection;
+    vec3 CameraPos;
+    int DebugView;
+    vec4 ClipPlane;
+};
 
-uniform vec3 CameraPos;
-uniform mat4 ViewProjection;
 uniform mat4 Model;
 
+#include "common/morph.glsl"
+#include "common/skinning.glsl"
+
 out VS_OUTPUT {
     vec2 Uv;
     vec3 TangentCameraPos;
     vec3 TangentPosition;
+    vec3 WorldPosition;
+    mat3 WorldTBN;
+    vec4 VertexColor;
+    vec2 Uv2;
 } OUT;
 
 void main()
 {
-    vec3 WorldPosition = vec3(Model * vec4(Position, 1.0));
+    vec3 DeformedPosition = Position;
+    vec3 DeformedN = N;
+    vec3 DeformedT = T;
+    ApplyMorphTargets(DeformedPosition, DeformedN);
+    ApplySkinning(DeformedPosition, DeformedN, DeformedT);
+
+    vec3 WorldPosition = vec3(Model * vec4(DeformedPosition, 1.0));
     gl_Position = ViewProjection * vec4(WorldPosition, 1.0);
+    gl_ClipDistance[0] = dot(vec4(WorldPosition, 1.0), ClipPlane);
 
     OUT.Uv = Uv;
+    OUT.VertexColor = VertexColor;
+    OUT.Uv2 = Uv2;
 
     mat3 IntoModelMatrix = transpose(inverse(mat3(Model)));
 
-    vec3 ModelT = normalize(IntoModelMatrix * T);
-    vec3 ModelN = normalize(IntoModelMatrix * N);
+    vec3 ModelT = normalize(IntoModelMatrix * DeformedT);
+    vec3 ModelN = normalize(IntoModelMatrix * DeformedN);
     ModelT = normalize(ModelT - dot(ModelT, ModelN) * ModelN);
     vec3 ModelB = cross(ModelN, ModelT);
 
     mat3 TBN = transpose(mat3(ModelT, ModelB, ModelN));
     OUT.TangentCameraPos = TBN * CameraPos;
     OUT.TangentPosition = TBN * WorldPosition;
+    OUT.WorldPosition = WorldPosition;
+    OUT.WorldTBN = mat3(ModelT, ModelB, ModelN);
 }
\ No newline at end of file
@@ -0,0 +1,14 @@
+#version 330 core
+
+uniform samplerCube Environment;
+
+in VS_OUTPUT {
+    vec3 Direction;
+} IN;
+
+out vec4 Color;
+
+void main()
+{
+    Color = vec4(texture(Environment, normalize(IN.Direction)).rgb, 1.0);
+}
\ No newline at end of file
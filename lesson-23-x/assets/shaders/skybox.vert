@@ -0,0 +1,24 @@
+#version 330 core
+
+layout (location = 0) in vec3 Position;
+
+layout (std140) uniform Camera {
+    mat4 ViewProjection;
+    vec3 CameraPos;
+    int DebugView;
+    vec4 ClipPlane;
+};
+
+out VS_OUTPUT {
+    vec3 Direction;
+} OUT;
+
+void main()
+{
+    OUT.Direction = Position;
+    vec4 ClipPosition = ViewProjection * vec4(CameraPos + Position, 1.0);
+    // keep the box at far plane
+    gl_Position = ClipPosition.xyww;
+    // sky is infinitely far away, only its direction can be clipped
+    gl_ClipDistance[0] = dot(vec4(Position, 0.0), ClipPlane);
+}
\ No newline at end of file
@@ -0,0 +1,21 @@
+#version 330 core
+
+uniform sampler2D Atlas;
+
+in VS_OUTPUT {
+    vec2 Uv;
+    vec4 Color;
+} IN;
+
+out vec4 OutColor;
+
+void main()
+{
+    vec4 color = texture(Atlas, IN.Uv) * IN.Color;
+    // cut out instead of blending, so that sprites need no sorting
+    if (color.a < 0.5) {
+        discard;
+    }
+
+    OutColor = vec4(color.rgb, 1.0);
+}
\ No newline at end of file
@@ -0,0 +1,39 @@
+#version 330 core
+
+layout (std140) uniform Camera {
+    mat4 ViewProjection;
+    vec3 CameraPos;
+    int DebugView;
+    vec4 ClipPlane;
+};
+
+layout (location = 0) in vec3 Position;
+layout (location = 1) in vec2 Size;
+// atlas region as min and max texture coordinates
+layout (location = 2) in vec4 UvRect;
+layout (location = 3) in vec4 Color;
+
+out VS_OUTPUT {
+    vec2 Uv;
+    vec4 Color;
+} OUT;
+
+void main()
+{
+    // quad corners of the triangle strip come from vertex id
+    vec2 corner = vec2(gl_VertexID & 1, (gl_VertexID >> 1) & 1);
+
+    vec3 toCamera = normalize(CameraPos - Position);
+    vec3 helper = abs(toCamera.z) < 0.99 ? vec3(0.0, 0.0, 1.0) : vec3(0.0, 1.0, 0.0);
+    vec3 right = normalize(cross(helper, toCamera));
+    vec3 up = cross(toCamera, right);
+
+    vec2 offset = (corner - 0.5) * Size;
+    vec3 position = Position + right * offset.x + up * offset.y;
+
+    // first row of the image is at the top of the quad
+    OUT.Uv = vec2(mix(UvRect.x, UvRect.z, corner.x), mix(UvRect.w, UvRect.y, corner.y));
+    OUT.Color = Color;
+    gl_Position = ViewProjection * vec4(position, 1.0);
+    gl_ClipDistance[0] = dot(vec4(position, 1.0), ClipPlane);
+}
\ No newline at end of file
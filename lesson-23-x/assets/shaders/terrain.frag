@@ -0,0 +1,48 @@
+#version 330 core
+
+layout (std140) uniform Camera {
+    mat4 ViewProjection;
+    vec3 CameraPos;
+    int DebugView;
+    vec4 ClipPlane;
+};
+
+// layers are blended by the red, green, blue and alpha channel of the splat map
+uniform sampler2D SplatMap;
+uniform sampler2D Layer0;
+uniform sampler2D Layer1;
+uniform sampler2D Layer2;
+uniform sampler2D Layer3;
+// layer repeats over the whole terrain
+uniform float LayerTiling;
+// direction the sun light travels in
+uniform vec3 LightDirection;
+
+in VS_OUTPUT {
+    vec3 WorldPosition;
+    vec3 Normal;
+    vec2 Uv;
+} IN;
+
+out vec4 OutColor;
+
+#define SHADOW_BIAS 0.002
+#include "common/shadows.glsl"
+#include "common/fog.glsl"
+
+void main()
+{
+    vec4 weights = texture(SplatMap, IN.Uv);
+    weights /= max(dot(weights, vec4(1.0)), 0.001);
+
+    vec2 layerUv = IN.Uv * LayerTiling;
+    vec3 color = texture(Layer0, layerUv).rgb * weights.r
+        + texture(Layer1, layerUv).rgb * weights.g
+        + texture(Layer2, layerUv).rgb * weights.b
+        + texture(Layer3, layerUv).rgb * weights.a;
+
+    float diffuse = max(dot(normalize(IN.Normal), -LightDirection), 0.0);
+    float lit = Lit(IN.WorldPosition);
+    color *= CascadeTint(IN.WorldPosition);
+    OutColor = vec4(ApplyFog(color * (0.3 + 0.7 * diffuse * lit), IN.WorldPosition), 1.0);
+}
\ No newline at end of file
@@ -0,0 +1,30 @@
+#version 330 core
+
+layout (location = 0) in vec3 Position;
+layout (location = 1) in vec3 Normal;
+layout (location = 2) in vec2 Uv;
+
+layout (std140) uniform Camera {
+    mat4 ViewProjection;
+    vec3 CameraPos;
+    int DebugView;
+    vec4 ClipPlane;
+};
+
+uniform mat4 Model;
+
+out VS_OUTPUT {
+    vec3 WorldPosition;
+    vec3 Normal;
+    vec2 Uv;
+} OUT;
+
+void main()
+{
+    vec4 WorldPosition = Model * vec4(Position, 1.0);
+    gl_Position = ViewProjection * WorldPosition;
+    gl_ClipDistance[0] = dot(WorldPosition, ClipPlane);
+    OUT.WorldPosition = WorldPosition.xyz;
+    OUT.Normal = normalize(mat3(Model) * Normal);
+    OUT.Uv = Uv;
+}
\ No newline at end of file
@@ -0,0 +1,34 @@
+#version 330 core
+
+uniform sampler2D SplatMap;
+uniform sampler2D Layer0;
+uniform sampler2D Layer1;
+uniform sampler2D Layer2;
+uniform sampler2D Layer3;
+uniform float LayerTiling;
+
+in VS_OUTPUT {
+    vec3 WorldPosition;
+    vec3 Normal;
+    vec2 Uv;
+} IN;
+
+layout (location = 0) out vec4 Albedo;
+layout (location = 1) out vec4 Normal;
+layout (location = 2) out vec4 Position;
+
+void main()
+{
+    vec4 weights = texture(SplatMap, IN.Uv);
+    weights /= max(dot(weights, vec4(1.0)), 0.001);
+
+    vec2 layerUv = IN.Uv * LayerTiling;
+    vec3 color = texture(Layer0, layerUv).rgb * weights.r
+        + texture(Layer1, layerUv).rgb * weights.g
+        + texture(Layer2, layerUv).rgb * weights.b
+        + texture(Layer3, layerUv).rgb * weights.a;
+
+    Albedo = vec4(color, 1.0);
+    Normal = vec4(normalize(IN.Normal), 0.0);
+    Position = vec4(IN.WorldPosition, 1.0);
+}
\ No newline at end of file
@@ -0,0 +1,56 @@
+#version 330 core
+
+layout (std140) uniform Camera {
+    mat4 ViewProjection;
+    vec3 CameraPos;
+};
+
+uniform sampler2D Reflection;
+uniform sampler2D Refraction;
+uniform sampler2D DuDvMap;
+uniform sampler2D NormalMap;
+// scrolls the distortion, grows with time
+uniform float WaveOffset;
+uniform float WaveStrength;
+// direction the sun light travels in
+uniform vec3 LightDirection;
+uniform vec3 WaterColor;
+
+in VS_OUTPUT {
+    vec4 ClipPosition;
+    vec3 WorldPosition;
+    vec2 Uv;
+} IN;
+
+out vec4 OutColor;
+
+#include "common/fog.glsl"
+
+void main()
+{
+    // both targets are sampled where the surface is on screen
+    vec2 screenUv = IN.ClipPosition.xy / IN.ClipPosition.w * 0.5 + 0.5;
+
+    // two samples scrolling in different directions hide the repetition
+    vec2 distortedUv = texture(DuDvMap, vec2(IN.Uv.x + WaveOffset, IN.Uv.y)).rg * 0.1;
+    distortedUv = IN.Uv + vec2(distortedUv.x, distortedUv.y + WaveOffset);
+    vec2 distortion = (texture(DuDvMap, distortedUv).rg * 2.0 - 1.0) * WaveStrength;
+
+    vec2 uv = clamp(screenUv + distortion, 0.001, 0.999);
+    vec3 reflection = texture(Reflection, uv).rgb;
+    vec3 refraction = texture(Refraction, uv).rgb;
+
+    vec3 normalColor = texture(NormalMap, distortedUv).rgb;
+    vec3 normal = normalize(vec3(normalColor.r * 2.0 - 1.0, normalColor.g * 2.0 - 1.0, normalColor.b * 3.0));
+
+    // Fresnel: more reflection at grazing angles, see through when looking down
+    vec3 toCamera = normalize(CameraPos - IN.WorldPosition);
+    float fresnel = pow(1.0 - max(dot(toCamera, normal), 0.0), 3.0);
+    fresnel = clamp(0.02 + 0.98 * fresnel, 0.0, 1.0);
+
+    vec3 halfway = normalize(toCamera - LightDirection);
+    float specular = pow(max(dot(normal, halfway), 0.0), 64.0) * 0.6;
+
+    vec3 color = mix(mix(refraction, WaterColor, 0.2), reflection, fresnel);
+    OutColor = vec4(ApplyFog(color + vec3(specular), IN.WorldPosition), 1.0);
+}
\ No newline at end of file
@@ -0,0 +1,27 @@
+#version 330 core
+
+layout (location = 0) in vec3 Position;
+
+layout (std140) uniform Camera {
+    mat4 ViewProjection;
+    vec3 CameraPos;
+};
+
+uniform mat4 Model;
+// distortion map repeats over the surface
+uniform float Tiling;
+
+out VS_OUTPUT {
+    vec4 ClipPosition;
+    vec3 WorldPosition;
+    vec2 Uv;
+} OUT;
+
+void main()
+{
+    vec4 WorldPosition = Model * vec4(Position, 1.0);
+    OUT.ClipPosition = ViewProjection * WorldPosition;
+    OUT.WorldPosition = WorldPosition.xyz;
+    OUT.Uv = Position.xy * Tiling;
+    gl_Position = OUT.ClipPosition;
+}
\ No newline at end of file
@@ -13,6 +13,14 @@ pub fn vertex_attrib_pointers_derive(input: proc_macro::TokenStream) -> proc_mac
     gen.parse().unwrap()
 }
 
+#[proc_macro_derive(Std140)]
+pub fn std140_derive(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let s = input.to_string();
+    let ast = syn::parse_derive_input(&s).unwrap();
+    let gen = generate_std140_impl(&ast);
+    gen.parse().unwrap()
+}
+
 fn generate_impl(ast: &syn::DeriveInput) -> quote::Tokens {
     let ident = &ast.ident;
     let generics = &ast.generics;
@@ -119,3 +127,65 @@ fn generate_struct_field_vertex_attrib_pointer_call(field: &syn::Field) -> quote
         }
     }
 }
+
+fn generate_std140_impl(ast: &syn::DeriveInput) -> quote::Tokens {
+    let ident = &ast.ident;
+    let generics = &ast.generics;
+    let where_clause = &ast.generics.where_clause;
+
+    let fields = match ast.body {
+        syn::Body::Struct(syn::VariantData::Struct(ref s)) => s,
+        _ => panic!("Std140 can only be implemented for structs with named fields"),
+    };
+
+    let size_steps = fields
+        .iter()
+        .map(|field| {
+            let field_ty = &field.ty;
+            quote! {
+                let offset = ::render_gl::std140_align_offset(
+                    offset,
+                    <#field_ty as ::render_gl::Std140>::std140_align(),
+                ) + <#field_ty as ::render_gl::Std140>::std140_size();
+            }
+        }).collect::<Vec<_>>();
+
+    let write_steps = fields
+        .iter()
+        .map(|field| {
+            let field_ty = &field.ty;
+            let field_ident = field
+                .ident
+                .as_ref()
+                .expect("Std140 fields must be named");
+            quote! {
+                let offset = ::render_gl::std140_align_offset(
+                    offset,
+                    <#field_ty as ::render_gl::Std140>::std140_align(),
+                );
+                let size = <#field_ty as ::render_gl::Std140>::std140_size();
+                ::render_gl::Std140::write_std140(&self.#field_ident, &mut out[offset..offset + size]);
+                let offset = offset + size;
+            }
+        }).collect::<Vec<_>>();
+
+    quote!{
+        impl #generics ::render_gl::Std140 for #ident #generics #where_clause {
+            fn std140_align() -> usize {
+                16
+            }
+
+            fn std140_size() -> usize {
+                let offset = 0;
+                #(#size_steps)*
+                ::render_gl::std140_align_offset(offset, 16)
+            }
+
+            #[allow(unused_variables)]
+            fn write_std140(&self, out: &mut [u8]) {
+                let offset = 0;
+                #(#write_steps)*
+            }
+        }
+    }
+}
@@ -0,0 +1,67 @@
+use std::f32::consts::PI;
+
+/// Curve applied to linear progress from 0 to 1, starting at 0 and ending at 1.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Easing {
+    Linear,
+    QuadIn,
+    QuadOut,
+    QuadInOut,
+    CubicIn,
+    CubicOut,
+    CubicInOut,
+    SineInOut,
+    /// Overshoots the end a little and settles back.
+    BackOut,
+    /// Bounces off the end like a dropped ball.
+    BounceOut,
+}
+
+impl Easing {
+    /// Eased progress, `t` is clamped to 0 and 1.
+    pub fn apply(self, t: f32) -> f32 {
+        let t = t.max(0.0).min(1.0);
+        match self {
+            Easing::Linear => t,
+            Easing::QuadIn => t * t,
+            Easing::QuadOut => 1.0 - (1.0 - t) * (1.0 - t),
+            Easing::QuadInOut => in_out(t, |t| t * t),
+            Easing::CubicIn => t * t * t,
+            Easing::CubicOut => 1.0 - (1.0 - t).powi(3),
+            Easing::CubicInOut => in_out(t, |t| t * t * t),
+            Easing::SineInOut => 0.5 - (t * PI).cos() * 0.5,
+            Easing::BackOut => {
+                const OVERSHOOT: f32 = 1.70158;
+                let t = t - 1.0;
+                1.0 + t * t * ((OVERSHOOT + 1.0) * t + OVERSHOOT)
+            }
+            Easing::BounceOut => bounce_out(t),
+        }
+    }
+}
+
+/// First half eased in with `ease_in`, second half mirrored.
+fn in_out<F: Fn(f32) -> f32>(t: f32, ease_in: F) -> f32 {
+    if t < 0.5 {
+        ease_in(t * 2.0) * 0.5
+    } else {
+        1.0 - ease_in((1.0 - t) * 2.0) * 0.5
+    }
+}
+
+fn bounce_out(t: f32) -> f32 {
+    const N: f32 = 7.5625;
+    const D: f32 = 2.75;
+    if t < 1.0 / D {
+        N * t * t
+    } else if t < 2.0 / D {
+        let t = t - 1.5 / D;
+        N * t * t + 0.75
+    } else if t < 2.5 / D {
+        let t = t - 2.25 / D;
+        N * t * t + 0.9375
+    } else {
+        let t = t - 2.625 / D;
+        N * t * t + 0.984375
+    }
+}
@@ -0,0 +1,5 @@
+mod easing;
+mod tween;
+
+pub use self::easing::Easing;
+pub use self::tween::{Interpolate, Keyframe, Repeat, Tween};
@@ -0,0 +1,186 @@
+use nalgebra as na;
+use super::Easing;
+
+/// Value that can be blended towards another, `t` goes from 0 to 1.
+pub trait Interpolate: Clone {
+    fn interpolate(&self, to: &Self, t: f32) -> Self;
+}
+
+impl Interpolate for f32 {
+    fn interpolate(&self, to: &f32, t: f32) -> f32 {
+        self + (to - self) * t
+    }
+}
+
+impl Interpolate for na::Vector2<f32> {
+    fn interpolate(&self, to: &Self, t: f32) -> Self {
+        self.lerp(to, t)
+    }
+}
+
+impl Interpolate for na::Vector3<f32> {
+    fn interpolate(&self, to: &Self, t: f32) -> Self {
+        self.lerp(to, t)
+    }
+}
+
+/// Colors with alpha.
+impl Interpolate for na::Vector4<f32> {
+    fn interpolate(&self, to: &Self, t: f32) -> Self {
+        self.lerp(to, t)
+    }
+}
+
+impl Interpolate for na::Point3<f32> {
+    fn interpolate(&self, to: &Self, t: f32) -> Self {
+        na::Point3::from(self.coords.lerp(&to.coords, t))
+    }
+}
+
+/// Shortest rotation, rotations half a turn apart jump at the end.
+impl Interpolate for na::UnitQuaternion<f32> {
+    fn interpolate(&self, to: &Self, t: f32) -> Self {
+        self.try_slerp(to, t, 1.0e-6)
+            .unwrap_or(if t < 1.0 { *self } else { *to })
+    }
+}
+
+/// Translation blended linearly and rotation along the shortest arc.
+impl Interpolate for na::Isometry3<f32> {
+    fn interpolate(&self, to: &Self, t: f32) -> Self {
+        na::Isometry3::from_parts(
+            na::Translation3::from(self.translation.vector.lerp(&to.translation.vector, t)),
+            self.rotation.interpolate(&to.rotation, t),
+        )
+    }
+}
+
+/// What a tween does after the last keyframe.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Repeat {
+    /// Stays at the last value.
+    Once,
+    /// Starts over from the first keyframe.
+    Loop,
+    /// Plays backwards to the first keyframe, then forwards again.
+    PingPong,
+}
+
+#[derive(Clone, Debug)]
+pub struct Keyframe<T> {
+    /// Seconds from the start of the tween.
+    pub time: f32,
+    pub value: T,
+    /// Curve of the way from the previous keyframe to this one.
+    pub easing: Easing,
+}
+
+/// Keyframed animation of a value, advanced by the frame delta.
+///
+/// Use it for transforms, or for floats and colors written to uniforms. Built with
+/// `Tween::new` and `then`, i.e. `Tween::new(a).then(b, 0.5, Easing::QuadOut)`.
+#[derive(Clone, Debug)]
+pub struct Tween<T> {
+    keyframes: Vec<Keyframe<T>>,
+    time: f32,
+    repeat: Repeat,
+}
+
+impl<T: Interpolate> Tween<T> {
+    /// Tween holding `start` until more keyframes are added.
+    pub fn new(start: T) -> Tween<T> {
+        Tween {
+            keyframes: vec![Keyframe {
+                time: 0.0,
+                value: start,
+                easing: Easing::Linear,
+            }],
+            time: 0.0,
+            repeat: Repeat::Once,
+        }
+    }
+
+    /// From one value to another over `duration` seconds.
+    pub fn between(from: T, to: T, duration: f32, easing: Easing) -> Tween<T> {
+        Tween::new(from).then(to, duration, easing)
+    }
+
+    /// Add a keyframe `duration` seconds after the last one.
+    pub fn then(mut self, value: T, duration: f32, easing: Easing) -> Self {
+        let time = self.duration() + duration.max(0.0);
+        self.keyframes.push(Keyframe {
+            time,
+            value,
+            easing,
+        });
+        self
+    }
+
+    pub fn with_repeat(mut self, repeat: Repeat) -> Self {
+        self.repeat = repeat;
+        self
+    }
+
+    /// Time of the last keyframe, in seconds.
+    pub fn duration(&self) -> f32 {
+        self.keyframes.last().map(|k| k.time).unwrap_or(0.0)
+    }
+
+    /// Seconds played since the start, not wrapped by repeats.
+    pub fn time(&self) -> f32 {
+        self.time
+    }
+
+    /// True once a tween that plays once has reached its last keyframe.
+    pub fn is_finished(&self) -> bool {
+        self.repeat == Repeat::Once && self.time >= self.duration()
+    }
+
+    pub fn restart(&mut self) {
+        self.time = 0.0;
+    }
+
+    /// Advance by `delta` seconds and return the new value.
+    pub fn update(&mut self, delta: f32) -> T {
+        self.time += delta.max(0.0);
+        if self.repeat == Repeat::Once {
+            self.time = self.time.min(self.duration());
+        }
+        self.value()
+    }
+
+    /// Value at the current time.
+    pub fn value(&self) -> T {
+        self.value_at(self.time)
+    }
+
+    /// Value `time` seconds from the start, with repeats applied.
+    pub fn value_at(&self, time: f32) -> T {
+        let duration = self.duration();
+        let time = if duration <= 0.0 {
+            duration
+        } else {
+            match self.repeat {
+                Repeat::Once => time.min(duration),
+                Repeat::Loop => time % duration,
+                Repeat::PingPong => {
+                    let time = time % (duration * 2.0);
+                    if time > duration {
+                        duration * 2.0 - time
+                    } else {
+                        time
+                    }
+                }
+            }
+        };
+
+        let next = match self.keyframes.iter().position(|k| k.time > time) {
+            Some(next) => next,
+            None => return self.keyframes[self.keyframes.len() - 1].value.clone(),
+        };
+        // the first keyframe is at 0, so a later one is found
+        let (from, to) = (&self.keyframes[next - 1], &self.keyframes[next]);
+        let t = (time - from.time) / (to.time - from.time);
+        from.value.interpolate(&to.value, to.easing.apply(t))
+    }
+}
@@ -0,0 +1,156 @@
+use nalgebra as na;
+use rodio::{self, Source};
+use crate::resources::{self, Resources};
+use crate::scene;
+use std::io::Cursor;
+
+mod selection_sounds;
+
+pub use self::selection_sounds::SelectionSounds;
+
+/// Distance of each ear from the listener position, along the listener X axis.
+const EAR_OFFSET: f32 = 0.1;
+/// Default `Audio::distance_scale`.
+const DISTANCE_SCALE: f32 = 0.1;
+
+#[derive(Debug, Fail)]
+pub enum Error {
+    #[fail(display = "No audio output device")]
+    NoOutputDevice,
+    #[fail(display = "Failed to load sound {}", name)]
+    ResourceLoad {
+        name: String,
+        #[cause]
+        inner: resources::Error,
+    },
+    #[fail(display = "Failed to decode sound {}: {}", name, message)]
+    Decode { name: String, message: String },
+}
+
+type Decoded = rodio::source::Buffered<rodio::Decoder<Cursor<Vec<u8>>>>;
+
+/// Decoded sound, cheap to play many times at once.
+#[derive(Clone)]
+pub struct Sound {
+    source: Decoded,
+}
+
+impl Sound {
+    /// Load a wav, ogg, flac or mp3 resource.
+    pub fn from_res(res: &Resources, name: &str) -> Result<Sound, Error> {
+        let bytes = res.load_bytes(name).map_err(|e| Error::ResourceLoad {
+            name: name.into(),
+            inner: e,
+        })?;
+        let decoder = rodio::Decoder::new(Cursor::new(bytes)).map_err(|e| Error::Decode {
+            name: name.into(),
+            message: e.to_string(),
+        })?;
+        Ok(Sound {
+            source: decoder.buffered(),
+        })
+    }
+}
+
+/// Output device and the listener, usually placed at the camera.
+pub struct Audio {
+    device: rodio::Device,
+    /// Distances are multiplied by this, sounds get quieter with the square of the scaled
+    /// distance beyond 1.
+    pub distance_scale: f32,
+    /// Ears in world space.
+    ears: [na::Point3<f32>; 2],
+}
+
+impl Audio {
+    /// Audio on the default output device, with the listener at the origin.
+    pub fn new() -> Result<Audio, Error> {
+        let device = rodio::default_output_device().ok_or(Error::NoOutputDevice)?;
+        let mut audio = Audio {
+            device,
+            distance_scale: DISTANCE_SCALE,
+            ears: [na::Point3::origin(); 2],
+        };
+        audio.set_listener(&na::Matrix4::identity());
+        Ok(audio)
+    }
+
+    /// Place the listener with the camera transform, the inverse of the view matrix.
+    ///
+    /// Emitters pick up the new place in their `update`.
+    pub fn set_listener(&mut self, camera_transform: &na::Matrix4<f32>) {
+        let ear = |x: f32| camera_transform.transform_point(&na::Point3::new(x, 0.0, 0.0));
+        self.ears = [ear(-EAR_OFFSET), ear(EAR_OFFSET)];
+    }
+
+    /// World position scaled by `distance_scale`.
+    fn scaled(&self, p: &na::Point3<f32>) -> [f32; 3] {
+        let p = p * self.distance_scale;
+        [p.x, p.y, p.z]
+    }
+
+    /// Emitter at `local` from the parent node, so that the sound follows the parent,
+    /// i.e. an entity.
+    pub fn emitter(
+        &self,
+        scene: &scene::Scene,
+        local: na::Isometry3<f32>,
+        parent: Option<&scene::Node>,
+    ) -> Emitter {
+        Emitter {
+            node: scene.node(local, parent),
+            sink: None,
+        }
+    }
+}
+
+/// Source of positional sound, placed by its scene node.
+pub struct Emitter {
+    node: scene::Node,
+    sink: Option<rodio::SpatialSink>,
+}
+
+impl Emitter {
+    /// Play the sound after the ones already playing from this emitter.
+    pub fn play(&mut self, audio: &Audio, sound: &Sound) {
+        let position = audio.scaled(&self.position());
+        let sink = self.sink.get_or_insert_with(|| {
+            rodio::SpatialSink::new(
+                &audio.device,
+                position,
+                audio.scaled(&audio.ears[0]),
+                audio.scaled(&audio.ears[1]),
+            )
+        });
+        sink.append(sound.source.clone());
+    }
+
+    /// Stop all sounds of this emitter.
+    pub fn stop(&mut self) {
+        // a stopped sink does not play anything again
+        if let Some(sink) = self.sink.take() {
+            sink.stop();
+        }
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.sink.as_ref().map_or(false, |sink| !sink.empty())
+    }
+
+    pub fn node(&self) -> &scene::Node {
+        &self.node
+    }
+
+    /// Move the sound to the node and the listener, call every frame while playing.
+    pub fn update(&self, audio: &Audio) {
+        if let Some(ref sink) = self.sink {
+            sink.set_emitter_position(audio.scaled(&self.position()));
+            sink.set_left_ear_position(audio.scaled(&audio.ears[0]));
+            sink.set_right_ear_position(audio.scaled(&audio.ears[1]));
+        }
+    }
+
+    fn position(&self) -> na::Point3<f32> {
+        na::Point3::from(self.node.world_transform().translation.vector)
+    }
+}
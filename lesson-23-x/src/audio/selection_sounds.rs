@@ -0,0 +1,98 @@
+use nalgebra as na;
+use crate::entity::Entities;
+use crate::resources::Resources;
+use crate::scene;
+use crate::selection::{ContainerHandle, Selectables, TransformChange};
+use super::{Audio, Emitter, Error, Sound};
+
+/// Played at an object when it gets selected.
+const SELECT_SOUND: &str = "sounds/select.wav";
+/// Played at an object dropped after dragging.
+const DROP_SOUND: &str = "sounds/drop.wav";
+
+/// Click when an object gets selected and thud when a dragged object is dropped.
+///
+/// Sounds are attached to the node of the entity owning the selectable, so that they
+/// follow it, i.e. a dice falling after the drop.
+pub struct SelectionSounds {
+    audio: Audio,
+    select: Sound,
+    drop: Sound,
+    selected: Vec<ContainerHandle>,
+    /// Emitters of sounds still playing.
+    playing: Vec<Emitter>,
+}
+
+impl SelectionSounds {
+    pub fn new(res: &Resources) -> Result<SelectionSounds, Error> {
+        Ok(SelectionSounds {
+            audio: Audio::new()?,
+            select: Sound::from_res(res, SELECT_SOUND)?,
+            drop: Sound::from_res(res, DROP_SOUND)?,
+            selected: Vec::new(),
+            playing: Vec::new(),
+        })
+    }
+
+    /// Play sounds for objects selected since the last call and for the finished drags,
+    /// and move playing sounds with their objects and the camera.
+    pub fn update(
+        &mut self,
+        camera_transform: &na::Matrix4<f32>,
+        scene: &scene::Scene,
+        entities: &Entities,
+        selectables: &Selectables,
+        drops: &[TransformChange],
+    ) {
+        self.audio.set_listener(camera_transform);
+
+        let selection = selectables.selection();
+        let newly_selected = selection
+            .handles()
+            .iter()
+            .filter(|handle| !self.selected.contains(handle))
+            .cloned()
+            .collect::<Vec<_>>();
+        self.selected = selection.handles().to_vec();
+
+        let (select, drop) = (&self.select, &self.drop);
+        let sounds = newly_selected
+            .into_iter()
+            .map(|handle| (handle, select))
+            .chain(drops.iter().map(|change| (change.handle, drop)));
+        for (handle, sound) in sounds {
+            if let Some(mut emitter) = emitter_at(&self.audio, scene, entities, selectables, handle)
+            {
+                emitter.play(&self.audio, sound);
+                self.playing.push(emitter);
+            }
+        }
+
+        self.playing.retain(Emitter::is_playing);
+        for emitter in &self.playing {
+            emitter.update(&self.audio);
+        }
+    }
+}
+
+/// Emitter on the node of the entity owning the selectable, or at the selectable if no
+/// entity has a node.
+fn emitter_at(
+    audio: &Audio,
+    scene: &scene::Scene,
+    entities: &Entities,
+    selectables: &Selectables,
+    handle: ContainerHandle,
+) -> Option<Emitter> {
+    let parent = entities
+        .find_by_selectable(handle)
+        .and_then(|entity| entities.get(entity))
+        .and_then(|entity| entity.node());
+    match parent {
+        Some(node) => Some(audio.emitter(scene, na::Isometry3::identity(), Some(node))),
+        None => {
+            let container = selectables.get_container(handle)?;
+            Some(audio.emitter(scene, container.isometry, None))
+        }
+    }
+}
@@ -0,0 +1,74 @@
+use nalgebra as na;
+use crate::input::InputMap;
+use crate::platform::Event;
+
+/// Camera that produces view and projection for rendering and reacts to input.
+pub trait Camera {
+    fn get_view_matrix(&self) -> na::Matrix4<f32>;
+
+    fn get_p_matrix(&self) -> na::Matrix4<f32>;
+
+    /// Position of the eye in world space.
+    fn project_pos(&self) -> na::Point3<f32>;
+
+    /// Point the camera is looking at, used as a pivot for dragging in the view plane.
+    fn target(&self) -> na::Point3<f32>;
+
+    fn update_aspect(&mut self, aspect: f32);
+
+    fn handle_event(&mut self, event: &Event);
+
+    /// Read movement actions and axes, once per frame before `update`.
+    fn handle_input(&mut self, input: &InputMap);
+
+    /// Apply movement and smoothing, returns true if the view changed.
+    fn update(&mut self, delta: f32) -> bool;
+
+    fn direction(&self) -> na::Vector3<f32> {
+        (self.target() - self.project_pos())
+            .try_normalize(::std::f32::EPSILON)
+            .unwrap_or_else(|| -na::Vector3::z())
+    }
+
+    fn get_vp_matrix(&self) -> na::Matrix4<f32> {
+        self.get_p_matrix() * self.get_view_matrix()
+    }
+
+    fn get_inverse_view_matrix(&self) -> na::Matrix4<f32> {
+        self.get_view_matrix()
+            .try_inverse()
+            .expect("get_inverse_view_matrix")
+    }
+
+    fn get_inverse_p_matrix(&self) -> na::Matrix4<f32> {
+        self.get_p_matrix().try_inverse().expect("get_inverse_p_matrix")
+    }
+}
+
+/// Orientation looking down the -Z axis tilted by `pitch` around X and turned by `yaw` around Z.
+pub fn yaw_pitch_rotation(yaw: f32, pitch: f32) -> na::UnitQuaternion<f32> {
+    na::UnitQuaternion::from_axis_angle(&na::Vector3::z_axis(), yaw)
+        * na::UnitQuaternion::from_axis_angle(&na::Vector3::x_axis(), pitch)
+}
+
+/// Fraction of the remaining distance to cover this frame, for frame-rate independent
+/// smoothing. `smoothing` is roughly the time in seconds to cover 63% of the distance.
+pub fn smoothing_factor(smoothing: f32, delta: f32) -> f32 {
+    if smoothing <= 0.0 {
+        1.0
+    } else {
+        1.0 - (-delta / smoothing).exp()
+    }
+}
+
+/// Mouse pixels to radians.
+pub const ROTATION_SPEED: f32 = 0.005;
+
+/// Fully deflected look axis to mouse pixels per second.
+pub const LOOK_AXIS_SPEED: f32 = 500.0;
+
+/// Keep pitch away from straight up and down, where yaw becomes undefined.
+pub fn clamp_pitch(pitch: f32) -> f32 {
+    const LIMIT: f32 = 0.01;
+    pitch.max(LIMIT).min(::std::f32::consts::PI - LIMIT)
+}
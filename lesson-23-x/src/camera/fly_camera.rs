@@ -0,0 +1,161 @@
+use super::controller::{
+    clamp_pitch, smoothing_factor, yaw_pitch_rotation, LOOK_AXIS_SPEED, ROTATION_SPEED,
+};
+use super::{Camera, WasdMovement};
+use nalgebra as na;
+use crate::input::InputMap;
+use crate::platform::Event;
+
+/// Free camera moved with WASD along the view direction and turned with the mouse
+/// or the look axes.
+///
+/// Velocity and look direction ease towards the input over `smoothing` seconds,
+/// which gives the movement some inertia.
+pub struct FlyCamera {
+    position: na::Point3<f32>,
+    velocity: na::Vector3<f32>,
+    yaw: f32,
+    pitch: f32,
+    desired_yaw: f32,
+    desired_pitch: f32,
+    /// Units per second, tripled while shift is held.
+    pub speed: f32,
+    pub smoothing: f32,
+    pub movement: WasdMovement,
+    projection: na::Perspective3<f32>,
+}
+
+impl FlyCamera {
+    pub fn new(aspect: f32, fov: f32, znear: f32, zfar: f32) -> Self {
+        FlyCamera {
+            position: na::Point3::origin(),
+            velocity: na::zero(),
+            yaw: 0.0,
+            pitch: ::std::f32::consts::PI / 2.0,
+            desired_yaw: 0.0,
+            desired_pitch: ::std::f32::consts::PI / 2.0,
+            speed: 5.0,
+            smoothing: 0.15,
+            movement: WasdMovement::new(),
+            projection: na::Perspective3::new(aspect, fov, znear, zfar),
+        }
+    }
+
+    pub fn yaw(&self) -> f32 {
+        self.yaw
+    }
+
+    pub fn pitch(&self) -> f32 {
+        self.pitch
+    }
+
+    /// Jump to the pose and stop moving.
+    pub fn set_pose(&mut self, position: na::Point3<f32>, yaw: f32, pitch: f32) {
+        self.position = position;
+        self.velocity = na::zero();
+        self.yaw = yaw;
+        self.pitch = clamp_pitch(pitch);
+        self.desired_yaw = self.yaw;
+        self.desired_pitch = self.pitch;
+    }
+
+    /// Turn camera using relative mouse movement over screen pixels.
+    pub fn rotate(&mut self, rel: &na::Vector2<f32>) {
+        self.desired_yaw -= rel.x * ROTATION_SPEED;
+        self.desired_pitch = clamp_pitch(self.desired_pitch + rel.y * ROTATION_SPEED);
+    }
+
+    fn rotation(&self) -> na::UnitQuaternion<f32> {
+        yaw_pitch_rotation(self.yaw, self.pitch)
+    }
+
+    fn isometry(&self) -> na::Isometry3<f32> {
+        na::Isometry3::from_parts(na::Translation3::from(self.position.coords), self.rotation())
+    }
+
+    /// Velocity requested by pressed keys. Up and down move along the world Z axis.
+    fn desired_velocity(&self) -> na::Vector3<f32> {
+        let mov3 = self.movement.get_vector();
+        let rotation = self.rotation();
+
+        let direction = rotation * na::Vector3::x() * mov3.x
+            + rotation * -na::Vector3::z() * mov3.y
+            + na::Vector3::z() * mov3.z;
+
+        match direction.try_normalize(0.01) {
+            Some(direction) => {
+                direction * self.speed * if self.movement.faster { 3.0 } else { 1.0 }
+            }
+            None => na::zero(),
+        }
+    }
+}
+
+impl Camera for FlyCamera {
+    fn get_view_matrix(&self) -> na::Matrix4<f32> {
+        self.isometry().inverse().to_homogeneous()
+    }
+
+    fn get_p_matrix(&self) -> na::Matrix4<f32> {
+        self.projection.unwrap()
+    }
+
+    fn project_pos(&self) -> na::Point3<f32> {
+        self.position
+    }
+
+    /// Point one unit in front of the camera.
+    fn target(&self) -> na::Point3<f32> {
+        self.position + self.direction()
+    }
+
+    fn direction(&self) -> na::Vector3<f32> {
+        self.rotation() * -na::Vector3::z()
+    }
+
+    fn get_inverse_view_matrix(&self) -> na::Matrix4<f32> {
+        self.isometry().to_homogeneous()
+    }
+
+    fn update_aspect(&mut self, aspect: f32) {
+        self.projection.set_aspect(aspect);
+    }
+
+    fn handle_event(&mut self, e: &Event) {
+        if let Event::MouseMotion {
+            xrel,
+            yrel,
+            buttons,
+            ..
+        } = *e
+        {
+            if buttons.right {
+                self.rotate(&na::Vector2::new(xrel as f32, -yrel as f32));
+            }
+        }
+    }
+
+    fn handle_input(&mut self, input: &InputMap) {
+        self.movement.handle_input(input);
+    }
+
+    fn update(&mut self, delta: f32) -> bool {
+        if self.movement.has_look() {
+            let look = self.movement.look * LOOK_AXIS_SPEED * delta;
+            self.rotate(&look);
+        }
+
+        let previous = (self.position, self.yaw, self.pitch);
+
+        let t = smoothing_factor(self.smoothing, delta);
+        self.velocity += (self.desired_velocity() - self.velocity) * t;
+        if self.velocity.norm_squared() < 0.0001 {
+            self.velocity = na::zero();
+        }
+        self.position += self.velocity * delta;
+        self.yaw += (self.desired_yaw - self.yaw) * t;
+        self.pitch += (self.desired_pitch - self.pitch) * t;
+
+        previous != (self.position, self.yaw, self.pitch)
+    }
+}
@@ -1,5 +1,21 @@
-mod target_camera;
-pub use self::target_camera::TargetCamera;
+mod controller;
+pub use self::controller::Camera;
+
+mod fly_camera;
+pub use self::fly_camera::FlyCamera;
+
+mod orbit_camera;
+pub use self::orbit_camera::OrbitCamera;
+
+mod switchable_camera;
+pub use self::switchable_camera::{CameraKind, SwitchableCamera};
 
 mod movement;
 pub use self::movement::WasdMovement;
+
+/// Keyframed camera flythroughs.
+pub mod path;
+pub use self::path::CameraPath;
+
+/// Conversions between screen, device and world coordinates.
+pub mod projection;
@@ -1,59 +1,47 @@
 use nalgebra as na;
+use crate::input::InputMap;
 
+/// Movement read from the `move_right`, `move_forward` and `move_up` axes, WASD by default.
 pub struct WasdMovement {
-    pub left: bool,
-    pub forward: bool,
-    pub backward: bool,
-    pub right: bool,
-    pub up: bool,
-    pub down: bool,
+    /// X is right, Y is forward and Z is up, each from -1 to 1.
+    pub direction: na::Vector3<f32>,
+    /// Turning from the `look_right` and `look_up` axes, from -1 to 1.
+    pub look: na::Vector2<f32>,
     pub faster: bool,
 }
 
 impl WasdMovement {
     pub fn new() -> WasdMovement {
         WasdMovement {
-            left: false,
-            forward: false,
-            backward: false,
-            right: false,
-            up: false,
-            down: false,
+            direction: na::zero(),
+            look: na::zero(),
             faster: false,
         }
     }
 
+    /// Read the axes and the `move_faster` action for this frame.
+    pub fn handle_input(&mut self, input: &InputMap) {
+        self.direction = na::Vector3::new(
+            input.axis("move_right"),
+            input.axis("move_forward"),
+            input.axis("move_up"),
+        );
+        self.look = na::Vector2::new(input.axis("look_right"), input.axis("look_up"));
+        self.faster = input.pressed("move_faster");
+    }
+
     /// Getting Vector2 for movement would result in non-zero movement.
     pub fn has_movement(&self) -> bool {
-        self.left || self.right || self.forward || self.backward || self.up || self.down
+        self.direction != na::zero()
+    }
+
+    /// True if the look axes would turn the camera.
+    pub fn has_look(&self) -> bool {
+        self.look != na::zero()
     }
 
     /// Get movement vector on a horizontal plane. The Y axis is forward, the Z is up.
     pub fn get_vector(&self) -> na::Vector3<f32> {
-        let mut x = 0.0;
-        if self.right {
-            x += 1.0;
-        }
-        if self.left {
-            x -= 1.0;
-        }
-
-        let mut y = 0.0;
-        if self.forward {
-            y += 1.0;
-        }
-        if self.backward {
-            y -= 1.0;
-        }
-
-        let mut z = 0.0;
-        if self.up {
-            z += 1.0;
-        }
-        if self.down {
-            z -= 1.0;
-        }
-
-        na::Vector3::new(x, y, z)
+        self.direction
     }
 }
@@ -0,0 +1,194 @@
+use super::controller::{
+    clamp_pitch, smoothing_factor, yaw_pitch_rotation, LOOK_AXIS_SPEED, ROTATION_SPEED,
+};
+use super::{Camera, WasdMovement};
+use nalgebra as na;
+use crate::input::InputMap;
+use crate::platform::Event;
+
+const MIN_DISTANCE: f32 = 0.5;
+
+/// Camera rotating around a target point, which is moved on the horizontal plane with WASD.
+///
+/// Input changes the desired pose, and the camera eases towards it over `smoothing` seconds.
+pub struct OrbitCamera {
+    target: na::Point3<f32>,
+    yaw: f32,
+    pitch: f32,
+    distance: f32,
+    desired_target: na::Point3<f32>,
+    desired_yaw: f32,
+    desired_pitch: f32,
+    desired_distance: f32,
+    pub smoothing: f32,
+    pub movement: WasdMovement,
+    projection: na::Perspective3<f32>,
+}
+
+impl OrbitCamera {
+    pub fn new(aspect: f32, fov: f32, znear: f32, zfar: f32, pitch: f32, distance: f32) -> Self {
+        OrbitCamera {
+            target: na::Point3::origin(),
+            yaw: 0.0,
+            pitch,
+            distance,
+            desired_target: na::Point3::origin(),
+            desired_yaw: 0.0,
+            desired_pitch: pitch,
+            desired_distance: distance,
+            smoothing: 0.08,
+            movement: WasdMovement::new(),
+            projection: na::Perspective3::new(aspect, fov, znear, zfar),
+        }
+    }
+
+    pub fn yaw(&self) -> f32 {
+        self.yaw
+    }
+
+    pub fn pitch(&self) -> f32 {
+        self.pitch
+    }
+
+    pub fn distance(&self) -> f32 {
+        self.distance
+    }
+
+    /// Jump to the pose without smoothing.
+    pub fn set_pose(&mut self, target: na::Point3<f32>, yaw: f32, pitch: f32, distance: f32) {
+        self.target = target;
+        self.yaw = yaw;
+        self.pitch = clamp_pitch(pitch);
+        self.distance = distance.max(MIN_DISTANCE);
+        self.desired_target = self.target;
+        self.desired_yaw = self.yaw;
+        self.desired_pitch = self.pitch;
+        self.desired_distance = self.distance;
+    }
+
+    /// Zoom scene using specified scroll wheel difference.
+    pub fn zoom(&mut self, rel: f32) {
+        self.desired_distance =
+            (self.desired_distance - rel * self.speed_from_distance()).max(MIN_DISTANCE);
+    }
+
+    /// Rotate camera using relative mouse movement over screen pixels.
+    pub fn rotate(&mut self, rel: &na::Vector2<f32>) {
+        self.desired_yaw -= rel.x * ROTATION_SPEED;
+        self.desired_pitch = clamp_pitch(self.desired_pitch + rel.y * ROTATION_SPEED);
+    }
+
+    pub fn speed_from_distance(&self) -> f32 {
+        let min_speed = 0.1;
+        let max_speed = 20.0;
+        let min_distance = 1.0;
+        let max_distance = 500.0;
+
+        if self.distance > max_distance {
+            max_speed
+        } else if self.distance < min_distance {
+            min_speed
+        } else {
+            (self.distance - min_distance) / (max_distance - min_distance) * (max_speed - min_speed)
+                + min_speed
+        }
+    }
+
+    fn rotation(&self) -> na::UnitQuaternion<f32> {
+        yaw_pitch_rotation(self.yaw, self.pitch)
+    }
+
+    fn isometry(&self) -> na::Isometry3<f32> {
+        na::Translation3::from(self.target.coords)
+            * self.rotation()
+            * na::Translation3::from(na::Vector3::z() * self.distance)
+    }
+
+    fn apply_movement(&mut self, delta: f32) {
+        let mut mov3 = self.movement.get_vector();
+
+        if self.project_pos().z < self.target.z {
+            mov3.y = -mov3.y;
+        }
+
+        // forward is along the view direction projected to the horizontal plane
+        let rotation = na::UnitQuaternion::from_axis_angle(&na::Vector3::z_axis(), self.yaw);
+        let xy = rotation * na::Vector3::new(mov3.x, mov3.y, 0.0);
+
+        if let Some(combined_movement) =
+            na::Vector3::new(xy.x, xy.y, mov3.z).try_normalize(0.01)
+        {
+            self.desired_target += combined_movement
+                * (if self.movement.faster { 75.0 } else { 25.0 })
+                * delta
+                * self.speed_from_distance();
+        }
+    }
+}
+
+impl Camera for OrbitCamera {
+    fn get_view_matrix(&self) -> na::Matrix4<f32> {
+        self.isometry().inverse().to_homogeneous()
+    }
+
+    fn get_p_matrix(&self) -> na::Matrix4<f32> {
+        self.projection.unwrap()
+    }
+
+    fn project_pos(&self) -> na::Point3<f32> {
+        self.isometry() * na::Point3::origin()
+    }
+
+    fn target(&self) -> na::Point3<f32> {
+        self.target
+    }
+
+    fn get_inverse_view_matrix(&self) -> na::Matrix4<f32> {
+        self.isometry().to_homogeneous()
+    }
+
+    fn update_aspect(&mut self, aspect: f32) {
+        self.projection.set_aspect(aspect);
+    }
+
+    fn handle_event(&mut self, e: &Event) {
+        match *e {
+            Event::MouseWheel { y, .. } => {
+                self.zoom(y as f32);
+            }
+            Event::MouseMotion {
+                xrel,
+                yrel,
+                buttons,
+                ..
+            } if buttons.right => {
+                self.rotate(&na::Vector2::new(xrel as f32, -yrel as f32));
+            }
+            _ => (),
+        }
+    }
+
+    fn handle_input(&mut self, input: &InputMap) {
+        self.movement.handle_input(input);
+    }
+
+    fn update(&mut self, delta: f32) -> bool {
+        if self.movement.has_movement() {
+            self.apply_movement(delta);
+        }
+        if self.movement.has_look() {
+            let look = self.movement.look * LOOK_AXIS_SPEED * delta;
+            self.rotate(&look);
+        }
+
+        let previous = (self.target, self.yaw, self.pitch, self.distance);
+
+        let t = smoothing_factor(self.smoothing, delta);
+        self.target += (self.desired_target - self.target) * t;
+        self.yaw += (self.desired_yaw - self.yaw) * t;
+        self.pitch += (self.desired_pitch - self.pitch) * t;
+        self.distance += (self.desired_distance - self.distance) * t;
+
+        previous != (self.target, self.yaw, self.pitch, self.distance)
+    }
+}
@@ -0,0 +1,187 @@
+use nalgebra as na;
+use ron;
+use crate::resources::{self, Resources};
+use super::{Camera, FlyCamera};
+use std::ops::{Add, Mul, Sub};
+
+/// Default `CameraPath::duration` in seconds.
+const DEFAULT_DURATION: f32 = 10.0;
+
+#[derive(Debug, Fail)]
+pub enum Error {
+    #[fail(display = "Failed to read or write camera path")]
+    Resource(#[cause] resources::Error),
+    #[fail(display = "Failed to parse camera path")]
+    Parse(#[cause] ron::de::Error),
+    #[fail(display = "Failed to serialize camera path")]
+    Serialize(#[cause] ron::ser::Error),
+}
+
+impl From<resources::Error> for Error {
+    fn from(other: resources::Error) -> Self {
+        Error::Resource(other)
+    }
+}
+
+impl From<ron::de::Error> for Error {
+    fn from(other: ron::de::Error) -> Self {
+        Error::Parse(other)
+    }
+}
+
+impl From<ron::ser::Error> for Error {
+    fn from(other: ron::ser::Error) -> Self {
+        Error::Serialize(other)
+    }
+}
+
+/// Camera pose dropped on the path, with the fly camera angles.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Keyframe {
+    pub position: [f32; 3],
+    pub yaw: f32,
+    pub pitch: f32,
+}
+
+/// Keyframes flown through by the fly camera, i.e. for showcase videos.
+///
+/// Keyframes are evenly spaced in time over `duration`, positions and angles follow
+/// Catmull-Rom splines through them. Saved as RON.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CameraPath {
+    pub keyframes: Vec<Keyframe>,
+    /// Seconds from the first to the last keyframe.
+    pub duration: f32,
+    /// Seconds into the playback, `None` when not playing.
+    #[serde(skip)]
+    playing: Option<f32>,
+}
+
+impl Default for CameraPath {
+    fn default() -> Self {
+        CameraPath {
+            keyframes: Vec::new(),
+            duration: DEFAULT_DURATION,
+            playing: None,
+        }
+    }
+}
+
+impl CameraPath {
+    pub fn load(res: &Resources, name: &str) -> Result<CameraPath, Error> {
+        Ok(ron::de::from_bytes(&res.load_bytes(name)?)?)
+    }
+
+    /// Path from the file, or an empty one if there is none or it can not be read.
+    pub fn load_or_default(res: &Resources, name: &str) -> CameraPath {
+        match CameraPath::load(res, name) {
+            Ok(path) => path,
+            Err(Error::Resource(ref e)) if e.is_not_found() => CameraPath::default(),
+            Err(e) => {
+                println!("Failed to load {}, starting an empty camera path: {}", name, e);
+                CameraPath::default()
+            }
+        }
+    }
+
+    pub fn save(&self, res: &Resources, name: &str) -> Result<(), Error> {
+        let text = ron::ser::to_string_pretty(self, Default::default())?;
+        res.write_bytes(name, text.as_bytes())?;
+        Ok(())
+    }
+
+    /// Append the current pose of the camera.
+    pub fn add_keyframe(&mut self, camera: &FlyCamera) {
+        let position = camera.project_pos();
+        self.keyframes.push(Keyframe {
+            position: [position.x, position.y, position.z],
+            yaw: camera.yaw(),
+            pitch: camera.pitch(),
+        });
+    }
+
+    pub fn clear(&mut self) {
+        self.keyframes.clear();
+        self.playing = None;
+    }
+
+    /// Start from the first keyframe, needs at least two.
+    pub fn play(&mut self) {
+        if self.keyframes.len() >= 2 {
+            self.playing = Some(0.0);
+        }
+    }
+
+    pub fn stop(&mut self) {
+        self.playing = None;
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.playing.is_some()
+    }
+
+    /// Advance the playback and place the camera on the path, stops after the last keyframe.
+    pub fn update(&mut self, delta: f32, camera: &mut FlyCamera) {
+        let time = match self.playing {
+            Some(time) => time + delta,
+            None => return,
+        };
+        if let Some((position, yaw, pitch)) = self.sample(time) {
+            camera.set_pose(position, yaw, pitch);
+        }
+        self.playing = if time < self.duration {
+            Some(time)
+        } else {
+            None
+        };
+    }
+
+    /// Position, yaw and pitch at `time` seconds, clamped to the path.
+    pub fn sample(&self, time: f32) -> Option<(na::Point3<f32>, f32, f32)> {
+        let last = self.keyframes.len().checked_sub(1)?;
+        if last == 0 {
+            let key = &self.keyframes[0];
+            return Some((key.position.into(), key.yaw, key.pitch));
+        }
+
+        let progress =
+            (time / self.duration.max(::std::f32::EPSILON)).max(0.0).min(1.0) * last as f32;
+        let index = (progress.floor() as usize).min(last - 1);
+        let t = progress - index as f32;
+        let key = |offset: isize| {
+            let index = (index as isize + offset).max(0).min(last as isize) as usize;
+            &self.keyframes[index]
+        };
+        let (k0, k1, k2, k3) = (key(-1), key(0), key(1), key(2));
+
+        let position = catmull_rom(
+            na::Vector3::from(k0.position),
+            na::Vector3::from(k1.position),
+            na::Vector3::from(k2.position),
+            na::Vector3::from(k3.position),
+            t,
+        );
+        let angles = catmull_rom(
+            na::Vector2::new(k0.yaw, k0.pitch),
+            na::Vector2::new(k1.yaw, k1.pitch),
+            na::Vector2::new(k2.yaw, k2.pitch),
+            na::Vector2::new(k3.yaw, k3.pitch),
+            t,
+        );
+        Some((na::Point3::from(position), angles.x, angles.y))
+    }
+}
+
+/// Uniform Catmull-Rom spline between `p1` and `p2`.
+fn catmull_rom<T>(p0: T, p1: T, p2: T, p3: T, t: f32) -> T
+where
+    T: Copy + Add<Output = T> + Sub<Output = T> + Mul<f32, Output = T>,
+{
+    let (t2, t3) = (t * t, t * t * t);
+    (p1 * 2.0
+        + (p2 - p0) * t
+        + (p0 * 2.0 - p1 * 5.0 + p2 * 4.0 - p3) * t2
+        + (p1 * 3.0 - p0 - p2 * 3.0 + p3) * t3)
+        * 0.5
+}
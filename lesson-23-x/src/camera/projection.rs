@@ -0,0 +1,100 @@
+use nalgebra as na;
+use ncollide3d::query::{Ray, RayCast};
+use ncollide3d::shape::Plane;
+
+/// Screen position to normalized device coordinates.
+///
+/// Screen positions are measured from the top left like mouse events, `size` is the
+/// screen size in the same units.
+pub fn screen_to_ndc(screen: &na::Point2<f32>, size: &na::Vector2<f32>) -> na::Point2<f32> {
+    na::Point2::new(
+        screen.x / size.x.max(1.0) * 2.0 - 1.0,
+        (1.0 - screen.y / size.y.max(1.0)) * 2.0 - 1.0,
+    )
+}
+
+/// Normalized device coordinates to screen position from the top left.
+pub fn ndc_to_screen(ndc: &na::Point2<f32>, size: &na::Vector2<f32>) -> na::Point2<f32> {
+    na::Point2::new(
+        (ndc.x + 1.0) * 0.5 * size.x,
+        (1.0 - (ndc.y + 1.0) * 0.5) * size.y,
+    )
+}
+
+/// Point in normalized device coordinates moved back to world space.
+pub fn ndc_to_world(
+    ndc: &na::Point3<f32>,
+    inverse_view_projection: &na::Matrix4<f32>,
+) -> na::Point3<f32> {
+    let p = inverse_view_projection * ndc.to_homogeneous();
+    na::Point3::new(p.x / p.w, p.y / p.w, p.z / p.w)
+}
+
+/// World point in normalized device coordinates, `None` behind the camera.
+pub fn world_to_ndc(
+    world: &na::Point3<f32>,
+    view_projection: &na::Matrix4<f32>,
+) -> Option<na::Point3<f32>> {
+    let clip = view_projection * world.to_homogeneous();
+    if clip.w <= 0.0 {
+        return None;
+    }
+    Some(na::Point3::new(
+        clip.x / clip.w,
+        clip.y / clip.w,
+        clip.z / clip.w,
+    ))
+}
+
+/// World ray through the screen position, starting at `depth` in normalized device
+/// coordinates (-1 is the near plane) and pointing away from the camera.
+///
+/// Returns `None` for a singular view projection.
+pub fn unproject(
+    screen: &na::Point2<f32>,
+    depth: f32,
+    size: &na::Vector2<f32>,
+    view_projection: &na::Matrix4<f32>,
+) -> Option<Ray<f32>> {
+    let inverse = view_projection.try_inverse()?;
+    let ndc = screen_to_ndc(screen, size);
+    let origin = ndc_to_world(&na::Point3::new(ndc.x, ndc.y, depth), &inverse);
+    let far = ndc_to_world(&na::Point3::new(ndc.x, ndc.y, 1.0), &inverse);
+    let dir = (far - origin).try_normalize(::std::f32::EPSILON)?;
+    Some(Ray::new(origin, dir))
+}
+
+/// Screen position of the world point from the top left, `None` behind the camera.
+pub fn project(
+    world: &na::Point3<f32>,
+    size: &na::Vector2<f32>,
+    view_projection: &na::Matrix4<f32>,
+) -> Option<na::Point2<f32>> {
+    world_to_ndc(world, view_projection)
+        .map(|ndc| ndc_to_screen(&na::Point2::new(ndc.x, ndc.y), size))
+}
+
+/// Distance along the ray to the plane through `point`, if the ray hits it.
+pub fn plane_toi(
+    ray: &Ray<f32>,
+    point: &na::Point3<f32>,
+    normal: &na::Unit<na::Vector3<f32>>,
+) -> Option<f32> {
+    let isometry = na::Isometry3::from_parts(
+        na::Translation3::from(point.coords),
+        na::UnitQuaternion::identity(),
+    );
+    Plane::new(*normal).toi_with_ray(&isometry, ray, true)
+}
+
+/// Point on the ground plane under the screen position, i.e. the mouse cursor.
+pub fn cursor_on_plane(
+    screen: &na::Point2<f32>,
+    size: &na::Vector2<f32>,
+    view_projection: &na::Matrix4<f32>,
+    point: &na::Point3<f32>,
+    normal: &na::Unit<na::Vector3<f32>>,
+) -> Option<na::Point3<f32>> {
+    let ray = unproject(screen, -1.0, size, view_projection)?;
+    plane_toi(&ray, point, normal).map(|toi| ray.origin + ray.dir * toi)
+}
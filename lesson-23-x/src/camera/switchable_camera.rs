@@ -0,0 +1,119 @@
+use super::{Camera, FlyCamera, OrbitCamera};
+use nalgebra as na;
+use crate::input::InputMap;
+use crate::platform::Event;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CameraKind {
+    Orbit,
+    Fly,
+}
+
+/// Orbit and fly cameras with one of them active, switching keeps the current view.
+pub struct SwitchableCamera {
+    pub orbit: OrbitCamera,
+    pub fly: FlyCamera,
+    kind: CameraKind,
+}
+
+impl SwitchableCamera {
+    pub fn new(orbit: OrbitCamera, fly: FlyCamera) -> SwitchableCamera {
+        SwitchableCamera {
+            orbit,
+            fly,
+            kind: CameraKind::Orbit,
+        }
+    }
+
+    pub fn kind(&self) -> CameraKind {
+        self.kind
+    }
+
+    pub fn set_kind(&mut self, kind: CameraKind) {
+        if kind == self.kind {
+            return;
+        }
+
+        match kind {
+            CameraKind::Fly => {
+                let position = self.orbit.project_pos();
+                self.fly
+                    .set_pose(position, self.orbit.yaw(), self.orbit.pitch());
+            }
+            CameraKind::Orbit => {
+                let target = self.fly.project_pos() + self.fly.direction() * self.orbit.distance();
+                let distance = self.orbit.distance();
+                self.orbit
+                    .set_pose(target, self.fly.yaw(), self.fly.pitch(), distance);
+            }
+        }
+
+        self.kind = kind;
+    }
+
+    pub fn switch(&mut self) {
+        let kind = match self.kind {
+            CameraKind::Orbit => CameraKind::Fly,
+            CameraKind::Fly => CameraKind::Orbit,
+        };
+        self.set_kind(kind);
+    }
+
+    fn active(&self) -> &dyn Camera {
+        match self.kind {
+            CameraKind::Orbit => &self.orbit,
+            CameraKind::Fly => &self.fly,
+        }
+    }
+
+    fn active_mut(&mut self) -> &mut dyn Camera {
+        match self.kind {
+            CameraKind::Orbit => &mut self.orbit,
+            CameraKind::Fly => &mut self.fly,
+        }
+    }
+}
+
+impl Camera for SwitchableCamera {
+    fn get_view_matrix(&self) -> na::Matrix4<f32> {
+        self.active().get_view_matrix()
+    }
+
+    fn get_p_matrix(&self) -> na::Matrix4<f32> {
+        self.active().get_p_matrix()
+    }
+
+    fn project_pos(&self) -> na::Point3<f32> {
+        self.active().project_pos()
+    }
+
+    fn target(&self) -> na::Point3<f32> {
+        self.active().target()
+    }
+
+    fn direction(&self) -> na::Vector3<f32> {
+        self.active().direction()
+    }
+
+    fn get_inverse_view_matrix(&self) -> na::Matrix4<f32> {
+        self.active().get_inverse_view_matrix()
+    }
+
+    /// Both cameras keep the aspect, so that switching does not distort the view.
+    fn update_aspect(&mut self, aspect: f32) {
+        self.orbit.update_aspect(aspect);
+        self.fly.update_aspect(aspect);
+    }
+
+    fn handle_event(&mut self, e: &Event) {
+        self.active_mut().handle_event(e);
+    }
+
+    fn handle_input(&mut self, input: &InputMap) {
+        self.active_mut().handle_input(input);
+    }
+
+    fn update(&mut self, delta: f32) -> bool {
+        self.active_mut().update(delta)
+    }
+}
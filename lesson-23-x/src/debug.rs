@@ -1,5 +1,13 @@
 use failure;
 
+/// Causes of the error on a single line, outermost first, i.e. for the status line.
+pub fn failure_to_line(e: failure::Error) -> String {
+    e.iter_chain()
+        .map(|cause| cause.to_string())
+        .collect::<Vec<_>>()
+        .join(": ")
+}
+
 pub fn failure_to_string(e: failure::Error) -> String {
     use std::fmt::Write;
 
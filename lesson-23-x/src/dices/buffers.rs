@@ -1,7 +1,8 @@
 use gl;
+use nalgebra as na;
 use crate::mesh;
-use crate::render_gl::buffer::{Buffer, VertexArray};
-use crate::render_gl::data;
+use crate::render_gl::buffer::{Buffer, IndexType, VertexArray};
+use crate::render_gl::{data, FrameStats, MorphTargets};
 
 #[derive(VertexAttribPointers, Copy, Clone, Debug)]
 #[repr(C, packed)]
@@ -10,48 +11,141 @@ pub struct ModelVertex {
     pub pos: data::f32_f32_f32,
     #[location = "1"]
     pub uv: data::f16_f16,
+    /// Tangent and normal are packed, positions stay full precision.
     #[location = "2"]
-    pub t: data::f32_f32_f32,
+    pub t: data::i2_i10_i10_i10_rev_float,
     #[location = "3"]
-    pub n: data::f32_f32_f32,
+    pub n: data::i2_i10_i10_i10_rev_float,
+    /// Locations 4 to 7 and 10 are taken by `InstanceVertex`.
+    #[location = "8"]
+    pub color: data::u8_u8_u8_u8_float,
+    #[location = "9"]
+    pub uv2: data::f16_f16,
+}
+
+/// Joints and weights of a skinned vertex, streamed only for meshes with a skeleton.
+#[derive(VertexAttribPointers, Copy, Clone, Debug)]
+#[repr(C, packed)]
+pub struct SkinVertex {
+    /// After `InstanceVertex` locations, read by `common/skinning.glsl`.
+    #[location = "11"]
+    pub joints: data::u16_u16_u16_u16,
+    #[location = "12"]
+    pub weights: data::f32_f32_f32_f32,
+}
+
+/// Per-instance model matrix, split into columns, and material layer.
+#[derive(VertexAttribPointers, Copy, Clone, Debug)]
+#[repr(C, packed)]
+pub struct InstanceVertex {
+    #[location = "4"]
+    #[divisor = "1"]
+    pub model_c0: data::f32_f32_f32_f32,
+    #[location = "5"]
+    #[divisor = "1"]
+    pub model_c1: data::f32_f32_f32_f32,
+    #[location = "6"]
+    #[divisor = "1"]
+    pub model_c2: data::f32_f32_f32_f32,
+    #[location = "7"]
+    #[divisor = "1"]
+    pub model_c3: data::f32_f32_f32_f32,
+    /// Layer of `render_gl::Texture2DArray` textures, after `ModelVertex` locations.
+    #[location = "10"]
+    #[divisor = "1"]
+    pub material: data::u16_,
+}
+
+impl InstanceVertex {
+    pub fn new(model: &na::Matrix4<f32>, material: u16) -> InstanceVertex {
+        let c = model.as_slice();
+        InstanceVertex {
+            model_c0: (c[0], c[1], c[2], c[3]).into(),
+            model_c1: (c[4], c[5], c[6], c[7]).into(),
+            model_c2: (c[8], c[9], c[10], c[11]).into(),
+            model_c3: (c[12], c[13], c[14], c[15]).into(),
+            material: material.into(),
+        }
+    }
 }
 
 pub struct Buffers {
     _vbo: Buffer,
     _ebo: Buffer,
+    /// Joints and weights of a mesh with a skeleton, blended by materials with `SKINNED`.
+    skin_vbo: Option<Buffer>,
+    instance_vbo: Option<Buffer>,
+    instance_count: i32,
     pub vao: VertexArray,
     pub index_count: i32,
+    /// 16-bit for meshes with few enough vertices.
+    index_type: IndexType,
+    /// Index ranges drawn with each material.
+    pub submeshes: Vec<mesh::Submesh>,
+    /// Deltas of a mesh with morph targets, blended by materials with `MORPH_TARGETS`.
+    pub morph_targets: Option<MorphTargets>,
 }
 
 impl Buffers {
     pub fn new(gl: &gl::Gl, mesh: &mesh::Mesh) -> Buffers {
+        Self::create(gl, mesh, false)
+    }
+
+    /// Create buffers with additional per-instance model matrix stream.
+    pub fn new_instanced(gl: &gl::Gl, mesh: &mesh::Mesh) -> Buffers {
+        Self::create(gl, mesh, true)
+    }
+
+    fn create(gl: &gl::Gl, mesh: &mesh::Mesh, instanced: bool) -> Buffers {
+        // generating tangents may split vertices, so indices are taken from the copy too
+        let mut mesh = mesh.clone();
+        mesh.ensure_tangents(mesh::TangentAlgorithm::AngleWeighted);
+
         let vbo_data = mesh
             .vertices
-            .clone()
-            .into_iter()
+            .iter()
             .map(|v| {
-                let tv = v.tangents.unwrap_or_else(|| {
-                    println!("Missing tangent vectors");
-                    mesh::Tangents::nans()
-                });
-                let uv = v.uv.unwrap_or_else(|| {
-                    println!("Missing uv vectors");
-                    [0.0, 0.0].into()
-                });
-                let normal = v.normal.unwrap_or_else(|| {
-                    println!("Missing normal vectors");
-                    [0.0, 0.0, 0.0].into()
-                });
+                let tv = v.tangents.unwrap_or_else(mesh::Tangents::nans);
+                let uv = v.uv.unwrap_or_else(|| [0.0, 0.0].into());
+                let normal = v.normal.unwrap_or_else(|| [0.0, 0.0, 0.0].into());
+                let color = v.color.unwrap_or_else(|| [1.0, 1.0, 1.0, 1.0].into());
+                // lightmaps fall back to the first coordinates
+                let uv2 = v.uv2.unwrap_or(uv);
                 ModelVertex {
                     pos: (v.pos.x, v.pos.y, v.pos.z).into(),
                     uv: (uv.x, -uv.y).into(),
-                    t: (tv.tangent.x, tv.tangent.y, tv.tangent.z).into(),
-                    n: (normal.x, normal.y, normal.z).into(),
+                    t: (tv.tangent.x, tv.tangent.y, tv.tangent.z, 0.0).into(),
+                    n: (normal.x, normal.y, normal.z, 0.0).into(),
+                    color: (color.x, color.y, color.z, color.w).into(),
+                    uv2: (uv2.x, -uv2.y).into(),
                 }
             }).collect::<Vec<_>>();
 
         let ebo_data = mesh.triangle_indices();
 
+        let skin_vbo = mesh.skeleton_index.map(|_| {
+            let skin_data = mesh
+                .vertices
+                .iter()
+                .map(|v| {
+                    // zero weights leave the vertex in bind space
+                    let bones = v.bones.unwrap_or(mesh::BoneWeights {
+                        joints: [0; 4],
+                        weights: [0.0; 4],
+                    });
+                    let (j, w) = (bones.joints, bones.weights);
+                    SkinVertex {
+                        joints: (j[0], j[1], j[2], j[3]).into(),
+                        weights: (w[0], w[1], w[2], w[3]).into(),
+                    }
+                }).collect::<Vec<_>>();
+            let skin_vbo = Buffer::new_array(gl);
+            skin_vbo.bind();
+            skin_vbo.static_draw_data(&skin_data);
+            skin_vbo.unbind();
+            skin_vbo
+        });
+
         let vbo = Buffer::new_array(gl);
         vbo.bind();
         vbo.stream_draw_data(&vbo_data);
@@ -59,7 +153,7 @@ impl Buffers {
 
         let ebo = Buffer::new_element_array(gl);
         ebo.bind();
-        ebo.stream_draw_data(&ebo_data);
+        let index_type = ebo.static_draw_indices(&ebo_data);
         ebo.unbind();
 
         // set up vertex array object
@@ -70,19 +164,89 @@ impl Buffers {
         vbo.bind();
         ebo.bind();
         ModelVertex::vertex_attrib_pointers(gl);
+
+        if let Some(ref skin_vbo) = skin_vbo {
+            skin_vbo.bind();
+            SkinVertex::vertex_attrib_pointers(gl);
+        }
+
+        let instance_vbo = if instanced {
+            let instance_vbo = Buffer::new_array(gl);
+            instance_vbo.bind();
+            InstanceVertex::vertex_attrib_pointers(gl);
+            Some(instance_vbo)
+        } else {
+            None
+        };
+
         vao.unbind();
 
         vbo.unbind();
         ebo.unbind();
 
+        if let Some(ref name) = mesh.name {
+            vao.set_label(name);
+            vbo.set_label(&format!("{} vertices", name));
+            ebo.set_label(&format!("{} indices", name));
+            if let Some(ref skin_vbo) = skin_vbo {
+                skin_vbo.set_label(&format!("{} joints", name));
+            }
+            if let Some(ref instance_vbo) = instance_vbo {
+                instance_vbo.set_label(&format!("{} instances", name));
+            }
+        }
+
         Buffers {
             _vbo: vbo,
             _ebo: ebo,
+            skin_vbo,
+            instance_vbo,
+            instance_count: 0,
             index_count: ebo_data.len() as i32,
+            index_type,
+            submeshes: mesh.draw_ranges(),
+            morph_targets: MorphTargets::new(gl, &mesh),
             vao,
         }
     }
 
+    /// Has joints and weights, to be drawn with bone matrices of the skeleton.
+    pub fn is_skinned(&self) -> bool {
+        self.skin_vbo.is_some()
+    }
+
+    /// Stream new model matrices and material layers for all instances, instances without
+    /// a material use layer 0.
+    pub fn update_instances(&mut self, transforms: &[na::Matrix4<f32>], materials: &[u16]) {
+        if let Some(ref instance_vbo) = self.instance_vbo {
+            let instance_data = transforms
+                .iter()
+                .enumerate()
+                .map(|(index, m)| {
+                    InstanceVertex::new(m, materials.get(index).cloned().unwrap_or(0))
+                }).collect::<Vec<_>>();
+
+            instance_vbo.bind();
+            instance_vbo.dynamic_draw_data(&instance_data);
+            instance_vbo.unbind();
+
+            self.instance_count = transforms.len() as i32;
+        }
+    }
+
+    pub fn render_instanced(&self) {
+        if self.instance_count == 0 {
+            return;
+        }
+
+        self.vao.draw_elements_instanced(
+            gl::TRIANGLES,
+            self.index_count,
+            self.index_type.gl_type(),
+            self.instance_count,
+        );
+    }
+
     pub fn render(&self, gl: &gl::Gl) {
         self.vao.bind();
 
@@ -90,11 +254,26 @@ impl Buffers {
             gl.DrawElements(
                 gl::TRIANGLES, // mode
                 self.index_count, // index vertex count
-                gl::UNSIGNED_INT, // index type
+                self.index_type.gl_type(), // index type
                 ::std::ptr::null(), // pointer to indices (we are using ebo configured at vao creation)
             );
         }
+        FrameStats::record_draw(gl::TRIANGLES, self.index_count, 1);
 
         self.vao.unbind();
     }
+
+    /// Draw only the indices of the submesh, the vertex array must be bound.
+    pub fn render_submesh(&self, gl: &gl::Gl, submesh: &mesh::Submesh) {
+        let indices = submesh.index_range();
+        unsafe {
+            gl.DrawElements(
+                gl::TRIANGLES,
+                indices.len() as i32,
+                self.index_type.gl_type(),
+                (indices.start * self.index_type.size()) as *const gl::types::GLvoid,
+            );
+        }
+        FrameStats::record_draw(gl::TRIANGLES, indices.len() as i32, 1);
+    }
 }
@@ -0,0 +1,231 @@
+use failure;
+use gl;
+use nalgebra as na;
+use crate::lights;
+use crate::render_gl::{self, DeferredGeometry, ProgramCache, ProgramPermutations};
+use crate::resources::{LoadErrors, ResourcePath, Resources, Watch};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use super::buffers::Buffers;
+use super::{
+    bind_lightmap, bind_material, bind_parallax, link_shiny, load_material, load_or_report,
+    reload_shared_model, share_model, shiny_variant, DiceMaterial, DiceModel, DEFAULT_MODEL,
+    SHINY_FEATURES,
+};
+
+const MATERIAL_FILES: [&str; 2] = ["shaders/shiny_instanced.vert", "shaders/shiny.frag"];
+const GEOMETRY_FILES: [&str; 2] = [
+    "shaders/deferred_geometry_instanced.vert",
+    "shaders/deferred_geometry.frag",
+];
+
+/// Many dice sharing the same mesh, rendered with a single instanced draw call.
+///
+/// Unlike `Dice`, instances are not selectable and have no debug markers. The whole mesh
+/// is drawn with textures of the first material, shared with `Dice` using the same model.
+/// With `set_skins`, each instance takes its color from a layer of a texture array instead,
+/// in the forward path only.
+///
+/// Like `Dice`, a model or programs that fail to load are drawn in magenta until `update`
+/// loads them again.
+pub struct DiceInstances {
+    model: Rc<DiceModel>,
+    /// Shiny program variants, picked by the features of the first material.
+    shiny: ProgramPermutations,
+    /// `LightClusters::supported`, shiny variants are then `CLUSTERED`.
+    clustered: bool,
+    geometry_material: render_gl::Material,
+    error: Rc<render_gl::Material>,
+    /// Shader files of programs that failed to load.
+    retry: RefCell<Option<Watch>>,
+    buffers: Buffers,
+    /// Color textures picked per instance by `materials`.
+    skins: Option<render_gl::Texture2DArray>,
+    /// Kept to fill buffers of a reloaded model.
+    transforms: Vec<na::Matrix4<f32>>,
+    materials: Vec<u16>,
+}
+
+impl DiceInstances {
+    pub fn new(res: &Resources, gl: &gl::Gl) -> Result<DiceInstances, failure::Error> {
+        let error_program = ProgramCache::error_program(gl, true)?;
+        let mut retry = None;
+        let shiny = shiny_permutations(gl, res, &mut retry);
+        let geometry_material = load_material(
+            load_program(gl, res, &GEOMETRY_FILES, &mut retry)
+                .unwrap_or_else(|| error_program.clone()),
+        );
+
+        let path: &ResourcePath = DEFAULT_MODEL.as_ref();
+        let model = match DiceModel::load_shared(res, gl, path) {
+            Ok(model) => model,
+            Err(e) => {
+                LoadErrors::report(path.as_clean_str(), &e);
+                share_model(path, DiceModel::placeholder(res, gl, path))
+            }
+        };
+
+        Ok(DiceInstances {
+            buffers: Buffers::new_instanced(gl, &model.mesh),
+            model,
+            shiny,
+            clustered: lights::LightClusters::supported(gl),
+            geometry_material,
+            error: Rc::new(load_material(error_program)),
+            retry: RefCell::new(retry),
+            skins: None,
+            transforms: Vec::new(),
+            materials: Vec::new(),
+        })
+    }
+
+    pub fn set_transforms(&mut self, transforms: &[na::Isometry3<f32>]) {
+        self.transforms = transforms.iter().map(|t| t.to_homogeneous()).collect();
+        self.buffers
+            .update_instances(&self.transforms, &self.materials);
+    }
+
+    /// Color textures of the instances, scaled to the size of the first one.
+    pub fn set_skins(
+        &mut self,
+        gl: &gl::Gl,
+        res: &Resources,
+        skins: &[&ResourcePath],
+    ) -> Result<(), render_gl::TextureArrayError> {
+        self.skins = Some(render_gl::Texture2DArray::from_res(
+            gl,
+            res,
+            skins,
+            render_gl::ColorSpace::Srgb,
+            render_gl::LayerSize::Resize,
+        )?);
+        Ok(())
+    }
+
+    /// Skin of each instance in the order of `set_transforms`, missing ones use the first.
+    pub fn set_materials(&mut self, materials: &[u16]) {
+        self.materials = materials.to_vec();
+        self.buffers
+            .update_instances(&self.transforms, &self.materials);
+    }
+
+    /// Load failed programs again once their files change, and switch to the model
+    /// reloaded by any of its users.
+    pub fn update(&mut self, gl: &gl::Gl, res: &Resources) {
+        if self.retry.get_mut().as_mut().map_or(false, |retry| retry.changed()) {
+            let mut retry = None;
+            self.shiny = shiny_permutations(gl, res, &mut retry);
+            if let Some(program) = load_program(gl, res, &GEOMETRY_FILES, &mut retry) {
+                self.geometry_material = load_material(program);
+            }
+            *self.retry.get_mut() = retry;
+        }
+
+        if let Some(model) = reload_shared_model(res, gl, DEFAULT_MODEL.as_ref(), &self.model) {
+            self.buffers = Buffers::new_instanced(gl, &model.mesh);
+            self.buffers
+                .update_instances(&self.transforms, &self.materials);
+            self.model = model;
+        }
+    }
+
+    pub fn render(&self, shadow_map: &render_gl::ShadowMap, parallax_mapping: bool) {
+        if self.model.placeholder {
+            self.render_error();
+            return;
+        }
+        let first = self.model.materials.first();
+        let mut features = DiceMaterial::shiny_features(first, parallax_mapping);
+        if self.skins.is_some() {
+            features.push("TEXTURE_ARRAY");
+        }
+        let shiny = shiny_variant(
+            &self.shiny,
+            &features,
+            self.clustered,
+            &self.error,
+            &self.retry,
+        );
+        shiny.set_used();
+        DiceMaterial::raster_state_of(first).apply(shiny.program().gl());
+
+        // model matrix comes from instance attributes
+        let (texture, texture_normals) = self.textures();
+        bind_material(
+            &shiny,
+            &na::Matrix4::identity(),
+            texture,
+            texture_normals,
+            Some(shadow_map),
+        );
+        bind_parallax(&shiny, first, parallax_mapping);
+        bind_lightmap(&shiny, first);
+        if let Some(ref skins) = self.skins {
+            shiny.set_texture_array("Skins", skins);
+        }
+
+        self.buffers.render_instanced();
+    }
+
+    fn render_error(&self) {
+        self.error.set_used();
+        self.buffers.render_instanced();
+    }
+
+    /// Instances always use the shiny program, with textures of the first material.
+    fn textures(&self) -> (Option<&render_gl::Texture>, Option<&render_gl::Texture>) {
+        DiceMaterial::textures_of(self.model.materials.first())
+    }
+
+    pub fn render_depth(&self, pass: &render_gl::ShadowPass) {
+        pass.prepare_instanced();
+        self.buffers.render_instanced();
+    }
+}
+
+impl DeferredGeometry for DiceInstances {
+    fn render_geometry(&self, gl: &gl::Gl, _pass: &render_gl::GeometryPass) {
+        if self.model.placeholder {
+            self.render_error();
+            return;
+        }
+        self.geometry_material.set_used();
+        DiceMaterial::raster_state_of(self.model.materials.first()).apply(gl);
+
+        let (texture, texture_normals) = self.textures();
+        bind_material(
+            &self.geometry_material,
+            &na::Matrix4::identity(),
+            texture,
+            texture_normals,
+            None,
+        );
+
+        self.buffers.render_instanced();
+    }
+}
+
+/// Shiny variants of the instanced program, see `Dice` for the features.
+fn shiny_permutations(
+    gl: &gl::Gl,
+    res: &Resources,
+    retry: &mut Option<Watch>,
+) -> ProgramPermutations {
+    let shiny = ProgramPermutations::from_res_files(gl, res, &MATERIAL_FILES, &SHINY_FEATURES)
+        .with_setup(load_material);
+    link_shiny(res, &shiny, retry);
+    shiny
+}
+
+/// Program linked from the files, `None` after reporting the failure.
+fn load_program(
+    gl: &gl::Gl,
+    res: &Resources,
+    files: &[&str],
+    retry: &mut Option<Watch>,
+) -> Option<Rc<render_gl::Program>> {
+    load_or_report(res, &files.join(", "), files, retry, || {
+        ProgramCache::from_res_files(gl, res, files)
+    })
+}
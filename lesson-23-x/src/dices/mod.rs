@@ -1,24 +1,109 @@
 use failure;
 use gl;
+use image;
 use nalgebra as na;
-use crate::render_gl::{self, DebugLines};
-use crate::resources::Resources;
+use ncollide3d::bounding_volume::aabb::AABB;
+use crate::animation::{Easing, Tween};
+use crate::entity::{DrawKey, Entity, RenderContext, UpdateContext};
+use crate::lights;
+use crate::mesh;
+use crate::physics;
+use crate::render;
+use crate::render_gl::{
+    self, pbr, Cullable, DebugLines, DeferredGeometry, ProgramCache, ProgramPermutations,
+    UploadQueue, DEFORM_FEATURES,
+};
+use crate::resources::{
+    Cache, CompressedImage, LoadErrors, LoadHandle, ResourcePath, ResourcePathBuf, Resources,
+    Watch,
+};
+use crate::scene;
 use crate::selection::{self, SelectableAABB, Selectables};
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
 
 mod buffers;
-mod dice_material;
+mod instances;
 
 use self::buffers::Buffers;
+pub use self::instances::DiceInstances;
 
+/// Model used by `Dice::new`.
+pub const DEFAULT_MODEL: &str = "objs/dice.obj";
+
+/// Camera distances up to which each level of detail is used, the last level is used beyond.
+const LOD_DISTANCES: [f32; 3] = [20.0, 40.0, ::std::f32::INFINITY];
+/// Clustering resolution of the first generated level.
+const LOD_RESOLUTION: u32 = 16;
+/// Share of the reflection probe color added to the shiny material.
+const REFLECTION_STRENGTH: f32 = 0.25;
+/// Depth of the height map surface in UV units, for materials with a height map.
+const PARALLAX_SCALE: f32 = 0.02;
+/// Most height map samples taken along the view ray, at grazing angles.
+const PARALLAX_STEPS: i32 = 32;
+
+/// Optional parts of the shiny program, see `DiceMaterial::shiny_features`.
+const SHINY_FEATURES: [&str; 8] = [
+    "NORMAL_MAP",
+    "PARALLAX_MAP",
+    "VERTEX_COLOR",
+    "LIGHTMAP",
+    "TEXTURE_ARRAY",
+    "MORPH_TARGETS",
+    "SKINNED",
+    "CLUSTERED",
+];
+
+/// Most normal and tangent rays drawn for one dice, dense meshes draw every Nth vertex.
+const MAX_DEBUG_RAYS: usize = 2048;
+/// Time a clicked dice takes to turn its front to the camera.
+const TURN_SECONDS: f32 = 0.6;
+
+thread_local! {
+    /// Uploaded models by path, there is one GL context per thread.
+    static MODELS: Cache<DiceModel> = Cache::new();
+}
+
+/// Dice with model loaded in background. Until the model arrives, only a placeholder
+/// box is drawn with debug lines.
+///
+/// A model that fails to load is replaced by a magenta box, and shaders that fail by the
+/// error program. Both are listed by `LoadErrors` and loaded again once their files change.
+///
+/// Programs, buffers and textures are shared by all dice with the same model, only the
+/// transform, selectable and body are per dice.
 pub struct Dice {
-    transform: na::Isometry3<f32>,
-    program: render_gl::Program,
-    texture: Option<render_gl::Texture>,
-    texture_normals: Option<render_gl::Texture>,
-    material: dice_material::Material,
-    buffers: Buffers,
+    node: scene::Node,
+    model_path: ResourcePathBuf,
+    /// Non-uniform scale set with the gizmo, applied before node transform.
+    scale: na::Vector3<f32>,
+    /// Below 1 the dice is drawn as glass in the blended pass.
+    opacity: f32,
+    programs: Rc<DicePrograms>,
+    pending: Option<PendingModel>,
+    model: Option<Rc<DiceModel>>,
+    /// Index of the current level in `DiceModel::levels`.
+    lod: Option<scene::Lod<usize>>,
+    aabb: Option<AABB<f32>>,
+    /// Joint bounds of a model with a skeleton, to refit `aabb` for a pose.
+    skinned_bounds: Option<mesh::SkinnedBounds>,
+    /// Weight of each morph target of the model, blended by the shiny program.
+    morph_weights: Vec<f32>,
+    /// Time into the animation clip played by a model with a skeleton.
+    animation_time: f32,
+    /// Skinning matrices of the current pose, empty for models without a skeleton.
+    bone_matrices: Vec<na::Matrix4<f32>>,
+    placeholder: Option<render_gl::AabbMarker>,
     debug_tangent_normals: render_gl::RayMarkers,
     selectable_aabb: Option<SelectableAABB>,
+    physics: Option<physics::World>,
+    body: Option<physics::RigidBody>,
+    /// Body transform of the previous and the current update, while the body moves.
+    body_isometries: Option<(na::Isometry3<f32>, na::Isometry3<f32>)>,
+    /// Clicked since the last update, the turn starts once the camera position is known.
+    turn_requested: bool,
+    turn: Option<Tween<na::UnitQuaternion<f32>>>,
 }
 
 impl Dice {
@@ -26,76 +111,276 @@ impl Dice {
         res: &Resources,
         gl: &gl::Gl,
         debug_lines: &DebugLines,
-        selectables: &Selectables,
+        scene: &scene::Scene,
     ) -> Result<Dice, failure::Error> {
-        // set up shader program
+        Dice::with_model(res, gl, debug_lines, scene, DEFAULT_MODEL)
+    }
 
-        let program = render_gl::Program::from_res(gl, res, "shaders/shiny")?;
-        let p_material = dice_material::Material::load_for(&program);
+    /// Dice with another obj or glTF model.
+    pub fn with_model<P: AsRef<ResourcePath>>(
+        res: &Resources,
+        gl: &gl::Gl,
+        debug_lines: &DebugLines,
+        scene: &scene::Scene,
+        model_path: P,
+    ) -> Result<Dice, failure::Error> {
+        let programs = Rc::new(DicePrograms::new(gl, res)?);
+        Ok(Dice::create(
+            res,
+            debug_lines,
+            scene,
+            ResourcePathBuf::from(model_path.as_ref()),
+            programs,
+            na::Isometry3::identity(),
+        ))
+    }
 
-        // this loader does not support file names with spaces
-        let imported_models = res.load_obj("objs/dice.obj")?;
+    /// New dice at the same place, with the same model, scale and opacity.
+    ///
+    /// Programs and the uploaded model are shared, the clone has its own transform,
+    /// selectable and body. It is not attached to the parent of this dice.
+    pub fn spawn_clone(
+        &self,
+        res: &Resources,
+        debug_lines: &DebugLines,
+        scene: &scene::Scene,
+    ) -> Dice {
+        let mut dice = Dice::create(
+            res,
+            debug_lines,
+            scene,
+            self.model_path.clone(),
+            self.programs.clone(),
+            self.node.world_transform(),
+        );
+        dice.scale = self.scale;
+        dice.opacity = self.opacity;
+        dice.physics = self.physics.clone();
+        dice
+    }
 
-        // take first material in obj
-        let material = imported_models.materials.into_iter().next();
-        let material_index = material.as_ref().map(|_| 0); // it is first or None
+    fn create(
+        res: &Resources,
+        debug_lines: &DebugLines,
+        scene: &scene::Scene,
+        model_path: ResourcePathBuf,
+        programs: Rc<DicePrograms>,
+        isometry: na::Isometry3<f32>,
+    ) -> Dice {
+        let pending = match shared_model(&model_path) {
+            Some(model) => PendingModel::Shared(model),
+            None => {
+                let load_path = model_path.clone();
+                PendingModel::Loading(Box::new(
+                    res.load_async(move |res| DiceModelData::load(res, &load_path)),
+                ))
+            }
+        };
 
-        let texture = material.as_ref().and_then(|m| {
-            m.diffuse_map.as_ref().and_then(|resource_path| {
-                render_gl::Texture::from_res_rgb(&resource_path)
-                    .with_gen_mipmaps()
-                    .load(gl, res)
-                    .map_err(|e| println!("Error loading {}: {}", resource_path, e))
-                    .ok()
-            })
-        });
-        let texture_normals = material.as_ref().and_then(|m| {
-            m.bump_map.as_ref().and_then(|resource_path| {
-                render_gl::Texture::from_res_rgb(&resource_path)
-                    .with_gen_mipmaps()
-                    .load(gl, res)
-                    .map_err(|e| println!("Error loading {}: {}", resource_path, e))
-                    .ok()
-            })
-        });
+        Dice {
+            node: scene.node(isometry, None),
+            model_path,
+            scale: na::Vector3::new(1.0, 1.0, 1.0),
+            opacity: 1.0,
+            programs,
+            pending: Some(pending),
+            model: None,
+            lod: None,
+            aabb: None,
+            skinned_bounds: None,
+            morph_weights: Vec::new(),
+            animation_time: 0.0,
+            bone_matrices: Vec::new(),
+            placeholder: Some(debug_lines.aabb_marker(
+                isometry,
+                AABB::new([-1.0, -1.0, 0.0].into(), [1.0, 1.0, 2.0].into()),
+                na::Vector4::new(0.5, 0.5, 0.5, 1.0),
+            )),
+            debug_tangent_normals: debug_lines.ray_markers_with_density(
+                isometry,
+                render_gl::RayDensity::default().with_max_count(MAX_DEBUG_RAYS),
+                None.into_iter(),
+            ),
+            selectable_aabb: None,
+            physics: None,
+            body: None,
+            body_isometries: None,
+            turn_requested: false,
+            turn: None,
+        }
+    }
 
-        // match mesh to material id and get the mesh
-        let mesh = imported_models
-            .meshes
-            .into_iter()
-            .filter(|model| model.material_index == material_index)
-            .next()
-            .expect("expected obj file to contain a mesh");
+    /// Upload the model once its background load has finished and the frame
+    /// upload budget allows it, unless another dice has uploaded it already.
+    pub fn poll_loading(
+        &mut self,
+        gl: &gl::Gl,
+        res: &Resources,
+        uploads: &UploadQueue,
+        selectables: &Selectables,
+    ) {
+        let model = match self.pending.take() {
+            Some(PendingModel::Shared(model)) => model,
+            Some(PendingModel::Loading(mut loading)) => {
+                if let Some(model) = shared_model(&self.model_path) {
+                    model
+                } else if !loading.is_ready() || !uploads.try_acquire() {
+                    self.pending = Some(PendingModel::Loading(loading));
+                    return;
+                } else {
+                    let path = &self.model_path;
+                    match loading.take() {
+                        Some(Ok(data)) => {
+                            LoadErrors::resolve(path.as_clean_str());
+                            share_model(path, DiceModel::upload(gl, data))
+                        }
+                        Some(Err(e)) => {
+                            LoadErrors::report(path.as_clean_str(), &e);
+                            share_model(path, DiceModel::placeholder(res, gl, path))
+                        }
+                        None => return,
+                    }
+                }
+            }
+            None => return,
+        };
 
-        let initial_isometry = na::Isometry3::identity();
+        self.apply_model(model, selectables);
+    }
 
-        Ok(Dice {
-            transform: initial_isometry,
-            texture,
-            texture_normals,
-            program,
-            material: p_material,
-            buffers: Buffers::new(gl, &mesh),
-            debug_tangent_normals: debug_lines.ray_markers(
-                initial_isometry,
-                mesh.vertices
-                    .iter()
-                    .filter_map(|v| v.normal.map(|n| (v.pos, n)))
-                    .map(|(p, n)| (p, n * 0.2, na::Vector4::new(0.0, 0.0, 1.0, 1.0)))
-                    .chain(
-                        mesh.vertices
-                            .iter()
-                            .filter_map(|v| v.tangents.map(|t| (v.pos, t.tangent)))
-                            .map(|(p, n)| (p, n * 0.2, na::Vector4::new(0.0, 1.0, 0.0, 1.0))),
-                    ),
-            ),
-            selectable_aabb: mesh
-                .aabb()
-                .map(|aabb| selectables.selectable(aabb, initial_isometry)),
-        })
+    /// Reload the model if its files have changed, keeping transform and selection.
+    ///
+    /// The first dice to notice the change reloads the model, the others pick it up
+    /// from the cache.
+    pub fn reload_if_changed(&mut self, gl: &gl::Gl, res: &Resources, selectables: &Selectables) {
+        let current = match self.model {
+            Some(ref model) => model.clone(),
+            None => return,
+        };
+
+        if let Some(model) = reload_shared_model(res, gl, &self.model_path, &current) {
+            self.apply_model(model, selectables);
+        }
     }
 
-    pub fn update(&mut self, _delta: f32) {
+    /// Switch to programs loaded again after shader files of failed ones changed.
+    ///
+    /// The first dice to notice the change loads the programs, the others follow it.
+    fn reload_programs_if_changed(&mut self, gl: &gl::Gl, res: &Resources) {
+        loop {
+            let reloaded = self.programs.reloaded.borrow().clone();
+            match reloaded {
+                Some(programs) => self.programs = programs,
+                None => break,
+            }
+        }
+
+        let changed = self
+            .programs
+            .retry
+            .borrow_mut()
+            .as_mut()
+            .map_or(false, |retry| retry.changed());
+        if !changed {
+            return;
+        }
+        match DicePrograms::new(gl, res) {
+            Ok(programs) => {
+                let programs = Rc::new(programs);
+                *self.programs.reloaded.borrow_mut() = Some(programs.clone());
+                self.programs = programs;
+            }
+            Err(e) => println!("Error reloading dice programs: {}", e),
+        }
+    }
+
+    /// Replace buffers and textures. Existing selectable only gets new bounds.
+    fn apply_model(&mut self, model: Rc<DiceModel>, selectables: &Selectables) {
+        let mesh = &model.mesh;
+
+        self.debug_tangent_normals.update_rays(
+            mesh.vertices
+                .iter()
+                .filter_map(|v| v.normal.map(|n| (v.pos, n)))
+                .map(|(p, n)| (p, n * 0.2, na::Vector4::new(0.0, 0.0, 1.0, 1.0)))
+                .chain(
+                    mesh.vertices
+                        .iter()
+                        .filter_map(|v| v.tangents.map(|t| (v.pos, t.tangent)))
+                        .map(|(p, n)| (p, n * 0.2, na::Vector4::new(0.0, 1.0, 0.0, 1.0))),
+                ),
+        );
+        let isometry = self.node.world_transform();
+        self.aabb = mesh.aabb();
+        self.skinned_bounds = mesh.skeleton_index.map(|_| mesh::SkinnedBounds::new(mesh));
+        self.morph_weights = mesh.morph_weights();
+        // bind pose until the first update
+        self.animation_time = 0.0;
+        self.bone_matrices = model.skeleton.as_ref().map_or_else(Vec::new, |skeleton| {
+            vec![na::Matrix4::identity(); skeleton.joints.len()]
+        });
+        let vertices = mesh.vertices.iter().map(|v| v.pos).collect();
+        self.selectable_aabb = match (self.selectable_aabb.take(), self.aabb.clone()) {
+            // the triangles would stay in the bind pose, the posed bounds are picked instead
+            (Some(selectable), Some(aabb)) if self.skinned_bounds.is_some() => {
+                selectable.update_aabb(aabb);
+                selectable.clear_mesh();
+                Some(selectable)
+            }
+            (None, Some(aabb)) if self.skinned_bounds.is_some() => {
+                let selectable = selectables.selectable(aabb, isometry);
+                selectable.update_scale(self.scale);
+                Some(selectable)
+            }
+            (Some(selectable), Some(aabb)) => {
+                selectable.update_aabb(aabb);
+                selectable.update_mesh(vertices, &mesh.triangle_indices());
+                Some(selectable)
+            }
+            (None, Some(aabb)) => {
+                let selectable = selectables.selectable_with_mesh(
+                    aabb,
+                    isometry,
+                    vertices,
+                    &mesh.triangle_indices(),
+                );
+                selectable.update_scale(self.scale);
+                Some(selectable)
+            }
+            (_, None) => None,
+        };
+        self.placeholder = None;
+        let levels = LOD_DISTANCES
+            .iter()
+            .cloned()
+            .zip(0..model.levels.len())
+            .collect();
+        self.lod = Some(scene::Lod::new(levels));
+        self.create_body(mesh);
+        self.model = Some(model);
+    }
+
+    /// Simulate the dice as a rigid body once its model is loaded. The dice should not
+    /// have a parent, because the body transform is written to the local transform.
+    pub fn enable_physics(&mut self, world: &physics::World) {
+        self.physics = Some(world.clone());
+    }
+
+    fn create_body(&mut self, mesh: &mesh::Mesh) {
+        let world = match self.physics {
+            Some(ref world) => world,
+            None => return,
+        };
+        let scale = self.scale;
+        let points: Vec<_> = mesh
+            .vertices
+            .iter()
+            .map(|v| na::Point3::from(v.pos.coords.component_mul(&scale)))
+            .collect();
+        self.body = world.add_convex_body(&points, self.node.world_transform(), 1.0);
+    }
+
+    pub fn update(&mut self, delta: f32) {
         loop {
             let action = self
                 .selectable_aabb
@@ -103,40 +388,1331 @@ impl Dice {
                 .and_then(|s| s.drain_pending_action());
 
             match action {
-                Some(selection::Action::Click) => {
+                Some(selection::Action::Click { .. }) => {
                     self.selectable_aabb.as_ref().map(|s| s.select());
+                    self.turn_requested = true;
+                }
+                Some(selection::Action::Drag { new_isometry }) => {
+                    // dragging takes over from the turn of the click
+                    self.turn_requested = false;
+                    self.turn = None;
+                    self.node.set_world_transform(new_isometry);
+                    if let Some(ref body) = self.body {
+                        body.set_isometry(new_isometry);
+                    }
+                    // moved by hand, there is nothing to blend from
+                    self.body_isometries = None;
+                }
+                Some(selection::Action::Scale { new_scale }) => {
+                    self.scale = new_scale;
+                    if let Some(ref selectable) = self.selectable_aabb {
+                        selectable.update_scale(new_scale);
+                    }
                 }
-                Some(selection::Action::Drag { new_isometry }) => self.set_transform(new_isometry),
                 _ => break,
             }
         }
+
+        if let Some(mut turn) = self.turn.take() {
+            let rotation = turn.update(delta);
+            let isometry =
+                na::Isometry3::from_parts(self.node.world_transform().translation, rotation);
+            self.node.set_world_transform(isometry);
+            if let Some(ref body) = self.body {
+                body.set_isometry(isometry);
+            }
+            self.body_isometries = None;
+            if !turn.is_finished() {
+                self.turn = Some(turn);
+            }
+        }
+
+        let body_isometry = match self.body {
+            Some(ref body) if !body.is_sleeping() => Some(body.isometry()),
+            _ => None,
+        };
+        self.body_isometries = body_isometry.map(|current| {
+            let previous = self.body_isometries.map(|(_, c)| c).unwrap_or(current);
+            (previous, current)
+        });
+        if let Some(isometry) = body_isometry {
+            self.set_transform(isometry);
+        }
+
+        self.update_pose(delta);
+
+        // this dice or any of its parents may have moved
+        if let Some(isometry) = self.node.take_changed_world_transform() {
+            if let Some(ref selectable) = self.selectable_aabb {
+                selectable.update_isometry(isometry);
+            }
+            if let Some(ref placeholder) = self.placeholder {
+                placeholder.update_isometry(isometry);
+            }
+            self.debug_tangent_normals.update_isometry(isometry);
+        }
     }
 
-    pub fn set_transform(&mut self, isometry: na::Isometry3<f32>) {
-        self.transform = isometry;
+    /// Play the first animation clip of a model with a skeleton, looping it.
+    fn update_pose(&mut self, delta: f32) {
+        let model = match self.model {
+            Some(ref model) => model.clone(),
+            None => return,
+        };
+        let (skeleton, clip) = match (&model.skeleton, model.animations.first()) {
+            (Some(skeleton), Some(clip)) => (skeleton, clip),
+            _ => return,
+        };
+        self.animation_time += delta;
+        if clip.duration > 0.0 {
+            self.animation_time %= clip.duration;
+        }
+        let mut pose = mesh::Pose::new(skeleton, clip);
+        let bone_matrices = pose.sample(self.animation_time).to_vec();
+        self.update_pose_bounds(&bone_matrices);
+        self.bone_matrices = bone_matrices;
+    }
+
+    /// Turn around the world Z axis until the front, local -Y, faces `target`, i.e. the
+    /// camera. Drags stop the turn.
+    pub fn turn_towards(&mut self, target: &na::Point3<f32>) {
+        let isometry = self.node.world_transform();
+        let to_target = target - na::Point3::from(isometry.translation.vector);
+        let front = isometry.rotation * -na::Vector3::y();
+        let flat = |v: na::Vector3<f32>| na::Vector2::new(v.x, v.y).try_normalize(1.0e-4);
+        let (front, to_target) = match (flat(front), flat(to_target)) {
+            (Some(front), Some(to_target)) => (front, to_target),
+            // front points up or the target is straight above
+            _ => return,
+        };
+
+        let angle = (front.x * to_target.y - front.y * to_target.x).atan2(front.dot(&to_target));
+        let rotation =
+            na::UnitQuaternion::from_axis_angle(&na::Vector3::z_axis(), angle) * isometry.rotation;
+        self.turn = Some(Tween::between(
+            isometry.rotation,
+            rotation,
+            TURN_SECONDS,
+            Easing::CubicInOut,
+        ));
+    }
+
+    /// Place between the body transforms of the last two updates.
+    pub fn interpolate(&mut self, alpha: f32) {
+        if let Some((previous, current)) = self.body_isometries {
+            let translation = previous
+                .translation
+                .vector
+                .lerp(&current.translation.vector, alpha);
+            let rotation = previous.rotation.slerp(&current.rotation, alpha);
+            self.set_transform(na::Isometry3::from_parts(
+                na::Translation3::from(translation),
+                rotation,
+            ));
+        }
+    }
+
+    /// Select level of detail from the distance to the camera.
+    pub fn update_lod(&mut self, camera_pos: &na::Point3<f32>) {
+        let position = self.node.world_transform() * na::Point3::origin();
+        let distance = na::distance(&position, camera_pos);
+        if let Some(ref mut lod) = self.lod {
+            lod.select(distance);
+        }
+    }
+
+    /// Current level of detail, 0 is the full model.
+    pub fn lod_level(&self) -> Option<usize> {
+        self.lod.as_ref().map(|lod| lod.current_level())
+    }
+
+    /// Buffers of the current level of detail, once the model is loaded.
+    fn current_buffers(&self) -> Option<&Buffers> {
+        match (&self.model, &self.lod) {
+            (Some(model), Some(lod)) => Some(&model.levels[*lod.current()]),
+            _ => None,
+        }
+    }
+
+    /// Materials of the model, indexed by submesh material index.
+    fn materials(&self) -> &[DiceMaterial] {
+        self.model
+            .as_ref()
+            .map(|model| &model.materials[..])
+            .unwrap_or(&[])
+    }
+
+    pub fn node(&self) -> &scene::Node {
+        &self.node
+    }
+
+    /// Attach to the parent node, keeping current world position.
+    pub fn set_parent(&self, parent: Option<&scene::Node>) {
+        self.node.set_parent(parent);
+    }
+
+    /// Set non-uniform scale, applied before the transform.
+    pub fn set_scale(&mut self, scale: na::Vector3<f32>) {
+        self.scale = scale;
+        if let Some(ref selectable) = self.selectable_aabb {
+            selectable.update_scale(scale);
+        }
+    }
+
+    /// Replace the bounds after the mesh deformed, for culling and picking.
+    pub fn update_bounds(&mut self, aabb: AABB<f32>) {
         if let Some(ref selectable) = self.selectable_aabb {
-            selectable.update_isometry(isometry);
+            selectable.update_aabb(aabb.clone());
+        }
+        self.aabb = Some(aabb);
+    }
+
+    /// Refit the bounds of a model with a skeleton to the bone matrices of a `mesh::Pose`.
+    ///
+    /// Does nothing for models without a skeleton.
+    pub fn update_pose_bounds(&mut self, bone_matrices: &[na::Matrix4<f32>]) {
+        let aabb = self
+            .skinned_bounds
+            .as_ref()
+            .and_then(|bounds| bounds.aabb(bone_matrices));
+        if let Some(aabb) = aabb {
+            self.update_bounds(aabb);
+        }
+    }
+
+    /// Morph targets of the model, zero until it is loaded.
+    pub fn morph_target_count(&self) -> usize {
+        self.model
+            .as_ref()
+            .map_or(0, |model| model.mesh.morph_targets.len())
+    }
+
+    /// Blend the morph targets of the model with the weights, missing weights are zero.
+    ///
+    /// Bounds and picking triangles are refit to the morphed mesh, so this is meant for
+    /// weights that change now and then rather than every frame. Targets are blended in
+    /// every pass the dice is drawn in, including shadows, outlines and deferred geometry.
+    pub fn set_morph_weights(&mut self, weights: &[f32]) {
+        self.morph_weights = weights.to_vec();
+        let mesh = match self.model {
+            Some(ref model) if !model.mesh.morph_targets.is_empty() => {
+                model.mesh.morphed(weights)
+            }
+            _ => return,
+        };
+        if let Some(aabb) = mesh.aabb() {
+            if let Some(ref selectable) = self.selectable_aabb {
+                selectable.update_aabb(aabb.clone());
+                let vertices = mesh.vertices.iter().map(|v| v.pos).collect();
+                selectable.update_mesh(vertices, &mesh.triangle_indices());
+            }
+            self.aabb = Some(aabb);
         }
-        self.debug_tangent_normals.update_isometry(isometry);
+    }
+
+    /// Opacity of the shiny material, dice with any PBR material stay opaque.
+    pub fn set_opacity(&mut self, opacity: f32) {
+        self.opacity = opacity.max(0.0).min(1.0);
+    }
+
+    pub fn opacity(&self) -> f32 {
+        self.opacity
+    }
+
+    /// Drawn in the blended pass.
+    pub fn is_transparent(&self) -> bool {
+        self.opacity < 1.0 && self.materials().iter().all(|m| m.pbr_material.is_none())
+    }
+
+    /// Material of the submesh, `None` if the model has no such material.
+    fn submesh_material(&self, submesh: &mesh::Submesh) -> Option<&DiceMaterial> {
+        submesh
+            .material_index
+            .and_then(|index| self.materials().get(index))
+    }
+
+    /// Set transform relative to the parent.
+    pub fn set_transform(&mut self, isometry: na::Isometry3<f32>) {
+        self.node.set_local_transform(isometry);
+    }
+
+    fn model_matrix(&self) -> na::Matrix4<f32> {
+        self.node.world_transform().to_homogeneous()
+            * na::Matrix4::new_nonuniform_scaling(&self.scale)
     }
 
     pub fn render(
         &self,
         gl: &gl::Gl,
-        viewprojection_matrix: &na::Matrix4<f32>,
-        camera_pos: &na::Vector3<f32>,
+        shadow_map: &render_gl::ShadowMap,
+        environment: &pbr::Environment,
+        reflection: Option<&render_gl::ReflectionProbe>,
+        parallax_mapping: bool,
     ) {
-        self.program.set_used();
-
-        self.material.bind(
-            &self.program,
-            viewprojection_matrix,
-            &self.transform.to_homogeneous(),
-            camera_pos,
-            &self.texture,
-            &self.texture_normals,
+        let buffers = match self.current_buffers() {
+            Some(buffers) => buffers,
+            None => return,
+        };
+        let model_matrix = self.model_matrix();
+        let programs = &self.programs;
+        if self.is_placeholder() {
+            self.render_error(gl, buffers, &model_matrix);
+            return;
+        }
+
+        let deform = self.deform_features(buffers);
+        let pbr_program = programs.pbr(&deform);
+
+        buffers.vao.bind();
+        for submesh in &buffers.submeshes {
+            let material = self.submesh_material(submesh);
+
+            let pbr = material
+                .and_then(|m| m.pbr_material.as_ref())
+                .and_then(|m| pbr_program.map(|program| (m, program)));
+            if let Some((pbr_material, pbr_program)) = pbr {
+                // probe replaces the sky only if its lighting is prefiltered
+                let environment = reflection
+                    .and_then(|probe| probe.environment())
+                    .unwrap_or(environment);
+                pbr_program.bind(pbr_material, &model_matrix, environment, shadow_map);
+                if let Some(ref morph_targets) = buffers.morph_targets {
+                    pbr_program.bind_morph_targets(morph_targets, &self.morph_weights);
+                }
+                buffers.render_submesh(gl, submesh);
+                continue;
+            }
+
+            let mut features = DiceMaterial::shiny_features(material, parallax_mapping);
+            features.extend(&deform);
+            let shiny = programs.shiny(&features);
+            shiny.set_used();
+            let raster_state = DiceMaterial::raster_state_of(material);
+            raster_state.apply(gl);
+            self.bind_morph_targets(buffers, &shiny);
+
+            let (texture, texture_normals) = DiceMaterial::textures_of(material);
+            bind_material(&shiny, &model_matrix, texture, texture_normals, Some(shadow_map));
+            bind_parallax(&shiny, material, parallax_mapping);
+            bind_lightmap(&shiny, material);
+            match reflection {
+                Some(probe) => {
+                    shiny.set_texture_cube("Reflection", probe.texture());
+                    shiny.set("ReflectionStrength", &REFLECTION_STRENGTH);
+                }
+                None => {
+                    shiny.set("ReflectionStrength", &0.0);
+                }
+            }
+            shiny.set("Opacity", &self.opacity);
+
+            if !self.is_transparent() {
+                buffers.render_submesh(gl, submesh);
+                continue;
+            }
+
+            // inner side first, so that the front blends over the back of the same dice
+            raster_state.with_cull(render_gl::CullMode::Front).apply(gl);
+            buffers.render_submesh(gl, submesh);
+            raster_state.with_cull(render_gl::CullMode::Back).apply(gl);
+            buffers.render_submesh(gl, submesh);
+            raster_state.apply(gl);
+        }
+        buffers.vao.unbind();
+    }
+
+    /// Upload the bone matrices of the current pose if the buffers are skinned, `true` if
+    /// they are to be drawn with a `SKINNED` program.
+    fn bind_bones(&self, buffers: &Buffers) -> bool {
+        if !buffers.is_skinned() || self.bone_matrices.is_empty() {
+            return false;
+        }
+        let mut bones = self.programs.bones.borrow_mut();
+        bones.update(&render::BoneUniforms::from_bone_matrices(&self.bone_matrices));
+        bones.bind_base(render::BONE_UNIFORMS_BINDING);
+        true
+    }
+
+    /// `DEFORM_FEATURES` of the program variants for the buffers, bone matrices are bound
+    /// if the buffers are skinned.
+    fn deform_features(&self, buffers: &Buffers) -> Vec<&'static str> {
+        let mut features = Vec::new();
+        if self.bind_bones(buffers) {
+            features.push("SKINNED");
+        }
+        if buffers.morph_targets.is_some() {
+            features.push("MORPH_TARGETS");
+        }
+        features
+    }
+
+    /// Bind the morph targets of the buffers with the current weights to a `MORPH_TARGETS`
+    /// variant in use. Does nothing for buffers without targets.
+    fn bind_morph_targets(&self, buffers: &Buffers, material: &render_gl::Material) {
+        if let Some(ref morph_targets) = buffers.morph_targets {
+            morph_targets.bind(material, &self.morph_weights);
+        }
+    }
+
+    /// Magenta model, in place of a model that failed to load.
+    fn render_error(&self, gl: &gl::Gl, buffers: &Buffers, model_matrix: &na::Matrix4<f32>) {
+        let error = &self.programs.error;
+        error.set_used();
+        error.set("Model", model_matrix);
+        buffers.render(gl);
+    }
+
+    /// True while the model failed to load and a magenta box is drawn instead.
+    pub fn is_placeholder(&self) -> bool {
+        self.model.as_ref().map_or(false, |model| model.placeholder)
+    }
+
+    /// Texture unit of the color texture in the program used by `render` for the first
+    /// material.
+    pub fn texture_sampler_unit(&self, parallax_mapping: bool) -> Option<u32> {
+        let first = self.materials().first();
+        let pbr = first.and_then(|m| m.pbr_material.as_ref()).is_some();
+        if pbr && self.programs.pbr(&[]).is_some() {
+            Some(pbr::ALBEDO_UNIT)
+        } else {
+            let features = DiceMaterial::shiny_features(first, parallax_mapping);
+            self.programs.shiny(&features).texture_unit("Texture")
+        }
+    }
+
+    pub fn render_depth(&self, gl: &gl::Gl, pass: &render_gl::ShadowPass) {
+        if let Some(buffers) = self.current_buffers() {
+            let features = self.deform_features(buffers);
+            if let Some(variant) = pass.prepare_deformed_model(&self.model_matrix(), &features) {
+                self.bind_morph_targets(buffers, &variant);
+            }
+            buffers.render(gl);
+        }
+    }
+
+    pub fn render_id(&self, gl: &gl::Gl, pass: &render_gl::IdPass) {
+        if let (Some(buffers), Some(selectable)) = (self.current_buffers(), &self.selectable_aabb)
+        {
+            pass.prepare_model(&self.model_matrix(), selectable.pick_id());
+            buffers.render(gl);
+        }
+    }
+
+    /// Outlined while selected.
+    pub fn render_outline(&self, gl: &gl::Gl, pass: &render_gl::OutlinePass) {
+        let selected = self
+            .selectable_aabb
+            .as_ref()
+            .map(|s| s.is_selected())
+            .unwrap_or(false);
+        if let (true, Some(buffers)) = (selected, self.current_buffers()) {
+            let features = self.deform_features(buffers);
+            if let Some(variant) = pass.prepare_deformed_model(&self.model_matrix(), &features) {
+                self.bind_morph_targets(buffers, &variant);
+            }
+            buffers.render(gl);
+        }
+    }
+}
+
+impl Entity for Dice {
+    fn update(&mut self, ctx: &UpdateContext) {
+        self.reload_programs_if_changed(ctx.gl, ctx.res);
+        self.poll_loading(ctx.gl, ctx.res, ctx.uploads, ctx.selectables);
+        self.reload_if_changed(ctx.gl, ctx.res, ctx.selectables);
+        Dice::update(self, ctx.delta);
+        if self.turn_requested {
+            self.turn_requested = false;
+            self.turn_towards(&ctx.camera_pos);
+        }
+        self.update_lod(&ctx.camera_pos);
+    }
+
+    fn interpolate(&mut self, alpha: f32) {
+        Dice::interpolate(self, alpha);
+    }
+
+    fn render(&self, ctx: &RenderContext) {
+        let sampler_unit = ctx.texture_sampler.and_then(|sampler| {
+            let unit = self.texture_sampler_unit(ctx.parallax_mapping)?;
+            sampler.bind_at(unit);
+            Some(unit)
+        });
+        Dice::render(
+            self,
+            ctx.gl,
+            ctx.shadow_map,
+            ctx.environment,
+            ctx.reflection,
+            ctx.parallax_mapping,
         );
+        // sampler would otherwise apply to whatever is drawn next with the unit
+        if let Some(unit) = sampler_unit {
+            render_gl::Sampler::unbind_at(ctx.gl, unit);
+        }
+    }
 
-        self.buffers.render(gl);
+    fn render_depth(&self, gl: &gl::Gl, pass: &render_gl::ShadowPass) {
+        Dice::render_depth(self, gl, pass);
+    }
+
+    fn render_id(&self, gl: &gl::Gl, pass: &render_gl::IdPass) {
+        Dice::render_id(self, gl, pass);
+    }
+
+    fn render_outline(&self, gl: &gl::Gl, pass: &render_gl::OutlinePass) {
+        Dice::render_outline(self, gl, pass);
+    }
+
+    fn render_geometry(&self, gl: &gl::Gl, pass: &render_gl::GeometryPass) {
+        DeferredGeometry::render_geometry(self, gl, pass);
+    }
+
+    fn bounds(&self) -> Option<(AABB<f32>, na::Matrix4<f32>)> {
+        Cullable::bounds(self)
+    }
+
+    fn draw_key(&self) -> DrawKey {
+        let first = self.materials().first();
+        let pbr_program = self.programs.pbr(&[]);
+        let (program, texture) = match (first.and_then(|m| m.pbr_material.as_ref()), pbr_program) {
+            _ if self.is_placeholder() => (self.programs.error.program().id(), None),
+            (Some(pbr_material), Some(pbr_program)) => {
+                (pbr_program.program().id(), pbr_material.albedo.as_ref().map(|t| &**t))
+            }
+            _ => {
+                // sorted as if parallax mapping is on, which it is unless toggled off
+                let features = DiceMaterial::shiny_features(first, true);
+                let shiny = self.programs.shiny(&features);
+                (shiny.program().id(), DiceMaterial::textures_of(first).0)
+            }
+        };
+        DrawKey {
+            program,
+            texture: texture.map(|t| t.id()).unwrap_or(0),
+        }
+    }
+
+    fn node(&self) -> Option<&scene::Node> {
+        Some(&self.node)
+    }
+
+    fn lod_level(&self) -> Option<usize> {
+        Dice::lod_level(self)
+    }
+
+    fn set_morph_weights(&mut self, weights: &[f32]) {
+        Dice::set_morph_weights(self, weights);
+    }
+
+    fn has_selectable(&self, handle: selection::ContainerHandle) -> bool {
+        self.selectable_aabb
+            .as_ref()
+            .map_or(false, |selectable| selectable.has_handle(handle))
+    }
+
+    fn occlusion_tested(&self) -> bool {
+        true
+    }
+
+    fn blended(&self) -> bool {
+        self.is_transparent()
+    }
+
+    fn record(&self) -> Option<scene::serde::EntityRecord> {
+        let record = scene::serde::EntityRecord::new(
+            "dice",
+            Some(&self.model_path),
+            &self.node.world_transform(),
+        );
+        Some(record.with_scale(self.scale))
+    }
+}
+
+impl Cullable for Dice {
+    fn bounds(&self) -> Option<(AABB<f32>, na::Matrix4<f32>)> {
+        self.aabb.clone().map(|aabb| (aabb, self.model_matrix()))
+    }
+}
+
+impl DeferredGeometry for Dice {
+    fn render_geometry(&self, gl: &gl::Gl, _pass: &render_gl::GeometryPass) {
+        let buffers = match self.current_buffers() {
+            Some(buffers) => buffers,
+            None => return,
+        };
+        let model_matrix = self.model_matrix();
+        if self.is_placeholder() {
+            self.render_error(gl, buffers, &model_matrix);
+            return;
+        }
+        let features = self.deform_features(buffers);
+        let geometry_material = self.programs.geometry(&features);
+
+        geometry_material.set_used();
+        self.bind_morph_targets(buffers, geometry_material);
+
+        buffers.vao.bind();
+        for submesh in &buffers.submeshes {
+            let material = self.submesh_material(submesh);
+            DiceMaterial::raster_state_of(material).apply(gl);
+            let (texture, texture_normals) = DiceMaterial::textures_of(material);
+            bind_material(
+                geometry_material,
+                &model_matrix,
+                texture,
+                texture_normals,
+                None,
+            );
+            buffers.render_submesh(gl, submesh);
+        }
+        buffers.vao.unbind();
+    }
+}
+
+fn load_material(program: Rc<render_gl::Program>) -> render_gl::Material {
+    program.bind_uniform_block("Camera", render::CAMERA_UNIFORMS_BINDING);
+    program.bind_uniform_block("Fog", render::FOG_UNIFORMS_BINDING);
+    program.bind_uniform_block("Bones", render::BONE_UNIFORMS_BINDING);
+    if lights::LightClusters::supported(program.gl()) {
+        program.bind_uniform_block("Clusters", lights::CLUSTER_UNIFORMS_BINDING);
+        program.bind_shader_storage_block("ClusterLights", lights::CLUSTER_LIGHTS_BINDING);
+        program.bind_shader_storage_block("ClusterGrid", lights::CLUSTER_GRID_BINDING);
+        program.bind_shader_storage_block("ClusterIndices", lights::CLUSTER_INDICES_BINDING);
+    }
+    let material = render_gl::Material::shared(program);
+    material.set_used();
+    material.set("Opacity", &1.0f32);
+    material
+}
+
+/// Set uniforms shared by the dice programs, the material must be in use.
+fn bind_material(
+    material: &render_gl::Material,
+    model_matrix: &na::Matrix4<f32>,
+    texture: Option<&render_gl::Texture>,
+    texture_normals: Option<&render_gl::Texture>,
+    shadow_map: Option<&render_gl::ShadowMap>,
+) {
+    if let Some(texture) = texture {
+        material.set_texture("Texture", texture);
+    }
+    if let Some(texture_normals) = texture_normals {
+        material.set_texture("Normals", texture_normals);
+    }
+    if let Some(shadow_map) = shadow_map {
+        shadow_map.bind_material(material);
+    }
+    material.set("Model", model_matrix);
+}
+
+/// Set the height map and parallax uniforms of the shiny material, plain normal mapping is
+/// used without a height map or if disabled.
+fn bind_parallax(
+    material: &render_gl::Material,
+    dice_material: Option<&DiceMaterial>,
+    enabled: bool,
+) {
+    let height = dice_material
+        .and_then(|m| m.texture_height.as_ref().map(|texture| (m, texture)))
+        .filter(|_| enabled);
+    match height {
+        Some((dice_material, texture_height)) => {
+            material.set_texture("Height", texture_height);
+            material.set("ParallaxScale", &dice_material.parallax_scale);
+            material.set("ParallaxSteps", &dice_material.parallax_steps);
+        }
+        None => {
+            material.set("ParallaxScale", &0.0f32);
+        }
+    }
+}
+
+/// Set the lightmap of the shiny material, only variants with `LIGHTMAP` sample it.
+fn bind_lightmap(material: &render_gl::Material, dice_material: Option<&DiceMaterial>) {
+    if let Some(lightmap) = dice_material.and_then(|m| m.texture_lightmap.as_ref()) {
+        material.set_texture("Lightmap", lightmap);
+    }
+}
+
+/// Shiny variant for the features, the error material after reporting a variant that
+/// fails to link and adding its files to `retry`.
+///
+/// Variants shade clustered point and spot lights if `clustered` is set.
+fn shiny_variant(
+    shiny: &ProgramPermutations,
+    features: &[&str],
+    clustered: bool,
+    error: &Rc<render_gl::Material>,
+    retry: &RefCell<Option<Watch>>,
+) -> Rc<render_gl::Material> {
+    let mut features = features.to_vec();
+    if clustered {
+        features.push("CLUSTERED");
+    }
+    match shiny.variant(&features) {
+        Ok(Some(material)) => material,
+        Ok(None) => error.clone(),
+        Err(e) => {
+            LoadErrors::report(&shiny.name(), &e);
+            let mut retry = retry.borrow_mut();
+            match *retry {
+                Some(ref mut watch) => {
+                    for file in shiny.files() {
+                        watch.add(&file);
+                    }
+                }
+                None => *retry = Some(shiny.watch()),
+            }
+            error.clone()
+        }
+    }
+}
+
+/// Link the shiny variant without features, so that errors in code shared by all variants
+/// are reported on load.
+fn link_shiny(res: &Resources, shiny: &ProgramPermutations, retry: &mut Option<Watch>) {
+    let files = shiny.files();
+    let files = files.iter().map(|file| &file[..]).collect::<Vec<_>>();
+    load_or_report(res, &shiny.name(), &files, retry, || shiny.variant(&[]));
+}
+
+/// Material with decoded textures.
+struct DiceMaterialData {
+    material: mesh::Material,
+    texture: Option<MapData>,
+    texture_normals: Option<MapData>,
+    metallic: Option<MapData>,
+    roughness: Option<MapData>,
+    occlusion: Option<MapData>,
+    height: Option<MapData>,
+    lightmap: Option<MapData>,
+}
+
+/// Decoded map and the path it is shared by in `TextureCache`.
+struct MapData {
+    path: ResourcePathBuf,
+    img: MapImage,
+}
+
+enum MapImage {
+    Decoded(image::RgbImage),
+    /// DDS or KTX2 file, uploaded with its own mipmaps if the format is supported.
+    Compressed(CompressedImage),
+}
+
+impl MapData {
+    fn load(res: &Resources, path: &ResourcePathBuf) -> Result<MapData, failure::Error> {
+        let name = path.as_clean_str().to_lowercase();
+        let img = if name.ends_with(".dds") || name.ends_with(".ktx2") {
+            MapImage::Compressed(res.load_compressed_image(path)?)
+        } else {
+            MapImage::Decoded(res.load_rgb_image(path)?)
+        };
+        Ok(MapData {
+            path: path.clone(),
+            img,
+        })
+    }
+}
+
+/// Dice mesh and decoded textures, loaded without GL context.
+struct DiceModelData {
+    materials: Vec<DiceMaterialData>,
+    /// All meshes of the file merged, with a submesh for each material.
+    mesh: mesh::Mesh,
+    /// Coarser levels of detail generated from the mesh.
+    lods: Vec<mesh::Mesh>,
+    /// Skeleton of the mesh and its animation clips.
+    skeleton: Option<mesh::Skeleton>,
+    animations: Vec<mesh::AnimationClip>,
+    watch: Watch,
+}
+
+impl DiceModelData {
+    fn load(res: &Resources, obj_path: &ResourcePath) -> Result<DiceModelData, failure::Error> {
+        let mut watch = res.watch(obj_path);
+        let imported_models = res.load_model(obj_path)?;
+
+        let mut load_map = |map: Option<&ResourcePathBuf>| {
+            map.and_then(|resource_path| {
+                watch.add(resource_path);
+                MapData::load(res, resource_path)
+                    .map_err(|e| println!("Error loading {}: {}", resource_path, e))
+                    .ok()
+            })
+        };
+
+        let materials = imported_models
+            .materials
+            .into_iter()
+            .map(|material| DiceMaterialData {
+                texture: load_map(material.diffuse_map.as_ref()),
+                texture_normals: load_map(material.bump_map.as_ref()),
+                metallic: load_map(material.metallic_map.as_ref()),
+                roughness: load_map(material.roughness_map.as_ref()),
+                occlusion: load_map(material.occlusion_map.as_ref()),
+                height: load_map(material.height_map.as_ref()),
+                lightmap: load_map(material.lightmap.as_ref()),
+                material,
+            }).collect();
+
+        let mesh = mesh::Mesh::merge(&imported_models.meshes).ok_or_else(|| {
+            failure::err_msg(format!("Model file {} contains no mesh", obj_path))
+        })?;
+        let lods = mesh.generate_lods(LOD_DISTANCES.len() - 1, LOD_RESOLUTION);
+
+        // merged meshes share the skeleton of the first one
+        let skeletons = imported_models.skeletons;
+        let skeleton = mesh
+            .skeleton_index
+            .and_then(|index| skeletons.get(index).cloned());
+        let animations = imported_models
+            .animations
+            .into_iter()
+            .filter(|clip| Some(clip.skeleton_index) == mesh.skeleton_index)
+            .collect();
+
+        Ok(DiceModelData {
+            materials,
+            mesh,
+            lods,
+            skeleton,
+            animations,
+            watch,
+        })
+    }
+
+    /// Make the next upload replace cached textures of the maps, i.e. after they changed.
+    fn forget_textures(&self) {
+        for material in &self.materials {
+            let maps = [
+                &material.texture,
+                &material.texture_normals,
+                &material.metallic,
+                &material.roughness,
+                &material.occlusion,
+                &material.height,
+                &material.lightmap,
+            ];
+            for map in maps.iter().filter_map(|map| map.as_ref()) {
+                render_gl::TextureCache::remove(&map.path);
+            }
+        }
+    }
+}
+
+/// Textures of one material of the model, shared with other models using the same maps.
+struct DiceMaterial {
+    texture: Option<Rc<render_gl::Texture>>,
+    texture_normals: Option<Rc<render_gl::Texture>>,
+    /// If the material has PBR maps, it takes over the textures above.
+    pbr_material: Option<pbr::Material>,
+    /// Parallax occlusion mapping of the shiny program, used with the normal map.
+    texture_height: Option<Rc<render_gl::Texture>>,
+    /// Depth of the height map in UV units.
+    parallax_scale: f32,
+    parallax_steps: i32,
+    /// Baked lighting of the shiny program, sampled with the second coordinates.
+    texture_lightmap: Option<Rc<render_gl::Texture>>,
+    vertex_colors: bool,
+    /// Culling of the shiny program, the PBR material has its own copy.
+    raster_state: render_gl::RasterState,
+}
+
+impl DiceMaterial {
+    fn upload(gl: &gl::Gl, data: DiceMaterialData) -> DiceMaterial {
+        let upload_map = |data: Option<MapData>, map: pbr::MaterialMap| {
+            data.and_then(|data| match data.img {
+                MapImage::Decoded(ref img) => Some(render_gl::TextureCache::from_rgb_image(
+                    gl,
+                    &data.path,
+                    img,
+                    map.color_space(),
+                    true,
+                )),
+                MapImage::Compressed(ref img) => {
+                    let texture =
+                        render_gl::TextureCache::from_compressed_image(gl, &data.path, img);
+                    if texture.is_none() {
+                        LoadErrors::report(
+                            data.path.as_clean_str(),
+                            format!("Compressed format {:?} is not supported", img.format),
+                        );
+                    }
+                    texture
+                }
+            })
+        };
+
+        let texture = upload_map(data.texture, pbr::MaterialMap::Albedo);
+        let texture_normals = upload_map(data.texture_normals, pbr::MaterialMap::Normal);
+        let texture_height = upload_map(data.height, pbr::MaterialMap::Height);
+        let texture_lightmap = upload_map(data.lightmap, pbr::MaterialMap::Lightmap);
+        let vertex_colors = data.material.vertex_colors;
+        let raster_state = if data.material.double_sided {
+            render_gl::RasterState::double_sided()
+        } else {
+            render_gl::RasterState::default()
+        };
+
+        if !data.material.has_pbr_maps() {
+            return DiceMaterial {
+                texture,
+                texture_normals,
+                pbr_material: None,
+                texture_height,
+                parallax_scale: PARALLAX_SCALE,
+                parallax_steps: PARALLAX_STEPS,
+                texture_lightmap,
+                vertex_colors,
+                raster_state,
+            };
+        }
+
+        DiceMaterial {
+            texture: None,
+            texture_normals: None,
+            texture_height,
+            parallax_scale: PARALLAX_SCALE,
+            parallax_steps: PARALLAX_STEPS,
+            texture_lightmap,
+            vertex_colors,
+            raster_state,
+            pbr_material: Some(pbr::Material {
+                albedo: texture,
+                normal: texture_normals,
+                metallic: upload_map(data.metallic, pbr::MaterialMap::Metallic),
+                roughness: upload_map(data.roughness, pbr::MaterialMap::Roughness),
+                occlusion: upload_map(data.occlusion, pbr::MaterialMap::Occlusion),
+                albedo_factor: na::Vector3::new(1.0, 1.0, 1.0),
+                metallic_factor: data.material.metallic.unwrap_or(1.0),
+                roughness_factor: data.material.roughness.unwrap_or(1.0),
+                raster_state,
+            }),
+        }
+    }
+
+    /// Features of the shiny program variant for the material, i.e. no normal mapping for
+    /// a material without a normal map.
+    fn shiny_features(
+        material: Option<&DiceMaterial>,
+        parallax_mapping: bool,
+    ) -> Vec<&'static str> {
+        let normal_map = DiceMaterial::textures_of(material).1.is_some();
+        let height_map = material.map_or(false, |m| m.texture_height.is_some());
+        let enabled = [
+            normal_map,
+            normal_map && height_map && parallax_mapping,
+            material.map_or(false, |m| m.vertex_colors),
+            material.map_or(false, |m| m.texture_lightmap.is_some()),
+            // only instances with skins sample a texture array
+            false,
+            // set by the buffers, not the material
+            false,
+            false,
+            // added by `shiny_variant` where clusters are supported
+            false,
+        ];
+        SHINY_FEATURES
+            .iter()
+            .zip(&enabled)
+            .filter(|&(_, &enabled)| enabled)
+            .map(|(&feature, _)| feature)
+            .collect()
+    }
+
+    /// Culling of the material, back faces for a submesh without one.
+    fn raster_state_of(material: Option<&DiceMaterial>) -> render_gl::RasterState {
+        material.map_or_else(render_gl::RasterState::default, |m| m.raster_state)
+    }
+
+    /// Color and normal textures, owned by the PBR material if there is one.
+    fn textures_of(
+        material: Option<&DiceMaterial>,
+    ) -> (Option<&render_gl::Texture>, Option<&render_gl::Texture>) {
+        match material {
+            Some(DiceMaterial {
+                pbr_material: Some(ref pbr_material),
+                ..
+            }) => (
+                pbr_material.albedo.as_ref().map(|t| &**t),
+                pbr_material.normal.as_ref().map(|t| &**t),
+            ),
+            Some(material) => (
+                material.texture.as_ref().map(|t| &**t),
+                material.texture_normals.as_ref().map(|t| &**t),
+            ),
+            None => (None, None),
+        }
+    }
+}
+
+/// Uploaded model, shared by all dice loaded from the same path.
+struct DiceModel {
+    materials: Vec<DiceMaterial>,
+    mesh: mesh::Mesh,
+    /// Buffers of the full mesh followed by the generated levels of detail.
+    levels: Vec<Buffers>,
+    skeleton: Option<mesh::Skeleton>,
+    /// Clips of the skeleton, the first one is played.
+    animations: Vec<mesh::AnimationClip>,
+    /// Model and texture files.
+    watch: RefCell<Watch>,
+    /// Magenta box standing in for a model that failed to load.
+    placeholder: bool,
+}
+
+impl DiceModel {
+    fn load(
+        res: &Resources,
+        gl: &gl::Gl,
+        obj_path: &ResourcePath,
+    ) -> Result<DiceModel, failure::Error> {
+        Ok(DiceModel::upload(gl, DiceModelData::load(res, obj_path)?))
+    }
+
+    /// Same as `load`, uploading textures again instead of taking them from the cache.
+    fn reload(
+        res: &Resources,
+        gl: &gl::Gl,
+        obj_path: &ResourcePath,
+    ) -> Result<DiceModel, failure::Error> {
+        let data = DiceModelData::load(res, obj_path)?;
+        data.forget_textures();
+        Ok(DiceModel::upload(gl, data))
+    }
+
+    fn upload(gl: &gl::Gl, data: DiceModelData) -> DiceModel {
+        let levels = ::std::iter::once(&data.mesh)
+            .chain(&data.lods)
+            .map(|mesh| Buffers::new(gl, mesh))
+            .collect();
+        DiceModel {
+            materials: data
+                .materials
+                .into_iter()
+                .map(|material| DiceMaterial::upload(gl, material))
+                .collect(),
+            mesh: data.mesh,
+            levels,
+            skeleton: data.skeleton,
+            animations: data.animations,
+            watch: RefCell::new(data.watch),
+            placeholder: false,
+        }
+    }
+
+    /// Box of the loading placeholder, shared under the path of the model that failed
+    /// to load until its file changes.
+    fn placeholder(res: &Resources, gl: &gl::Gl, obj_path: &ResourcePath) -> DiceModel {
+        let mut mesh = mesh::primitives::cube(&na::Vector3::new(2.0, 2.0, 2.0));
+        for vertex in &mut mesh.vertices {
+            vertex.pos.z += 1.0;
+        }
+        DiceModel {
+            materials: Vec::new(),
+            levels: vec![Buffers::new(gl, &mesh)],
+            mesh,
+            skeleton: None,
+            animations: Vec::new(),
+            watch: RefCell::new(res.watch(obj_path)),
+            placeholder: true,
+        }
+    }
+
+    /// Model from the cache, loaded and uploaded if no dice uses it yet.
+    fn load_shared(
+        res: &Resources,
+        gl: &gl::Gl,
+        obj_path: &ResourcePath,
+    ) -> Result<Rc<DiceModel>, failure::Error> {
+        MODELS.with(|models| {
+            models.get_or_load(obj_path.as_clean_str(), || {
+                DiceModel::load(res, gl, obj_path)
+            })
+        })
+    }
+}
+
+fn shared_model(obj_path: &ResourcePath) -> Option<Rc<DiceModel>> {
+    MODELS.with(|models| models.get(obj_path.as_clean_str()))
+}
+
+/// Put the model into the cache, replacing the previous one for the path.
+fn share_model(obj_path: &ResourcePath, model: DiceModel) -> Rc<DiceModel> {
+    MODELS.with(|models| models.insert(obj_path.as_clean_str(), model))
+}
+
+/// Model to replace `current` with: loaded again if its files have changed, or taken from
+/// the cache if another user has reloaded it.
+fn reload_shared_model(
+    res: &Resources,
+    gl: &gl::Gl,
+    obj_path: &ResourcePath,
+    current: &Rc<DiceModel>,
+) -> Option<Rc<DiceModel>> {
+    let changed = current.watch.borrow_mut().changed();
+    if !changed {
+        return shared_model(obj_path).filter(|model| !Rc::ptr_eq(model, current));
+    }
+    match DiceModel::reload(res, gl, obj_path) {
+        Ok(model) => {
+            LoadErrors::resolve(obj_path.as_clean_str());
+            Some(share_model(obj_path, model))
+        }
+        Err(e) => {
+            LoadErrors::report(obj_path.as_clean_str(), &e);
+            None
+        }
+    }
+}
+
+/// Value from `load`, or `None` after reporting the failure and adding the files to the
+/// `retry` watch.
+fn load_or_report<T, E, F>(
+    res: &Resources,
+    name: &str,
+    files: &[&str],
+    retry: &mut Option<Watch>,
+    load: F,
+) -> Option<T>
+where
+    E: fmt::Display,
+    F: FnOnce() -> Result<T, E>,
+{
+    match load() {
+        Ok(value) => {
+            LoadErrors::resolve(name);
+            Some(value)
+        }
+        Err(e) => {
+            LoadErrors::report(name, &e);
+            for file in files {
+                match retry {
+                    Some(watch) => watch.add(file),
+                    None => *retry = Some(res.watch(file)),
+                }
+            }
+            None
+        }
+    }
+}
+
+/// Programs of all dice, created once by `Dice::new` and shared by clones.
+struct DicePrograms {
+    /// Shiny program variants, picked by the features of each material.
+    shiny: ProgramPermutations,
+    /// `LightClusters::supported`, shiny variants are then `CLUSTERED`.
+    clustered: bool,
+    /// Used instead of the shiny program if the model material has PBR maps, one for each
+    /// combination of `DEFORM_FEATURES` by `deform_index`.
+    pbr_programs: Vec<Option<pbr::MaterialProgram>>,
+    /// Bone matrices of the dice drawn last, uploaded before each skinned draw.
+    bones: RefCell<render_gl::UniformBuffer<render::BoneUniforms>>,
+    /// Deferred geometry materials by `deform_index`.
+    geometry_materials: Vec<render_gl::Material>,
+    /// Magenta program for placeholder models and programs that failed to load.
+    error: Rc<render_gl::Material>,
+    /// Shader files of programs that failed to load.
+    retry: RefCell<Option<Watch>>,
+    /// Programs loaded again after the retry files changed, dice follow them.
+    reloaded: RefCell<Option<Rc<DicePrograms>>>,
+}
+
+impl DicePrograms {
+    /// Fails only if the error program can not be built.
+    fn new(gl: &gl::Gl, res: &Resources) -> Result<DicePrograms, failure::Error> {
+        let error_program = ProgramCache::error_program(gl, false)?;
+        let mut retry = None;
+        let load_program = |name: &str, defines: &[(&str, &str)], retry: &mut Option<Watch>| {
+            let files = [format!("{}.vert", name), format!("{}.frag", name)];
+            let files = [&files[0][..], &files[1][..]];
+            let program = load_or_report(res, name, &files, retry, || {
+                ProgramCache::from_res_with_defines(gl, res, name, defines)
+            });
+            load_material(program.unwrap_or_else(|| error_program.clone()))
+        };
+
+        let shiny = ProgramPermutations::from_res(gl, res, "shaders/shiny", &SHINY_FEATURES)
+            .with_setup(load_material);
+        link_shiny(res, &shiny, &mut retry);
+        let deform_variants = deform_variants();
+        let geometry_materials = deform_variants
+            .iter()
+            .map(|features| {
+                let defines = features.iter().map(|&feature| (feature, "1")).collect::<Vec<_>>();
+                load_program("shaders/deferred_geometry", &defines, &mut retry)
+            }).collect();
+        let pbr_programs = deform_variants
+            .iter()
+            .map(|features| {
+                let name = if features.is_empty() {
+                    "shaders/pbr".to_string()
+                } else {
+                    format!("shaders/pbr ({})", features.join(", "))
+                };
+                load_or_report(
+                    res,
+                    &name,
+                    &["shaders/pbr.vert", "shaders/pbr.frag"],
+                    &mut retry,
+                    || pbr::MaterialProgram::deformed(gl, res, features),
+                )
+            }).collect();
+
+        Ok(DicePrograms {
+            shiny,
+            clustered: lights::LightClusters::supported(gl),
+            pbr_programs,
+            bones: RefCell::new(render_gl::UniformBuffer::new(gl)),
+            geometry_materials,
+            error: Rc::new(load_material(error_program)),
+            retry: RefCell::new(retry),
+            reloaded: RefCell::new(None),
+        })
+    }
+
+    /// Shiny variant for the features, the error material if it fails to link.
+    fn shiny(&self, features: &[&str]) -> Rc<render_gl::Material> {
+        shiny_variant(&self.shiny, features, self.clustered, &self.error, &self.retry)
+    }
+
+    /// PBR program for the `DEFORM_FEATURES`, `None` if it failed to load.
+    fn pbr(&self, features: &[&str]) -> Option<&pbr::MaterialProgram> {
+        self.pbr_programs[deform_index(features)].as_ref()
+    }
+
+    /// Deferred geometry material for the `DEFORM_FEATURES`.
+    fn geometry(&self, features: &[&str]) -> &render_gl::Material {
+        &self.geometry_materials[deform_index(features)]
+    }
+}
+
+/// Every combination of `DEFORM_FEATURES`, in the order of `deform_index`.
+fn deform_variants() -> Vec<Vec<&'static str>> {
+    (0..1 << DEFORM_FEATURES.len())
+        .map(|bits| {
+            DEFORM_FEATURES
+                .iter()
+                .enumerate()
+                .filter(|&(index, _)| bits & 1 << index != 0)
+                .map(|(_, &feature)| feature)
+                .collect()
+        }).collect()
+}
+
+/// Position of the combination of `DEFORM_FEATURES` in `deform_variants`.
+fn deform_index(features: &[&str]) -> usize {
+    DEFORM_FEATURES
+        .iter()
+        .enumerate()
+        .filter(|&(_, feature)| features.contains(feature))
+        .fold(0, |bits, (index, _)| bits | 1 << index)
+}
+
+enum PendingModel {
+    Loading(Box<LoadHandle<DiceModelData>>),
+    /// Already uploaded by another dice.
+    Shared(Rc<DiceModel>),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lights;
+    use crate::render_gl::golden;
+    use crate::resources::FileSystem;
+    use crate::skybox;
+    use crate::system::headless::HeadlessContext;
+    use std::f32;
+    use std::path::Path;
+
+    /// Reference `tests/golden/dice.png` is rendered by Mesa llvmpipe, other drivers may
+    /// differ beyond the tolerance. After an intended change in the look of the dice, render
+    /// it again and commit the new image:
+    ///
+    /// `LIBGL_ALWAYS_SOFTWARE=1 UPDATE_GOLDEN=1 cargo test dice_matches_golden -- --ignored`
+    #[test]
+    #[ignore] // needs a GPU, run with `cargo test -- --ignored`
+    fn dice_matches_golden_image() {
+        let context = HeadlessContext::new().unwrap();
+        let gl = &context.gl;
+        let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let res = Resources::from_backend(FileSystem::new(manifest_dir.join("assets")));
+
+        let scene = scene::Scene::new();
+        let debug_lines = DebugLines::new(gl, &res).unwrap();
+        let mut dice = Dice::new(&res, gl, &debug_lines, &scene).unwrap();
+        let model = DiceModel::load(&res, gl, DEFAULT_MODEL.as_ref()).unwrap();
+        dice.apply_model(Rc::new(model), &Selectables::new());
+
+        let sun = lights::DirectionalLight {
+            direction: na::Vector3::new(-0.4, 0.6, -1.0),
+            color: na::Vector3::new(1.0, 1.0, 1.0),
+            intensity: 1.0,
+            casts_shadows: true,
+        };
+        let mut shadow_map = render_gl::ShadowMap::new(gl, &res, 512, 1).unwrap();
+        shadow_map.set_directional_light(&sun.direction, &na::Point3::origin(), 4.0);
+        let mut scene_lights = lights::Lights::new();
+        scene_lights.add(sun);
+        let mut light_uniforms = render_gl::UniformBuffer::<lights::LightUniforms>::new(gl);
+        light_uniforms.update(&scene_lights.uniforms());
+
+        let skybox = skybox::Skybox::new(
+            &res,
+            gl,
+            render_gl::CubeTextureSource::Equirectangular {
+                resource_name: "textures/sky.hdr".as_ref(),
+                face_size: 64,
+            },
+        ).unwrap();
+        let environment = pbr::Environment::new(gl, &res, skybox.texture()).unwrap();
+
+        let camera_pos = na::Point3::new(3.0, -3.0, 3.0);
+        let target = na::Point3::new(0.0, 0.0, 1.0);
+        let view_projection = na::Matrix4::new_perspective(1.0, f32::consts::FRAC_PI_4, 0.1, 100.0)
+            * na::Matrix4::look_at_rh(&camera_pos, &target, &na::Vector3::z());
+        let mut camera_uniforms = render_gl::UniformBuffer::<render::CameraUniforms>::new(gl);
+        camera_uniforms.update(&render::CameraUniforms {
+            view_projection,
+            camera_pos: camera_pos.coords,
+            debug_view: render::MaterialDebugView::Shaded.shader_index(),
+            clip_plane: na::Vector4::zeros(),
+        });
+        camera_uniforms.bind_base(render::CAMERA_UNIFORMS_BINDING);
+        light_uniforms.bind_base(render::LIGHT_UNIFORMS_BINDING);
+        let mut fog_uniforms = render_gl::UniformBuffer::<render::FogUniforms>::new(gl);
+        fog_uniforms.update(&render::Fog::default().uniforms());
+        fog_uniforms.bind_base(render::FOG_UNIFORMS_BINDING);
+        let clusters = if lights::LightClusters::supported(gl) {
+            Some(lights::LightClusters::new(gl))
+        } else {
+            None
+        };
+        if let Some(ref clusters) = clusters {
+            clusters.bind_disabled();
+        }
+
+        {
+            let shadow_pass = shadow_map.begin(0);
+            dice.render_depth(gl, &shadow_pass);
+        }
+
+        let image = golden::render_offscreen(gl, 128, 128, [0.3, 0.3, 0.5, 1.0], || {
+            dice.render(gl, &shadow_map, &environment, None, false)
+        }).unwrap();
+
+        if let Err(e) = golden::check_reference(
+            &image,
+            manifest_dir.join("tests/golden/dice.png"),
+            golden::Tolerance {
+                channel: 4,
+                pixels: 64,
+            },
+        ) {
+            panic!("{}", e);
+        }
     }
 }
@@ -0,0 +1,271 @@
+use failure;
+use gl;
+use image;
+use nalgebra as na;
+use ncollide3d::bounding_volume::aabb::AABB;
+use crate::entity::{DrawKey, Entity, RenderContext};
+use crate::render;
+use crate::render_gl::buffer::{Buffer, VertexArray};
+use crate::render_gl::{self, data, Program, ProgramCache, Texture, Uniform};
+use crate::resources::Resources;
+use crate::scene;
+use std::f32::consts::PI;
+use std::rc::Rc;
+
+/// Surfaces turned further than this from the projection direction are not stamped,
+/// cosine of the angle.
+const MIN_FACING: f32 = 0.3;
+
+#[derive(VertexAttribPointers, Copy, Clone, Debug)]
+#[repr(C, packed)]
+struct DecalVertex {
+    #[location = "0"]
+    pos: data::f32_f32_f32,
+    #[location = "1"]
+    uv: data::f32_f32,
+}
+
+/// Texture projected along -Z of an oriented box onto the surfaces inside it,
+/// i.e. a paint splat.
+///
+/// Target triangles are clipped to the box once, when the decal is created, and the
+/// result is drawn over them in the blended pass of both forward and deferred renderers.
+/// Attach the decal to the node of a moving object so that the mark follows it.
+pub struct Decal {
+    node: scene::Node,
+    half_extents: na::Vector3<f32>,
+    texture: Rc<Texture>,
+    /// Multiplies the texture, alpha fades the whole decal.
+    pub color: na::Vector4<f32>,
+    program: Rc<Program>,
+    program_model: Uniform<na::Matrix4<f32>>,
+    program_texture: Uniform<i32>,
+    program_color: Uniform<na::Vector4<f32>>,
+    _vbo: Buffer,
+    vao: VertexArray,
+    vertex_count: usize,
+}
+
+impl Decal {
+    /// Decal box at `isometry` with half size `half_extents`, clipping `triangles` given
+    /// in world space.
+    pub fn new(
+        gl: &gl::Gl,
+        res: &Resources,
+        scene: &scene::Scene,
+        isometry: na::Isometry3<f32>,
+        half_extents: na::Vector3<f32>,
+        texture: Rc<Texture>,
+        triangles: &[[na::Point3<f32>; 3]],
+    ) -> Result<Decal, failure::Error> {
+        let program = ProgramCache::from_res(gl, res, "shaders/decal")?;
+        program.bind_uniform_block("Camera", render::CAMERA_UNIFORMS_BINDING);
+
+        let vertices = clip_to_box(&isometry, &half_extents, triangles);
+
+        let vbo = Buffer::new_array(gl);
+        vbo.set_label("decal");
+        vbo.bind();
+        vbo.static_draw_data(&vertices);
+        vbo.unbind();
+
+        let vao = VertexArray::new(gl);
+        vao.bind();
+        vbo.bind();
+        DecalVertex::vertex_attrib_pointers(gl);
+        vao.unbind();
+        vbo.unbind();
+
+        Ok(Decal {
+            node: scene.node(isometry, None),
+            half_extents,
+            texture,
+            color: na::Vector4::new(1.0, 1.0, 1.0, 1.0),
+            program_model: program.uniform("Model"),
+            program_texture: program.uniform("Texture"),
+            program_color: program.uniform("Color"),
+            program,
+            _vbo: vbo,
+            vao,
+            vertex_count: vertices.len(),
+        })
+    }
+
+    /// Box placement stamping the surface at `point` with `normal`, turned by `angle`
+    /// around the normal.
+    pub fn isometry_on_surface(
+        point: &na::Point3<f32>,
+        normal: &na::Vector3<f32>,
+        angle: f32,
+    ) -> na::Isometry3<f32> {
+        let normal = normal.try_normalize(::std::f32::EPSILON).unwrap_or_else(na::Vector3::z);
+        let facing = na::UnitQuaternion::rotation_between(&na::Vector3::z(), &normal)
+            .unwrap_or_else(|| {
+                // normal points straight down
+                na::UnitQuaternion::from_axis_angle(&na::Vector3::x_axis(), PI)
+            });
+        let turn = na::UnitQuaternion::from_axis_angle(&na::Vector3::z_axis(), angle);
+        na::Isometry3::from_parts(na::Translation3::from(point.coords), facing * turn)
+    }
+
+    pub fn node(&self) -> &scene::Node {
+        &self.node
+    }
+
+    /// Attach to the parent keeping the current place, i.e. to the stamped object.
+    pub fn set_parent(&self, parent: Option<&scene::Node>) {
+        self.node.set_parent(parent);
+    }
+
+    /// True if no triangle was inside the box, so that nothing is drawn.
+    pub fn is_empty(&self) -> bool {
+        self.vertex_count == 0
+    }
+}
+
+impl Entity for Decal {
+    fn render(&self, ctx: &RenderContext) {
+        if self.is_empty() {
+            return;
+        }
+        let gl = ctx.gl;
+        let program = &self.program;
+        program.set_used();
+        self.program_model
+            .set(program, &self.node.world_transform().to_homogeneous());
+        self.texture.bind_at(0);
+        self.program_texture.set(program, &0);
+        self.program_color.set(program, &self.color);
+
+        self.vao.bind();
+        unsafe {
+            // pull towards the camera, the triangles lie exactly on the surface
+            gl.Enable(gl::POLYGON_OFFSET_FILL);
+            gl.PolygonOffset(-1.0, -2.0);
+            gl.DrawArrays(gl::TRIANGLES, 0, self.vertex_count as i32);
+            gl.Disable(gl::POLYGON_OFFSET_FILL);
+        }
+        render_gl::FrameStats::record_draw(gl::TRIANGLES, self.vertex_count as i32, 1);
+        self.vao.unbind();
+    }
+
+    fn bounds(&self) -> Option<(AABB<f32>, na::Matrix4<f32>)> {
+        Some((
+            AABB::new(na::Point3::from(-self.half_extents), na::Point3::from(self.half_extents)),
+            self.node.world_transform().to_homogeneous(),
+        ))
+    }
+
+    fn draw_key(&self) -> DrawKey {
+        DrawKey {
+            program: self.program.id(),
+            texture: self.texture.id(),
+        }
+    }
+
+    fn node(&self) -> Option<&scene::Node> {
+        Some(&self.node)
+    }
+
+    fn blended(&self) -> bool {
+        true
+    }
+}
+
+/// Parts of the triangles inside the box, in box space with UVs from the box XY.
+fn clip_to_box(
+    isometry: &na::Isometry3<f32>,
+    half_extents: &na::Vector3<f32>,
+    triangles: &[[na::Point3<f32>; 3]],
+) -> Vec<DecalVertex> {
+    let mut vertices = Vec::new();
+    for triangle in triangles {
+        let local = [
+            isometry.inverse_transform_point(&triangle[0]),
+            isometry.inverse_transform_point(&triangle[1]),
+            isometry.inverse_transform_point(&triangle[2]),
+        ];
+        let normal = (local[1] - local[0]).cross(&(local[2] - local[0]));
+        match normal.try_normalize(::std::f32::EPSILON) {
+            Some(normal) if normal.z >= MIN_FACING => (),
+            _ => continue,
+        }
+
+        // clip in the unit cube, so that all planes are at -1 and 1
+        let mut polygon = local
+            .iter()
+            .map(|p| na::Point3::from(p.coords.component_div(half_extents)))
+            .collect::<Vec<_>>();
+        for axis in 0..3 {
+            for &side in &[-1.0, 1.0] {
+                polygon = clip_polygon(&polygon, axis, side);
+            }
+        }
+
+        let vertex = |p: &na::Point3<f32>| {
+            let pos = p.coords.component_mul(half_extents);
+            DecalVertex {
+                pos: (pos.x, pos.y, pos.z).into(),
+                uv: (p.x * 0.5 + 0.5, p.y * 0.5 + 0.5).into(),
+            }
+        };
+        for index in 1..polygon.len().saturating_sub(1) {
+            vertices.push(vertex(&polygon[0]));
+            vertices.push(vertex(&polygon[index]));
+            vertices.push(vertex(&polygon[index + 1]));
+        }
+    }
+    vertices
+}
+
+/// Keep the part of the convex polygon where `side * p[axis] <= 1`.
+fn clip_polygon(polygon: &[na::Point3<f32>], axis: usize, side: f32) -> Vec<na::Point3<f32>> {
+    let distance = |p: &na::Point3<f32>| 1.0 - side * p[axis];
+    let mut clipped = Vec::with_capacity(polygon.len() + 1);
+    for (index, current) in polygon.iter().enumerate() {
+        let next = &polygon[(index + 1) % polygon.len()];
+        let (d_current, d_next) = (distance(current), distance(next));
+        if d_current >= 0.0 {
+            clipped.push(*current);
+        }
+        if (d_current >= 0.0) != (d_next >= 0.0) {
+            let t = d_current / (d_current - d_next);
+            clipped.push(current + (next - current) * t);
+        }
+    }
+    clipped
+}
+
+/// Small deterministic generator, splats only need to look random.
+struct XorShift(u32);
+
+impl XorShift {
+    /// Next value from 0 to 1.
+    fn next(&mut self) -> f32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 17;
+        self.0 ^= self.0 << 5;
+        (self.0 % 10_000) as f32 / 10_000.0
+    }
+}
+
+/// White paint splat with a wobbly edge in alpha, tinted by `Decal::color`.
+pub fn splat_image(size: u32, seed: u32) -> image::RgbaImage {
+    let mut random = XorShift(seed.max(1));
+    let waves = (1..6)
+        .map(|frequency| (frequency as f32 * 2.0, random.next() * 0.08, random.next() * 2.0 * PI))
+        .collect::<Vec<_>>();
+
+    image::RgbaImage::from_fn(size, size, |x, y| {
+        let p = na::Vector2::new(
+            (x as f32 + 0.5) / size as f32 * 2.0 - 1.0,
+            (y as f32 + 0.5) / size as f32 * 2.0 - 1.0,
+        );
+        let angle = p.y.atan2(p.x);
+        let radius = waves.iter().fold(0.7, |radius, &(frequency, amplitude, phase)| {
+            radius + amplitude * (angle * frequency + phase).sin()
+        });
+        let edge = ((radius - p.norm()) * size as f32 * 0.25).max(0.0).min(1.0);
+        image::Rgba([255, 255, 255, (edge * 255.0) as u8])
+    })
+}
@@ -0,0 +1,273 @@
+use failure;
+use gl;
+use nalgebra as na;
+use ncollide3d::bounding_volume::aabb::AABB;
+use ncollide3d::bounding_volume::BoundingVolume;
+use crate::entity::{DrawKey, Entity, RenderContext};
+use crate::mesh::Mesh;
+use crate::render;
+use crate::render_gl::buffer::{Buffer, VertexArray};
+use crate::render_gl::{self, data, Program, Uniform};
+use crate::resources::Resources;
+
+const DRAWS_BINDING: u32 = 1;
+const MATERIALS_BINDING: u32 = 2;
+
+#[derive(VertexAttribPointers, Copy, Clone, Debug)]
+#[repr(C, packed)]
+struct BatchVertex {
+    #[location = "0"]
+    pos: data::f32_f32_f32,
+    #[location = "1"]
+    normal: data::f32_f32_f32,
+}
+
+/// Layout of `glMultiDrawElementsIndirect` commands.
+#[derive(Copy, Clone, Debug)]
+#[repr(C)]
+struct DrawElementsIndirectCommand {
+    count: u32,
+    instance_count: u32,
+    first_index: u32,
+    base_vertex: i32,
+    base_instance: u32,
+}
+
+/// Element of the `Draws` storage block, std430 layout.
+#[derive(Copy, Clone, Debug)]
+#[repr(C)]
+struct DrawData {
+    model: na::Matrix4<f32>,
+    material: u32,
+    _padding: [u32; 3],
+}
+
+/// Meshes collected for a `MeshBatch`.
+#[derive(Default)]
+pub struct MeshBatchBuilder {
+    vertices: Vec<BatchVertex>,
+    indices: Vec<u32>,
+    commands: Vec<DrawElementsIndirectCommand>,
+    draws: Vec<DrawData>,
+    materials: Vec<na::Vector4<f32>>,
+    aabb: Option<AABB<f32>>,
+}
+
+impl MeshBatchBuilder {
+    /// Add a material color, returns the index to add meshes with.
+    pub fn add_material(&mut self, color: na::Vector3<f32>) -> u32 {
+        self.materials
+            .push(na::Vector4::new(color.x, color.y, color.z, 1.0));
+        self.materials.len() as u32 - 1
+    }
+
+    /// Add a mesh drawn with the model matrix, returns the index of its draw.
+    ///
+    /// Meshes added more than once are stored once per call.
+    pub fn add_mesh(&mut self, mesh: &Mesh, model: &na::Matrix4<f32>, material: u32) -> usize {
+        let base_vertex = self.vertices.len() as i32;
+        let first_index = self.indices.len() as u32;
+        let indices = mesh.triangle_indices();
+
+        self.commands.push(DrawElementsIndirectCommand {
+            count: indices.len() as u32,
+            instance_count: 1,
+            first_index,
+            base_vertex,
+            base_instance: 0,
+        });
+        self.draws.push(DrawData {
+            model: *model,
+            material,
+            _padding: [0; 3],
+        });
+        self.indices.extend(indices);
+        self.vertices.extend(mesh.vertices.iter().map(|v| {
+            let normal = v.normal.unwrap_or_else(na::Vector3::z);
+            BatchVertex {
+                pos: (v.pos.x, v.pos.y, v.pos.z).into(),
+                normal: (normal.x, normal.y, normal.z).into(),
+            }
+        }));
+
+        for v in &mesh.vertices {
+            let point = model.transform_point(&v.pos);
+            let world = AABB::new(point, point);
+            self.aabb = Some(match self.aabb.take() {
+                Some(aabb) => aabb.merged(&world),
+                None => world,
+            });
+        }
+
+        self.draws.len() - 1
+    }
+
+    /// Upload all meshes into shared buffers.
+    pub fn build(self, gl: &gl::Gl, res: &Resources) -> Result<MeshBatch, failure::Error> {
+        let vbo = Buffer::new_array(gl);
+        vbo.set_label("mesh batch vertices");
+        vbo.bind();
+        vbo.static_draw_data(&self.vertices);
+        vbo.unbind();
+
+        let ebo = Buffer::new_element_array(gl);
+        ebo.set_label("mesh batch indices");
+        ebo.bind();
+        ebo.static_draw_data(&self.indices);
+        ebo.unbind();
+
+        let vao = VertexArray::new(gl);
+        vao.set_label("mesh batch");
+        vao.bind();
+        vbo.bind();
+        ebo.bind();
+        BatchVertex::vertex_attrib_pointers(gl);
+        vao.unbind();
+        vbo.unbind();
+        ebo.unbind();
+
+        let commands = Buffer::new_draw_indirect(gl);
+        commands.set_label("mesh batch commands");
+        commands.bind();
+        commands.static_draw_data(&self.commands);
+        commands.unbind();
+
+        let draws = Buffer::new_shader_storage(gl);
+        draws.set_label("mesh batch draws");
+        draws.bind();
+        draws.static_draw_data(&self.draws);
+        draws.unbind();
+
+        let materials = Buffer::new_shader_storage(gl);
+        materials.set_label("mesh batch materials");
+        materials.bind();
+        materials.static_draw_data(&self.materials);
+        materials.unbind();
+
+        let program = Program::from_res(gl, res, "shaders/mesh_batch")?;
+        program.bind_uniform_block("Camera", render::CAMERA_UNIFORMS_BINDING);
+        program.bind_uniform_block("Fog", render::FOG_UNIFORMS_BINDING);
+        program.bind_shader_storage_block("Draws", DRAWS_BINDING);
+        program.bind_shader_storage_block("Materials", MATERIALS_BINDING);
+        let depth_program = Program::from_res_files(
+            gl,
+            res,
+            &[
+                "shaders/mesh_batch_depth.vert",
+                "shaders/render_gl/shadow_depth.frag",
+            ],
+        )?;
+        depth_program.bind_shader_storage_block("Draws", DRAWS_BINDING);
+
+        Ok(MeshBatch {
+            gl: gl.clone(),
+            program_light_direction: program.uniform("LightDirection"),
+            program_shadow: render_gl::ShadowUniforms::new(&program),
+            program,
+            depth_program_light_space: depth_program.uniform("LightSpace"),
+            depth_program,
+            _vbo: vbo,
+            _ebo: ebo,
+            vao,
+            commands,
+            draws,
+            materials,
+            draw_count: self.commands.len(),
+            index_count: self.indices.len(),
+            aabb: self.aabb,
+        })
+    }
+}
+
+/// Static meshes sharing one program, drawn with a single `glMultiDrawElementsIndirect`.
+///
+/// Vertices and indices of all meshes are packed into shared buffers, each mesh is one
+/// indirect command. Shaders read the model matrix and material of the mesh from storage
+/// buffers by `gl_DrawIDARB`.
+pub struct MeshBatch {
+    gl: gl::Gl,
+    program: Program,
+    program_light_direction: Uniform<na::Vector3<f32>>,
+    program_shadow: render_gl::ShadowUniforms,
+    depth_program: Program,
+    depth_program_light_space: Uniform<na::Matrix4<f32>>,
+    _vbo: Buffer,
+    _ebo: Buffer,
+    vao: VertexArray,
+    commands: Buffer,
+    draws: Buffer,
+    materials: Buffer,
+    draw_count: usize,
+    /// Indices of all draws, for frame statistics.
+    index_count: usize,
+    aabb: Option<AABB<f32>>,
+}
+
+impl MeshBatch {
+    pub fn builder() -> MeshBatchBuilder {
+        MeshBatchBuilder::default()
+    }
+
+    /// Storage buffers, indirect draws and `gl_DrawIDARB` need GL 4.3 and
+    /// `GL_ARB_shader_draw_parameters`.
+    pub fn supported(gl: &gl::Gl) -> bool {
+        render_gl::compute_supported(gl)
+            && render_gl::extension_supported(gl, "GL_ARB_shader_draw_parameters")
+    }
+
+    /// Number of meshes drawn by one call.
+    pub fn draw_count(&self) -> usize {
+        self.draw_count
+    }
+
+    fn draw(&self) {
+        if self.draw_count == 0 {
+            return;
+        }
+
+        self.draws.bind_base(DRAWS_BINDING);
+        self.materials.bind_base(MATERIALS_BINDING);
+        self.vao.bind();
+        self.commands.bind();
+        unsafe {
+            self.gl.MultiDrawElementsIndirect(
+                gl::TRIANGLES,
+                gl::UNSIGNED_INT,
+                ::std::ptr::null(), // offset into the bound indirect buffer
+                self.draw_count as i32,
+                0, // commands are tightly packed
+            );
+        }
+        render_gl::FrameStats::record_draw(gl::TRIANGLES, self.index_count as i32, 1);
+        self.commands.unbind();
+        self.vao.unbind();
+    }
+}
+
+impl Entity for MeshBatch {
+    fn render(&self, ctx: &RenderContext) {
+        self.program.set_used();
+        self.program_light_direction
+            .set(&self.program, ctx.shadow_map.light_direction());
+        ctx.shadow_map.bind(&self.program, &self.program_shadow, 0);
+        self.draw();
+    }
+
+    fn render_depth(&self, _gl: &gl::Gl, pass: &render_gl::ShadowPass) {
+        self.depth_program.set_used();
+        self.depth_program_light_space
+            .set(&self.depth_program, pass.light_space_matrix());
+        self.draw();
+    }
+
+    fn bounds(&self) -> Option<(AABB<f32>, na::Matrix4<f32>)> {
+        self.aabb.clone().map(|aabb| (aabb, na::Matrix4::identity()))
+    }
+
+    fn draw_key(&self) -> DrawKey {
+        DrawKey {
+            program: self.program.id(),
+            texture: 0,
+        }
+    }
+}
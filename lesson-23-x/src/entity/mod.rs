@@ -0,0 +1,474 @@
+use failure;
+use gl;
+use nalgebra as na;
+use ncollide3d::bounding_volume::aabb::AABB;
+use crate::render_gl::{
+    self, pbr, BoundsProxy, DebugLines, DebugRenderMode, Frustum, GeometryPass, IdPass,
+    LinesMarker, OcclusionQuery, OutlinePass, ShadowPass, StateCache, UploadQueue,
+};
+use crate::resources::Resources;
+use crate::scene;
+use crate::selection::{ContainerHandle, Selectables};
+use slab::Slab;
+use std::cell::RefCell;
+use std::cmp::Ordering;
+
+mod decal;
+mod mesh_batch;
+mod particles;
+mod sprites;
+mod terrain;
+mod water;
+
+pub use self::decal::{splat_image, Decal};
+pub use self::mesh_batch::{MeshBatch, MeshBatchBuilder};
+pub use self::particles::{EmitterSettings, ParticleEmitter, ParticleSimulation, Particles};
+pub use self::sprites::{AtlasRegion, Sprite, SpriteAtlas, SpriteHandle, Sprites};
+pub use self::terrain::{Terrain, TerrainSettings};
+pub use self::water::Water;
+
+/// Frame state available to entities while updating.
+pub struct UpdateContext<'a> {
+    pub gl: &'a gl::Gl,
+    pub res: &'a Resources,
+    pub uploads: &'a UploadQueue,
+    pub selectables: &'a Selectables,
+    pub camera_pos: na::Point3<f32>,
+    pub delta: f32,
+}
+
+/// Shared inputs of the forward pass.
+pub struct RenderContext<'a> {
+    pub gl: &'a gl::Gl,
+    pub shadow_map: &'a render_gl::ShadowMap,
+    pub environment: &'a pbr::Environment,
+    pub projection: na::Matrix4<f32>,
+    /// Camera of the pass, blended entities are sorted by distance to it.
+    pub camera_pos: na::Point3<f32>,
+    /// View volume of the pass, for entities that cull their own parts.
+    pub frustum: &'a Frustum,
+    /// Overrides filtering of entity color textures, for comparing samplers.
+    pub texture_sampler: Option<&'a render_gl::Sampler>,
+    /// Surroundings for reflective materials, `None` while the probe itself is rendered.
+    pub reflection: Option<&'a render_gl::ReflectionProbe>,
+    /// Parallax occlusion mapping of materials with height maps, plain normal mapping if off.
+    pub parallax_mapping: bool,
+}
+
+/// Program and main texture used by the entity. Draws are sorted by this key,
+/// so that entities sharing them are drawn one after another.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct DrawKey {
+    pub program: gl::types::GLuint,
+    pub texture: gl::types::GLuint,
+}
+
+/// Object owned by `Entities`, updated and drawn every frame.
+pub trait Entity {
+    /// Called with fixed `delta`, zero or more times per frame.
+    fn update(&mut self, _ctx: &UpdateContext) {}
+
+    /// Blend the state of the last two updates for rendering, `alpha` is the progress from
+    /// the previous to the current one.
+    fn interpolate(&mut self, _alpha: f32) {}
+
+    fn render(&self, ctx: &RenderContext);
+
+    /// Draw into the shadow map, entities that do not cast shadows can skip it.
+    fn render_depth(&self, _gl: &gl::Gl, _pass: &ShadowPass) {}
+
+    /// Draw into the G-buffer of the deferred renderer.
+    fn render_geometry(&self, _gl: &gl::Gl, _pass: &GeometryPass) {}
+
+    /// Draw selectable id for GPU picking, entities that can not be selected can skip it.
+    fn render_id(&self, _gl: &gl::Gl, _pass: &IdPass) {}
+
+    /// Draw for the highlight outline, usually only while the entity is selected.
+    fn render_outline(&self, _gl: &gl::Gl, _pass: &OutlinePass) {}
+
+    /// Model-space bounds and model matrix for frustum culling, `None` is never culled.
+    fn bounds(&self) -> Option<(AABB<f32>, na::Matrix4<f32>)> {
+        None
+    }
+
+    fn draw_key(&self) -> DrawKey {
+        DrawKey::default()
+    }
+
+    /// Scene node, if the entity can be attached to others.
+    fn node(&self) -> Option<&scene::Node> {
+        None
+    }
+
+    /// Level of detail currently drawn, for entities that have more than one.
+    fn lod_level(&self) -> Option<usize> {
+        None
+    }
+
+    /// Blend morph targets with the weights, for entities with blend shapes.
+    fn set_morph_weights(&mut self, _weights: &[f32]) {}
+
+    /// True if the selectable belongs to this entity, i.e. to find what the cursor hit.
+    fn has_selectable(&self, _handle: ContainerHandle) -> bool {
+        false
+    }
+
+    /// State to save with the scene, `None` if the entity is not saved.
+    fn record(&self) -> Option<scene::serde::EntityRecord> {
+        None
+    }
+
+    /// Expensive entities can return true to have their bounds tested against the depth
+    /// buffer first, and skip the draw when fully occluded. Needs `bounds`.
+    fn occlusion_tested(&self) -> bool {
+        false
+    }
+
+    /// Transparent entities are drawn by `Entities::render_blended` after opaque ones,
+    /// without writing depth.
+    fn blended(&self) -> bool {
+        false
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct EntityHandle(usize);
+
+#[derive(Copy, Clone, Debug, Default)]
+pub struct RenderStats {
+    pub visible: usize,
+    pub culled: usize,
+    /// Visible entities hidden behind others, as found by the previous frame's queries.
+    pub occluded: usize,
+}
+
+/// Bounds proxy and one query per entity slot.
+struct OcclusionCulling {
+    gl: gl::Gl,
+    proxy: BoundsProxy,
+    queries: RefCell<Vec<OcclusionQuery>>,
+}
+
+/// Owns entities and dispatches update and render calls to them.
+#[derive(Default)]
+pub struct Entities {
+    entities: Slab<Box<dyn Entity>>,
+    bounds_lines: Option<LinesMarker>,
+    occlusion: Option<OcclusionCulling>,
+}
+
+impl Entities {
+    pub fn new() -> Entities {
+        Entities::default()
+    }
+
+    pub fn add<E: Entity + 'static>(&mut self, entity: E) -> EntityHandle {
+        self.add_boxed(Box::new(entity))
+    }
+
+    pub fn add_boxed(&mut self, entity: Box<dyn Entity>) -> EntityHandle {
+        EntityHandle(self.entities.insert(entity))
+    }
+
+    pub fn remove(&mut self, handle: EntityHandle) -> Option<Box<dyn Entity>> {
+        if self.entities.contains(handle.0) {
+            Some(self.entities.remove(handle.0))
+        } else {
+            None
+        }
+    }
+
+    /// Remove the entity and the entities attached to its node, returns all removed handles.
+    ///
+    /// Entities are dropped right away, so that their selectables, debug lines and
+    /// buffers go with them. Textures no longer used by anything are evicted from the cache.
+    pub fn despawn(&mut self, handle: EntityHandle) -> Vec<EntityHandle> {
+        let mut removed = Vec::new();
+        let mut nodes = Vec::new();
+        let mut pending = vec![handle];
+        while let Some(handle) = pending.pop() {
+            let entity = match self.remove(handle) {
+                Some(entity) => entity,
+                None => continue,
+            };
+            removed.push(handle);
+            if let Some(node) = entity.node() {
+                nodes.push(node.handle());
+            }
+            // children find their parent while it still exists
+            pending.extend(
+                self.entities
+                    .iter()
+                    .filter(|(_, child)| {
+                        child
+                            .node()
+                            .and_then(|node| node.parent())
+                            .map_or(false, |parent| nodes.contains(&parent))
+                    }).map(|(key, _)| EntityHandle(key)),
+            );
+        }
+        if !removed.is_empty() {
+            render_gl::TextureCache::evict_unused();
+        }
+        removed
+    }
+
+    /// Entity owning the selectable, i.e. the one under the cursor.
+    pub fn find_by_selectable(&self, handle: ContainerHandle) -> Option<EntityHandle> {
+        self.entities
+            .iter()
+            .find(|(_, entity)| entity.has_selectable(handle))
+            .map(|(key, _)| EntityHandle(key))
+    }
+
+    pub fn get(&self, handle: EntityHandle) -> Option<&dyn Entity> {
+        self.entities.get(handle.0).map(|entity| &**entity)
+    }
+
+    pub fn get_mut(&mut self, handle: EntityHandle) -> Option<&mut (dyn Entity + 'static)> {
+        self.entities.get_mut(handle.0).map(|entity| &mut **entity)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entities.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entities.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &dyn Entity> {
+        self.entities.iter().map(|(_, entity)| &**entity)
+    }
+
+    /// Remove entities for which the predicate returns false.
+    pub fn retain<F: FnMut(&dyn Entity) -> bool>(&mut self, mut keep: F) {
+        self.entities.retain(|_, entity| keep(&**entity));
+    }
+
+    /// Test entities that ask for it with occlusion queries in forward `render`.
+    pub fn enable_occlusion_culling(
+        &mut self,
+        gl: &gl::Gl,
+        res: &Resources,
+    ) -> Result<(), failure::Error> {
+        if self.occlusion.is_none() {
+            self.occlusion = Some(OcclusionCulling {
+                gl: gl.clone(),
+                proxy: BoundsProxy::new(gl, res)?,
+                queries: RefCell::new(Vec::new()),
+            });
+        }
+        Ok(())
+    }
+
+    pub fn disable_occlusion_culling(&mut self) {
+        self.occlusion = None;
+    }
+
+    pub fn occlusion_culling_enabled(&self) -> bool {
+        self.occlusion.is_some()
+    }
+
+    pub fn update(&mut self, ctx: &UpdateContext) {
+        for (_, entity) in self.entities.iter_mut() {
+            entity.update(ctx);
+        }
+    }
+
+    pub fn interpolate(&mut self, alpha: f32) {
+        for (_, entity) in self.entities.iter_mut() {
+            entity.interpolate(alpha);
+        }
+    }
+
+    pub fn render_depth(&self, gl: &gl::Gl, pass: &ShadowPass) {
+        for (_, entity) in self.entities.iter() {
+            entity.render_depth(gl, pass);
+        }
+    }
+
+    pub fn render_id(&self, gl: &gl::Gl, pass: &IdPass) {
+        for (_, entity) in self.entities.iter() {
+            entity.render_id(gl, pass);
+        }
+    }
+
+    pub fn render_outlines(&self, gl: &gl::Gl, pass: &OutlinePass) {
+        for (_, entity) in self.entities.iter() {
+            entity.render_outline(gl, pass);
+        }
+    }
+
+    /// Draw entities visible in the frustum, sorted by draw key.
+    ///
+    /// With occlusion culling enabled, occlusion tested entities are drawn after the rest,
+    /// each behind a query on its bounds proxy and with conditional rendering.
+    pub fn render(&self, ctx: &RenderContext, frustum: &Frustum) -> RenderStats {
+        let (visible, mut stats) = self.visible_sorted(frustum);
+        let opaque = visible.into_iter().filter(|&(_, entity)| !entity.blended());
+
+        let occlusion = match self.occlusion {
+            Some(ref occlusion) => occlusion,
+            None => {
+                for (_, entity) in opaque {
+                    entity.render(ctx);
+                }
+                return stats;
+            }
+        };
+
+        let (tested, occluders): (Vec<_>, Vec<_>) = opaque.partition(|&(_, entity)| {
+            entity.occlusion_tested() && entity.bounds().is_some()
+        });
+
+        for (_, entity) in occluders {
+            entity.render(ctx);
+        }
+
+        let mut queries = occlusion.queries.borrow_mut();
+        for (key, entity) in tested {
+            while queries.len() <= key {
+                queries.push(OcclusionQuery::new(&occlusion.gl));
+            }
+            let query = &queries[key];
+
+            if query.try_result() == Some(false) {
+                stats.occluded += 1;
+            }
+
+            if let Some((aabb, model_matrix)) = entity.bounds() {
+                let _query_scope = query.begin();
+                occlusion.proxy.render(&aabb, &model_matrix);
+            }
+
+            let _conditional_scope = query.conditional_render();
+            entity.render(ctx);
+        }
+
+        stats
+    }
+
+    /// Draw blended entities visible in the frustum, after opaque entities and the sky.
+    ///
+    /// Entities are sorted back to front by the center of their bounds, entities without
+    /// bounds are drawn last. Alpha blending is enabled and depth writes are disabled for
+    /// the whole pass, so blended entities are hidden by opaque ones but not by each other.
+    pub fn render_blended(&self, ctx: &RenderContext, frustum: &Frustum) {
+        let (visible, _) = self.visible_sorted(frustum);
+        let mut blended = visible
+            .into_iter()
+            .filter(|&(_, entity)| entity.blended())
+            .map(|(_, entity)| {
+                let distance = entity
+                    .bounds()
+                    .map(|(aabb, model_matrix)| {
+                        let center = model_matrix.transform_point(&aabb.center());
+                        na::distance_squared(&center, &ctx.camera_pos)
+                    }).unwrap_or(-1.0);
+                (distance, entity)
+            }).collect::<Vec<_>>();
+        if blended.is_empty() {
+            return;
+        }
+        blended.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(Ordering::Equal));
+
+        StateCache::set_blend(ctx.gl, true);
+        StateCache::set_blend_func(ctx.gl, gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+        StateCache::set_depth_mask(ctx.gl, false);
+        for (_, entity) in blended {
+            entity.render(ctx);
+        }
+        StateCache::set_depth_mask(ctx.gl, true);
+        StateCache::set_blend(ctx.gl, false);
+    }
+
+    /// Draw opaque entities visible in the frustum except one, i.e. into a reflection probe
+    /// placed inside the excluded entity. Occlusion queries are left to the main view.
+    pub fn render_excluding(
+        &self,
+        ctx: &RenderContext,
+        frustum: &Frustum,
+        excluded: Option<EntityHandle>,
+    ) {
+        let (visible, _) = self.visible_sorted(frustum);
+        for (key, entity) in visible {
+            if !entity.blended() && excluded != Some(EntityHandle(key)) {
+                entity.render(ctx);
+            }
+        }
+    }
+
+    pub fn render_geometry(
+        &self,
+        gl: &gl::Gl,
+        pass: &GeometryPass,
+        frustum: &Frustum,
+    ) -> RenderStats {
+        let (visible, stats) = self.visible_sorted(frustum);
+        // blended entities are drawn forward after lighting
+        for (_, entity) in visible.into_iter().filter(|&(_, entity)| !entity.blended()) {
+            entity.render_geometry(gl, pass);
+        }
+        stats
+    }
+
+    /// Show entity bounds with debug lines while the mode draws bounds, hide them otherwise.
+    pub fn update_bounds_lines(&mut self, debug_lines: &DebugLines, mode: DebugRenderMode) {
+        if !mode.draws_bounds() {
+            self.bounds_lines = None;
+            return;
+        }
+
+        let color = na::Vector4::new(1.0, 0.8, 0.2, 1.0);
+        let mut segments = Vec::new();
+        for (_, entity) in self.entities.iter() {
+            if let Some((aabb, model_matrix)) = entity.bounds() {
+                let (a, b) = (aabb.mins(), aabb.maxs());
+                let corner = |index: usize| {
+                    model_matrix.transform_point(&na::Point3::new(
+                        if index & 1 == 0 { a.x } else { b.x },
+                        if index & 2 == 0 { a.y } else { b.y },
+                        if index & 4 == 0 { a.z } else { b.z },
+                    ))
+                };
+                // corners differing in a single bit share an edge
+                for index in 0..8 {
+                    for bit in &[1, 2, 4] {
+                        if index & bit == 0 {
+                            segments.push((corner(index), corner(index | bit), color));
+                        }
+                    }
+                }
+            }
+        }
+
+        match self.bounds_lines {
+            Some(ref lines) => lines.update_lines(segments.into_iter()),
+            None => {
+                self.bounds_lines = Some(
+                    debug_lines.lines_marker(na::Isometry3::identity(), segments.into_iter()),
+                )
+            }
+        }
+    }
+
+    fn visible_sorted(&self, frustum: &Frustum) -> (Vec<(usize, &dyn Entity)>, RenderStats) {
+        let mut visible: Vec<(usize, &dyn Entity)> = self
+            .entities
+            .iter()
+            .map(|(key, entity)| (key, &**entity))
+            .filter(|&(_, entity)| match entity.bounds() {
+                Some((aabb, model_matrix)) => frustum.intersects_aabb(&aabb, &model_matrix),
+                None => true,
+            }).collect();
+        visible.sort_by_key(|&(_, entity)| entity.draw_key());
+
+        let stats = RenderStats {
+            visible: visible.len(),
+            culled: self.entities.len() - visible.len(),
+            occluded: 0,
+        };
+        (visible, stats)
+    }
+}
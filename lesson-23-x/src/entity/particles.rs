@@ -0,0 +1,530 @@
+use failure;
+use gl;
+use nalgebra as na;
+use crate::entity::{Entity, RenderContext, UpdateContext};
+use crate::render;
+use crate::render_gl::buffer::{Buffer, VertexArray};
+use crate::render_gl::{self, data, Framebuffer, Program};
+use crate::resources::Resources;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// Storage block binding point of the particle buffer in the compute shader.
+const PARTICLES_BINDING: u32 = 0;
+
+/// Streamed CPU buffers in flight, so that the GPU is not waited for while writing.
+const RING_SIZE: usize = 3;
+
+/// Instance data of one particle, also the layout of the compute shader storage buffer.
+#[derive(VertexAttribPointers, Copy, Clone, Debug)]
+#[repr(C, packed)]
+struct ParticleVertex {
+    #[location = "0"]
+    #[divisor = "1"]
+    position_age: data::f32_f32_f32_f32,
+    #[location = "1"]
+    #[divisor = "1"]
+    velocity_lifetime: data::f32_f32_f32_f32,
+}
+
+/// Emitter parameters, can be changed while particles are running.
+#[derive(Copy, Clone, Debug)]
+pub struct EmitterSettings {
+    pub origin: na::Point3<f32>,
+    /// Particles spawned per second.
+    pub rate: f32,
+    /// Seconds a particle lives.
+    pub lifetime: f32,
+    pub velocity: na::Vector3<f32>,
+    /// Radius of random velocity added to `velocity`.
+    pub velocity_spread: f32,
+    pub gravity: na::Vector3<f32>,
+    pub size: f32,
+    pub color: na::Vector4<f32>,
+    /// Distance over which particles fade out in front of geometry, 0 disables fading.
+    pub soft_distance: f32,
+}
+
+impl Default for EmitterSettings {
+    fn default() -> EmitterSettings {
+        EmitterSettings {
+            origin: na::Point3::origin(),
+            rate: 200.0,
+            lifetime: 3.0,
+            velocity: na::Vector3::new(0.0, 0.0, 6.0),
+            velocity_spread: 2.0,
+            gravity: na::Vector3::new(0.0, 0.0, -3.0),
+            size: 0.3,
+            color: na::Vector4::new(1.0, 0.6, 0.2, 0.8),
+            soft_distance: 0.5,
+        }
+    }
+}
+
+/// Shared handle to emitter settings of `Particles`, kept after the particles
+/// are moved into `Entities`.
+#[derive(Clone)]
+pub struct ParticleEmitter {
+    settings: Rc<RefCell<EmitterSettings>>,
+}
+
+impl ParticleEmitter {
+    pub fn settings(&self) -> EmitterSettings {
+        *self.settings.borrow()
+    }
+
+    pub fn set_settings(&self, settings: EmitterSettings) {
+        *self.settings.borrow_mut() = settings;
+    }
+
+    pub fn update<F: FnOnce(&mut EmitterSettings)>(&self, f: F) {
+        f(&mut self.settings.borrow_mut());
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ParticleSimulation {
+    /// Simulate on the CPU and stream particles every frame.
+    Cpu,
+    /// Simulate in a compute shader, particles never leave the GPU.
+    Gpu,
+}
+
+impl ParticleSimulation {
+    /// GPU simulation if compute shaders are supported.
+    pub fn best(gl: &gl::Gl) -> ParticleSimulation {
+        if render_gl::compute_supported(gl) {
+            ParticleSimulation::Gpu
+        } else {
+            ParticleSimulation::Cpu
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+struct Particle {
+    position: na::Point3<f32>,
+    velocity: na::Vector3<f32>,
+    age: f32,
+    lifetime: f32,
+}
+
+impl Particle {
+    fn is_alive(&self) -> bool {
+        self.age < self.lifetime
+    }
+
+    fn to_vertex(self) -> ParticleVertex {
+        let (p, v) = (self.position, self.velocity);
+        ParticleVertex {
+            position_age: (p.x, p.y, p.z, self.age).into(),
+            velocity_lifetime: (v.x, v.y, v.z, self.lifetime).into(),
+        }
+    }
+}
+
+/// Particles simulated on the CPU and streamed through a ring of orphaned buffers.
+struct CpuParticles {
+    particles: Vec<Particle>,
+    ring: Vec<(Buffer, VertexArray)>,
+    ring_index: usize,
+    alive_count: usize,
+    random: u32,
+}
+
+/// Particles in a storage buffer updated by a compute shader, and drawn from it directly.
+struct GpuParticles {
+    program: Program,
+    buffer: Buffer,
+    vao: VertexArray,
+    seed: i32,
+}
+
+enum Simulation {
+    Cpu(CpuParticles),
+    Gpu(GpuParticles),
+}
+
+/// Pool of billboard particles spawned by an emitter, drawn with alpha blending
+/// after opaque entities.
+pub struct Particles {
+    gl: gl::Gl,
+    program: Program,
+    capacity: usize,
+    settings: Rc<RefCell<EmitterSettings>>,
+    simulation: Simulation,
+    spawn_accumulator: f32,
+    spawn_cursor: usize,
+    /// Copy of the scene depth, sampled for soft fading.
+    scene_depth: RefCell<Option<Framebuffer>>,
+}
+
+impl Particles {
+    pub fn new(
+        gl: &gl::Gl,
+        res: &Resources,
+        capacity: usize,
+        simulation: ParticleSimulation,
+        settings: EmitterSettings,
+    ) -> Result<Particles, failure::Error> {
+        let program = Program::from_res(gl, res, "shaders/particles")?;
+        program.bind_uniform_block("Camera", render::CAMERA_UNIFORMS_BINDING);
+
+        let dead = ParticleVertex {
+            position_age: (0.0, 0.0, 0.0, 0.0).into(),
+            velocity_lifetime: (0.0, 0.0, 0.0, 0.0).into(),
+        };
+
+        let simulation = match simulation {
+            ParticleSimulation::Cpu => Simulation::Cpu(CpuParticles {
+                particles: Vec::with_capacity(capacity),
+                ring: (0..RING_SIZE)
+                    .map(|_| particle_buffers(gl, Buffer::new_array(gl)))
+                    .collect(),
+                ring_index: 0,
+                alive_count: 0,
+                random: 0x9e37_79b9,
+            }),
+            ParticleSimulation::Gpu => {
+                let compute_program = Program::from_res_compute(gl, res, "shaders/particles")?;
+                compute_program.bind_shader_storage_block("Particles", PARTICLES_BINDING);
+
+                let buffer = Buffer::new_shader_storage(gl);
+                buffer.bind();
+                buffer.dynamic_draw_data(&vec![dead; capacity]);
+                buffer.unbind();
+                let (buffer, vao) = particle_buffers(gl, buffer);
+
+                Simulation::Gpu(GpuParticles {
+                    program: compute_program,
+                    buffer,
+                    vao,
+                    seed: 0,
+                })
+            }
+        };
+
+        Ok(Particles {
+            gl: gl.clone(),
+            program,
+            capacity,
+            settings: Rc::new(RefCell::new(settings)),
+            simulation,
+            spawn_accumulator: 0.0,
+            spawn_cursor: 0,
+            scene_depth: RefCell::new(None),
+        })
+    }
+
+    pub fn emitter(&self) -> ParticleEmitter {
+        ParticleEmitter {
+            settings: self.settings.clone(),
+        }
+    }
+
+    pub fn simulation(&self) -> ParticleSimulation {
+        match self.simulation {
+            Simulation::Cpu(_) => ParticleSimulation::Cpu,
+            Simulation::Gpu(_) => ParticleSimulation::Gpu,
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Spawn new particles and move existing ones.
+    pub fn simulate(&mut self, delta: f32) {
+        let settings = *self.settings.borrow();
+
+        // new particles replace the oldest ones in a ring
+        self.spawn_accumulator += settings.rate.max(0.0) * delta;
+        let spawn_count = (self.spawn_accumulator as usize).min(self.capacity);
+        // drop the remainder if more than the whole pool was due
+        self.spawn_accumulator = (self.spawn_accumulator - spawn_count as f32).min(1.0);
+        let spawn_start = self.spawn_cursor;
+        self.spawn_cursor = (self.spawn_cursor + spawn_count) % self.capacity.max(1);
+
+        match self.simulation {
+            Simulation::Cpu(ref mut cpu) => {
+                cpu.simulate(&settings, self.capacity, spawn_start, spawn_count, delta)
+            }
+            Simulation::Gpu(ref mut gpu) => {
+                gpu.simulate(&settings, self.capacity, spawn_start, spawn_count, delta)
+            }
+        }
+    }
+
+    pub fn render(&self, ctx: &RenderContext) {
+        let gl = &self.gl;
+        let settings = *self.settings.borrow();
+
+        let (vao, instance_count) = match self.simulation {
+            Simulation::Cpu(ref cpu) => (&cpu.ring[cpu.ring_index].1, cpu.alive_count),
+            Simulation::Gpu(ref gpu) => {
+                render_gl::memory_barrier(gl, gl::VERTEX_ATTRIB_ARRAY_BARRIER_BIT);
+                (&gpu.vao, self.capacity)
+            }
+        };
+        if instance_count == 0 {
+            return;
+        }
+
+        let mut viewport: [gl::types::GLint; 4] = [0; 4];
+        unsafe {
+            gl.GetIntegerv(gl::VIEWPORT, viewport.as_mut_ptr());
+        }
+
+        let soft_distance =
+            if settings.soft_distance > 0.0 && self.copy_scene_depth(viewport[2], viewport[3]) {
+                settings.soft_distance
+            } else {
+                0.0
+            };
+
+        self.program.set_used();
+        if let Some(loc) = self.program.get_uniform_location("Size") {
+            self.program.set_uniform_1f(loc, settings.size);
+        }
+        if let Some(loc) = self.program.get_uniform_location("Color") {
+            self.program.set_uniform_4f(loc, &settings.color);
+        }
+        if let Some(loc) = self.program.get_uniform_location("SoftDistance") {
+            self.program.set_uniform_1f(loc, soft_distance);
+        }
+        if let Some(loc) = self.program.get_uniform_location("Projection") {
+            let projection = na::Vector2::new(ctx.projection[(2, 2)], ctx.projection[(2, 3)]);
+            self.program.set_uniform_2f(loc, &projection);
+        }
+        if let Some(loc) = self.program.get_uniform_location("ViewportSize") {
+            let size = na::Vector2::new(viewport[2] as f32, viewport[3] as f32);
+            self.program.set_uniform_2f(loc, &size);
+        }
+        if let Some(loc) = self.program.get_uniform_location("SceneDepth") {
+            if let Some(texture) = self
+                .scene_depth
+                .borrow()
+                .as_ref()
+                .and_then(|framebuffer| framebuffer.depth_texture())
+            {
+                texture.bind_at(0);
+            }
+            self.program.set_uniform_1i(loc, 0);
+        }
+
+        // blending and depth writes are set by the blended pass
+        render_gl::StateCache::set_cull_face(gl, false);
+
+        vao.bind();
+        unsafe {
+            gl.DrawArraysInstanced(gl::TRIANGLE_STRIP, 0, 4, instance_count as i32);
+        }
+        render_gl::FrameStats::record_draw(gl::TRIANGLE_STRIP, 4, instance_count as i32);
+        vao.unbind();
+
+        render_gl::StateCache::set_cull_face(gl, true);
+    }
+
+    /// Blit depth of the bound framebuffer into the scene depth copy.
+    /// Returns false if there is no copy to sample.
+    fn copy_scene_depth(&self, width: i32, height: i32) -> bool {
+        let gl = &self.gl;
+        let mut scene_depth = self.scene_depth.borrow_mut();
+
+        let ready = match *scene_depth {
+            Some(ref mut framebuffer) => framebuffer.resize(width, height).is_ok(),
+            None => match Framebuffer::builder(width, height)
+                .with_depth_stencil()
+                .build(gl)
+            {
+                Ok(framebuffer) => {
+                    *scene_depth = Some(framebuffer);
+                    true
+                }
+                Err(e) => {
+                    println!("Soft particles disabled: {}", e);
+                    false
+                }
+            },
+        };
+        let framebuffer = match *scene_depth {
+            Some(ref framebuffer) if ready => framebuffer,
+            _ => return false,
+        };
+
+        let mut draw_fbo: gl::types::GLint = 0;
+        let mut read_fbo: gl::types::GLint = 0;
+        unsafe {
+            gl.GetIntegerv(gl::DRAW_FRAMEBUFFER_BINDING, &mut draw_fbo);
+            gl.GetIntegerv(gl::READ_FRAMEBUFFER_BINDING, &mut read_fbo);
+
+            gl.BindFramebuffer(gl::READ_FRAMEBUFFER, draw_fbo as gl::types::GLuint);
+            gl.BindFramebuffer(gl::DRAW_FRAMEBUFFER, framebuffer.id());
+            gl.BlitFramebuffer(
+                0,
+                0,
+                width,
+                height,
+                0,
+                0,
+                width,
+                height,
+                gl::DEPTH_BUFFER_BIT,
+                gl::NEAREST,
+            );
+            gl.BindFramebuffer(gl::DRAW_FRAMEBUFFER, draw_fbo as gl::types::GLuint);
+            gl.BindFramebuffer(gl::READ_FRAMEBUFFER, read_fbo as gl::types::GLuint);
+        }
+
+        true
+    }
+}
+
+impl CpuParticles {
+    fn simulate(
+        &mut self,
+        settings: &EmitterSettings,
+        capacity: usize,
+        spawn_start: usize,
+        spawn_count: usize,
+        delta: f32,
+    ) {
+        for particle in self.particles.iter_mut().filter(|p| p.is_alive()) {
+            particle.velocity += settings.gravity * delta;
+            particle.position += particle.velocity * delta;
+            particle.age += delta;
+        }
+
+        for offset in 0..spawn_count {
+            let particle = Particle {
+                position: settings.origin,
+                velocity: settings.velocity + self.random_in_sphere() * settings.velocity_spread,
+                age: 0.0,
+                lifetime: settings.lifetime,
+            };
+            let index = (spawn_start + offset) % capacity;
+            if index < self.particles.len() {
+                self.particles[index] = particle;
+            } else {
+                self.particles.push(particle);
+            }
+        }
+
+        self.stream();
+    }
+
+    /// Write alive particles into the next buffer of the ring. The buffer is orphaned
+    /// first, so the driver does not wait for draws still reading the old storage.
+    fn stream(&mut self) {
+        self.ring_index = (self.ring_index + 1) % self.ring.len();
+        let buffer = &self.ring[self.ring_index].0;
+
+        buffer.bind();
+        buffer.stream_draw_data_null::<ParticleVertex>(self.particles.len());
+        self.alive_count = 0;
+        if !self.particles.is_empty() {
+            if let Some(mut mapped) = unsafe {
+                buffer.map_buffer_range_write_invalidate::<ParticleVertex>(0, self.particles.len())
+            } {
+                for particle in self.particles.iter().filter(|p| p.is_alive()) {
+                    mapped.push(particle.to_vertex());
+                    self.alive_count += 1;
+                }
+            }
+        }
+        buffer.unbind();
+    }
+
+    /// Random point in the unit sphere, from a xorshift generator.
+    fn random_in_sphere(&mut self) -> na::Vector3<f32> {
+        loop {
+            let v = na::Vector3::new(self.random(), self.random(), self.random()) * 2.0
+                - na::Vector3::repeat(1.0);
+            if v.norm_squared() <= 1.0 {
+                return v;
+            }
+        }
+    }
+
+    fn random(&mut self) -> f32 {
+        self.random ^= self.random << 13;
+        self.random ^= self.random >> 17;
+        self.random ^= self.random << 5;
+        self.random as f32 / ::std::u32::MAX as f32
+    }
+}
+
+impl GpuParticles {
+    fn simulate(
+        &mut self,
+        settings: &EmitterSettings,
+        capacity: usize,
+        spawn_start: usize,
+        spawn_count: usize,
+        delta: f32,
+    ) {
+        let program = &self.program;
+        self.seed = self.seed.wrapping_add(1);
+
+        program.set_used();
+        let ints = [
+            ("ParticleCount", capacity as i32),
+            ("SpawnStart", spawn_start as i32),
+            ("SpawnCount", spawn_count as i32),
+            ("Seed", self.seed),
+        ];
+        for &(name, value) in &ints {
+            if let Some(loc) = program.get_uniform_location(name) {
+                program.set_uniform_1i(loc, value);
+            }
+        }
+        let floats = [
+            ("Delta", delta),
+            ("VelocitySpread", settings.velocity_spread),
+            ("Lifetime", settings.lifetime),
+        ];
+        for &(name, value) in &floats {
+            if let Some(loc) = program.get_uniform_location(name) {
+                program.set_uniform_1f(loc, value);
+            }
+        }
+        let vectors = [
+            ("Origin", settings.origin.coords),
+            ("Velocity", settings.velocity),
+            ("Gravity", settings.gravity),
+        ];
+        for &(name, ref value) in &vectors {
+            if let Some(loc) = program.get_uniform_location(name) {
+                program.set_uniform_3f(loc, value);
+            }
+        }
+
+        self.buffer.bind_base(PARTICLES_BINDING);
+        program.dispatch_compute_1d(capacity as u32);
+    }
+}
+
+/// Vertex array drawing one particle instance per element of the buffer.
+fn particle_buffers(gl: &gl::Gl, buffer: Buffer) -> (Buffer, VertexArray) {
+    let vao = VertexArray::new(gl);
+    vao.bind();
+    buffer.bind_as(gl::ARRAY_BUFFER);
+    ParticleVertex::vertex_attrib_pointers(gl);
+    vao.unbind();
+    buffer.unbind_as(gl::ARRAY_BUFFER);
+    (buffer, vao)
+}
+
+impl Entity for Particles {
+    fn update(&mut self, ctx: &UpdateContext) {
+        self.simulate(ctx.delta);
+    }
+
+    fn render(&self, ctx: &RenderContext) {
+        Particles::render(self, ctx);
+    }
+
+    fn blended(&self) -> bool {
+        true
+    }
+}
@@ -0,0 +1,319 @@
+use failure;
+use gl;
+use nalgebra as na;
+use ncollide3d::bounding_volume::aabb::AABB;
+use crate::entity::{DrawKey, Entity, RenderContext, UpdateContext};
+use crate::mesh;
+use crate::render;
+use crate::render_gl::buffer::{Buffer, VertexArray};
+use crate::render_gl::{data, ColorSpace, FrameStats, Program, StateCache, Texture, Uniform};
+use crate::resources::Resources;
+use crate::selection::{Action, SelectableAABB, Selectables};
+use serde_json;
+use slab::Slab;
+use std::collections::HashMap;
+
+#[derive(VertexAttribPointers, Copy, Clone, Debug)]
+#[repr(C, packed)]
+struct SpriteInstance {
+    #[location = "0"]
+    #[divisor = "1"]
+    position: data::f32_f32_f32,
+    #[location = "1"]
+    #[divisor = "1"]
+    size: data::f32_f32,
+    #[location = "2"]
+    #[divisor = "1"]
+    uv_rect: data::f32_f32_f32_f32,
+    #[location = "3"]
+    #[divisor = "1"]
+    color: data::f32_f32_f32_f32,
+}
+
+/// Atlas descriptor, regions are `[x, y, width, height]` in pixels from the top left.
+#[derive(Deserialize)]
+struct AtlasFile {
+    texture: String,
+    regions: HashMap<String, [u32; 4]>,
+}
+
+/// Part of the atlas texture, in texture coordinates.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct AtlasRegion {
+    pub min: na::Point2<f32>,
+    pub max: na::Point2<f32>,
+}
+
+/// Texture with named regions, described by a JSON file next to it.
+pub struct SpriteAtlas {
+    texture: Texture,
+    regions: HashMap<String, AtlasRegion>,
+}
+
+impl SpriteAtlas {
+    pub fn from_res(
+        gl: &gl::Gl,
+        res: &Resources,
+        name: &str,
+    ) -> Result<SpriteAtlas, failure::Error> {
+        let file: AtlasFile = serde_json::from_slice(&res.load_bytes(name)?)?;
+        let img = res.load_rgba_image(&file.texture[..])?;
+        let (width, height) = (img.width() as f32, img.height() as f32);
+
+        let texture = Texture::from_rgba_image(gl, &img, ColorSpace::Srgb, true);
+        texture.set_label(&file.texture);
+
+        let regions = file
+            .regions
+            .into_iter()
+            .map(|(name, [x, y, w, h])| {
+                let region = AtlasRegion {
+                    min: na::Point2::new(x as f32 / width, y as f32 / height),
+                    max: na::Point2::new((x + w) as f32 / width, (y + h) as f32 / height),
+                };
+                (name, region)
+            }).collect();
+
+        Ok(SpriteAtlas { texture, regions })
+    }
+
+    pub fn region(&self, name: &str) -> Option<AtlasRegion> {
+        self.regions.get(name).cloned()
+    }
+
+    pub fn texture(&self) -> &Texture {
+        &self.texture
+    }
+}
+
+/// Camera-facing quad showing a region of the atlas, i.e. a marker or a label.
+#[derive(Clone, Debug)]
+pub struct Sprite {
+    pub position: na::Point3<f32>,
+    /// Width and height in world units.
+    pub size: na::Vector2<f32>,
+    pub region: String,
+    /// Multiplied with the atlas color.
+    pub color: na::Vector4<f32>,
+}
+
+impl Sprite {
+    pub fn new(position: na::Point3<f32>, region: &str) -> Sprite {
+        Sprite {
+            position,
+            size: na::Vector2::new(1.0, 1.0),
+            region: region.to_string(),
+            color: na::Vector4::new(1.0, 1.0, 1.0, 1.0),
+        }
+    }
+
+    pub fn with_size(mut self, width: f32, height: f32) -> Self {
+        self.size = na::Vector2::new(width, height);
+        self
+    }
+
+    pub fn with_color(mut self, color: na::Vector4<f32>) -> Self {
+        self.color = color;
+        self
+    }
+
+    /// Box around the quad in any orientation, relative to the position.
+    fn aabb(&self) -> AABB<f32> {
+        let half = self.size.x.max(self.size.y) * 0.5;
+        AABB::new(
+            na::Point3::new(-half, -half, -half),
+            na::Point3::new(half, half, half),
+        )
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct SpriteHandle(usize);
+
+/// Sprites of one atlas, drawn with a single instanced draw call.
+///
+/// Sprites added with `add_selectable` can be clicked and dragged around. They are
+/// ray cast against their bounds and do not take part in GPU picking.
+pub struct Sprites {
+    gl: gl::Gl,
+    atlas: SpriteAtlas,
+    program: Program,
+    atlas_uniform: Uniform<i32>,
+    sprites: Slab<(Sprite, Option<SelectableAABB>)>,
+    instances: Vec<SpriteInstance>,
+    vbo: Buffer,
+    vao: VertexArray,
+}
+
+impl Sprites {
+    pub fn new(
+        gl: &gl::Gl,
+        res: &Resources,
+        atlas: SpriteAtlas,
+    ) -> Result<Sprites, failure::Error> {
+        let program = Program::from_res(gl, res, "shaders/sprite")?;
+        program.bind_uniform_block("Camera", render::CAMERA_UNIFORMS_BINDING);
+
+        let vbo = Buffer::new_array(gl);
+        let vao = VertexArray::new(gl);
+        vao.bind();
+        vbo.bind();
+        SpriteInstance::vertex_attrib_pointers(gl);
+        vbo.unbind();
+        vao.unbind();
+
+        Ok(Sprites {
+            gl: gl.clone(),
+            atlas,
+            atlas_uniform: program.uniform("Atlas"),
+            program,
+            sprites: Slab::new(),
+            instances: Vec::new(),
+            vbo,
+            vao,
+        })
+    }
+
+    pub fn add(&mut self, sprite: Sprite) -> SpriteHandle {
+        let handle = SpriteHandle(self.sprites.insert((sprite, None)));
+        self.upload();
+        handle
+    }
+
+    pub fn add_selectable(&mut self, sprite: Sprite, selectables: &Selectables) -> SpriteHandle {
+        let selectable = selectables.selectable(
+            sprite.aabb(),
+            na::Isometry3::translation(sprite.position.x, sprite.position.y, sprite.position.z),
+        );
+        let handle = SpriteHandle(self.sprites.insert((sprite, Some(selectable))));
+        self.upload();
+        handle
+    }
+
+    pub fn remove(&mut self, handle: SpriteHandle) -> Option<Sprite> {
+        if !self.sprites.contains(handle.0) {
+            return None;
+        }
+        let (sprite, _) = self.sprites.remove(handle.0);
+        self.upload();
+        Some(sprite)
+    }
+
+    pub fn get(&self, handle: SpriteHandle) -> Option<&Sprite> {
+        self.sprites.get(handle.0).map(|(sprite, _)| sprite)
+    }
+
+    pub fn set_position(&mut self, handle: SpriteHandle, position: na::Point3<f32>) {
+        if let Some((sprite, selectable)) = self.sprites.get_mut(handle.0) {
+            sprite.position = position;
+            if let Some(selectable) = selectable {
+                selectable.update_isometry(na::Isometry3::translation(
+                    position.x, position.y, position.z,
+                ));
+            }
+        }
+        self.upload();
+    }
+
+    pub fn len(&self) -> usize {
+        self.sprites.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sprites.is_empty()
+    }
+
+    /// Rebuild the instance buffer, sprites with unknown regions are skipped.
+    fn upload(&mut self) {
+        let atlas = &self.atlas;
+        self.instances.clear();
+        self.instances
+            .extend(self.sprites.iter().filter_map(|(_, (sprite, _))| {
+                let region = atlas.region(&sprite.region)?;
+                let p = sprite.position;
+                let c = sprite.color;
+                Some(SpriteInstance {
+                    position: (p.x, p.y, p.z).into(),
+                    size: (sprite.size.x, sprite.size.y).into(),
+                    uv_rect: (region.min.x, region.min.y, region.max.x, region.max.y).into(),
+                    color: (c.x, c.y, c.z, c.w).into(),
+                })
+            }));
+
+        self.vbo.bind();
+        self.vbo.static_draw_data(&self.instances);
+        self.vbo.unbind();
+    }
+
+    /// Apply clicks and drags of selectable sprites.
+    fn apply_actions(&mut self) {
+        let mut moved = false;
+        for (_, (sprite, selectable)) in self.sprites.iter_mut() {
+            let selectable = match selectable {
+                Some(selectable) => selectable,
+                None => continue,
+            };
+            while let Some(action) = selectable.drain_pending_action() {
+                match action {
+                    Action::Click { .. } => selectable.select(),
+                    Action::Drag { new_isometry } => {
+                        sprite.position = na::Point3::from(new_isometry.translation.vector);
+                        selectable.update_isometry(na::Isometry3::from_parts(
+                            new_isometry.translation,
+                            na::UnitQuaternion::identity(),
+                        ));
+                        moved = true;
+                    }
+                    // quads keep their size, they always face the camera
+                    Action::Scale { .. } => (),
+                }
+            }
+        }
+        if moved {
+            self.upload();
+        }
+    }
+}
+
+impl Entity for Sprites {
+    fn update(&mut self, _ctx: &UpdateContext) {
+        self.apply_actions();
+    }
+
+    fn render(&self, _ctx: &RenderContext) {
+        if self.instances.is_empty() {
+            return;
+        }
+
+        self.program.set_used();
+        self.atlas.texture.bind_at(0);
+        self.atlas_uniform.set(&self.program, &0);
+
+        let gl = &self.gl;
+        StateCache::set_cull_face(gl, false);
+        self.vao.bind();
+        unsafe {
+            gl.DrawArraysInstanced(gl::TRIANGLE_STRIP, 0, 4, self.instances.len() as i32);
+        }
+        FrameStats::record_draw(gl::TRIANGLE_STRIP, 4, self.instances.len() as i32);
+        self.vao.unbind();
+        StateCache::set_cull_face(gl, true);
+    }
+
+    /// Union of sprite bounds in world space.
+    fn bounds(&self) -> Option<(AABB<f32>, na::Matrix4<f32>)> {
+        let mut bounds = None;
+        for (_, (sprite, _)) in self.sprites.iter() {
+            let (aabb, offset) = (sprite.aabb(), sprite.position.coords);
+            mesh::merge_aabb(&mut bounds, &AABB::new(aabb.mins() + offset, aabb.maxs() + offset));
+        }
+        bounds.map(|aabb| (aabb, na::Matrix4::identity()))
+    }
+
+    fn draw_key(&self) -> DrawKey {
+        DrawKey {
+            program: self.program.id(),
+            texture: self.atlas.texture.id(),
+        }
+    }
+}
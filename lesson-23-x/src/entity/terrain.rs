@@ -0,0 +1,418 @@
+use failure;
+use gl;
+use nalgebra as na;
+use ncollide3d::bounding_volume::aabb::AABB;
+use ncollide3d::query::Ray;
+use crate::entity::{DrawKey, Entity, RenderContext};
+use crate::mesh::Heightmap;
+use crate::render;
+use crate::render_gl::buffer::{Buffer, VertexArray};
+use crate::render_gl::{self, data, Program, Texture, Uniform};
+use crate::resources::Resources;
+use crate::selection::{DragSurface, Hit};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+#[derive(VertexAttribPointers, Copy, Clone, Debug)]
+#[repr(C, packed)]
+struct TerrainVertex {
+    #[location = "0"]
+    pos: data::f32_f32_f32,
+    #[location = "1"]
+    normal: data::f32_f32_f32,
+    #[location = "2"]
+    uv: data::f32_f32,
+}
+
+#[derive(Clone, Debug)]
+pub struct TerrainSettings {
+    /// Grayscale image, black is the lowest point.
+    pub heightmap: String,
+    /// RGBA image with the weights of the four layers.
+    pub splat_map: String,
+    /// Layer textures, blended by the red, green, blue and alpha channel of the splat map.
+    pub layers: [String; 4],
+    /// Width and depth of the whole terrain.
+    pub size: f32,
+    /// Height of white heightmap pixels.
+    pub height: f32,
+    /// Cells along each side of a chunk, chunks outside the view are not drawn.
+    pub chunk_cells: usize,
+    /// Layer texture repeats over the whole terrain.
+    pub layer_tiling: f32,
+}
+
+impl Default for TerrainSettings {
+    fn default() -> TerrainSettings {
+        TerrainSettings {
+            heightmap: "terrain/heightmap.png".into(),
+            splat_map: "terrain/splat.png".into(),
+            layers: [
+                "terrain/sand.png".into(),
+                "terrain/grass.png".into(),
+                "terrain/rock.png".into(),
+                "terrain/snow.png".into(),
+            ],
+            size: 64.0,
+            height: 8.0,
+            chunk_cells: 32,
+            layer_tiling: 16.0,
+        }
+    }
+}
+
+/// Part of the grid drawn with one call.
+struct Chunk {
+    aabb: AABB<f32>,
+    first_index: usize,
+    index_count: usize,
+}
+
+/// Heightmap with its placement, shared with selection for dragging objects onto it.
+struct TerrainShape {
+    heightmap: Heightmap,
+    scale: na::Vector3<f32>,
+    transform: na::Isometry3<f32>,
+}
+
+/// Ground mesh generated from a heightmap, centered at the origin in XY plane with Z up.
+pub struct Terrain {
+    shape: Rc<RefCell<TerrainShape>>,
+    aabb: AABB<f32>,
+    chunks: Vec<Chunk>,
+    program: Program,
+    program_model: Uniform<na::Matrix4<f32>>,
+    program_light_direction: Uniform<na::Vector3<f32>>,
+    program_shadow: render_gl::ShadowUniforms,
+    geometry_program: Program,
+    geometry_program_model: Uniform<na::Matrix4<f32>>,
+    splat_map: Texture,
+    layers: Vec<Texture>,
+    _vbo: Buffer,
+    _ebo: Buffer,
+    vao: VertexArray,
+}
+
+impl Terrain {
+    pub fn new(
+        res: &Resources,
+        gl: &gl::Gl,
+        settings: &TerrainSettings,
+    ) -> Result<Terrain, failure::Error> {
+        let heightmap = Heightmap::from_luma_image(&res.load_luma_image(&settings.heightmap[..])?);
+        let scale = na::Vector3::new(settings.size, settings.size, settings.height);
+
+        let cells = settings.chunk_cells.max(1);
+        let mut vertices = Vec::new();
+        let mut indices: Vec<u32> = Vec::new();
+        let mut chunks = Vec::new();
+        for row in (0..heightmap.rows() - 1).step_by(cells) {
+            for column in (0..heightmap.columns() - 1).step_by(cells) {
+                let mesh = heightmap.grid_mesh(column..column + cells, row..row + cells, &scale);
+                let aabb = match mesh.aabb() {
+                    Some(aabb) => aabb,
+                    None => continue,
+                };
+
+                let base_vertex = vertices.len() as u32;
+                chunks.push(Chunk {
+                    aabb,
+                    first_index: indices.len(),
+                    index_count: mesh.primitives.len() * 3,
+                });
+                indices.extend(mesh.triangle_indices().iter().map(|i| base_vertex + i));
+                vertices.extend(mesh.vertices.iter().map(|v| {
+                    let normal = v.normal.unwrap_or_else(na::Vector3::z);
+                    let uv = v.uv.unwrap_or_else(na::Vector2::zeros);
+                    TerrainVertex {
+                        pos: (v.pos.x, v.pos.y, v.pos.z).into(),
+                        normal: (normal.x, normal.y, normal.z).into(),
+                        uv: (uv.x, uv.y).into(),
+                    }
+                }));
+            }
+        }
+
+        let vbo = Buffer::new_array(gl);
+        vbo.bind();
+        vbo.static_draw_data(&vertices);
+        vbo.unbind();
+
+        let ebo = Buffer::new_element_array(gl);
+        ebo.bind();
+        ebo.static_draw_data(&indices);
+        ebo.unbind();
+
+        let vao = VertexArray::new(gl);
+        vao.bind();
+        vbo.bind();
+        ebo.bind();
+        TerrainVertex::vertex_attrib_pointers(gl);
+        vao.unbind();
+        vbo.unbind();
+        ebo.unbind();
+
+        let program = Program::from_res(gl, res, "shaders/terrain")?;
+        program.bind_uniform_block("Camera", render::CAMERA_UNIFORMS_BINDING);
+        program.bind_uniform_block("Fog", render::FOG_UNIFORMS_BINDING);
+        let geometry_program = Program::from_res_files(
+            gl,
+            res,
+            &["shaders/terrain.vert", "shaders/terrain_geometry.frag"],
+        )?;
+        geometry_program.bind_uniform_block("Camera", render::CAMERA_UNIFORMS_BINDING);
+
+        // samplers keep the same units in both programs, shadow map comes after the layers
+        for program in &[&program, &geometry_program] {
+            program.set_used();
+            program.uniform::<i32>("SplatMap").set(program, &0);
+            for index in 0..4 {
+                let name = format!("Layer{}", index);
+                program.uniform::<i32>(&name).set(program, &(index + 1));
+            }
+            program
+                .uniform::<f32>("LayerTiling")
+                .set(program, &settings.layer_tiling);
+        }
+
+        let splat_map = Texture::from_res_rgba(settings.splat_map.as_ref()).load(gl, res)?;
+        let layers = settings
+            .layers
+            .iter()
+            .map(|name| {
+                Texture::from_res_srgb(name.as_ref())
+                    .with_gen_mipmaps()
+                    .load(gl, res)
+            }).collect::<Result<Vec<_>, _>>()?;
+
+        let half = settings.size * 0.5;
+        Ok(Terrain {
+            shape: Rc::new(RefCell::new(TerrainShape {
+                heightmap,
+                scale,
+                transform: na::Isometry3::identity(),
+            })),
+            aabb: AABB::new(
+                na::Point3::new(-half, -half, 0.0),
+                na::Point3::new(half, half, settings.height),
+            ),
+            chunks,
+            program_model: program.uniform("Model"),
+            program_light_direction: program.uniform("LightDirection"),
+            program_shadow: render_gl::ShadowUniforms::new(&program),
+            program,
+            geometry_program_model: geometry_program.uniform("Model"),
+            geometry_program,
+            splat_map,
+            layers,
+            _vbo: vbo,
+            _ebo: ebo,
+            vao,
+        })
+    }
+
+    pub fn set_transform(&mut self, isometry: na::Isometry3<f32>) {
+        self.shape.borrow_mut().transform = isometry;
+    }
+
+    /// Point on the surface straight above or below a world position, `None` outside.
+    pub fn surface_point(&self, position: &na::Point3<f32>) -> Option<na::Point3<f32>> {
+        let shape = self.shape.borrow();
+        let local = shape.transform.inverse_transform_point(position);
+        let height = shape.local_height(local.x, local.y)?;
+        Some(shape.transform * na::Point3::new(local.x, local.y, height))
+    }
+
+    /// First point where the ray hits the terrain surface.
+    pub fn cast_ray(&self, ray: &Ray<f32>) -> Option<Hit> {
+        self.shape.borrow().cast_ray(ray)
+    }
+
+    /// Surface for `Selectables::set_drag_surface`, follows later transform changes.
+    pub fn drag_surface(&self) -> Rc<dyn DragSurface> {
+        self.shape.clone()
+    }
+
+    fn model_matrix(&self) -> na::Matrix4<f32> {
+        self.shape.borrow().transform.to_homogeneous()
+    }
+
+    fn bind_textures(&self) {
+        self.splat_map.bind_at(0);
+        for (index, layer) in self.layers.iter().enumerate() {
+            layer.bind_at(index as u32 + 1);
+        }
+    }
+
+    fn draw_chunk(&self, gl: &gl::Gl, chunk: &Chunk) {
+        unsafe {
+            gl.DrawElements(
+                gl::TRIANGLES,
+                chunk.index_count as i32,
+                gl::UNSIGNED_INT,
+                (chunk.first_index * ::std::mem::size_of::<u32>()) as *const gl::types::GLvoid,
+            );
+        }
+        render_gl::FrameStats::record_draw(gl::TRIANGLES, chunk.index_count as i32, 1);
+    }
+
+    fn draw_all(&self, gl: &gl::Gl) {
+        self.vao.bind();
+        for chunk in &self.chunks {
+            self.draw_chunk(gl, chunk);
+        }
+        self.vao.unbind();
+    }
+}
+
+impl Entity for Terrain {
+    fn render(&self, ctx: &RenderContext) {
+        let model_matrix = self.model_matrix();
+
+        self.program.set_used();
+        self.program_model.set(&self.program, &model_matrix);
+        self.program_light_direction
+            .set(&self.program, ctx.shadow_map.light_direction());
+        ctx.shadow_map.bind(&self.program, &self.program_shadow, 5);
+        self.bind_textures();
+
+        self.vao.bind();
+        for chunk in &self.chunks {
+            if ctx.frustum.intersects_aabb(&chunk.aabb, &model_matrix) {
+                self.draw_chunk(ctx.gl, chunk);
+            }
+        }
+        self.vao.unbind();
+    }
+
+    fn render_depth(&self, gl: &gl::Gl, pass: &render_gl::ShadowPass) {
+        pass.prepare_model(&self.model_matrix());
+        self.draw_all(gl);
+    }
+
+    /// Terrain is not selectable, but hides objects below it from picking.
+    fn render_id(&self, gl: &gl::Gl, pass: &render_gl::IdPass) {
+        pass.prepare_model(&self.model_matrix(), render_gl::NO_ID);
+        self.draw_all(gl);
+    }
+
+    fn render_geometry(&self, gl: &gl::Gl, _pass: &render_gl::GeometryPass) {
+        self.geometry_program.set_used();
+        self.geometry_program_model
+            .set(&self.geometry_program, &self.model_matrix());
+        self.bind_textures();
+        self.draw_all(gl);
+    }
+
+    fn bounds(&self) -> Option<(AABB<f32>, na::Matrix4<f32>)> {
+        Some((self.aabb.clone(), self.model_matrix()))
+    }
+
+    fn draw_key(&self) -> DrawKey {
+        DrawKey {
+            program: self.program.id(),
+            texture: self.splat_map.id(),
+        }
+    }
+}
+
+impl TerrainShape {
+    /// Height in model space at model space X and Y.
+    fn local_height(&self, x: f32, y: f32) -> Option<f32> {
+        let column = (x / self.scale.x + 0.5) * (self.heightmap.columns() - 1) as f32;
+        let row = (y / self.scale.y + 0.5) * (self.heightmap.rows() - 1) as f32;
+        let (max_column, max_row) = (
+            (self.heightmap.columns() - 1) as f32,
+            (self.heightmap.rows() - 1) as f32,
+        );
+        if column < 0.0 || row < 0.0 || column > max_column || row > max_row {
+            return None;
+        }
+        Some(self.heightmap.interpolated_height(column, row) * self.scale.z)
+    }
+
+    fn local_normal(&self, x: f32, y: f32) -> na::Vector3<f32> {
+        let column = (x / self.scale.x + 0.5) * (self.heightmap.columns() - 1) as f32;
+        let row = (y / self.scale.y + 0.5) * (self.heightmap.rows() - 1) as f32;
+        self.heightmap.normal(
+            column.round().max(0.0) as usize,
+            row.round().max(0.0) as usize,
+            &self.scale,
+        )
+    }
+
+    /// Steps along the ray in half cells inside the terrain bounds, then refines the first
+    /// step that ends below the surface.
+    fn cast_ray(&self, ray: &Ray<f32>) -> Option<Hit> {
+        let local_ray = ray.inverse_transform_by(&self.transform);
+        let half = na::Vector3::new(self.scale.x * 0.5, self.scale.y * 0.5, 0.0);
+        let (mins, maxs) = (-half, half + na::Vector3::z() * self.scale.z);
+        let (enter, exit) = clip_ray(&local_ray, &mins, &maxs)?;
+
+        let below = |t: f32| {
+            let p = local_ray.origin + local_ray.dir * t;
+            self.local_height(p.x, p.y).map(|h| p.z <= h)
+        };
+        let cell = self.scale.x / (self.heightmap.columns() - 1) as f32;
+        let step = cell * 0.5 / local_ray.dir.norm().max(::std::f32::EPSILON);
+
+        let mut previous = enter;
+        let mut t = enter;
+        while t <= exit {
+            if below(t) == Some(true) {
+                let (mut above_t, mut below_t) = (previous, t);
+                for _ in 0..16 {
+                    let middle = (above_t + below_t) * 0.5;
+                    if below(middle) == Some(true) {
+                        below_t = middle;
+                    } else {
+                        above_t = middle;
+                    }
+                }
+                let point = local_ray.origin + local_ray.dir * below_t;
+                let normal = self.local_normal(point.x, point.y);
+                return Some(Hit {
+                    point: self.transform * point,
+                    normal: self.transform * normal,
+                    triangle: None,
+                });
+            }
+            previous = t;
+            t += step;
+        }
+        None
+    }
+}
+
+impl DragSurface for RefCell<TerrainShape> {
+    fn cast_ray(&self, ray: &Ray<f32>) -> Option<Hit> {
+        self.borrow().cast_ray(ray)
+    }
+}
+
+/// Ray parameters where it enters and leaves the box, `None` if it misses.
+fn clip_ray(
+    ray: &Ray<f32>,
+    mins: &na::Vector3<f32>,
+    maxs: &na::Vector3<f32>,
+) -> Option<(f32, f32)> {
+    let mut enter = 0.0f32;
+    let mut exit = ::std::f32::MAX;
+    for axis in 0..3 {
+        let (origin, dir) = (ray.origin.coords[axis], ray.dir[axis]);
+        if dir.abs() < ::std::f32::EPSILON {
+            if origin < mins[axis] || origin > maxs[axis] {
+                return None;
+            }
+            continue;
+        }
+        let (a, b) = ((mins[axis] - origin) / dir, (maxs[axis] - origin) / dir);
+        enter = enter.max(a.min(b));
+        exit = exit.min(a.max(b));
+    }
+    if enter <= exit {
+        Some((enter, exit))
+    } else {
+        None
+    }
+}
@@ -0,0 +1,196 @@
+use failure;
+use gl;
+use nalgebra as na;
+use ncollide3d::bounding_volume::aabb::AABB;
+use crate::entity::{DrawKey, Entity, RenderContext, UpdateContext};
+use crate::render;
+use crate::render_gl::buffer::{Buffer, VertexArray};
+use crate::render_gl::{data, FrameStats, PlanarReflection, Program, Texture, Uniform};
+use crate::resources::Resources;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+#[derive(VertexAttribPointers, Copy, Clone, Debug)]
+#[repr(C, packed)]
+struct WaterVertex {
+    #[location = "0"]
+    pos: data::f32_f32_f32,
+}
+
+/// Flat water square in XY plane, showing the scene reflected above and refracted below it.
+///
+/// Reflection and refraction are drawn by the owner of the scene with the shared
+/// `PlanarReflection` before the water itself, excluding the water entity. Water is drawn by
+/// forward rendering only.
+pub struct Water {
+    center: na::Point3<f32>,
+    size: f32,
+    wave_offset: f32,
+    /// Distortion scroll per second.
+    pub wave_speed: f32,
+    pub wave_strength: f32,
+    pub color: na::Vector3<f32>,
+    reflection: Rc<RefCell<PlanarReflection>>,
+    program: Program,
+    program_model: Uniform<na::Matrix4<f32>>,
+    program_wave_offset: Uniform<f32>,
+    program_wave_strength: Uniform<f32>,
+    program_light_direction: Uniform<na::Vector3<f32>>,
+    program_color: Uniform<na::Vector3<f32>>,
+    dudv_map: Texture,
+    normal_map: Texture,
+    _vbo: Buffer,
+    vao: VertexArray,
+}
+
+impl Water {
+    /// Reflection and refraction targets are `target_width` by `target_height` pixels.
+    pub fn new(
+        res: &Resources,
+        gl: &gl::Gl,
+        center: na::Point3<f32>,
+        size: f32,
+        target_width: i32,
+        target_height: i32,
+    ) -> Result<Water, failure::Error> {
+        let program = Program::from_res(gl, res, "shaders/water")?;
+        program.bind_uniform_block("Camera", render::CAMERA_UNIFORMS_BINDING);
+        program.bind_uniform_block("Fog", render::FOG_UNIFORMS_BINDING);
+        program.set_used();
+        program.uniform::<i32>("Reflection").set(&program, &0);
+        program.uniform::<i32>("Refraction").set(&program, &1);
+        program.uniform::<i32>("DuDvMap").set(&program, &2);
+        program.uniform::<i32>("NormalMap").set(&program, &3);
+        program
+            .uniform::<f32>("Tiling")
+            .set(&program, &(size / 8.0).max(1.0));
+
+        let dudv_map = Texture::from_res_rgb("textures/water_dudv.png".as_ref())
+            .with_gen_mipmaps()
+            .load(gl, res)?;
+        let normal_map = Texture::from_res_rgb("textures/water_normal.png".as_ref())
+            .with_gen_mipmaps()
+            .load(gl, res)?;
+
+        let half = size * 0.5;
+        let vertices = [
+            (-half, -half),
+            (half, -half),
+            (half, half),
+            (-half, -half),
+            (half, half),
+            (-half, half),
+        ]
+            .iter()
+            .map(|&(x, y)| WaterVertex {
+                pos: (x, y, 0.0).into(),
+            }).collect::<Vec<_>>();
+
+        let vbo = Buffer::new_array(gl);
+        vbo.bind();
+        vbo.static_draw_data(&vertices);
+        vbo.unbind();
+
+        let vao = VertexArray::new(gl);
+        vao.bind();
+        vbo.bind();
+        WaterVertex::vertex_attrib_pointers(gl);
+        vao.unbind();
+        vbo.unbind();
+
+        let reflection = PlanarReflection::new(
+            gl,
+            target_width,
+            target_height,
+            center,
+            na::Vector3::z(),
+        )?;
+
+        Ok(Water {
+            center,
+            size,
+            wave_offset: 0.0,
+            wave_speed: 0.03,
+            wave_strength: 0.02,
+            color: na::Vector3::new(0.1, 0.3, 0.4),
+            reflection: Rc::new(RefCell::new(reflection)),
+            program_model: program.uniform("Model"),
+            program_wave_offset: program.uniform("WaveOffset"),
+            program_wave_strength: program.uniform("WaveStrength"),
+            program_light_direction: program.uniform("LightDirection"),
+            program_color: program.uniform("WaterColor"),
+            program,
+            dudv_map,
+            normal_map,
+            _vbo: vbo,
+            vao,
+        })
+    }
+
+    /// Targets to draw the scene into before the water, i.e. once per frame.
+    pub fn reflection(&self) -> Rc<RefCell<PlanarReflection>> {
+        self.reflection.clone()
+    }
+
+    pub fn set_center(&mut self, center: na::Point3<f32>) {
+        self.center = center;
+        self.reflection
+            .borrow_mut()
+            .set_plane(center, na::Vector3::z());
+    }
+
+    fn model_matrix(&self) -> na::Matrix4<f32> {
+        na::Matrix4::new_translation(&self.center.coords)
+    }
+}
+
+impl Entity for Water {
+    fn update(&mut self, ctx: &UpdateContext) {
+        self.wave_offset = (self.wave_offset + self.wave_speed * ctx.delta).fract();
+    }
+
+    fn render(&self, ctx: &RenderContext) {
+        let reflection = self.reflection.borrow();
+
+        self.program.set_used();
+        self.program_model
+            .set(&self.program, &self.model_matrix());
+        self.program_wave_offset
+            .set(&self.program, &self.wave_offset);
+        self.program_wave_strength
+            .set(&self.program, &self.wave_strength);
+        self.program_light_direction
+            .set(&self.program, ctx.shadow_map.light_direction());
+        self.program_color.set(&self.program, &self.color);
+
+        reflection.reflection_texture().bind_at(0);
+        reflection.refraction_texture().bind_at(1);
+        self.dudv_map.bind_at(2);
+        self.normal_map.bind_at(3);
+
+        self.vao.bind();
+        unsafe {
+            ctx.gl.DrawArrays(gl::TRIANGLES, 0, 6);
+        }
+        FrameStats::record_draw(gl::TRIANGLES, 6, 1);
+        self.vao.unbind();
+    }
+
+    fn bounds(&self) -> Option<(AABB<f32>, na::Matrix4<f32>)> {
+        let half = self.size * 0.5;
+        Some((
+            AABB::new(
+                na::Point3::new(-half, -half, 0.0),
+                na::Point3::new(half, half, 0.0),
+            ),
+            self.model_matrix(),
+        ))
+    }
+
+    fn draw_key(&self) -> DrawKey {
+        DrawKey {
+            program: self.program.id(),
+            texture: self.dudv_map.id(),
+        }
+    }
+}
@@ -0,0 +1,176 @@
+use failure;
+use gl;
+use nalgebra as na;
+use crate::entity::{DrawKey, Entity, RenderContext};
+use crate::lights;
+use crate::render;
+use crate::render_gl::buffer::{Buffer, VertexArray};
+use crate::render_gl::{self, data, DeferredGeometry};
+use crate::resources::Resources;
+
+#[derive(VertexAttribPointers, Copy, Clone, Debug)]
+#[repr(C, packed)]
+struct FloorVertex {
+    #[location = "0"]
+    pos: data::f32_f32_f32,
+}
+
+/// Flat square receiving shadows, centered at the origin in XY plane.
+pub struct Floor {
+    transform: na::Isometry3<f32>,
+    program: render_gl::Program,
+    program_model_location: Option<i32>,
+    shadow_uniforms: render_gl::ShadowUniforms,
+    program_color_location: Option<i32>,
+    geometry_program: render_gl::Program,
+    geometry_program_model_location: Option<i32>,
+    geometry_program_color_location: Option<i32>,
+    color: na::Vector3<f32>,
+    _vbo: Buffer,
+    vao: VertexArray,
+}
+
+impl Floor {
+    pub fn new(
+        res: &Resources,
+        gl: &gl::Gl,
+        size: f32,
+        color: na::Vector3<f32>,
+    ) -> Result<Floor, failure::Error> {
+        // point and spot lights only reach the floor with clusters
+        let clustered = lights::LightClusters::supported(gl);
+        let defines: &[(&str, &str)] = if clustered { &[("CLUSTERED", "1")] } else { &[] };
+        let program =
+            render_gl::Program::from_res_with_defines(gl, res, "shaders/floor", defines)?;
+        program.bind_uniform_block("Camera", render::CAMERA_UNIFORMS_BINDING);
+        program.bind_uniform_block("Fog", render::FOG_UNIFORMS_BINDING);
+        if clustered {
+            program.bind_uniform_block("Clusters", lights::CLUSTER_UNIFORMS_BINDING);
+            program.bind_shader_storage_block("ClusterLights", lights::CLUSTER_LIGHTS_BINDING);
+            program.bind_shader_storage_block("ClusterGrid", lights::CLUSTER_GRID_BINDING);
+            program.bind_shader_storage_block("ClusterIndices", lights::CLUSTER_INDICES_BINDING);
+        }
+        let geometry_program = render_gl::Program::from_res(gl, res, "shaders/floor_geometry")?;
+        geometry_program.bind_uniform_block("Camera", render::CAMERA_UNIFORMS_BINDING);
+
+        let half = size * 0.5;
+        let vertices = [
+            (-half, -half),
+            (half, -half),
+            (half, half),
+            (-half, -half),
+            (half, half),
+            (-half, half),
+        ]
+            .iter()
+            .map(|&(x, y)| FloorVertex {
+                pos: (x, y, 0.0).into(),
+            }).collect::<Vec<_>>();
+
+        let vbo = Buffer::new_array(gl);
+        vbo.bind();
+        vbo.static_draw_data(&vertices);
+        vbo.unbind();
+
+        let vao = VertexArray::new(gl);
+        vao.bind();
+        vbo.bind();
+        FloorVertex::vertex_attrib_pointers(gl);
+        vao.unbind();
+        vbo.unbind();
+
+        Ok(Floor {
+            transform: na::Isometry3::identity(),
+            program_model_location: program.get_uniform_location("Model"),
+            shadow_uniforms: render_gl::ShadowUniforms::new(&program),
+            program_color_location: program.get_uniform_location("Color"),
+            program,
+            geometry_program_model_location: geometry_program.get_uniform_location("Model"),
+            geometry_program_color_location: geometry_program.get_uniform_location("Color"),
+            geometry_program,
+            color,
+            _vbo: vbo,
+            vao,
+        })
+    }
+
+    pub fn set_transform(&mut self, isometry: na::Isometry3<f32>) {
+        self.transform = isometry;
+    }
+
+    pub fn render(&self, gl: &gl::Gl, shadow_map: &render_gl::ShadowMap) {
+        self.program.set_used();
+
+        if let Some(loc) = self.program_model_location {
+            self.program
+                .set_uniform_matrix_4fv(loc, &self.transform.to_homogeneous());
+        }
+        shadow_map.bind(&self.program, &self.shadow_uniforms, 0);
+        if let Some(loc) = self.program_color_location {
+            self.program.set_uniform_3f(loc, &self.color);
+        }
+
+        self.draw(gl);
+    }
+
+    pub fn render_depth(&self, gl: &gl::Gl, pass: &render_gl::ShadowPass) {
+        pass.prepare_model(&self.transform.to_homogeneous());
+        self.draw(gl);
+    }
+
+    /// Floor is not selectable, but hides objects below it from picking.
+    pub fn render_id(&self, gl: &gl::Gl, pass: &render_gl::IdPass) {
+        pass.prepare_model(&self.transform.to_homogeneous(), render_gl::NO_ID);
+        self.draw(gl);
+    }
+
+    fn draw(&self, gl: &gl::Gl) {
+        self.vao.bind();
+        unsafe {
+            gl.DrawArrays(gl::TRIANGLES, 0, 6);
+        }
+        render_gl::FrameStats::record_draw(gl::TRIANGLES, 6, 1);
+        self.vao.unbind();
+    }
+}
+
+impl Entity for Floor {
+    fn render(&self, ctx: &RenderContext) {
+        Floor::render(self, ctx.gl, ctx.shadow_map);
+    }
+
+    fn render_depth(&self, gl: &gl::Gl, pass: &render_gl::ShadowPass) {
+        Floor::render_depth(self, gl, pass);
+    }
+
+    fn render_id(&self, gl: &gl::Gl, pass: &render_gl::IdPass) {
+        Floor::render_id(self, gl, pass);
+    }
+
+    fn render_geometry(&self, gl: &gl::Gl, pass: &render_gl::GeometryPass) {
+        DeferredGeometry::render_geometry(self, gl, pass);
+    }
+
+    fn draw_key(&self) -> DrawKey {
+        DrawKey {
+            program: self.program.id(),
+            texture: 0,
+        }
+    }
+}
+
+impl DeferredGeometry for Floor {
+    fn render_geometry(&self, gl: &gl::Gl, _pass: &render_gl::GeometryPass) {
+        self.geometry_program.set_used();
+
+        if let Some(loc) = self.geometry_program_model_location {
+            self.geometry_program
+                .set_uniform_matrix_4fv(loc, &self.transform.to_homogeneous());
+        }
+        if let Some(loc) = self.geometry_program_color_location {
+            self.geometry_program.set_uniform_3f(loc, &self.color);
+        }
+
+        self.draw(gl);
+    }
+}
@@ -0,0 +1,173 @@
+use crate::platform::{ControllerAxis, ControllerButton, Key, MouseButton};
+use crate::resources::{self, Resources};
+use serde_json;
+use std::collections::BTreeMap;
+
+#[derive(Debug, Fail)]
+pub enum Error {
+    #[fail(display = "Failed to read input bindings")]
+    Resource(#[cause] resources::Error),
+    #[fail(display = "Failed to parse input bindings")]
+    Json(#[cause] serde_json::Error),
+}
+
+impl From<resources::Error> for Error {
+    fn from(other: resources::Error) -> Self {
+        Error::Resource(other)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(other: serde_json::Error) -> Self {
+        Error::Json(other)
+    }
+}
+
+/// Key or button that holds an action down.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Binding {
+    Key(Key),
+    Mouse(MouseButton),
+    Controller(ControllerButton),
+}
+
+/// Source of an axis value from -1 to 1.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum AxisBinding {
+    /// -1 while `negative` is held and 1 while `positive` is held.
+    Buttons { negative: Binding, positive: Binding },
+    /// Controller stick or trigger multiplied by `scale`, negative scale inverts it.
+    Controller { axis: ControllerAxis, scale: f32 },
+}
+
+/// Named actions and axes, loaded from JSON.
+///
+/// Actions and axes missing from a file keep their default bindings, so that a file only
+/// needs to list the changed ones.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Bindings {
+    pub actions: BTreeMap<String, Vec<Binding>>,
+    pub axes: BTreeMap<String, Vec<AxisBinding>>,
+}
+
+impl Default for Bindings {
+    fn default() -> Self {
+        use self::Binding::{Controller as Pad, Key as K};
+
+        let actions = vec![
+            ("move_faster", vec![K(Key::LShift), K(Key::RShift), Pad(ControllerButton::LeftStick)]),
+            ("switch_camera", vec![K(Key::Tab), Pad(ControllerButton::Back)]),
+            ("select_additive", vec![K(Key::LCtrl), K(Key::RCtrl)]),
+            ("edit_modifier", vec![K(Key::LCtrl), K(Key::RCtrl)]),
+            ("undo", vec![K(Key::Z)]),
+            ("redo", vec![K(Key::Y)]),
+            ("cancel_drag", vec![K(Key::Escape), Pad(ControllerButton::B)]),
+            ("gizmo_translate", vec![K(Key::Num1)]),
+            ("gizmo_rotate", vec![K(Key::Num2)]),
+            ("gizmo_scale", vec![K(Key::Num3)]),
+            ("toggle_drag_mode", vec![K(Key::Num4)]),
+            ("toggle_drag_grid", vec![K(Key::Num5)]),
+            ("toggle_side_camera", vec![K(Key::C)]),
+            ("toggle_debug", vec![K(Key::I)]),
+            ("toggle_profilers", vec![K(Key::P)]),
+            ("toggle_stats", vec![K(Key::H), Pad(ControllerButton::Start)]),
+            ("toggle_pixel_inspector", vec![K(Key::Q)]),
+            ("screenshot", vec![K(Key::F12)]),
+            ("save_scene", vec![K(Key::F5)]),
+            ("load_scene", vec![K(Key::F6)]),
+            ("toggle_recording", vec![K(Key::F9)]),
+            ("add_camera_keyframe", vec![K(Key::F7)]),
+            ("play_camera_path", vec![K(Key::F8)]),
+            ("clear_camera_path", vec![K(Key::F10)]),
+            ("camera_path_longer", vec![K(Key::U)]),
+            ("camera_path_shorter", vec![K(Key::J)]),
+            ("cycle_msaa", vec![K(Key::M)]),
+            ("cycle_material_view", vec![K(Key::N)]),
+            ("cycle_render_mode", vec![K(Key::V)]),
+            ("cycle_texture_filter", vec![K(Key::F)]),
+            ("particle_rate_down", vec![K(Key::LeftBracket)]),
+            ("particle_rate_up", vec![K(Key::RightBracket)]),
+            ("particle_spread_down", vec![K(Key::Semicolon)]),
+            ("particle_spread_up", vec![K(Key::Apostrophe)]),
+            ("toggle_occlusion_culling", vec![K(Key::O)]),
+            ("toggle_bloom", vec![K(Key::B)]),
+            ("cycle_tone_mapper", vec![K(Key::T)]),
+            ("toggle_reflections", vec![K(Key::R)]),
+            ("toggle_parallax", vec![K(Key::X)]),
+            ("toggle_gpu_picking", vec![K(Key::G)]),
+            ("toggle_selection_bvh", vec![K(Key::L)]),
+            ("throw_dice", vec![K(Key::Space), Pad(ControllerButton::A)]),
+            ("stamp_decal", vec![K(Key::K), Pad(ControllerButton::X)]),
+            ("delete_selected", vec![K(Key::Delete)]),
+            ("exposure_up", vec![K(Key::Equals), Pad(ControllerButton::DPadUp)]),
+            ("exposure_down", vec![K(Key::Minus), Pad(ControllerButton::DPadDown)]),
+        ];
+
+        let buttons = |negative, positive| AxisBinding::Buttons { negative, positive };
+        let stick = |axis, scale| AxisBinding::Controller { axis, scale };
+        let axes = vec![
+            (
+                "move_right",
+                vec![buttons(K(Key::A), K(Key::D)), stick(ControllerAxis::LeftX, 1.0)],
+            ),
+            (
+                "move_forward",
+                vec![buttons(K(Key::S), K(Key::W)), stick(ControllerAxis::LeftY, -1.0)],
+            ),
+            (
+                "move_up",
+                vec![
+                    buttons(K(Key::LCtrl), K(Key::Space)),
+                    buttons(
+                        Pad(ControllerButton::LeftShoulder),
+                        Pad(ControllerButton::RightShoulder),
+                    ),
+                ],
+            ),
+            ("look_right", vec![stick(ControllerAxis::RightX, 1.0)]),
+            ("look_up", vec![stick(ControllerAxis::RightY, -1.0)]),
+            (
+                "drag_depth",
+                vec![
+                    stick(ControllerAxis::TriggerRight, 1.0),
+                    stick(ControllerAxis::TriggerLeft, -1.0),
+                ],
+            ),
+        ];
+
+        Bindings {
+            actions: actions
+                .into_iter()
+                .map(|(name, bindings)| (name.to_string(), bindings))
+                .collect(),
+            axes: axes
+                .into_iter()
+                .map(|(name, bindings)| (name.to_string(), bindings))
+                .collect(),
+        }
+    }
+}
+
+impl Bindings {
+    /// Bindings from the file, on top of the defaults.
+    pub fn load(res: &Resources, name: &str) -> Result<Bindings, Error> {
+        let loaded: Bindings = serde_json::from_slice(&res.load_bytes(name)?)?;
+        let mut bindings = Bindings::default();
+        bindings.actions.extend(loaded.actions);
+        bindings.axes.extend(loaded.axes);
+        Ok(bindings)
+    }
+
+    /// Bindings from the file, or defaults if there is none or it can not be read.
+    pub fn load_or_default(res: &Resources, name: &str) -> Bindings {
+        match Bindings::load(res, name) {
+            Ok(bindings) => bindings,
+            Err(Error::Resource(ref e)) if e.is_not_found() => Bindings::default(),
+            Err(e) => {
+                println!("Failed to load {}, using default bindings: {}", name, e);
+                Bindings::default()
+            }
+        }
+    }
+}
@@ -0,0 +1,128 @@
+use crate::input::{AxisBinding, Binding, Bindings};
+use crate::platform::{ControllerAxis, Event};
+use std::collections::{HashMap, HashSet};
+
+/// Held keys and buttons and controller axes, queried by action and axis names.
+///
+/// `handle_event` follows the events as they arrive, `begin_frame` forgets the presses and
+/// releases of the previous frame. Unknown names read as released and 0.
+pub struct InputMap {
+    bindings: Bindings,
+    held: HashSet<Binding>,
+    pressed: HashSet<Binding>,
+    released: HashSet<Binding>,
+    controller_axes: HashMap<ControllerAxis, f32>,
+}
+
+impl InputMap {
+    pub fn new(bindings: Bindings) -> InputMap {
+        InputMap {
+            bindings,
+            held: HashSet::new(),
+            pressed: HashSet::new(),
+            released: HashSet::new(),
+            controller_axes: HashMap::new(),
+        }
+    }
+
+    pub fn bindings(&self) -> &Bindings {
+        &self.bindings
+    }
+
+    /// Call before handling the events of a frame.
+    pub fn begin_frame(&mut self) {
+        self.pressed.clear();
+        self.released.clear();
+    }
+
+    pub fn handle_event(&mut self, event: &Event) {
+        match *event {
+            Event::KeyDown { key, repeat: false } => self.press(Binding::Key(key)),
+            Event::KeyUp { key } => self.release(Binding::Key(key)),
+            Event::MouseButtonDown { button, .. } => self.press(Binding::Mouse(button)),
+            Event::MouseButtonUp { button, .. } => self.release(Binding::Mouse(button)),
+            Event::ControllerButtonDown { button } => self.press(Binding::Controller(button)),
+            Event::ControllerButtonUp { button } => self.release(Binding::Controller(button)),
+            Event::ControllerAxisMotion { axis, value } => {
+                self.controller_axes.insert(axis, value);
+            }
+            Event::ControllerRemoved => {
+                let released = self
+                    .held
+                    .iter()
+                    .filter(|binding| match binding {
+                        Binding::Controller(_) => true,
+                        _ => false,
+                    })
+                    .cloned()
+                    .collect::<Vec<_>>();
+                for binding in released {
+                    self.release(binding);
+                }
+                self.controller_axes.clear();
+            }
+            _ => (),
+        }
+    }
+
+    /// True while a binding of the action is held.
+    pub fn pressed(&self, action: &str) -> bool {
+        self.any_binding(action, &self.held)
+    }
+
+    /// True if a binding of the action went down this frame.
+    pub fn just_pressed(&self, action: &str) -> bool {
+        self.any_binding(action, &self.pressed)
+    }
+
+    /// True if a binding of the action went up this frame.
+    pub fn just_released(&self, action: &str) -> bool {
+        self.any_binding(action, &self.released)
+    }
+
+    /// Sum of the axis bindings, clamped to -1 to 1.
+    pub fn axis(&self, name: &str) -> f32 {
+        self.bindings
+            .axes
+            .get(name)
+            .map(|bindings| {
+                bindings
+                    .iter()
+                    .map(|binding| self.axis_value(binding))
+                    .sum::<f32>()
+            }).unwrap_or(0.0)
+            .max(-1.0).min(1.0)
+    }
+
+    fn press(&mut self, binding: Binding) {
+        if self.held.insert(binding) {
+            self.pressed.insert(binding);
+        }
+    }
+
+    fn release(&mut self, binding: Binding) {
+        if self.held.remove(&binding) {
+            self.released.insert(binding);
+        }
+    }
+
+    fn any_binding(&self, action: &str, set: &HashSet<Binding>) -> bool {
+        self.bindings
+            .actions
+            .get(action)
+            .map(|bindings| bindings.iter().any(|binding| set.contains(binding)))
+            .unwrap_or(false)
+    }
+
+    fn axis_value(&self, binding: &AxisBinding) -> f32 {
+        match *binding {
+            AxisBinding::Buttons { negative, positive } => {
+                let value = |binding| if self.held.contains(&binding) { 1.0 } else { 0.0 };
+                value(positive) - value(negative)
+            }
+            AxisBinding::Controller { axis, scale } => {
+                self.controller_axes.get(&axis).cloned().unwrap_or(0.0) * scale
+            }
+        }
+    }
+}
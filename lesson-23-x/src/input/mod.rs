@@ -0,0 +1,8 @@
+mod bindings;
+pub use self::bindings::{AxisBinding, Binding, Bindings, Error};
+
+mod input_map;
+pub use self::input_map::InputMap;
+
+pub mod replay;
+pub use self::replay::{InputRecorder, InputReplay};
@@ -0,0 +1,118 @@
+use crate::platform::Event;
+use crate::resources::{self, Resources};
+use serde_json;
+use std::time::Duration;
+
+#[derive(Debug, Fail)]
+pub enum Error {
+    #[fail(display = "Failed to read or write input recording")]
+    Resource(#[cause] resources::Error),
+    #[fail(display = "Failed to parse input recording")]
+    Json(#[cause] serde_json::Error),
+}
+
+impl From<resources::Error> for Error {
+    fn from(other: resources::Error) -> Self {
+        Error::Resource(other)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(other: serde_json::Error) -> Self {
+        Error::Json(other)
+    }
+}
+
+/// Events received during one frame and the time the frame took.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RecordedFrame {
+    /// Nanoseconds, exact so that replayed frames run the same fixed updates.
+    pub frame_time: u64,
+    pub events: Vec<Event>,
+}
+
+/// Events and frame times of every frame, saved as JSON for `InputReplay`.
+///
+/// `Quit` is left out, so that closing the window does not end the replay early.
+#[derive(Default)]
+pub struct InputRecorder {
+    frames: Vec<RecordedFrame>,
+}
+
+impl InputRecorder {
+    pub fn new() -> InputRecorder {
+        InputRecorder::default()
+    }
+
+    /// Start a frame with the events polled for it.
+    pub fn record_events(&mut self, events: &[Event]) {
+        self.frames.push(RecordedFrame {
+            frame_time: 0,
+            events: events
+                .iter()
+                .filter(|event| **event != Event::Quit)
+                .cloned()
+                .collect(),
+        });
+    }
+
+    /// Time of the frame started by the last `record_events`.
+    pub fn record_frame_time(&mut self, frame_time: Duration) {
+        if let Some(frame) = self.frames.last_mut() {
+            frame.frame_time = frame_time.as_secs() * 1_000_000_000
+                + u64::from(frame_time.subsec_nanos());
+        }
+    }
+
+    pub fn frame_count(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn save(&self, res: &Resources, name: &str) -> Result<(), Error> {
+        res.write_bytes(name, &serde_json::to_vec(&self.frames)?)?;
+        Ok(())
+    }
+}
+
+/// Recorded frames played back one per frame instead of the window events.
+///
+/// Feed the frame times to `GameLoop::advance`, so that updates, and with them the rendered
+/// frames, match the recording. The window should have the size it had when recording
+/// started, recorded resizes are replayed as they are.
+pub struct InputReplay {
+    frames: Vec<RecordedFrame>,
+    next: usize,
+}
+
+impl InputReplay {
+    pub fn load(res: &Resources, name: &str) -> Result<InputReplay, Error> {
+        Ok(InputReplay {
+            frames: serde_json::from_slice(&res.load_bytes(name)?)?,
+            next: 0,
+        })
+    }
+
+    /// Replace the events with the next recorded frame and return its time, `None` once
+    /// all frames are played.
+    ///
+    /// Live `Quit` is kept, so that the window can still be closed.
+    pub fn next_frame(&mut self, events: &mut Vec<Event>) -> Option<Duration> {
+        let frame = self.frames.get(self.next)?;
+        self.next += 1;
+        let quit = events.contains(&Event::Quit);
+        events.clear();
+        events.extend(frame.events.iter().cloned());
+        if quit {
+            events.push(Event::Quit);
+        }
+        Some(Duration::from_nanos(frame.frame_time))
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.next >= self.frames.len()
+    }
+
+    pub fn frame_count(&self) -> usize {
+        self.frames.len()
+    }
+}
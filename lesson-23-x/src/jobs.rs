@@ -0,0 +1,258 @@
+use num_cpus;
+use once_cell::sync::OnceCell;
+use std::collections::VecDeque;
+use std::marker::PhantomData;
+use std::mem;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+
+/// Closure called once through a box, as `Box<FnOnce>` can not be called directly.
+trait CallBox {
+    fn call_box(self: Box<Self>);
+}
+
+impl<F: FnOnce()> CallBox for F {
+    fn call_box(self: Box<F>) {
+        (*self)()
+    }
+}
+
+type Job = Box<dyn CallBox + Send>;
+
+/// Worker threads taking jobs from a shared queue.
+struct Pool {
+    queue: Mutex<VecDeque<Job>>,
+    available: Condvar,
+}
+
+static POOL: OnceCell<Pool> = OnceCell::INIT;
+
+impl Pool {
+    /// Shared pool, workers are started on first use.
+    fn get() -> &'static Pool {
+        let mut created = false;
+        let pool = POOL.get_or_init(|| {
+            created = true;
+            Pool {
+                queue: Mutex::new(VecDeque::new()),
+                available: Condvar::new(),
+            }
+        });
+        if created {
+            for index in 0..num_cpus::get() {
+                thread::Builder::new()
+                    .name(format!("job worker {}", index))
+                    .spawn(move || pool.work())
+                    .expect("failed to spawn job worker thread");
+            }
+        }
+        pool
+    }
+
+    fn push(&self, job: Job) {
+        self.queue.lock().expect("job queue poisoned").push_back(job);
+        self.available.notify_one();
+    }
+
+    fn work(&self) {
+        loop {
+            let job = {
+                let mut queue = self.queue.lock().expect("job queue poisoned");
+                loop {
+                    if let Some(job) = queue.pop_front() {
+                        break job;
+                    }
+                    queue = self.available.wait(queue).expect("job queue poisoned");
+                }
+            };
+            job.call_box();
+        }
+    }
+}
+
+/// Run the job on the pool without waiting for it, i.e. a resource load.
+///
+/// A panic ends only the job.
+pub fn spawn<F>(job: F)
+where
+    F: FnOnce() + Send + 'static,
+{
+    Pool::get().push(Box::new(move || {
+        let _ = panic::catch_unwind(AssertUnwindSafe(job));
+    }));
+}
+
+struct ScopeState {
+    /// Jobs of this scope, run by whichever of the pool and `wait` gets to them first.
+    queue: Mutex<VecDeque<Job>>,
+    pending: Mutex<usize>,
+    finished: Condvar,
+    panicked: AtomicBool,
+}
+
+/// Jobs spawned here may borrow from the caller of `scope`, which waits for all of them.
+pub struct Scope<'env> {
+    state: Arc<ScopeState>,
+    /// Invariant, so that jobs can not borrow anything shorter lived than `'env`.
+    _env: PhantomData<&'env mut &'env ()>,
+}
+
+impl<'env> Scope<'env> {
+    pub fn spawn<F>(&self, job: F)
+    where
+        F: FnOnce() + Send + 'env,
+    {
+        let state = self.state.clone();
+        *state.pending.lock().expect("job scope poisoned") += 1;
+
+        let job: Box<dyn CallBox + Send + 'env> = Box::new(move || {
+            if panic::catch_unwind(AssertUnwindSafe(job)).is_err() {
+                state.panicked.store(true, Ordering::SeqCst);
+            }
+            let mut pending = state.pending.lock().expect("job scope poisoned");
+            *pending -= 1;
+            if *pending == 0 {
+                state.finished.notify_all();
+            }
+        });
+        // `scope` does not return before the job has run, so the borrows outlive it
+        let job: Job = unsafe { mem::transmute::<Box<dyn CallBox + Send + 'env>, Job>(job) };
+        self.state.queue.lock().expect("job scope poisoned").push_back(job);
+
+        // the pool runs the next job of this scope, if `wait` has not taken it already
+        let state = self.state.clone();
+        Pool::get().push(Box::new(move || {
+            if let Some(job) = state.try_pop() {
+                job.call_box();
+            }
+        }));
+    }
+
+    /// Block until all jobs have run, running queued jobs of this scope meanwhile, so that
+    /// scopes nested in jobs can not leave every worker waiting. Jobs of other scopes and
+    /// detached `spawn` jobs are left to the pool, as they may take any amount of time.
+    fn wait(&self) {
+        while let Some(job) = self.state.try_pop() {
+            job.call_box();
+        }
+        // the remaining jobs are running on workers, which wake this once the last is done
+        let mut pending = self.state.pending.lock().expect("job scope poisoned");
+        while *pending != 0 {
+            pending = self.state.finished.wait(pending).expect("job scope poisoned");
+        }
+    }
+}
+
+impl ScopeState {
+    fn try_pop(&self) -> Option<Job> {
+        self.queue.lock().expect("job scope poisoned").pop_front()
+    }
+}
+
+/// Run `f` with a scope for spawning jobs that borrow local data, and wait for the jobs.
+///
+/// Safe to call from anywhere, including entity constructors and other jobs. A panic in a
+/// job is raised again here, after all jobs have finished.
+pub fn scope<'env, F, R>(f: F) -> R
+where
+    F: FnOnce(&Scope<'env>) -> R,
+{
+    let scope = Scope {
+        state: Arc::new(ScopeState {
+            queue: Mutex::new(VecDeque::new()),
+            pending: Mutex::new(0),
+            finished: Condvar::new(),
+            panicked: AtomicBool::new(false),
+        }),
+        _env: PhantomData,
+    };
+    let result = panic::catch_unwind(AssertUnwindSafe(|| f(&scope)));
+    scope.wait();
+    match result {
+        Ok(_) if scope.state.panicked.load(Ordering::SeqCst) => panic!("job panicked"),
+        Ok(result) => result,
+        Err(payload) => panic::resume_unwind(payload),
+    }
+}
+
+/// Results of `f` for each chunk of the items, in order. Runs in parallel if there is more
+/// than one chunk.
+pub fn map_chunks<T, R, F>(items: &[T], chunk_size: usize, f: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&[T]) -> R + Sync,
+{
+    let chunk_size = chunk_size.max(1);
+    if items.len() <= chunk_size {
+        return vec![f(items)];
+    }
+
+    let mut results = (0..(items.len() + chunk_size - 1) / chunk_size)
+        .map(|_| None)
+        .collect::<Vec<_>>();
+    let f = &f;
+    scope(|scope| {
+        for (chunk, result) in items.chunks(chunk_size).zip(results.iter_mut()) {
+            scope.spawn(move || *result = Some(f(chunk)));
+        }
+    });
+    results
+        .into_iter()
+        .map(|result| result.expect("job did not run"))
+        .collect()
+}
+
+/// Run `f` for each chunk of the items with the index of its first item. Runs in parallel
+/// if there is more than one chunk.
+pub fn for_each_chunk_mut<T, F>(items: &mut [T], chunk_size: usize, f: F)
+where
+    T: Send,
+    F: Fn(usize, &mut [T]) + Sync,
+{
+    let chunk_size = chunk_size.max(1);
+    if items.len() <= chunk_size {
+        f(0, items);
+        return;
+    }
+
+    let f = &f;
+    scope(|scope| {
+        for (index, chunk) in items.chunks_mut(chunk_size).enumerate() {
+            scope.spawn(move || f(index * chunk_size, chunk));
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    #[test]
+    fn blocked_spawn_does_not_delay_scope() {
+        // keep every worker busy and leave more blocked jobs queued behind them
+        let releases = (0..num_cpus::get() * 2)
+            .map(|_| {
+                let (release, blocked) = mpsc::channel::<()>();
+                spawn(move || {
+                    let _ = blocked.recv();
+                });
+                release
+            }).collect::<Vec<_>>();
+
+        let (done, finished) = mpsc::channel();
+        thread::spawn(move || {
+            let mut value = 0;
+            scope(|scope| scope.spawn(|| value = 1));
+            done.send(value).unwrap();
+        });
+        let result = finished.recv_timeout(Duration::from_secs(10));
+
+        drop(releases);
+        assert_eq!(result, Ok(1));
+    }
+}
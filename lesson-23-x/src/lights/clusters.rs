@@ -0,0 +1,298 @@
+use gl;
+use nalgebra as na;
+use crate::jobs;
+use crate::render_gl::buffer::Buffer;
+use crate::render_gl::{self, Std140, UniformBuffer};
+use super::{Light, LightUniform, Lights};
+
+/// Clusters across and up the screen, and depth slices.
+pub const CLUSTER_GRID: [usize; 3] = [16, 9, 24];
+
+/// Uniform block binding point of `ClusterUniforms`.
+pub const CLUSTER_UNIFORMS_BINDING: u32 = 3;
+/// Storage block binding points of the clustered light data.
+pub const CLUSTER_LIGHTS_BINDING: u32 = 3;
+pub const CLUSTER_GRID_BINDING: u32 = 4;
+pub const CLUSTER_INDICES_BINDING: u32 = 5;
+
+/// Grid parameters shared by programs through the `Clusters` uniform block.
+#[derive(Std140)]
+pub struct ClusterUniforms {
+    /// Row of the view matrix giving the distance in front of the camera.
+    pub depth_row: na::Vector4<f32>,
+    /// Size in pixels of the target the clusters cover.
+    pub viewport: na::Vector2<f32>,
+    pub near: f32,
+    pub far: f32,
+    pub tiles_x: i32,
+    pub tiles_y: i32,
+    pub slices: i32,
+    /// Zero in passes with another camera, which use the `Lights` block only.
+    pub enabled: i32,
+}
+
+/// View space box of one cluster.
+#[derive(Copy, Clone)]
+struct ClusterBounds {
+    min: na::Vector3<f32>,
+    max: na::Vector3<f32>,
+}
+
+impl ClusterBounds {
+    fn intersects_sphere(&self, center: &na::Vector3<f32>, radius: f32) -> bool {
+        let closest = center.zip_zip_map(&self.min, &self.max, |c, min, max| c.max(min).min(max));
+        (closest - center).norm_squared() <= radius * radius
+    }
+}
+
+/// Point or spot light in view space, with its index in the light storage.
+struct ViewLight {
+    index: u32,
+    center: na::Vector3<f32>,
+    radius: f32,
+}
+
+/// Point and spot lights assigned to a froxel grid each frame, for clustered forward shading.
+///
+/// The view is split into `CLUSTER_GRID` clusters, with depth slices growing exponentially
+/// from `near` to `far`. Materials with `CLUSTERED` look up the cluster of the fragment and
+/// only shade its lights, directional lights stay in the `Lights` block. Lights beyond `far`
+/// are left out.
+///
+/// Clusters are built on the CPU with `jobs`, one depth slice per job. Storage buffers need
+/// GL 4.3 or `GL_ARB_shader_storage_buffer_object`.
+pub struct LightClusters {
+    pub enabled: bool,
+    pub near: f32,
+    pub far: f32,
+    /// Projection and depth range the bounds were computed for.
+    bounds_key: Option<(na::Matrix4<f32>, f32, f32)>,
+    bounds: Vec<ClusterBounds>,
+    lights: Buffer,
+    grid: Buffer,
+    indices: Buffer,
+    uniforms: UniformBuffer<ClusterUniforms>,
+    disabled_uniforms: UniformBuffer<ClusterUniforms>,
+    light_count: usize,
+    max_cluster_lights: usize,
+}
+
+impl LightClusters {
+    /// Storage buffers are available, without them materials shade only the `Lights` block.
+    pub fn supported(gl: &gl::Gl) -> bool {
+        render_gl::Capabilities::get(gl).storage_buffers
+    }
+
+    pub fn new(gl: &gl::Gl) -> LightClusters {
+        let buffer = |label: &str| {
+            let buffer = Buffer::new_shader_storage(gl);
+            buffer.set_label(label);
+            buffer
+        };
+        let mut disabled_uniforms = UniformBuffer::new(gl);
+        disabled_uniforms.update(&ClusterUniforms {
+            depth_row: na::Vector4::zeros(),
+            viewport: na::Vector2::repeat(1.0),
+            near: 1.0,
+            far: 2.0,
+            tiles_x: 1,
+            tiles_y: 1,
+            slices: 1,
+            enabled: 0,
+        });
+
+        let clusters = LightClusters {
+            enabled: true,
+            near: 0.5,
+            far: 200.0,
+            bounds_key: None,
+            bounds: Vec::new(),
+            lights: buffer("cluster lights"),
+            grid: buffer("cluster grid"),
+            indices: buffer("cluster indices"),
+            uniforms: UniformBuffer::new(gl),
+            disabled_uniforms,
+            light_count: 0,
+            max_cluster_lights: 0,
+        };
+        clusters.upload(&[], &[[0, 0]], &[0]);
+        clusters
+    }
+
+    /// Assign the lights to the clusters of the camera, and upload them for `bind`.
+    pub fn update(
+        &mut self,
+        lights: &Lights,
+        view: &na::Matrix4<f32>,
+        projection: &na::Matrix4<f32>,
+        viewport_width: i32,
+        viewport_height: i32,
+    ) {
+        if !self.enabled {
+            return;
+        }
+        self.update_bounds(projection);
+
+        let mut uniforms = Vec::new();
+        let mut view_lights = Vec::new();
+        for (_, light) in lights.iter() {
+            let (position, radius) = match *light {
+                Light::Point(ref light) => (light.position, light.radius),
+                Light::Spot(ref light) => (light.position, light.radius),
+                Light::Directional(_) => continue,
+            };
+            view_lights.push(ViewLight {
+                index: uniforms.len() as u32,
+                center: view.transform_point(&position).coords,
+                radius,
+            });
+            uniforms.push(light.to_uniform());
+        }
+
+        let view_lights = &view_lights;
+        let slices = jobs::map_chunks(&self.bounds, CLUSTER_GRID[0] * CLUSTER_GRID[1], |bounds| {
+            let mut counts = Vec::with_capacity(bounds.len());
+            let mut indices = Vec::new();
+            for cluster in bounds {
+                let first = indices.len();
+                indices.extend(
+                    view_lights
+                        .iter()
+                        .filter(|light| cluster.intersects_sphere(&light.center, light.radius))
+                        .map(|light| light.index),
+                );
+                counts.push(indices.len() - first);
+            }
+            (counts, indices)
+        });
+
+        let mut grid = Vec::with_capacity(self.bounds.len());
+        let mut indices = Vec::new();
+        for (counts, slice_indices) in slices {
+            let mut offset = indices.len();
+            for count in counts {
+                grid.push([offset as u32, count as u32]);
+                offset += count;
+            }
+            indices.extend(slice_indices);
+        }
+        self.max_cluster_lights = grid.iter().map(|cell| cell[1] as usize).max().unwrap_or(0);
+        self.light_count = uniforms.len();
+        self.upload(&uniforms, &grid, &indices);
+
+        let row = view.row(2);
+        self.uniforms.update(&ClusterUniforms {
+            depth_row: -na::Vector4::new(row[0], row[1], row[2], row[3]),
+            viewport: na::Vector2::new(viewport_width as f32, viewport_height as f32),
+            near: self.near,
+            far: self.far,
+            tiles_x: CLUSTER_GRID[0] as i32,
+            tiles_y: CLUSTER_GRID[1] as i32,
+            slices: CLUSTER_GRID[2] as i32,
+            enabled: 1,
+        });
+    }
+
+    /// Bind the clusters for passes with the camera of the last `update`.
+    pub fn bind(&self) {
+        let uniforms = if self.enabled {
+            &self.uniforms
+        } else {
+            &self.disabled_uniforms
+        };
+        uniforms.bind_base(CLUSTER_UNIFORMS_BINDING);
+        self.lights.bind_base(CLUSTER_LIGHTS_BINDING);
+        self.grid.bind_base(CLUSTER_GRID_BINDING);
+        self.indices.bind_base(CLUSTER_INDICES_BINDING);
+    }
+
+    /// Bind for passes with another camera, i.e. reflections, which shade the lights of the
+    /// `Lights` block without clusters.
+    pub fn bind_disabled(&self) {
+        self.disabled_uniforms.bind_base(CLUSTER_UNIFORMS_BINDING);
+    }
+
+    /// Point and spot lights of the last update.
+    pub fn light_count(&self) -> usize {
+        self.light_count
+    }
+
+    /// Lights of the fullest cluster in the last update.
+    pub fn max_cluster_lights(&self) -> usize {
+        self.max_cluster_lights
+    }
+
+    /// Depth where `slice` starts, exponential so that clusters stay about as deep as wide.
+    fn slice_depth(&self, slice: usize) -> f32 {
+        match slice {
+            0 => 0.0,
+            _ => self.near * (self.far / self.near).powf(slice as f32 / CLUSTER_GRID[2] as f32),
+        }
+    }
+
+    fn update_bounds(&mut self, projection: &na::Matrix4<f32>) {
+        let key = (*projection, self.near, self.far);
+        if self.bounds_key == Some(key) {
+            return;
+        }
+        self.bounds_key = Some(key);
+
+        let inverse = projection.try_inverse().unwrap_or_else(na::Matrix4::identity);
+        // view space point at depth 1 through the NDC position
+        let at_unit_depth = |x: f32, y: f32| {
+            let point = inverse.transform_point(&na::Point3::new(x, y, -1.0)).coords;
+            point / -point.z
+        };
+
+        let [tiles_x, tiles_y, slices] = CLUSTER_GRID;
+        self.bounds.clear();
+        for slice in 0..slices {
+            let depths = [self.slice_depth(slice), self.slice_depth(slice + 1)];
+            for y in 0..tiles_y {
+                for x in 0..tiles_x {
+                    let x0 = x as f32 / tiles_x as f32 * 2.0 - 1.0;
+                    let y0 = y as f32 / tiles_y as f32 * 2.0 - 1.0;
+                    let x1 = (x + 1) as f32 / tiles_x as f32 * 2.0 - 1.0;
+                    let y1 = (y + 1) as f32 / tiles_y as f32 * 2.0 - 1.0;
+                    let corners = [
+                        at_unit_depth(x0, y0),
+                        at_unit_depth(x1, y0),
+                        at_unit_depth(x0, y1),
+                        at_unit_depth(x1, y1),
+                    ];
+                    let mut min = na::Vector3::repeat(::std::f32::INFINITY);
+                    let mut max = na::Vector3::repeat(::std::f32::NEG_INFINITY);
+                    for corner in &corners {
+                        for depth in &depths {
+                            let point = corner * *depth;
+                            min = min.zip_map(&point, f32::min);
+                            max = max.zip_map(&point, f32::max);
+                        }
+                    }
+                    self.bounds.push(ClusterBounds { min, max });
+                }
+            }
+        }
+    }
+
+    fn upload(&self, lights: &[LightUniform], grid: &[[u32; 2]], indices: &[u32]) {
+        // std430 layout of the light struct is the same as std140
+        let stride = LightUniform::std140_size();
+        let mut light_bytes = vec![0u8; stride * lights.len().max(1)];
+        for (light, bytes) in lights.iter().zip(light_bytes.chunks_mut(stride)) {
+            light.write_std140(bytes);
+        }
+        // empty buffers can not be bound to a storage block
+        let indices = if indices.is_empty() { &[0][..] } else { indices };
+
+        self.lights.bind();
+        self.lights.stream_draw_data(&light_bytes);
+        self.lights.unbind();
+        self.grid.bind();
+        self.grid.stream_draw_data(grid);
+        self.grid.unbind();
+        self.indices.bind();
+        self.indices.stream_draw_data(indices);
+        self.indices.unbind();
+    }
+}
@@ -0,0 +1,281 @@
+use nalgebra as na;
+use crate::render_gl::{DebugLines, LinesMarker};
+use slab::Slab;
+
+mod clusters;
+
+pub use self::clusters::{
+    ClusterUniforms, LightClusters, CLUSTER_GRID, CLUSTER_GRID_BINDING, CLUSTER_INDICES_BINDING,
+    CLUSTER_LIGHTS_BINDING, CLUSTER_UNIFORMS_BINDING,
+};
+
+/// Maximum number of lights in the `Lights` uniform block, must match the shaders.
+pub const MAX_LIGHTS: usize = 32;
+
+/// Light infinitely far away, such as the sun.
+#[derive(Copy, Clone, Debug)]
+pub struct DirectionalLight {
+    /// Direction the light travels in.
+    pub direction: na::Vector3<f32>,
+    pub color: na::Vector3<f32>,
+    pub intensity: f32,
+    pub casts_shadows: bool,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct PointLight {
+    pub position: na::Point3<f32>,
+    pub color: na::Vector3<f32>,
+    pub intensity: f32,
+    /// Light fades out to zero at this distance.
+    pub radius: f32,
+    pub casts_shadows: bool,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct SpotLight {
+    pub position: na::Point3<f32>,
+    pub direction: na::Vector3<f32>,
+    pub color: na::Vector3<f32>,
+    pub intensity: f32,
+    pub radius: f32,
+    /// Half angle in radians of the fully lit cone.
+    pub inner_angle: f32,
+    /// Half angle in radians where the light fades out.
+    pub outer_angle: f32,
+    pub casts_shadows: bool,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub enum Light {
+    Directional(DirectionalLight),
+    Point(PointLight),
+    Spot(SpotLight),
+}
+
+impl Light {
+    pub fn casts_shadows(&self) -> bool {
+        match *self {
+            Light::Directional(ref light) => light.casts_shadows,
+            Light::Point(ref light) => light.casts_shadows,
+            Light::Spot(ref light) => light.casts_shadows,
+        }
+    }
+
+    pub fn color(&self) -> na::Vector3<f32> {
+        match *self {
+            Light::Directional(ref light) => light.color,
+            Light::Point(ref light) => light.color,
+            Light::Spot(ref light) => light.color,
+        }
+    }
+
+    /// Light type constant used in shaders.
+    pub fn shader_kind(&self) -> i32 {
+        match *self {
+            Light::Directional(_) => 0,
+            Light::Point(_) => 1,
+            Light::Spot(_) => 2,
+        }
+    }
+
+    fn to_uniform(self) -> LightUniform {
+        let kind = self.shader_kind() as f32;
+        let shadows = if self.casts_shadows() { 1.0 } else { 0.0 };
+
+        match self {
+            Light::Directional(light) => LightUniform {
+                position_kind: na::Vector4::new(0.0, 0.0, 0.0, kind),
+                direction_radius: light.direction.normalize().push(0.0),
+                color_intensity: light.color.push(light.intensity),
+                params: na::Vector4::new(0.0, 0.0, shadows, 0.0),
+            },
+            Light::Point(light) => LightUniform {
+                position_kind: light.position.coords.push(kind),
+                direction_radius: na::Vector4::new(0.0, 0.0, 0.0, light.radius),
+                color_intensity: light.color.push(light.intensity),
+                params: na::Vector4::new(0.0, 0.0, shadows, 0.0),
+            },
+            Light::Spot(light) => LightUniform {
+                position_kind: light.position.coords.push(kind),
+                direction_radius: light.direction.normalize().push(light.radius),
+                color_intensity: light.color.push(light.intensity),
+                params: na::Vector4::new(
+                    light.inner_angle.cos(),
+                    light.outer_angle.cos(),
+                    shadows,
+                    0.0,
+                ),
+            },
+        }
+    }
+}
+
+impl From<DirectionalLight> for Light {
+    fn from(light: DirectionalLight) -> Light {
+        Light::Directional(light)
+    }
+}
+
+impl From<PointLight> for Light {
+    fn from(light: PointLight) -> Light {
+        Light::Point(light)
+    }
+}
+
+impl From<SpotLight> for Light {
+    fn from(light: SpotLight) -> Light {
+        Light::Spot(light)
+    }
+}
+
+/// Single light in the `Lights` uniform block.
+#[derive(Std140, Copy, Clone)]
+pub struct LightUniform {
+    /// Position, and light kind in w.
+    pub position_kind: na::Vector4<f32>,
+    pub direction_radius: na::Vector4<f32>,
+    pub color_intensity: na::Vector4<f32>,
+    /// Spot cone cosines (inner, outer), and 1.0 in z if the light casts shadows.
+    pub params: na::Vector4<f32>,
+}
+
+/// Light list shared by programs through the `Lights` uniform block.
+#[derive(Std140)]
+pub struct LightUniforms {
+    pub count: i32,
+    pub lights: [LightUniform; MAX_LIGHTS],
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct LightHandle(usize);
+
+/// Lights in the scene, added and removed at runtime.
+#[derive(Default)]
+pub struct Lights {
+    lights: Slab<Light>,
+}
+
+impl Lights {
+    pub fn new() -> Lights {
+        Lights::default()
+    }
+
+    pub fn add<L: Into<Light>>(&mut self, light: L) -> LightHandle {
+        LightHandle(self.lights.insert(light.into()))
+    }
+
+    pub fn remove(&mut self, handle: LightHandle) -> Option<Light> {
+        if self.lights.contains(handle.0) {
+            Some(self.lights.remove(handle.0))
+        } else {
+            None
+        }
+    }
+
+    pub fn get(&self, handle: LightHandle) -> Option<&Light> {
+        self.lights.get(handle.0)
+    }
+
+    pub fn get_mut(&mut self, handle: LightHandle) -> Option<&mut Light> {
+        self.lights.get_mut(handle.0)
+    }
+
+    pub fn len(&self) -> usize {
+        self.lights.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lights.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (LightHandle, &Light)> {
+        self.lights.iter().map(|(key, light)| (LightHandle(key), light))
+    }
+
+    /// First directional light that casts shadows, the only kind the shadow map supports.
+    pub fn shadow_caster(&self) -> Option<&DirectionalLight> {
+        self.lights
+            .iter()
+            .filter_map(|(_, light)| match *light {
+                Light::Directional(ref light) if light.casts_shadows => Some(light),
+                _ => None,
+            }).next()
+    }
+
+    /// Uniform block contents. Lights over `MAX_LIGHTS` are left out.
+    pub fn uniforms(&self) -> LightUniforms {
+        let empty = LightUniform {
+            position_kind: na::Vector4::zeros(),
+            direction_radius: na::Vector4::zeros(),
+            color_intensity: na::Vector4::zeros(),
+            params: na::Vector4::zeros(),
+        };
+        let mut uniforms = LightUniforms {
+            count: 0,
+            lights: [empty; MAX_LIGHTS],
+        };
+
+        for (slot, (_, light)) in uniforms.lights.iter_mut().zip(self.lights.iter()) {
+            *slot = light.to_uniform();
+            uniforms.count += 1;
+        }
+
+        uniforms
+    }
+}
+
+/// Debug lines showing light positions and directions in light color.
+pub struct LightMarkers {
+    lines: LinesMarker,
+}
+
+impl LightMarkers {
+    pub fn new(debug_lines: &DebugLines) -> LightMarkers {
+        LightMarkers {
+            lines: debug_lines.lines_marker(na::Isometry3::identity(), None.into_iter()),
+        }
+    }
+
+    /// Rebuild lines, call after lights were changed.
+    pub fn update(&self, lights: &Lights) {
+        let mut segments = Vec::new();
+
+        for (_, light) in lights.iter() {
+            let color = light.color().push(1.0);
+            match *light {
+                Light::Directional(ref light) => {
+                    // directional light has no position, show it above the origin
+                    let end = na::Point3::new(0.0, 0.0, 10.0);
+                    let start = end - light.direction.normalize() * 3.0;
+                    segments.push((start, end, color));
+                }
+                Light::Point(ref light) => {
+                    for axis in &[na::Vector3::x(), na::Vector3::y(), na::Vector3::z()] {
+                        let half = axis * 0.25;
+                        segments.push((light.position - half, light.position + half, color));
+                    }
+                }
+                Light::Spot(ref light) => {
+                    let direction = light.direction.normalize();
+                    let helper = if direction.x.abs() < 0.9 {
+                        na::Vector3::x()
+                    } else {
+                        na::Vector3::y()
+                    };
+                    let side = direction.cross(&helper).normalize();
+                    let up = direction.cross(&side);
+                    let length = light.radius * 0.25;
+                    let spread = light.outer_angle.tan() * length;
+                    let center = light.position + direction * length;
+                    segments.push((light.position, center, color));
+                    for offset in &[side, -side, up, -up] {
+                        segments.push((light.position, center + offset * spread, color));
+                    }
+                }
+            }
+        }
+
+        self.lines.update_lines(segments.into_iter());
+    }
+}
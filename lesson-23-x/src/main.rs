@@ -1,36 +1,64 @@
 extern crate floating_duration;
+extern crate game_loop;
 extern crate gl;
+extern crate gltf;
+#[cfg(feature = "winit")]
+extern crate glutin;
 extern crate half;
 extern crate image;
 extern crate nalgebra;
 extern crate ncollide3d;
+extern crate num_cpus;
 extern crate once_cell;
+extern crate ron;
+#[cfg(feature = "audio")]
+extern crate rodio;
 extern crate sdl2;
 extern crate slab;
 extern crate tobj;
 extern crate vec_2_10_10_10;
+extern crate serde;
+extern crate serde_json;
 #[macro_use]
 extern crate failure;
 #[macro_use]
+extern crate serde_derive;
+#[macro_use]
 extern crate lesson_23_x_render_gl_derive as render_gl_derive;
 
+pub mod animation;
+#[cfg(feature = "audio")]
+pub mod audio;
 pub mod camera;
 mod debug;
 pub mod dices;
+pub mod entity;
+pub mod floor;
+pub mod input;
+pub mod jobs;
+pub mod lights;
 pub mod mesh;
+pub mod physics;
+pub mod platform;
 pub mod render;
 pub mod render_gl;
 pub mod resources;
+pub mod scene;
 pub mod selection;
+pub mod skybox;
 pub mod system;
+pub mod ui;
+pub mod undo;
 
-use failure::err_msg;
-use floating_duration::TimeAsFloat;
 use nalgebra as na;
+use crate::camera::Camera;
+use crate::platform::{Event, Platform};
+use crate::render_gl::DeferredGeometry;
 use crate::resources::Resources;
-use std::time::{Duration, Instant};
 use crate::system::profiling::alloc_watch::PeekAlloc;
 use crate::system::profiling::gl_watch;
+use std::collections::VecDeque;
+use std::rc::Rc;
 
 #[global_allocator]
 static GLOBAL: PeekAlloc = PeekAlloc;
@@ -41,59 +69,240 @@ fn main() {
     }
 }
 
-fn run() -> Result<(), failure::Error> {
-    PeekAlloc::init();
+/// Saved with F5 and loaded with F6, in the working directory.
+const SCENE_FILE: &str = "scene.json";
+
+/// Saved next to the executable whenever graphics options change.
+const GRAPHICS_OPTIONS_FILE: &str = "graphics.json";
+
+/// Read next to the executable, actions it does not list keep their default bindings.
+const INPUT_BINDINGS_FILE: &str = "input.json";
+
+/// Linked program binaries are kept here next to the executable, delete it to recompile.
+const PROGRAM_CACHE_DIR: &str = "program-cache";
+
+/// Written next to the executable with `--record-input`, played with `--replay-input`.
+const INPUT_RECORDING_FILE: &str = "input-recording.json";
+
+/// Camera keyframes, saved next to the executable whenever they change.
+const CAMERA_PATH_FILE: &str = "camera-path.ron";
+
+/// Last frame of an input replay, in the working directory.
+const REPLAY_SCREENSHOT_FILE: &str = "replay.png";
 
-    let res = Resources::from_relative_exe_path("assets-23-x").unwrap();
+/// Oldest decals are removed beyond this count.
+const MAX_DECALS: usize = 64;
 
-    let sdl = sdl2::init().map_err(err_msg)?;
-    let video_subsystem = sdl.video().map_err(err_msg)?;
+/// Half size of stamped decals, the last one is the projection depth.
+const DECAL_HALF_EXTENTS: [f32; 3] = [0.6, 0.6, 0.3];
 
-    let gl_attr = video_subsystem.gl_attr();
-    gl_attr.set_context_profile(sdl2::video::GLProfile::Core);
-    gl_attr.set_context_version(4, 1);
-    gl_attr.set_accelerated_visual(true);
-    gl_attr.set_double_buffer(true);
+/// Paint colors of stamped decals, used in turn.
+const DECAL_COLORS: [[f32; 4]; 4] = [
+    [0.9, 0.2, 0.2, 0.9],
+    [0.2, 0.7, 0.3, 0.9],
+    [0.2, 0.4, 0.9, 0.9],
+    [0.9, 0.8, 0.2, 0.9],
+];
 
-    let mut window_size = render::WindowSize {
-        width: 960,
-        height: 600,
-        highdpi_width: 960,
-        highdpi_height: 600,
+/// Decal on the surface under the cursor: a selectable mesh, attached to its entity so
+/// that it follows it, or the ground plane. `index` varies color and rotation.
+fn stamp_decal(
+    res: &Resources,
+    gl: &gl::Gl,
+    scene: &scene::Scene,
+    selectables: &selection::Selectables,
+    entities: &entity::Entities,
+    texture: &Rc<render_gl::Texture>,
+    index: usize,
+) -> Result<Option<entity::Decal>, failure::Error> {
+    let half_extents = na::Vector3::from(DECAL_HALF_EXTENTS);
+    let (point, normal, triangles, parent) = match selectables.cursor_hit() {
+        Some((handle, hit)) => {
+            let triangles = selectables
+                .get_container(handle)
+                .map(|container| container.world_triangles())
+                .unwrap_or_default();
+            let parent = entities
+                .find_by_selectable(handle)
+                .and_then(|entity| entities.get(entity))
+                .and_then(|entity| entity.node());
+            (hit.point, hit.normal, triangles, parent)
+        }
+        None => {
+            // floor is not selectable, stamp a quad on the ground plane instead
+            let ray = match selectables.cursor_ray() {
+                Some(ray) => ray,
+                None => return Ok(None),
+            };
+            let up = na::Vector3::z_axis();
+            let point = match camera::projection::plane_toi(&ray, &na::Point3::origin(), &up) {
+                Some(toi) => ray.origin + ray.dir * toi,
+                None => return Ok(None),
+            };
+            let size = half_extents.x.max(half_extents.y) * 2.0;
+            let corner = |x: f32, y: f32| point + na::Vector3::new(x * size, y * size, 0.0);
+            let triangles = vec![
+                [corner(-1.0, -1.0), corner(1.0, -1.0), corner(1.0, 1.0)],
+                [corner(-1.0, -1.0), corner(1.0, 1.0), corner(-1.0, 1.0)],
+            ];
+            (point, up.into_inner(), triangles, None)
+        }
     };
 
-    let window = video_subsystem
-        .window("Game", window_size.width as u32, window_size.height as u32)
-        .opengl()
-        .resizable()
-        .allow_highdpi()
-        .build()?;
+    let angle = index as f32 * 2.4;
+    let isometry = entity::Decal::isometry_on_surface(&point, &normal, angle);
+    let mut decal = entity::Decal::new(
+        gl,
+        res,
+        scene,
+        isometry,
+        half_extents,
+        texture.clone(),
+        &triangles,
+    )?;
+    if decal.is_empty() {
+        return Ok(None);
+    }
+    decal.set_parent(parent);
+    decal.color = na::Vector4::from(DECAL_COLORS[index % DECAL_COLORS.len()]);
+    Ok(Some(decal))
+}
 
-    let drawable_size = window.drawable_size();
-    window_size.highdpi_width = drawable_size.0 as i32;
-    window_size.highdpi_height = drawable_size.1 as i32;
+/// Small colored point lights on a spiral around the origin, i.e. to compare clustered
+/// lighting with many lights. Every call continues the spiral further out.
+fn add_point_lights(lights: &mut lights::Lights, count: usize) {
+    let first = lights.len();
+    for index in first..first + count {
+        let angle = index as f32 * 2.4;
+        let distance = (index as f32).sqrt() * 2.0;
+        lights.add(lights::PointLight {
+            position: na::Point3::new(angle.cos() * distance, angle.sin() * distance, 1.0),
+            color: na::Vector3::new(
+                0.5 + 0.5 * angle.sin(),
+                0.5 + 0.5 * (angle * 0.7).cos(),
+                0.5 + 0.5 * (angle * 1.3).sin(),
+            ),
+            intensity: 2.0,
+            radius: 4.0,
+            casts_shadows: false,
+        });
+    }
+}
 
-    let _gl_context = window.gl_create_context().map_err(err_msg)?;
-    let gl = gl::Gl::load_with(|s| {
-        video_subsystem.gl_get_proc_address(s) as *const std::os::raw::c_void
-    });
+/// Entity types that can be loaded from the scene file.
+fn scene_registry<'a>(
+    res: &'a Resources,
+    gl: &'a gl::Gl,
+    debug_lines: &'a render_gl::DebugLines,
+    scene: &'a scene::Scene,
+    physics: &'a physics::World,
+) -> scene::serde::Registry<'a> {
+    scene::serde::Registry::new().with_kind("dice", move |record| {
+        let model = record
+            .resource
+            .as_ref()
+            .map(|path| &path[..])
+            .unwrap_or(dices::DEFAULT_MODEL);
+        let mut dice = dices::Dice::with_model(res, gl, debug_lines, scene, model)?;
+        dice.set_transform(record.isometry());
+        if let Some(scale) = record.scale {
+            dice.set_scale(scale.into());
+        }
+        dice.enable_physics(physics);
+        Ok(dice)
+    })
+}
 
-    // 0 for immediate updates,
-    // 1 for updates synchronized with the vertical retrace,
-    // -1 for late swap tearing
+fn run() -> Result<(), failure::Error> {
+    PeekAlloc::init();
+
+    // packed assets make the lesson a single file to distribute, loose files are for development
+    let res = Resources::from_relative_exe_pack("assets-23-x.tar")
+        .or_else(|_| Resources::from_relative_exe_path("assets-23-x"))
+        .unwrap();
 
-    let vsync = false;
-    video_subsystem.gl_set_swap_interval(if vsync { 1 } else { 0 });
+    let options_res = Resources::from_exe_path()?;
+    let mut graphics_options =
+        render::GraphicsOptions::load_or_default(&options_res, GRAPHICS_OPTIONS_FILE);
+
+    // build with --features winit for the winit backend
+    let mut platform = platform::DefaultPlatform::create(
+        &platform::WindowSettings::new("Game", 960, 600)
+            // drivers report more through KHR_debug for debug contexts
+            .with_debug_context(cfg!(debug_assertions))
+            .with_vsync(graphics_options.vsync),
+    )?;
+    let gl = platform.gl().clone();
+    let mut window_size = platform.window_size();
+    // features the context lacks fall back quietly, pass --print-capabilities to see what
+    // it has
+    if std::env::args().any(|a| a == "--print-capabilities") {
+        for line in render_gl::Capabilities::get(&gl).report() {
+            println!("{}", line);
+        }
+    }
+
+    let _debug_output = render_gl::debug::DebugOutput::install(
+        &gl,
+        render_gl::debug::Severity::Low,
+        render_gl::debug::print_message,
+    );
+
+    render_gl::ProgramBinaryCache::install(
+        &gl,
+        Resources::from_relative_exe_path(PROGRAM_CACHE_DIR)?,
+    );
+
+    graphics_options.vsync = platform.set_vsync(graphics_options.vsync);
+    let mut input_map =
+        input::InputMap::new(input::Bindings::load_or_default(&options_res, INPUT_BINDINGS_FILE));
+    // --record-input saves events and frame times on exit, --replay-input plays them back
+    // instead of the window events and saves the last frame, so that runs can be compared
+    let mut input_recorder = if std::env::args().any(|a| a == "--record-input") {
+        Some(input::InputRecorder::new())
+    } else {
+        None
+    };
+    let mut input_replay = if std::env::args().any(|a| a == "--replay-input") {
+        Some(input::InputReplay::load(&options_res, INPUT_RECORDING_FILE)?)
+    } else {
+        None
+    };
 
     let mut frame_profiler = render_gl::FrameProfiler::new(&gl, &res, 80)?;
     let mut allocation_profiler = render_gl::EventCountProfiler::new(&gl, &res, 3, 0)?;
     let mut gl_call_profiler = render_gl::EventCountProfiler::new(&gl, &res, 1, 20)?;
+    let mut gpu_profiler = render_gl::Profiler::new(&gl, &res)?;
+    let mut debug_text = render_gl::DebugText::new(&gl, &res)?;
+    let mut stats_hud = render_gl::StatsHud::new();
+    let error_console = render_gl::ErrorConsole::new();
+    let mut status_line = render_gl::StatusLine::new();
+    let mut frame_recorder = render_gl::capture::FrameRecorder::new(&gl, "capture");
+    let mut msaa = render_gl::MsaaTarget::new(
+        &gl,
+        graphics_options.msaa_samples,
+        render_gl::ColorFormat::Rgba16f,
+    );
+    graphics_options.msaa_samples = msaa.samples();
+    let mut post_process =
+        render_gl::PostProcess::new(&gl, window_size.highdpi_width, window_size.highdpi_height)?
+            .with_effect(render_gl::Bloom::new(&gl, &res)?)
+            .with_effect(render_gl::ToneMapping::new(&gl, &res)?);
+    let mut screenshot_requested = false;
+    let mut material_debug_view = render::MaterialDebugView::Shaded;
+    let mut debug_render_mode = render_gl::DebugRenderMode::Solid;
+    let mut texture_sampler = graphics_options
+        .texture_filter
+        .build_with_anisotropy(&gl, graphics_options.anisotropy);
+    // drawn and frustum culled entities
+    let mut visibility_profiler = render_gl::EventCountProfiler::new(&gl, &res, 4, 40)?;
 
-    let mut viewport =
-        render_gl::Viewport::for_window(window_size.highdpi_width, window_size.highdpi_height);
+    let mut viewport = render_gl::Viewport::for_window_size(&window_size);
     let color_buffer = render_gl::ColorBuffer::new();
     let mut editor_lines = render_gl::DebugLines::new(&gl, &res)?;
     let mut debug_lines = render_gl::DebugLines::new(&gl, &res)?;
+    // drawn without depth test
+    let mut gizmo_lines = render_gl::DebugLines::new(&gl, &res)?;
     let _grid = editor_lines.grid_marker(
         na::Isometry3::identity(),
         1.0,
@@ -103,11 +312,18 @@ fn run() -> Result<(), failure::Error> {
     let selectables = selection::Selectables::new();
     let mut render_selectables = system::render::selectables::RenderSelectables::new();
     let mut input_selectables = system::input::selectables::SelectablesInput::new();
+    let scene = scene::Scene::new();
+    // dice models load in background, upload a few of them per frame
+    let uploads = render_gl::UploadQueue::new(4);
 
-    let mut dices = Vec::new();
+    // all dice share programs and the uploaded model with the first one
+    let mut dices: Vec<dices::Dice> = Vec::new();
     for x in -3..=3 {
         for y in -3..=3 {
-            let mut dice = dices::Dice::new(&res, &gl, &debug_lines, &selectables)?;
+            let mut dice = match dices.first() {
+                Some(first) => first.spawn_clone(&res, &debug_lines, &scene),
+                None => dices::Dice::new(&res, &gl, &debug_lines, &scene)?,
+            };
             dice.set_transform(na::Isometry3::from_parts(
                 na::Translation3::from(na::Vector3::new(4.0 * x as f32, 4.0 * y as f32, 0.0)),
                 na::UnitQuaternion::identity(),
@@ -116,27 +332,297 @@ fn run() -> Result<(), failure::Error> {
         }
     }
 
-    let mut camera = camera::TargetCamera::new(
-        window_size.width as f32 / window_size.height as f32,
-        3.14 / 2.5,
-        0.01,
-        1000.0,
-        3.14 / 4.0,
-        5.0,
+    // the dice next to the center one follows it around
+    dices[25].set_parent(Some(dices[24].node()));
+
+    // glass dice, drawn in the blended pass
+    dices[23].set_opacity(0.4);
+
+    // dice hidden behind the wall, skipped by occlusion culling when seen from the front
+    for x in -1..=1 {
+        let mut dice = dices[0].spawn_clone(&res, &debug_lines, &scene);
+        dice.set_transform(na::Isometry3::translation(3.0 * x as f32, -22.0, 0.0));
+        dices.push(dice);
+    }
+
+    // dice roll on the floor, except the attached one
+    let physics = physics::World::new(0.0);
+    for (index, dice) in dices.iter_mut().enumerate() {
+        if index != 25 {
+            dice.enable_physics(&physics);
+        }
+    }
+
+    let mut entities = entity::Entities::new();
+    let mut dice_handles: Vec<_> = dices.into_iter().map(|dice| entities.add(dice)).collect();
+
+    // paint splats stamped with the cursor, oldest first
+    let splat_texture = Rc::new(render_gl::Texture::from_rgba_image(
+        &gl,
+        &entity::splat_image(64, 0x1234_5678),
+        render_gl::ColorSpace::Linear,
+        true,
+    ));
+    let mut decals = VecDeque::new();
+    let mut stamped_count = 0;
+
+    // finished drags, walked back with Ctrl+Z and forward with Ctrl+Y
+    let mut undo_stack = undo::UndoStack::default();
+    // object and its transform before the transform panel edit in progress
+    let mut panel_edit: Option<(selection::ContainerHandle, na::Isometry3<f32>, na::Vector3<f32>)> =
+        None;
+
+    // build with --features audio for sounds when dice are selected and dropped
+    #[cfg(feature = "audio")]
+    let mut selection_sounds = audio::SelectionSounds::new(&res)
+        .map_err(|e| println!("Audio disabled: {}", e))
+        .ok();
+
+    let mut floor = floor::Floor::new(&res, &gl, 32.0, [0.6, 0.6, 0.6].into())?;
+    floor.set_transform(na::Isometry3::translation(0.0, 0.0, -0.005));
+    entities.add(floor);
+
+    // floor is one-sided, the wall is two of them back to back
+    for &angle in &[::std::f32::consts::FRAC_PI_2, -::std::f32::consts::FRAC_PI_2] {
+        let mut wall = floor::Floor::new(&res, &gl, 12.0, [0.7, 0.5, 0.4].into())?;
+        wall.set_transform(na::Isometry3::new(
+            na::Vector3::new(0.0, -18.0, 6.0),
+            na::Vector3::x() * angle,
+        ));
+        entities.add(wall);
+    }
+    entities.enable_occlusion_culling(&gl, &res)?;
+
+    // hills behind the dice, objects dragged in surface mode can be placed on them
+    let mut terrain = entity::Terrain::new(&res, &gl, &entity::TerrainSettings::default())?;
+    terrain.set_transform(na::Isometry3::translation(0.0, 52.0, -0.01));
+    selectables.set_drag_surface(Some(terrain.drag_surface()));
+    entities.add(terrain);
+
+    // lake in the terrain valleys, reflected and refracted at half the window resolution
+    let water = entity::Water::new(
+        &res,
+        &gl,
+        na::Point3::new(0.0, 52.0, 1.5),
+        56.0,
+        (viewport.w / 2).max(1),
+        (viewport.h / 2).max(1),
+    )?;
+    let water_reflection = water.reflection();
+    let water_handle = entities.add(water);
+
+    let particles = entity::Particles::new(
+        &gl,
+        &res,
+        4096,
+        entity::ParticleSimulation::best(&gl),
+        entity::EmitterSettings {
+            origin: na::Point3::new(16.0, 0.0, 0.0),
+            ..Default::default()
+        },
+    )?;
+    let particle_simulation = particles.simulation();
+    let particle_emitter = particles.emitter();
+    entities.add(particles);
+
+    // static ring of dice around the floor, all drawn by one indirect call
+    if entity::MeshBatch::supported(&gl) {
+        let dice_mesh = match res.load_obj(dices::DEFAULT_MODEL) {
+            Ok(mut models) if !models.meshes.is_empty() => models.meshes.remove(0),
+            result => {
+                if let Err(e) = result {
+                    resources::LoadErrors::report(dices::DEFAULT_MODEL, &e);
+                }
+                mesh::primitives::cube(&na::Vector3::new(2.0, 2.0, 2.0))
+            }
+        };
+        let mut batch = entity::MeshBatch::builder();
+        let materials = [
+            batch.add_material([0.8, 0.3, 0.3].into()),
+            batch.add_material([0.3, 0.8, 0.3].into()),
+            batch.add_material([0.3, 0.3, 0.8].into()),
+        ];
+        for index in 0..48 {
+            let angle = index as f32 / 48.0 * 2.0 * ::std::f32::consts::PI;
+            let model = na::Isometry3::new(
+                na::Vector3::new(15.0 * angle.cos(), 15.0 * angle.sin(), 0.5),
+                na::Vector3::z() * angle,
+            ).to_homogeneous() * na::Matrix4::new_scaling(0.5);
+            batch.add_mesh(&dice_mesh, &model, materials[index % materials.len()]);
+        }
+        let batch = batch.build(&gl, &res)?;
+        stats_hud.set_batched_draws(batch.draw_count());
+        entities.add(batch);
+    }
+
+    let mut lights = lights::Lights::new();
+    let sun = lights.add(lights::DirectionalLight {
+        direction: na::Vector3::new(-0.4, 0.6, -1.0),
+        color: na::Vector3::new(1.0, 1.0, 1.0),
+        intensity: 1.0,
+        casts_shadows: true,
+    });
+
+    // sun shadows cover the view up to this distance, split into cascades
+    let shadow_distance = 120.0;
+    let mut shadow_map =
+        render_gl::ShadowMap::new(&gl, &res, graphics_options.shadow_resolution, 4)?;
+    if let Some(sun) = lights.shadow_caster() {
+        shadow_map.set_directional_light(&sun.direction, &na::Point3::origin(), 40.0);
+    }
+    // boxes covered by shadow cascades, shown together with the cascade tint
+    let shadow_frustum_color = na::Vector4::new(1.0, 0.8, 0.2, 1.0);
+    let mut shadow_frusta: Vec<render_gl::FrustumMarker> = Vec::new();
+
+    // pass --deferred to compare forward shading with the deferred path
+    let mut deferred_renderer = if std::env::args().any(|a| a == "--deferred") {
+        Some(render_gl::DeferredRenderer::new(
+            &gl,
+            &res,
+            window_size.highdpi_width,
+            window_size.highdpi_height,
+        )?)
+    } else {
+        None
+    };
+    for x in -2..2 {
+        for y in -2..2 {
+            lights.add(lights::PointLight {
+                position: na::Point3::new(8.0 * x as f32 + 4.0, 8.0 * y as f32 + 4.0, 3.0),
+                color: na::Vector3::new(
+                    0.5 + 0.5 * (x as f32).sin(),
+                    0.5 + 0.5 * (y as f32).cos(),
+                    0.8,
+                ),
+                intensity: 1.0,
+                radius: 10.0,
+                casts_shadows: false,
+            });
+        }
+    }
+    let light_markers = lights::LightMarkers::new(&debug_lines);
+    light_markers.update(&lights);
+
+    // dots above point lights, the diamond can be clicked and dragged
+    let sprite_atlas = entity::SpriteAtlas::from_res(&gl, &res, "sprites/markers.json")?;
+    let mut sprites = entity::Sprites::new(&gl, &res, sprite_atlas)?;
+    for (_, light) in lights.iter() {
+        if let lights::Light::Point(ref light) = *light {
+            sprites.add(
+                entity::Sprite::new(light.position + na::Vector3::z() * 1.5, "dot")
+                    .with_size(0.5, 0.5)
+                    .with_color(light.color.push(1.0)),
+            );
+        }
+    }
+    sprites.add_selectable(
+        entity::Sprite::new(na::Point3::new(0.0, 8.0, 3.0), "diamond"),
+        &selectables,
     );
-    let camera_target_marker = editor_lines.marker(camera.target, 0.25);
+    entities.add(sprites);
+    let mut light_uniforms = render_gl::UniformBuffer::<lights::LightUniforms>::new(&gl);
+    light_uniforms.update(&lights.uniforms());
+    // point and spot lights beyond the `Lights` block are only shaded with clusters
+    let mut light_clusters = if lights::LightClusters::supported(&gl) {
+        Some(lights::LightClusters::new(&gl))
+    } else {
+        None
+    };
+
+    let mut skybox = skybox::Skybox::new(
+        &res,
+        &gl,
+        render_gl::CubeTextureSource::Equirectangular {
+            resource_name: "textures/sky.hdr".as_ref(),
+            face_size: 512,
+        },
+    )?;
+
+    let pbr_environment = render_gl::pbr::Environment::new(&gl, &res, skybox.texture())?;
+
+    // the sky model follows the sun and gives the fog its horizon color
+    let mut sky_model = skybox::SkyModel::default();
+    let mut sky_enabled = false;
+    let mut fog = render::Fog {
+        mode: render::FogMode::Exponential,
+        density: 0.015,
+        ..render::Fog::default()
+    };
+    let mut fog_uniforms = render_gl::UniformBuffer::<render::FogUniforms>::new(&gl);
+    fog_uniforms.update(&fog.uniforms());
+
+    // center dice reflects the others, R toggles it
+    let mut reflection_probe =
+        render_gl::ReflectionProbe::new(&gl, 128, na::Point3::new(0.0, 0.0, 1.0))?;
+    let mut reflections = true;
+    // X compares parallax occlusion mapping of the dice with plain normal mapping
+    let mut parallax_mapping = true;
+
+    // G switches hover and clicks from ray casting to the id buffer
+    let mut id_buffer =
+        render_gl::IdBuffer::new(&gl, &res, window_size.highdpi_width, window_size.highdpi_height)?;
+    let mut gpu_picking = false;
+    // Q shows scene and g-buffer values under the cursor
+    let mut pixel_inspector = render_gl::PixelInspector::new(&gl);
+
+    let outline = render_gl::Outline::new(&gl, &res)?;
+
+    let mut ui = ui::Ui::new();
+    let mut ui_renderer = ui::UiRenderer::new(&gl, &res)?;
+    let mut cursor_pixel = None;
+
+    let mut dice_instances = dices::DiceInstances::new(&res, &gl)?;
+    let mut dice_instance_transforms = Vec::new();
+    let mut dice_instance_skins = Vec::new();
+    for x in -10..10 {
+        for y in -10..10 {
+            dice_instance_transforms.push(na::Isometry3::from_parts(
+                na::Translation3::from(na::Vector3::new(3.0 * x as f32, 3.0 * y as f32, -8.0)),
+                na::UnitQuaternion::from_euler_angles(0.3 * x as f32, 0.2 * y as f32, 0.0),
+            ));
+            // checkerboard of skins, still drawn in one call
+            dice_instance_skins.push(((x + y) & 1) as u16);
+        }
+    }
+    dice_instances.set_transforms(&dice_instance_transforms);
+    let skins: [&resources::ResourcePath; 2] = [
+        "textures/dice.png".as_ref(),
+        "textures/dice_high_contrast.png".as_ref(),
+    ];
+    match dice_instances.set_skins(&gl, &res, &skins) {
+        Ok(()) => dice_instances.set_materials(&dice_instance_skins),
+        Err(e) => println!("Failed to load dice skins: {}", e),
+    }
+
+    let aspect = viewport.aspect();
+    let fov = 3.14 / 2.5;
+    let mut camera = camera::SwitchableCamera::new(
+        camera::OrbitCamera::new(aspect, fov, 0.01, 1000.0, 3.14 / 4.0, 5.0),
+        camera::FlyCamera::new(aspect, fov, 0.01, 1000.0),
+    );
+    let camera_target_marker = editor_lines.marker(camera.target(), 0.25);
+    // flythrough for showcase videos, played with the fly camera
+    let mut camera_path = camera::CameraPath::load_or_default(&options_res, CAMERA_PATH_FILE);
+    let mut camera_uniforms = render_gl::UniformBuffer::<render::CameraUniforms>::new(&gl);
 
     // set up shared state for window
 
     viewport.set_used(&gl);
-    color_buffer.set_clear_color(&gl, na::Vector3::new(0.3, 0.3, 0.5));
+    let mut frame_passes = render::FramePasses::default();
+    color_buffer.set_clear_color(&gl, frame_passes.background);
     let mut side_cam = false;
 
     // main loop
 
-    let mut time = Instant::now();
+    // physics and entities update at a fixed rate, frames are capped to keep the fans quiet
+    let mut game_loop = game_loop::GameLoop::new(120).with_frame_cap(80);
+    let mut frame_cap = true;
+    // weight of all morph targets of dice models with blend shapes, i.e. glTF face shapes
+    let mut morph_weight = 0.0;
+    // options changed by keys and the Graphics panel are applied once per frame
+    let mut applied_options = graphics_options.clone();
 
-    let mut event_pump = sdl.event_pump().map_err(err_msg)?;
+    let mut events = Vec::new();
     'main: loop {
         PeekAlloc::reset();
         gl_watch::reset();
@@ -144,67 +630,612 @@ fn run() -> Result<(), failure::Error> {
         frame_profiler.begin();
         allocation_profiler.begin();
         gl_call_profiler.begin();
+        visibility_profiler.begin();
+        gpu_profiler.begin_frame();
 
-        for event in event_pump.poll_iter() {
-            if system::input::window::handle_default_window_events(
+        input_map.begin_frame();
+        platform.poll_events(&mut events);
+        let replayed_frame_time = input_replay
+            .as_mut()
+            .and_then(|replay| replay.next_frame(&mut events));
+        if let Some(ref mut recorder) = input_recorder {
+            recorder.record_events(&events);
+        }
+        for event in events.drain(..) {
+            match system::input::window::handle_default_window_events(
                 &event,
                 &gl,
-                &window,
                 &mut window_size,
                 &mut viewport,
                 &mut camera,
-            ) == system::input::window::HandleResult::Quit
-            {
-                break 'main;
+            ) {
+                system::input::window::HandleResult::Quit => break 'main,
+                system::input::window::HandleResult::Resized(resize) => {
+                    if let Some(ref mut deferred_renderer) = deferred_renderer {
+                        deferred_renderer.resize(resize.width, resize.height)?;
+                    }
+                    id_buffer.resize(resize.width, resize.height)?;
+                    water_reflection
+                        .borrow_mut()
+                        .resize((resize.width / 2).max(1), (resize.height / 2).max(1))?;
+                }
+                system::input::window::HandleResult::Continue => (),
+            }
+            if ui.handle_event(&event, &window_size) {
+                continue;
             }
+            input_map.handle_event(&event);
             system::input::camera::handle_camera_events(&event, &mut camera);
-            input_selectables.handle_selectable_events(&event, &window_size, &camera, &selectables);
-
-            match event {
-                sdl2::event::Event::KeyDown {
-                    scancode: Some(sdl2::keyboard::Scancode::C),
-                    ..
-                } => {
-                    side_cam = !side_cam;
+            input_selectables.handle_selectable_events(
+                &event,
+                &input_map,
+                &window_size,
+                &camera,
+                &selectables,
+            );
+
+            if let Event::MouseMotion { x, y, .. } = event {
+                cursor_pixel = Some(viewport.to_pixels(x, y));
+            }
+        }
+
+        if input_map.just_pressed("toggle_side_camera") {
+            side_cam = !side_cam;
+        }
+        if input_map.just_pressed("toggle_debug") {
+            debug_lines.toggle();
+            debug_text.toggle();
+        }
+        if input_map.just_pressed("toggle_profilers") {
+            frame_profiler.toggle();
+            allocation_profiler.toggle();
+            gl_call_profiler.toggle();
+            visibility_profiler.toggle();
+            gpu_profiler.toggle();
+        }
+        if input_map.just_pressed("toggle_stats") {
+            stats_hud.toggle();
+        }
+        if input_map.just_pressed("toggle_pixel_inspector") {
+            pixel_inspector.enabled = !pixel_inspector.enabled;
+        }
+        if input_map.just_pressed("screenshot") {
+            screenshot_requested = true;
+        }
+        if input_map.just_pressed("save_scene") {
+            let file = scene::serde::SceneFile::from_entities(&entities);
+            match file.save(SCENE_FILE.as_ref()) {
+                Ok(()) => status_line.show(format!("Saved scene to {}", SCENE_FILE)),
+                Err(e) => status_line.show_error(format!(
+                    "Failed to save scene: {}",
+                    debug::failure_to_line(e.into())
+                )),
+            }
+        }
+        if input_map.just_pressed("load_scene") {
+            let registry = scene_registry(&res, &gl, &debug_lines, &scene, &physics);
+            let loaded = scene::serde::SceneFile::load(SCENE_FILE.as_ref())
+                .and_then(|file| registry.load_into(&file, &mut entities));
+            match loaded {
+                Ok(handles) => {
+                    dice_handles = handles;
+                    status_line.show(format!("Loaded scene from {}", SCENE_FILE));
                 }
-                sdl2::event::Event::KeyDown {
-                    scancode: Some(sdl2::keyboard::Scancode::I),
-                    ..
-                } => {
-                    debug_lines.toggle();
+                Err(e) => status_line.show_error(format!(
+                    "Failed to load scene: {}",
+                    debug::failure_to_line(e.into())
+                )),
+            }
+            // recorded commands refer to the replaced selectables
+            undo_stack.clear();
+            // maps of the replaced entities are no longer used
+            stats_hud.add_evicted_textures(render_gl::TextureCache::evict_unused());
+        }
+        if input_map.just_pressed("toggle_recording") {
+            frame_recorder.toggle()?;
+        }
+        let mut camera_path_changed = false;
+        if input_map.just_pressed("add_camera_keyframe") {
+            camera.set_kind(camera::CameraKind::Fly);
+            camera_path.add_keyframe(&camera.fly);
+            camera_path_changed = true;
+        }
+        if input_map.just_pressed("clear_camera_path") {
+            camera_path.clear();
+            camera_path_changed = true;
+        }
+        if input_map.just_pressed("camera_path_longer") {
+            camera_path.duration *= 1.25;
+            camera_path_changed = true;
+        }
+        if input_map.just_pressed("camera_path_shorter") {
+            camera_path.duration /= 1.25;
+            camera_path_changed = true;
+        }
+        if camera_path_changed {
+            match camera_path.save(&options_res, CAMERA_PATH_FILE) {
+                Ok(()) => status_line.show(format!(
+                    "Camera path: {} keyframes over {:.1} s",
+                    camera_path.keyframes.len(),
+                    camera_path.duration
+                )),
+                Err(e) => status_line.show_error(format!(
+                    "Failed to save camera path: {}",
+                    debug::failure_to_line(e.into())
+                )),
+            }
+        }
+        if input_map.just_pressed("play_camera_path") {
+            if camera_path.is_playing() {
+                camera_path.stop();
+                status_line.show("Camera path stopped");
+            } else {
+                camera_path.play();
+                if camera_path.is_playing() {
+                    status_line.show(format!(
+                        "Playing camera path over {:.1} s",
+                        camera_path.duration
+                    ));
+                } else {
+                    status_line.show_error("Camera path needs at least two keyframes");
                 }
-                sdl2::event::Event::KeyDown {
-                    scancode: Some(sdl2::keyboard::Scancode::P),
-                    ..
-                } => {
-                    frame_profiler.toggle();
-                    allocation_profiler.toggle();
-                    gl_call_profiler.toggle();
+            }
+        }
+        if input_map.just_pressed("cycle_msaa") {
+            msaa.cycle_samples();
+            graphics_options.msaa_samples = msaa.samples();
+        }
+        if input_map.just_pressed("cycle_material_view") {
+            material_debug_view = material_debug_view.next();
+        }
+        if input_map.just_pressed("cycle_render_mode") {
+            debug_render_mode = debug_render_mode.next();
+        }
+        if input_map.just_pressed("cycle_texture_filter") {
+            graphics_options.texture_filter = graphics_options.texture_filter.next();
+        }
+        if input_map.just_pressed("particle_rate_down") {
+            particle_emitter.update(|settings| settings.rate /= 1.5);
+        }
+        if input_map.just_pressed("particle_rate_up") {
+            particle_emitter.update(|settings| settings.rate *= 1.5);
+        }
+        if input_map.just_pressed("particle_spread_down") {
+            particle_emitter.update(|settings| settings.velocity_spread /= 1.5);
+        }
+        if input_map.just_pressed("particle_spread_up") {
+            particle_emitter.update(|settings| settings.velocity_spread *= 1.5);
+        }
+        if input_map.just_pressed("toggle_occlusion_culling") {
+            if entities.occlusion_culling_enabled() {
+                entities.disable_occlusion_culling();
+            } else {
+                entities.enable_occlusion_culling(&gl, &res)?;
+            }
+        }
+        if input_map.just_pressed("toggle_bloom") {
+            if let Some(bloom) = post_process.effect_mut::<render_gl::Bloom>() {
+                bloom.toggle();
+            }
+        }
+        if input_map.just_pressed("cycle_tone_mapper") {
+            if let Some(tone_mapping) = post_process.effect_mut::<render_gl::ToneMapping>() {
+                tone_mapping.tone_mapper = tone_mapping.tone_mapper.next();
+            }
+        }
+        if input_map.just_pressed("toggle_reflections") {
+            reflections = !reflections;
+        }
+        if input_map.just_pressed("toggle_parallax") {
+            parallax_mapping = !parallax_mapping;
+        }
+        if input_map.just_pressed("toggle_gpu_picking") {
+            gpu_picking = !gpu_picking;
+            if !gpu_picking {
+                selectables.clear_picked_id();
+            }
+        }
+        if input_map.just_pressed("toggle_selection_bvh") {
+            render_selectables.toggle_bvh();
+        }
+        if input_map.just_pressed("delete_selected") {
+            let owners: Vec<_> = selectables
+                .selection()
+                .handles()
+                .iter()
+                .filter_map(|&handle| entities.find_by_selectable(handle))
+                .collect();
+            let mut removed = Vec::new();
+            for owner in owners {
+                removed.extend(entities.despawn(owner));
+            }
+            if !removed.is_empty() {
+                dice_handles.retain(|handle| !removed.contains(handle));
+                decals.retain(|handle| !removed.contains(handle));
+                // selectable handles of recorded commands may be reused by new objects
+                undo_stack.clear();
+            }
+        }
+        if input_map.just_pressed("throw_dice") {
+            physics.throw_all(na::Vector3::new(0.0, 0.0, 6.0), 10.0);
+        }
+        if input_map.just_pressed("stamp_decal") {
+            let stamped = stamp_decal(
+                &res,
+                &gl,
+                &scene,
+                &selectables,
+                &entities,
+                &splat_texture,
+                stamped_count,
+            )?;
+            if let Some(decal) = stamped {
+                stamped_count += 1;
+                decals.push_back(entities.add(decal));
+                if decals.len() > MAX_DECALS {
+                    if let Some(oldest) = decals.pop_front() {
+                        entities.remove(oldest);
+                    }
                 }
-                _ => (),
             }
         }
+        if input_map.just_pressed("exposure_up") {
+            if let Some(tone_mapping) = post_process.effect_mut::<render_gl::ToneMapping>() {
+                tone_mapping.exposure *= 1.25;
+            }
+        }
+        if input_map.just_pressed("exposure_down") {
+            if let Some(tone_mapping) = post_process.effect_mut::<render_gl::ToneMapping>() {
+                tone_mapping.exposure /= 1.25;
+            }
+        }
+        system::input::camera::handle_camera_input(&input_map, &mut camera);
 
         frame_profiler.push(render::color_white());
 
-        let delta = time.elapsed().as_fractional_secs() as f32;
-        time = Instant::now();
-        if camera.update(delta) {
-            camera_target_marker.update_position(camera.target);
+        let updates = match replayed_frame_time {
+            Some(frame_time) => game_loop.advance(frame_time),
+            None => game_loop.begin_frame(),
+        };
+        if let Some(ref mut recorder) = input_recorder {
+            recorder.record_frame_time(game_loop.frame_duration());
         }
+        let delta = game_loop.frame_time();
+        status_line.update(delta);
+        let camera_moved = if camera_path.is_playing() {
+            camera.set_kind(camera::CameraKind::Fly);
+            camera_path.update(delta, &mut camera.fly);
+            true
+        } else {
+            camera.update(delta)
+        };
+        if camera_moved {
+            camera_target_marker.update_position(camera.target());
+        }
+        input_selectables.handle_selectable_input(&input_map, &selectables, delta);
         input_selectables.update(&camera, &selectables);
-        for dice in &mut dices {
-            dice.update(delta);
+        let finished_drags = selectables.drain_finished_drags();
+        #[cfg(feature = "audio")]
+        {
+            if let Some(ref mut sounds) = selection_sounds {
+                sounds.update(
+                    &camera.get_inverse_view_matrix(),
+                    &scene,
+                    &entities,
+                    &selectables,
+                    &finished_drags,
+                );
+            }
+        }
+        if let Some(command) = undo::TransformCommand::new(finished_drags) {
+            undo_stack.push(Box::new(command));
+        }
+        if input_map.pressed("edit_modifier") {
+            let ctx = undo::UndoContext {
+                selectables: &selectables,
+            };
+            if input_map.just_pressed("undo") {
+                if let Some(name) = undo_stack.undo(&ctx) {
+                    status_line.show(format!("Undo {}", name));
+                }
+            }
+            if input_map.just_pressed("redo") {
+                if let Some(name) = undo_stack.redo(&ctx) {
+                    status_line.show(format!("Redo {}", name));
+                }
+            }
+        }
+
+        ui.begin_frame(&debug_text);
+        let mut sky_changed = false;
+        ui.panel("Lights", 10.0, 80.0, |ui| {
+            let mut changed = false;
+            if let Some(lights::Light::Directional(ref mut light)) = lights.get_mut(sun) {
+                changed |= ui.slider("sun intensity", &mut light.intensity, 0.0, 4.0);
+                changed |= ui.drag_vector3("sun direction", &mut light.direction, 0.01);
+            }
+            if let Some(ref mut clusters) = light_clusters {
+                ui.checkbox("clustered lights", &mut clusters.enabled);
+                ui.label(&format!(
+                    "{} lights, up to {} per cluster",
+                    clusters.light_count(),
+                    clusters.max_cluster_lights()
+                ));
+                if ui.button("add 100 point lights") {
+                    add_point_lights(&mut lights, 100);
+                    changed = true;
+                }
+            }
+            if changed {
+                light_uniforms.update(&lights.uniforms());
+                light_markers.update(&lights);
+                if let Some(sun) = lights.shadow_caster() {
+                    shadow_map.set_directional_light(&sun.direction, &na::Point3::origin(), 40.0);
+                }
+                sky_changed = sky_enabled;
+            }
+        });
+        ui.panel("Graphics", 310.0, 80.0, |ui| {
+            let options = &mut graphics_options;
+            if ui.button(&format!("msaa: {}x", options.msaa_samples)) {
+                msaa.cycle_samples();
+                options.msaa_samples = msaa.samples();
+            }
+            if ui.button(&format!("filter: {:?}", options.texture_filter)) {
+                options.texture_filter = options.texture_filter.next();
+            }
+            ui.slider("anisotropy", &mut options.anisotropy, 1.0, 16.0);
+            if ui.button(&format!("shadows: {}", options.shadow_resolution)) {
+                options.shadow_resolution = options.next_shadow_resolution();
+            }
+            let cascades = if shadow_map.show_cascades { "tinted" } else { "hidden" };
+            if ui.button(&format!("cascades: {}", cascades)) {
+                shadow_map.show_cascades = !shadow_map.show_cascades;
+            }
+            if let Some(ref mut deferred_renderer) = deferred_renderer {
+                let ssao = deferred_renderer.ssao_mut();
+                if ui.button(&format!("ssao: {}", if ssao.enabled { "on" } else { "off" })) {
+                    ssao.toggle();
+                }
+                ui.slider("ssao radius", &mut ssao.radius, 0.05, 2.0);
+                ui.slider("ssao intensity", &mut ssao.intensity, 0.0, 4.0);
+            }
+            if ui.button(&format!("vsync: {:?}", options.vsync)) {
+                options.vsync = options.vsync.next();
+            }
+        });
+        if graphics_options != applied_options {
+            msaa.set_samples(graphics_options.msaa_samples);
+            graphics_options.msaa_samples = msaa.samples();
+            if graphics_options.vsync != applied_options.vsync {
+                graphics_options.vsync = platform.set_vsync(graphics_options.vsync);
+            }
+            if graphics_options.shadow_resolution != applied_options.shadow_resolution {
+                shadow_map.set_size(graphics_options.shadow_resolution)?;
+            }
+            if graphics_options.texture_filter != applied_options.texture_filter
+                || graphics_options.anisotropy != applied_options.anisotropy
+            {
+                texture_sampler = graphics_options
+                    .texture_filter
+                    .build_with_anisotropy(&gl, graphics_options.anisotropy);
+            }
+            if let Err(e) = graphics_options.save(&options_res, GRAPHICS_OPTIONS_FILE) {
+                println!("Failed to save graphics options: {}", e);
+            }
+            applied_options = graphics_options.clone();
+        }
+        let previous_fog = fog;
+        let previous_passes = frame_passes;
+        ui.panel("Atmosphere", 610.0, 80.0, |ui| {
+            ui.drag_vector3("background", &mut frame_passes.background, 0.01);
+            if ui.button(&format!("fog: {:?}", fog.mode)) {
+                fog.mode = fog.mode.next();
+            }
+            if fog.mode == render::FogMode::Linear {
+                ui.slider("fog start", &mut fog.start, 0.0, 100.0);
+                ui.slider("fog end", &mut fog.end, 0.0, 200.0);
+            } else {
+                ui.slider("fog density", &mut fog.density, 0.0, 0.1);
+            }
+            sky_changed |= ui.checkbox("sky model", &mut sky_enabled);
+            if sky_enabled {
+                sky_changed |= ui.slider("turbidity", &mut sky_model.turbidity, 2.0, 10.0);
+                sky_changed |= ui.slider("sky exposure", &mut sky_model.exposure, 0.01, 0.5);
+            }
+        });
+        if sky_changed {
+            if sky_enabled {
+                if let Some(sun) = lights.shadow_caster() {
+                    sky_model = sky_model.with_light_direction(&sun.direction);
+                }
+                fog.color = sky_model.horizon_color();
+                skybox.set_sky(&gl, Some(&sky_model));
+            } else {
+                skybox.set_sky(&gl, None);
+            }
+            pbr_environment.update(skybox.texture());
+        }
+        if fog != previous_fog {
+            fog_uniforms.update(&fog.uniforms());
+        }
+        if frame_passes != previous_passes {
+            // scene targets are cleared with the current clear color
+            color_buffer.set_clear_color(&gl, frame_passes.background);
+        }
+        let mut occlusion_culling = entities.occlusion_culling_enabled();
+        let previous_morph_weight = morph_weight;
+        ui.panel("Debug", 10.0, 200.0, |ui| {
+            ui.checkbox("reflections", &mut reflections);
+            ui.checkbox("parallax mapping", &mut parallax_mapping);
+            if ui.checkbox("gpu picking", &mut gpu_picking) && !gpu_picking {
+                selectables.clear_picked_id();
+            }
+            ui.checkbox("side camera", &mut side_cam);
+            ui.checkbox("occlusion culling", &mut occlusion_culling);
+            if ui.checkbox("frame cap", &mut frame_cap) {
+                game_loop.set_frame_cap(if frame_cap { Some(80) } else { None });
+            }
+            ui.slider("morph weight", &mut morph_weight, 0.0, 1.0);
+        });
+        if morph_weight != previous_morph_weight {
+            // every target of models with blend shapes, others ignore the weights
+            let weights = [morph_weight; mesh::MAX_MORPH_TARGETS];
+            for &handle in &dice_handles {
+                if let Some(dice) = entities.get_mut(handle) {
+                    dice.set_morph_weights(&weights);
+                }
+            }
+        }
+        if occlusion_culling != entities.occlusion_culling_enabled() {
+            if occlusion_culling {
+                entities.enable_occlusion_culling(&gl, &res)?;
+            } else {
+                entities.disable_occlusion_culling();
+            }
+        }
+        if let Some((handle, container)) = selectables.get_selected_aabb() {
+            let mut edited = false;
+            ui.panel("Transform", 10.0, 380.0, |ui| {
+                let mut isometry = container.isometry;
+                let (roll, pitch, yaw) = isometry.rotation.euler_angles();
+                let mut angles = na::Vector3::new(roll, pitch, yaw).map(f32::to_degrees);
+                let mut scale = container.scale;
+
+                let moved = ui.drag_vector3("position", &mut isometry.translation.vector, 0.02);
+                if ui.drag_vector3("rotation", &mut angles, 0.5) || moved {
+                    let radians = angles.map(f32::to_radians);
+                    isometry.rotation =
+                        na::UnitQuaternion::from_euler_angles(radians.x, radians.y, radians.z);
+                    selectables.queue_action(
+                        handle,
+                        selection::Action::Drag {
+                            new_isometry: isometry,
+                        },
+                    );
+                    edited = true;
+                }
+                if ui.drag_vector3("scale", &mut scale, 0.01) {
+                    selectables.queue_action(handle, selection::Action::Scale { new_scale: scale });
+                    edited = true;
+                }
+            });
+            if edited && panel_edit.is_none() {
+                panel_edit = Some((handle, container.isometry, container.scale));
+            }
+        }
+        // a panel edit is recorded for undo once its field is released, like a drag
+        if !ui.is_dragging() {
+            if let Some((handle, old_isometry, old_scale)) = panel_edit.take() {
+                if let Some((new_isometry, new_scale)) = selectables.target_transform(handle) {
+                    let change = selection::TransformChange {
+                        handle,
+                        old_isometry,
+                        old_scale,
+                        new_isometry,
+                        new_scale,
+                    };
+                    if let Some(command) = undo::TransformCommand::new(vec![change]) {
+                        undo_stack.push(Box::new(command));
+                    }
+                }
+            }
         }
-        render_selectables.update(&selectables, &editor_lines);
+
+        uploads.begin_frame();
+        for _ in 0..updates {
+            physics.step(game_loop.timestep());
+            entities.update(&entity::UpdateContext {
+                gl: &gl,
+                res: &res,
+                uploads: &uploads,
+                selectables: &selectables,
+                camera_pos: camera.project_pos(),
+                delta: game_loop.timestep(),
+            });
+        }
+        dice_instances.update(&gl, &res);
+        entities.interpolate(game_loop.alpha());
+        render_selectables.update(&selectables, &editor_lines, &gizmo_lines);
+        render_selectables.update_selection_rect(
+            input_selectables.selection_rect(&selectables),
+            &(camera.get_inverse_view_matrix() * camera.get_inverse_p_matrix()),
+            &gizmo_lines,
+        );
 
         frame_profiler.push(render::color_yellow());
 
-        unsafe {
-            gl.Enable(gl::CULL_FACE);
-            gl.Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
-            gl.Enable(gl::DEPTH_TEST);
+        render_gl::RasterState::default().apply(&gl);
+        render_gl::begin_pass(&gl, &frame_passes.window()).end_pass();
+        if deferred_renderer.is_none() {
+            let _zone = gpu_profiler.scope("shadows");
+            let _group = render_gl::debug::group(&gl, "shadows");
+            shadow_map.fit_to_view(
+                &camera.get_view_matrix(),
+                &camera.get_p_matrix(),
+                shadow_distance,
+            );
+            for cascade in 0..shadow_map.cascade_count() {
+                let shadow_pass = shadow_map.begin(cascade);
+                entities.render_depth(&gl, &shadow_pass);
+                dice_instances.render_depth(&shadow_pass);
+            }
+        }
+        if shadow_map.show_cascades {
+            let matrices = shadow_map.light_space_matrices();
+            shadow_frusta.truncate(matrices.len());
+            for (index, matrix) in matrices.iter().enumerate() {
+                match shadow_frusta.get(index) {
+                    Some(marker) => marker.update_matrix(matrix, shadow_frustum_color),
+                    None => shadow_frusta
+                        .push(debug_lines.frustum_marker(matrix, shadow_frustum_color)),
+                }
+            }
+        } else {
+            shadow_frusta.clear();
+        }
+
+        if reflections && deferred_renderer.is_none() {
+            let _group = render_gl::debug::group(&gl, "reflection probe");
+            let reflection_dice = dice_handles.get(24).cloned();
+            let bounds = reflection_dice
+                .and_then(|handle| entities.get(handle))
+                .and_then(|dice| dice.bounds());
+            if let Some((aabb, model_matrix)) = bounds {
+                reflection_probe.set_position(model_matrix.transform_point(&aabb.center()));
+            }
+
+            let probe_projection = reflection_probe.projection();
+            light_uniforms.bind_base(render::LIGHT_UNIFORMS_BINDING);
+            fog_uniforms.bind_base(render::FOG_UNIFORMS_BINDING);
+            if let Some(ref clusters) = light_clusters {
+                clusters.bind_disabled();
+            }
+            reflection_probe.render(&gl, |face| {
+                camera_uniforms.update(&render::CameraUniforms {
+                    view_projection: face.view_projection,
+                    camera_pos: face.position.coords,
+                    debug_view: material_debug_view.shader_index(),
+                    clip_plane: na::Vector4::zeros(),
+                });
+                camera_uniforms.bind_base(render::CAMERA_UNIFORMS_BINDING);
+                let frustum = render_gl::Frustum::from_matrix(&face.view_projection);
+                let probe_ctx = entity::RenderContext {
+                    gl: &gl,
+                    shadow_map: &shadow_map,
+                    environment: &pbr_environment,
+                    projection: probe_projection,
+                    camera_pos: face.position,
+                    frustum: &frustum,
+                    texture_sampler: None,
+                    reflection: None,
+                    parallax_mapping,
+                };
+                entities.render_excluding(&probe_ctx, &frustum, reflection_dice);
+                skybox.render(&gl);
+            });
         }
+
         let vp_matrix = if side_cam {
             camera.get_p_matrix() * na::Matrix4::look_at_rh(
                 &na::Point3::new(-2.0, -2.0, 2.0),
@@ -215,22 +1246,184 @@ fn run() -> Result<(), failure::Error> {
             camera.get_vp_matrix()
         };
 
+        // water is drawn by forward rendering only
+        if deferred_renderer.is_none() && debug_render_mode.draws_surfaces() {
+            let _group = render_gl::debug::group(&gl, "water");
+            light_uniforms.bind_base(render::LIGHT_UNIFORMS_BINDING);
+            fog_uniforms.bind_base(render::FOG_UNIFORMS_BINDING);
+            if let Some(ref clusters) = light_clusters {
+                clusters.bind_disabled();
+            }
+            let camera_pos = camera.project_pos();
+            water_reflection
+                .borrow()
+                .render(&gl, &vp_matrix, &camera_pos, |pass| {
+                    camera_uniforms.update(&render::CameraUniforms {
+                        view_projection: pass.view_projection,
+                        camera_pos: pass.camera_pos.coords,
+                        debug_view: material_debug_view.shader_index(),
+                        clip_plane: pass.clip_plane,
+                    });
+                    camera_uniforms.bind_base(render::CAMERA_UNIFORMS_BINDING);
+                    let frustum = render_gl::Frustum::from_matrix(&pass.view_projection);
+                    let water_ctx = entity::RenderContext {
+                        gl: &gl,
+                        shadow_map: &shadow_map,
+                        environment: &pbr_environment,
+                        projection: camera.get_p_matrix(),
+                        camera_pos: pass.camera_pos,
+                        frustum: &frustum,
+                        texture_sampler: texture_sampler.as_ref(),
+                        reflection: None,
+                        parallax_mapping,
+                    };
+                    entities.render_excluding(&water_ctx, &frustum, Some(water_handle));
+                    if pass.kind == render_gl::PlanarPassKind::Reflection {
+                        skybox.render(&gl);
+                    }
+                });
+        }
+
+        camera_uniforms.update(&render::CameraUniforms {
+            view_projection: vp_matrix,
+            camera_pos: camera.project_pos().coords,
+            debug_view: material_debug_view.shader_index(),
+            clip_plane: na::Vector4::zeros(),
+        });
+        camera_uniforms.bind_base(render::CAMERA_UNIFORMS_BINDING);
+        light_uniforms.bind_base(render::LIGHT_UNIFORMS_BINDING);
+        fog_uniforms.bind_base(render::FOG_UNIFORMS_BINDING);
+        if let Some(ref mut clusters) = light_clusters {
+            // the side camera looks from elsewhere, so the clusters would not match
+            if side_cam {
+                clusters.bind_disabled();
+            } else {
+                clusters.update(
+                    &lights,
+                    &camera.get_view_matrix(),
+                    &camera.get_p_matrix(),
+                    viewport.w,
+                    viewport.h,
+                );
+                clusters.bind();
+            }
+        }
+
+        if gpu_picking {
+            let _group = render_gl::debug::group(&gl, "object ids");
+            {
+                let id_pass = id_buffer.begin(&vp_matrix);
+                entities.render_id(&gl, &id_pass);
+            }
+            if let Some((x, y)) = cursor_pixel {
+                id_buffer.request(x, y);
+            }
+            if let Some(id) = id_buffer.poll() {
+                selectables.set_picked_id(id);
+            }
+        }
+
+        let scene_pass = post_process.begin(viewport.w, viewport.h)?;
+
+        // deferred lighting blits g-buffer depth, which needs a single-sampled target
+        let msaa_pass = if deferred_renderer.is_none() {
+            Some(msaa.begin(viewport.w, viewport.h)?)
+        } else {
+            None
+        };
+
         color_buffer.clear(&gl);
 
         frame_profiler.push(render::color_white());
 
-        for dice in &mut dices {
-            dice.render(&gl, &vp_matrix, &camera.project_pos().coords);
+        let scene_zone = gpu_profiler.scope("dice");
+        let scene_group = render_gl::debug::group(&gl, "scene");
+
+        let frustum = render_gl::Frustum::from_matrix(&vp_matrix);
+        let render_ctx = entity::RenderContext {
+            gl: &gl,
+            shadow_map: &shadow_map,
+            environment: &pbr_environment,
+            projection: camera.get_p_matrix(),
+            camera_pos: camera.project_pos(),
+            frustum: &frustum,
+            texture_sampler: texture_sampler.as_ref(),
+            reflection: if reflections {
+                Some(&reflection_probe)
+            } else {
+                None
+            },
+            parallax_mapping,
+        };
+        entities.update_bounds_lines(&debug_lines, debug_render_mode);
+        let render_stats = if !debug_render_mode.draws_surfaces() {
+            entity::RenderStats::default()
+        } else if let Some(ref deferred_renderer) = deferred_renderer {
+            let stats = {
+                let geometry_pass = deferred_renderer.geometry_pass();
+                let _debug_render_scope = debug_render_mode.begin(&gl);
+                let stats = entities.render_geometry(&gl, &geometry_pass, &frustum);
+                dice_instances.render_geometry(&gl, &geometry_pass);
+                stats
+            };
+            deferred_renderer.lighting_pass();
+            stats
+        } else {
+            let _debug_render_scope = debug_render_mode.begin(&gl);
+            let stats = entities.render(&render_ctx, &frustum);
+            dice_instances.render(&shadow_map, parallax_mapping);
+            stats
+        };
+        skybox.render(&gl);
+        if debug_render_mode.draws_surfaces() {
+            let _group = render_gl::debug::group(&gl, "blended");
+            entities.render_blended(&render_ctx, &frustum);
         }
+        drop(scene_group);
+        drop(scene_zone);
 
         frame_profiler.push(render::color_red());
 
+        let debug_lines_zone = gpu_profiler.scope("debug lines");
+        let debug_lines_group = render_gl::debug::group(&gl, "debug lines");
         debug_lines.render(&gl, &color_buffer, &vp_matrix);
 
         frame_profiler.push(render::color_white());
 
         editor_lines.render(&gl, &color_buffer, &vp_matrix);
 
+        {
+            let _group = render_gl::debug::group(&gl, "outlines");
+            outline.render(&vp_matrix, |pass| entities.render_outlines(&gl, pass));
+        }
+
+        let overlay_pass = render_gl::begin_pass(&gl, &frame_passes.overlay());
+        gizmo_lines.render(&gl, &color_buffer, &vp_matrix);
+        overlay_pass.end_pass();
+        drop(debug_lines_group);
+        drop(msaa_pass);
+        drop(scene_pass);
+        if let Some((x, y)) = cursor_pixel {
+            let scene = post_process.scene();
+            let mut sources = vec![render_gl::PixelSource::color("color", scene, 0)];
+            if let Some(ref deferred_renderer) = deferred_renderer {
+                let gbuffer = deferred_renderer.gbuffer();
+                sources.push(render_gl::PixelSource::color("albedo", gbuffer, 0));
+                sources.push(render_gl::PixelSource::color("normal", gbuffer, 1));
+                sources.push(render_gl::PixelSource::color("position", gbuffer, 2));
+                sources.push(render_gl::PixelSource::depth("depth", gbuffer));
+            } else if msaa.samples() == 0 {
+                // multisampled depth is not resolved into the scene target
+                sources.push(render_gl::PixelSource::depth("depth", scene));
+            }
+            pixel_inspector.request(x, y, &sources);
+        }
+        {
+            let _group = render_gl::debug::group(&gl, "post process");
+            post_process.render()?;
+        }
+        drop(debug_lines_zone);
+
         frame_profiler.push(render::color_gray());
 
         let left = 0;
@@ -247,6 +1440,8 @@ fn run() -> Result<(), failure::Error> {
             10.0,
         );
 
+        let ui_zone = gpu_profiler.scope("ui");
+        let ui_pass = render_gl::begin_pass(&gl, &frame_passes.ui());
         frame_profiler.render(
             &gl,
             &color_buffer,
@@ -256,13 +1451,138 @@ fn run() -> Result<(), failure::Error> {
         );
         allocation_profiler.render(&gl, &color_buffer, &ui_matrix, window_size.highdpi_width);
         gl_call_profiler.render(&gl, &color_buffer, &ui_matrix, window_size.highdpi_width);
+        visibility_profiler.render(&gl, &color_buffer, &ui_matrix, window_size.highdpi_width);
+        ui_pass.end_pass();
+        drop(ui_zone);
+        gpu_profiler.render(&gl, &color_buffer, &ui_matrix, window_size.highdpi_height);
+        gpu_profiler.draw_labels(&debug_text);
+
+        debug_text.draw_2d(
+            window_size.highdpi_width as f32 - 120.0,
+            2.0,
+            &format!("fps: {:.0}", 1.0 / delta),
+        );
+        debug_text.draw_2d(
+            window_size.highdpi_width as f32 - 120.0,
+            2.0 + debug_text.line_height(),
+            &format!("msaa: {}x", msaa.samples()),
+        );
+        if material_debug_view != render::MaterialDebugView::Shaded {
+            debug_text.draw_2d(
+                2.0,
+                window_size.highdpi_height as f32 - debug_text.line_height(),
+                &format!("view: {:?}", material_debug_view),
+            );
+        }
+        if debug_render_mode != render_gl::DebugRenderMode::Solid {
+            debug_text.draw_2d(
+                2.0,
+                window_size.highdpi_height as f32 - debug_text.line_height() * 2.0,
+                &format!("render: {:?}", debug_render_mode),
+            );
+        }
+        if let Some(ref sampler) = texture_sampler {
+            debug_text.draw_2d(
+                2.0,
+                window_size.highdpi_height as f32 - debug_text.line_height() * 3.0,
+                &format!(
+                    "filter: {:?} x{}",
+                    graphics_options.texture_filter,
+                    sampler.anisotropy()
+                ),
+            );
+        }
+        if let Some(tone_mapping) = post_process.effect_mut::<render_gl::ToneMapping>() {
+            debug_text.draw_2d(
+                window_size.highdpi_width as f32 - 120.0,
+                2.0 + debug_text.line_height() * 2.0,
+                &format!("{:?} x{:.2}", tone_mapping.tone_mapper, tone_mapping.exposure),
+            );
+        }
+        debug_text.draw_2d(
+            window_size.highdpi_width as f32 - 120.0,
+            2.0 + debug_text.line_height() * 3.0,
+            &format!("particles: {:?}", particle_simulation),
+        );
+        stats_hud.draw(
+            &debug_text,
+            window_size.highdpi_width as f32 - 320.0,
+            2.0 + debug_text.line_height() * 4.0,
+        );
+        error_console.draw(
+            &debug_text,
+            window_size.highdpi_width as f32 * 0.25,
+            2.0,
+        );
+        status_line.draw(
+            &debug_text,
+            2.0,
+            window_size.highdpi_height as f32 - debug_text.line_height() * 4.0,
+        );
+        if pixel_inspector.enabled {
+            if let (Some((x, y)), Some(sample)) = (cursor_pixel, pixel_inspector.poll()) {
+                for (index, line) in sample.lines().iter().enumerate() {
+                    debug_text.draw_2d(
+                        x as f32 + 16.0,
+                        y as f32 + 16.0 + debug_text.line_height() * index as f32,
+                        line,
+                    );
+                }
+            }
+        }
+        for (index, dice) in dice_handles
+            .iter()
+            .filter_map(|&handle| entities.get(handle))
+            .enumerate()
+        {
+            if let Some(node) = dice.node() {
+                let pos = node.world_transform() * na::Point3::new(0.0, 0.0, 2.5);
+                match dice.lod_level() {
+                    Some(lod) => debug_text.draw_3d(pos, &format!("dice {} lod {}", index, lod)),
+                    None => debug_text.draw_3d(pos, &format!("dice {}", index)),
+                }
+            }
+        }
+        ui_renderer.render(
+            &gl,
+            &color_buffer,
+            &ui,
+            &debug_text,
+            &ui_matrix,
+            window_size.highdpi_height,
+        );
+        ui.end_frame();
+        debug_text.render(
+            &gl,
+            &color_buffer,
+            &ui_matrix,
+            &vp_matrix,
+            window_size.highdpi_width,
+            window_size.highdpi_height,
+        );
 
         frame_profiler.push(render::color_green());
 
-        while time.elapsed() < Duration::from_millis(12) {
-            ::std::thread::yield_now()
+        let gpu_results = gpu_profiler.results();
+        let gpu_ms = if gpu_results.is_empty() {
+            None
+        } else {
+            Some(gpu_results.iter().map(|&(_, ms)| ms).sum())
+        };
+        stats_hud.end_frame(delta * 1000.0, gpu_ms);
+
+        if screenshot_requested {
+            screenshot_requested = false;
+            render_gl::capture::capture_frame(&gl, "screenshot.png")?;
+        }
+        frame_recorder.capture()?;
+        if input_replay.as_ref().map_or(false, input::InputReplay::is_finished) {
+            render_gl::capture::capture_frame(&gl, REPLAY_SCREENSHOT_FILE)?;
+            break 'main;
         }
 
+        game_loop.end_frame();
+
         if let Some(values) = PeekAlloc::peek() {
             if values.alloc_num > 0 {
                 allocation_profiler.push(values.alloc_num, render::color_white());
@@ -277,12 +1597,31 @@ fn run() -> Result<(), failure::Error> {
             gl_call_profiler.push(gl_errors, render::color_red());
         }
 
+        if render_stats.visible > 0 {
+            visibility_profiler.push(render_stats.visible, render::color_green());
+        }
+        if render_stats.culled > 0 {
+            visibility_profiler.push(render_stats.culled, render::color_red());
+        }
+        if render_stats.occluded > 0 {
+            visibility_profiler.push(render_stats.occluded, render::color_yellow());
+        }
+
         let gl_calls = gl_watch::calls();
         if gl_calls > 0 {
             gl_call_profiler.push(gl_calls, render::color_light_blue());
         }
+        let elided_calls = render_gl::StateCache::take_elided_count();
+        if elided_calls > 0 {
+            gl_call_profiler.push(elided_calls, render::color_green());
+        }
+
+        platform.swap_buffers();
+    }
 
-        window.gl_swap_window();
+    if let Some(recorder) = input_recorder {
+        recorder.save(&options_res, INPUT_RECORDING_FILE)?;
+        println!("Recorded {} frames of input", recorder.frame_count());
     }
 
     Ok(())
@@ -0,0 +1,169 @@
+use nalgebra as na;
+use ncollide3d::bounding_volume::aabb::AABB;
+use ncollide3d::bounding_volume::{BoundingSphere, BoundingVolume};
+use super::{Mesh, Vertex, JOB_CHUNK};
+use crate::jobs;
+
+impl Mesh {
+    /// Bounds of the vertex positions, computed in parallel for large meshes.
+    pub fn aabb(&self) -> Option<AABB<f32>> {
+        jobs::map_chunks(&self.vertices, JOB_CHUNK, |vertices| {
+            points_aabb(vertices.iter().map(|v| v.pos))
+        }).into_iter()
+            .fold(None, |mut bounds, aabb| {
+                if let Some(aabb) = aabb {
+                    merge_aabb(&mut bounds, &aabb);
+                }
+                bounds
+            })
+    }
+
+    /// Sphere around the vertex positions, centered on the box around them.
+    ///
+    /// Not the smallest sphere, but at most the half diagonal of the box. `None` if there
+    /// are no vertices.
+    pub fn bounding_sphere(&self) -> Option<BoundingSphere<f32>> {
+        let center = self.aabb()?.center();
+        let radius = jobs::map_chunks(&self.vertices, JOB_CHUNK, |vertices: &[Vertex]| {
+            vertices
+                .iter()
+                .map(|v| na::distance_squared(&center, &v.pos))
+                .fold(0.0, f32::max)
+        }).into_iter()
+            .fold(0.0, f32::max)
+            .sqrt();
+        Some(BoundingSphere::new(center, radius))
+    }
+}
+
+/// Box around the points, `None` if there are none.
+pub fn points_aabb<I>(points: I) -> Option<AABB<f32>>
+where
+    I: IntoIterator<Item = na::Point3<f32>>,
+{
+    let mut points = points.into_iter();
+    let first = points.next()?;
+    let (mins, maxs) = points.fold((first, first), |(mins, maxs), point| {
+        (
+            mins.coords.zip_map(&point.coords, f32::min).into(),
+            maxs.coords.zip_map(&point.coords, f32::max).into(),
+        )
+    });
+    Some(AABB::new(mins, maxs))
+}
+
+/// Grow the bounds to contain the box, or start them with it.
+pub fn merge_aabb(bounds: &mut Option<AABB<f32>>, other: &AABB<f32>) {
+    *bounds = Some(match bounds.take() {
+        Some(bounds) => bounds.merged(other),
+        None => other.clone(),
+    });
+}
+
+/// Box around the box moved by an affine matrix, i.e. a model or bone matrix.
+///
+/// The center is moved and the half extents are projected on the new axes, which gives the
+/// same box as the eight moved corners.
+pub fn transform_aabb(aabb: &AABB<f32>, matrix: &na::Matrix4<f32>) -> AABB<f32> {
+    let center = matrix.transform_point(&aabb.center());
+    let linear = matrix.fixed_slice::<na::U3, na::U3>(0, 0).abs();
+    let half_extents = linear * aabb.half_extents();
+    AABB::new(center - half_extents, center + half_extents)
+}
+
+/// Box around the box moved by an isometry, i.e. an entity transform.
+pub fn isometry_aabb(aabb: &AABB<f32>, isometry: &na::Isometry3<f32>) -> AABB<f32> {
+    transform_aabb(aabb, &isometry.to_homogeneous())
+}
+
+/// Box scaled along each axis, negative scales mirror it.
+pub fn scale_aabb(aabb: &AABB<f32>, scale: &na::Vector3<f32>) -> AABB<f32> {
+    let a = aabb.mins().coords.component_mul(scale);
+    let b = aabb.maxs().coords.component_mul(scale);
+    AABB::new(
+        a.zip_map(&b, f32::min).into(),
+        a.zip_map(&b, f32::max).into(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mesh::primitives;
+
+    fn assert_point_eq(a: &na::Point3<f32>, b: &na::Point3<f32>) {
+        assert!(na::distance(a, b) < 1e-5, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn cube_bounds() {
+        let mesh = primitives::cube(&na::Vector3::new(2.0, 4.0, 6.0));
+
+        let aabb = mesh.aabb().unwrap();
+        assert_point_eq(aabb.mins(), &na::Point3::new(-1.0, -2.0, -3.0));
+        assert_point_eq(aabb.maxs(), &na::Point3::new(1.0, 2.0, 3.0));
+
+        let sphere = mesh.bounding_sphere().unwrap();
+        assert_point_eq(sphere.center(), &na::Point3::origin());
+        assert!((sphere.radius() - 14.0f32.sqrt()).abs() < 1e-5);
+        for vertex in &mesh.vertices {
+            assert!(na::distance(sphere.center(), &vertex.pos) <= sphere.radius() + 1e-5);
+        }
+    }
+
+    #[test]
+    fn empty_mesh_has_no_bounds() {
+        let mut mesh = primitives::cube(&na::Vector3::repeat(1.0));
+        mesh.vertices.clear();
+        assert!(mesh.aabb().is_none());
+        assert!(mesh.bounding_sphere().is_none());
+        assert!(points_aabb(Vec::new()).is_none());
+    }
+
+    #[test]
+    fn merged_bounds_contain_both() {
+        let first = AABB::new(na::Point3::new(0.0, 0.0, 0.0), na::Point3::new(1.0, 1.0, 1.0));
+        let second = AABB::new(na::Point3::new(-1.0, 2.0, 0.5), na::Point3::new(0.0, 3.0, 0.5));
+        let mut bounds = None;
+        merge_aabb(&mut bounds, &first);
+        merge_aabb(&mut bounds, &second);
+        let bounds = bounds.unwrap();
+        assert_point_eq(bounds.mins(), &na::Point3::new(-1.0, 0.0, 0.0));
+        assert_point_eq(bounds.maxs(), &na::Point3::new(1.0, 3.0, 1.0));
+    }
+
+    #[test]
+    fn moved_bounds_match_moved_corners() {
+        let aabb = AABB::new(na::Point3::new(-1.0, -2.0, 0.0), na::Point3::new(3.0, 1.0, 2.0));
+        let isometry = na::Isometry3::new(
+            na::Vector3::new(5.0, -1.0, 2.0),
+            na::Vector3::new(0.3, -1.1, 0.7),
+        );
+        let (a, b) = (aabb.mins(), aabb.maxs());
+        let corners = (0..8).map(|index| {
+            isometry * na::Point3::new(
+                if index & 1 == 0 { a.x } else { b.x },
+                if index & 2 == 0 { a.y } else { b.y },
+                if index & 4 == 0 { a.z } else { b.z },
+            )
+        });
+        let expected = points_aabb(corners).unwrap();
+
+        let moved = isometry_aabb(&aabb, &isometry);
+        assert_point_eq(moved.mins(), expected.mins());
+        assert_point_eq(moved.maxs(), expected.maxs());
+
+        let matrix = na::Matrix4::new_nonuniform_scaling(&na::Vector3::new(2.0, 1.0, 0.5));
+        let scaled = transform_aabb(&aabb, &matrix);
+        assert_point_eq(scaled.mins(), &na::Point3::new(-2.0, -2.0, 0.0));
+        assert_point_eq(scaled.maxs(), &na::Point3::new(6.0, 1.0, 1.0));
+    }
+
+    #[test]
+    fn mirrored_scale_keeps_mins_below_maxs() {
+        let aabb = AABB::new(na::Point3::new(-1.0, 0.0, 1.0), na::Point3::new(2.0, 1.0, 3.0));
+        let scaled = scale_aabb(&aabb, &na::Vector3::new(-1.0, 2.0, 1.0));
+        assert_point_eq(scaled.mins(), &na::Point3::new(-2.0, 0.0, 1.0));
+        assert_point_eq(scaled.maxs(), &na::Point3::new(1.0, 2.0, 3.0));
+    }
+}
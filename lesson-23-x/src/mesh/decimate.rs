@@ -0,0 +1,114 @@
+use nalgebra as na;
+use super::{Mesh, Primitive, Submesh, Vertex};
+use std::collections::HashMap;
+
+impl Mesh {
+    /// Simplify by vertex clustering: vertices in the same grid cell and facing the same
+    /// dominant axis are merged, collapsed triangles are removed.
+    ///
+    /// `resolution` is the number of cells along the longest side of the bounding box.
+    /// Tangents are dropped, so that they can be regenerated for the new surface, and so are
+    /// morph targets.
+    pub fn decimate(&self, resolution: u32) -> Mesh {
+        let aabb = match self.aabb() {
+            Some(aabb) => aabb,
+            None => return self.clone(),
+        };
+        let extents = aabb.maxs() - aabb.mins();
+        let cell_size = extents.x.max(extents.y).max(extents.z) / resolution.max(1) as f32;
+        if cell_size <= 0.0 {
+            return self.clone();
+        }
+
+        let mut clusters: HashMap<(i32, i32, i32, u8), usize> = HashMap::new();
+        let mut sums: Vec<(na::Vector3<f32>, na::Vector3<f32>, usize)> = Vec::new();
+        let mut vertices: Vec<Vertex> = Vec::new();
+        let mut remap = Vec::with_capacity(self.vertices.len());
+
+        for vertex in &self.vertices {
+            let cell = (vertex.pos - aabb.mins()) / cell_size;
+            let key = (
+                cell.x.floor() as i32,
+                cell.y.floor() as i32,
+                cell.z.floor() as i32,
+                vertex.normal.map(dominant_axis).unwrap_or(0),
+            );
+            let index = *clusters.entry(key).or_insert_with(|| {
+                sums.push((na::Vector3::zeros(), na::Vector3::zeros(), 0));
+                vertices.push(Vertex {
+                    tangents: None,
+                    ..*vertex
+                });
+                vertices.len() - 1
+            });
+
+            let sum = &mut sums[index];
+            sum.0 += vertex.pos.coords;
+            sum.1 += vertex.normal.unwrap_or_else(na::Vector3::zeros);
+            sum.2 += 1;
+            remap.push(index as u32);
+        }
+
+        for (vertex, (pos_sum, normal_sum, count)) in vertices.iter_mut().zip(sums) {
+            vertex.pos = na::Point3::from(pos_sum / count as f32);
+            if vertex.normal.is_some() {
+                vertex.normal = normal_sum.try_normalize(::std::f32::EPSILON).or(vertex.normal);
+            }
+        }
+
+        // collapsed triangles are removed from each submesh separately
+        let mut primitives = Vec::with_capacity(self.primitives.len());
+        let mut submeshes = Vec::with_capacity(self.submeshes.len());
+        for submesh in &self.submeshes {
+            let start = primitives.len();
+            primitives.extend(collapse(&self.primitives[submesh.primitives.clone()], &remap));
+            submeshes.push(Submesh {
+                material_index: submesh.material_index,
+                primitives: start..primitives.len(),
+            });
+        }
+        if self.submeshes.is_empty() {
+            primitives.extend(collapse(&self.primitives, &remap));
+        }
+
+        Mesh {
+            name: self.name.clone(),
+            material_index: self.material_index,
+            skeleton_index: self.skeleton_index,
+            vertices,
+            primitives,
+            submeshes,
+            morph_targets: Vec::new(),
+        }
+    }
+
+    /// Generate progressively coarser meshes, halving the clustering resolution each level.
+    pub fn generate_lods(&self, count: usize, resolution: u32) -> Vec<Mesh> {
+        (0..count)
+            .map(|level| self.decimate((resolution >> level).max(1)))
+            .collect()
+    }
+}
+
+/// Remapped triangles, without those whose corners merged.
+fn collapse<'a>(
+    primitives: &'a [Primitive],
+    remap: &'a [u32],
+) -> impl Iterator<Item = Primitive> + 'a {
+    primitives.iter().filter_map(move |primitive| match *primitive {
+        Primitive::Triangle(a, b, c) => {
+            let (a, b, c) = (remap[a as usize], remap[b as usize], remap[c as usize]);
+            if a == b || b == c || a == c {
+                None
+            } else {
+                Some(Primitive::Triangle(a, b, c))
+            }
+        }
+    })
+}
+
+/// Index of the dominant axis and its sign, 0 to 5.
+fn dominant_axis(normal: na::Vector3<f32>) -> u8 {
+    let axis = normal.iamax();
+    axis as u8 * 2 + if normal[axis] < 0.0 { 1 } else { 0 }
+}
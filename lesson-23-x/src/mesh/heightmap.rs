@@ -0,0 +1,140 @@
+use image;
+use nalgebra as na;
+use super::{Mesh, Primitive, Vertex};
+use std::ops::Range;
+
+/// Grid of heights from 0 to 1, row by row.
+#[derive(Clone, Debug)]
+pub struct Heightmap {
+    columns: usize,
+    rows: usize,
+    heights: Vec<f32>,
+}
+
+impl Heightmap {
+    pub fn new(columns: usize, rows: usize, heights: Vec<f32>) -> Heightmap {
+        assert!(columns >= 2 && rows >= 2, "heightmap needs at least 2x2 samples");
+        assert_eq!(columns * rows, heights.len());
+        Heightmap {
+            columns,
+            rows,
+            heights,
+        }
+    }
+
+    /// Black is the lowest and white the highest point, the first image row is row 0.
+    pub fn from_luma_image(img: &image::GrayImage) -> Heightmap {
+        Heightmap::new(
+            img.width() as usize,
+            img.height() as usize,
+            img.pixels().map(|p| f32::from(p.data[0]) / 255.0).collect(),
+        )
+    }
+
+    pub fn columns(&self) -> usize {
+        self.columns
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    /// Sample height, coordinates outside the grid are clamped to the edge.
+    pub fn height(&self, column: isize, row: isize) -> f32 {
+        let column = column.max(0).min(self.columns as isize - 1) as usize;
+        let row = row.max(0).min(self.rows as isize - 1) as usize;
+        self.heights[row * self.columns + column]
+    }
+
+    /// Bilinear height between samples, `x` and `y` are in columns and rows.
+    pub fn interpolated_height(&self, x: f32, y: f32) -> f32 {
+        let (x0, y0) = (x.floor(), y.floor());
+        let (tx, ty) = (x - x0, y - y0);
+        let (column, row) = (x0 as isize, y0 as isize);
+
+        let top = lerp(self.height(column, row), self.height(column + 1, row), tx);
+        let bottom = lerp(
+            self.height(column, row + 1),
+            self.height(column + 1, row + 1),
+            tx,
+        );
+        lerp(top, bottom, ty)
+    }
+
+    /// Position of a sample, with the grid centered at the origin in XY plane and Z up.
+    ///
+    /// `scale` is the size of the whole grid along X and Y, and the height of a white sample.
+    pub fn position(&self, column: usize, row: usize, scale: &na::Vector3<f32>) -> na::Point3<f32> {
+        na::Point3::new(
+            (column as f32 / (self.columns - 1) as f32 - 0.5) * scale.x,
+            (row as f32 / (self.rows - 1) as f32 - 0.5) * scale.y,
+            self.height(column as isize, row as isize) * scale.z,
+        )
+    }
+
+    /// Normal from central differences of neighbouring samples.
+    pub fn normal(&self, column: usize, row: usize, scale: &na::Vector3<f32>) -> na::Vector3<f32> {
+        let (column, row) = (column as isize, row as isize);
+        let step_x = scale.x / (self.columns - 1) as f32;
+        let step_y = scale.y / (self.rows - 1) as f32;
+        let dx = (self.height(column + 1, row) - self.height(column - 1, row)) * scale.z;
+        let dy = (self.height(column, row + 1) - self.height(column, row - 1)) * scale.z;
+        na::Vector3::new(-dx * step_y, -dy * step_x, 2.0 * step_x * step_y).normalize()
+    }
+
+    /// Grid mesh of the cells in column and row ranges, two triangles per cell.
+    ///
+    /// UVs go from 0 to 1 over the whole heightmap, so that neighbouring chunks line up.
+    pub fn grid_mesh(
+        &self,
+        columns: Range<usize>,
+        rows: Range<usize>,
+        scale: &na::Vector3<f32>,
+    ) -> Mesh {
+        let columns = columns.start..columns.end.min(self.columns - 1);
+        let rows = rows.start..rows.end.min(self.rows - 1);
+        let stride = (columns.end - columns.start + 1) as u32;
+
+        let mut vertices = Vec::new();
+        for row in rows.start..=rows.end {
+            for column in columns.start..=columns.end {
+                vertices.push(Vertex {
+                    pos: self.position(column, row, scale),
+                    normal: Some(self.normal(column, row, scale)),
+                    tangents: None,
+                    uv: Some(na::Vector2::new(
+                        column as f32 / (self.columns - 1) as f32,
+                        row as f32 / (self.rows - 1) as f32,
+                    )),
+                    uv2: None,
+                    color: None,
+                    bones: None,
+                });
+            }
+        }
+
+        let mut primitives = Vec::new();
+        for y in 0..(rows.end - rows.start) as u32 {
+            for x in 0..(columns.end - columns.start) as u32 {
+                let a = y * stride + x;
+                let (b, c, d) = (a + 1, a + stride + 1, a + stride);
+                primitives.push(Primitive::Triangle(a, b, c));
+                primitives.push(Primitive::Triangle(a, c, d));
+            }
+        }
+
+        Mesh {
+            name: None,
+            material_index: None,
+            skeleton_index: None,
+            vertices,
+            primitives,
+            submeshes: Vec::new(),
+            morph_targets: Vec::new(),
+        }
+    }
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
@@ -1,19 +1,69 @@
 use nalgebra as na;
-use ncollide3d::bounding_volume::aabb::AABB;
 use crate::resources::ResourcePathBuf;
+use std::ops::Range;
+
+mod bounds;
+mod decimate;
+mod heightmap;
+mod morph;
+mod optimize;
+/// Generated shapes, i.e. for lessons and tests that need no model file.
+pub mod primitives;
+mod skeleton;
+mod tangents;
+
+pub use self::bounds::{isometry_aabb, merge_aabb, points_aabb, scale_aabb, transform_aabb};
+pub use self::skeleton::{
+    AnimationClip, BoneWeights, Channel, ChannelValues, Interpolation, Joint, JointTransform,
+    KeyframeTangents, Pose, Skeleton, SkinnedBounds, MAX_JOINTS,
+};
+pub use self::heightmap::Heightmap;
+pub use self::morph::{MorphTarget, MAX_MORPH_TARGETS};
+pub use self::optimize::{OptimizeStats, VERTEX_CACHE_SIZE};
+pub use self::tangents::TangentAlgorithm;
+
+/// Vertices or triangles processed by one job, smaller meshes stay on the calling thread.
+const JOB_CHUNK: usize = 4096;
 
 #[derive(Clone, Debug)]
 pub struct MeshSet {
     pub meshes: Vec<Mesh>,
     pub materials: Vec<Material>,
+    pub skeletons: Vec<Skeleton>,
+    pub animations: Vec<AnimationClip>,
+    /// Parts of the file left out on import, i.e. point and line primitives.
+    pub skipped: Vec<String>,
+    /// Result of optimizing each mesh on import, empty if the loader keeps meshes as stored.
+    pub optimize_stats: Vec<OptimizeStats>,
 }
 
 #[derive(Clone, Debug)]
 pub struct Mesh {
     pub name: Option<String>,
     pub material_index: Option<usize>,
+    /// Skeleton deforming this mesh; vertices are then in bind space.
+    pub skeleton_index: Option<usize>,
     pub vertices: Vec<Vertex>,
     pub primitives: Vec<Primitive>,
+    /// Material ranges of merged meshes, empty if all primitives use `material_index`.
+    pub submeshes: Vec<Submesh>,
+    /// Blend shapes with deltas for each vertex, none in merged and decimated meshes.
+    pub morph_targets: Vec<MorphTarget>,
+}
+
+/// Consecutive primitives of a mesh drawn with one material.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Submesh {
+    pub material_index: Option<usize>,
+    /// Range of `Mesh::primitives`.
+    pub primitives: Range<usize>,
+}
+
+impl Submesh {
+    /// Range of `Mesh::triangle_indices`.
+    pub fn index_range(&self) -> Range<usize> {
+        self.primitives.start * 3..self.primitives.end * 3
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -27,6 +77,11 @@ pub struct Vertex {
     pub normal: Option<na::Vector3<f32>>,
     pub tangents: Option<Tangents>,
     pub uv: Option<na::Vector2<f32>>,
+    /// Second texture coordinates, i.e. for lightmaps.
+    pub uv2: Option<na::Vector2<f32>>,
+    /// Linear RGBA, multiplied into the color by materials with `vertex_colors`.
+    pub color: Option<na::Vector4<f32>>,
+    pub bones: Option<BoneWeights>,
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -40,6 +95,29 @@ pub struct Material {
     pub name: Option<String>,
     pub diffuse_map: Option<ResourcePathBuf>,
     pub bump_map: Option<ResourcePathBuf>,
+    /// Metalness is read from the blue channel.
+    pub metallic_map: Option<ResourcePathBuf>,
+    /// Roughness is read from the green channel.
+    pub roughness_map: Option<ResourcePathBuf>,
+    /// Ambient occlusion is read from the red channel.
+    pub occlusion_map: Option<ResourcePathBuf>,
+    /// Height is read from the red channel, white is the top of the surface.
+    pub height_map: Option<ResourcePathBuf>,
+    /// Baked lighting multiplied into the color, sampled with `Vertex::uv2` if present.
+    pub lightmap: Option<ResourcePathBuf>,
+    /// Multiply the color by `Vertex::color`.
+    pub vertex_colors: bool,
+    /// Both sides are drawn, i.e. for foliage, back faces are culled otherwise.
+    pub double_sided: bool,
+    pub metallic: Option<f32>,
+    pub roughness: Option<f32>,
+}
+
+impl Material {
+    /// Material provides metallic-roughness maps for physically based shading.
+    pub fn has_pbr_maps(&self) -> bool {
+        self.metallic_map.is_some() || self.roughness_map.is_some()
+    }
 }
 
 impl Tangents {
@@ -97,132 +175,86 @@ impl Tangents {
 }
 
 impl Mesh {
-    pub fn triangle_indices(&self) -> Vec<u32> {
-        let mut result = Vec::with_capacity(self.primitives.len() * 3);
+    /// Merge meshes into one, with a submesh for each material.
+    ///
+    /// Meshes are grouped by material, so that each material has a single range.
+    /// Name and skeleton are taken from the first mesh, morph targets are dropped.
+    pub fn merge(meshes: &[Mesh]) -> Option<Mesh> {
+        let first = meshes.first()?;
+        let mut sorted = meshes.iter().collect::<Vec<_>>();
+        sorted.sort_by_key(|mesh| mesh.material_index);
 
-        for primitive in self.primitives.iter() {
-            match *primitive {
-                Primitive::Triangle(a, b, c) => {
-                    result.push(a);
-                    result.push(b);
-                    result.push(c);
+        let mut vertices = Vec::new();
+        let mut primitives = Vec::new();
+        let mut submeshes: Vec<Submesh> = Vec::new();
+        for mesh in sorted {
+            let base = vertices.len() as u32;
+            vertices.extend_from_slice(&mesh.vertices);
+            for submesh in mesh.draw_ranges() {
+                let start = primitives.len();
+                primitives.extend(mesh.primitives[submesh.primitives].iter().map(|p| {
+                    let Primitive::Triangle(a, b, c) = *p;
+                    Primitive::Triangle(a + base, b + base, c + base)
+                }));
+
+                match submeshes.last_mut() {
+                    Some(ref mut last) if last.material_index == submesh.material_index => {
+                        last.primitives.end = primitives.len();
+                        continue;
+                    }
+                    _ => (),
                 }
+                submeshes.push(Submesh {
+                    material_index: submesh.material_index,
+                    primitives: start..primitives.len(),
+                });
             }
         }
 
-        result
-    }
-
-    pub fn aabb(&self) -> Option<AABB<f32>> {
-        let mut min_x = None;
-        let mut min_y = None;
-        let mut min_z = None;
-        let mut max_x = None;
-        let mut max_y = None;
-        let mut max_z = None;
-
-        fn update_min(val: &mut Option<f32>, new: f32) {
-            *val = match val {
-                None => Some(new),
-                Some(val) => if new < *val {
-                    Some(new)
-                } else {
-                    return;
-                },
-            };
-        }
-
-        fn update_max(val: &mut Option<f32>, new: f32) {
-            *val = match val {
-                None => Some(new),
-                Some(val) => if new > *val {
-                    Some(new)
-                } else {
-                    return;
-                },
-            };
+        let material_index = match submeshes.len() {
+            1 => submeshes[0].material_index,
+            _ => None,
+        };
+        if submeshes.len() == 1 {
+            submeshes.clear();
         }
 
-        for v in &self.vertices {
-            update_min(&mut min_x, v.pos.x);
-            update_min(&mut min_y, v.pos.y);
-            update_min(&mut min_z, v.pos.z);
-            update_max(&mut max_x, v.pos.x);
-            update_max(&mut max_y, v.pos.y);
-            update_max(&mut max_z, v.pos.z);
-        }
+        Some(Mesh {
+            name: first.name.clone(),
+            material_index,
+            skeleton_index: first.skeleton_index,
+            vertices,
+            primitives,
+            submeshes,
+            morph_targets: Vec::new(),
+        })
+    }
 
-        if let (Some(min_x), Some(min_y), Some(min_z), Some(max_x), Some(max_y), Some(max_z)) =
-            (min_x, min_y, min_z, max_x, max_y, max_z)
-        {
-            Some(AABB::new(
-                [min_x, min_y, min_z].into(),
-                [max_x, max_y, max_z].into(),
-            ))
+    /// Primitive ranges with their material, a single range if the mesh has no submeshes.
+    pub fn draw_ranges(&self) -> Vec<Submesh> {
+        if self.submeshes.is_empty() {
+            vec![Submesh {
+                material_index: self.material_index,
+                primitives: 0..self.primitives.len(),
+            }]
         } else {
-            None
+            self.submeshes.clone()
         }
     }
 
-    pub fn calculate_tangents(&mut self) {
+    pub fn triangle_indices(&self) -> Vec<u32> {
+        let mut result = Vec::with_capacity(self.primitives.len() * 3);
+
         for primitive in self.primitives.iter() {
             match *primitive {
-                Primitive::Triangle(ai, bi, ci) => {
-                    let a = self.vertices[ai as usize];
-                    let b = self.vertices[bi as usize];
-                    let c = self.vertices[ci as usize];
-
-                    if let (Some(auv), Some(buv), Some(cuv), Some(an), Some(bn), Some(cn)) =
-                        (a.uv, b.uv, c.uv, a.normal, b.normal, c.normal)
-                    {
-                        // this was shamelessly "inspired" by assimp library https://github.com/assimp/assimp/blob/master/code/CalcTangentsProcess.cpp
-
-                        let face_tangent_vectors =
-                            Tangents::from_triangle(&a.pos, &b.pos, &c.pos, &auv, &buv, &cuv);
-
-                        fn is_special_float(v: f32) -> bool {
-                            v.is_nan() || v.is_infinite()
-                        }
-
-                        // for each vertice's (normal, index)...
-
-                        for (n, i) in &[(an, ai), (bn, bi), (cn, ci)] {
-                            // project tangent and bitangent into the plane formed by the vertex' normal
-                            let mut local_tangent = face_tangent_vectors.tangent
-                                - n * face_tangent_vectors.tangent.dot(&n);
-                            let mut local_bitangent = face_tangent_vectors.bitangent
-                                - n * face_tangent_vectors.bitangent.dot(&n);
-                            local_tangent.normalize_mut();
-                            local_bitangent.normalize_mut();
-
-                            // reconstruct tangent/bitangent according to normal and bitangent/tangent when it's infinite or NaN.
-                            let invalid_tangent = is_special_float(local_tangent.x)
-                                || is_special_float(local_tangent.y)
-                                || is_special_float(local_tangent.z);
-                            let invalid_bitangent = is_special_float(local_bitangent.x)
-                                || is_special_float(local_bitangent.y)
-                                || is_special_float(local_bitangent.z);
-
-                            if invalid_tangent != invalid_bitangent {
-                                if invalid_tangent {
-                                    local_tangent = n.cross(&local_bitangent);
-                                    local_tangent.normalize_mut();
-                                } else {
-                                    local_bitangent = local_tangent.cross(&n);
-                                    local_bitangent.normalize_mut();
-                                }
-                            }
-
-                            let ref mut existing_tangent_vectors =
-                                self.vertices[*i as usize].tangents;
-                            *existing_tangent_vectors = Some(Tangents {
-                                tangent: local_tangent,
-                                bitangent: local_bitangent,
-                            });
-                        }
-                    }
+                Primitive::Triangle(a, b, c) => {
+                    result.push(a);
+                    result.push(b);
+                    result.push(c);
                 }
             }
         }
+
+        result
     }
 }
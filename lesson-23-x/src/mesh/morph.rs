@@ -0,0 +1,96 @@
+use nalgebra as na;
+use super::Mesh;
+
+/// Most morph targets blended per mesh, the size of `MorphWeights` in `common/morph.glsl`.
+pub const MAX_MORPH_TARGETS: usize = 8;
+
+/// Blend shape of a mesh, each vertex is displaced by the weight times its deltas.
+#[derive(Clone, Debug)]
+pub struct MorphTarget {
+    /// Position delta of each vertex.
+    pub positions: Vec<na::Vector3<f32>>,
+    /// Normal delta of each vertex, empty if the target does not change normals.
+    pub normals: Vec<na::Vector3<f32>>,
+    /// Weight when not animated, from the model file.
+    pub default_weight: f32,
+}
+
+impl MorphTarget {
+    fn position(&self, vertex: usize) -> na::Vector3<f32> {
+        self.positions
+            .get(vertex)
+            .cloned()
+            .unwrap_or_else(na::Vector3::zeros)
+    }
+
+    fn normal(&self, vertex: usize) -> na::Vector3<f32> {
+        self.normals
+            .get(vertex)
+            .cloned()
+            .unwrap_or_else(na::Vector3::zeros)
+    }
+}
+
+impl Mesh {
+    /// Default weight of each morph target.
+    pub fn morph_weights(&self) -> Vec<f32> {
+        self.morph_targets
+            .iter()
+            .map(|target| target.default_weight)
+            .collect()
+    }
+
+    /// Copy with the morph targets applied at the weights, i.e. for bounds and picking.
+    ///
+    /// Missing weights are zero, the copy has no morph targets.
+    pub fn morphed(&self, weights: &[f32]) -> Mesh {
+        let mut mesh = self.clone();
+        mesh.morph_targets.clear();
+        for (target, &weight) in self.morph_targets.iter().zip(weights) {
+            if weight == 0.0 {
+                continue;
+            }
+            for (index, vertex) in mesh.vertices.iter_mut().enumerate() {
+                vertex.pos += target.position(index) * weight;
+                if let Some(ref mut normal) = vertex.normal {
+                    *normal += target.normal(index) * weight;
+                }
+            }
+        }
+        for vertex in &mut mesh.vertices {
+            if let Some(ref mut normal) = vertex.normal {
+                *normal = normal.try_normalize(::std::f32::EPSILON).unwrap_or(*normal);
+            }
+        }
+        mesh
+    }
+
+    /// Keep morph deltas in line with rebuilt vertices, `sources` has the previous index of
+    /// each new vertex.
+    pub(super) fn select_morph_vertices(&mut self, sources: &[usize]) {
+        for target in &mut self.morph_targets {
+            let positions = sources.iter().map(|&index| target.position(index)).collect();
+            let normals = if target.normals.is_empty() {
+                Vec::new()
+            } else {
+                sources.iter().map(|&index| target.normal(index)).collect()
+            };
+            target.positions = positions;
+            target.normals = normals;
+        }
+    }
+
+    /// Append the deltas of a vertex to its key, so that vertices which morph differently are
+    /// not merged.
+    pub(super) fn push_morph_key(&self, vertex: usize, key: &mut Vec<u32>) {
+        for target in &self.morph_targets {
+            let position = target.position(vertex);
+            let normal = target.normal(vertex);
+            key.extend(
+                [position.x, position.y, position.z, normal.x, normal.y, normal.z]
+                    .iter()
+                    .map(|v| (v + 0.0).to_bits()),
+            );
+        }
+    }
+}
@@ -0,0 +1,295 @@
+use super::{Mesh, Primitive, Vertex, JOB_CHUNK};
+use crate::jobs;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+
+/// Post-transform cache size assumed when reordering triangles.
+pub const VERTEX_CACHE_SIZE: usize = 16;
+
+/// Result of `Mesh::optimize`.
+#[derive(Copy, Clone, Debug)]
+pub struct OptimizeStats {
+    pub vertices_before: usize,
+    pub vertices_after: usize,
+    /// Average cache miss ratio, vertex shader invocations per triangle.
+    pub acmr_before: f32,
+    pub acmr_after: f32,
+}
+
+impl fmt::Display for OptimizeStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} -> {} vertices, ACMR {:.3} -> {:.3}",
+            self.vertices_before, self.vertices_after, self.acmr_before, self.acmr_after
+        )
+    }
+}
+
+impl Mesh {
+    /// Deduplicate vertices, reorder triangles for the vertex cache and vertices for fetch.
+    pub fn optimize(&mut self) -> OptimizeStats {
+        let vertices_before = self.vertices.len();
+        let acmr_before = self.average_cache_miss_ratio(VERTEX_CACHE_SIZE);
+
+        self.deduplicate_vertices();
+        self.optimize_vertex_cache(VERTEX_CACHE_SIZE);
+        self.optimize_vertex_fetch();
+
+        OptimizeStats {
+            vertices_before,
+            vertices_after: self.vertices.len(),
+            acmr_before,
+            acmr_after: self.average_cache_miss_ratio(VERTEX_CACHE_SIZE),
+        }
+    }
+
+    /// Merge bitwise identical vertices, with identical morph deltas, and rebuild the indices.
+    /// Returns removed vertex count.
+    pub fn deduplicate_vertices(&mut self) -> usize {
+        let mut unique = HashMap::with_capacity(self.vertices.len());
+        let mut vertices = Vec::with_capacity(self.vertices.len());
+        let mut remap = Vec::with_capacity(self.vertices.len());
+        let mut sources = Vec::with_capacity(self.vertices.len());
+
+        // keys take most of the time, and do not depend on each other
+        let keys = jobs::map_chunks(&self.vertices, JOB_CHUNK, |vertices| {
+            vertices.iter().map(vertex_key).collect::<Vec<_>>()
+        });
+
+        for (source, (vertex, mut key)) in self
+            .vertices
+            .iter()
+            .zip(keys.into_iter().flatten())
+            .enumerate()
+        {
+            self.push_morph_key(source, &mut key);
+            let next_index = vertices.len() as u32;
+            let index = *unique.entry(key).or_insert(next_index);
+            if index == next_index {
+                vertices.push(*vertex);
+                sources.push(source);
+            }
+            remap.push(index);
+        }
+
+        let removed = self.vertices.len() - vertices.len();
+        self.vertices = vertices;
+        self.select_morph_vertices(&sources);
+        self.remap_primitives(&remap);
+        removed
+    }
+
+    /// Reorder triangles with Tipsify (Sander, Nehab, Barczak 2007).
+    ///
+    /// Triangles stay within their submesh, so that material ranges remain valid.
+    pub fn optimize_vertex_cache(&mut self, cache_size: usize) {
+        let triangles = self.triangle_indices();
+        let mut output = Vec::with_capacity(self.primitives.len());
+        for submesh in self.draw_ranges() {
+            output.extend(tipsify(
+                &triangles[submesh.index_range()],
+                self.vertices.len(),
+                cache_size,
+            ));
+        }
+        self.primitives = output;
+    }
+
+    /// Renumber vertices in order of first use by the triangles, dropping unused ones.
+    pub fn optimize_vertex_fetch(&mut self) {
+        const UNUSED: u32 = ::std::u32::MAX;
+
+        let mut remap = vec![UNUSED; self.vertices.len()];
+        let mut vertices = Vec::with_capacity(self.vertices.len());
+        let mut sources = Vec::with_capacity(self.vertices.len());
+
+        for index in self.triangle_indices() {
+            if remap[index as usize] == UNUSED {
+                remap[index as usize] = vertices.len() as u32;
+                vertices.push(self.vertices[index as usize]);
+                sources.push(index as usize);
+            }
+        }
+
+        self.vertices = vertices;
+        self.select_morph_vertices(&sources);
+        self.remap_primitives(&remap);
+    }
+
+    /// Vertex shader invocations per triangle with a FIFO cache of the given size.
+    pub fn average_cache_miss_ratio(&self, cache_size: usize) -> f32 {
+        if self.primitives.is_empty() {
+            return 0.0;
+        }
+
+        let mut cache = VecDeque::with_capacity(cache_size);
+        let mut misses = 0;
+        for index in self.triangle_indices() {
+            if !cache.contains(&index) {
+                misses += 1;
+                if cache.len() == cache_size {
+                    cache.pop_front();
+                }
+                cache.push_back(index);
+            }
+        }
+
+        misses as f32 / self.primitives.len() as f32
+    }
+
+    fn remap_primitives(&mut self, remap: &[u32]) {
+        for primitive in &mut self.primitives {
+            *primitive = match *primitive {
+                Primitive::Triangle(a, b, c) => {
+                    Primitive::Triangle(remap[a as usize], remap[b as usize], remap[c as usize])
+                }
+            };
+        }
+    }
+}
+
+/// Tipsify order of the triangles, indices may refer to any of `vertex_count` vertices.
+fn tipsify(triangles: &[u32], vertex_count: usize, cache_size: usize) -> Vec<Primitive> {
+    let triangle_count = triangles.len() / 3;
+
+    // triangles using each vertex
+    let mut offsets = vec![0usize; vertex_count + 1];
+    for &index in triangles {
+        offsets[index as usize + 1] += 1;
+    }
+    for i in 0..vertex_count {
+        offsets[i + 1] += offsets[i];
+    }
+    let mut adjacency = vec![0usize; triangles.len()];
+    let mut fill = offsets.clone();
+    for (corner, &index) in triangles.iter().enumerate() {
+        adjacency[fill[index as usize]] = corner / 3;
+        fill[index as usize] += 1;
+    }
+
+    let mut live: Vec<usize> = (0..vertex_count)
+        .map(|v| offsets[v + 1] - offsets[v])
+        .collect();
+    let mut cache_time = vec![0usize; vertex_count];
+    let mut emitted = vec![false; triangle_count];
+    let mut dead_end = Vec::new();
+    let mut output = Vec::with_capacity(triangle_count);
+
+    let mut time = cache_size + 1;
+    let mut cursor = 0;
+    let mut fanning = next_live_vertex(&live, &mut dead_end, &mut cursor);
+
+    while let Some(vertex) = fanning {
+        let mut candidates = Vec::new();
+
+        for &triangle in &adjacency[offsets[vertex]..offsets[vertex + 1]] {
+            if emitted[triangle] {
+                continue;
+            }
+            emitted[triangle] = true;
+
+            let corners = &triangles[triangle * 3..triangle * 3 + 3];
+            output.push(Primitive::Triangle(corners[0], corners[1], corners[2]));
+
+            for &index in corners {
+                let index = index as usize;
+                dead_end.push(index);
+                candidates.push(index);
+                live[index] -= 1;
+                if time - cache_time[index] > cache_size {
+                    cache_time[index] = time;
+                    time += 1;
+                }
+            }
+        }
+
+        // prefer the oldest candidate still in cache that will not be evicted while fanning
+        let mut best = None;
+        let mut best_priority = 0;
+        for &candidate in &candidates {
+            if live[candidate] == 0 {
+                continue;
+            }
+            let age = time - cache_time[candidate];
+            let priority = if age + 2 * live[candidate] <= cache_size {
+                age
+            } else {
+                0
+            };
+            if best.is_none() || priority > best_priority {
+                best = Some(candidate);
+                best_priority = priority;
+            }
+        }
+
+        fanning = match best {
+            Some(vertex) => Some(vertex),
+            None => next_live_vertex(&live, &mut dead_end, &mut cursor),
+        };
+    }
+
+    output
+}
+
+/// Dead-end stack first, then scan forward for any vertex with unemitted triangles.
+fn next_live_vertex(
+    live: &[usize],
+    dead_end: &mut Vec<usize>,
+    cursor: &mut usize,
+) -> Option<usize> {
+    while let Some(vertex) = dead_end.pop() {
+        if live[vertex] > 0 {
+            return Some(vertex);
+        }
+    }
+    while *cursor < live.len() {
+        if live[*cursor] > 0 {
+            return Some(*cursor);
+        }
+        *cursor += 1;
+    }
+    None
+}
+
+fn vertex_key(vertex: &Vertex) -> Vec<u32> {
+    // adding zero turns -0.0 into 0.0, so both compare equal
+    fn push(key: &mut Vec<u32>, values: &[f32]) {
+        key.extend(values.iter().map(|v| (v + 0.0).to_bits()));
+    }
+
+    // first value tells which optional attributes follow
+    let present = [
+        vertex.normal.is_some(),
+        vertex.tangents.is_some(),
+        vertex.uv.is_some(),
+        vertex.uv2.is_some(),
+        vertex.color.is_some(),
+        vertex.bones.is_some(),
+    ];
+    let mut key = Vec::with_capacity(24);
+    key.push(present.iter().fold(0, |bits, &p| bits << 1 | p as u32));
+
+    push(&mut key, &[vertex.pos.x, vertex.pos.y, vertex.pos.z]);
+    if let Some(n) = vertex.normal {
+        push(&mut key, &[n.x, n.y, n.z]);
+    }
+    if let Some(t) = vertex.tangents {
+        push(&mut key, &[t.tangent.x, t.tangent.y, t.tangent.z]);
+        push(&mut key, &[t.bitangent.x, t.bitangent.y, t.bitangent.z]);
+    }
+    if let Some(uv) = vertex.uv {
+        push(&mut key, &[uv.x, uv.y]);
+    }
+    if let Some(uv2) = vertex.uv2 {
+        push(&mut key, &[uv2.x, uv2.y]);
+    }
+    if let Some(color) = vertex.color {
+        push(&mut key, &[color.x, color.y, color.z, color.w]);
+    }
+    if let Some(ref bones) = vertex.bones {
+        key.extend(bones.joints.iter().map(|&j| u32::from(j)));
+        push(&mut key, &bones.weights);
+    }
+    key
+}
@@ -0,0 +1,183 @@
+use nalgebra as na;
+use super::{Mesh, Primitive, TangentAlgorithm, Vertex};
+use std::f32::consts::PI;
+
+/// Box centered at the origin with a separate quad for each face, so that edges stay sharp.
+///
+/// Each face has UVs from 0 to 1.
+pub fn cube(size: &na::Vector3<f32>) -> Mesh {
+    let half = size * 0.5;
+    // normal, then U and V directions with `u x v = normal`
+    let faces = [
+        (na::Vector3::x(), na::Vector3::y(), na::Vector3::z()),
+        (-na::Vector3::x(), -na::Vector3::y(), na::Vector3::z()),
+        (na::Vector3::y(), -na::Vector3::x(), na::Vector3::z()),
+        (-na::Vector3::y(), na::Vector3::x(), na::Vector3::z()),
+        (na::Vector3::z(), na::Vector3::x(), na::Vector3::y()),
+        (-na::Vector3::z(), na::Vector3::x(), -na::Vector3::y()),
+    ];
+
+    let mut builder = Builder::default();
+    for &(normal, u, v) in &faces {
+        builder.grid(1, 1, |s, t| {
+            let pos = normal + u * (s * 2.0 - 1.0) + v * (t * 2.0 - 1.0);
+            (na::Point3::from(pos.component_mul(&half)), normal)
+        });
+    }
+    builder.build("cube")
+}
+
+/// UV sphere centered at the origin, with poles on the Z axis.
+///
+/// U goes around the equator and V from the bottom to the top pole.
+pub fn sphere(radius: f32, segments: usize, rings: usize) -> Mesh {
+    let mut builder = Builder::default();
+    builder.grid(segments.max(3), rings.max(2), |s, t| {
+        let (longitude, latitude) = (s * 2.0 * PI, (t - 0.5) * PI);
+        let normal = na::Vector3::new(
+            latitude.cos() * longitude.cos(),
+            latitude.cos() * longitude.sin(),
+            latitude.sin(),
+        );
+        (na::Point3::from(normal * radius), normal)
+    });
+    builder.build("sphere")
+}
+
+/// Flat grid in the XY plane facing Z, split into `subdivisions` cells along each side.
+pub fn plane(size: &na::Vector2<f32>, subdivisions: usize) -> Mesh {
+    let subdivisions = subdivisions.max(1);
+    let mut builder = Builder::default();
+    builder.grid(subdivisions, subdivisions, |s, t| {
+        let pos = na::Point3::new((s - 0.5) * size.x, (t - 0.5) * size.y, 0.0);
+        (pos, na::Vector3::z())
+    });
+    builder.build("plane")
+}
+
+/// Closed cylinder around the Z axis, centered at the origin.
+///
+/// The side wraps U once around, caps map the unit disc into UV space.
+pub fn cylinder(radius: f32, height: f32, segments: usize) -> Mesh {
+    let segments = segments.max(3);
+    let mut builder = Builder::default();
+    builder.grid(segments, 1, |s, t| {
+        let angle = s * 2.0 * PI;
+        let normal = na::Vector3::new(angle.cos(), angle.sin(), 0.0);
+        let pos = na::Point3::new(normal.x * radius, normal.y * radius, (t - 0.5) * height);
+        (pos, normal)
+    });
+    builder.disc(radius, height * 0.5, segments, na::Vector3::z());
+    builder.disc(radius, -height * 0.5, segments, -na::Vector3::z());
+    builder.build("cylinder")
+}
+
+/// Ring around the Z axis, `major_radius` to the center of the tube of `minor_radius`.
+///
+/// U goes around the Z axis and V around the tube.
+pub fn torus(major_radius: f32, minor_radius: f32, segments: usize, sides: usize) -> Mesh {
+    let mut builder = Builder::default();
+    builder.grid(segments.max(3), sides.max(3), |s, t| {
+        let (around, tube) = (s * 2.0 * PI, t * 2.0 * PI);
+        let normal = na::Vector3::new(
+            tube.cos() * around.cos(),
+            tube.cos() * around.sin(),
+            tube.sin(),
+        );
+        let center = na::Vector3::new(around.cos(), around.sin(), 0.0) * major_radius;
+        (na::Point3::from(center + normal * minor_radius), normal)
+    });
+    builder.build("torus")
+}
+
+#[derive(Default)]
+struct Builder {
+    vertices: Vec<Vertex>,
+    primitives: Vec<Primitive>,
+}
+
+impl Builder {
+    /// Grid of `columns` by `rows` cells, `surface` gives position and normal for UV.
+    ///
+    /// Triangles collapsed to a point, i.e. at the poles of a sphere, are left out.
+    fn grid<F>(&mut self, columns: usize, rows: usize, surface: F)
+    where
+        F: Fn(f32, f32) -> (na::Point3<f32>, na::Vector3<f32>),
+    {
+        let base = self.vertices.len() as u32;
+        let stride = columns as u32 + 1;
+        for row in 0..=rows {
+            for column in 0..=columns {
+                let uv = na::Vector2::new(column as f32 / columns as f32, row as f32 / rows as f32);
+                let (pos, normal) = surface(uv.x, uv.y);
+                self.vertex(pos, normal, uv);
+            }
+        }
+
+        for y in 0..rows as u32 {
+            for x in 0..columns as u32 {
+                let a = base + y * stride + x;
+                let (b, c, d) = (a + 1, a + stride + 1, a + stride);
+                self.triangle(a, b, c);
+                self.triangle(a, c, d);
+            }
+        }
+    }
+
+    /// Cap of a cylinder at height `z`, facing `normal` up or down the Z axis.
+    fn disc(&mut self, radius: f32, z: f32, segments: usize, normal: na::Vector3<f32>) {
+        let center = self.vertices.len() as u32;
+        self.vertex(na::Point3::new(0.0, 0.0, z), normal, na::Vector2::new(0.5, 0.5));
+        for segment in 0..=segments {
+            let angle = segment as f32 / segments as f32 * 2.0 * PI;
+            let (cos, sin) = (angle.cos(), angle.sin());
+            self.vertex(
+                na::Point3::new(cos * radius, sin * radius, z),
+                normal,
+                na::Vector2::new(0.5 + cos * 0.5, 0.5 + sin * 0.5 * normal.z),
+            );
+        }
+        for segment in 0..segments as u32 {
+            let (a, b) = (center + 1 + segment, center + 2 + segment);
+            if normal.z > 0.0 {
+                self.triangle(center, a, b);
+            } else {
+                self.triangle(center, b, a);
+            }
+        }
+    }
+
+    fn vertex(&mut self, pos: na::Point3<f32>, normal: na::Vector3<f32>, uv: na::Vector2<f32>) {
+        self.vertices.push(Vertex {
+            pos,
+            normal: Some(normal),
+            tangents: None,
+            uv: Some(uv),
+            uv2: None,
+            color: None,
+            bones: None,
+        });
+    }
+
+    fn triangle(&mut self, a: u32, b: u32, c: u32) {
+        let pos = |index: u32| self.vertices[index as usize].pos;
+        let (pa, pb, pc) = (pos(a), pos(b), pos(c));
+        if (pb - pa).cross(&(pc - pa)).norm_squared() > ::std::f32::EPSILON * ::std::f32::EPSILON {
+            self.primitives.push(Primitive::Triangle(a, b, c));
+        }
+    }
+
+    fn build(self, name: &str) -> Mesh {
+        let mut mesh = Mesh {
+            name: Some(name.to_string()),
+            material_index: None,
+            skeleton_index: None,
+            vertices: self.vertices,
+            primitives: self.primitives,
+            submeshes: Vec::new(),
+            morph_targets: Vec::new(),
+        };
+        mesh.generate_tangents(TangentAlgorithm::Accumulated);
+        mesh
+    }
+}
@@ -0,0 +1,331 @@
+use nalgebra as na;
+use ncollide3d::bounding_volume::aabb::AABB;
+use super::{merge_aabb, transform_aabb, Mesh};
+
+/// Maximum number of joints a skinned mesh may use, matches the bone uniform block size.
+pub const MAX_JOINTS: usize = 64;
+
+/// Up to four joint influences of a skinned vertex.
+#[derive(Copy, Clone, Debug)]
+pub struct BoneWeights {
+    pub joints: [u16; 4],
+    pub weights: [f32; 4],
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct JointTransform {
+    pub translation: na::Vector3<f32>,
+    pub rotation: na::UnitQuaternion<f32>,
+    pub scale: na::Vector3<f32>,
+}
+
+impl JointTransform {
+    pub fn identity() -> JointTransform {
+        JointTransform {
+            translation: na::Vector3::zeros(),
+            rotation: na::UnitQuaternion::identity(),
+            scale: na::Vector3::new(1.0, 1.0, 1.0),
+        }
+    }
+
+    pub fn to_homogeneous(&self) -> na::Matrix4<f32> {
+        na::Matrix4::new_translation(&self.translation)
+            * self.rotation.to_homogeneous()
+            * na::Matrix4::new_nonuniform_scaling(&self.scale)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Joint {
+    pub name: Option<String>,
+    /// Parent joint index, always smaller than the index of this joint.
+    pub parent: Option<usize>,
+    /// Transform from mesh space into joint space at bind time.
+    pub inverse_bind: na::Matrix4<f32>,
+    /// Joint transform relative to parent when not animated.
+    pub rest: JointTransform,
+    /// Transform of the nodes between the parent joint, or the scene root for joints
+    /// without a parent, and this joint. Identity if the joint is a direct child.
+    pub offset: na::Matrix4<f32>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Skeleton {
+    pub name: Option<String>,
+    /// Joints ordered so that parents come before children.
+    pub joints: Vec<Joint>,
+}
+
+#[derive(Clone, Debug)]
+pub enum ChannelValues {
+    Translation(Vec<na::Vector3<f32>>),
+    Rotation(Vec<na::UnitQuaternion<f32>>),
+    Scale(Vec<na::Vector3<f32>>),
+}
+
+/// How values between two keyframes are found.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Interpolation {
+    /// Value of the previous keyframe.
+    Step,
+    Linear,
+    /// Hermite spline through the values, with the tangents of the channel.
+    CubicSpline,
+}
+
+/// In and out tangent of a cubic spline keyframe, as x, y, z and w. Translations and scales
+/// leave w at zero.
+pub type KeyframeTangents = (na::Vector4<f32>, na::Vector4<f32>);
+
+/// Keyframes of a single joint property.
+#[derive(Clone, Debug)]
+pub struct Channel {
+    pub joint: usize,
+    /// Keyframe times in seconds, ascending.
+    pub times: Vec<f32>,
+    pub interpolation: Interpolation,
+    pub values: ChannelValues,
+    /// Tangents of each keyframe of a `CubicSpline` channel, empty for other channels.
+    pub tangents: Vec<KeyframeTangents>,
+}
+
+#[derive(Clone, Debug)]
+pub struct AnimationClip {
+    pub name: Option<String>,
+    /// Index of the skeleton in the mesh set this clip animates.
+    pub skeleton_index: usize,
+    /// Clip duration in seconds.
+    pub duration: f32,
+    pub channels: Vec<Channel>,
+}
+
+/// Joint transforms of a skeleton at some point of an animation.
+pub struct Pose<'a> {
+    skeleton: &'a Skeleton,
+    clip: &'a AnimationClip,
+    local: Vec<JointTransform>,
+    bone_matrices: Vec<na::Matrix4<f32>>,
+}
+
+impl<'a> Pose<'a> {
+    pub fn new(skeleton: &'a Skeleton, clip: &'a AnimationClip) -> Pose<'a> {
+        let mut pose = Pose {
+            skeleton,
+            clip,
+            local: skeleton.joints.iter().map(|j| j.rest).collect(),
+            bone_matrices: vec![na::Matrix4::identity(); skeleton.joints.len()],
+        };
+        pose.update_bone_matrices();
+        pose
+    }
+
+    /// Sample the clip at time in seconds, looping it over its duration.
+    pub fn sample(&mut self, time: f32) -> &[na::Matrix4<f32>] {
+        let duration = self.clip.duration;
+        let time = if duration > 0.0 {
+            let time = time % duration;
+            if time < 0.0 {
+                time + duration
+            } else {
+                time
+            }
+        } else {
+            0.0
+        };
+
+        for (local, joint) in self.local.iter_mut().zip(self.skeleton.joints.iter()) {
+            *local = joint.rest;
+        }
+
+        for channel in &self.clip.channels {
+            let local = match self.local.get_mut(channel.joint) {
+                Some(local) => local,
+                None => continue,
+            };
+            let (index, t) = match keyframe_position(&channel.times, time) {
+                Some(position) => position,
+                None => continue,
+            };
+            let next = ::std::cmp::min(index + 1, channel.times.len() - 1);
+            let t = match channel.interpolation {
+                Interpolation::Step => 0.0,
+                _ => t,
+            };
+            let tangents = match channel.interpolation {
+                Interpolation::CubicSpline => {
+                    // tangents are per second, the spline runs from 0 to 1 over the span
+                    let span = channel.times[next] - channel.times[index];
+                    channel
+                        .tangents
+                        .get(index)
+                        .and_then(|out| channel.tangents.get(next).map(|inn| (out.1, inn.0)))
+                        .map(|(out, inn)| (out * span, inn * span))
+                }
+                _ => None,
+            };
+
+            match channel.values {
+                ChannelValues::Translation(ref values) => {
+                    local.translation =
+                        interpolate_vector(values[index], values[next], t, tangents);
+                }
+                ChannelValues::Rotation(ref values) => {
+                    local.rotation = match tangents {
+                        Some(tangents) => {
+                            let (a, b) = (values[index].coords, values[next].coords);
+                            let value = hermite(a, b, t, tangents);
+                            na::UnitQuaternion::from_quaternion(na::Quaternion::from(value))
+                        }
+                        None => values[index]
+                            .try_slerp(&values[next], t, 1.0e-6)
+                            .unwrap_or(values[index]),
+                    };
+                }
+                ChannelValues::Scale(ref values) => {
+                    local.scale = interpolate_vector(values[index], values[next], t, tangents);
+                }
+            }
+        }
+
+        self.update_bone_matrices();
+
+        &self.bone_matrices
+    }
+
+    /// Skinning matrices from mesh bind space into animated mesh space, one per joint.
+    pub fn bone_matrices(&self) -> &[na::Matrix4<f32>] {
+        &self.bone_matrices
+    }
+
+    fn update_bone_matrices(&mut self) {
+        let mut world = Vec::with_capacity(self.local.len());
+
+        for (index, joint) in self.skeleton.joints.iter().enumerate() {
+            let local = joint.offset * self.local[index].to_homogeneous();
+            let joint_world = match joint.parent {
+                Some(parent) => world[parent] * local,
+                None => local,
+            };
+            world.push(joint_world);
+            self.bone_matrices[index] = joint_world * joint.inverse_bind;
+        }
+    }
+}
+
+/// Bind space bounds of the vertices each joint moves, to refit the bounds of a skinned mesh
+/// for every pose without skinning its vertices on the CPU.
+///
+/// A skinned vertex is a weighted average of its positions moved by each of its joints, so
+/// it stays inside the joint boxes moved by the bone matrices. The refit box is conservative,
+/// but it may be larger than the deformed mesh.
+#[derive(Clone, Debug)]
+pub struct SkinnedBounds {
+    /// Indexed by joint, `None` for joints without weighted vertices.
+    joints: Vec<Option<AABB<f32>>>,
+    /// Vertices without bone weights, which do not move.
+    fixed: Option<AABB<f32>>,
+}
+
+impl SkinnedBounds {
+    pub fn new(mesh: &Mesh) -> SkinnedBounds {
+        let mut bounds = SkinnedBounds {
+            joints: Vec::new(),
+            fixed: None,
+        };
+        for vertex in &mesh.vertices {
+            let point = AABB::new(vertex.pos, vertex.pos);
+            let weights = match vertex.bones {
+                Some(weights) if weights.weights.iter().any(|&weight| weight > 0.0) => weights,
+                _ => {
+                    merge_aabb(&mut bounds.fixed, &point);
+                    continue;
+                }
+            };
+            for (&joint, &weight) in weights.joints.iter().zip(weights.weights.iter()) {
+                if weight <= 0.0 {
+                    continue;
+                }
+                let joint = joint as usize;
+                if bounds.joints.len() <= joint {
+                    bounds.joints.resize(joint + 1, None);
+                }
+                merge_aabb(&mut bounds.joints[joint], &point);
+            }
+        }
+        bounds
+    }
+
+    /// Bounds of the mesh in animated mesh space, for the bone matrices of a `Pose`.
+    ///
+    /// Joints without a bone matrix keep their bind position. `None` for meshes without
+    /// vertices.
+    pub fn aabb(&self, bone_matrices: &[na::Matrix4<f32>]) -> Option<AABB<f32>> {
+        let mut bounds = self.fixed.clone();
+        for (index, joint) in self.joints.iter().enumerate() {
+            if let Some(ref joint) = *joint {
+                let moved = match bone_matrices.get(index) {
+                    Some(matrix) => transform_aabb(joint, matrix),
+                    None => joint.clone(),
+                };
+                merge_aabb(&mut bounds, &moved);
+            }
+        }
+        bounds
+    }
+}
+
+/// Translation or scale between two keyframes, on the spline if there are tangents.
+fn interpolate_vector(
+    a: na::Vector3<f32>,
+    b: na::Vector3<f32>,
+    t: f32,
+    tangents: Option<KeyframeTangents>,
+) -> na::Vector3<f32> {
+    match tangents {
+        Some(tangents) => {
+            let value = hermite(a.push(0.0), b.push(0.0), t, tangents);
+            na::Vector3::new(value.x, value.y, value.z)
+        }
+        None => a.lerp(&b, t),
+    }
+}
+
+/// Cubic Hermite spline from `a` to `b`, with the out tangent of `a` and the in tangent of
+/// `b` scaled to the keyframe span.
+fn hermite(
+    a: na::Vector4<f32>,
+    b: na::Vector4<f32>,
+    t: f32,
+    (out_a, in_b): KeyframeTangents,
+) -> na::Vector4<f32> {
+    let t2 = t * t;
+    let t3 = t2 * t;
+    a * (2.0 * t3 - 3.0 * t2 + 1.0)
+        + out_a * (t3 - 2.0 * t2 + t)
+        + b * (3.0 * t2 - 2.0 * t3)
+        + in_b * (t3 - t2)
+}
+
+/// Find keyframe index before the time and interpolation factor to the next one.
+fn keyframe_position(times: &[f32], time: f32) -> Option<(usize, f32)> {
+    if times.is_empty() {
+        return None;
+    }
+
+    let next = times.iter().position(|t| *t > time);
+
+    Some(match next {
+        None => (times.len() - 1, 0.0),
+        Some(0) => (0, 0.0),
+        Some(next) => {
+            let index = next - 1;
+            let span = times[next] - times[index];
+            let t = if span > 0.0 {
+                (time - times[index]) / span
+            } else {
+                0.0
+            };
+            (index, t)
+        }
+    })
+}
@@ -0,0 +1,251 @@
+use super::{Mesh, Primitive, Tangents, Vertex, JOB_CHUNK};
+use crate::jobs;
+use nalgebra as na;
+
+/// How vertex tangents are built from triangle UV gradients.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TangentAlgorithm {
+    /// Sum of area-weighted triangle tangents, orthogonalized against the vertex normal.
+    Accumulated,
+    /// Close to MikkTSpace: per-corner tangents are orthogonalized before being weighted
+    /// by corner angle, and vertices shared by triangles with mirrored UVs are split.
+    /// Results are not bit-exact with the reference implementation.
+    AngleWeighted,
+}
+
+/// Tangent of a triangle corner, already projected to the vertex normal plane.
+struct Corner {
+    vertex: usize,
+    tangent: na::Vector3<f32>,
+    /// `1.0` if bitangent follows `normal x tangent`, `-1.0` for mirrored UVs.
+    handedness: f32,
+    weight: f32,
+}
+
+impl Mesh {
+    /// Fill in missing normals with area-weighted face normals.
+    pub fn calculate_missing_normals(&mut self) {
+        if self.vertices.iter().all(|v| v.normal.is_some()) {
+            return;
+        }
+
+        let vertices = &self.vertices;
+        let face_normals = jobs::map_chunks(&self.primitives, JOB_CHUNK, |primitives| {
+            primitives
+                .iter()
+                .map(|&Primitive::Triangle(a, b, c)| {
+                    let (a, b, c) = (a as usize, b as usize, c as usize);
+                    let e1 = vertices[b].pos - vertices[a].pos;
+                    let e2 = vertices[c].pos - vertices[a].pos;
+                    // cross product length is twice the area, which gives the weighting
+                    e1.cross(&e2)
+                }).collect::<Vec<_>>()
+        });
+
+        let mut sums = vec![na::Vector3::<f32>::zeros(); self.vertices.len()];
+        let face_normals = self.primitives.iter().zip(face_normals.iter().flatten());
+        for (&Primitive::Triangle(a, b, c), face_normal) in face_normals {
+            for &index in &[a, b, c] {
+                sums[index as usize] += face_normal;
+            }
+        }
+
+        for (vertex, sum) in self.vertices.iter_mut().zip(sums) {
+            if vertex.normal.is_none() {
+                vertex.normal = Some(
+                    sum.try_normalize(::std::f32::EPSILON)
+                        .unwrap_or_else(na::Vector3::z),
+                );
+            }
+        }
+    }
+
+    /// Generate tangents only if some vertex is missing them. Missing normals are
+    /// generated first, missing UVs are treated as zero.
+    pub fn ensure_tangents(&mut self, algorithm: TangentAlgorithm) {
+        if self.vertices.iter().any(|v| v.tangents.is_none()) {
+            self.generate_tangents(algorithm);
+        }
+    }
+
+    /// Replace tangents of all vertices. `AngleWeighted` may append vertices.
+    pub fn generate_tangents(&mut self, algorithm: TangentAlgorithm) {
+        self.calculate_missing_normals();
+
+        let mut corners = self.corners(algorithm);
+
+        if algorithm == TangentAlgorithm::AngleWeighted {
+            self.split_mirrored_vertices(&mut corners);
+        }
+
+        let mut sums = vec![(na::Vector3::<f32>::zeros(), 0.0f32); self.vertices.len()];
+        for corner in &corners {
+            let sum = &mut sums[corner.vertex];
+            sum.0 += corner.tangent * corner.weight;
+            sum.1 += corner.handedness * corner.weight;
+        }
+
+        jobs::for_each_chunk_mut(&mut self.vertices, JOB_CHUNK, |first, vertices| {
+            for (vertex, &(tangent, handedness)) in vertices.iter_mut().zip(&sums[first..]) {
+                vertex.tangents = Some(vertex_tangents(vertex, tangent, handedness));
+            }
+        });
+    }
+
+    /// Kept for importers, same as generating with `TangentAlgorithm::Accumulated`.
+    pub fn calculate_tangents(&mut self) {
+        self.generate_tangents(TangentAlgorithm::Accumulated);
+    }
+
+    fn corners(&self, algorithm: TangentAlgorithm) -> Vec<Corner> {
+        let chunks = jobs::map_chunks(&self.primitives, JOB_CHUNK, |primitives| {
+            let mut corners = Vec::with_capacity(primitives.len() * 3);
+
+            for &Primitive::Triangle(a, b, c) in primitives {
+                let indices = [a as usize, b as usize, c as usize];
+                let vertices = [
+                    &self.vertices[indices[0]],
+                    &self.vertices[indices[1]],
+                    &self.vertices[indices[2]],
+                ];
+                let (face_tangent, face_bitangent) = triangle_uv_gradients(&vertices);
+
+                for corner in 0..3 {
+                    let vertex = vertices[corner];
+                    let normal = vertex.normal.unwrap_or_else(na::Vector3::z);
+
+                    let tangent = face_tangent - normal * normal.dot(&face_tangent);
+                    let handedness = if normal.cross(&tangent).dot(&face_bitangent) < 0.0 {
+                        -1.0
+                    } else {
+                        1.0
+                    };
+
+                    let (tangent, weight) = match algorithm {
+                        TangentAlgorithm::Accumulated => {
+                            // unnormalized face tangent carries the area weighting
+                            (tangent, 1.0)
+                        }
+                        TangentAlgorithm::AngleWeighted => {
+                            let next = vertices[(corner + 1) % 3].pos - vertex.pos;
+                            let previous = vertices[(corner + 2) % 3].pos - vertex.pos;
+                            let angle = match (
+                                next.try_normalize(::std::f32::EPSILON),
+                                previous.try_normalize(::std::f32::EPSILON),
+                            ) {
+                                (Some(next), Some(previous)) => {
+                                    next.dot(&previous).max(-1.0).min(1.0).acos()
+                                }
+                                _ => 0.0,
+                            };
+                            let tangent = tangent
+                                .try_normalize(::std::f32::EPSILON)
+                                .unwrap_or_else(na::Vector3::zeros);
+                            (tangent, angle)
+                        }
+                    };
+
+                    corners.push(Corner {
+                        vertex: indices[corner],
+                        tangent,
+                        handedness,
+                        weight,
+                    });
+                }
+            }
+
+            corners
+        });
+        chunks.into_iter().flatten().collect()
+    }
+
+    /// Duplicate vertices used by corners of both handedness, and point mirrored
+    /// corners to the copies.
+    fn split_mirrored_vertices(&mut self, corners: &mut [Corner]) {
+        let mut handedness = vec![(false, false); self.vertices.len()];
+        for corner in corners.iter() {
+            let entry = &mut handedness[corner.vertex];
+            if corner.handedness > 0.0 {
+                entry.0 = true;
+            } else {
+                entry.1 = true;
+            }
+        }
+
+        let mut copies = vec![None; self.vertices.len()];
+        let mut sources = (0..self.vertices.len()).collect::<Vec<_>>();
+        for (index, &(right, left)) in handedness.iter().enumerate() {
+            if right && left {
+                copies[index] = Some(self.vertices.len());
+                let copy = self.vertices[index];
+                self.vertices.push(copy);
+                sources.push(index);
+            }
+        }
+        self.select_morph_vertices(&sources);
+
+        for corner in corners.iter_mut() {
+            if let (Some(copy), true) = (copies[corner.vertex], corner.handedness < 0.0) {
+                corner.vertex = copy;
+            }
+        }
+
+        // corners are stored in primitive order
+        for (primitive, corners) in self.primitives.iter_mut().zip(corners.chunks(3)) {
+            *primitive = Primitive::Triangle(
+                corners[0].vertex as u32,
+                corners[1].vertex as u32,
+                corners[2].vertex as u32,
+            );
+        }
+    }
+}
+
+/// Tangent and bitangent directions of the triangle in UV space, not normalized.
+///
+/// V is flipped to match the texture coordinates uploaded by the renderers.
+fn triangle_uv_gradients(vertices: &[&Vertex; 3]) -> (na::Vector3<f32>, na::Vector3<f32>) {
+    let uv = |v: &Vertex| v.uv.unwrap_or_else(na::Vector2::zeros);
+    let (uv0, uv1, uv2) = (uv(vertices[0]), uv(vertices[1]), uv(vertices[2]));
+
+    let e1 = vertices[1].pos - vertices[0].pos;
+    let e2 = vertices[2].pos - vertices[0].pos;
+    let (du1, dv1) = (uv1.x - uv0.x, uv0.y - uv1.y);
+    let (du2, dv2) = (uv2.x - uv0.x, uv0.y - uv2.y);
+
+    let det = du1 * dv2 - du2 * dv1;
+    if det.abs() < ::std::f32::EPSILON {
+        // degenerate UVs, pick any direction along the triangle
+        let tangent = e1;
+        let bitangent = e1.cross(&e2).cross(&e1);
+        return (tangent, bitangent);
+    }
+
+    // the determinant sign keeps mirrored triangles pointing the same way
+    let scale = det.abs();
+    let tangent = (e1 * dv2 - e2 * dv1) / det * scale;
+    let bitangent = (e2 * du1 - e1 * du2) / det * scale;
+    (tangent, bitangent)
+}
+
+fn vertex_tangents(vertex: &Vertex, tangent: na::Vector3<f32>, handedness: f32) -> Tangents {
+    let normal = vertex.normal.unwrap_or_else(na::Vector3::z);
+
+    // fall back to any direction perpendicular to the normal
+    let tangent = (tangent - normal * normal.dot(&tangent))
+        .try_normalize(::std::f32::EPSILON)
+        .unwrap_or_else(|| {
+            let axis = if normal.x.abs() < 0.9 {
+                na::Vector3::x()
+            } else {
+                na::Vector3::y()
+            };
+            normal.cross(&axis).normalize()
+        });
+    let sign = if handedness < 0.0 { -1.0 } else { 1.0 };
+
+    Tangents {
+        tangent,
+        bitangent: normal.cross(&tangent) * sign,
+    }
+}
@@ -0,0 +1,94 @@
+use nalgebra as na;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+mod shared_world;
+use self::shared_world::SharedWorld;
+
+/// Rigid body simulation with convex hull colliders resting on a ground plane.
+///
+/// Bodies are removed from the world when their `RigidBody` handle is dropped.
+#[derive(Clone)]
+pub struct World {
+    shared: Rc<RefCell<SharedWorld>>,
+}
+
+impl World {
+    /// Create world with gravity along -Z and a ground plane at the given height.
+    pub fn new(ground_height: f32) -> World {
+        World {
+            shared: Rc::new(RefCell::new(SharedWorld::new(
+                na::Vector3::new(0.0, 0.0, -9.81),
+                ground_height,
+            ))),
+        }
+    }
+
+    /// Add body with collider made from convex hull of the points, which are in body space.
+    /// Returns `None` if the points do not form a hull with volume.
+    pub fn add_convex_body(
+        &self,
+        points: &[na::Point3<f32>],
+        isometry: na::Isometry3<f32>,
+        density: f32,
+    ) -> Option<RigidBody> {
+        let key = self
+            .shared
+            .borrow_mut()
+            .add_convex_body(points, isometry, density)?;
+
+        Some(RigidBody {
+            shared: self.shared.clone(),
+            key,
+        })
+    }
+
+    /// Advance the simulation, in fixed substeps.
+    pub fn step(&self, delta: f32) {
+        self.shared.borrow_mut().step(delta);
+    }
+
+    /// Launch all bodies with the velocity and a spin of up to `max_spin` radians per second.
+    pub fn throw_all(&self, linear_velocity: na::Vector3<f32>, max_spin: f32) {
+        self.shared
+            .borrow_mut()
+            .throw_all(linear_velocity, max_spin);
+    }
+}
+
+/// Handle to a body in the `World`.
+pub struct RigidBody {
+    shared: Rc<RefCell<SharedWorld>>,
+    key: usize,
+}
+
+impl RigidBody {
+    pub fn isometry(&self) -> na::Isometry3<f32> {
+        self.shared.borrow().body(self.key).isometry()
+    }
+
+    /// Move the body, i.e. while it is dragged. Velocity is reset.
+    pub fn set_isometry(&self, isometry: na::Isometry3<f32>) {
+        self.shared
+            .borrow_mut()
+            .body_mut(self.key)
+            .set_isometry(isometry);
+    }
+
+    pub fn set_velocity(&self, linear: na::Vector3<f32>, angular: na::Vector3<f32>) {
+        self.shared
+            .borrow_mut()
+            .body_mut(self.key)
+            .set_velocity(linear, angular);
+    }
+
+    pub fn is_sleeping(&self) -> bool {
+        self.shared.borrow().body(self.key).is_sleeping()
+    }
+}
+
+impl Drop for RigidBody {
+    fn drop(&mut self) {
+        self.shared.borrow_mut().remove_body(self.key);
+    }
+}
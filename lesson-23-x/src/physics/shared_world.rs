@@ -0,0 +1,348 @@
+use nalgebra as na;
+use ncollide3d::bounding_volume;
+use ncollide3d::query;
+use ncollide3d::shape::ConvexHull;
+use slab::Slab;
+
+const SUBSTEP: f32 = 1.0 / 120.0;
+const MAX_SUBSTEPS: usize = 8;
+const SOLVER_ITERATIONS: usize = 8;
+const RESTITUTION: f32 = 0.3;
+/// Slower impacts do not bounce, so that resting bodies settle.
+const RESTITUTION_THRESHOLD: f32 = 1.0;
+const FRICTION: f32 = 0.6;
+/// Allowed penetration, which keeps contacts alive between steps.
+const SLOP: f32 = 0.005;
+const POSITION_CORRECTION: f32 = 0.4;
+const LINEAR_DAMPING: f32 = 0.999;
+const ANGULAR_DAMPING: f32 = 0.98;
+const SLEEP_VELOCITY: f32 = 0.05;
+const SLEEP_TIME: f32 = 0.5;
+
+pub struct Body {
+    /// World position of the center of mass.
+    position: na::Point3<f32>,
+    rotation: na::UnitQuaternion<f32>,
+    /// Center of mass in body space.
+    center_of_mass: na::Point3<f32>,
+    linear_velocity: na::Vector3<f32>,
+    angular_velocity: na::Vector3<f32>,
+    inverse_mass: f32,
+    /// Diagonal of inverse inertia tensor in body space.
+    inverse_inertia: na::Vector3<f32>,
+    hull: ConvexHull<f32>,
+    rest_time: f32,
+    sleeping: bool,
+}
+
+impl Body {
+    pub fn isometry(&self) -> na::Isometry3<f32> {
+        let origin = self.position - self.rotation * self.center_of_mass.coords;
+        na::Isometry3::from_parts(na::Translation3::from(origin.coords), self.rotation)
+    }
+
+    pub fn set_isometry(&mut self, isometry: na::Isometry3<f32>) {
+        self.rotation = isometry.rotation;
+        self.position = isometry * self.center_of_mass;
+        self.set_velocity(na::Vector3::zeros(), na::Vector3::zeros());
+    }
+
+    pub fn set_velocity(&mut self, linear: na::Vector3<f32>, angular: na::Vector3<f32>) {
+        self.linear_velocity = linear;
+        self.angular_velocity = angular;
+        self.wake();
+    }
+
+    pub fn is_sleeping(&self) -> bool {
+        self.sleeping
+    }
+
+    fn wake(&mut self) {
+        self.sleeping = false;
+        self.rest_time = 0.0;
+    }
+
+    fn inverse_inertia_world(&self) -> na::Matrix3<f32> {
+        let rotation = self.rotation.to_rotation_matrix();
+        rotation.matrix()
+            * na::Matrix3::from_diagonal(&self.inverse_inertia)
+            * rotation.matrix().transpose()
+    }
+
+    fn velocity_at(&self, point: &na::Point3<f32>) -> na::Vector3<f32> {
+        self.linear_velocity + self.angular_velocity.cross(&(point - self.position))
+    }
+
+    fn apply_impulse(&mut self, impulse: na::Vector3<f32>, point: &na::Point3<f32>) {
+        self.linear_velocity += impulse * self.inverse_mass;
+        self.angular_velocity +=
+            self.inverse_inertia_world() * (point - self.position).cross(&impulse);
+    }
+
+    /// Inverse of the mass felt by an impulse along the direction at the point.
+    fn inverse_effective_mass(&self, point: &na::Point3<f32>, direction: &na::Vector3<f32>) -> f32 {
+        let r = point - self.position;
+        let angular = (self.inverse_inertia_world() * r.cross(direction)).cross(&r);
+        self.inverse_mass + direction.dot(&angular)
+    }
+}
+
+/// Contact of body `a` with body `b`, or with the ground if `b` is `None`.
+struct Contact {
+    a: usize,
+    b: Option<usize>,
+    point: na::Point3<f32>,
+    /// Direction in which `a` is pushed out.
+    normal: na::Vector3<f32>,
+    depth: f32,
+}
+
+pub struct SharedWorld {
+    gravity: na::Vector3<f32>,
+    ground_height: f32,
+    bodies: Slab<Body>,
+    accumulator: f32,
+}
+
+impl SharedWorld {
+    pub fn new(gravity: na::Vector3<f32>, ground_height: f32) -> SharedWorld {
+        SharedWorld {
+            gravity,
+            ground_height,
+            bodies: Slab::new(),
+            accumulator: 0.0,
+        }
+    }
+
+    pub fn add_convex_body(
+        &mut self,
+        points: &[na::Point3<f32>],
+        isometry: na::Isometry3<f32>,
+        density: f32,
+    ) -> Option<usize> {
+        let hull = ConvexHull::try_from_points(points)?;
+
+        // inertia of the bounding box is close enough for dice-like shapes
+        let aabb = bounding_volume::point_cloud_aabb(&na::Isometry3::identity(), hull.points());
+        let (mins, maxs) = (*aabb.mins(), *aabb.maxs());
+        let size = maxs - mins;
+        let mass = density * size.x * size.y * size.z;
+        if mass <= 0.0 {
+            return None;
+        }
+        let squared = size.component_mul(&size);
+        let inertia = na::Vector3::new(
+            squared.y + squared.z,
+            squared.x + squared.z,
+            squared.x + squared.y,
+        ) * (mass / 12.0);
+        let center_of_mass = na::center(&mins, &maxs);
+
+        Some(self.bodies.insert(Body {
+            position: isometry * center_of_mass,
+            rotation: isometry.rotation,
+            center_of_mass,
+            linear_velocity: na::Vector3::zeros(),
+            angular_velocity: na::Vector3::zeros(),
+            inverse_mass: 1.0 / mass,
+            inverse_inertia: inertia.map(|i| 1.0 / i),
+            hull,
+            rest_time: 0.0,
+            sleeping: false,
+        }))
+    }
+
+    pub fn remove_body(&mut self, key: usize) {
+        self.bodies.remove(key);
+    }
+
+    pub fn body(&self, key: usize) -> &Body {
+        &self.bodies[key]
+    }
+
+    pub fn body_mut(&mut self, key: usize) -> &mut Body {
+        &mut self.bodies[key]
+    }
+
+    pub fn throw_all(&mut self, linear_velocity: na::Vector3<f32>, max_spin: f32) {
+        // deterministic spin per body, so that they do not all tumble the same way
+        let noise = |seed: f32| ((seed * 12.9898).sin() * 43_758.547).fract() * 2.0 - 1.0;
+        for (key, body) in self.bodies.iter_mut() {
+            let seed = key as f32 * 3.0;
+            let spin = na::Vector3::new(noise(seed + 1.0), noise(seed + 2.0), noise(seed + 3.0));
+            body.set_velocity(linear_velocity, spin * max_spin);
+        }
+    }
+
+    pub fn step(&mut self, delta: f32) {
+        self.accumulator = (self.accumulator + delta).min(SUBSTEP * MAX_SUBSTEPS as f32);
+        while self.accumulator >= SUBSTEP {
+            self.substep(SUBSTEP);
+            self.accumulator -= SUBSTEP;
+        }
+    }
+
+    fn substep(&mut self, dt: f32) {
+        for (_, body) in self.bodies.iter_mut().filter(|(_, b)| !b.sleeping) {
+            body.linear_velocity += self.gravity * dt;
+        }
+
+        let contacts = self.find_contacts();
+
+        for iteration in 0..SOLVER_ITERATIONS {
+            for contact in &contacts {
+                self.solve_velocity(contact, iteration == 0);
+            }
+        }
+        for contact in &contacts {
+            self.solve_position(contact);
+        }
+
+        for (_, body) in self.bodies.iter_mut().filter(|(_, b)| !b.sleeping) {
+            body.position += body.linear_velocity * dt;
+            body.rotation =
+                na::UnitQuaternion::from_scaled_axis(body.angular_velocity * dt) * body.rotation;
+            body.linear_velocity *= LINEAR_DAMPING;
+            body.angular_velocity *= ANGULAR_DAMPING;
+
+            if body.linear_velocity.norm() < SLEEP_VELOCITY
+                && body.angular_velocity.norm() < SLEEP_VELOCITY
+            {
+                body.rest_time += dt;
+                if body.rest_time > SLEEP_TIME {
+                    body.sleeping = true;
+                    body.linear_velocity = na::Vector3::zeros();
+                    body.angular_velocity = na::Vector3::zeros();
+                }
+            } else {
+                body.rest_time = 0.0;
+            }
+        }
+    }
+
+    fn find_contacts(&mut self) -> Vec<Contact> {
+        let mut contacts = Vec::new();
+
+        for (key, body) in self.bodies.iter().filter(|(_, b)| !b.sleeping) {
+            let isometry = body.isometry();
+            for point in body.hull.points() {
+                let point = isometry * point;
+                let depth = self.ground_height - point.z;
+                if depth > 0.0 {
+                    contacts.push(Contact {
+                        a: key,
+                        b: None,
+                        point,
+                        normal: na::Vector3::z(),
+                        depth,
+                    });
+                }
+            }
+        }
+
+        let keys: Vec<usize> = self.bodies.iter().map(|(key, _)| key).collect();
+        for (index, &a) in keys.iter().enumerate() {
+            for &b in &keys[index + 1..] {
+                let (body_a, body_b) = (&self.bodies[a], &self.bodies[b]);
+                if body_a.sleeping && body_b.sleeping {
+                    continue;
+                }
+                let contact = query::contact(
+                    &body_a.isometry(),
+                    &body_a.hull,
+                    &body_b.isometry(),
+                    &body_b.hull,
+                    0.0,
+                );
+                if let Some(contact) = contact {
+                    contacts.push(Contact {
+                        a,
+                        b: Some(b),
+                        point: na::center(&contact.world1, &contact.world2),
+                        normal: -contact.normal.into_inner(),
+                        depth: contact.depth,
+                    });
+                }
+            }
+        }
+
+        // bodies hit by awake bodies take part in the solve
+        for contact in &contacts {
+            if let Some(b) = contact.b {
+                self.bodies[contact.a].wake();
+                self.bodies[b].wake();
+            }
+        }
+
+        contacts
+    }
+
+    fn solve_velocity(&mut self, contact: &Contact, bounce: bool) {
+        let relative_velocity = |world: &SharedWorld| {
+            let velocity_a = world.bodies[contact.a].velocity_at(&contact.point);
+            match contact.b {
+                Some(b) => velocity_a - world.bodies[b].velocity_at(&contact.point),
+                None => velocity_a,
+            }
+        };
+        let inverse_effective_mass = |world: &SharedWorld, direction: &na::Vector3<f32>| {
+            let a = world.bodies[contact.a].inverse_effective_mass(&contact.point, direction);
+            match contact.b {
+                Some(b) => a + world.bodies[b].inverse_effective_mass(&contact.point, direction),
+                None => a,
+            }
+        };
+
+        let normal_velocity = relative_velocity(self).dot(&contact.normal);
+        if normal_velocity >= 0.0 {
+            return;
+        }
+        let restitution = if bounce && normal_velocity < -RESTITUTION_THRESHOLD {
+            RESTITUTION
+        } else {
+            0.0
+        };
+        let normal_impulse =
+            -(1.0 + restitution) * normal_velocity / inverse_effective_mass(self, &contact.normal);
+        self.apply_impulse(contact, contact.normal * normal_impulse);
+
+        let velocity = relative_velocity(self);
+        let tangent_velocity = velocity - contact.normal * velocity.dot(&contact.normal);
+        if let Some(tangent) = tangent_velocity.try_normalize(::std::f32::EPSILON) {
+            let friction_impulse = (tangent_velocity.norm()
+                / inverse_effective_mass(self, &tangent))
+            .min(FRICTION * normal_impulse);
+            self.apply_impulse(contact, -tangent * friction_impulse);
+        }
+    }
+
+    fn solve_position(&mut self, contact: &Contact) {
+        let correction = (contact.depth - SLOP).max(0.0) * POSITION_CORRECTION;
+        if correction <= 0.0 {
+            return;
+        }
+
+        let inverse_mass_a = self.bodies[contact.a].inverse_mass;
+        let inverse_mass_b = contact
+            .b
+            .map(|b| self.bodies[b].inverse_mass)
+            .unwrap_or(0.0);
+        // ground does not move, so the body takes the whole correction
+        let share_a = match contact.b {
+            Some(_) => inverse_mass_a / (inverse_mass_a + inverse_mass_b),
+            None => 1.0,
+        };
+
+        self.bodies[contact.a].position += contact.normal * correction * share_a;
+        if let Some(b) = contact.b {
+            self.bodies[b].position -= contact.normal * correction * (1.0 - share_a);
+        }
+    }
+
+    fn apply_impulse(&mut self, contact: &Contact, impulse: na::Vector3<f32>) {
+        self.bodies[contact.a].apply_impulse(impulse, &contact.point);
+        if let Some(b) = contact.b {
+            self.bodies[b].apply_impulse(-impulse, &contact.point);
+        }
+    }
+}
@@ -0,0 +1,223 @@
+use failure;
+use game_loop::VSync;
+use gl;
+use crate::render::WindowSize;
+
+pub mod sdl;
+#[cfg(feature = "winit")]
+pub mod winit;
+
+pub use self::sdl::SdlPlatform;
+#[cfg(feature = "winit")]
+pub use self::winit::WinitPlatform;
+
+/// Backend picked at build time, SDL2 unless built with `--features winit`.
+#[cfg(not(feature = "winit"))]
+pub type DefaultPlatform = SdlPlatform;
+#[cfg(feature = "winit")]
+pub type DefaultPlatform = WinitPlatform;
+
+/// Keys the lessons react to, by position on a US layout.
+#[rustfmt::skip]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Key {
+    A, B, C, D, E, F, G, H, I, J, K, L, M,
+    N, O, P, Q, R, S, T, U, V, W, X, Y, Z,
+    Num0, Num1, Num2, Num3, Num4, Num5, Num6, Num7, Num8, Num9,
+    F1, F2, F3, F4, F5, F6, F7, F8, F9, F10, F11, F12,
+    Escape,
+    Tab,
+    Space,
+    Return,
+    Backspace,
+    Delete,
+    Up,
+    Down,
+    Left,
+    Right,
+    LShift,
+    RShift,
+    LCtrl,
+    RCtrl,
+    LAlt,
+    RAlt,
+    LeftBracket,
+    RightBracket,
+    Semicolon,
+    Apostrophe,
+    Comma,
+    Period,
+    Slash,
+    Backslash,
+    Grave,
+    Equals,
+    Minus,
+    Unknown,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum MouseButton {
+    Left,
+    Middle,
+    Right,
+    Other,
+}
+
+/// Game controller buttons, by position on an Xbox layout.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ControllerButton {
+    A,
+    B,
+    X,
+    Y,
+    Back,
+    Guide,
+    Start,
+    LeftStick,
+    RightStick,
+    LeftShoulder,
+    RightShoulder,
+    DPadUp,
+    DPadDown,
+    DPadLeft,
+    DPadRight,
+}
+
+/// Game controller sticks and triggers.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ControllerAxis {
+    LeftX,
+    LeftY,
+    RightX,
+    RightY,
+    TriggerLeft,
+    TriggerRight,
+}
+
+/// Buttons held during mouse motion.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MouseButtons {
+    pub left: bool,
+    pub middle: bool,
+    pub right: bool,
+}
+
+impl MouseButtons {
+    pub fn set(&mut self, button: MouseButton, pressed: bool) {
+        match button {
+            MouseButton::Left => self.left = pressed,
+            MouseButton::Middle => self.middle = pressed,
+            MouseButton::Right => self.right = pressed,
+            MouseButton::Other => (),
+        }
+    }
+}
+
+/// Window and input events, translated from the backend.
+///
+/// Mouse positions are in window coordinates with the origin at the top left, not in pixels
+/// of the drawable on high DPI displays.
+///
+/// Controller events are merged from all connected controllers, only the SDL backend sends them.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Event {
+    Quit,
+    Resized(WindowSize),
+    KeyDown { key: Key, repeat: bool },
+    KeyUp { key: Key },
+    MouseMotion {
+        x: i32,
+        y: i32,
+        xrel: i32,
+        yrel: i32,
+        buttons: MouseButtons,
+    },
+    MouseButtonDown { button: MouseButton, x: i32, y: i32 },
+    MouseButtonUp { button: MouseButton, x: i32, y: i32 },
+    /// Scroll amount, positive `y` is away from the user.
+    MouseWheel { x: i32, y: i32 },
+    ControllerButtonDown { button: ControllerButton },
+    ControllerButtonUp { button: ControllerButton },
+    /// Sticks from -1 to 1 with positive `LeftY` and `RightY` down, triggers from 0 to 1.
+    ControllerAxisMotion { axis: ControllerAxis, value: f32 },
+    /// A controller was disconnected, buttons and axes of all controllers read as released.
+    ControllerRemoved,
+}
+
+/// Window with a GL context, for `Platform::create`.
+#[derive(Clone, Debug)]
+pub struct WindowSettings {
+    pub title: String,
+    pub width: u32,
+    pub height: u32,
+    pub gl_version: (u8, u8),
+    pub depth_bits: u8,
+    pub stencil_bits: u8,
+    /// Request a debug context, drivers report more through KHR_debug for them.
+    pub debug_context: bool,
+    /// Stick deflection and trigger travel from 0 to 1 that reads as 0, against drift.
+    pub controller_dead_zone: f32,
+    /// Swap interval the context starts with, backends that can not change it later keep it.
+    pub vsync: VSync,
+}
+
+impl WindowSettings {
+    /// Resizable window with a core 4.1 context, 24 bit depth, 8 bit stencil and vsync.
+    pub fn new(title: &str, width: u32, height: u32) -> WindowSettings {
+        WindowSettings {
+            title: title.to_string(),
+            width,
+            height,
+            gl_version: (4, 1),
+            depth_bits: 24,
+            stencil_bits: 8,
+            debug_context: false,
+            controller_dead_zone: 0.15,
+            vsync: VSync::On,
+        }
+    }
+
+    pub fn with_gl_version(mut self, major: u8, minor: u8) -> Self {
+        self.gl_version = (major, minor);
+        self
+    }
+
+    pub fn with_debug_context(mut self, debug_context: bool) -> Self {
+        self.debug_context = debug_context;
+        self
+    }
+
+    pub fn with_controller_dead_zone(mut self, dead_zone: f32) -> Self {
+        self.controller_dead_zone = dead_zone;
+        self
+    }
+
+    pub fn with_vsync(mut self, vsync: VSync) -> Self {
+        self.vsync = vsync;
+        self
+    }
+}
+
+/// Window, GL context and event pump of a windowing backend.
+///
+/// Everything past the platform consumes `Event`, so that rendering and input handling do
+/// not depend on the backend.
+pub trait Platform {
+    /// Open the window and make its GL context current.
+    fn create(settings: &WindowSettings) -> Result<Self, failure::Error>
+    where
+        Self: Sized;
+
+    /// GL functions of the window's context.
+    fn gl(&self) -> &gl::Gl;
+
+    fn window_size(&self) -> WindowSize;
+
+    /// Append events received since the last call.
+    fn poll_events(&mut self, events: &mut Vec<Event>);
+
+    fn swap_buffers(&self);
+
+    /// Change how swaps wait for the retrace and return the mode that is in effect.
+    fn set_vsync(&mut self, vsync: VSync) -> VSync;
+}
@@ -0,0 +1,336 @@
+use failure;
+use failure::err_msg;
+use game_loop::VSync;
+use gl;
+use crate::platform::{
+    ControllerAxis, ControllerButton, Event, Key, MouseButton, MouseButtons, Platform,
+    WindowSettings,
+};
+use crate::render::WindowSize;
+use crate::render_gl::Capabilities;
+use sdl2;
+use sdl2::event::{Event as SdlEvent, WindowEvent};
+use sdl2::keyboard::Scancode;
+use sdl2::video::SwapInterval;
+use std::collections::HashMap;
+use std::os::raw;
+
+pub struct SdlPlatform {
+    gl: gl::Gl,
+    event_pump: sdl2::EventPump,
+    controller_subsystem: sdl2::GameControllerSubsystem,
+    /// Opened as they are connected, events are only sent for open controllers.
+    controllers: Vec<sdl2::controller::GameController>,
+    /// Positions before the dead zone, both axes of a stick are needed to remove it.
+    raw_axes: HashMap<ControllerAxis, f32>,
+    controller_dead_zone: f32,
+    _gl_context: sdl2::video::GLContext,
+    window: sdl2::video::Window,
+    video_subsystem: sdl2::VideoSubsystem,
+    _sdl: sdl2::Sdl,
+}
+
+impl SdlPlatform {
+    pub fn window(&self) -> &sdl2::video::Window {
+        &self.window
+    }
+}
+
+impl Platform for SdlPlatform {
+    fn create(settings: &WindowSettings) -> Result<SdlPlatform, failure::Error> {
+        let sdl = sdl2::init().map_err(err_msg)?;
+        let video_subsystem = sdl.video().map_err(err_msg)?;
+
+        let gl_attr = video_subsystem.gl_attr();
+        gl_attr.set_context_profile(sdl2::video::GLProfile::Core);
+        gl_attr.set_context_version(settings.gl_version.0, settings.gl_version.1);
+        gl_attr.set_accelerated_visual(true);
+        gl_attr.set_double_buffer(true);
+        gl_attr.set_depth_size(settings.depth_bits);
+        gl_attr.set_stencil_size(settings.stencil_bits);
+        if settings.debug_context {
+            gl_attr.set_context_flags().debug().set();
+        }
+
+        let window = video_subsystem
+            .window(&settings.title, settings.width, settings.height)
+            .opengl()
+            .resizable()
+            .allow_highdpi()
+            .build()?;
+
+        let gl_context = window.gl_create_context().map_err(err_msg)?;
+        let gl = gl::Gl::load_with(|s| {
+            video_subsystem.gl_get_proc_address(s) as *const raw::c_void
+        });
+        Capabilities::install(&gl);
+        apply_vsync(&video_subsystem, settings.vsync);
+        let event_pump = sdl.event_pump().map_err(err_msg)?;
+        let controller_subsystem = sdl.game_controller().map_err(err_msg)?;
+
+        Ok(SdlPlatform {
+            gl,
+            event_pump,
+            controller_subsystem,
+            controllers: Vec::new(),
+            raw_axes: HashMap::new(),
+            controller_dead_zone: settings.controller_dead_zone,
+            _gl_context: gl_context,
+            window,
+            video_subsystem,
+            _sdl: sdl,
+        })
+    }
+
+    fn gl(&self) -> &gl::Gl {
+        &self.gl
+    }
+
+    fn window_size(&self) -> WindowSize {
+        let (width, height) = self.window.size();
+        let (highdpi_width, highdpi_height) = self.window.drawable_size();
+        WindowSize {
+            width: width as i32,
+            height: height as i32,
+            highdpi_width: highdpi_width as i32,
+            highdpi_height: highdpi_height as i32,
+        }
+    }
+
+    fn poll_events(&mut self, events: &mut Vec<Event>) {
+        while let Some(event) = self.event_pump.poll_event() {
+            let event = match event {
+                SdlEvent::Quit { .. } => Event::Quit,
+                // also sent for size changes that were not made by the user, unlike `Resized`
+                SdlEvent::Window {
+                    win_event: WindowEvent::SizeChanged(..),
+                    ..
+                } => Event::Resized(self.window_size()),
+                SdlEvent::KeyDown {
+                    scancode: Some(scancode),
+                    repeat,
+                    ..
+                } => Event::KeyDown {
+                    key: key(scancode),
+                    repeat,
+                },
+                SdlEvent::KeyUp {
+                    scancode: Some(scancode),
+                    ..
+                } => Event::KeyUp {
+                    key: key(scancode),
+                },
+                SdlEvent::MouseMotion {
+                    x,
+                    y,
+                    xrel,
+                    yrel,
+                    mousestate,
+                    ..
+                } => Event::MouseMotion {
+                    x,
+                    y,
+                    xrel,
+                    yrel,
+                    buttons: MouseButtons {
+                        left: mousestate.left(),
+                        middle: mousestate.middle(),
+                        right: mousestate.right(),
+                    },
+                },
+                SdlEvent::MouseButtonDown {
+                    mouse_btn, x, y, ..
+                } => Event::MouseButtonDown {
+                    button: mouse_button(mouse_btn),
+                    x,
+                    y,
+                },
+                SdlEvent::MouseButtonUp {
+                    mouse_btn, x, y, ..
+                } => Event::MouseButtonUp {
+                    button: mouse_button(mouse_btn),
+                    x,
+                    y,
+                },
+                SdlEvent::MouseWheel { x, y, .. } => Event::MouseWheel { x, y },
+                // also sent for controllers connected before start
+                SdlEvent::ControllerDeviceAdded { which, .. } => {
+                    match self.controller_subsystem.open(which) {
+                        Ok(controller) => {
+                            println!("Game controller connected: {}", controller.name());
+                            self.controllers.push(controller);
+                        }
+                        Err(e) => println!("Failed to open game controller {}: {}", which, e),
+                    }
+                    continue;
+                }
+                SdlEvent::ControllerDeviceRemoved { which, .. } => {
+                    if let Some(index) = self
+                        .controllers
+                        .iter()
+                        .position(|controller| controller.instance_id() == which)
+                    {
+                        let controller = self.controllers.remove(index);
+                        println!("Game controller disconnected: {}", controller.name());
+                    }
+                    self.raw_axes.clear();
+                    Event::ControllerRemoved
+                }
+                SdlEvent::ControllerButtonDown { button, .. } => Event::ControllerButtonDown {
+                    button: controller_button(button),
+                },
+                SdlEvent::ControllerButtonUp { button, .. } => Event::ControllerButtonUp {
+                    button: controller_button(button),
+                },
+                SdlEvent::ControllerAxisMotion { axis, value, .. } => {
+                    let axis = controller_axis(axis);
+                    self.raw_axes
+                        .insert(axis, (f32::from(value) / 32767.0).max(-1.0));
+                    self.push_axis_events(axis, events);
+                    continue;
+                }
+                _ => continue,
+            };
+            events.push(event);
+        }
+    }
+
+    fn swap_buffers(&self) {
+        self.window.gl_swap_window();
+    }
+
+    fn set_vsync(&mut self, vsync: VSync) -> VSync {
+        apply_vsync(&self.video_subsystem, vsync)
+    }
+}
+
+/// Set swap interval of the current GL context and return the mode that is in effect.
+///
+/// Adaptive vsync falls back to regular vsync where late swap tearing is not supported.
+fn apply_vsync(video: &sdl2::VideoSubsystem, vsync: VSync) -> VSync {
+    let interval = match vsync {
+        VSync::Off => SwapInterval::Immediate,
+        VSync::On => SwapInterval::VSync,
+        VSync::Adaptive => SwapInterval::LateSwapTearing,
+    };
+
+    if video.gl_set_swap_interval(interval) {
+        return vsync;
+    }
+    if vsync == VSync::Adaptive {
+        return apply_vsync(video, VSync::On);
+    }
+    match video.gl_get_swap_interval() {
+        SwapInterval::Immediate => VSync::Off,
+        SwapInterval::VSync => VSync::On,
+        SwapInterval::LateSwapTearing => VSync::Adaptive,
+    }
+}
+
+fn mouse_button(button: sdl2::mouse::MouseButton) -> MouseButton {
+    match button {
+        sdl2::mouse::MouseButton::Left => MouseButton::Left,
+        sdl2::mouse::MouseButton::Middle => MouseButton::Middle,
+        sdl2::mouse::MouseButton::Right => MouseButton::Right,
+        _ => MouseButton::Other,
+    }
+}
+
+impl SdlPlatform {
+    /// Moving one axis of a stick changes both, if the stick leaves or enters the dead zone.
+    fn push_axis_events(&self, axis: ControllerAxis, events: &mut Vec<Event>) {
+        let raw = |axis| self.raw_axes.get(&axis).cloned().unwrap_or(0.0);
+        let dead_zone = self.controller_dead_zone;
+        let stick = match axis {
+            ControllerAxis::LeftX | ControllerAxis::LeftY => {
+                Some((ControllerAxis::LeftX, ControllerAxis::LeftY))
+            }
+            ControllerAxis::RightX | ControllerAxis::RightY => {
+                Some((ControllerAxis::RightX, ControllerAxis::RightY))
+            }
+            ControllerAxis::TriggerLeft | ControllerAxis::TriggerRight => None,
+        };
+
+        match stick {
+            Some((x_axis, y_axis)) => {
+                let (x, y) = stick_dead_zone(raw(x_axis), raw(y_axis), dead_zone);
+                events.push(Event::ControllerAxisMotion {
+                    axis: x_axis,
+                    value: x,
+                });
+                events.push(Event::ControllerAxisMotion {
+                    axis: y_axis,
+                    value: y,
+                });
+            }
+            None => events.push(Event::ControllerAxisMotion {
+                axis,
+                value: trigger_dead_zone(raw(axis), dead_zone),
+            }),
+        }
+    }
+}
+
+/// Stick position with a round dead zone removed, the rest is rescaled to start from 0.
+///
+/// A round dead zone does not snap diagonal movement to the axes like one per axis would.
+fn stick_dead_zone(x: f32, y: f32, dead_zone: f32) -> (f32, f32) {
+    let length = (x * x + y * y).sqrt();
+    if length <= dead_zone {
+        return (0.0, 0.0);
+    }
+    let scale = ((length - dead_zone) / (1.0 - dead_zone)).min(1.0) / length;
+    (x * scale, y * scale)
+}
+
+fn trigger_dead_zone(value: f32, dead_zone: f32) -> f32 {
+    if value <= dead_zone {
+        0.0
+    } else {
+        ((value - dead_zone) / (1.0 - dead_zone)).min(1.0)
+    }
+}
+
+/// `ControllerButton` and `ControllerAxis` variants are named after SDL's.
+macro_rules! same_names {
+    ($value:expr, $from:ident => $to:ident, $($name:ident),*) => {
+        match $value {
+            $($from::$name => $to::$name,)*
+        }
+    };
+}
+
+fn controller_button(button: sdl2::controller::Button) -> ControllerButton {
+    use sdl2::controller::Button;
+    same_names!(
+        button, Button => ControllerButton, A, B, X, Y, Back, Guide, Start, LeftStick,
+        RightStick, LeftShoulder, RightShoulder, DPadUp, DPadDown, DPadLeft, DPadRight
+    )
+}
+
+fn controller_axis(axis: sdl2::controller::Axis) -> ControllerAxis {
+    use sdl2::controller::Axis;
+    same_names!(
+        axis, Axis => ControllerAxis, LeftX, LeftY, RightX, RightY, TriggerLeft, TriggerRight
+    )
+}
+
+/// `Key` variants are named after scancodes.
+macro_rules! scancode_keys {
+    ($scancode:expr, $($name:ident),*) => {
+        match $scancode {
+            $(Scancode::$name => Key::$name,)*
+            _ => Key::Unknown,
+        }
+    };
+}
+
+fn key(scancode: Scancode) -> Key {
+    scancode_keys!(
+        scancode, A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T, U, V, W, X, Y, Z,
+        Num0, Num1, Num2, Num3, Num4, Num5, Num6, Num7, Num8, Num9, F1, F2, F3, F4, F5, F6, F7,
+        F8, F9, F10, F11, F12, Escape, Tab, Space, Return, Backspace, Delete, Up, Down, Left,
+        Right, LShift, RShift, LCtrl, RCtrl, LAlt, RAlt, LeftBracket, RightBracket, Semicolon,
+        Apostrophe, Comma, Period, Slash, Backslash, Grave, Equals, Minus
+    )
+}
@@ -0,0 +1,214 @@
+use failure;
+use failure::err_msg;
+use game_loop::VSync;
+use gl;
+use glutin;
+use glutin::dpi::LogicalSize;
+use glutin::{ElementState, VirtualKeyCode, WindowEvent};
+use crate::platform::{Event, Key, MouseButton, MouseButtons, Platform, WindowSettings};
+use crate::render::WindowSize;
+use crate::render_gl::Capabilities;
+use std::collections::HashSet;
+
+/// Window through winit, with GL context from glutin.
+///
+/// Keys are mapped from virtual key codes, so they follow the keyboard layout instead of
+/// the key position like with SDL2.
+pub struct WinitPlatform {
+    gl: gl::Gl,
+    events_loop: glutin::EventsLoop,
+    context: glutin::WindowedContext<glutin::PossiblyCurrent>,
+    vsync: VSync,
+    cursor: Option<(i32, i32)>,
+    buttons: MouseButtons,
+    pressed_keys: HashSet<Key>,
+}
+
+impl Platform for WinitPlatform {
+    /// Vsync is fixed at context creation, adaptive vsync is created as regular vsync.
+    fn create(settings: &WindowSettings) -> Result<WinitPlatform, failure::Error> {
+        let events_loop = glutin::EventsLoop::new();
+        let window_builder = glutin::WindowBuilder::new()
+            .with_title(settings.title.clone())
+            .with_dimensions(LogicalSize::new(
+                f64::from(settings.width),
+                f64::from(settings.height),
+            ))
+            .with_resizable(true);
+
+        let context = glutin::ContextBuilder::new()
+            .with_gl(glutin::GlRequest::Specific(
+                glutin::Api::OpenGl,
+                settings.gl_version,
+            ))
+            .with_gl_profile(glutin::GlProfile::Core)
+            .with_gl_debug_flag(settings.debug_context)
+            .with_double_buffer(Some(true))
+            .with_depth_buffer(settings.depth_bits)
+            .with_stencil_buffer(settings.stencil_bits)
+            .with_vsync(settings.vsync != VSync::Off)
+            .build_windowed(window_builder, &events_loop)
+            .map_err(|e| err_msg(e.to_string()))?;
+        let context = unsafe { context.make_current() }
+            .map_err(|(_, e)| err_msg(e.to_string()))?;
+
+        let gl = gl::Gl::load_with(|s| context.get_proc_address(s) as *const _);
+        Capabilities::install(&gl);
+
+        Ok(WinitPlatform {
+            gl,
+            events_loop,
+            context,
+            vsync: match settings.vsync {
+                VSync::Off => VSync::Off,
+                VSync::On | VSync::Adaptive => VSync::On,
+            },
+            cursor: None,
+            buttons: MouseButtons::default(),
+            pressed_keys: HashSet::new(),
+        })
+    }
+
+    fn gl(&self) -> &gl::Gl {
+        &self.gl
+    }
+
+    fn window_size(&self) -> WindowSize {
+        let window = self.context.window();
+        let size = window
+            .get_inner_size()
+            .unwrap_or_else(|| LogicalSize::new(1.0, 1.0));
+        let highdpi_size = size.to_physical(window.get_hidpi_factor());
+        WindowSize {
+            width: size.width.round() as i32,
+            height: size.height.round() as i32,
+            highdpi_width: highdpi_size.width.round() as i32,
+            highdpi_height: highdpi_size.height.round() as i32,
+        }
+    }
+
+    fn poll_events(&mut self, events: &mut Vec<Event>) {
+        let mut window_events = Vec::new();
+        self.events_loop.poll_events(|event| {
+            if let glutin::Event::WindowEvent { event, .. } = event {
+                window_events.push(event);
+            }
+        });
+
+        for event in window_events {
+            if let Some(event) = self.translate(event) {
+                events.push(event);
+            }
+        }
+    }
+
+    fn swap_buffers(&self) {
+        if let Err(e) = self.context.swap_buffers() {
+            println!("Failed to swap buffers: {}", e);
+        }
+    }
+
+    /// Changing it needs a new context with glutin, this keeps the mode it was created with.
+    fn set_vsync(&mut self, _vsync: VSync) -> VSync {
+        self.vsync
+    }
+}
+
+impl WinitPlatform {
+    fn translate(&mut self, event: WindowEvent) -> Option<Event> {
+        Some(match event {
+            WindowEvent::CloseRequested => Event::Quit,
+            WindowEvent::Resized(size) => {
+                let dpi_factor = self.context.window().get_hidpi_factor();
+                self.context.resize(size.to_physical(dpi_factor));
+                Event::Resized(self.window_size())
+            }
+            WindowEvent::KeyboardInput { input, .. } => {
+                let key = input.virtual_keycode.map(key).unwrap_or(Key::Unknown);
+                match input.state {
+                    ElementState::Pressed => Event::KeyDown {
+                        key,
+                        repeat: !self.pressed_keys.insert(key),
+                    },
+                    ElementState::Released => {
+                        self.pressed_keys.remove(&key);
+                        Event::KeyUp { key }
+                    }
+                }
+            }
+            WindowEvent::CursorMoved { position, .. } => {
+                let (x, y) = (position.x.round() as i32, position.y.round() as i32);
+                let (xrel, yrel) = match self.cursor {
+                    Some((previous_x, previous_y)) => (x - previous_x, y - previous_y),
+                    None => (0, 0),
+                };
+                self.cursor = Some((x, y));
+                Event::MouseMotion {
+                    x,
+                    y,
+                    xrel,
+                    yrel,
+                    buttons: self.buttons,
+                }
+            }
+            WindowEvent::CursorLeft { .. } => {
+                self.cursor = None;
+                return None;
+            }
+            WindowEvent::MouseInput { state, button, .. } => {
+                let button = mouse_button(button);
+                let pressed = state == ElementState::Pressed;
+                self.buttons.set(button, pressed);
+                let (x, y) = self.cursor.unwrap_or((0, 0));
+                if pressed {
+                    Event::MouseButtonDown { button, x, y }
+                } else {
+                    Event::MouseButtonUp { button, x, y }
+                }
+            }
+            WindowEvent::MouseWheel { delta, .. } => match delta {
+                glutin::MouseScrollDelta::LineDelta(x, y) => Event::MouseWheel {
+                    x: x.round() as i32,
+                    y: y.round() as i32,
+                },
+                glutin::MouseScrollDelta::PixelDelta(position) => Event::MouseWheel {
+                    x: position.x.signum() as i32,
+                    y: position.y.signum() as i32,
+                },
+            },
+            _ => return None,
+        })
+    }
+}
+
+fn mouse_button(button: glutin::MouseButton) -> MouseButton {
+    match button {
+        glutin::MouseButton::Left => MouseButton::Left,
+        glutin::MouseButton::Middle => MouseButton::Middle,
+        glutin::MouseButton::Right => MouseButton::Right,
+        glutin::MouseButton::Other(_) => MouseButton::Other,
+    }
+}
+
+/// Letters and function keys share names, the rest is listed.
+macro_rules! virtual_keys {
+    ($code:expr, $($name:ident),* ; $($from:ident => $to:ident),*) => {
+        match $code {
+            $(VirtualKeyCode::$name => Key::$name,)*
+            $(VirtualKeyCode::$from => Key::$to,)*
+            _ => Key::Unknown,
+        }
+    };
+}
+
+fn key(code: VirtualKeyCode) -> Key {
+    virtual_keys!(
+        code, A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T, U, V, W, X, Y, Z,
+        F1, F2, F3, F4, F5, F6, F7, F8, F9, F10, F11, F12, Escape, Tab, Space, Return, Delete,
+        Up, Down, Left, Right, LShift, RShift, LAlt, RAlt, Semicolon, Apostrophe, Comma,
+        Period, Slash, Backslash, Grave, Equals, Minus;
+        Key0 => Num0, Key1 => Num1, Key2 => Num2, Key3 => Num3, Key4 => Num4, Key5 => Num5,
+        Key6 => Num6, Key7 => Num7, Key8 => Num8, Key9 => Num9, Back => Backspace,
+        LControl => LCtrl, RControl => RCtrl, LBracket => LeftBracket, RBracket => RightBracket
+    )
+}
@@ -0,0 +1,81 @@
+use crate::na::Vector3;
+
+/// How fog grows with the distance from the camera.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum FogMode {
+    Off,
+    /// Grows evenly from `Fog::start` to `Fog::end`.
+    Linear,
+    Exponential,
+    /// Stays thin near the camera and thickens faster than `Exponential`.
+    ExponentialSquared,
+}
+
+impl FogMode {
+    /// Mode value in the `Fog` uniform block, see `shaders/common/fog.glsl`.
+    pub fn shader_index(self) -> i32 {
+        match self {
+            FogMode::Off => 0,
+            FogMode::Linear => 1,
+            FogMode::Exponential => 2,
+            FogMode::ExponentialSquared => 3,
+        }
+    }
+
+    pub fn next(self) -> FogMode {
+        match self {
+            FogMode::Off => FogMode::Linear,
+            FogMode::Linear => FogMode::Exponential,
+            FogMode::Exponential => FogMode::ExponentialSquared,
+            FogMode::ExponentialSquared => FogMode::Off,
+        }
+    }
+}
+
+/// Distance fog applied by the scene materials.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Fog {
+    pub mode: FogMode,
+    /// Linear color the scene fades into, i.e. the sky color at the horizon.
+    pub color: Vector3<f32>,
+    /// Used by the exponential modes, per world unit.
+    pub density: f32,
+    /// Distances where linear fog starts and becomes opaque.
+    pub start: f32,
+    pub end: f32,
+}
+
+impl Default for Fog {
+    fn default() -> Self {
+        Fog {
+            mode: FogMode::Off,
+            color: Vector3::new(0.6, 0.7, 0.8),
+            density: 0.03,
+            start: 10.0,
+            end: 60.0,
+        }
+    }
+}
+
+impl Fog {
+    pub fn uniforms(&self) -> FogUniforms {
+        FogUniforms {
+            color: self.color,
+            mode: self.mode.shader_index(),
+            density: self.density,
+            start: self.start,
+            end: self.end,
+        }
+    }
+}
+
+/// Fog shared by programs through the `Fog` uniform block.
+#[derive(Std140)]
+pub struct FogUniforms {
+    pub color: Vector3<f32>,
+    /// `FogMode::shader_index`.
+    pub mode: i32,
+    pub density: f32,
+    pub start: f32,
+    pub end: f32,
+}
@@ -0,0 +1,85 @@
+use game_loop::VSync;
+use crate::render_gl::SamplerPreset;
+use crate::resources::{self, Resources};
+use serde_json;
+
+#[derive(Debug, Fail)]
+pub enum Error {
+    #[fail(display = "Failed to read or write graphics options")]
+    Resource(#[cause] resources::Error),
+    #[fail(display = "Failed to parse graphics options")]
+    Json(#[cause] serde_json::Error),
+}
+
+impl From<resources::Error> for Error {
+    fn from(other: resources::Error) -> Self {
+        Error::Resource(other)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(other: serde_json::Error) -> Self {
+        Error::Json(other)
+    }
+}
+
+/// Quality settings that can change while running, saved as JSON.
+///
+/// Fields missing from a saved file keep their defaults, so that older files still load.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GraphicsOptions {
+    /// Samples of the scene target, 0 disables MSAA.
+    pub msaa_samples: i32,
+    /// Maximum anisotropy of `SamplerPreset::Anisotropic`.
+    pub anisotropy: f32,
+    pub vsync: VSync,
+    /// Width and height of the shadow map in texels.
+    pub shadow_resolution: i32,
+    pub texture_filter: SamplerPreset,
+}
+
+impl Default for GraphicsOptions {
+    fn default() -> Self {
+        GraphicsOptions {
+            msaa_samples: 4,
+            anisotropy: 16.0,
+            vsync: VSync::Off,
+            shadow_resolution: 2048,
+            texture_filter: SamplerPreset::Texture,
+        }
+    }
+}
+
+impl GraphicsOptions {
+    pub fn load(res: &Resources, name: &str) -> Result<GraphicsOptions, Error> {
+        Ok(serde_json::from_slice(&res.load_bytes(name)?)?)
+    }
+
+    /// Saved options, or defaults if there are none yet or they can not be read.
+    pub fn load_or_default(res: &Resources, name: &str) -> GraphicsOptions {
+        match GraphicsOptions::load(res, name) {
+            Ok(options) => options,
+            Err(Error::Resource(ref e)) if e.is_not_found() => GraphicsOptions::default(),
+            Err(e) => {
+                println!("Failed to load {}, using defaults: {}", name, e);
+                GraphicsOptions::default()
+            }
+        }
+    }
+
+    pub fn save(&self, res: &Resources, name: &str) -> Result<(), Error> {
+        res.write_bytes(name, &serde_json::to_vec_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Next shadow map size from 512 to 4096 texels.
+    pub fn next_shadow_resolution(&self) -> i32 {
+        match self.shadow_resolution {
+            r if r < 1024 => 1024,
+            r if r < 2048 => 2048,
+            r if r < 4096 => 4096,
+            _ => 512,
+        }
+    }
+}
@@ -1,4 +1,13 @@
-use crate::na::Vector3;
+use crate::mesh;
+use crate::na::{Matrix4, Vector3, Vector4};
+
+mod fog;
+mod graphics_options;
+mod passes;
+
+pub use self::fog::{Fog, FogMode, FogUniforms};
+pub use self::graphics_options::{Error as GraphicsOptionsError, GraphicsOptions};
+pub use self::passes::FramePasses;
 
 pub fn color_red() -> Vector3<f32> {
     Vector3::<f32>::new(1.0, 0.0, 0.0)
@@ -32,6 +41,88 @@ pub fn color_gray() -> Vector3<f32> {
     Vector3::<f32>::new(0.5, 0.5, 0.5)
 }
 
+/// Uniform block binding point of per-frame camera data.
+pub const CAMERA_UNIFORMS_BINDING: u32 = 0;
+
+/// Uniform block binding point of `lights::LightUniforms`.
+pub const LIGHT_UNIFORMS_BINDING: u32 = 1;
+
+/// Uniform block binding point of `FogUniforms`.
+pub const FOG_UNIFORMS_BINDING: u32 = 2;
+
+/// Per-frame camera data, shared by programs through the `Camera` uniform block.
+#[derive(Std140)]
+pub struct CameraUniforms {
+    pub view_projection: Matrix4<f32>,
+    pub camera_pos: Vector3<f32>,
+    /// `MaterialDebugView::shader_index`, read by material shaders.
+    pub debug_view: i32,
+    /// Plane from `render_gl::clip_plane`, used inside `render_gl::ClipPlaneScope`.
+    pub clip_plane: Vector4<f32>,
+}
+
+/// Surface attribute shown as color by material shaders instead of lighting.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MaterialDebugView {
+    Shaded,
+    WorldNormal,
+    WorldTangent,
+    Uv,
+    /// Normal map texel as stored, before the tangent space transform.
+    NormalMap,
+    /// Normal map transformed to world space, the normal used for lighting.
+    MappedNormal,
+    /// Heat map of the lights in each cluster, only materials with clustered lighting.
+    LightClusters,
+}
+
+impl MaterialDebugView {
+    pub fn shader_index(self) -> i32 {
+        match self {
+            MaterialDebugView::Shaded => 0,
+            MaterialDebugView::WorldNormal => 1,
+            MaterialDebugView::WorldTangent => 2,
+            MaterialDebugView::Uv => 3,
+            MaterialDebugView::NormalMap => 4,
+            MaterialDebugView::MappedNormal => 5,
+            MaterialDebugView::LightClusters => 6,
+        }
+    }
+
+    pub fn next(self) -> MaterialDebugView {
+        match self {
+            MaterialDebugView::Shaded => MaterialDebugView::WorldNormal,
+            MaterialDebugView::WorldNormal => MaterialDebugView::WorldTangent,
+            MaterialDebugView::WorldTangent => MaterialDebugView::Uv,
+            MaterialDebugView::Uv => MaterialDebugView::NormalMap,
+            MaterialDebugView::NormalMap => MaterialDebugView::MappedNormal,
+            MaterialDebugView::MappedNormal => MaterialDebugView::LightClusters,
+            MaterialDebugView::LightClusters => MaterialDebugView::Shaded,
+        }
+    }
+}
+
+/// Uniform block binding point of skinning matrices, after `lights::CLUSTER_UNIFORMS_BINDING`.
+pub const BONE_UNIFORMS_BINDING: u32 = 4;
+
+/// Skinning matrices of a single skeleton, shared through the `Bones` uniform block.
+#[derive(Std140)]
+pub struct BoneUniforms {
+    pub bones: [Matrix4<f32>; mesh::MAX_JOINTS],
+}
+
+impl BoneUniforms {
+    /// Copy bone matrices of a sampled pose, joints above the limit are ignored.
+    pub fn from_bone_matrices(matrices: &[Matrix4<f32>]) -> BoneUniforms {
+        let mut bones = [Matrix4::identity(); mesh::MAX_JOINTS];
+        for (bone, matrix) in bones.iter_mut().zip(matrices.iter()) {
+            *bone = *matrix;
+        }
+        BoneUniforms { bones }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct WindowSize {
     pub width: i32,
     pub height: i32,
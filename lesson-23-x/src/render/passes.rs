@@ -0,0 +1,45 @@
+use crate::na::{Vector3, Vector4};
+use crate::render_gl::{BlendMode, DepthMode, RenderPass};
+
+/// Passes of the main window drawn by the lesson each frame, in the order they run.
+///
+/// The shadow, scene and post process targets are owned by their renderers and cleared
+/// there, with the clear color set from `background`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct FramePasses {
+    /// Linear color where nothing is drawn, behind the skybox.
+    pub background: Vector3<f32>,
+}
+
+impl Default for FramePasses {
+    fn default() -> Self {
+        FramePasses {
+            background: Vector3::new(0.3, 0.3, 0.5),
+        }
+    }
+}
+
+impl FramePasses {
+    /// Clear of the window at the start of the frame.
+    pub fn window(&self) -> RenderPass<'static> {
+        RenderPass::new("window")
+            .with_clear_color(Vector4::new(
+                self.background.x,
+                self.background.y,
+                self.background.z,
+                1.0,
+            )).with_clear_depth(1.0)
+    }
+
+    /// Gizmos over the scene, never hidden by it.
+    pub fn overlay(&self) -> RenderPass<'static> {
+        RenderPass::new("overlay").with_depth(DepthMode::Disabled)
+    }
+
+    /// Profiler graphs and panels over the post processed image.
+    pub fn ui(&self) -> RenderPass<'static> {
+        RenderPass::new("ui")
+            .with_depth(DepthMode::Disabled)
+            .with_blend(BlendMode::Alpha)
+    }
+}
@@ -1,4 +1,29 @@
 use gl;
+use crate::render_gl::{debug, FrameStats, StateCache};
+
+/// Type of values in an element array buffer.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum IndexType {
+    U16,
+    U32,
+}
+
+impl IndexType {
+    pub fn gl_type(self) -> gl::types::GLenum {
+        match self {
+            IndexType::U16 => gl::UNSIGNED_SHORT,
+            IndexType::U32 => gl::UNSIGNED_INT,
+        }
+    }
+
+    /// Bytes of one index, i.e. to turn the first index of a draw into a buffer offset.
+    pub fn size(self) -> usize {
+        match self {
+            IndexType::U16 => ::std::mem::size_of::<u16>(),
+            IndexType::U32 => ::std::mem::size_of::<u32>(),
+        }
+    }
+}
 
 pub struct Buffer {
     gl: gl::Gl,
@@ -19,6 +44,23 @@ impl Buffer where {
         Self::new(gl, gl::DRAW_INDIRECT_BUFFER)
     }
 
+    pub fn new_pixel_pack(gl: &gl::Gl) -> Buffer {
+        Self::new(gl, gl::PIXEL_PACK_BUFFER)
+    }
+
+    pub fn new_shader_storage(gl: &gl::Gl) -> Buffer {
+        Self::new(gl, gl::SHADER_STORAGE_BUFFER)
+    }
+
+    pub fn new_dispatch_indirect(gl: &gl::Gl) -> Buffer {
+        Self::new(gl, gl::DISPATCH_INDIRECT_BUFFER)
+    }
+
+    /// Storage of a buffer texture, attached with `TexBuffer`.
+    pub fn new_texture(gl: &gl::Gl) -> Buffer {
+        Self::new(gl, gl::TEXTURE_BUFFER)
+    }
+
     pub fn new(gl: &gl::Gl, buffer_type: gl::types::GLuint) -> Buffer {
         let mut vbo: gl::types::GLuint = 0;
         unsafe {
@@ -32,19 +74,64 @@ impl Buffer where {
         }
     }
 
+    pub fn id(&self) -> gl::types::GLuint {
+        self.vbo
+    }
+
     pub fn bind(&self) {
         unsafe {
             self.gl.BindBuffer(self.buffer_type, self.vbo);
         }
     }
 
+    /// Name shown in debug messages and captures.
+    pub fn set_label(&self, label: &str) {
+        debug::object_label(&self.gl, gl::BUFFER, self.vbo, label);
+    }
+
     pub fn unbind(&self) {
         unsafe {
             self.gl.BindBuffer(self.buffer_type, 0);
         }
     }
 
+    /// Attach the whole buffer to an indexed binding point of its type,
+    /// i.e. a shader storage block binding.
+    pub fn bind_base(&self, index: u32) {
+        unsafe {
+            self.gl.BindBufferBase(self.buffer_type, index, self.vbo);
+        }
+    }
+
+    /// Attach `size` elements of `T` starting at element `offset` to an indexed binding point.
+    pub fn bind_range<T>(&self, index: u32, offset: usize, size: usize) {
+        unsafe {
+            self.gl.BindBufferRange(
+                self.buffer_type,
+                index,
+                self.vbo,
+                (offset * ::std::mem::size_of::<T>()) as gl::types::GLintptr,
+                (size * ::std::mem::size_of::<T>()) as gl::types::GLsizeiptr,
+            );
+        }
+    }
+
+    /// Bind the same buffer object to another target, i.e. storage written by a compute
+    /// shader as an array buffer for drawing. Unbind with `unbind_as`.
+    pub fn bind_as(&self, target: gl::types::GLenum) {
+        unsafe {
+            self.gl.BindBuffer(target, self.vbo);
+        }
+    }
+
+    pub fn unbind_as(&self, target: gl::types::GLenum) {
+        unsafe {
+            self.gl.BindBuffer(target, 0);
+        }
+    }
+
     pub fn static_draw_data<T>(&self, data: &[T]) {
+        FrameStats::record_upload(::std::mem::size_of_val(data));
         unsafe {
             self.gl.BufferData(
                 self.buffer_type, // target
@@ -56,6 +143,7 @@ impl Buffer where {
     }
 
     pub fn stream_draw_data<T>(&self, data: &[T]) {
+        FrameStats::record_upload(::std::mem::size_of_val(data));
         unsafe {
             self.gl.BufferData(
                 self.buffer_type, // target
@@ -66,6 +154,31 @@ impl Buffer where {
         }
     }
 
+    pub fn dynamic_draw_data<T>(&self, data: &[T]) {
+        FrameStats::record_upload(::std::mem::size_of_val(data));
+        unsafe {
+            self.gl.BufferData(
+                self.buffer_type, // target
+                (data.len() * ::std::mem::size_of::<T>()) as gl::types::GLsizeiptr, // size of data in bytes
+                data.as_ptr() as *const gl::types::GLvoid, // pointer to data
+                gl::DYNAMIC_DRAW, // usage
+            );
+        }
+    }
+
+    /// Upload indices of an element array buffer, as 16-bit values if all of them fit.
+    /// Returns the type to draw them with.
+    pub fn static_draw_indices(&self, indices: &[u32]) -> IndexType {
+        if indices.iter().all(|&index| index <= u32::from(::std::u16::MAX)) {
+            let indices = indices.iter().map(|&index| index as u16).collect::<Vec<_>>();
+            self.static_draw_data(&indices);
+            IndexType::U16
+        } else {
+            self.static_draw_data(indices);
+            IndexType::U32
+        }
+    }
+
     pub fn stream_draw_data_null<T>(&self, size: usize) {
         unsafe {
             self.gl.BufferData(
@@ -98,6 +211,33 @@ impl Buffer where {
             position: 0,
         });
     }
+
+    /// Map for reading, waits for the GPU if the buffer is still being written to.
+    ///
+    /// # Safety
+    ///
+    /// Buffer must be bound and hold at least `offset + size` elements of `T`.
+    pub unsafe fn map_buffer_range_read<'r, T>(
+        &self,
+        offset: usize,
+        size: usize,
+    ) -> Option<MappedBuffer<'r, T>> {
+        let ptr = self.gl.MapBufferRange(
+            self.buffer_type,
+            (offset * ::std::mem::size_of::<T>()) as gl::types::GLsizeiptr,
+            (size * ::std::mem::size_of::<T>()) as gl::types::GLsizeiptr,
+            gl::MAP_READ_BIT,
+        );
+        if ptr.is_null() {
+            return None;
+        }
+        Some(MappedBuffer {
+            gl: self.gl.clone(),
+            buffer_type: self.buffer_type,
+            data: ::std::slice::from_raw_parts_mut(ptr as *mut T, size),
+            position: 0,
+        })
+    }
 }
 
 impl Drop for Buffer {
@@ -169,20 +309,48 @@ impl VertexArray {
     }
 
     pub fn bind(&self) {
-        unsafe {
-            self.gl.BindVertexArray(self.vao);
-        }
+        StateCache::bind_vertex_array(&self.gl, self.vao);
     }
 
     pub fn unbind(&self) {
+        StateCache::bind_vertex_array(&self.gl, 0);
+    }
+
+    /// Name shown in debug messages and captures.
+    pub fn set_label(&self, label: &str) {
+        debug::object_label(&self.gl, gl::VERTEX_ARRAY, self.vao, label);
+    }
+
+    /// Draw multiple instances of indexed geometry configured in this vertex array.
+    ///
+    /// Attributes with a divisor advance once per instance instead of once per vertex.
+    pub fn draw_elements_instanced(
+        &self,
+        mode: gl::types::GLenum,
+        index_count: i32,
+        index_type: gl::types::GLenum,
+        instance_count: i32,
+    ) {
+        self.bind();
+
         unsafe {
-            self.gl.BindVertexArray(0);
+            self.gl.DrawElementsInstanced(
+                mode,               // mode
+                index_count,        // index vertex count
+                index_type,         // index type
+                ::std::ptr::null(), // pointer to indices (we are using ebo configured at vao creation)
+                instance_count,     // number of instances
+            );
         }
+        FrameStats::record_draw(mode, index_count, instance_count);
+
+        self.unbind();
     }
 }
 
 impl Drop for VertexArray {
     fn drop(&mut self) {
+        StateCache::vertex_array_deleted(self.vao);
         unsafe {
             self.gl.DeleteVertexArrays(1, &mut self.vao);
         }
@@ -0,0 +1,132 @@
+use gl;
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::ffi::CStr;
+use std::rc::Rc;
+
+/// `GL_MAX_TEXTURE_MAX_ANISOTROPY`, the same value for the EXT and ARB extensions.
+const MAX_TEXTURE_MAX_ANISOTROPY: gl::types::GLenum = 0x84FF;
+
+thread_local! {
+    static CAPABILITIES: RefCell<Option<Rc<Capabilities>>> = RefCell::new(None);
+}
+
+/// Version, extensions and limits of the context, queried once when it is created.
+///
+/// Subsystems check them with `Capabilities::get` and fall back to what the context can do,
+/// i.e. no compute culling before GL 4.3, orphaning instead of persistent mapping, or no
+/// anisotropy, instead of generating GL errors. Like `StateCache`, there is one per thread.
+#[derive(Clone, Debug)]
+pub struct Capabilities {
+    /// Context version as (major, minor).
+    pub version: (i32, i32),
+    pub vendor: String,
+    pub renderer: String,
+    pub extensions: HashSet<String>,
+    /// Largest width and height of 2D textures.
+    pub max_texture_size: i32,
+    pub max_array_texture_layers: i32,
+    /// Most samples of multisampled framebuffers.
+    pub max_samples: i32,
+    /// Anisotropy limit, 1.0 without anisotropic filtering.
+    pub max_anisotropy: f32,
+    /// Compute shaders, GL 4.3.
+    pub compute: bool,
+    /// Shader storage buffers, GL 4.3 or `GL_ARB_shader_storage_buffer_object`.
+    pub storage_buffers: bool,
+    /// Persistently mapped buffers, GL 4.4 or `GL_ARB_buffer_storage`.
+    pub persistent_mapping: bool,
+}
+
+impl Capabilities {
+    /// Query the context, without storing the result.
+    pub fn query(gl: &gl::Gl) -> Capabilities {
+        let version = (get_integer(gl, gl::MAJOR_VERSION), get_integer(gl, gl::MINOR_VERSION));
+        let extensions = (0..get_integer(gl, gl::NUM_EXTENSIONS) as gl::types::GLuint)
+            .filter_map(|index| {
+                let name = unsafe { gl.GetStringi(gl::EXTENSIONS, index) };
+                string_from_gl(name)
+            }).collect::<HashSet<_>>();
+
+        let anisotropic_filtering = extensions.contains("GL_EXT_texture_filter_anisotropic")
+            || extensions.contains("GL_ARB_texture_filter_anisotropic");
+        let mut max_anisotropy: gl::types::GLfloat = 1.0;
+        if anisotropic_filtering {
+            unsafe {
+                gl.GetFloatv(MAX_TEXTURE_MAX_ANISOTROPY, &mut max_anisotropy);
+            }
+        }
+
+        let compute = version >= (4, 3);
+        Capabilities {
+            version,
+            vendor: string_from_gl(unsafe { gl.GetString(gl::VENDOR) }).unwrap_or_default(),
+            renderer: string_from_gl(unsafe { gl.GetString(gl::RENDERER) }).unwrap_or_default(),
+            max_texture_size: get_integer(gl, gl::MAX_TEXTURE_SIZE),
+            max_array_texture_layers: get_integer(gl, gl::MAX_ARRAY_TEXTURE_LAYERS),
+            max_samples: get_integer(gl, gl::MAX_SAMPLES),
+            max_anisotropy,
+            compute,
+            storage_buffers: compute
+                || extensions.contains("GL_ARB_shader_storage_buffer_object"),
+            persistent_mapping: version >= (4, 4) || extensions.contains("GL_ARB_buffer_storage"),
+            extensions,
+        }
+    }
+
+    /// Query a newly created context and keep the result for `get`.
+    pub fn install(gl: &gl::Gl) -> Rc<Capabilities> {
+        let capabilities = Rc::new(Capabilities::query(gl));
+        CAPABILITIES.with(|current| *current.borrow_mut() = Some(capabilities.clone()));
+        capabilities
+    }
+
+    /// Capabilities of the context on this thread, queried on first use if it was created
+    /// without `install`.
+    pub fn get(gl: &gl::Gl) -> Rc<Capabilities> {
+        let installed = CAPABILITIES.with(|current| current.borrow().clone());
+        match installed {
+            Some(capabilities) => capabilities,
+            None => Capabilities::install(gl),
+        }
+    }
+
+    /// Driver lists the extension, such as `GL_ARB_shader_draw_parameters`.
+    pub fn has_extension(&self, extension: &str) -> bool {
+        self.extensions.contains(extension)
+    }
+
+    /// One line per value, i.e. to print at startup.
+    pub fn report(&self) -> Vec<String> {
+        let yes_no = |supported: bool| if supported { "yes" } else { "no" };
+        vec![
+            format!("GL {}.{}, {} {}", self.version.0, self.version.1, self.vendor, self.renderer),
+            format!("{} extensions", self.extensions.len()),
+            format!(
+                "max texture size {}, array layers {}",
+                self.max_texture_size, self.max_array_texture_layers
+            ),
+            format!("max samples {}", self.max_samples),
+            format!("max anisotropy {}", self.max_anisotropy),
+            format!("compute shaders: {}", yes_no(self.compute)),
+            format!("storage buffers: {}", yes_no(self.storage_buffers)),
+            format!("persistent mapping: {}", yes_no(self.persistent_mapping)),
+        ]
+    }
+}
+
+fn get_integer(gl: &gl::Gl, name: gl::types::GLenum) -> i32 {
+    let mut value: gl::types::GLint = 0;
+    unsafe {
+        gl.GetIntegerv(name, &mut value);
+    }
+    value
+}
+
+fn string_from_gl(value: *const gl::types::GLubyte) -> Option<String> {
+    if value.is_null() {
+        return None;
+    }
+    let value = unsafe { CStr::from_ptr(value as *const _) };
+    Some(value.to_string_lossy().into_owned())
+}
@@ -0,0 +1,317 @@
+use gl;
+use image;
+use crate::render_gl::buffer::Buffer;
+use crate::render_gl::Framebuffer;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::thread;
+
+/// Recorded frames are read back this many frames later, so that the CPU never waits for the GPU.
+const FRAMES_IN_FLIGHT: usize = 3;
+
+#[derive(Debug, Fail)]
+pub enum Error {
+    #[fail(display = "Capture size {}x{} is invalid", width, height)]
+    InvalidSize { width: i32, height: i32 },
+    #[fail(display = "Failed to map pixel buffer")]
+    MapFailed,
+    #[fail(display = "Failed to create capture directory {:?}: {}", path, inner)]
+    FailedToCreateDirectory {
+        path: PathBuf,
+        #[cause]
+        inner: io::Error,
+    },
+}
+
+struct PngJob {
+    path: PathBuf,
+    pixels: Vec<u8>,
+    width: u32,
+    height: u32,
+}
+
+impl PngJob {
+    fn write(self) {
+        if let Err(e) = image::save_buffer(
+            &self.path,
+            &self.pixels,
+            self.width,
+            self.height,
+            image::ColorType::RGBA(8),
+        ) {
+            println!("Error writing capture {:?}: {}", self.path, e);
+        }
+    }
+}
+
+/// Save current viewport of the default framebuffer as PNG.
+///
+/// Pixels are read back immediately, only the PNG encoding runs on a background thread.
+pub fn capture_frame<P: AsRef<Path>>(gl: &gl::Gl, path: P) -> Result<(), Error> {
+    let (x, y, width, height) = current_viewport(gl);
+    capture_region(gl, 0, x, y, width, height, path.as_ref())
+}
+
+/// Save first color attachment of the framebuffer as PNG.
+pub fn capture_framebuffer<P: AsRef<Path>>(
+    gl: &gl::Gl,
+    framebuffer: &Framebuffer,
+    path: P,
+) -> Result<(), Error> {
+    capture_region(
+        gl,
+        framebuffer.id(),
+        0,
+        0,
+        framebuffer.width(),
+        framebuffer.height(),
+        path.as_ref(),
+    )
+}
+
+/// Read first color attachment of the framebuffer, first row at the top.
+///
+/// Waits for the GPU to finish drawing, meant for tests and tools rather than every frame.
+pub fn read_framebuffer(
+    gl: &gl::Gl,
+    framebuffer: &Framebuffer,
+) -> Result<image::RgbaImage, Error> {
+    let (width, height) = (framebuffer.width(), framebuffer.height());
+    check_size(width, height)?;
+
+    let pbo = Buffer::new_pixel_pack(gl);
+    read_pixels(gl, &pbo, framebuffer.id(), 0, 0, width, height);
+    let pixels = map_pixels(&pbo, width, height).ok_or(Error::MapFailed)?;
+
+    image::RgbaImage::from_raw(width as u32, height as u32, pixels).ok_or(Error::MapFailed)
+}
+
+fn capture_region(
+    gl: &gl::Gl,
+    fbo: gl::types::GLuint,
+    x: i32,
+    y: i32,
+    width: i32,
+    height: i32,
+    path: &Path,
+) -> Result<(), Error> {
+    check_size(width, height)?;
+
+    let pbo = Buffer::new_pixel_pack(gl);
+    read_pixels(gl, &pbo, fbo, x, y, width, height);
+    let pixels = map_pixels(&pbo, width, height).ok_or(Error::MapFailed)?;
+
+    let job = PngJob {
+        path: path.to_path_buf(),
+        pixels,
+        width: width as u32,
+        height: height as u32,
+    };
+    thread::spawn(move || job.write());
+
+    Ok(())
+}
+
+struct PendingFrame {
+    number: usize,
+    width: i32,
+    height: i32,
+}
+
+/// Continuous capture of numbered frames, for recording lesson videos.
+///
+/// Each frame is read into its own pixel buffer and mapped a few frames later, PNG files
+/// are written in order on a single background thread.
+pub struct FrameRecorder {
+    gl: gl::Gl,
+    directory: PathBuf,
+    slots: Vec<(Buffer, Option<PendingFrame>)>,
+    current: usize,
+    next_frame_number: usize,
+    recording: bool,
+    writer: Option<mpsc::Sender<PngJob>>,
+}
+
+impl FrameRecorder {
+    pub fn new<P: Into<PathBuf>>(gl: &gl::Gl, directory: P) -> FrameRecorder {
+        FrameRecorder {
+            gl: gl.clone(),
+            directory: directory.into(),
+            slots: (0..FRAMES_IN_FLIGHT)
+                .map(|_| (Buffer::new_pixel_pack(gl), None))
+                .collect(),
+            current: 0,
+            next_frame_number: 0,
+            recording: false,
+            writer: None,
+        }
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recording
+    }
+
+    /// Start writing frames to the directory, numbering continues from the last recording.
+    pub fn start(&mut self) -> Result<(), Error> {
+        fs::create_dir_all(&self.directory).map_err(|e| Error::FailedToCreateDirectory {
+            path: self.directory.clone(),
+            inner: e,
+        })?;
+
+        if self.writer.is_none() {
+            let (sender, receiver) = mpsc::channel::<PngJob>();
+            thread::Builder::new()
+                .name("frame recorder".into())
+                .spawn(move || {
+                    for job in receiver {
+                        job.write();
+                    }
+                }).expect("failed to spawn frame recorder thread");
+            self.writer = Some(sender);
+        }
+
+        self.recording = true;
+        Ok(())
+    }
+
+    /// Stop recording and write frames that are still in flight.
+    pub fn stop(&mut self) -> Result<(), Error> {
+        self.recording = false;
+        for _ in 0..self.slots.len() {
+            self.advance()?;
+        }
+        Ok(())
+    }
+
+    pub fn toggle(&mut self) -> Result<(), Error> {
+        if self.recording {
+            self.stop()
+        } else {
+            self.start()
+        }
+    }
+
+    /// Read current viewport of the default framebuffer, call after the frame is drawn.
+    pub fn capture(&mut self) -> Result<(), Error> {
+        if !self.recording {
+            return Ok(());
+        }
+
+        let (x, y, width, height) = current_viewport(&self.gl);
+        check_size(width, height)?;
+
+        // slot is reused, so the frame recorded in it earlier has to be written out first
+        self.flush_slot(self.current)?;
+
+        let number = self.next_frame_number;
+        self.next_frame_number += 1;
+
+        let (ref pbo, ref mut pending) = self.slots[self.current];
+        read_pixels(&self.gl, pbo, 0, x, y, width, height);
+        *pending = Some(PendingFrame {
+            number,
+            width,
+            height,
+        });
+
+        self.current = (self.current + 1) % self.slots.len();
+        Ok(())
+    }
+
+    fn advance(&mut self) -> Result<(), Error> {
+        self.flush_slot(self.current)?;
+        self.current = (self.current + 1) % self.slots.len();
+        Ok(())
+    }
+
+    fn flush_slot(&mut self, index: usize) -> Result<(), Error> {
+        let (ref pbo, ref mut pending) = self.slots[index];
+        let frame = match pending.take() {
+            Some(frame) => frame,
+            None => return Ok(()),
+        };
+
+        let pixels = map_pixels(pbo, frame.width, frame.height).ok_or(Error::MapFailed)?;
+        let job = PngJob {
+            path: self
+                .directory
+                .join(format!("frame_{:06}.png", frame.number)),
+            pixels,
+            width: frame.width as u32,
+            height: frame.height as u32,
+        };
+
+        if let Some(ref writer) = self.writer {
+            // writer thread only stops when the sender is dropped
+            let _ = writer.send(job);
+        }
+        Ok(())
+    }
+}
+
+fn current_viewport(gl: &gl::Gl) -> (i32, i32, i32, i32) {
+    let mut viewport: [gl::types::GLint; 4] = [0; 4];
+    unsafe {
+        gl.GetIntegerv(gl::VIEWPORT, viewport.as_mut_ptr());
+    }
+    (viewport[0], viewport[1], viewport[2], viewport[3])
+}
+
+fn check_size(width: i32, height: i32) -> Result<(), Error> {
+    if width <= 0 || height <= 0 {
+        return Err(Error::InvalidSize { width, height });
+    }
+    Ok(())
+}
+
+/// Start asynchronous copy of RGBA pixels into the pixel buffer.
+fn read_pixels(
+    gl: &gl::Gl,
+    pbo: &Buffer,
+    fbo: gl::types::GLuint,
+    x: i32,
+    y: i32,
+    width: i32,
+    height: i32,
+) {
+    let mut previous_fbo: gl::types::GLint = 0;
+
+    pbo.bind();
+    pbo.stream_draw_data_null::<u8>((width * height * 4) as usize);
+    unsafe {
+        gl.GetIntegerv(gl::READ_FRAMEBUFFER_BINDING, &mut previous_fbo);
+        gl.BindFramebuffer(gl::READ_FRAMEBUFFER, fbo);
+        gl.PixelStorei(gl::PACK_ALIGNMENT, 1);
+        gl.ReadPixels(
+            x,
+            y,
+            width,
+            height,
+            gl::RGBA,
+            gl::UNSIGNED_BYTE,
+            ::std::ptr::null_mut(),
+        );
+        gl.BindFramebuffer(gl::READ_FRAMEBUFFER, previous_fbo as gl::types::GLuint);
+    }
+    pbo.unbind();
+}
+
+/// Copy pixels out of the buffer, flipping rows so that the first row is at the top.
+fn map_pixels(pbo: &Buffer, width: i32, height: i32) -> Option<Vec<u8>> {
+    let row_len = width as usize * 4;
+    let size = row_len * height as usize;
+
+    pbo.bind();
+    let pixels = unsafe { pbo.map_buffer_range_read::<u8>(0, size) }.map(|mapped| {
+        let mut pixels = Vec::with_capacity(size);
+        for row in mapped.chunks(row_len).rev() {
+            pixels.extend_from_slice(row);
+        }
+        pixels
+    });
+    pbo.unbind();
+
+    pixels
+}
@@ -0,0 +1,34 @@
+use gl;
+use nalgebra as na;
+
+/// Plane through `point` that keeps the side `normal` points to, as `(normal, distance)` for
+/// `render::CameraUniforms::clip_plane`.
+pub fn clip_plane(point: &na::Point3<f32>, normal: &na::Vector3<f32>) -> na::Vector4<f32> {
+    let normal = normal.normalize();
+    na::Vector4::new(normal.x, normal.y, normal.z, -normal.dot(&point.coords))
+}
+
+/// Clip distance 0 enabled while alive.
+///
+/// Shaders write `dot(WorldPosition, ClipPlane)` of the `Camera` block to it, and everything
+/// on the negative side is cut away. Every shader drawn in the scope must write it.
+pub struct ClipPlaneScope<'a> {
+    gl: &'a gl::Gl,
+}
+
+impl<'a> ClipPlaneScope<'a> {
+    pub fn enable(gl: &'a gl::Gl) -> ClipPlaneScope<'a> {
+        unsafe {
+            gl.Enable(gl::CLIP_DISTANCE0);
+        }
+        ClipPlaneScope { gl }
+    }
+}
+
+impl<'a> Drop for ClipPlaneScope<'a> {
+    fn drop(&mut self) {
+        unsafe {
+            self.gl.Disable(gl::CLIP_DISTANCE0);
+        }
+    }
+}
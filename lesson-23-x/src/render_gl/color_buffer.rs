@@ -1,4 +1,5 @@
 use gl;
+use crate::render_gl::StateCache;
 use nalgebra as na;
 
 pub struct ColorBuffer;
@@ -15,9 +16,7 @@ impl ColorBuffer {
     }
 
     pub fn set_default_blend_func(&self, gl: &gl::Gl) {
-        unsafe {
-            gl.BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
-        }
+        StateCache::set_blend_func(gl, gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
     }
 
     pub fn clear(&self, gl: &gl::Gl) {
@@ -27,14 +26,10 @@ impl ColorBuffer {
     }
 
     pub fn enable_blend(&self, gl: &gl::Gl) {
-        unsafe {
-            gl.Enable(gl::BLEND);
-        }
+        StateCache::set_blend(gl, true);
     }
 
     pub fn disable_blend(&self, gl: &gl::Gl) {
-        unsafe {
-            gl.Disable(gl::BLEND);
-        }
+        StateCache::set_blend(gl, false);
     }
 }
@@ -0,0 +1,36 @@
+use gl;
+use crate::render_gl::Capabilities;
+
+/// Compute shaders and shader storage buffers need GL 4.3.
+pub fn compute_supported(gl: &gl::Gl) -> bool {
+    Capabilities::get(gl).compute
+}
+
+/// Context version as (major, minor).
+pub fn gl_version(gl: &gl::Gl) -> (i32, i32) {
+    Capabilities::get(gl).version
+}
+
+/// Driver lists the extension, such as `GL_ARB_shader_draw_parameters`.
+pub fn extension_supported(gl: &gl::Gl, extension: &str) -> bool {
+    Capabilities::get(gl).has_extension(extension)
+}
+
+/// Make compute shader writes visible to the kinds of later reads in `barriers`,
+/// such as `gl::SHADER_STORAGE_BARRIER_BIT` or `gl::VERTEX_ATTRIB_ARRAY_BARRIER_BIT`.
+pub fn memory_barrier(gl: &gl::Gl, barriers: gl::types::GLbitfield) {
+    unsafe {
+        gl.MemoryBarrier(barriers);
+    }
+}
+
+/// Barrier for storage buffers written by a dispatch and then read as vertex
+/// attributes, indirect commands or by another dispatch.
+pub fn storage_to_draw_barrier(gl: &gl::Gl) {
+    memory_barrier(
+        gl,
+        gl::SHADER_STORAGE_BARRIER_BIT
+            | gl::VERTEX_ATTRIB_ARRAY_BARRIER_BIT
+            | gl::COMMAND_BARRIER_BIT,
+    );
+}
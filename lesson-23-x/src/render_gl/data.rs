@@ -1217,6 +1217,14 @@ impl From<(u8, u8, u8, u8)> for u8_u8_u8_u8_float {
     }
 }
 
+/// Values in [0, 1], i.e. colors, rounded to the nearest byte.
+impl From<(f32, f32, f32, f32)> for u8_u8_u8_u8_float {
+    fn from(other: (f32, f32, f32, f32)) -> Self {
+        let unorm = |value: f32| (value.max(0.0).min(1.0) * 255.0).round() as u8;
+        u8_u8_u8_u8_float::new(unorm(other.0), unorm(other.1), unorm(other.2), unorm(other.3))
+    }
+}
+
 // -----------------------------------------
 
 #[derive(Copy, Clone, Debug)]
@@ -2438,6 +2446,20 @@ impl i2_i10_i10_i10_rev_float {
         i2_i10_i10_i10_rev_float { inner }
     }
 
+    /// Pack values in [-1, 1], i.e. a normal or tangent with w as the handedness.
+    pub fn from_normalized(x: f32, y: f32, z: f32, w: f32) -> i2_i10_i10_i10_rev_float {
+        let snorm = |value: f32, max: f32, bits: u32| {
+            let value = if value.is_nan() { 0.0 } else { value.max(-1.0).min(1.0) };
+            ((value * max).round() as i32 as u32) & ((1 << bits) - 1)
+        };
+        i2_i10_i10_i10_rev_float::new(
+            snorm(x, 511.0, 10)
+                | snorm(y, 511.0, 10) << 10
+                | snorm(z, 511.0, 10) << 20
+                | snorm(w, 1.0, 2) << 30,
+        )
+    }
+
     pub unsafe fn vertex_attrib_pointer(
         gl: &gl::Gl,
         stride: usize,
@@ -2456,6 +2478,12 @@ impl i2_i10_i10_i10_rev_float {
     }
 }
 
+impl From<(f32, f32, f32, f32)> for i2_i10_i10_i10_rev_float {
+    fn from(other: (f32, f32, f32, f32)) -> Self {
+        i2_i10_i10_i10_rev_float::from_normalized(other.0, other.1, other.2, other.3)
+    }
+}
+
 // -----------------------------------------
 
 #[derive(Copy, Clone, Debug)]
@@ -0,0 +1,230 @@
+use gl;
+use std::os::raw;
+use std::ptr;
+use std::slice;
+use std::str;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Notification,
+    Low,
+    Medium,
+    High,
+}
+
+impl Severity {
+    fn from_gl(severity: gl::types::GLenum) -> Severity {
+        match severity {
+            gl::DEBUG_SEVERITY_HIGH => Severity::High,
+            gl::DEBUG_SEVERITY_MEDIUM => Severity::Medium,
+            gl::DEBUG_SEVERITY_LOW => Severity::Low,
+            _ => Severity::Notification,
+        }
+    }
+}
+
+/// Message reported by the driver through KHR_debug.
+#[derive(Debug)]
+pub struct Message<'a> {
+    pub source: &'static str,
+    pub kind: &'static str,
+    pub id: u32,
+    pub severity: Severity,
+    pub text: &'a str,
+}
+
+/// Print messages to stdout, the default logger.
+pub fn print_message(message: &Message) {
+    println!(
+        "GL {:?} {} {} [{}]: {}",
+        message.severity, message.source, message.kind, message.id, message.text
+    );
+}
+
+type Logger = Box<dyn Fn(&Message)>;
+
+/// Installed debug message callback, removed on drop.
+pub struct DebugOutput {
+    gl: gl::Gl,
+    // boxed twice so that the callback receives a thin pointer
+    _logger: Box<Logger>,
+}
+
+impl DebugOutput {
+    /// Route driver messages of at least `min_severity` to the logger.
+    ///
+    /// Returns `None` if the context does not support KHR_debug. Drivers report the most
+    /// messages when the context is created with the debug flag.
+    pub fn install<F>(gl: &gl::Gl, min_severity: Severity, logger: F) -> Option<DebugOutput>
+    where
+        F: Fn(&Message) + 'static,
+    {
+        if !debug_supported(gl) {
+            return None;
+        }
+
+        let logger: Box<Logger> = Box::new(Box::new(move |message: &Message| {
+            if message.severity >= min_severity {
+                logger(message)
+            }
+        }));
+
+        unsafe {
+            gl.Enable(gl::DEBUG_OUTPUT);
+            // report messages from the thread of the failing call, for usable backtraces
+            gl.Enable(gl::DEBUG_OUTPUT_SYNCHRONOUS);
+            gl.DebugMessageCallback(
+                debug_callback,
+                &*logger as *const Logger as *const raw::c_void,
+            );
+            gl.DebugMessageControl(
+                gl::DONT_CARE,
+                gl::DONT_CARE,
+                gl::DONT_CARE,
+                0,
+                ptr::null(),
+                gl::TRUE,
+            );
+        }
+
+        Some(DebugOutput {
+            gl: gl.clone(),
+            _logger: logger,
+        })
+    }
+}
+
+impl Drop for DebugOutput {
+    fn drop(&mut self) {
+        unsafe {
+            self.gl.Disable(gl::DEBUG_OUTPUT);
+            self.gl.DebugMessageCallback(ignore_callback, ptr::null());
+        }
+    }
+}
+
+extern "system" fn debug_callback(
+    source: gl::types::GLenum,
+    kind: gl::types::GLenum,
+    id: gl::types::GLuint,
+    severity: gl::types::GLenum,
+    length: gl::types::GLsizei,
+    text: *const gl::types::GLchar,
+    user_param: *mut raw::c_void,
+) {
+    if user_param.is_null() || text.is_null() {
+        return;
+    }
+
+    let logger = unsafe { &*(user_param as *const Logger) };
+    let bytes = unsafe { slice::from_raw_parts(text as *const u8, length.max(0) as usize) };
+    let text = str::from_utf8(bytes).unwrap_or("<invalid utf-8>");
+
+    logger(&Message {
+        source: source_to_str(source),
+        kind: kind_to_str(kind),
+        id,
+        severity: Severity::from_gl(severity),
+        text: text.trim_end_matches('\0').trim_end(),
+    });
+}
+
+extern "system" fn ignore_callback(
+    _source: gl::types::GLenum,
+    _kind: gl::types::GLenum,
+    _id: gl::types::GLuint,
+    _severity: gl::types::GLenum,
+    _length: gl::types::GLsizei,
+    _text: *const gl::types::GLchar,
+    _user_param: *mut raw::c_void,
+) {
+}
+
+fn source_to_str(source: gl::types::GLenum) -> &'static str {
+    match source {
+        gl::DEBUG_SOURCE_API => "api",
+        gl::DEBUG_SOURCE_WINDOW_SYSTEM => "window system",
+        gl::DEBUG_SOURCE_SHADER_COMPILER => "shader compiler",
+        gl::DEBUG_SOURCE_THIRD_PARTY => "third party",
+        gl::DEBUG_SOURCE_APPLICATION => "application",
+        _ => "other",
+    }
+}
+
+fn kind_to_str(kind: gl::types::GLenum) -> &'static str {
+    match kind {
+        gl::DEBUG_TYPE_ERROR => "error",
+        gl::DEBUG_TYPE_DEPRECATED_BEHAVIOR => "deprecated",
+        gl::DEBUG_TYPE_UNDEFINED_BEHAVIOR => "undefined behavior",
+        gl::DEBUG_TYPE_PORTABILITY => "portability",
+        gl::DEBUG_TYPE_PERFORMANCE => "performance",
+        gl::DEBUG_TYPE_MARKER => "marker",
+        gl::DEBUG_TYPE_PUSH_GROUP => "push group",
+        gl::DEBUG_TYPE_POP_GROUP => "pop group",
+        _ => "other",
+    }
+}
+
+/// KHR_debug is core since GL 4.3, older contexts may still expose it as an extension.
+pub fn debug_supported(gl: &gl::Gl) -> bool {
+    gl.DebugMessageCallback.is_loaded()
+}
+
+/// Start a named group of commands, shown as a tree in tools like RenderDoc.
+pub fn push_group(gl: &gl::Gl, name: &str) {
+    if gl.PushDebugGroup.is_loaded() {
+        unsafe {
+            gl.PushDebugGroup(
+                gl::DEBUG_SOURCE_APPLICATION,
+                0,
+                name.len() as gl::types::GLsizei,
+                name.as_ptr() as *const gl::types::GLchar,
+            );
+        }
+    }
+}
+
+pub fn pop_group(gl: &gl::Gl) {
+    if gl.PopDebugGroup.is_loaded() {
+        unsafe {
+            gl.PopDebugGroup();
+        }
+    }
+}
+
+/// Push a group that is popped when the scope is dropped.
+pub fn group(gl: &gl::Gl, name: &str) -> GroupScope {
+    push_group(gl, name);
+    GroupScope { gl: gl.clone() }
+}
+
+pub struct GroupScope {
+    gl: gl::Gl,
+}
+
+impl Drop for GroupScope {
+    fn drop(&mut self) {
+        pop_group(&self.gl);
+    }
+}
+
+/// Name GL object for debug messages and captures.
+///
+/// `identifier` is the object namespace, i.e. `gl::BUFFER` or `gl::TEXTURE`.
+pub fn object_label(
+    gl: &gl::Gl,
+    identifier: gl::types::GLenum,
+    name: gl::types::GLuint,
+    label: &str,
+) {
+    if gl.ObjectLabel.is_loaded() {
+        unsafe {
+            gl.ObjectLabel(
+                identifier,
+                name,
+                label.len() as gl::types::GLsizei,
+                label.as_ptr() as *const gl::types::GLchar,
+            );
+        }
+    }
+}
@@ -1,6 +1,7 @@
 use gl;
 use nalgebra as na;
 use crate::render_gl::data;
+use crate::render_gl::StreamBuffer;
 
 #[derive(VertexAttribPointers, Copy, Clone, Debug)]
 #[repr(C, packed)]
@@ -11,7 +12,7 @@ pub struct LinePoint {
     pub color: data::u2_u10_u10_u10_rev_float,
 }
 
-use crate::render_gl::buffer::{Buffer, VertexArray};
+use crate::render_gl::buffer::VertexArray;
 
 pub struct MultiDrawItem {
     pub model_matrix: na::Matrix4<f32>,
@@ -22,49 +23,42 @@ pub struct MultiDrawItem {
 pub struct Buffers {
     pub vbo_capacity: usize,
     pub multi_draw_items: Vec<MultiDrawItem>,
-    lines_vbo: Buffer,
+    lines_vbo: StreamBuffer<LinePoint>,
+    /// Offset of the last upload in the stream buffer, added to item starting indices.
+    pub first_vertex: i32,
     pub lines_vao: VertexArray,
 }
 
 impl Buffers {
     pub fn new(gl: &gl::Gl, vbo_capacity: usize) -> Buffers {
-        let lines_vbo = Buffer::new_array(&gl);
+        let lines_vbo = StreamBuffer::new(gl, vbo_capacity);
         let lines_vao = VertexArray::new(gl);
 
         lines_vao.bind();
 
-        lines_vbo.bind();
+        lines_vbo.buffer().bind();
         LinePoint::vertex_attrib_pointers(gl);
-        lines_vbo.unbind();
+        lines_vbo.buffer().unbind();
 
         lines_vao.unbind();
 
-        if vbo_capacity > 0 {
-            lines_vbo.bind();
-            lines_vbo.stream_draw_data_null::<LinePoint>(vbo_capacity);
-            lines_vbo.unbind();
-        }
-
         Buffers {
             vbo_capacity,
             lines_vbo,
+            first_vertex: 0,
             multi_draw_items: Vec::new(),
             lines_vao,
         }
     }
 
-    pub fn upload_vertices(&self, items: impl Iterator<Item = LinePoint>) {
-        if self.vbo_capacity > 0 {
-            self.lines_vbo.bind();
-            if let Some(mut buffer) = unsafe {
-                self.lines_vbo
-                    .map_buffer_range_write_invalidate::<LinePoint>(0, self.vbo_capacity)
-            } {
-                for (index, item) in items.enumerate().take(self.vbo_capacity) {
-                    *unsafe { buffer.get_unchecked_mut(index) } = item;
-                }
-            }
-            self.lines_vbo.unbind();
-        }
+    /// Write vertices into the next region of the stream buffer, without waiting for draws
+    /// of previous uploads.
+    pub fn upload_vertices(&mut self, items: impl Iterator<Item = LinePoint>) {
+        self.first_vertex = self.lines_vbo.write(items) as i32;
+    }
+
+    /// Call after draw calls using the uploaded vertices.
+    pub fn fence(&mut self) {
+        self.lines_vbo.fence();
     }
 }
@@ -4,6 +4,7 @@ use nalgebra as na;
 use ncollide3d;
 use crate::render_gl::data;
 use crate::render_gl::ColorBuffer;
+use crate::render_gl::FrameStats;
 use crate::render_gl::Program;
 use crate::resources::Resources;
 
@@ -16,6 +17,14 @@ mod shared_debug_lines;
 use self::buffers::{Buffers, LinePoint, MultiDrawItem};
 use self::shared_debug_lines::SharedDebugLines;
 
+/// Handle of lines in `DebugLines`, the lines are removed when the handle is dropped.
+pub trait Marker: Sized {
+    /// Remove the lines now, i.e. together with the object they belong to.
+    fn remove(self) {
+        drop(self);
+    }
+}
+
 pub struct DebugLines {
     program: Program,
     program_view_projection_location: Option<i32>,
@@ -52,6 +61,7 @@ impl DebugLines {
             let num_items = shared_debug_lines
                 .containers
                 .values()
+                .filter(|v| v.visible)
                 .flat_map(|v| v.data.iter())
                 .count();
 
@@ -70,13 +80,14 @@ impl DebugLines {
                     shared_debug_lines
                         .containers
                         .values()
+                        .filter(|v| v.visible)
                         .flat_map(|v| v.data.iter())
                         .map(|item| *item),
                 );
 
                 buffers.multi_draw_items.clear();
                 let mut offset = 0;
-                for container in shared_debug_lines.containers.values() {
+                for container in shared_debug_lines.containers.values().filter(|v| v.visible) {
                     buffers.multi_draw_items.push(MultiDrawItem {
                         model_matrix: container.isometry.to_homogeneous(),
                         starting_index: offset,
@@ -94,7 +105,7 @@ impl DebugLines {
         if self.draw_enabled {
             self.check_if_invalidated_and_reinitialize(gl);
 
-            if let Some(ref buffers) = self.buffers {
+            if let Some(ref mut buffers) = self.buffers {
                 if buffers.multi_draw_items.len() > 0 {
                     self.program.set_used();
                     if let Some(loc) = self.program_view_projection_location {
@@ -117,13 +128,19 @@ impl DebugLines {
                                 &instance.model_matrix,
                             );
 
-                            gl.DrawArrays(gl::LINES, instance.starting_index, instance.index_count);
+                            gl.DrawArrays(
+                                gl::LINES,
+                                buffers.first_vertex + instance.starting_index,
+                                instance.index_count,
+                            );
+                            FrameStats::record_draw(gl::LINES, instance.index_count, 1);
                         }
 
                         target.disable_blend(gl);
                     }
 
                     buffers.lines_vao.unbind();
+                    buffers.fence();
                 }
             }
         }
@@ -225,56 +242,44 @@ impl DebugLines {
             Item = (na::Point3<f32>, na::Vector3<f32>, na::Vector4<f32>),
         >,
     ) -> RayMarkers {
-        struct PositionsIter {
-            pos: na::Point3<f32>,
-            dir: na::Vector3<f32>,
-            color: na::Vector4<f32>,
-            index: u8,
-        }
-
-        impl Iterator for PositionsIter {
-            type Item = LinePoint;
-
-            fn next(&mut self) -> Option<LinePoint> {
-                match self.index {
-                    0 => {
-                        self.index = 1;
-                        Some(LinePoint {
-                            pos: render_p3(self.pos),
-                            color: render_color_vec4(self.color),
-                        })
-                    }
-                    1 => {
-                        self.index = 2;
-                        Some(LinePoint {
-                            pos: render_p3(self.pos + self.dir),
-                            color: render_color_vec4(na::Vector4::new(
-                                self.color.x,
-                                self.color.y,
-                                self.color.z,
-                                0.0,
-                            )),
-                        })
-                    }
-                    _ => None,
-                }
-            }
-        }
+        self.ray_markers_with_density(isometry, RayDensity::default(), pos_direction_colors)
+    }
 
+    /// Rays thinned out by `density`, i.e. normals of a dense mesh.
+    pub fn ray_markers_with_density(
+        &self,
+        isometry: na::Isometry3<f32>,
+        density: RayDensity,
+        pos_direction_colors: impl Iterator<
+            Item = (na::Point3<f32>, na::Vector3<f32>, na::Vector4<f32>),
+        >,
+    ) -> RayMarkers {
         let new_id = self.containers.borrow_mut().new_container(
             isometry,
-            pos_direction_colors
-                .flat_map(|(pos, dir, color)| PositionsIter {
-                    pos,
-                    dir,
-                    color,
-                    index: 0,
-                }).collect(),
+            ray_line_points(density.decimate(pos_direction_colors).into_iter()),
         );
 
         RayMarkers {
             containers: self.containers.clone(),
             id: new_id,
+            density,
+        }
+    }
+
+    /// Solid line segments, as (start, end, color).
+    pub fn lines_marker(
+        &self,
+        isometry: na::Isometry3<f32>,
+        segments: impl Iterator<Item = (na::Point3<f32>, na::Point3<f32>, na::Vector4<f32>)>,
+    ) -> LinesMarker {
+        let new_id = self
+            .containers
+            .borrow_mut()
+            .new_container(isometry, segment_line_points(segments));
+
+        LinesMarker {
+            containers: self.containers.clone(),
+            id: new_id,
         }
     }
 
@@ -284,109 +289,9 @@ impl DebugLines {
         aabb: ncollide3d::bounding_volume::aabb::AABB<f32>,
         color: na::Vector4<f32>,
     ) -> AabbMarker {
-        let a = aabb.mins();
-        let b = aabb.maxs();
-
         let new_id = self.containers.borrow_mut().new_container(
             isometry,
-            vec![
-                LinePoint {
-                    pos: render_p3([a.x, a.y, a.z].into()),
-                    color: render_color_vec4(color),
-                },
-                LinePoint {
-                    pos: render_p3([b.x, a.y, a.z].into()),
-                    color: render_color_vec4(color),
-                },
-                LinePoint {
-                    pos: render_p3([a.x, a.y, a.z].into()),
-                    color: render_color_vec4(color),
-                },
-                LinePoint {
-                    pos: render_p3([a.x, b.y, a.z].into()),
-                    color: render_color_vec4(color),
-                },
-                LinePoint {
-                    pos: render_p3([a.x, a.y, a.z].into()),
-                    color: render_color_vec4(color),
-                },
-                LinePoint {
-                    pos: render_p3([a.x, a.y, b.z].into()),
-                    color: render_color_vec4(color),
-                },
-                LinePoint {
-                    pos: render_p3([a.x, b.y, b.z].into()),
-                    color: render_color_vec4(color),
-                },
-                LinePoint {
-                    pos: render_p3([b.x, b.y, b.z].into()),
-                    color: render_color_vec4(color),
-                },
-                LinePoint {
-                    pos: render_p3([b.x, a.y, b.z].into()),
-                    color: render_color_vec4(color),
-                },
-                LinePoint {
-                    pos: render_p3([b.x, b.y, b.z].into()),
-                    color: render_color_vec4(color),
-                },
-                LinePoint {
-                    pos: render_p3([b.x, b.y, a.z].into()),
-                    color: render_color_vec4(color),
-                },
-                LinePoint {
-                    pos: render_p3([b.x, b.y, b.z].into()),
-                    color: render_color_vec4(color),
-                },
-                LinePoint {
-                    pos: render_p3([a.x, b.y, a.z].into()),
-                    color: render_color_vec4(color),
-                },
-                LinePoint {
-                    pos: render_p3([b.x, b.y, a.z].into()),
-                    color: render_color_vec4(color),
-                },
-                LinePoint {
-                    pos: render_p3([a.x, b.y, a.z].into()),
-                    color: render_color_vec4(color),
-                },
-                LinePoint {
-                    pos: render_p3([a.x, b.y, b.z].into()),
-                    color: render_color_vec4(color),
-                },
-                LinePoint {
-                    pos: render_p3([a.x, a.y, b.z].into()),
-                    color: render_color_vec4(color),
-                },
-                LinePoint {
-                    pos: render_p3([a.x, b.y, b.z].into()),
-                    color: render_color_vec4(color),
-                },
-                LinePoint {
-                    pos: render_p3([a.x, a.y, b.z].into()),
-                    color: render_color_vec4(color),
-                },
-                LinePoint {
-                    pos: render_p3([b.x, a.y, b.z].into()),
-                    color: render_color_vec4(color),
-                },
-                LinePoint {
-                    pos: render_p3([b.x, a.y, a.z].into()),
-                    color: render_color_vec4(color),
-                },
-                LinePoint {
-                    pos: render_p3([b.x, b.y, a.z].into()),
-                    color: render_color_vec4(color),
-                },
-                LinePoint {
-                    pos: render_p3([b.x, a.y, a.z].into()),
-                    color: render_color_vec4(color),
-                },
-                LinePoint {
-                    pos: render_p3([b.x, a.y, b.z].into()),
-                    color: render_color_vec4(color),
-                },
-            ],
+            aabb_line_points(&aabb, color),
         );
 
         AabbMarker {
@@ -395,6 +300,24 @@ impl DebugLines {
         }
     }
 
+    /// Edges of the volume seen through a view-projection matrix, i.e. a camera frustum or
+    /// the box covered by a shadow map.
+    pub fn frustum_marker(
+        &self,
+        view_projection: &na::Matrix4<f32>,
+        color: na::Vector4<f32>,
+    ) -> FrustumMarker {
+        let new_id = self.containers.borrow_mut().new_container(
+            na::Isometry3::identity(),
+            frustum_line_points(view_projection, color),
+        );
+
+        FrustumMarker {
+            containers: self.containers.clone(),
+            id: new_id,
+        }
+    }
+
     pub fn grid_marker(
         &self,
         isometry: na::Isometry3<f32>,
@@ -457,14 +380,47 @@ impl AabbMarker {
             data.isometry = isometry;
         }
     }
+
+    pub fn update_aabb(
+        &self,
+        aabb: ncollide3d::bounding_volume::aabb::AABB<f32>,
+        color: na::Vector4<f32>,
+    ) {
+        if let Some(data) = self.containers.borrow_mut().get_container_mut(self.id) {
+            data.data = aabb_line_points(&aabb, color);
+        }
+    }
 }
 
+impl Marker for AabbMarker {}
+
 impl Drop for AabbMarker {
     fn drop(&mut self) {
         self.containers.borrow_mut().remove_container(self.id);
     }
 }
 
+pub struct FrustumMarker {
+    containers: Rc<RefCell<SharedDebugLines>>,
+    pub id: i32,
+}
+
+impl FrustumMarker {
+    pub fn update_matrix(&self, view_projection: &na::Matrix4<f32>, color: na::Vector4<f32>) {
+        if let Some(data) = self.containers.borrow_mut().get_container_mut(self.id) {
+            data.data = frustum_line_points(view_projection, color);
+        }
+    }
+}
+
+impl Marker for FrustumMarker {}
+
+impl Drop for FrustumMarker {
+    fn drop(&mut self) {
+        self.containers.borrow_mut().remove_container(self.id);
+    }
+}
+
 pub struct GridMarker {
     containers: Rc<RefCell<SharedDebugLines>>,
     pub id: i32,
@@ -478,15 +434,58 @@ impl GridMarker {
     }
 }
 
+impl Marker for GridMarker {}
+
 impl Drop for GridMarker {
     fn drop(&mut self) {
         self.containers.borrow_mut().remove_container(self.id);
     }
 }
 
+/// Which of the rays passed to `RayMarkers` are drawn.
+#[derive(Copy, Clone, Debug)]
+pub struct RayDensity {
+    /// Keep one ray out of this many.
+    pub every_nth: usize,
+    /// Upper bound of kept rays, the step grows so that they stay spread over all rays.
+    pub max_count: Option<usize>,
+}
+
+impl Default for RayDensity {
+    fn default() -> RayDensity {
+        RayDensity {
+            every_nth: 1,
+            max_count: None,
+        }
+    }
+}
+
+impl RayDensity {
+    pub fn with_every_nth(mut self, every_nth: usize) -> RayDensity {
+        self.every_nth = every_nth.max(1);
+        self
+    }
+
+    pub fn with_max_count(mut self, max_count: usize) -> RayDensity {
+        self.max_count = Some(max_count);
+        self
+    }
+
+    fn decimate<T>(&self, items: impl Iterator<Item = T>) -> Vec<T> {
+        let items = items.collect::<Vec<_>>();
+        let step = match self.max_count {
+            Some(0) => return Vec::new(),
+            Some(max_count) => self.every_nth.max((items.len() + max_count - 1) / max_count),
+            None => self.every_nth,
+        };
+        items.into_iter().step_by(step.max(1)).collect()
+    }
+}
+
 pub struct RayMarkers {
     containers: Rc<RefCell<SharedDebugLines>>,
     id: i32,
+    density: RayDensity,
 }
 
 impl RayMarkers {
@@ -504,14 +503,93 @@ impl RayMarkers {
             data.isometry = isometry;
         }
     }
+
+    /// Replace all rays, i.e. once the mesh they visualize has finished loading.
+    pub fn update_rays(
+        &self,
+        pos_direction_colors: impl Iterator<
+            Item = (na::Point3<f32>, na::Vector3<f32>, na::Vector4<f32>),
+        >,
+    ) {
+        if let Some(data) = self.containers.borrow_mut().get_container_mut(self.id) {
+            data.data = ray_line_points(self.density.decimate(pos_direction_colors).into_iter());
+        }
+    }
+
+    /// Density used by the next `update_rays`.
+    pub fn set_density(&mut self, density: RayDensity) {
+        self.density = density;
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.containers
+            .borrow()
+            .containers
+            .get(&self.id)
+            .map_or(false, |data| data.visible)
+    }
+
+    /// Hide or show the rays, keeping them for later.
+    pub fn set_visible(&self, visible: bool) {
+        if let Some(data) = self.containers.borrow_mut().get_container_mut(self.id) {
+            data.visible = visible;
+        }
+    }
+
+    pub fn toggle(&self) {
+        let visible = self.is_visible();
+        self.set_visible(!visible);
+    }
+
+    /// Recolor all rays, each still fades out towards its end.
+    pub fn set_color(&self, color: na::Vector4<f32>) {
+        let end_color = na::Vector4::new(color.x, color.y, color.z, 0.0);
+        if let Some(data) = self.containers.borrow_mut().get_container_mut(self.id) {
+            for (index, point) in data.data.iter_mut().enumerate() {
+                point.color = render_color_vec4(if index % 2 == 0 { color } else { end_color });
+            }
+        }
+    }
 }
 
+impl Marker for RayMarkers {}
+
 impl Drop for RayMarkers {
     fn drop(&mut self) {
         self.containers.borrow_mut().remove_container(self.id);
     }
 }
 
+pub struct LinesMarker {
+    containers: Rc<RefCell<SharedDebugLines>>,
+    id: i32,
+}
+
+impl LinesMarker {
+    pub fn update_isometry(&self, isometry: na::Isometry3<f32>) {
+        if let Some(data) = self.containers.borrow_mut().get_container_mut(self.id) {
+            data.isometry = isometry;
+        }
+    }
+
+    pub fn update_lines(
+        &self,
+        segments: impl Iterator<Item = (na::Point3<f32>, na::Point3<f32>, na::Vector4<f32>)>,
+    ) {
+        if let Some(data) = self.containers.borrow_mut().get_container_mut(self.id) {
+            data.data = segment_line_points(segments);
+        }
+    }
+}
+
+impl Marker for LinesMarker {}
+
+impl Drop for LinesMarker {
+    fn drop(&mut self) {
+        self.containers.borrow_mut().remove_container(self.id);
+    }
+}
+
 pub struct PointMarker {
     containers: Rc<RefCell<SharedDebugLines>>,
     id: i32,
@@ -528,12 +606,221 @@ impl PointMarker {
     }
 }
 
+impl Marker for PointMarker {}
+
 impl Drop for PointMarker {
     fn drop(&mut self) {
         self.containers.borrow_mut().remove_container(self.id);
     }
 }
 
+fn aabb_line_points(
+    aabb: &ncollide3d::bounding_volume::aabb::AABB<f32>,
+    color: na::Vector4<f32>,
+) -> Vec<LinePoint> {
+    let a = aabb.mins();
+    let b = aabb.maxs();
+
+    vec![
+        LinePoint {
+            pos: render_p3([a.x, a.y, a.z].into()),
+            color: render_color_vec4(color),
+        },
+        LinePoint {
+            pos: render_p3([b.x, a.y, a.z].into()),
+            color: render_color_vec4(color),
+        },
+        LinePoint {
+            pos: render_p3([a.x, a.y, a.z].into()),
+            color: render_color_vec4(color),
+        },
+        LinePoint {
+            pos: render_p3([a.x, b.y, a.z].into()),
+            color: render_color_vec4(color),
+        },
+        LinePoint {
+            pos: render_p3([a.x, a.y, a.z].into()),
+            color: render_color_vec4(color),
+        },
+        LinePoint {
+            pos: render_p3([a.x, a.y, b.z].into()),
+            color: render_color_vec4(color),
+        },
+        LinePoint {
+            pos: render_p3([a.x, b.y, b.z].into()),
+            color: render_color_vec4(color),
+        },
+        LinePoint {
+            pos: render_p3([b.x, b.y, b.z].into()),
+            color: render_color_vec4(color),
+        },
+        LinePoint {
+            pos: render_p3([b.x, a.y, b.z].into()),
+            color: render_color_vec4(color),
+        },
+        LinePoint {
+            pos: render_p3([b.x, b.y, b.z].into()),
+            color: render_color_vec4(color),
+        },
+        LinePoint {
+            pos: render_p3([b.x, b.y, a.z].into()),
+            color: render_color_vec4(color),
+        },
+        LinePoint {
+            pos: render_p3([b.x, b.y, b.z].into()),
+            color: render_color_vec4(color),
+        },
+        LinePoint {
+            pos: render_p3([a.x, b.y, a.z].into()),
+            color: render_color_vec4(color),
+        },
+        LinePoint {
+            pos: render_p3([b.x, b.y, a.z].into()),
+            color: render_color_vec4(color),
+        },
+        LinePoint {
+            pos: render_p3([a.x, b.y, a.z].into()),
+            color: render_color_vec4(color),
+        },
+        LinePoint {
+            pos: render_p3([a.x, b.y, b.z].into()),
+            color: render_color_vec4(color),
+        },
+        LinePoint {
+            pos: render_p3([a.x, a.y, b.z].into()),
+            color: render_color_vec4(color),
+        },
+        LinePoint {
+            pos: render_p3([a.x, b.y, b.z].into()),
+            color: render_color_vec4(color),
+        },
+        LinePoint {
+            pos: render_p3([a.x, a.y, b.z].into()),
+            color: render_color_vec4(color),
+        },
+        LinePoint {
+            pos: render_p3([b.x, a.y, b.z].into()),
+            color: render_color_vec4(color),
+        },
+        LinePoint {
+            pos: render_p3([b.x, a.y, a.z].into()),
+            color: render_color_vec4(color),
+        },
+        LinePoint {
+            pos: render_p3([b.x, b.y, a.z].into()),
+            color: render_color_vec4(color),
+        },
+        LinePoint {
+            pos: render_p3([b.x, a.y, a.z].into()),
+            color: render_color_vec4(color),
+        },
+        LinePoint {
+            pos: render_p3([b.x, a.y, b.z].into()),
+            color: render_color_vec4(color),
+        },
+    ]
+}
+
+/// Corners of the clip space cube moved back to world space, nothing for singular matrices.
+fn frustum_line_points(
+    view_projection: &na::Matrix4<f32>,
+    color: na::Vector4<f32>,
+) -> Vec<LinePoint> {
+    let inverse = match view_projection.try_inverse() {
+        Some(inverse) => inverse,
+        None => return Vec::new(),
+    };
+    let corner = |x: f32, y: f32, z: f32| inverse.transform_point(&na::Point3::new(x, y, z));
+    let near = [
+        corner(-1.0, -1.0, -1.0),
+        corner(1.0, -1.0, -1.0),
+        corner(1.0, 1.0, -1.0),
+        corner(-1.0, 1.0, -1.0),
+    ];
+    let far = [
+        corner(-1.0, -1.0, 1.0),
+        corner(1.0, -1.0, 1.0),
+        corner(1.0, 1.0, 1.0),
+        corner(-1.0, 1.0, 1.0),
+    ];
+
+    segment_line_points((0..4).flat_map(|i| {
+        let next = (i + 1) % 4;
+        vec![
+            (near[i], near[next], color),
+            (far[i], far[next], color),
+            (near[i], far[i], color),
+        ]
+    }))
+}
+
+fn segment_line_points(
+    segments: impl Iterator<Item = (na::Point3<f32>, na::Point3<f32>, na::Vector4<f32>)>,
+) -> Vec<LinePoint> {
+    let mut points = Vec::new();
+    for (start, end, color) in segments {
+        points.push(LinePoint {
+            pos: render_p3(start),
+            color: render_color_vec4(color),
+        });
+        points.push(LinePoint {
+            pos: render_p3(end),
+            color: render_color_vec4(color),
+        });
+    }
+    points
+}
+
+fn ray_line_points(
+    pos_direction_colors: impl Iterator<
+        Item = (na::Point3<f32>, na::Vector3<f32>, na::Vector4<f32>),
+    >,
+) -> Vec<LinePoint> {
+    pos_direction_colors
+        .flat_map(|(pos, dir, color)| PositionsIter {
+            pos,
+            dir,
+            color,
+            index: 0,
+        }).collect()
+}
+
+struct PositionsIter {
+    pos: na::Point3<f32>,
+    dir: na::Vector3<f32>,
+    color: na::Vector4<f32>,
+    index: u8,
+}
+
+impl Iterator for PositionsIter {
+    type Item = LinePoint;
+
+    fn next(&mut self) -> Option<LinePoint> {
+        match self.index {
+            0 => {
+                self.index = 1;
+                Some(LinePoint {
+                    pos: render_p3(self.pos),
+                    color: render_color_vec4(self.color),
+                })
+            }
+            1 => {
+                self.index = 2;
+                Some(LinePoint {
+                    pos: render_p3(self.pos + self.dir),
+                    color: render_color_vec4(na::Vector4::new(
+                        self.color.x,
+                        self.color.y,
+                        self.color.z,
+                        0.0,
+                    )),
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
 fn render_p3(v: na::Point3<f32>) -> data::f32_f32_f32 {
     data::f32_f32_f32::new(v.x, v.y, v.z)
 }
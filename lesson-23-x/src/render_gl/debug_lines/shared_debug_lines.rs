@@ -5,6 +5,8 @@ use std::collections::BTreeMap;
 pub struct Container {
     pub isometry: na::Isometry3<f32>,
     pub data: Vec<LinePoint>,
+    /// Hidden containers keep their lines but are not uploaded.
+    pub visible: bool,
 }
 
 pub struct SharedDebugLines {
@@ -31,7 +33,7 @@ impl SharedDebugLines {
     pub fn new_container(&mut self, isometry: na::Isometry3<f32>, data: Vec<LinePoint>) -> i32 {
         let next_id = self.get_next_id();
         self.containers
-            .insert(next_id, Container { isometry, data });
+            .insert(next_id, Container { isometry, data, visible: true });
         self.invalidated = true;
         next_id
     }
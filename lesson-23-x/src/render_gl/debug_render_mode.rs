@@ -0,0 +1,52 @@
+use gl;
+
+/// Global switch between solid rendering and debug views of the scene geometry.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DebugRenderMode {
+    Solid,
+    /// Triangle edges only, with `glPolygonMode`.
+    Wireframe,
+    /// Surfaces are skipped, entities show their bounding boxes with debug lines.
+    Bounds,
+}
+
+impl DebugRenderMode {
+    pub fn next(self) -> DebugRenderMode {
+        match self {
+            DebugRenderMode::Solid => DebugRenderMode::Wireframe,
+            DebugRenderMode::Wireframe => DebugRenderMode::Bounds,
+            DebugRenderMode::Bounds => DebugRenderMode::Solid,
+        }
+    }
+
+    pub fn draws_surfaces(self) -> bool {
+        self != DebugRenderMode::Bounds
+    }
+
+    pub fn draws_bounds(self) -> bool {
+        self == DebugRenderMode::Bounds
+    }
+
+    /// Set polygon mode for the geometry rendered while the returned scope is alive.
+    pub fn begin(self, gl: &gl::Gl) -> DebugRenderScope {
+        if self == DebugRenderMode::Wireframe {
+            unsafe {
+                gl.PolygonMode(gl::FRONT_AND_BACK, gl::LINE);
+            }
+        }
+        DebugRenderScope { gl: gl.clone() }
+    }
+}
+
+/// Restores filled polygons when dropped.
+pub struct DebugRenderScope {
+    gl: gl::Gl,
+}
+
+impl Drop for DebugRenderScope {
+    fn drop(&mut self) {
+        unsafe {
+            self.gl.PolygonMode(gl::FRONT_AND_BACK, gl::FILL);
+        }
+    }
+}
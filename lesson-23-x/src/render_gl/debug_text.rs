@@ -0,0 +1,250 @@
+use failure;
+use gl;
+use nalgebra as na;
+use crate::camera::projection;
+use crate::render_gl::buffer::{Buffer, VertexArray};
+use crate::render_gl::data;
+use crate::render_gl::{ColorBuffer, ColorSpace, FrameStats, Program, StateCache, Texture};
+use crate::resources::Resources;
+use std::cell::RefCell;
+
+/// Monospace atlas with printable ASCII characters, starting from space.
+const FONT_ATLAS: &str = "fonts/debug_mono.png";
+const ATLAS_COLUMNS: u32 = 16;
+const ATLAS_ROWS: u32 = 6;
+const FIRST_CHAR: u32 = 32;
+
+#[derive(VertexAttribPointers, Copy, Clone, Debug)]
+#[repr(C, packed)]
+struct GlyphVertex {
+    #[location = "0"]
+    pos: data::f32_f32,
+    #[location = "1"]
+    uv: data::f32_f32,
+    #[location = "2"]
+    color: data::u2_u10_u10_u10_rev_float,
+}
+
+enum Anchor {
+    /// Pixels from top left corner of the viewport.
+    Screen(f32, f32),
+    World(na::Point3<f32>),
+}
+
+struct Label {
+    anchor: Anchor,
+    text: String,
+    color: na::Vector4<f32>,
+}
+
+/// Text drawn on top of the scene, queued during the frame and batched in `render`.
+pub struct DebugText {
+    program: Program,
+    program_view_projection_location: Option<i32>,
+    program_font_location: Option<i32>,
+    font: Texture,
+    glyph_width: f32,
+    glyph_height: f32,
+    scale: f32,
+    draw_enabled: bool,
+    labels: RefCell<Vec<Label>>,
+    vertices: Vec<GlyphVertex>,
+    vbo: Buffer,
+    vao: VertexArray,
+}
+
+impl DebugText {
+    pub fn new(gl: &gl::Gl, res: &Resources) -> Result<DebugText, failure::Error> {
+        let program = Program::from_res(gl, res, "shaders/render_gl/debug_text")?;
+        let program_view_projection_location = program.get_uniform_location("ViewProjection");
+        let program_font_location = program.get_uniform_location("Font");
+
+        let img = res.load_rgba_image(FONT_ATLAS)?;
+        let font = Texture::from_rgba_image(gl, &img, ColorSpace::Linear, false);
+
+        let vbo = Buffer::new_array(gl);
+        let vao = VertexArray::new(gl);
+        vao.bind();
+        vbo.bind();
+        GlyphVertex::vertex_attrib_pointers(gl);
+        vbo.unbind();
+        vao.unbind();
+
+        Ok(DebugText {
+            program,
+            program_view_projection_location,
+            program_font_location,
+            font,
+            glyph_width: (img.width() / ATLAS_COLUMNS) as f32,
+            glyph_height: (img.height() / ATLAS_ROWS) as f32,
+            scale: 0.6,
+            draw_enabled: true,
+            labels: RefCell::new(Vec::new()),
+            vertices: Vec::new(),
+            vbo,
+            vao,
+        })
+    }
+
+    pub fn toggle(&mut self) {
+        self.draw_enabled = !self.draw_enabled;
+    }
+
+    /// Glyph size relative to the atlas cell size.
+    pub fn set_scale(&mut self, scale: f32) {
+        self.scale = scale;
+    }
+
+    /// Height of a single line in pixels.
+    pub fn line_height(&self) -> f32 {
+        self.glyph_height * self.scale
+    }
+
+    /// Draw text with top left corner at pixel position from the top left of the viewport.
+    pub fn draw_2d(&self, x: f32, y: f32, text: &str) {
+        self.draw_2d_colored(x, y, text, na::Vector4::new(1.0, 1.0, 1.0, 1.0));
+    }
+
+    pub fn draw_2d_colored(&self, x: f32, y: f32, text: &str, color: na::Vector4<f32>) {
+        self.labels.borrow_mut().push(Label {
+            anchor: Anchor::Screen(x, y),
+            text: text.into(),
+            color,
+        });
+    }
+
+    /// Draw label centered above the world position. Hidden if the position is behind
+    /// the camera.
+    pub fn draw_3d(&self, world_pos: na::Point3<f32>, text: &str) {
+        self.draw_3d_colored(world_pos, text, na::Vector4::new(1.0, 1.0, 1.0, 1.0));
+    }
+
+    pub fn draw_3d_colored(&self, world_pos: na::Point3<f32>, text: &str, color: na::Vector4<f32>) {
+        self.labels.borrow_mut().push(Label {
+            anchor: Anchor::World(world_pos),
+            text: text.into(),
+            color,
+        });
+    }
+
+    /// Draw and clear all text queued this frame.
+    ///
+    /// `ui_matrix` maps pixels to clip space with origin at the bottom left, `vp_matrix`
+    /// is used to place world-space labels.
+    pub fn render(
+        &mut self,
+        gl: &gl::Gl,
+        target: &ColorBuffer,
+        ui_matrix: &na::Matrix4<f32>,
+        vp_matrix: &na::Matrix4<f32>,
+        view_width_pixels: i32,
+        view_height_pixels: i32,
+    ) {
+        let labels: Vec<_> = self.labels.borrow_mut().drain(..).collect();
+        if !self.draw_enabled || labels.is_empty() {
+            return;
+        }
+
+        let (width, height) = (view_width_pixels as f32, view_height_pixels as f32);
+
+        self.vertices.clear();
+        for label in &labels {
+            let text_width = label.text.chars().count() as f32 * self.glyph_width * self.scale;
+
+            // convert to pixels from the bottom left
+            let (x, y) = match label.anchor {
+                Anchor::Screen(x, y) => (x, height - y),
+                Anchor::World(pos) => {
+                    let ndc = match projection::world_to_ndc(&pos, vp_matrix) {
+                        Some(ndc) => ndc,
+                        None => continue,
+                    };
+                    (
+                        ((ndc.x + 1.0) * 0.5 * width - text_width * 0.5).round(),
+                        ((ndc.y + 1.0) * 0.5 * height + self.line_height()).round(),
+                    )
+                }
+            };
+
+            self.push_text(x, y, &label.text, label.color);
+        }
+
+        if self.vertices.is_empty() {
+            return;
+        }
+
+        self.vbo.bind();
+        self.vbo.stream_draw_data(&self.vertices);
+        self.vbo.unbind();
+
+        self.program.set_used();
+        if let Some(loc) = self.program_view_projection_location {
+            self.program.set_uniform_matrix_4fv(loc, ui_matrix);
+        }
+        if let Some(loc) = self.program_font_location {
+            self.font.bind_at(0);
+            self.program.set_uniform_1i(loc, 0);
+        }
+
+        self.vao.bind();
+        unsafe {
+            target.set_default_blend_func(gl);
+            target.enable_blend(gl);
+            StateCache::set_depth_test(gl, false);
+
+            gl.DrawArrays(gl::TRIANGLES, 0, self.vertices.len() as i32);
+            FrameStats::record_draw(gl::TRIANGLES, self.vertices.len() as i32, 1);
+
+            StateCache::set_depth_test(gl, true);
+            target.disable_blend(gl);
+        }
+        self.vao.unbind();
+    }
+
+    /// Push two triangles per glyph, with (x, y) at top left.
+    fn push_text(&mut self, x: f32, y: f32, text: &str, color: na::Vector4<f32>) {
+        let w = self.glyph_width * self.scale;
+        let h = self.glyph_height * self.scale;
+        let color: data::u2_u10_u10_u10_rev_float = (color.x, color.y, color.z, color.w).into();
+
+        let mut cursor_x = x;
+        let mut cursor_y = y;
+
+        for c in text.chars() {
+            if c == '\n' {
+                cursor_x = x;
+                cursor_y -= h;
+                continue;
+            }
+
+            let index = match (c as u32).checked_sub(FIRST_CHAR) {
+                Some(index) if index < ATLAS_COLUMNS * ATLAS_ROWS => index,
+                _ => '?' as u32 - FIRST_CHAR,
+            };
+
+            let u0 = (index % ATLAS_COLUMNS) as f32 / ATLAS_COLUMNS as f32;
+            let v0 = (index / ATLAS_COLUMNS) as f32 / ATLAS_ROWS as f32;
+            let u1 = u0 + 1.0 / ATLAS_COLUMNS as f32;
+            let v1 = v0 + 1.0 / ATLAS_ROWS as f32;
+
+            let (left, right, top, bottom) = (cursor_x, cursor_x + w, cursor_y, cursor_y - h);
+
+            let vertex = |x, y, u, v| GlyphVertex {
+                pos: (x, y).into(),
+                uv: (u, v).into(),
+                color,
+            };
+
+            self.vertices.extend_from_slice(&[
+                vertex(left, top, u0, v0),
+                vertex(left, bottom, u0, v1),
+                vertex(right, bottom, u1, v1),
+                vertex(left, top, u0, v0),
+                vertex(right, bottom, u1, v1),
+                vertex(right, top, u1, v0),
+            ]);
+
+            cursor_x += w;
+        }
+    }
+}
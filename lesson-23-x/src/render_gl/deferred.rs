@@ -0,0 +1,188 @@
+use failure;
+use gl;
+use crate::render;
+use crate::render_gl::buffer::VertexArray;
+use crate::render_gl::{
+    ColorFormat, FrameStats, Framebuffer, FramebufferBinding, FramebufferError, Program, Ssao,
+    StateCache,
+};
+use crate::resources::Resources;
+
+/// Entity that can write its surface into G-buffer.
+///
+/// Geometry pass programs should write albedo to output 0, world normal to output 1
+/// and world position to output 2.
+pub trait DeferredGeometry {
+    fn render_geometry(&self, gl: &gl::Gl, pass: &GeometryPass);
+}
+
+/// Deferred shading pipeline: geometry is first rendered into the G-buffer,
+/// and then all lights from the `Lights` uniform block are applied in a single fullscreen pass.
+///
+/// Ambient light is darkened by screen-space ambient occlusion computed from the G-buffer.
+pub struct DeferredRenderer {
+    gl: gl::Gl,
+    gbuffer: Framebuffer,
+    lighting_program: Program,
+    lighting_albedo_location: Option<i32>,
+    lighting_normal_location: Option<i32>,
+    lighting_position_location: Option<i32>,
+    lighting_ao_location: Option<i32>,
+    lighting_use_ao_location: Option<i32>,
+    ssao: Ssao,
+    fullscreen_vao: VertexArray,
+}
+
+impl DeferredRenderer {
+    pub fn new(
+        gl: &gl::Gl,
+        res: &Resources,
+        width: i32,
+        height: i32,
+    ) -> Result<DeferredRenderer, failure::Error> {
+        let gbuffer = Framebuffer::builder(width, height)
+            .with_color(ColorFormat::Rgba8)
+            .with_color(ColorFormat::Rgba16f)
+            .with_color(ColorFormat::Rgba32f)
+            .with_depth_stencil()
+            .build(gl)?;
+
+        let lighting_program = Program::from_res(gl, res, "shaders/render_gl/deferred_lighting")?;
+        lighting_program.bind_uniform_block("Camera", render::CAMERA_UNIFORMS_BINDING);
+        lighting_program.bind_uniform_block("Lights", render::LIGHT_UNIFORMS_BINDING);
+        lighting_program.bind_uniform_block("Fog", render::FOG_UNIFORMS_BINDING);
+
+        Ok(DeferredRenderer {
+            gl: gl.clone(),
+            gbuffer,
+            lighting_albedo_location: lighting_program.get_uniform_location("Albedo"),
+            lighting_normal_location: lighting_program.get_uniform_location("Normal"),
+            lighting_position_location: lighting_program.get_uniform_location("Position"),
+            lighting_ao_location: lighting_program.get_uniform_location("AmbientOcclusion"),
+            lighting_use_ao_location: lighting_program
+                .get_uniform_location("UseAmbientOcclusion"),
+            lighting_program,
+            ssao: Ssao::new(gl, res, width, height)?,
+            fullscreen_vao: VertexArray::new(gl),
+        })
+    }
+
+    pub fn resize(&mut self, width: i32, height: i32) -> Result<(), FramebufferError> {
+        self.gbuffer.resize(width, height)?;
+        self.ssao.resize(width, height)
+    }
+
+    pub fn ssao(&self) -> &Ssao {
+        &self.ssao
+    }
+
+    /// Occlusion settings, applied on the next `lighting_pass`.
+    pub fn ssao_mut(&mut self) -> &mut Ssao {
+        &mut self.ssao
+    }
+
+    pub fn gbuffer(&self) -> &Framebuffer {
+        &self.gbuffer
+    }
+
+    /// Bind and clear the G-buffer. Render entities while the returned pass is alive.
+    pub fn geometry_pass(&self) -> GeometryPass {
+        let binding = self.gbuffer.bind();
+
+        let mut clear_color: [f32; 4] = [0.0; 4];
+        unsafe {
+            self.gl
+                .GetFloatv(gl::COLOR_CLEAR_VALUE, clear_color.as_mut_ptr());
+            // zero albedo alpha marks pixels without geometry
+            self.gl.ClearColor(0.0, 0.0, 0.0, 0.0);
+            self.gl
+                .Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT | gl::STENCIL_BUFFER_BIT);
+            self.gl.ClearColor(
+                clear_color[0],
+                clear_color[1],
+                clear_color[2],
+                clear_color[3],
+            );
+        }
+
+        GeometryPass { _binding: binding }
+    }
+
+    /// Shade the G-buffer into the currently bound framebuffer, then copy G-buffer depth
+    /// so that forward-rendered overlays are occluded correctly.
+    ///
+    /// Depth copy requires the target framebuffer to have 24 bit depth and 8 bit stencil.
+    pub fn lighting_pass(&self) {
+        let gl = &self.gl;
+
+        let use_ao = self.ssao.enabled;
+        if use_ao {
+            if let (Some(normal), Some(position)) =
+                (self.gbuffer.color_texture(1), self.gbuffer.color_texture(2))
+            {
+                self.ssao.render(normal, position);
+            }
+        }
+
+        self.lighting_program.set_used();
+
+        let attachments = [
+            (self.lighting_albedo_location, self.gbuffer.color_texture(0)),
+            (self.lighting_normal_location, self.gbuffer.color_texture(1)),
+            (self.lighting_position_location, self.gbuffer.color_texture(2)),
+        ];
+        for (index, &(location, texture)) in attachments.iter().enumerate() {
+            if let (Some(loc), Some(texture)) = (location, texture) {
+                texture.bind_at(index as u32);
+                self.lighting_program.set_uniform_1i(loc, index as i32);
+            }
+        }
+        if let Some(loc) = self.lighting_ao_location {
+            self.ssao.ao_texture().bind_at(3);
+            self.lighting_program.set_uniform_1i(loc, 3);
+        }
+        if let Some(loc) = self.lighting_use_ao_location {
+            self.lighting_program.set_uniform_1i(loc, use_ao as i32);
+        }
+
+        StateCache::set_depth_test(gl, false);
+
+        // fullscreen triangle is generated from vertex id in the shader
+        self.fullscreen_vao.bind();
+        unsafe {
+            gl.DrawArrays(gl::TRIANGLES, 0, 3);
+        }
+        FrameStats::record_draw(gl::TRIANGLES, 3, 1);
+        self.fullscreen_vao.unbind();
+
+        let mut target_fbo: gl::types::GLint = 0;
+        let mut viewport: [gl::types::GLint; 4] = [0; 4];
+
+        unsafe {
+            StateCache::set_depth_test(gl, true);
+
+            gl.GetIntegerv(gl::DRAW_FRAMEBUFFER_BINDING, &mut target_fbo);
+            gl.GetIntegerv(gl::VIEWPORT, viewport.as_mut_ptr());
+
+            gl.BindFramebuffer(gl::READ_FRAMEBUFFER, self.gbuffer.id());
+            gl.BlitFramebuffer(
+                0,
+                0,
+                self.gbuffer.width(),
+                self.gbuffer.height(),
+                viewport[0],
+                viewport[1],
+                viewport[0] + viewport[2],
+                viewport[1] + viewport[3],
+                gl::DEPTH_BUFFER_BIT,
+                gl::NEAREST,
+            );
+            gl.BindFramebuffer(gl::FRAMEBUFFER, target_fbo as gl::types::GLuint);
+        }
+    }
+}
+
+/// G-buffer binding, active while this value is alive.
+pub struct GeometryPass<'a> {
+    _binding: FramebufferBinding<'a>,
+}
@@ -0,0 +1,56 @@
+use nalgebra as na;
+use crate::render_gl::DebugText;
+use crate::resources::LoadErrors;
+
+/// Failures beyond this many are counted in the last line.
+const MAX_LINES: usize = 8;
+
+/// Text overlay listing resources that failed to load, while there are any.
+///
+/// A line disappears once a retry loads the resource.
+pub struct ErrorConsole {
+    pub enabled: bool,
+}
+
+impl ErrorConsole {
+    pub fn new() -> ErrorConsole {
+        ErrorConsole { enabled: true }
+    }
+
+    pub fn toggle(&mut self) {
+        self.enabled = !self.enabled;
+    }
+
+    /// Queue the text lines with top left corner at (x, y).
+    pub fn draw(&self, text: &DebugText, x: f32, y: f32) {
+        if !self.enabled {
+            return;
+        }
+        let errors = LoadErrors::list();
+        if errors.is_empty() {
+            return;
+        }
+
+        let mut lines = vec!["failed to load, retried when the files change:".to_string()];
+        lines.extend(
+            errors
+                .iter()
+                .take(MAX_LINES)
+                .map(|error| format!("{}: {}", error.path, error.message)),
+        );
+        if errors.len() > MAX_LINES {
+            lines.push(format!("and {} more", errors.len() - MAX_LINES));
+        }
+
+        let color = na::Vector4::new(1.0, 0.0, 1.0, 1.0);
+        for (index, line) in lines.iter().enumerate() {
+            text.draw_2d_colored(x, y + text.line_height() * index as f32, line, color);
+        }
+    }
+}
+
+impl Default for ErrorConsole {
+    fn default() -> ErrorConsole {
+        ErrorConsole::new()
+    }
+}
@@ -0,0 +1,61 @@
+use gl;
+use std::cell::Cell;
+
+/// Work submitted through the render_gl wrappers since the last `FrameStats::take`.
+///
+/// Counters are per thread, like `StateCache`. Draws issued with raw GL calls count
+/// only if they are followed by `record_draw`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct FrameStats {
+    pub draw_calls: usize,
+    pub triangles: usize,
+    pub buffer_uploads: usize,
+    pub uploaded_bytes: usize,
+    /// Texture binds that reached the driver, those elided by `StateCache` are not counted.
+    pub texture_binds: usize,
+}
+
+thread_local! {
+    static FRAME_STATS: Cell<FrameStats> = Cell::new(FrameStats::default());
+}
+
+impl FrameStats {
+    fn update<F: FnOnce(&mut FrameStats)>(f: F) {
+        FRAME_STATS.with(|stats| {
+            let mut current = stats.get();
+            f(&mut current);
+            stats.set(current);
+        });
+    }
+
+    /// Counters since the last call, usually once per frame.
+    pub fn take() -> FrameStats {
+        FRAME_STATS.with(|stats| stats.replace(FrameStats::default()))
+    }
+
+    /// Count one draw call of `vertex_count` vertices or indices in each instance.
+    pub fn record_draw(mode: gl::types::GLenum, vertex_count: i32, instance_count: i32) {
+        let vertex_count = vertex_count.max(0) as usize;
+        let triangles = match mode {
+            gl::TRIANGLES => vertex_count / 3,
+            gl::TRIANGLE_STRIP | gl::TRIANGLE_FAN => vertex_count.saturating_sub(2),
+            _ => 0,
+        };
+        FrameStats::update(|stats| {
+            stats.draw_calls += 1;
+            stats.triangles += triangles * instance_count.max(0) as usize;
+        });
+    }
+
+    /// Count a write of `bytes` into buffer storage.
+    pub fn record_upload(bytes: usize) {
+        FrameStats::update(|stats| {
+            stats.buffer_uploads += 1;
+            stats.uploaded_bytes += bytes;
+        });
+    }
+
+    pub(crate) fn record_texture_bind() {
+        FrameStats::update(|stats| stats.texture_binds += 1);
+    }
+}
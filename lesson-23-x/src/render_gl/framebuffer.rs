@@ -0,0 +1,520 @@
+use gl;
+use crate::render_gl::{Texture, TextureCube};
+
+#[derive(Debug, Fail)]
+pub enum Error {
+    #[fail(display = "Framebuffer is incomplete: {}", status)]
+    Incomplete { status: &'static str },
+    #[fail(display = "Framebuffer size {}x{} is invalid", width, height)]
+    InvalidSize { width: i32, height: i32 },
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ColorFormat {
+    Rgba8,
+    Rgba16f,
+    Rgba32f,
+    R8,
+    R32ui,
+}
+
+impl ColorFormat {
+    fn gl_formats(self) -> (gl::types::GLenum, gl::types::GLenum, gl::types::GLenum) {
+        match self {
+            ColorFormat::Rgba8 => (gl::RGBA8, gl::RGBA, gl::UNSIGNED_BYTE),
+            ColorFormat::Rgba16f => (gl::RGBA16F, gl::RGBA, gl::FLOAT),
+            ColorFormat::Rgba32f => (gl::RGBA32F, gl::RGBA, gl::FLOAT),
+            ColorFormat::R8 => (gl::R8, gl::RED, gl::UNSIGNED_BYTE),
+            ColorFormat::R32ui => (gl::R32UI, gl::RED_INTEGER, gl::UNSIGNED_INT),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum DepthFormat {
+    Depth,
+    DepthStencil,
+}
+
+impl DepthFormat {
+    fn gl_formats(self) -> (gl::types::GLenum, gl::types::GLenum, gl::types::GLenum) {
+        match self {
+            DepthFormat::Depth => (gl::DEPTH_COMPONENT24, gl::DEPTH_COMPONENT, gl::FLOAT),
+            DepthFormat::DepthStencil => {
+                (gl::DEPTH24_STENCIL8, gl::DEPTH_STENCIL, gl::UNSIGNED_INT_24_8)
+            }
+        }
+    }
+
+    fn attachment(self) -> gl::types::GLenum {
+        match self {
+            DepthFormat::Depth => gl::DEPTH_ATTACHMENT,
+            DepthFormat::DepthStencil => gl::DEPTH_STENCIL_ATTACHMENT,
+        }
+    }
+}
+
+/// Multisampled storage that can be blitted from, but not sampled in shaders.
+struct Renderbuffer {
+    gl: gl::Gl,
+    obj: gl::types::GLuint,
+}
+
+impl Renderbuffer {
+    fn new(
+        gl: &gl::Gl,
+        width: i32,
+        height: i32,
+        internal_format: gl::types::GLenum,
+        samples: i32,
+    ) -> Renderbuffer {
+        let mut obj: gl::types::GLuint = 0;
+        unsafe {
+            gl.GenRenderbuffers(1, &mut obj);
+        }
+
+        let renderbuffer = Renderbuffer {
+            gl: gl.clone(),
+            obj,
+        };
+        renderbuffer.allocate(width, height, internal_format, samples);
+        renderbuffer
+    }
+
+    fn allocate(&self, width: i32, height: i32, internal_format: gl::types::GLenum, samples: i32) {
+        unsafe {
+            self.gl.BindRenderbuffer(gl::RENDERBUFFER, self.obj);
+            self.gl.RenderbufferStorageMultisample(
+                gl::RENDERBUFFER,
+                samples,
+                internal_format,
+                width,
+                height,
+            );
+            self.gl.BindRenderbuffer(gl::RENDERBUFFER, 0);
+        }
+    }
+}
+
+impl Drop for Renderbuffer {
+    fn drop(&mut self) {
+        unsafe {
+            self.gl.DeleteRenderbuffers(1, &self.obj);
+        }
+    }
+}
+
+/// Texture for single-sampled framebuffers, renderbuffer for multisampled.
+enum Attachment {
+    Texture(Texture),
+    Renderbuffer(Renderbuffer),
+}
+
+impl Attachment {
+    fn new(
+        gl: &gl::Gl,
+        width: i32,
+        height: i32,
+        formats: (gl::types::GLenum, gl::types::GLenum, gl::types::GLenum),
+        samples: i32,
+    ) -> Attachment {
+        let (internal_format, format, data_type) = formats;
+        if samples > 0 {
+            Attachment::Renderbuffer(Renderbuffer::new(
+                gl,
+                width,
+                height,
+                internal_format,
+                samples,
+            ))
+        } else {
+            Attachment::Texture(Texture::new_empty(
+                gl,
+                width,
+                height,
+                internal_format,
+                format,
+                data_type,
+            ))
+        }
+    }
+
+    fn allocate(
+        &self,
+        width: i32,
+        height: i32,
+        formats: (gl::types::GLenum, gl::types::GLenum, gl::types::GLenum),
+        samples: i32,
+    ) {
+        let (internal_format, format, data_type) = formats;
+        match *self {
+            Attachment::Texture(ref texture) => {
+                texture.allocate_empty(width, height, internal_format, format, data_type)
+            }
+            Attachment::Renderbuffer(ref renderbuffer) => {
+                renderbuffer.allocate(width, height, internal_format, samples)
+            }
+        }
+    }
+
+    fn texture(&self) -> Option<&Texture> {
+        match *self {
+            Attachment::Texture(ref texture) => Some(texture),
+            Attachment::Renderbuffer(_) => None,
+        }
+    }
+
+    unsafe fn attach(&self, gl: &gl::Gl, attachment: gl::types::GLenum) {
+        match *self {
+            Attachment::Texture(ref texture) => {
+                gl.FramebufferTexture2D(
+                    gl::FRAMEBUFFER,
+                    attachment,
+                    gl::TEXTURE_2D,
+                    texture.id(),
+                    0,
+                );
+            }
+            Attachment::Renderbuffer(ref renderbuffer) => {
+                gl.FramebufferRenderbuffer(
+                    gl::FRAMEBUFFER,
+                    attachment,
+                    gl::RENDERBUFFER,
+                    renderbuffer.obj,
+                );
+            }
+        }
+    }
+}
+
+pub struct FramebufferBuilder {
+    width: i32,
+    height: i32,
+    samples: i32,
+    color: Vec<ColorFormat>,
+    depth: Option<DepthFormat>,
+}
+
+impl FramebufferBuilder {
+    /// Add color attachment; attachments are numbered in the order they are added.
+    pub fn with_color(mut self, format: ColorFormat) -> Self {
+        self.color.push(format);
+        self
+    }
+
+    pub fn with_depth(mut self) -> Self {
+        self.depth = Some(DepthFormat::Depth);
+        self
+    }
+
+    pub fn with_depth_stencil(mut self) -> Self {
+        self.depth = Some(DepthFormat::DepthStencil);
+        self
+    }
+
+    /// Use multisampled renderbuffers for all attachments. Zero disables multisampling.
+    ///
+    /// Multisampled attachments can not be sampled in shaders, use `Framebuffer::resolve`
+    /// to blit them into a single-sampled target.
+    pub fn with_samples(mut self, samples: i32) -> Self {
+        self.samples = samples.max(0);
+        self
+    }
+
+    pub fn build(self, gl: &gl::Gl) -> Result<Framebuffer, Error> {
+        check_size(self.width, self.height)?;
+
+        let mut fbo: gl::types::GLuint = 0;
+        unsafe {
+            gl.GenFramebuffers(1, &mut fbo);
+        }
+
+        let (width, height, samples) = (self.width, self.height, self.samples);
+
+        let color = self
+            .color
+            .into_iter()
+            .map(|format| {
+                let attachment = Attachment::new(gl, width, height, format.gl_formats(), samples);
+                (format, attachment)
+            }).collect::<Vec<_>>();
+
+        let depth = self.depth.map(|format| {
+            let attachment = Attachment::new(gl, width, height, format.gl_formats(), samples);
+            (format, attachment)
+        });
+
+        let framebuffer = Framebuffer {
+            gl: gl.clone(),
+            fbo,
+            width,
+            height,
+            samples,
+            color,
+            depth,
+        };
+
+        framebuffer.attach()?;
+
+        Ok(framebuffer)
+    }
+}
+
+/// Off-screen render target with texture attachments, or renderbuffers when multisampled.
+pub struct Framebuffer {
+    gl: gl::Gl,
+    fbo: gl::types::GLuint,
+    width: i32,
+    height: i32,
+    samples: i32,
+    color: Vec<(ColorFormat, Attachment)>,
+    depth: Option<(DepthFormat, Attachment)>,
+}
+
+impl Framebuffer {
+    pub fn builder(width: i32, height: i32) -> FramebufferBuilder {
+        FramebufferBuilder {
+            width,
+            height,
+            samples: 0,
+            color: Vec::new(),
+            depth: None,
+        }
+    }
+
+    pub fn id(&self) -> gl::types::GLuint {
+        self.fbo
+    }
+
+    pub fn width(&self) -> i32 {
+        self.width
+    }
+
+    pub fn height(&self) -> i32 {
+        self.height
+    }
+
+    /// Samples per pixel, zero if not multisampled.
+    pub fn samples(&self) -> i32 {
+        self.samples
+    }
+
+    /// Color attachment texture, `None` for multisampled framebuffers.
+    pub fn color_texture(&self, index: usize) -> Option<&Texture> {
+        self.color
+            .get(index)
+            .and_then(|&(_, ref attachment)| attachment.texture())
+    }
+
+    /// Depth attachment texture, `None` for multisampled framebuffers.
+    pub fn depth_texture(&self) -> Option<&Texture> {
+        self.depth
+            .as_ref()
+            .and_then(|&(_, ref attachment)| attachment.texture())
+    }
+
+    /// Render into a mipmap level of a cube map face instead of the first color attachment,
+    /// i.e. to fill a cube map with a depth-only framebuffer. `face` is 0 to 5 in GL order.
+    pub fn attach_cube_face(&self, cube: &TextureCube, face: u32, level: i32) {
+        let gl = &self.gl;
+        let mut previous_fbo: gl::types::GLint = 0;
+
+        unsafe {
+            gl.GetIntegerv(gl::DRAW_FRAMEBUFFER_BINDING, &mut previous_fbo);
+            gl.BindFramebuffer(gl::FRAMEBUFFER, self.fbo);
+            gl.FramebufferTexture2D(
+                gl::FRAMEBUFFER,
+                gl::COLOR_ATTACHMENT0,
+                gl::TEXTURE_CUBE_MAP_POSITIVE_X + face,
+                cube.id(),
+                level,
+            );
+            gl.DrawBuffer(gl::COLOR_ATTACHMENT0);
+            gl.ReadBuffer(gl::COLOR_ATTACHMENT0);
+            gl.BindFramebuffer(gl::FRAMEBUFFER, previous_fbo as gl::types::GLuint);
+        }
+    }
+
+    /// Reallocate all attachments for the new size. Contents are discarded.
+    pub fn resize(&mut self, width: i32, height: i32) -> Result<(), Error> {
+        check_size(width, height)?;
+
+        if width == self.width && height == self.height {
+            return Ok(());
+        }
+
+        self.width = width;
+        self.height = height;
+
+        for &(format, ref attachment) in &self.color {
+            attachment.allocate(width, height, format.gl_formats(), self.samples);
+        }
+
+        if let Some((format, ref attachment)) = self.depth {
+            attachment.allocate(width, height, format.gl_formats(), self.samples);
+        }
+
+        self.attach()
+    }
+
+    /// Blit first color attachment into the currently bound draw framebuffer, filling
+    /// the current viewport. Resolves multisampled framebuffers.
+    pub fn resolve(&self) {
+        let gl = &self.gl;
+        let mut previous_read_fbo: gl::types::GLint = 0;
+        let mut viewport: [gl::types::GLint; 4] = [0; 4];
+
+        unsafe {
+            gl.GetIntegerv(gl::READ_FRAMEBUFFER_BINDING, &mut previous_read_fbo);
+            gl.GetIntegerv(gl::VIEWPORT, viewport.as_mut_ptr());
+
+            gl.BindFramebuffer(gl::READ_FRAMEBUFFER, self.fbo);
+            gl.ReadBuffer(gl::COLOR_ATTACHMENT0);
+            gl.BlitFramebuffer(
+                0,
+                0,
+                self.width,
+                self.height,
+                viewport[0],
+                viewport[1],
+                viewport[0] + viewport[2],
+                viewport[1] + viewport[3],
+                gl::COLOR_BUFFER_BIT,
+                gl::NEAREST,
+            );
+            gl.BindFramebuffer(
+                gl::READ_FRAMEBUFFER,
+                previous_read_fbo as gl::types::GLuint,
+            );
+        }
+    }
+
+    /// Bind framebuffer for drawing and set viewport to its size.
+    ///
+    /// Previous framebuffer and viewport are restored when the returned guard is dropped.
+    pub fn bind(&self) -> FramebufferBinding {
+        FramebufferBinding::new(&self.gl, self.fbo, self.width, self.height)
+    }
+
+    fn attach(&self) -> Result<(), Error> {
+        let gl = &self.gl;
+
+        let mut previous_fbo: gl::types::GLint = 0;
+
+        unsafe {
+            gl.GetIntegerv(gl::DRAW_FRAMEBUFFER_BINDING, &mut previous_fbo);
+            gl.BindFramebuffer(gl::FRAMEBUFFER, self.fbo);
+
+            for (index, &(_, ref attachment)) in self.color.iter().enumerate() {
+                attachment.attach(gl, gl::COLOR_ATTACHMENT0 + index as u32);
+            }
+
+            if let Some((format, ref attachment)) = self.depth {
+                attachment.attach(gl, format.attachment());
+            }
+
+            if self.color.is_empty() {
+                gl.DrawBuffer(gl::NONE);
+                gl.ReadBuffer(gl::NONE);
+            } else {
+                let draw_buffers = (0..self.color.len())
+                    .map(|index| gl::COLOR_ATTACHMENT0 + index as u32)
+                    .collect::<Vec<_>>();
+                gl.DrawBuffers(draw_buffers.len() as i32, draw_buffers.as_ptr());
+            }
+        }
+
+        let status = unsafe { gl.CheckFramebufferStatus(gl::FRAMEBUFFER) };
+
+        unsafe {
+            gl.BindFramebuffer(gl::FRAMEBUFFER, previous_fbo as gl::types::GLuint);
+        }
+
+        if status != gl::FRAMEBUFFER_COMPLETE {
+            return Err(Error::Incomplete {
+                status: framebuffer_status_to_str(status),
+            });
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for Framebuffer {
+    fn drop(&mut self) {
+        unsafe {
+            self.gl.DeleteFramebuffers(1, &self.fbo);
+        }
+    }
+}
+
+/// Scoped framebuffer binding returned by `Framebuffer::bind`.
+pub struct FramebufferBinding<'a> {
+    gl: &'a gl::Gl,
+    previous_fbo: gl::types::GLuint,
+    previous_viewport: [gl::types::GLint; 4],
+}
+
+impl<'a> FramebufferBinding<'a> {
+    /// Bind framebuffer object not owned by a `Framebuffer`, i.e. with texture array layers.
+    pub(crate) fn new(
+        gl: &'a gl::Gl,
+        fbo: gl::types::GLuint,
+        width: i32,
+        height: i32,
+    ) -> FramebufferBinding<'a> {
+        let mut previous_fbo: gl::types::GLint = 0;
+        let mut previous_viewport: [gl::types::GLint; 4] = [0; 4];
+
+        unsafe {
+            gl.GetIntegerv(gl::DRAW_FRAMEBUFFER_BINDING, &mut previous_fbo);
+            gl.GetIntegerv(gl::VIEWPORT, previous_viewport.as_mut_ptr());
+
+            gl.BindFramebuffer(gl::FRAMEBUFFER, fbo);
+            gl.Viewport(0, 0, width, height);
+        }
+
+        FramebufferBinding {
+            gl,
+            previous_fbo: previous_fbo as gl::types::GLuint,
+            previous_viewport,
+        }
+    }
+}
+
+impl<'a> Drop for FramebufferBinding<'a> {
+    fn drop(&mut self) {
+        unsafe {
+            self.gl.BindFramebuffer(gl::FRAMEBUFFER, self.previous_fbo);
+            self.gl.Viewport(
+                self.previous_viewport[0],
+                self.previous_viewport[1],
+                self.previous_viewport[2],
+                self.previous_viewport[3],
+            );
+        }
+    }
+}
+
+fn check_size(width: i32, height: i32) -> Result<(), Error> {
+    if width <= 0 || height <= 0 {
+        return Err(Error::InvalidSize { width, height });
+    }
+    Ok(())
+}
+
+fn framebuffer_status_to_str(status: gl::types::GLenum) -> &'static str {
+    match status {
+        gl::FRAMEBUFFER_UNDEFINED => "FRAMEBUFFER_UNDEFINED",
+        gl::FRAMEBUFFER_INCOMPLETE_ATTACHMENT => "FRAMEBUFFER_INCOMPLETE_ATTACHMENT",
+        gl::FRAMEBUFFER_INCOMPLETE_MISSING_ATTACHMENT => {
+            "FRAMEBUFFER_INCOMPLETE_MISSING_ATTACHMENT"
+        }
+        gl::FRAMEBUFFER_INCOMPLETE_DRAW_BUFFER => "FRAMEBUFFER_INCOMPLETE_DRAW_BUFFER",
+        gl::FRAMEBUFFER_INCOMPLETE_READ_BUFFER => "FRAMEBUFFER_INCOMPLETE_READ_BUFFER",
+        gl::FRAMEBUFFER_UNSUPPORTED => "FRAMEBUFFER_UNSUPPORTED",
+        gl::FRAMEBUFFER_INCOMPLETE_MULTISAMPLE => "FRAMEBUFFER_INCOMPLETE_MULTISAMPLE",
+        gl::FRAMEBUFFER_INCOMPLETE_LAYER_TARGETS => "FRAMEBUFFER_INCOMPLETE_LAYER_TARGETS",
+        _ => "Unknown framebuffer status",
+    }
+}
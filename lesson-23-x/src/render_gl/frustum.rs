@@ -0,0 +1,79 @@
+use nalgebra as na;
+use ncollide3d::bounding_volume::aabb::AABB;
+use crate::mesh;
+
+/// Object that can be tested against the view frustum before rendering.
+pub trait Cullable {
+    /// Local AABB and model matrix. Objects without bounds are always drawn.
+    fn bounds(&self) -> Option<(AABB<f32>, na::Matrix4<f32>)>;
+}
+
+/// View volume as six inward-facing planes, `normal.dot(p) + w >= 0` inside.
+#[derive(Copy, Clone, Debug)]
+pub struct Frustum {
+    planes: [na::Vector4<f32>; 6],
+}
+
+impl Frustum {
+    /// Extract planes from view-projection matrix.
+    pub fn from_matrix(view_projection: &na::Matrix4<f32>) -> Frustum {
+        let row = |index: usize| view_projection.row(index).transpose();
+        let (r0, r1, r2, r3) = (row(0), row(1), row(2), row(3));
+
+        let normalize = |plane: na::Vector4<f32>| {
+            let length = plane.fixed_rows::<na::U3>(0).norm();
+            if length > 0.0 {
+                plane / length
+            } else {
+                plane
+            }
+        };
+
+        Frustum {
+            planes: [
+                normalize(r3 + r0), // left
+                normalize(r3 - r0), // right
+                normalize(r3 + r1), // bottom
+                normalize(r3 - r1), // top
+                normalize(r3 + r2), // near
+                normalize(r3 - r2), // far
+            ],
+        }
+    }
+
+    pub fn contains_point(&self, point: &na::Point3<f32>) -> bool {
+        self.planes
+            .iter()
+            .all(|plane| plane_distance(plane, &point.coords) >= 0.0)
+    }
+
+    pub fn intersects_sphere(&self, center: &na::Point3<f32>, radius: f32) -> bool {
+        self.planes
+            .iter()
+            .all(|plane| plane_distance(plane, &center.coords) >= -radius)
+    }
+
+    /// Conservative test, may report boxes near frustum corners as visible.
+    pub fn intersects_aabb(&self, aabb: &AABB<f32>, model_matrix: &na::Matrix4<f32>) -> bool {
+        let world = mesh::transform_aabb(aabb, model_matrix);
+        let (center, half_extents) = (world.center().coords, world.half_extents());
+
+        self.planes.iter().all(|plane| {
+            let radius = half_extents.x * plane.x.abs()
+                + half_extents.y * plane.y.abs()
+                + half_extents.z * plane.z.abs();
+            plane_distance(plane, &center) >= -radius
+        })
+    }
+
+    pub fn is_visible<T: Cullable>(&self, object: &T) -> bool {
+        match object.bounds() {
+            Some((aabb, model_matrix)) => self.intersects_aabb(&aabb, &model_matrix),
+            None => true,
+        }
+    }
+}
+
+fn plane_distance(plane: &na::Vector4<f32>, point: &na::Vector3<f32>) -> f32 {
+    plane.x * point.x + plane.y * point.y + plane.z * point.z + plane.w
+}
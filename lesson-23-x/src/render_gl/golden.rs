@@ -0,0 +1,248 @@
+use gl;
+use image;
+use crate::render_gl::capture;
+use crate::render_gl::{ColorFormat, Framebuffer, FramebufferError, StateCache};
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Set to write rendered images as new references instead of comparing against them.
+pub const UPDATE_ENV_VAR: &str = "UPDATE_GOLDEN";
+
+#[derive(Debug, Fail)]
+pub enum Error {
+    #[fail(display = "Failed to create offscreen target: {}", inner)]
+    Framebuffer {
+        #[cause]
+        inner: FramebufferError,
+    },
+    #[fail(display = "Failed to read rendered image: {}", inner)]
+    Capture {
+        #[cause]
+        inner: capture::Error,
+    },
+    #[fail(display = "Failed to load reference {:?}: {}", path, inner)]
+    FailedToLoadReference {
+        path: PathBuf,
+        #[cause]
+        inner: image::ImageError,
+    },
+    #[fail(display = "Failed to save {:?}: {}", path, inner)]
+    FailedToSave {
+        path: PathBuf,
+        #[cause]
+        inner: io::Error,
+    },
+    #[fail(
+        display = "Reference {:?} does not exist, run with {}=1 to create it",
+        path, env_var
+    )]
+    MissingReference { path: PathBuf, env_var: &'static str },
+    #[fail(
+        display = "Rendered size {:?} differs from reference {:?} size {:?}",
+        actual, path, expected
+    )]
+    SizeMismatch {
+        path: PathBuf,
+        actual: (u32, u32),
+        expected: (u32, u32),
+    },
+    #[fail(
+        display = "{} pixels differ from reference {:?} by up to {}, see {:?}",
+        differing_pixels, path, max_channel_difference, actual_path
+    )]
+    Mismatch {
+        path: PathBuf,
+        actual_path: PathBuf,
+        differing_pixels: usize,
+        max_channel_difference: u8,
+    },
+}
+
+/// Allowed difference between rendered and reference images, drivers do not
+/// rasterize and filter identically.
+#[derive(Copy, Clone, Debug)]
+pub struct Tolerance {
+    /// Largest difference of a channel that does not count the pixel as differing.
+    pub channel: u8,
+    /// Number of differing pixels that still passes, i.e. along triangle edges.
+    pub pixels: usize,
+}
+
+impl Default for Tolerance {
+    fn default() -> Self {
+        Tolerance {
+            channel: 2,
+            pixels: 0,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct ImageDiff {
+    pub differing_pixels: usize,
+    pub max_channel_difference: u8,
+}
+
+/// Compare images of the same size, pixel by pixel.
+pub fn compare_images(
+    actual: &image::RgbaImage,
+    expected: &image::RgbaImage,
+    channel_tolerance: u8,
+) -> ImageDiff {
+    assert_eq!(actual.dimensions(), expected.dimensions());
+
+    let mut diff = ImageDiff::default();
+    for (a, b) in actual.pixels().zip(expected.pixels()) {
+        let difference = a
+            .data
+            .iter()
+            .zip(b.data.iter())
+            .map(|(&a, &b)| if a > b { a - b } else { b - a })
+            .max()
+            .unwrap_or(0);
+        diff.max_channel_difference = diff.max_channel_difference.max(difference);
+        if difference > channel_tolerance {
+            diff.differing_pixels += 1;
+        }
+    }
+    diff
+}
+
+/// Render into a new RGBA8 framebuffer with depth and read the result back.
+pub fn render_offscreen<F: FnOnce()>(
+    gl: &gl::Gl,
+    width: i32,
+    height: i32,
+    clear_color: [f32; 4],
+    draw: F,
+) -> Result<image::RgbaImage, Error> {
+    let framebuffer = Framebuffer::builder(width, height)
+        .with_color(ColorFormat::Rgba8)
+        .with_depth()
+        .build(gl)
+        .map_err(|inner| Error::Framebuffer { inner })?;
+
+    {
+        let _binding = framebuffer.bind();
+        StateCache::set_depth_test(gl, true);
+        StateCache::set_depth_mask(gl, true);
+        unsafe {
+            gl.ClearColor(clear_color[0], clear_color[1], clear_color[2], clear_color[3]);
+            gl.Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
+        }
+        draw();
+    }
+
+    capture::read_framebuffer(gl, &framebuffer).map_err(|inner| Error::Capture { inner })
+}
+
+/// Compare the image against the reference PNG.
+///
+/// On failure the image is saved next to the reference with `.actual.png` extension.
+/// With `UPDATE_GOLDEN` set the reference is overwritten instead.
+pub fn check_reference<P: AsRef<Path>>(
+    actual: &image::RgbaImage,
+    reference: P,
+    tolerance: Tolerance,
+) -> Result<ImageDiff, Error> {
+    let path = reference.as_ref();
+    let actual_path = path.with_extension("actual.png");
+
+    if env::var_os(UPDATE_ENV_VAR).is_some() {
+        save(actual, path)?;
+        return Ok(ImageDiff::default());
+    }
+
+    if !path.exists() {
+        save(actual, &actual_path)?;
+        return Err(Error::MissingReference {
+            path: path.into(),
+            env_var: UPDATE_ENV_VAR,
+        });
+    }
+
+    let expected = image::open(path)
+        .map_err(|inner| Error::FailedToLoadReference {
+            path: path.into(),
+            inner,
+        })?.to_rgba();
+
+    if actual.dimensions() != expected.dimensions() {
+        save(actual, &actual_path)?;
+        return Err(Error::SizeMismatch {
+            path: path.into(),
+            actual: actual.dimensions(),
+            expected: expected.dimensions(),
+        });
+    }
+
+    let diff = compare_images(actual, &expected, tolerance.channel);
+    if diff.differing_pixels > tolerance.pixels {
+        save(actual, &actual_path)?;
+        return Err(Error::Mismatch {
+            path: path.into(),
+            actual_path,
+            differing_pixels: diff.differing_pixels,
+            max_channel_difference: diff.max_channel_difference,
+        });
+    }
+
+    Ok(diff)
+}
+
+fn save(img: &image::RgbaImage, path: &Path) -> Result<(), Error> {
+    let result = match path.parent() {
+        Some(dir) => fs::create_dir_all(dir).and_then(|_| img.save(path)),
+        None => img.save(path),
+    };
+    result.map_err(|inner| Error::FailedToSave {
+        path: path.into(),
+        inner,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filled(width: u32, height: u32, value: u8) -> image::RgbaImage {
+        image::ImageBuffer::from_pixel(width, height, image::Rgba([value, value, value, 255]))
+    }
+
+    #[test]
+    fn identical_images_do_not_differ() {
+        let img = filled(4, 4, 100);
+        assert_eq!(compare_images(&img, &img, 0), ImageDiff::default());
+    }
+
+    #[test]
+    fn differences_within_tolerance_are_not_counted() {
+        let mut actual = filled(4, 4, 100);
+        actual.put_pixel(1, 1, image::Rgba([102, 100, 100, 255]));
+        actual.put_pixel(2, 2, image::Rgba([100, 90, 100, 255]));
+
+        let diff = compare_images(&actual, &filled(4, 4, 100), 2);
+
+        assert_eq!(diff.differing_pixels, 1);
+        assert_eq!(diff.max_channel_difference, 10);
+    }
+
+    #[test]
+    fn missing_reference_saves_actual_image() {
+        if env::var_os(UPDATE_ENV_VAR).is_some() {
+            return;
+        }
+
+        let dir = env::temp_dir().join("golden-missing-reference");
+        let reference = dir.join("missing.png");
+        let _ = fs::remove_file(&reference);
+
+        match check_reference(&filled(2, 2, 0), &reference, Tolerance::default()) {
+            Err(Error::MissingReference { .. }) => (),
+            other => panic!("unexpected result {:?}", other),
+        }
+        assert!(dir.join("missing.actual.png").exists());
+    }
+}
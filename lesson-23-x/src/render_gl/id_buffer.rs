@@ -0,0 +1,180 @@
+use failure;
+use gl;
+use nalgebra as na;
+use crate::render_gl::buffer::Buffer;
+use crate::render_gl::{
+    ColorFormat, Framebuffer, FramebufferBinding, FramebufferError, Program, StateCache, Uniform,
+};
+use crate::resources::Resources;
+
+/// Pixel reads are mapped this many frames later, so that picking never stalls the GPU.
+const FRAMES_IN_FLIGHT: usize = 3;
+
+/// Id written where nothing was drawn.
+pub const NO_ID: u32 = 0;
+
+/// Object ids rendered into a R32UI target, for pixel-perfect picking under the cursor.
+///
+/// Draw pickable meshes between `begin` and the drop of returned `IdPass`, then `request`
+/// the pixel under the cursor. The id becomes available from `poll` a few frames later.
+pub struct IdBuffer {
+    gl: gl::Gl,
+    framebuffer: Framebuffer,
+    program: Program,
+    view_projection_uniform: Uniform<na::Matrix4<f32>>,
+    model_uniform: Uniform<na::Matrix4<f32>>,
+    id_uniform: Uniform<u32>,
+    slots: Vec<(Buffer, bool)>,
+    current: usize,
+    last_id: Option<u32>,
+}
+
+impl IdBuffer {
+    pub fn new(
+        gl: &gl::Gl,
+        res: &Resources,
+        width: i32,
+        height: i32,
+    ) -> Result<IdBuffer, failure::Error> {
+        let framebuffer = Framebuffer::builder(width, height)
+            .with_color(ColorFormat::R32ui)
+            .with_depth()
+            .build(gl)?;
+
+        let program = Program::from_res(gl, res, "shaders/render_gl/object_id")?;
+        let view_projection_uniform = program.uniform("ViewProjection");
+        let model_uniform = program.uniform("Model");
+        let id_uniform = program.uniform("Id");
+
+        Ok(IdBuffer {
+            gl: gl.clone(),
+            framebuffer,
+            program,
+            view_projection_uniform,
+            model_uniform,
+            id_uniform,
+            slots: (0..FRAMES_IN_FLIGHT)
+                .map(|_| {
+                    let pbo = Buffer::new_pixel_pack(gl);
+                    pbo.bind();
+                    pbo.stream_draw_data_null::<u32>(1);
+                    pbo.unbind();
+                    (pbo, false)
+                }).collect(),
+            current: 0,
+            last_id: None,
+        })
+    }
+
+    /// Follow the window size, ids must line up with the pixels under the cursor.
+    pub fn resize(&mut self, width: i32, height: i32) -> Result<(), FramebufferError> {
+        self.framebuffer.resize(width, height)
+    }
+
+    pub fn width(&self) -> i32 {
+        self.framebuffer.width()
+    }
+
+    pub fn height(&self) -> i32 {
+        self.framebuffer.height()
+    }
+
+    /// Bind id target and clear it to `NO_ID`.
+    pub fn begin(&self, view_projection: &na::Matrix4<f32>) -> IdPass {
+        let binding = self.framebuffer.bind();
+
+        StateCache::set_depth_test(&self.gl, true);
+        StateCache::set_depth_mask(&self.gl, true);
+        let clear_id = [NO_ID; 4];
+        unsafe {
+            self.gl.ClearBufferuiv(gl::COLOR, 0, clear_id.as_ptr());
+            self.gl.Clear(gl::DEPTH_BUFFER_BIT);
+        }
+
+        self.program.set_used();
+        self.view_projection_uniform
+            .set(&self.program, view_projection);
+
+        IdPass {
+            id_buffer: self,
+            _binding: binding,
+        }
+    }
+
+    /// Start reading the id at window position, with the origin at the top left corner.
+    ///
+    /// Positions outside the target are ignored.
+    pub fn request(&mut self, x: i32, y: i32) {
+        let (width, height) = (self.width(), self.height());
+        if x < 0 || y < 0 || x >= width || y >= height {
+            return;
+        }
+
+        self.map_slot(self.current);
+
+        let (ref pbo, ref mut pending) = self.slots[self.current];
+        let mut previous_fbo: gl::types::GLint = 0;
+        pbo.bind();
+        unsafe {
+            self.gl
+                .GetIntegerv(gl::READ_FRAMEBUFFER_BINDING, &mut previous_fbo);
+            self.gl
+                .BindFramebuffer(gl::READ_FRAMEBUFFER, self.framebuffer.id());
+            self.gl.ReadBuffer(gl::COLOR_ATTACHMENT0);
+            self.gl.PixelStorei(gl::PACK_ALIGNMENT, 4);
+            self.gl.ReadPixels(
+                x,
+                height - 1 - y,
+                1,
+                1,
+                gl::RED_INTEGER,
+                gl::UNSIGNED_INT,
+                ::std::ptr::null_mut(),
+            );
+            self.gl
+                .BindFramebuffer(gl::READ_FRAMEBUFFER, previous_fbo as gl::types::GLuint);
+        }
+        pbo.unbind();
+        *pending = true;
+
+        self.current = (self.current + 1) % self.slots.len();
+    }
+
+    /// Map the oldest request that had time to finish and return the latest known id.
+    ///
+    /// `None` until the first request completes.
+    pub fn poll(&mut self) -> Option<u32> {
+        self.map_slot(self.current);
+        self.last_id
+    }
+
+    fn map_slot(&mut self, index: usize) {
+        let (ref pbo, ref mut pending) = self.slots[index];
+        if !*pending {
+            return;
+        }
+        *pending = false;
+
+        pbo.bind();
+        if let Some(mapped) = unsafe { pbo.map_buffer_range_read::<u32>(0, 1) } {
+            self.last_id = Some(mapped[0]);
+        }
+        pbo.unbind();
+    }
+}
+
+/// Id pass into the `IdBuffer`, active while this value is alive.
+pub struct IdPass<'a> {
+    id_buffer: &'a IdBuffer,
+    _binding: FramebufferBinding<'a>,
+}
+
+impl<'a> IdPass<'a> {
+    /// Use id program for the mesh with the model matrix, drawn pixels get the id.
+    pub fn prepare_model(&self, model_matrix: &na::Matrix4<f32>, id: u32) {
+        let id_buffer = self.id_buffer;
+        id_buffer.program.set_used();
+        id_buffer.model_uniform.set(&id_buffer.program, model_matrix);
+        id_buffer.id_uniform.set(&id_buffer.program, &id);
+    }
+}
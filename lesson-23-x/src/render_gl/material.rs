@@ -0,0 +1,310 @@
+use crate::render_gl::{
+    ActiveUniform, MorphTargets, Program, RasterState, Texture, Texture2DArray, TextureCube,
+};
+use gl;
+use nalgebra as na;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+/// Value that can be assigned to a uniform of matching GL type.
+pub trait UniformValue {
+    /// GL type name used in type mismatch messages.
+    const TYPE_NAME: &'static str;
+
+    fn accepts(kind: gl::types::GLenum) -> bool;
+
+    fn set(&self, program: &Program, location: i32);
+}
+
+impl UniformValue for f32 {
+    const TYPE_NAME: &'static str = "float";
+
+    fn accepts(kind: gl::types::GLenum) -> bool {
+        kind == gl::FLOAT
+    }
+
+    fn set(&self, program: &Program, location: i32) {
+        program.set_uniform_1f(location, *self);
+    }
+}
+
+impl UniformValue for i32 {
+    const TYPE_NAME: &'static str = "int";
+
+    fn accepts(kind: gl::types::GLenum) -> bool {
+        kind == gl::INT || kind == gl::BOOL || is_sampler(kind)
+    }
+
+    fn set(&self, program: &Program, location: i32) {
+        program.set_uniform_1i(location, *self);
+    }
+}
+
+impl UniformValue for u32 {
+    const TYPE_NAME: &'static str = "uint";
+
+    fn accepts(kind: gl::types::GLenum) -> bool {
+        kind == gl::UNSIGNED_INT
+    }
+
+    fn set(&self, program: &Program, location: i32) {
+        program.set_uniform_1ui(location, *self);
+    }
+}
+
+impl UniformValue for bool {
+    const TYPE_NAME: &'static str = "bool";
+
+    fn accepts(kind: gl::types::GLenum) -> bool {
+        kind == gl::BOOL || kind == gl::INT
+    }
+
+    fn set(&self, program: &Program, location: i32) {
+        program.set_uniform_1i(location, *self as i32);
+    }
+}
+
+impl UniformValue for na::Vector2<f32> {
+    const TYPE_NAME: &'static str = "vec2";
+
+    fn accepts(kind: gl::types::GLenum) -> bool {
+        kind == gl::FLOAT_VEC2
+    }
+
+    fn set(&self, program: &Program, location: i32) {
+        program.set_uniform_2f(location, self);
+    }
+}
+
+impl UniformValue for na::Vector3<f32> {
+    const TYPE_NAME: &'static str = "vec3";
+
+    fn accepts(kind: gl::types::GLenum) -> bool {
+        kind == gl::FLOAT_VEC3
+    }
+
+    fn set(&self, program: &Program, location: i32) {
+        program.set_uniform_3f(location, self);
+    }
+}
+
+impl UniformValue for na::Vector4<f32> {
+    const TYPE_NAME: &'static str = "vec4";
+
+    fn accepts(kind: gl::types::GLenum) -> bool {
+        kind == gl::FLOAT_VEC4
+    }
+
+    fn set(&self, program: &Program, location: i32) {
+        program.set_uniform_4f(location, self);
+    }
+}
+
+impl UniformValue for na::Matrix4<f32> {
+    const TYPE_NAME: &'static str = "mat4";
+
+    fn accepts(kind: gl::types::GLenum) -> bool {
+        kind == gl::FLOAT_MAT4
+    }
+
+    fn set(&self, program: &Program, location: i32) {
+        program.set_uniform_matrix_4fv(location, self);
+    }
+}
+
+fn is_sampler(kind: gl::types::GLenum) -> bool {
+    match kind {
+            gl::SAMPLER_1D
+                | gl::SAMPLER_2D
+                | gl::SAMPLER_3D
+                | gl::SAMPLER_CUBE
+                | gl::SAMPLER_2D_SHADOW
+                | gl::SAMPLER_2D_ARRAY
+                | gl::SAMPLER_2D_ARRAY_SHADOW
+                | gl::SAMPLER_CUBE_SHADOW
+                | gl::SAMPLER_2D_MULTISAMPLE
+                | gl::SAMPLER_BUFFER
+                | gl::INT_SAMPLER_2D
+                | gl::UNSIGNED_INT_SAMPLER_2D => true,
+        _ => false,
+    }
+}
+
+struct Uniform {
+    info: ActiveUniform,
+    /// Texture unit reserved for samplers.
+    texture_unit: Option<u32>,
+}
+
+/// Program with uniforms reflected after linking, set by name.
+///
+/// Uniforms optimized out of the program are silently ignored, but setting a value
+/// of the wrong type panics. Texture units are assigned to samplers in reflection order.
+pub struct Material {
+    program: Rc<Program>,
+    uniforms: HashMap<String, Uniform>,
+    /// Applied by `set_used`, the state of the pass is kept if not set.
+    raster_state: Option<RasterState>,
+}
+
+impl Material {
+    pub fn new(program: Program) -> Material {
+        Material::shared(Rc::new(program))
+    }
+
+    /// Material for a program from `ProgramCache`, uniform values are shared with other
+    /// materials of the program.
+    pub fn shared(program: Rc<Program>) -> Material {
+        let mut next_unit = 0;
+        let uniforms = program
+            .active_uniforms()
+            .into_iter()
+            .map(|info| {
+                let texture_unit = if is_sampler(info.kind) {
+                    next_unit += 1;
+                    Some(next_unit - 1)
+                } else {
+                    None
+                };
+                (info.name.clone(), Uniform { info, texture_unit })
+            })
+            .collect::<HashMap<_, _>>();
+
+        // samplers of different types must not share unit 0 while nothing is bound to them
+        program.set_used();
+        for uniform in uniforms.values() {
+            if let Some(unit) = uniform.texture_unit {
+                program.set_uniform_1i(uniform.info.location, unit as i32);
+            }
+        }
+
+        Material {
+            program,
+            uniforms,
+            raster_state: None,
+        }
+    }
+
+    pub fn with_raster_state(mut self, raster_state: RasterState) -> Self {
+        self.raster_state = Some(raster_state);
+        self
+    }
+
+    pub fn raster_state(&self) -> Option<RasterState> {
+        self.raster_state
+    }
+
+    pub fn program(&self) -> &Program {
+        &self.program
+    }
+
+    /// Use the program and apply the raster state, if the material has one.
+    pub fn set_used(&self) {
+        self.program.set_used();
+        if let Some(ref raster_state) = self.raster_state {
+            raster_state.apply(self.program.gl());
+        }
+    }
+
+    pub fn has_uniform(&self, name: &str) -> bool {
+        self.uniforms.contains_key(name)
+    }
+
+    pub fn uniforms(&self) -> impl Iterator<Item = &ActiveUniform> {
+        self.uniforms.values().map(|uniform| &uniform.info)
+    }
+
+    /// Set uniform value, the program must be in use. Returns false if the uniform is not active.
+    pub fn set<T: UniformValue>(&self, name: &str, value: &T) -> bool {
+        let uniform = match self.uniforms.get(name) {
+            Some(uniform) => uniform,
+            None => return false,
+        };
+        assert!(
+            T::accepts(uniform.info.kind),
+            "uniform {} of GL type 0x{:x} can not be set from {}",
+            name,
+            uniform.info.kind,
+            T::TYPE_NAME
+        );
+        value.set(&self.program, uniform.info.location);
+        true
+    }
+
+    /// Set elements of an array uniform, values past the array length are ignored.
+    pub fn set_array<T: UniformValue>(&self, name: &str, values: &[T]) -> bool {
+        let uniform = match self.uniforms.get(name) {
+            Some(uniform) => uniform,
+            None => return false,
+        };
+        assert!(
+            T::accepts(uniform.info.kind),
+            "uniform {} of GL type 0x{:x} can not be set from {}",
+            name,
+            uniform.info.kind,
+            T::TYPE_NAME
+        );
+        for (index, value) in values.iter().take(uniform.info.size as usize).enumerate() {
+            value.set(&self.program, uniform.info.location + index as i32);
+        }
+        true
+    }
+
+    /// Bind texture to the unit reserved for the sampler. Returns false if the sampler is not active.
+    pub fn set_texture(&self, name: &str, texture: &Texture) -> bool {
+        match self.sampler_unit(name) {
+            Some(unit) => {
+                texture.bind_at(unit);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn set_texture_cube(&self, name: &str, texture: &TextureCube) -> bool {
+        match self.sampler_unit(name) {
+            Some(unit) => {
+                texture.bind_at(unit);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn set_texture_array(&self, name: &str, texture: &Texture2DArray) -> bool {
+        match self.sampler_unit(name) {
+            Some(unit) => {
+                texture.bind_at(unit);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn set_morph_targets(&self, name: &str, targets: &MorphTargets) -> bool {
+        match self.sampler_unit(name) {
+            Some(unit) => {
+                targets.bind_at(unit);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Texture unit assigned to the sampler uniform, `None` if the program does not use it.
+    pub fn texture_unit(&self, name: &str) -> Option<u32> {
+        self.uniforms
+            .get(name)
+            .and_then(|uniform| uniform.texture_unit)
+    }
+
+    fn sampler_unit(&self, name: &str) -> Option<u32> {
+        let uniform = self.uniforms.get(name)?;
+        let unit = uniform
+            .texture_unit
+            .unwrap_or_else(|| panic!("uniform {} is not a sampler", name));
+        self.program
+            .set_uniform_1i(uniform.info.location, unit as i32);
+        Some(unit)
+    }
+}
@@ -1,21 +1,117 @@
 pub mod buffer;
+pub mod capture;
 pub mod data;
+pub mod debug;
+pub mod golden;
+pub mod pbr;
 
+mod capabilities;
+mod clip_plane;
 mod color_buffer;
+mod compute;
 mod debug_lines;
+mod debug_render_mode;
+mod debug_text;
+mod deferred;
+mod error_console;
+mod frame_stats;
+mod framebuffer;
+mod frustum;
+mod id_buffer;
+mod material;
+mod morph_targets;
+mod msaa;
+mod occlusion_query;
+mod outline;
+mod permutations;
+mod pixel_inspector;
+mod planar_reflection;
+mod post_process;
 mod profiler;
+mod program_binary;
+mod program_cache;
+mod raster_state;
+mod reflection_probe;
+mod render_pass;
+mod sampler;
 mod shader;
+mod shader_source;
+mod shadow_map;
+mod ssao;
+mod status_line;
+mod state_cache;
+mod stream_buffer;
 mod texture;
+mod texture_array;
+mod texture_cache;
+mod texture_cube;
+mod uniform;
+mod uniform_buffer;
+mod upload_queue;
 mod viewport;
 
 use gl;
 
+pub use self::capabilities::Capabilities;
+pub use self::clip_plane::{clip_plane, ClipPlaneScope};
 pub use self::color_buffer::ColorBuffer;
-pub use self::debug_lines::{AabbMarker, DebugLines, RayMarkers};
-pub use self::profiler::{EventCountProfiler, FrameProfiler};
-pub use self::shader::{Error, Program, Shader};
-pub use self::texture::{Texture, TextureLoadBuilder, TextureLoadOptions};
-pub use self::viewport::Viewport;
+pub use self::compute::{
+    compute_supported, extension_supported, gl_version, memory_barrier, storage_to_draw_barrier,
+};
+pub use self::debug_lines::{
+    AabbMarker, DebugLines, FrustumMarker, GridMarker, LinesMarker, Marker, RayDensity,
+    RayMarkers,
+};
+pub use self::debug_render_mode::{DebugRenderMode, DebugRenderScope};
+pub use self::debug_text::DebugText;
+pub use self::deferred::{DeferredGeometry, DeferredRenderer, GeometryPass};
+pub use self::error_console::ErrorConsole;
+pub use self::frame_stats::FrameStats;
+pub use self::framebuffer::{
+    ColorFormat, Error as FramebufferError, Framebuffer, FramebufferBinding, FramebufferBuilder,
+};
+pub use self::frustum::{Cullable, Frustum};
+pub use self::id_buffer::{IdBuffer, IdPass, NO_ID};
+pub use self::material::{Material, UniformValue};
+pub use self::morph_targets::MorphTargets;
+pub use self::msaa::{MsaaPass, MsaaTarget};
+pub use self::occlusion_query::{
+    BoundsProxy, ConditionalRenderScope, OcclusionQuery, OcclusionQueryScope,
+};
+pub use self::outline::{Outline, OutlinePass};
+pub use self::permutations::{ProgramPermutations, DEFORM_FEATURES};
+pub use self::pixel_inspector::{
+    PixelAttachment, PixelInspector, PixelSample, PixelSource, PixelValue, MAX_PIXEL_SOURCES,
+};
+pub use self::planar_reflection::{PlanarPass, PlanarPassKind, PlanarReflection};
+pub use self::post_process::{
+    Bloom, FullscreenTriangle, PostEffect, PostProcess, ScenePass, ToneMapper, ToneMapping,
+};
+pub use self::profiler::{
+    EventCountProfiler, FrameProfiler, Profiler, ProfilerScope, StatsHud,
+};
+pub use self::program_binary::ProgramBinaryCache;
+pub use self::program_cache::ProgramCache;
+pub use self::raster_state::{CullMode, FrontFace, RasterState};
+pub use self::reflection_probe::{ProbeFace, ReflectionProbe};
+pub use self::render_pass::{begin_pass, ActivePass, BlendMode, DepthMode, RenderPass};
+pub use self::sampler::{max_anisotropy, Filter, Sampler, SamplerBuilder, SamplerPreset, Wrap};
+pub use self::shader::{ActiveUniform, Error, Program, Shader};
+pub use self::shadow_map::{ShadowMap, ShadowPass, ShadowUniforms, MAX_SHADOW_CASCADES};
+pub use self::ssao::Ssao;
+pub use self::status_line::StatusLine;
+pub use self::state_cache::StateCache;
+pub use self::stream_buffer::StreamBuffer;
+pub use self::texture::{
+    compressed_format_supported, ColorSpace, Texture, TextureLoadBuilder, TextureLoadOptions,
+};
+pub use self::texture_array::{Error as TextureArrayError, LayerSize, Texture2DArray};
+pub use self::texture_cache::TextureCache;
+pub use self::texture_cube::{CubeTextureSource, TextureCube};
+pub use self::uniform::Uniform;
+pub use self::uniform_buffer::{std140_align_offset, Std140, UniformBuffer};
+pub use self::upload_queue::UploadQueue;
+pub use self::viewport::{Viewport, ViewportResize};
 
 fn gl_error_to_str(error: u32) -> &'static str {
     match error {
@@ -0,0 +1,103 @@
+use gl;
+use crate::mesh::{self, MAX_MORPH_TARGETS};
+use crate::render_gl::buffer::Buffer;
+use crate::render_gl::{Material, StateCache};
+
+/// Morph target deltas of a mesh in a texture buffer, blended in the vertex shader.
+///
+/// Each target has two `RGBA32F` texels per vertex, the position delta followed by the
+/// normal delta, read by `ApplyMorphTargets` of `common/morph.glsl` with `gl_VertexID`.
+/// Materials need the `MORPH_TARGETS` define, weights are set per draw with `bind`, or with
+/// `pbr::MaterialProgram::bind_morph_targets` for the PBR program.
+pub struct MorphTargets {
+    gl: gl::Gl,
+    _buffer: Buffer,
+    obj: gl::types::GLuint,
+    vertex_count: usize,
+    target_count: usize,
+}
+
+impl Drop for MorphTargets {
+    fn drop(&mut self) {
+        StateCache::texture_deleted(self.obj);
+        unsafe { self.gl.DeleteTextures(1, &self.obj) };
+    }
+}
+
+impl MorphTargets {
+    /// Upload the targets of the mesh, `None` if it has none.
+    ///
+    /// Targets past `MAX_MORPH_TARGETS` are left out.
+    pub fn new(gl: &gl::Gl, mesh: &mesh::Mesh) -> Option<MorphTargets> {
+        if mesh.morph_targets.is_empty() {
+            return None;
+        }
+        let vertex_count = mesh.vertices.len();
+        let targets = &mesh.morph_targets[..mesh.morph_targets.len().min(MAX_MORPH_TARGETS)];
+
+        let mut texels = Vec::with_capacity(targets.len() * vertex_count * 2);
+        for target in targets {
+            for index in 0..vertex_count {
+                let position = target.positions.get(index);
+                let normal = target.normals.get(index);
+                texels.push(position.map_or([0.0; 4], |p| [p.x, p.y, p.z, 0.0]));
+                texels.push(normal.map_or([0.0; 4], |n| [n.x, n.y, n.z, 0.0]));
+            }
+        }
+
+        let buffer = Buffer::new_texture(gl);
+        buffer.bind();
+        buffer.static_draw_data(&texels);
+        buffer.unbind();
+
+        let mut obj: gl::types::GLuint = 0;
+        unsafe {
+            gl.GenTextures(1, &mut obj);
+            StateCache::bind_texture(gl, gl::TEXTURE_BUFFER, obj);
+            gl.TexBuffer(gl::TEXTURE_BUFFER, gl::RGBA32F, buffer.id());
+            StateCache::bind_texture(gl, gl::TEXTURE_BUFFER, 0);
+        }
+
+        if let Some(ref name) = mesh.name {
+            buffer.set_label(&format!("{} morph targets", name));
+        }
+
+        Some(MorphTargets {
+            gl: gl.clone(),
+            _buffer: buffer,
+            obj,
+            vertex_count,
+            target_count: targets.len(),
+        })
+    }
+
+    pub fn target_count(&self) -> usize {
+        self.target_count
+    }
+
+    pub fn vertex_count(&self) -> usize {
+        self.vertex_count
+    }
+
+    /// Weights for `MorphWeights`, missing weights are zero.
+    pub fn padded_weights(weights: &[f32]) -> [f32; MAX_MORPH_TARGETS] {
+        let mut padded = [0.0; MAX_MORPH_TARGETS];
+        for (padded, &weight) in padded.iter_mut().zip(weights) {
+            *padded = weight;
+        }
+        padded
+    }
+
+    pub fn bind_at(&self, index: u32) {
+        StateCache::bind_texture_at(&self.gl, index, gl::TEXTURE_BUFFER, self.obj);
+    }
+
+    /// Bind the deltas and set the weight of each target, missing weights are zero.
+    pub fn bind(&self, material: &Material, weights: &[f32]) {
+        let padded = MorphTargets::padded_weights(weights);
+        material.set_morph_targets("MorphDeltas", self);
+        material.set("MorphVertexCount", &(self.vertex_count as i32));
+        material.set("MorphTargetCount", &(self.target_count as i32));
+        material.set_array("MorphWeights", &padded);
+    }
+}
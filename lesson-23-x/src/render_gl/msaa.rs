@@ -0,0 +1,113 @@
+use gl;
+use crate::render_gl::{
+    Capabilities, ColorFormat, Framebuffer, FramebufferBinding, FramebufferError,
+};
+
+/// Sample counts cycled through by `MsaaTarget::cycle_samples`.
+const SAMPLE_STEPS: [i32; 4] = [0, 2, 4, 8];
+
+/// Multisampled color and depth target, resolved into the previously bound framebuffer.
+///
+/// Color format has to match the framebuffer it is resolved into.
+/// Render between `begin` and the drop of returned `MsaaPass`. With zero samples the pass
+/// renders directly to the current framebuffer.
+pub struct MsaaTarget {
+    gl: gl::Gl,
+    framebuffer: Option<Framebuffer>,
+    color_format: ColorFormat,
+    samples: i32,
+    max_samples: i32,
+}
+
+impl MsaaTarget {
+    pub fn new(gl: &gl::Gl, samples: i32, color_format: ColorFormat) -> MsaaTarget {
+        let max_samples = Capabilities::get(gl).max_samples;
+        let mut target = MsaaTarget {
+            gl: gl.clone(),
+            framebuffer: None,
+            color_format,
+            samples: 0,
+            max_samples,
+        };
+        target.set_samples(samples);
+        target
+    }
+
+    pub fn samples(&self) -> i32 {
+        self.samples
+    }
+
+    /// Change sample count, limited by `GL_MAX_SAMPLES`. The target is recreated on next `begin`.
+    pub fn set_samples(&mut self, samples: i32) {
+        let samples = samples.max(0).min(self.max_samples);
+        if samples != self.samples {
+            self.samples = samples;
+            self.framebuffer = None;
+        }
+    }
+
+    /// Switch to the next supported sample count: 0, 2, 4, 8, then back to 0.
+    pub fn cycle_samples(&mut self) {
+        let next = SAMPLE_STEPS
+            .iter()
+            .cloned()
+            .find(|&s| s > self.samples && s <= self.max_samples)
+            .unwrap_or(0);
+        self.set_samples(next);
+    }
+
+    /// Bind multisampled target of the given size and clear its color and depth.
+    pub fn begin(&mut self, width: i32, height: i32) -> Result<MsaaPass, FramebufferError> {
+        if self.samples == 0 {
+            self.framebuffer = None;
+            return Ok(MsaaPass {
+                framebuffer: None,
+                binding: None,
+            });
+        }
+
+        let recreate = match self.framebuffer {
+            Some(ref mut framebuffer) => {
+                framebuffer.resize(width, height)?;
+                false
+            }
+            None => true,
+        };
+        if recreate {
+            self.framebuffer = Some(
+                Framebuffer::builder(width, height)
+                    .with_samples(self.samples)
+                    .with_color(self.color_format)
+                    .with_depth_stencil()
+                    .build(&self.gl)?,
+            );
+        }
+
+        let framebuffer = self.framebuffer.as_ref().expect("msaa framebuffer");
+        let binding = framebuffer.bind();
+        unsafe {
+            self.gl.Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
+        }
+
+        Ok(MsaaPass {
+            framebuffer: Some(framebuffer),
+            binding: Some(binding),
+        })
+    }
+}
+
+/// Multisampled target binding, resolved when this value is dropped.
+pub struct MsaaPass<'a> {
+    framebuffer: Option<&'a Framebuffer>,
+    binding: Option<FramebufferBinding<'a>>,
+}
+
+impl<'a> Drop for MsaaPass<'a> {
+    fn drop(&mut self) {
+        // restore previous framebuffer before blitting into it
+        self.binding.take();
+        if let Some(framebuffer) = self.framebuffer {
+            framebuffer.resolve();
+        }
+    }
+}
@@ -0,0 +1,168 @@
+use failure;
+use gl;
+use nalgebra as na;
+use ncollide3d::bounding_volume::aabb::AABB;
+use crate::render;
+use crate::render_gl::buffer::VertexArray;
+use crate::render_gl::{FrameStats, Program, StateCache};
+use crate::resources::Resources;
+
+/// `GL_ANY_SAMPLES_PASSED` query, tells if anything drawn between `begin` and the end
+/// of the returned scope passed the depth test.
+pub struct OcclusionQuery {
+    gl: gl::Gl,
+    id: gl::types::GLuint,
+}
+
+impl OcclusionQuery {
+    pub fn new(gl: &gl::Gl) -> OcclusionQuery {
+        let mut id: gl::types::GLuint = 0;
+        unsafe {
+            gl.GenQueries(1, &mut id);
+        }
+        OcclusionQuery { gl: gl.clone(), id }
+    }
+
+    pub fn id(&self) -> gl::types::GLuint {
+        self.id
+    }
+
+    pub fn begin(&self) -> OcclusionQueryScope {
+        unsafe {
+            self.gl.BeginQuery(gl::ANY_SAMPLES_PASSED, self.id);
+        }
+        OcclusionQueryScope { gl: &self.gl }
+    }
+
+    /// Result of the last finished query, `None` while the GPU has not finished it.
+    pub fn try_result(&self) -> Option<bool> {
+        let mut available: gl::types::GLuint = 0;
+        unsafe {
+            self.gl
+                .GetQueryObjectuiv(self.id, gl::QUERY_RESULT_AVAILABLE, &mut available);
+        }
+        if available == 0 {
+            return None;
+        }
+
+        let mut result: gl::types::GLuint = 0;
+        unsafe {
+            self.gl
+                .GetQueryObjectuiv(self.id, gl::QUERY_RESULT, &mut result);
+        }
+        Some(result != 0)
+    }
+
+    /// Draw calls made while the returned scope is alive are discarded by the GPU
+    /// if the query found no visible samples. The GPU waits for the query result.
+    pub fn conditional_render(&self) -> ConditionalRenderScope {
+        unsafe {
+            self.gl.BeginConditionalRender(self.id, gl::QUERY_WAIT);
+        }
+        ConditionalRenderScope { gl: &self.gl }
+    }
+}
+
+impl Drop for OcclusionQuery {
+    fn drop(&mut self) {
+        unsafe {
+            self.gl.DeleteQueries(1, &self.id);
+        }
+    }
+}
+
+/// Active query, ended when dropped.
+pub struct OcclusionQueryScope<'a> {
+    gl: &'a gl::Gl,
+}
+
+impl<'a> Drop for OcclusionQueryScope<'a> {
+    fn drop(&mut self) {
+        unsafe {
+            self.gl.EndQuery(gl::ANY_SAMPLES_PASSED);
+        }
+    }
+}
+
+/// Active conditional rendering, ended when dropped.
+pub struct ConditionalRenderScope<'a> {
+    gl: &'a gl::Gl,
+}
+
+impl<'a> Drop for ConditionalRenderScope<'a> {
+    fn drop(&mut self) {
+        unsafe {
+            self.gl.EndConditionalRender();
+        }
+    }
+}
+
+/// Draws bounding boxes into the depth test only, as cheap stand-ins for occlusion queries.
+pub struct BoundsProxy {
+    gl: gl::Gl,
+    program: Program,
+    model_location: Option<i32>,
+    bounds_min_location: Option<i32>,
+    bounds_max_location: Option<i32>,
+    vao: VertexArray,
+}
+
+impl BoundsProxy {
+    pub fn new(gl: &gl::Gl, res: &Resources) -> Result<BoundsProxy, failure::Error> {
+        let program = Program::from_res_files(
+            gl,
+            res,
+            &[
+                "shaders/render_gl/bounds_proxy.vert",
+                "shaders/render_gl/shadow_depth.frag",
+            ],
+        )?;
+        program.bind_uniform_block("Camera", render::CAMERA_UNIFORMS_BINDING);
+
+        Ok(BoundsProxy {
+            gl: gl.clone(),
+            model_location: program.get_uniform_location("Model"),
+            bounds_min_location: program.get_uniform_location("BoundsMin"),
+            bounds_max_location: program.get_uniform_location("BoundsMax"),
+            program,
+            vao: VertexArray::new(gl),
+        })
+    }
+
+    /// Test the box against the depth buffer without writing color or depth.
+    pub fn render(&self, aabb: &AABB<f32>, model_matrix: &na::Matrix4<f32>) {
+        let gl = &self.gl;
+
+        self.program.set_used();
+        if let Some(loc) = self.model_location {
+            self.program.set_uniform_matrix_4fv(loc, model_matrix);
+        }
+        if let Some(loc) = self.bounds_min_location {
+            self.program.set_uniform_3f(loc, &aabb.mins().coords);
+        }
+        if let Some(loc) = self.bounds_max_location {
+            self.program.set_uniform_3f(loc, &aabb.maxs().coords);
+        }
+
+        unsafe {
+            gl.ColorMask(gl::FALSE, gl::FALSE, gl::FALSE, gl::FALSE);
+            StateCache::set_depth_mask(gl, false);
+        }
+        // back faces keep the box visible when its front is clipped by the near plane
+        StateCache::set_cull_face(gl, false);
+
+        // box is generated from vertex id in the shader
+        self.vao.bind();
+        unsafe {
+            gl.DrawArrays(gl::TRIANGLES, 0, 36);
+        }
+        FrameStats::record_draw(gl::TRIANGLES, 36, 1);
+        self.vao.unbind();
+
+        StateCache::set_cull_face(gl, true);
+        unsafe {
+            StateCache::set_depth_mask(gl, true);
+            gl.ColorMask(gl::TRUE, gl::TRUE, gl::TRUE, gl::TRUE);
+        }
+    }
+}
@@ -0,0 +1,170 @@
+use failure;
+use gl;
+use nalgebra as na;
+use crate::render_gl::{
+    Material, Program, ProgramPermutations, StateCache, Uniform, DEFORM_FEATURES,
+};
+use crate::resources::Resources;
+use std::rc::Rc;
+
+const STENCIL_MASK_VALUE: i32 = 1;
+
+/// Stencil outline around highlighted meshes, i.e. the current selection.
+///
+/// Meshes are drawn twice: first into the stencil buffer, then extruded along their normals
+/// in a flat color where the stencil is not set. Outlines stay visible behind other objects.
+/// The bound framebuffer needs a stencil attachment.
+pub struct Outline {
+    gl: gl::Gl,
+    program: Program,
+    view_projection_uniform: Uniform<na::Matrix4<f32>>,
+    model_uniform: Uniform<na::Matrix4<f32>>,
+    thickness_uniform: Uniform<f32>,
+    viewport_size_uniform: Uniform<na::Vector2<f32>>,
+    color_uniform: Uniform<na::Vector4<f32>>,
+    /// Program variants for meshes moved in the vertex shader.
+    deformed_programs: ProgramPermutations,
+    pub color: na::Vector4<f32>,
+    /// Outline width in pixels.
+    pub thickness: f32,
+}
+
+impl Outline {
+    pub fn new(gl: &gl::Gl, res: &Resources) -> Result<Outline, failure::Error> {
+        let program = Program::from_res(gl, res, "shaders/render_gl/outline")?;
+        let deformed_programs =
+            ProgramPermutations::deformed(gl, res, "shaders/render_gl/outline");
+        deformed_programs.variant(&DEFORM_FEATURES)?;
+
+        Ok(Outline {
+            gl: gl.clone(),
+            view_projection_uniform: program.uniform("ViewProjection"),
+            model_uniform: program.uniform("Model"),
+            thickness_uniform: program.uniform("Thickness"),
+            viewport_size_uniform: program.uniform("ViewportSize"),
+            color_uniform: program.uniform("Color"),
+            program,
+            deformed_programs,
+            color: na::Vector4::new(1.0, 0.6, 0.1, 1.0),
+            thickness: 3.0,
+        })
+    }
+
+    pub fn with_color(mut self, color: na::Vector4<f32>) -> Self {
+        self.color = color;
+        self
+    }
+
+    pub fn with_thickness(mut self, thickness: f32) -> Self {
+        self.thickness = thickness;
+        self
+    }
+
+    /// Call `draw` twice, once for the stencil mask and once for the outline.
+    ///
+    /// The callback draws highlighted meshes after `OutlinePass::prepare_model`. Their vertex
+    /// arrays need positions at location 0 and normals at location 3.
+    pub fn render<F: Fn(&OutlinePass)>(&self, view_projection: &na::Matrix4<f32>, draw: F) {
+        let gl = &self.gl;
+        let mut viewport = [0; 4];
+        unsafe {
+            gl.GetIntegerv(gl::VIEWPORT, viewport.as_mut_ptr());
+        }
+        let viewport_size = na::Vector2::new(viewport[2] as f32, viewport[3] as f32);
+
+        self.program.set_used();
+        self.view_projection_uniform
+            .set(&self.program, view_projection);
+        self.viewport_size_uniform.set(&self.program, &viewport_size);
+        self.color_uniform.set(&self.program, &self.color);
+
+        StateCache::set_depth_test(gl, false);
+        StateCache::set_depth_mask(gl, false);
+        unsafe {
+            gl.Enable(gl::STENCIL_TEST);
+            gl.Clear(gl::STENCIL_BUFFER_BIT);
+
+            gl.ColorMask(gl::FALSE, gl::FALSE, gl::FALSE, gl::FALSE);
+            gl.StencilFunc(gl::ALWAYS, STENCIL_MASK_VALUE, 0xff);
+            gl.StencilOp(gl::KEEP, gl::KEEP, gl::REPLACE);
+            gl.StencilMask(0xff);
+        }
+        self.thickness_uniform.set(&self.program, &0.0);
+        draw(&OutlinePass {
+            outline: self,
+            view_projection,
+            viewport_size,
+            thickness: 0.0,
+        });
+
+        unsafe {
+            gl.ColorMask(gl::TRUE, gl::TRUE, gl::TRUE, gl::TRUE);
+            gl.StencilFunc(gl::NOTEQUAL, STENCIL_MASK_VALUE, 0xff);
+            gl.StencilMask(0x00);
+        }
+        self.thickness_uniform
+            .set(&self.program, &self.thickness);
+        draw(&OutlinePass {
+            outline: self,
+            view_projection,
+            viewport_size,
+            thickness: self.thickness,
+        });
+
+        unsafe {
+            gl.StencilMask(0xff);
+            gl.Disable(gl::STENCIL_TEST);
+        }
+        StateCache::set_depth_mask(gl, true);
+        StateCache::set_depth_test(gl, true);
+    }
+}
+
+/// One of the two draws of `Outline::render`.
+pub struct OutlinePass<'a> {
+    outline: &'a Outline,
+    /// Uniforms of the draw, set again on deformed variants.
+    view_projection: &'a na::Matrix4<f32>,
+    viewport_size: na::Vector2<f32>,
+    thickness: f32,
+}
+
+impl<'a> OutlinePass<'a> {
+    /// Use outline program for the mesh with the model matrix.
+    pub fn prepare_model(&self, model_matrix: &na::Matrix4<f32>) {
+        let outline = self.outline;
+        outline.program.set_used();
+        outline.model_uniform.set(&outline.program, model_matrix);
+    }
+
+    /// Use the program variant for a mesh moved in the vertex shader, i.e. `SKINNED` with
+    /// the bone matrices of its pose bound. Morph targets are bound to the returned variant.
+    /// Without features, or if the variant failed to link, the mesh is drawn as modelled and
+    /// `None` is returned.
+    pub fn prepare_deformed_model(
+        &self,
+        model_matrix: &na::Matrix4<f32>,
+        features: &[&str],
+    ) -> Option<Rc<Material>> {
+        let variant = if features.is_empty() {
+            None
+        } else {
+            let programs = &self.outline.deformed_programs;
+            programs.variant(features).ok().and_then(|variant| variant)
+        };
+        let variant = match variant {
+            Some(variant) => variant,
+            None => {
+                self.prepare_model(model_matrix);
+                return None;
+            }
+        };
+        variant.set_used();
+        variant.set("ViewProjection", self.view_projection);
+        variant.set("ViewportSize", &self.viewport_size);
+        variant.set("Thickness", &self.thickness);
+        variant.set("Color", &self.outline.color);
+        variant.set("Model", model_matrix);
+        Some(variant)
+    }
+}
@@ -0,0 +1,394 @@
+use failure;
+use gl;
+use image;
+use nalgebra as na;
+use crate::lights;
+use crate::render;
+use crate::render_gl::buffer::VertexArray;
+use crate::render_gl::{
+    ColorFormat, ColorSpace, FrameStats, Framebuffer, MorphTargets, Program, ProgramCache,
+    RasterState, ShadowMap, ShadowUniforms, StateCache, Texture, TextureCube,
+};
+use crate::resources::Resources;
+use std::rc::Rc;
+
+const IRRADIANCE_SIZE: i32 = 32;
+const SPECULAR_SIZE: i32 = 128;
+const SPECULAR_LEVELS: i32 = 5;
+const BRDF_LUT_SIZE: i32 = 512;
+
+/// Texture unit of the albedo map, i.e. to bind a sampler for it.
+pub const ALBEDO_UNIT: u32 = 0;
+const NORMAL_UNIT: u32 = 1;
+const METALLIC_UNIT: u32 = 2;
+const ROUGHNESS_UNIT: u32 = 3;
+const OCCLUSION_UNIT: u32 = 4;
+const SHADOW_MAP_UNIT: u32 = 5;
+const IRRADIANCE_UNIT: u32 = 6;
+const SPECULAR_UNIT: u32 = 7;
+const BRDF_LUT_UNIT: u32 = 8;
+const MORPH_DELTAS_UNIT: u32 = 9;
+
+/// Texture slot of a material.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MaterialMap {
+    Albedo,
+    Normal,
+    Metallic,
+    Roughness,
+    Occlusion,
+    Height,
+    Lightmap,
+}
+
+impl MaterialMap {
+    /// Albedo holds colors, the other maps hold data that must not be gamma decoded.
+    pub fn color_space(self) -> ColorSpace {
+        match self {
+            MaterialMap::Albedo => ColorSpace::Srgb,
+            _ => ColorSpace::Linear,
+        }
+    }
+}
+
+/// Metallic-roughness material. Missing maps default to white, so only factors are used.
+///
+/// Maps are shared, i.e. from `TextureCache`.
+pub struct Material {
+    pub albedo: Option<Rc<Texture>>,
+    pub normal: Option<Rc<Texture>>,
+    /// Metalness in blue channel.
+    pub metallic: Option<Rc<Texture>>,
+    /// Roughness in green channel.
+    pub roughness: Option<Rc<Texture>>,
+    /// Ambient occlusion in red channel.
+    pub occlusion: Option<Rc<Texture>>,
+    pub albedo_factor: na::Vector3<f32>,
+    pub metallic_factor: f32,
+    pub roughness_factor: f32,
+    /// Applied by `MaterialProgram::bind`.
+    pub raster_state: RasterState,
+}
+
+impl Material {
+    pub fn new() -> Material {
+        Material {
+            albedo: None,
+            normal: None,
+            metallic: None,
+            roughness: None,
+            occlusion: None,
+            albedo_factor: na::Vector3::new(1.0, 1.0, 1.0),
+            metallic_factor: 0.0,
+            roughness_factor: 1.0,
+            raster_state: RasterState::default(),
+        }
+    }
+}
+
+impl Default for Material {
+    fn default() -> Material {
+        Material::new()
+    }
+}
+
+/// Image based lighting prefiltered from an environment cube map.
+pub struct Environment {
+    irradiance: TextureCube,
+    specular: TextureCube,
+    brdf_lut: Framebuffer,
+    irradiance_program: Program,
+    prefilter_program: Program,
+}
+
+impl Environment {
+    pub fn new(
+        gl: &gl::Gl,
+        res: &Resources,
+        environment: &TextureCube,
+    ) -> Result<Environment, failure::Error> {
+        let irradiance_program = Program::from_res_files(
+            gl,
+            res,
+            &[
+                "shaders/render_gl/cube_face.vert",
+                "shaders/render_gl/pbr_irradiance.frag",
+            ],
+        )?;
+        let irradiance = TextureCube::new_empty(gl, IRRADIANCE_SIZE, gl::RGB16F, 1);
+
+        let prefilter_program = Program::from_res_files(
+            gl,
+            res,
+            &[
+                "shaders/render_gl/cube_face.vert",
+                "shaders/render_gl/pbr_prefilter.frag",
+            ],
+        )?;
+        let specular = TextureCube::new_empty(gl, SPECULAR_SIZE, gl::RGB16F, SPECULAR_LEVELS);
+
+        let brdf_program = Program::from_res_files(
+            gl,
+            res,
+            &[
+                "shaders/render_gl/fullscreen.vert",
+                "shaders/render_gl/pbr_brdf.frag",
+            ],
+        )?;
+        let brdf_lut = Framebuffer::builder(BRDF_LUT_SIZE, BRDF_LUT_SIZE)
+            .with_color(ColorFormat::Rgba16f)
+            .build(gl)?;
+        {
+            let _binding = brdf_lut.bind();
+            let vao = VertexArray::new(gl);
+
+            brdf_program.set_used();
+            vao.bind();
+            unsafe {
+                StateCache::set_depth_test(gl, false);
+                gl.DrawArrays(gl::TRIANGLES, 0, 3);
+                FrameStats::record_draw(gl::TRIANGLES, 3, 1);
+                StateCache::set_depth_test(gl, true);
+            }
+            vao.unbind();
+        }
+
+        let result = Environment {
+            irradiance,
+            specular,
+            brdf_lut,
+            irradiance_program,
+            prefilter_program,
+        };
+        result.update(environment);
+        Ok(result)
+    }
+
+    /// Prefilter lighting again from another cube map, i.e. one rendered by a reflection probe.
+    pub fn update(&self, environment: &TextureCube) {
+        let irradiance_program = &self.irradiance_program;
+        irradiance_program.set_used();
+        if let Some(loc) = irradiance_program.get_uniform_location("Environment") {
+            environment.bind_at(0);
+            irradiance_program.set_uniform_1i(loc, 0);
+        }
+        self.irradiance.render_faces(irradiance_program, 0);
+
+        let prefilter_program = &self.prefilter_program;
+        prefilter_program.set_used();
+        if let Some(loc) = prefilter_program.get_uniform_location("Environment") {
+            environment.bind_at(0);
+            prefilter_program.set_uniform_1i(loc, 0);
+        }
+        if let Some(loc) = prefilter_program.get_uniform_location("EnvironmentSize") {
+            prefilter_program.set_uniform_1f(loc, environment.face_size() as f32);
+        }
+        let prefilter_roughness_location = prefilter_program.get_uniform_location("Roughness");
+
+        // roughness increases with each mip level
+        for level in 0..SPECULAR_LEVELS {
+            if let Some(loc) = prefilter_roughness_location {
+                prefilter_program.set_used();
+                prefilter_program
+                    .set_uniform_1f(loc, level as f32 / (SPECULAR_LEVELS - 1) as f32);
+            }
+            self.specular.render_faces(prefilter_program, level);
+        }
+    }
+
+    fn bind(&self, program: &Program, locations: &EnvironmentLocations) {
+        if let Some(loc) = locations.irradiance {
+            self.irradiance.bind_at(IRRADIANCE_UNIT);
+            program.set_uniform_1i(loc, IRRADIANCE_UNIT as i32);
+        }
+        if let Some(loc) = locations.specular {
+            self.specular.bind_at(SPECULAR_UNIT);
+            program.set_uniform_1i(loc, SPECULAR_UNIT as i32);
+        }
+        if let Some(loc) = locations.specular_max_lod {
+            program.set_uniform_1f(loc, (SPECULAR_LEVELS - 1) as f32);
+        }
+        if let (Some(loc), Some(texture)) = (locations.brdf_lut, self.brdf_lut.color_texture(0)) {
+            texture.bind_at(BRDF_LUT_UNIT);
+            program.set_uniform_1i(loc, BRDF_LUT_UNIT as i32);
+        }
+    }
+}
+
+struct EnvironmentLocations {
+    irradiance: Option<i32>,
+    specular: Option<i32>,
+    specular_max_lod: Option<i32>,
+    brdf_lut: Option<i32>,
+}
+
+/// Uniforms of `common/morph.glsl`, set only in `MORPH_TARGETS` programs.
+struct MorphLocations {
+    vertex_count: Option<i32>,
+    target_count: Option<i32>,
+    weights: Option<i32>,
+}
+
+/// Reference `shaders/pbr` program, lit by the `Lights` uniform block and environment.
+///
+/// Shadow map is applied to directional lights that cast shadows.
+pub struct MaterialProgram {
+    program: Rc<Program>,
+    albedo_location: Option<i32>,
+    normal_location: Option<i32>,
+    metallic_location: Option<i32>,
+    roughness_location: Option<i32>,
+    occlusion_location: Option<i32>,
+    albedo_factor_location: Option<i32>,
+    metallic_factor_location: Option<i32>,
+    roughness_factor_location: Option<i32>,
+    shadow_uniforms: ShadowUniforms,
+    model_location: Option<i32>,
+    environment_locations: EnvironmentLocations,
+    morph_locations: MorphLocations,
+    white: Texture,
+    flat_normal: Texture,
+}
+
+impl MaterialProgram {
+    pub fn new(gl: &gl::Gl, res: &Resources) -> Result<MaterialProgram, failure::Error> {
+        MaterialProgram::deformed(gl, res, &[])
+    }
+
+    /// Program for meshes moved in the vertex shader, with some of `DEFORM_FEATURES`.
+    /// `SKINNED` meshes are moved by the `Bones` uniform block, `MORPH_TARGETS` meshes by
+    /// the targets bound with `bind_morph_targets`.
+    pub fn deformed(
+        gl: &gl::Gl,
+        res: &Resources,
+        features: &[&str],
+    ) -> Result<MaterialProgram, failure::Error> {
+        let clustered = lights::LightClusters::supported(gl);
+        let skinned = features.contains(&"SKINNED");
+        let mut defines = Vec::new();
+        if clustered {
+            defines.push(("CLUSTERED", "1"));
+        }
+        defines.extend(features.iter().map(|&feature| (feature, "1")));
+        let program = if defines.is_empty() {
+            ProgramCache::from_res(gl, res, "shaders/pbr")?
+        } else {
+            ProgramCache::from_res_with_defines(gl, res, "shaders/pbr", &defines)?
+        };
+        program.bind_uniform_block("Camera", render::CAMERA_UNIFORMS_BINDING);
+        program.bind_uniform_block("Lights", render::LIGHT_UNIFORMS_BINDING);
+        program.bind_uniform_block("Fog", render::FOG_UNIFORMS_BINDING);
+        if skinned {
+            program.bind_uniform_block("Bones", render::BONE_UNIFORMS_BINDING);
+        }
+        if clustered {
+            program.bind_uniform_block("Clusters", lights::CLUSTER_UNIFORMS_BINDING);
+            program.bind_shader_storage_block("ClusterLights", lights::CLUSTER_LIGHTS_BINDING);
+            program.bind_shader_storage_block("ClusterGrid", lights::CLUSTER_GRID_BINDING);
+            program.bind_shader_storage_block("ClusterIndices", lights::CLUSTER_INDICES_BINDING);
+        }
+
+        // deltas are the only buffer texture, they must not share the unit of a 2D map
+        if let Some(loc) = program.get_uniform_location("MorphDeltas") {
+            program.set_used();
+            program.set_uniform_1i(loc, MORPH_DELTAS_UNIT as i32);
+        }
+
+        let single_pixel = |r, g, b| {
+            let img = image::ImageBuffer::from_pixel(1, 1, image::Rgb([r, g, b]));
+            Texture::from_rgb_image(gl, &img, ColorSpace::Linear, false)
+        };
+
+        Ok(MaterialProgram {
+            albedo_location: program.get_uniform_location("AlbedoMap"),
+            normal_location: program.get_uniform_location("NormalMap"),
+            metallic_location: program.get_uniform_location("MetallicMap"),
+            roughness_location: program.get_uniform_location("RoughnessMap"),
+            occlusion_location: program.get_uniform_location("OcclusionMap"),
+            albedo_factor_location: program.get_uniform_location("AlbedoFactor"),
+            metallic_factor_location: program.get_uniform_location("MetallicFactor"),
+            roughness_factor_location: program.get_uniform_location("RoughnessFactor"),
+            shadow_uniforms: ShadowUniforms::new(&program),
+            model_location: program.get_uniform_location("Model"),
+            environment_locations: EnvironmentLocations {
+                irradiance: program.get_uniform_location("IrradianceMap"),
+                specular: program.get_uniform_location("SpecularMap"),
+                specular_max_lod: program.get_uniform_location("SpecularMaxLod"),
+                brdf_lut: program.get_uniform_location("BrdfLut"),
+            },
+            morph_locations: MorphLocations {
+                vertex_count: program.get_uniform_location("MorphVertexCount"),
+                target_count: program.get_uniform_location("MorphTargetCount"),
+                weights: program.get_uniform_location("MorphWeights"),
+            },
+            program,
+            white: single_pixel(255, 255, 255),
+            flat_normal: single_pixel(128, 128, 255),
+        })
+    }
+
+    pub fn program(&self) -> &Program {
+        &self.program
+    }
+
+    /// Use program with the material, then draw the mesh.
+    pub fn bind(
+        &self,
+        material: &Material,
+        model_matrix: &na::Matrix4<f32>,
+        environment: &Environment,
+        shadow_map: &ShadowMap,
+    ) {
+        let program = &self.program;
+        program.set_used();
+        material.raster_state.apply(program.gl());
+
+        let maps = [
+            (self.albedo_location, &material.albedo, &self.white, ALBEDO_UNIT),
+            (self.normal_location, &material.normal, &self.flat_normal, NORMAL_UNIT),
+            (self.metallic_location, &material.metallic, &self.white, METALLIC_UNIT),
+            (self.roughness_location, &material.roughness, &self.white, ROUGHNESS_UNIT),
+            (self.occlusion_location, &material.occlusion, &self.white, OCCLUSION_UNIT),
+        ];
+        for &(location, texture, fallback, unit) in maps.iter() {
+            if let Some(loc) = location {
+                texture.as_ref().map_or(fallback, |texture| &**texture).bind_at(unit);
+                program.set_uniform_1i(loc, unit as i32);
+            }
+        }
+
+        if let Some(loc) = self.albedo_factor_location {
+            program.set_uniform_3f(loc, &material.albedo_factor);
+        }
+        if let Some(loc) = self.metallic_factor_location {
+            program.set_uniform_1f(loc, material.metallic_factor);
+        }
+        if let Some(loc) = self.roughness_factor_location {
+            program.set_uniform_1f(loc, material.roughness_factor);
+        }
+
+        shadow_map.bind(program, &self.shadow_uniforms, SHADOW_MAP_UNIT);
+        if let Some(loc) = self.model_location {
+            program.set_uniform_matrix_4fv(loc, model_matrix);
+        }
+
+        environment.bind(program, &self.environment_locations);
+    }
+
+    /// Bind the targets and set the weight of each, missing weights are zero. Call after
+    /// `bind`, programs without `MORPH_TARGETS` draw the mesh as modelled.
+    pub fn bind_morph_targets(&self, targets: &MorphTargets, weights: &[f32]) {
+        let program = &self.program;
+        let locations = &self.morph_locations;
+        targets.bind_at(MORPH_DELTAS_UNIT);
+        if let Some(loc) = locations.vertex_count {
+            program.set_uniform_1i(loc, targets.vertex_count() as i32);
+        }
+        if let Some(loc) = locations.target_count {
+            program.set_uniform_1i(loc, targets.target_count() as i32);
+        }
+        if let Some(loc) = locations.weights {
+            program.set_uniform_1fv(loc, &MorphTargets::padded_weights(weights));
+        }
+    }
+}
@@ -0,0 +1,177 @@
+use gl;
+use crate::render;
+use crate::render_gl::{Error, Material, Program, ProgramCache};
+use crate::resources::{Resources, Watch};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+/// Features of meshes moved in the vertex shader, for programs that include
+/// `common/skinning.glsl` and `common/morph.glsl`. `SKINNED` reads the bone matrices of the
+/// `Bones` block, `MORPH_TARGETS` the deltas bound with `MorphTargets::bind`.
+pub const DEFORM_FEATURES: [&str; 2] = ["SKINNED", "MORPH_TARGETS"];
+
+/// Shader resources every variant is linked from.
+enum Source {
+    /// `name.vert`, `name.frag` and optional stages, as for `Program::from_res`.
+    Name(String),
+    Files(Vec<String>),
+}
+
+/// Variants of one program for the optional features it supports, each feature compiled
+/// in with `#define NAME 1`.
+///
+/// A variant is linked the first time it is requested, i.e. a material without a normal
+/// map asks for a variant without `NORMAL_MAP`. Programs are shared through `ProgramCache`,
+/// each variant has its own `Material`.
+pub struct ProgramPermutations {
+    gl: gl::Gl,
+    res: Resources,
+    source: Source,
+    features: Vec<&'static str>,
+    /// Makes the material of a linked variant, i.e. to bind uniform blocks.
+    setup: Box<dyn Fn(Rc<Program>) -> Material>,
+    /// Variants by feature bits, `None` if linking failed.
+    variants: RefCell<HashMap<u32, Option<Rc<Material>>>>,
+}
+
+impl ProgramPermutations {
+    /// Variants of `Program::from_res`.
+    pub fn from_res(
+        gl: &gl::Gl,
+        res: &Resources,
+        name: &str,
+        features: &[&'static str],
+    ) -> ProgramPermutations {
+        ProgramPermutations::new(gl, res, Source::Name(name.into()), features)
+    }
+
+    /// Variants of `Program::from_res_files`.
+    pub fn from_res_files(
+        gl: &gl::Gl,
+        res: &Resources,
+        resource_names: &[&str],
+        features: &[&'static str],
+    ) -> ProgramPermutations {
+        let files = resource_names.iter().map(|name| name.to_string()).collect();
+        ProgramPermutations::new(gl, res, Source::Files(files), features)
+    }
+
+    /// Variants of `Program::from_res` for `DEFORM_FEATURES`, the `Bones` block bound at
+    /// `render::BONE_UNIFORMS_BINDING`.
+    pub fn deformed(gl: &gl::Gl, res: &Resources, name: &str) -> ProgramPermutations {
+        ProgramPermutations::from_res(gl, res, name, &DEFORM_FEATURES).with_setup(|program| {
+            program.bind_uniform_block("Bones", render::BONE_UNIFORMS_BINDING);
+            Material::shared(program)
+        })
+    }
+
+    fn new(
+        gl: &gl::Gl,
+        res: &Resources,
+        source: Source,
+        features: &[&'static str],
+    ) -> ProgramPermutations {
+        assert!(features.len() <= 32, "at most 32 program features are supported");
+        ProgramPermutations {
+            gl: gl.clone(),
+            res: res.clone(),
+            source,
+            features: features.to_vec(),
+            setup: Box::new(Material::shared),
+            variants: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// Make materials of linked variants with `setup`.
+    pub fn with_setup<F>(mut self, setup: F) -> Self
+    where
+        F: Fn(Rc<Program>) -> Material + 'static,
+    {
+        self.setup = Box::new(setup);
+        self
+    }
+
+    /// Program name used in errors.
+    pub fn name(&self) -> String {
+        match self.source {
+            Source::Name(ref name) => name.clone(),
+            Source::Files(ref files) => files.join(", "),
+        }
+    }
+
+    /// Shader resources of the variants, without included files.
+    pub fn files(&self) -> Vec<String> {
+        match self.source {
+            Source::Name(ref name) => vec![format!("{}.vert", name), format!("{}.frag", name)],
+            Source::Files(ref files) => files.clone(),
+        }
+    }
+
+    /// Watch of the shader files, i.e. to link failed variants again once they change.
+    pub fn watch(&self) -> Watch {
+        let mut files = self.files().into_iter();
+        let first = files.next().expect("expected program with shader files");
+        files.fold(self.res.watch(&first), |watch, file| watch.with(&file))
+    }
+
+    /// Variant with the listed features, linked on first request.
+    ///
+    /// Fails only the first time a variant does not link, later requests for it return
+    /// `None` without trying again. Panics on features not listed on creation.
+    pub fn variant(&self, features: &[&str]) -> Result<Option<Rc<Material>>, Error> {
+        let bits = self.bits(features);
+        if let Some(variant) = self.variants.borrow().get(&bits) {
+            return Ok(variant.clone());
+        }
+
+        let result = self.link(bits).map(|program| Rc::new((self.setup)(program)));
+        let variant = result.as_ref().ok().cloned();
+        self.variants.borrow_mut().insert(bits, variant);
+        result.map(Some)
+    }
+
+    /// Number of variants linked so far.
+    pub fn len(&self) -> usize {
+        self.variants
+            .borrow()
+            .values()
+            .filter(|variant| variant.is_some())
+            .count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn bits(&self, features: &[&str]) -> u32 {
+        features.iter().fold(0, |bits, feature| {
+            let index = self
+                .features
+                .iter()
+                .position(|known| known == feature)
+                .unwrap_or_else(|| panic!("program {} has no feature {}", self.name(), feature));
+            bits | 1 << index
+        })
+    }
+
+    fn link(&self, bits: u32) -> Result<Rc<Program>, Error> {
+        // defines in creation order, so that the cache key does not depend on request order
+        let defines = self
+            .features
+            .iter()
+            .enumerate()
+            .filter(|&(index, _)| bits & 1 << index != 0)
+            .map(|(_, &feature)| (feature, "1"))
+            .collect::<Vec<_>>();
+        match self.source {
+            Source::Name(ref name) => {
+                ProgramCache::from_res_with_defines(&self.gl, &self.res, name, &defines)
+            }
+            Source::Files(ref files) => {
+                let files = files.iter().map(|file| &file[..]).collect::<Vec<_>>();
+                ProgramCache::from_res_files_with_defines(&self.gl, &self.res, &files, &defines)
+            }
+        }
+    }
+}
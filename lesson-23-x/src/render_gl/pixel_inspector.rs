@@ -0,0 +1,224 @@
+use gl;
+use crate::render_gl::buffer::Buffer;
+use crate::render_gl::Framebuffer;
+
+/// Pixel reads are mapped this many frames later, so that inspecting never stalls the GPU.
+const FRAMES_IN_FLIGHT: usize = 3;
+
+/// Most values read per request.
+pub const MAX_PIXEL_SOURCES: usize = 8;
+
+/// Attachment of a framebuffer read by the `PixelInspector`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PixelAttachment {
+    /// Color attachment by index, read as RGBA floats.
+    Color(u32),
+    Depth,
+}
+
+/// Framebuffer attachment read under the cursor, with the label it is shown with.
+pub struct PixelSource<'a> {
+    pub label: &'static str,
+    pub framebuffer: &'a Framebuffer,
+    pub attachment: PixelAttachment,
+}
+
+impl<'a> PixelSource<'a> {
+    pub fn color(label: &'static str, framebuffer: &'a Framebuffer, index: u32) -> Self {
+        PixelSource {
+            label,
+            framebuffer,
+            attachment: PixelAttachment::Color(index),
+        }
+    }
+
+    pub fn depth(label: &'static str, framebuffer: &'a Framebuffer) -> Self {
+        PixelSource {
+            label,
+            framebuffer,
+            attachment: PixelAttachment::Depth,
+        }
+    }
+}
+
+/// Value of one source, depth is the first component.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct PixelValue {
+    pub label: &'static str,
+    pub attachment: PixelAttachment,
+    pub value: [f32; 4],
+}
+
+/// Values read at a window position, with the origin at the top left corner.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PixelSample {
+    pub x: i32,
+    pub y: i32,
+    pub values: Vec<PixelValue>,
+}
+
+impl PixelSample {
+    /// One line for the position and one for each value, i.e. for `DebugText`.
+    pub fn lines(&self) -> Vec<String> {
+        let mut lines = vec![format!("pixel {}, {}", self.x, self.y)];
+        for value in &self.values {
+            let [r, g, b, a] = value.value;
+            lines.push(match value.attachment {
+                PixelAttachment::Color(_) => {
+                    format!("{}: {:.4} {:.4} {:.4} {:.4}", value.label, r, g, b, a)
+                }
+                PixelAttachment::Depth => format!("{}: {:.6}", value.label, r),
+            });
+        }
+        lines
+    }
+}
+
+/// Request that has been read into a pixel pack buffer, but not mapped yet.
+struct Pending {
+    x: i32,
+    y: i32,
+    sources: Vec<(&'static str, PixelAttachment)>,
+}
+
+/// Scene and G-buffer values under the cursor, for debugging shader output numerically.
+///
+/// `request` reads single-sampled attachments after the scene is drawn, values become
+/// available from `poll` a few frames later. Multisampled framebuffers can not be read,
+/// so they are left out.
+pub struct PixelInspector {
+    gl: gl::Gl,
+    pub enabled: bool,
+    slots: Vec<(Buffer, Option<Pending>)>,
+    current: usize,
+    last_sample: Option<PixelSample>,
+}
+
+impl PixelInspector {
+    pub fn new(gl: &gl::Gl) -> PixelInspector {
+        PixelInspector {
+            gl: gl.clone(),
+            enabled: false,
+            slots: (0..FRAMES_IN_FLIGHT)
+                .map(|_| {
+                    let pbo = Buffer::new_pixel_pack(gl);
+                    pbo.bind();
+                    pbo.stream_draw_data_null::<[f32; 4]>(MAX_PIXEL_SOURCES);
+                    pbo.unbind();
+                    (pbo, None)
+                }).collect(),
+            current: 0,
+            last_sample: None,
+        }
+    }
+
+    /// Start reading the sources at window position, with the origin at the top left corner.
+    ///
+    /// Does nothing while disabled. Sources the position is outside of, multisampled ones,
+    /// and those past `MAX_PIXEL_SOURCES` are left out.
+    pub fn request(&mut self, x: i32, y: i32, sources: &[PixelSource]) {
+        if !self.enabled {
+            return;
+        }
+        let sources = sources
+            .iter()
+            .filter(|source| {
+                let framebuffer = source.framebuffer;
+                framebuffer.samples() == 0
+                    && x >= 0
+                    && y >= 0
+                    && x < framebuffer.width()
+                    && y < framebuffer.height()
+            }).take(MAX_PIXEL_SOURCES)
+            .collect::<Vec<_>>();
+        if sources.is_empty() {
+            return;
+        }
+
+        self.map_slot(self.current);
+
+        let (ref pbo, ref mut pending) = self.slots[self.current];
+        let mut previous_fbo: gl::types::GLint = 0;
+        pbo.bind();
+        unsafe {
+            self.gl
+                .GetIntegerv(gl::READ_FRAMEBUFFER_BINDING, &mut previous_fbo);
+            self.gl.PixelStorei(gl::PACK_ALIGNMENT, 4);
+            for (index, source) in sources.iter().enumerate() {
+                let framebuffer = source.framebuffer;
+                self.gl
+                    .BindFramebuffer(gl::READ_FRAMEBUFFER, framebuffer.id());
+                let format = match source.attachment {
+                    PixelAttachment::Color(attachment) => {
+                        self.gl.ReadBuffer(gl::COLOR_ATTACHMENT0 + attachment);
+                        gl::RGBA
+                    }
+                    PixelAttachment::Depth => gl::DEPTH_COMPONENT,
+                };
+                // offset into the bound pack buffer
+                let offset = index * ::std::mem::size_of::<[f32; 4]>();
+                self.gl.ReadPixels(
+                    x,
+                    framebuffer.height() - 1 - y,
+                    1,
+                    1,
+                    format,
+                    gl::FLOAT,
+                    offset as *mut gl::types::GLvoid,
+                );
+            }
+            self.gl
+                .BindFramebuffer(gl::READ_FRAMEBUFFER, previous_fbo as gl::types::GLuint);
+        }
+        pbo.unbind();
+        *pending = Some(Pending {
+            x,
+            y,
+            sources: sources
+                .iter()
+                .map(|source| (source.label, source.attachment))
+                .collect(),
+        });
+
+        self.current = (self.current + 1) % self.slots.len();
+    }
+
+    /// Map the oldest request that had time to finish and return the latest known values.
+    ///
+    /// `None` until the first request completes.
+    pub fn poll(&mut self) -> Option<&PixelSample> {
+        self.map_slot(self.current);
+        self.last_sample.as_ref()
+    }
+
+    fn map_slot(&mut self, index: usize) {
+        let (ref pbo, ref mut pending) = self.slots[index];
+        let pending = match pending.take() {
+            Some(pending) => pending,
+            None => return,
+        };
+
+        pbo.bind();
+        if let Some(mapped) =
+            unsafe { pbo.map_buffer_range_read::<[f32; 4]>(0, pending.sources.len()) }
+        {
+            self.last_sample = Some(PixelSample {
+                x: pending.x,
+                y: pending.y,
+                values: pending
+                    .sources
+                    .iter()
+                    .zip(mapped.iter())
+                    .map(|(&(label, attachment), value)| PixelValue {
+                        label,
+                        attachment,
+                        value: match attachment {
+                            PixelAttachment::Color(_) => *value,
+                            PixelAttachment::Depth => [value[0], 0.0, 0.0, 0.0],
+                        },
+                    }).collect(),
+            });
+        }
+        pbo.unbind();
+    }
+}
@@ -0,0 +1,144 @@
+use gl;
+use nalgebra as na;
+use crate::render_gl::{
+    clip_plane, ClipPlaneScope, ColorFormat, Framebuffer, FramebufferError, StateCache, Texture,
+};
+
+/// Extra distance kept on the clipped side, so that distorted samples near the plane do not
+/// reach the cut edge.
+const CLIP_OFFSET: f32 = 0.05;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PlanarPassKind {
+    /// Scene above the plane, seen from the camera mirrored below it.
+    Reflection,
+    /// Scene below the plane, seen from the camera.
+    Refraction,
+}
+
+/// Camera of one pass, passed to the draw callback.
+pub struct PlanarPass {
+    pub kind: PlanarPassKind,
+    pub view_projection: na::Matrix4<f32>,
+    pub camera_pos: na::Point3<f32>,
+    /// Plane for `render::CameraUniforms::clip_plane`.
+    pub clip_plane: na::Vector4<f32>,
+}
+
+/// Reflection and refraction of the scene at a flat surface such as water.
+///
+/// Both targets are sampled at the screen position of the surface. The reflection is drawn
+/// with the mirrored view, so it needs no flipping.
+pub struct PlanarReflection {
+    point: na::Point3<f32>,
+    normal: na::Vector3<f32>,
+    reflection: Framebuffer,
+    refraction: Framebuffer,
+}
+
+impl PlanarReflection {
+    /// Targets are usually smaller than the window, reflections get distorted anyway.
+    pub fn new(
+        gl: &gl::Gl,
+        width: i32,
+        height: i32,
+        point: na::Point3<f32>,
+        normal: na::Vector3<f32>,
+    ) -> Result<PlanarReflection, FramebufferError> {
+        Ok(PlanarReflection {
+            point,
+            normal: normal.normalize(),
+            reflection: PlanarReflection::target(gl, width, height)?,
+            refraction: PlanarReflection::target(gl, width, height)?,
+        })
+    }
+
+    pub fn set_plane(&mut self, point: na::Point3<f32>, normal: na::Vector3<f32>) {
+        self.point = point;
+        self.normal = normal.normalize();
+    }
+
+    pub fn resize(&mut self, width: i32, height: i32) -> Result<(), FramebufferError> {
+        self.reflection.resize(width, height)?;
+        self.refraction.resize(width, height)
+    }
+
+    pub fn reflection_texture(&self) -> &Texture {
+        self.reflection
+            .color_texture(0)
+            .expect("reflection target has a color attachment")
+    }
+
+    pub fn refraction_texture(&self) -> &Texture {
+        self.refraction
+            .color_texture(0)
+            .expect("refraction target has a color attachment")
+    }
+
+    /// Mirror transform across the plane.
+    pub fn mirror_matrix(&self) -> na::Matrix4<f32> {
+        let n = self.normal;
+        let d = -n.dot(&self.point.coords);
+        let linear = na::Matrix3::identity() - n * n.transpose() * 2.0;
+        let mut matrix = linear.to_homogeneous();
+        matrix
+            .fixed_slice_mut::<na::U3, na::U1>(0, 3)
+            .copy_from(&(n * (-2.0 * d)));
+        matrix
+    }
+
+    /// Call `draw` for the reflection and then the refraction with the clip distance enabled,
+    /// each with a cleared target bound.
+    ///
+    /// Mirroring flips triangle winding, so front faces are clockwise during the reflection.
+    /// The callback must not draw the surface itself.
+    pub fn render<F: FnMut(&PlanarPass)>(
+        &self,
+        gl: &gl::Gl,
+        view_projection: &na::Matrix4<f32>,
+        camera_pos: &na::Point3<f32>,
+        mut draw: F,
+    ) {
+        let _clip_scope = ClipPlaneScope::enable(gl);
+        let mirror = self.mirror_matrix();
+
+        {
+            let _binding = self.reflection.bind();
+            PlanarReflection::clear(gl);
+            StateCache::set_mirrored(gl, true);
+            draw(&PlanarPass {
+                kind: PlanarPassKind::Reflection,
+                view_projection: view_projection * mirror,
+                camera_pos: mirror.transform_point(camera_pos),
+                clip_plane: clip_plane(&(self.point - self.normal * CLIP_OFFSET), &self.normal),
+            });
+            StateCache::set_mirrored(gl, false);
+        }
+
+        {
+            let _binding = self.refraction.bind();
+            PlanarReflection::clear(gl);
+            draw(&PlanarPass {
+                kind: PlanarPassKind::Refraction,
+                view_projection: *view_projection,
+                camera_pos: *camera_pos,
+                clip_plane: clip_plane(&(self.point + self.normal * CLIP_OFFSET), &-self.normal),
+            });
+        }
+    }
+
+    fn target(gl: &gl::Gl, width: i32, height: i32) -> Result<Framebuffer, FramebufferError> {
+        Framebuffer::builder(width, height)
+            .with_color(ColorFormat::Rgba16f)
+            .with_depth()
+            .build(gl)
+    }
+
+    fn clear(gl: &gl::Gl) {
+        StateCache::set_depth_test(gl, true);
+        StateCache::set_depth_mask(gl, true);
+        unsafe {
+            gl.Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
+        }
+    }
+}
@@ -0,0 +1,241 @@
+use failure;
+use gl;
+use nalgebra as na;
+use crate::render_gl::post_process::{FullscreenTriangle, PostEffect};
+use crate::render_gl::{
+    ColorFormat, Framebuffer, FramebufferError, Program, StateCache, Texture,
+};
+use crate::resources::Resources;
+use std::any::Any;
+
+/// Number of half-resolution steps, each blurred separately.
+const MIP_LEVELS: usize = 5;
+
+/// Blur targets for one mip level: result in `a`, `b` holds the horizontal pass.
+struct Level {
+    a: Framebuffer,
+    b: Framebuffer,
+}
+
+/// Bright parts of the HDR image blurred over a mip chain and added back to the image.
+///
+/// Place it before tone mapping, since the threshold is in linear HDR units.
+pub struct Bloom {
+    gl: gl::Gl,
+    bright_program: Program,
+    bright_input_location: Option<i32>,
+    bright_threshold_location: Option<i32>,
+    bright_knee_location: Option<i32>,
+    blur_program: Program,
+    blur_input_location: Option<i32>,
+    blur_step_location: Option<i32>,
+    copy_program: Program,
+    copy_input_location: Option<i32>,
+    composite_program: Program,
+    composite_input_location: Option<i32>,
+    composite_bloom_location: Option<i32>,
+    composite_intensity_location: Option<i32>,
+    levels: Vec<Level>,
+    pub enabled: bool,
+    /// Brightness above which pixels start to bloom.
+    pub threshold: f32,
+    /// Width of soft transition around the threshold, relative to it.
+    pub soft_knee: f32,
+    pub intensity: f32,
+}
+
+impl Bloom {
+    pub fn new(gl: &gl::Gl, res: &Resources) -> Result<Bloom, failure::Error> {
+        let load = |frag: &str| {
+            Program::from_res_files(gl, res, &["shaders/render_gl/fullscreen.vert", frag])
+        };
+        let bright_program = load("shaders/render_gl/bloom_bright.frag")?;
+        let blur_program = load("shaders/render_gl/bloom_blur.frag")?;
+        let copy_program = load("shaders/render_gl/bloom_copy.frag")?;
+        let composite_program = load("shaders/render_gl/bloom_composite.frag")?;
+
+        Ok(Bloom {
+            gl: gl.clone(),
+            bright_input_location: bright_program.get_uniform_location("Input"),
+            bright_threshold_location: bright_program.get_uniform_location("Threshold"),
+            bright_knee_location: bright_program.get_uniform_location("Knee"),
+            bright_program,
+            blur_input_location: blur_program.get_uniform_location("Input"),
+            blur_step_location: blur_program.get_uniform_location("Step"),
+            blur_program,
+            copy_input_location: copy_program.get_uniform_location("Input"),
+            copy_program,
+            composite_input_location: composite_program.get_uniform_location("Input"),
+            composite_bloom_location: composite_program.get_uniform_location("Bloom"),
+            composite_intensity_location: composite_program.get_uniform_location("Intensity"),
+            composite_program,
+            levels: Vec::new(),
+            enabled: true,
+            threshold: 1.0,
+            soft_knee: 0.5,
+            intensity: 0.3,
+        })
+    }
+
+    pub fn toggle(&mut self) {
+        self.enabled = !self.enabled;
+    }
+
+    fn draw_with_input(
+        gl: &gl::Gl,
+        program: &Program,
+        input_location: Option<i32>,
+        input: &Texture,
+        fullscreen: &FullscreenTriangle,
+    ) {
+        program.set_used();
+        if let Some(loc) = input_location {
+            input.bind_at(0);
+            program.set_uniform_1i(loc, 0);
+        }
+        fullscreen.draw(gl);
+    }
+
+    fn blur(&self, gl: &gl::Gl, level: &Level, fullscreen: &FullscreenTriangle) {
+        let texel = na::Vector2::new(1.0 / level.a.width() as f32, 1.0 / level.a.height() as f32);
+        let passes = [
+            (&level.a, &level.b, na::Vector2::new(texel.x, 0.0)),
+            (&level.b, &level.a, na::Vector2::new(0.0, texel.y)),
+        ];
+
+        for &(source, target, step) in passes.iter() {
+            let _binding = target.bind();
+            self.blur_program.set_used();
+            if let Some(loc) = self.blur_step_location {
+                self.blur_program.set_uniform_2f(loc, &step);
+            }
+            Self::draw_with_input(
+                gl,
+                &self.blur_program,
+                self.blur_input_location,
+                source.color_texture(0).expect("bloom color texture"),
+                fullscreen,
+            );
+        }
+    }
+}
+
+impl PostEffect for Bloom {
+    fn resize(&mut self, width: i32, height: i32) -> Result<(), FramebufferError> {
+        let level_size = |index: usize| {
+            (
+                ::std::cmp::max(width >> (index + 1), 1),
+                ::std::cmp::max(height >> (index + 1), 1),
+            )
+        };
+
+        if self.levels.is_empty() {
+            for index in 0..MIP_LEVELS {
+                let (w, h) = level_size(index);
+                let build = || {
+                    Framebuffer::builder(w, h)
+                        .with_color(ColorFormat::Rgba16f)
+                        .build(&self.gl)
+                };
+                let level = Level {
+                    a: build()?,
+                    b: build()?,
+                };
+                self.levels.push(level);
+            }
+        }
+
+        for (index, level) in self.levels.iter_mut().enumerate() {
+            let (w, h) = level_size(index);
+            level.a.resize(w, h)?;
+            level.b.resize(w, h)?;
+        }
+
+        Ok(())
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    fn apply(&self, gl: &gl::Gl, input: &Texture, fullscreen: &FullscreenTriangle) {
+        if self.levels.is_empty() {
+            return;
+        }
+
+        // bright pass at half resolution
+        {
+            let _binding = self.levels[0].a.bind();
+            self.bright_program.set_used();
+            if let Some(loc) = self.bright_threshold_location {
+                self.bright_program.set_uniform_1f(loc, self.threshold);
+            }
+            if let Some(loc) = self.bright_knee_location {
+                self.bright_program
+                    .set_uniform_1f(loc, self.threshold * self.soft_knee);
+            }
+            Self::draw_with_input(
+                gl,
+                &self.bright_program,
+                self.bright_input_location,
+                input,
+                fullscreen,
+            );
+        }
+        self.blur(gl, &self.levels[0], fullscreen);
+
+        // downsample and blur each level, so that lower levels spread further
+        for pair in self.levels.windows(2) {
+            {
+                let _binding = pair[1].a.bind();
+                Self::draw_with_input(
+                    gl,
+                    &self.copy_program,
+                    self.copy_input_location,
+                    pair[0].a.color_texture(0).expect("bloom color texture"),
+                    fullscreen,
+                );
+            }
+            self.blur(gl, &pair[1], fullscreen);
+        }
+
+        // accumulate levels back up with additive blending
+        StateCache::set_blend(gl, true);
+        StateCache::set_blend_func(gl, gl::ONE, gl::ONE);
+        for pair in self.levels.windows(2).rev() {
+            let _binding = pair[0].a.bind();
+            Self::draw_with_input(
+                gl,
+                &self.copy_program,
+                self.copy_input_location,
+                pair[1].a.color_texture(0).expect("bloom color texture"),
+                fullscreen,
+            );
+        }
+        StateCache::set_blend(gl, false);
+
+        self.composite_program.set_used();
+        if let Some(loc) = self.composite_bloom_location {
+            self.levels[0]
+                .a
+                .color_texture(0)
+                .expect("bloom color texture")
+                .bind_at(1);
+            self.composite_program.set_uniform_1i(loc, 1);
+        }
+        if let Some(loc) = self.composite_intensity_location {
+            self.composite_program.set_uniform_1f(loc, self.intensity);
+        }
+        Self::draw_with_input(
+            gl,
+            &self.composite_program,
+            self.composite_input_location,
+            input,
+            fullscreen,
+        );
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
@@ -0,0 +1,174 @@
+use gl;
+use crate::render_gl::buffer::VertexArray;
+use crate::render_gl::{
+    ColorFormat, FrameStats, Framebuffer, FramebufferBinding, FramebufferError, StateCache,
+    Texture,
+};
+use std::any::Any;
+
+mod bloom;
+mod tone_mapping;
+
+pub use self::bloom::Bloom;
+pub use self::tone_mapping::{ToneMapper, ToneMapping};
+
+/// Fullscreen pass in a `PostProcess` chain.
+pub trait PostEffect {
+    /// Called before the chain runs, with the size of the scene target.
+    fn resize(&mut self, _width: i32, _height: i32) -> Result<(), FramebufferError> {
+        Ok(())
+    }
+
+    /// Disabled effects are skipped by the chain.
+    fn is_enabled(&self) -> bool {
+        true
+    }
+
+    /// Draw into the currently bound framebuffer, reading previous result from `input`.
+    ///
+    /// Effects may render into their own framebuffers, but have to restore the binding.
+    fn apply(&self, gl: &gl::Gl, input: &Texture, fullscreen: &FullscreenTriangle);
+
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+/// Triangle covering the viewport, generated from vertex ids in `fullscreen.vert`.
+pub struct FullscreenTriangle {
+    vao: VertexArray,
+}
+
+impl FullscreenTriangle {
+    pub fn new(gl: &gl::Gl) -> FullscreenTriangle {
+        FullscreenTriangle {
+            vao: VertexArray::new(gl),
+        }
+    }
+
+    pub fn draw(&self, gl: &gl::Gl) {
+        self.vao.bind();
+        unsafe {
+            gl.DrawArrays(gl::TRIANGLES, 0, 3);
+        }
+        FrameStats::record_draw(gl::TRIANGLES, 3, 1);
+        self.vao.unbind();
+    }
+}
+
+/// HDR scene target followed by a chain of fullscreen effects.
+///
+/// Render the scene between `begin` and the drop of returned `ScenePass`, then call
+/// `render` to run the effects; the last one writes to the currently bound framebuffer.
+pub struct PostProcess {
+    gl: gl::Gl,
+    scene: Framebuffer,
+    intermediate: Vec<Framebuffer>,
+    effects: Vec<Box<dyn PostEffect>>,
+    fullscreen: FullscreenTriangle,
+}
+
+impl PostProcess {
+    pub fn new(gl: &gl::Gl, width: i32, height: i32) -> Result<PostProcess, FramebufferError> {
+        let scene = Framebuffer::builder(width, height)
+            .with_color(ColorFormat::Rgba16f)
+            .with_depth_stencil()
+            .build(gl)?;
+
+        Ok(PostProcess {
+            gl: gl.clone(),
+            scene,
+            intermediate: Vec::new(),
+            effects: Vec::new(),
+            fullscreen: FullscreenTriangle::new(gl),
+        })
+    }
+
+    /// Append effect to the end of the chain.
+    pub fn with_effect<E: PostEffect + 'static>(mut self, effect: E) -> Self {
+        self.effects.push(Box::new(effect));
+        self
+    }
+
+    /// Insert effect at position in the chain, i.e. before tone mapping.
+    pub fn insert_effect<E: PostEffect + 'static>(&mut self, index: usize, effect: E) {
+        self.effects.insert(index, Box::new(effect));
+    }
+
+    /// First effect of the type, for changing its settings.
+    pub fn effect_mut<E: PostEffect + 'static>(&mut self) -> Option<&mut E> {
+        self.effects
+            .iter_mut()
+            .filter_map(|effect| effect.as_any_mut().downcast_mut::<E>())
+            .next()
+    }
+
+    /// Floating point color and depth target with the scene, valid after `begin`.
+    pub fn scene(&self) -> &Framebuffer {
+        &self.scene
+    }
+
+    /// Resize targets, then bind and clear the HDR scene target.
+    pub fn begin(&mut self, width: i32, height: i32) -> Result<ScenePass, FramebufferError> {
+        self.scene.resize(width, height)?;
+        for framebuffer in &mut self.intermediate {
+            framebuffer.resize(width, height)?;
+        }
+        for effect in &mut self.effects {
+            effect.resize(width, height)?;
+        }
+
+        let binding = self.scene.bind();
+        unsafe {
+            self.gl
+                .Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT | gl::STENCIL_BUFFER_BIT);
+        }
+
+        Ok(ScenePass { _binding: binding })
+    }
+
+    /// Run enabled effects in order. Without effects the scene is copied as is.
+    pub fn render(&mut self) -> Result<(), FramebufferError> {
+        let gl = &self.gl;
+
+        let effects: Vec<_> = self.effects.iter().filter(|e| e.is_enabled()).collect();
+        if effects.is_empty() {
+            self.scene.resolve();
+            return Ok(());
+        }
+
+        // two targets are enough to ping-pong between any number of effects
+        let needed = ::std::cmp::min(effects.len() - 1, 2);
+        while self.intermediate.len() < needed {
+            self.intermediate.push(
+                Framebuffer::builder(self.scene.width(), self.scene.height())
+                    .with_color(ColorFormat::Rgba16f)
+                    .build(gl)?,
+            );
+        }
+
+        StateCache::set_depth_test(gl, false);
+
+        let last = effects.len() - 1;
+        let mut input = self.scene.color_texture(0).expect("scene color texture");
+        for (index, effect) in effects.iter().enumerate() {
+            if index == last {
+                effect.apply(gl, input, &self.fullscreen);
+            } else {
+                let target = &self.intermediate[index % 2];
+                {
+                    let _binding = target.bind();
+                    effect.apply(gl, input, &self.fullscreen);
+                }
+                input = target.color_texture(0).expect("intermediate color texture");
+            }
+        }
+
+        StateCache::set_depth_test(gl, true);
+
+        Ok(())
+    }
+}
+
+/// HDR scene target binding, active while this value is alive.
+pub struct ScenePass<'a> {
+    _binding: FramebufferBinding<'a>,
+}
@@ -0,0 +1,90 @@
+use failure;
+use gl;
+use crate::render_gl::post_process::{FullscreenTriangle, PostEffect};
+use crate::render_gl::{Program, Texture, Uniform};
+use crate::resources::Resources;
+use std::any::Any;
+
+/// Operator mapping HDR color to displayable range, must match the shader.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ToneMapper {
+    /// Clamp only, for comparison.
+    None,
+    Reinhard,
+    /// Filmic curve fitted by Krzysztof Narkowicz.
+    Aces,
+}
+
+impl ToneMapper {
+    fn shader_index(self) -> i32 {
+        match self {
+            ToneMapper::None => 0,
+            ToneMapper::Reinhard => 1,
+            ToneMapper::Aces => 2,
+        }
+    }
+
+    pub fn next(self) -> ToneMapper {
+        match self {
+            ToneMapper::None => ToneMapper::Reinhard,
+            ToneMapper::Reinhard => ToneMapper::Aces,
+            ToneMapper::Aces => ToneMapper::None,
+        }
+    }
+}
+
+/// Exposure, tone mapping and gamma correction, usually the last effect in the chain.
+pub struct ToneMapping {
+    program: Program,
+    input_uniform: Uniform<i32>,
+    exposure_uniform: Uniform<f32>,
+    operator_uniform: Uniform<i32>,
+    gamma_uniform: Uniform<f32>,
+    pub tone_mapper: ToneMapper,
+    pub exposure: f32,
+    pub gamma: f32,
+}
+
+impl ToneMapping {
+    pub fn new(gl: &gl::Gl, res: &Resources) -> Result<ToneMapping, failure::Error> {
+        let program = Program::from_res_files(
+            gl,
+            res,
+            &[
+                "shaders/render_gl/fullscreen.vert",
+                "shaders/render_gl/tone_mapping.frag",
+            ],
+        )?;
+
+        Ok(ToneMapping {
+            input_uniform: program.uniform("Input"),
+            exposure_uniform: program.uniform("Exposure"),
+            operator_uniform: program.uniform("Operator"),
+            gamma_uniform: program.uniform("Gamma"),
+            program,
+            tone_mapper: ToneMapper::Aces,
+            exposure: 1.0,
+            gamma: 2.2,
+        })
+    }
+}
+
+impl PostEffect for ToneMapping {
+    fn apply(&self, gl: &gl::Gl, input: &Texture, fullscreen: &FullscreenTriangle) {
+        self.program.set_used();
+        if self.input_uniform.is_active() {
+            input.bind_at(0);
+            self.input_uniform.set(&self.program, &0);
+        }
+        self.exposure_uniform.set(&self.program, &self.exposure);
+        self.operator_uniform
+            .set(&self.program, &self.tone_mapper.shader_index());
+        self.gamma_uniform.set(&self.program, &self.gamma);
+
+        fullscreen.draw(gl);
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
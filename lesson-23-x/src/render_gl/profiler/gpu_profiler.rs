@@ -0,0 +1,315 @@
+use super::buffers::{Buffers, LinePoint};
+use failure;
+use gl;
+use nalgebra as na;
+use crate::render_gl::ColorBuffer;
+use crate::render_gl::DebugText;
+use crate::render_gl::Program;
+use crate::resources::Resources;
+use std::cell::RefCell;
+
+/// Results are read back this many frames later, so that the CPU never waits for the GPU.
+const FRAMES_IN_FLIGHT: usize = 2;
+const PIXELS_PER_MS: f32 = 40.0;
+const BAR_HEIGHT: i32 = 4;
+const BAR_SPACING: i32 = 2;
+const FRAME_BUDGET_MS: f32 = 1000.0 / 60.0;
+
+struct Zone {
+    name: &'static str,
+    query: gl::types::GLuint,
+}
+
+struct FrameQueries {
+    zones: Vec<Zone>,
+}
+
+struct State {
+    frames: Vec<FrameQueries>,
+    current: usize,
+    free_queries: Vec<gl::types::GLuint>,
+    scope_active: bool,
+}
+
+/// GPU time measured with `GL_TIME_ELAPSED` queries in named zones.
+///
+/// Zones can not be nested: a scope started while another is active measures nothing.
+pub struct Profiler {
+    gl: gl::Gl,
+    program: Program,
+    program_view_projection_location: Option<i32>,
+    buffers: Option<Buffers>,
+    draw_enabled: bool,
+    state: RefCell<State>,
+    results: Vec<(&'static str, f32)>,
+}
+
+impl Profiler {
+    pub fn new(gl: &gl::Gl, res: &Resources) -> Result<Profiler, failure::Error> {
+        let program = Program::from_res(gl, res, "shaders/render_gl/profiler_lines")?;
+        let program_view_projection_location = program.get_uniform_location("ViewProjection");
+
+        Ok(Profiler {
+            gl: gl.clone(),
+            program,
+            program_view_projection_location,
+            buffers: None,
+            draw_enabled: true,
+            state: RefCell::new(State {
+                frames: (0..FRAMES_IN_FLIGHT)
+                    .map(|_| FrameQueries { zones: Vec::new() })
+                    .collect(),
+                current: 0,
+                free_queries: Vec::new(),
+                scope_active: false,
+            }),
+            results: Vec::new(),
+        })
+    }
+
+    pub fn toggle(&mut self) {
+        self.draw_enabled = !self.draw_enabled;
+    }
+
+    /// Read back the oldest frame queries and start recording a new frame.
+    pub fn begin_frame(&mut self) {
+        let gl = &self.gl;
+        let state = self.state.get_mut();
+        state.current = (state.current + 1) % FRAMES_IN_FLIGHT;
+
+        let zones: Vec<_> = state.frames[state.current].zones.drain(..).collect();
+
+        // queries finish in order, so checking the last one is enough
+        let available = match zones.last() {
+            Some(zone) => {
+                let mut available: gl::types::GLint = 0;
+                unsafe {
+                    gl.GetQueryObjectiv(zone.query, gl::QUERY_RESULT_AVAILABLE, &mut available);
+                }
+                available != 0
+            }
+            None => false,
+        };
+
+        if available {
+            self.results.clear();
+            for zone in &zones {
+                let mut nanoseconds: gl::types::GLuint64 = 0;
+                unsafe {
+                    gl.GetQueryObjectui64v(zone.query, gl::QUERY_RESULT, &mut nanoseconds);
+                }
+                let ms = nanoseconds as f32 / 1_000_000.0;
+
+                // zones with the same name are summed
+                match self.results.iter_mut().find(|r| r.0 == zone.name) {
+                    Some(result) => result.1 += ms,
+                    None => self.results.push((zone.name, ms)),
+                }
+            }
+        }
+
+        state.free_queries.extend(zones.into_iter().map(|z| z.query));
+    }
+
+    /// Measure GPU time of commands issued until the returned scope is dropped.
+    pub fn scope(&self, name: &'static str) -> ProfilerScope {
+        let mut state = self.state.borrow_mut();
+        if state.scope_active {
+            return ProfilerScope {
+                profiler: self,
+                active: false,
+            };
+        }
+
+        let gl = &self.gl;
+        let query = state.free_queries.pop().unwrap_or_else(|| {
+            let mut query: gl::types::GLuint = 0;
+            unsafe {
+                gl.GenQueries(1, &mut query);
+            }
+            query
+        });
+
+        unsafe {
+            gl.BeginQuery(gl::TIME_ELAPSED, query);
+        }
+
+        let current = state.current;
+        state.frames[current].zones.push(Zone { name, query });
+        state.scope_active = true;
+
+        ProfilerScope {
+            profiler: self,
+            active: true,
+        }
+    }
+
+    /// Zone names with milliseconds, from a frame completed on the GPU.
+    pub fn results(&self) -> &[(&'static str, f32)] {
+        &self.results
+    }
+
+    /// Write zone names and times below the bars, in matching colors.
+    pub fn draw_labels(&self, text: &DebugText) {
+        if !self.draw_enabled {
+            return;
+        }
+
+        let top = (self.results.len() as i32 * (BAR_HEIGHT + BAR_SPACING) + BAR_SPACING) as f32;
+        for (index, &(name, ms)) in self.results.iter().enumerate() {
+            let (r, g, b) = zone_color(index);
+            text.draw_2d_colored(
+                2.0,
+                top + index as f32 * text.line_height(),
+                &format!("{}: {:.2} ms", name, ms),
+                na::Vector4::new(r, g, b, 1.0),
+            );
+        }
+    }
+
+    fn end_scope(&self) {
+        unsafe {
+            self.gl.EndQuery(gl::TIME_ELAPSED);
+        }
+        self.state.borrow_mut().scope_active = false;
+    }
+
+    fn update_buffer(&mut self, gl: &gl::Gl, view_height_pixels: i32) {
+        // a few lines per bar, and frame budget marker
+        let vertex_count = (self.results.len() * BAR_HEIGHT as usize + 1) * 2;
+
+        let recreate_buffer_capacity = match self.buffers {
+            None => Some(256),
+            Some(ref buffers) if buffers.vertex_capacity < vertex_count => {
+                Some(buffers.vertex_capacity * 2)
+            }
+            _ => None,
+        };
+
+        if let Some(new_capacity) = recreate_buffer_capacity {
+            self.buffers = Some(Buffers::new(gl, new_capacity));
+        }
+
+        if let Some(ref mut buffers) = self.buffers {
+            buffers.lines_vbo.bind();
+            if let Some(mut buffer) = unsafe {
+                buffers
+                    .lines_vbo
+                    .map_buffer_range_write_invalidate::<LinePoint>(0, vertex_count)
+            } {
+                let top = view_height_pixels as f32;
+
+                for (index, &(_, ms)) in self.results.iter().enumerate() {
+                    let (r, g, b) = zone_color(index);
+                    let color = (r, g, b, 0.8).into();
+                    let bar_top = top - (index as i32 * (BAR_HEIGHT + BAR_SPACING)) as f32;
+
+                    for row in 0..BAR_HEIGHT {
+                        let y = bar_top - row as f32 - 0.5;
+                        buffer.push(LinePoint {
+                            pos: (0.0, y).into(),
+                            color,
+                        });
+                        buffer.push(LinePoint {
+                            pos: (ms * PIXELS_PER_MS, y).into(),
+                            color,
+                        });
+                    }
+                }
+
+                let budget_x = FRAME_BUDGET_MS * PIXELS_PER_MS;
+                let bottom =
+                    top - (self.results.len() as i32 * (BAR_HEIGHT + BAR_SPACING)) as f32;
+                let white = (1.0, 1.0, 1.0, 0.6).into();
+                buffer.push(LinePoint {
+                    pos: (budget_x, top).into(),
+                    color: white,
+                });
+                buffer.push(LinePoint {
+                    pos: (budget_x, bottom).into(),
+                    color: white,
+                });
+            }
+            buffers.lines_vbo.unbind();
+
+            buffers.vertex_count = vertex_count;
+        }
+    }
+
+    /// Draw horizontal bar per zone at the top of the screen, with 60 FPS budget marker.
+    pub fn render(
+        &mut self,
+        gl: &gl::Gl,
+        target: &ColorBuffer,
+        vp_matrix: &na::Matrix4<f32>,
+        view_height_pixels: i32,
+    ) {
+        if !self.draw_enabled || self.results.is_empty() {
+            return;
+        }
+
+        self.update_buffer(gl, view_height_pixels);
+
+        if let Some(ref buffers) = self.buffers {
+            self.program.set_used();
+            if let Some(loc) = self.program_view_projection_location {
+                self.program.set_uniform_matrix_4fv(loc, vp_matrix);
+            }
+
+            buffers.lines_vao.bind();
+
+            unsafe {
+                target.set_default_blend_func(gl);
+                target.enable_blend(gl);
+
+                gl.DrawArrays(gl::LINES, 0, buffers.vertex_count as i32);
+
+                target.disable_blend(gl);
+            }
+
+            buffers.lines_vao.unbind();
+        }
+    }
+}
+
+impl Drop for Profiler {
+    fn drop(&mut self) {
+        let state = self.state.get_mut();
+        let queries: Vec<_> = state
+            .frames
+            .iter()
+            .flat_map(|f| f.zones.iter().map(|z| z.query))
+            .chain(state.free_queries.iter().cloned())
+            .collect();
+        if !queries.is_empty() {
+            unsafe {
+                self.gl.DeleteQueries(queries.len() as i32, queries.as_ptr());
+            }
+        }
+    }
+}
+
+pub struct ProfilerScope<'a> {
+    profiler: &'a Profiler,
+    active: bool,
+}
+
+impl<'a> Drop for ProfilerScope<'a> {
+    fn drop(&mut self) {
+        if self.active {
+            self.profiler.end_scope();
+        }
+    }
+}
+
+fn zone_color(index: usize) -> (f32, f32, f32) {
+    const COLORS: [(f32, f32, f32); 6] = [
+        (1.0, 0.8, 0.2),
+        (0.2, 0.8, 1.0),
+        (1.0, 0.4, 0.4),
+        (0.4, 1.0, 0.4),
+        (0.8, 0.4, 1.0),
+        (1.0, 1.0, 1.0),
+    ];
+    COLORS[index % COLORS.len()]
+}
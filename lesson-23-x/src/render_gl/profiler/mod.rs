@@ -1,6 +1,10 @@
 mod buffers;
 mod event_count_profiler;
 mod frame_profiler;
+mod gpu_profiler;
+mod stats_hud;
 
 pub use self::event_count_profiler::EventCountProfiler;
 pub use self::frame_profiler::FrameProfiler;
+pub use self::gpu_profiler::{Profiler, ProfilerScope};
+pub use self::stats_hud::StatsHud;
@@ -0,0 +1,130 @@
+use crate::render_gl::DebugText;
+use crate::render_gl::{FrameStats, Texture, TextureCache};
+use std::collections::VecDeque;
+
+/// Frames kept for the frame time percentiles.
+const FRAME_HISTORY: usize = 240;
+/// Texture load notices kept, the oldest are dropped first.
+const MAX_NOTICES: usize = 4;
+
+/// Text overlay with the work of the last frame and frame time percentiles.
+pub struct StatsHud {
+    pub enabled: bool,
+    frame_times: VecDeque<f32>,
+    stats: FrameStats,
+    gpu_ms: Option<f32>,
+    textures: usize,
+    texture_bytes: usize,
+    /// Textures dropped by `TextureCache::evict_unused` so far.
+    evicted_textures: usize,
+    /// Latest fallbacks taken by texture loads, see `Texture::take_notices`.
+    notices: VecDeque<String>,
+    /// Draws of the static mesh batch, if the scene has one.
+    batched_draws: Option<usize>,
+}
+
+impl StatsHud {
+    pub fn new() -> StatsHud {
+        StatsHud {
+            enabled: false,
+            frame_times: VecDeque::with_capacity(FRAME_HISTORY),
+            stats: FrameStats::default(),
+            gpu_ms: None,
+            textures: 0,
+            texture_bytes: 0,
+            evicted_textures: 0,
+            notices: VecDeque::with_capacity(MAX_NOTICES),
+            batched_draws: None,
+        }
+    }
+
+    pub fn toggle(&mut self) {
+        self.enabled = !self.enabled;
+    }
+
+    /// Draws issued by a `MeshBatch` in its single indirect call.
+    pub fn set_batched_draws(&mut self, draws: usize) {
+        self.batched_draws = Some(draws);
+    }
+
+    /// Count textures dropped by `TextureCache::evict_unused`.
+    pub fn add_evicted_textures(&mut self, count: usize) {
+        self.evicted_textures += count;
+    }
+
+    /// Take the counters of the finished frame, `gpu_ms` is the GPU time if it was measured.
+    pub fn end_frame(&mut self, frame_ms: f32, gpu_ms: Option<f32>) {
+        if self.frame_times.len() == FRAME_HISTORY {
+            self.frame_times.pop_front();
+        }
+        self.frame_times.push_back(frame_ms);
+        self.stats = FrameStats::take();
+        self.gpu_ms = gpu_ms;
+        self.textures = TextureCache::len();
+        self.texture_bytes = TextureCache::memory_usage();
+        for notice in Texture::take_notices() {
+            if self.notices.len() == MAX_NOTICES {
+                self.notices.pop_front();
+            }
+            self.notices.push_back(notice);
+        }
+    }
+
+    /// Frame time below which `percent` of the recent frames finished, in milliseconds.
+    pub fn frame_time_percentile(&self, percent: f32) -> f32 {
+        if self.frame_times.is_empty() {
+            return 0.0;
+        }
+        let mut sorted = self.frame_times.iter().cloned().collect::<Vec<_>>();
+        sorted.sort_by(|a, b| a.partial_cmp(b).unwrap_or(::std::cmp::Ordering::Equal));
+        let index = ((percent / 100.0) * (sorted.len() - 1) as f32).round() as usize;
+        sorted[index.min(sorted.len() - 1)]
+    }
+
+    /// Queue the text lines with top left corner at (x, y).
+    pub fn draw(&self, text: &DebugText, x: f32, y: f32) {
+        if !self.enabled {
+            return;
+        }
+
+        let stats = &self.stats;
+        let mut lines = vec![
+            format!(
+                "frame ms p50 {:.1} p95 {:.1} p99 {:.1}",
+                self.frame_time_percentile(50.0),
+                self.frame_time_percentile(95.0),
+                self.frame_time_percentile(99.0)
+            ),
+            match self.gpu_ms {
+                Some(ms) => format!("gpu ms {:.2}", ms),
+                None => "gpu ms -".to_string(),
+            },
+            format!("draws {} tris {}", stats.draw_calls, stats.triangles),
+            format!(
+                "uploads {} ({} KiB)",
+                stats.buffer_uploads,
+                stats.uploaded_bytes / 1024
+            ),
+            format!("texture binds {}", stats.texture_binds),
+            format!(
+                "cached textures {} ({} KiB), evicted {}",
+                self.textures,
+                self.texture_bytes / 1024,
+                self.evicted_textures
+            ),
+        ];
+        if let Some(draws) = self.batched_draws {
+            lines.push(format!("batched draws {}", draws));
+        }
+        lines.extend(self.notices.iter().cloned());
+        for (index, line) in lines.iter().enumerate() {
+            text.draw_2d(x, y + text.line_height() * index as f32, line);
+        }
+    }
+}
+
+impl Default for StatsHud {
+    fn default() -> StatsHud {
+        StatsHud::new()
+    }
+}
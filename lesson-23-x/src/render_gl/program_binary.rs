@@ -0,0 +1,245 @@
+use gl;
+use crate::render_gl;
+use crate::resources::Resources;
+use std::cell::RefCell;
+use std::ffi::CStr;
+
+/// Start of every saved binary, followed by the driver string, the binary format and the binary.
+const MAGIC: &[u8; 4] = b"PBIN";
+
+thread_local! {
+    static CACHE: RefCell<Option<Installed>> = RefCell::new(None);
+}
+
+struct Installed {
+    res: Resources,
+    /// Vendor, renderer and version, binaries are only valid for the driver that made them.
+    driver: String,
+}
+
+/// Linked program binaries saved between runs, so that shaders are compiled only once.
+///
+/// Binaries are keyed by an FNV-1a hash of the expanded sources of all stages, so that edited
+/// shaders compile again. The file starts with the driver string, a binary saved by another
+/// driver is deleted, as is one that is malformed or that the driver rejects, and the program
+/// is compiled from source and saved again. Nothing is cached before `install`, or on contexts
+/// without program binary formats.
+pub struct ProgramBinaryCache;
+
+impl ProgramBinaryCache {
+    /// Keep binaries in `res` for programs linked on this thread. Resources must be
+    /// writable, i.e. a directory next to the executable rather than a pack.
+    pub fn install(gl: &gl::Gl, res: Resources) {
+        let installed = if binaries_supported(gl) {
+            Some(Installed {
+                res,
+                driver: driver_string(gl),
+            })
+        } else {
+            None
+        };
+        CACHE.with(|cache| *cache.borrow_mut() = installed);
+    }
+
+    /// Compile every program from source again.
+    pub fn uninstall() {
+        CACHE.with(|cache| *cache.borrow_mut() = None);
+    }
+
+    pub fn is_installed() -> bool {
+        CACHE.with(|cache| cache.borrow().is_some())
+    }
+
+    /// Name of the binary for a program linked from these stages, `None` if not installed.
+    pub(crate) fn key(stages: &[(gl::types::GLenum, &CStr)]) -> Option<String> {
+        CACHE.with(|cache| {
+            cache.borrow().as_ref().map(|_| {
+                let mut hash = Fnv1a::new();
+                for (kind, source) in stages {
+                    let source = source.to_bytes();
+                    hash.write(&u32_bytes(*kind));
+                    hash.write(&u32_bytes(source.len() as u32));
+                    hash.write(source);
+                }
+                format!("{:016x}.bin", hash.finish())
+            })
+        })
+    }
+
+    /// Program linked from the saved binary, `None` if there is none or the driver rejects it.
+    ///
+    /// Binaries that can not be used are deleted.
+    pub(crate) fn load(gl: &gl::Gl, key: &str) -> Option<gl::types::GLuint> {
+        let (bytes, driver) = CACHE.with(|cache| {
+            cache.borrow().as_ref().and_then(|installed| {
+                installed
+                    .res
+                    .load_bytes(key)
+                    .ok()
+                    .map(|bytes| (bytes, installed.driver.clone()))
+            })
+        })?;
+        let (format, binary) = match parse_header(&bytes) {
+            Some((saved_driver, format, binary)) if saved_driver == driver.as_bytes() => {
+                (format, binary)
+            }
+            _ => {
+                remove(key);
+                return None;
+            }
+        };
+
+        let mut success: gl::types::GLint = 0;
+        let program_id = unsafe {
+            let program_id = gl.CreateProgram();
+            gl.ProgramBinary(
+                program_id,
+                format,
+                binary.as_ptr() as *const gl::types::GLvoid,
+                binary.len() as gl::types::GLsizei,
+            );
+            gl.GetProgramiv(program_id, gl::LINK_STATUS, &mut success);
+            program_id
+        };
+
+        if success == 0 {
+            unsafe {
+                gl.DeleteProgram(program_id);
+            }
+            remove(key);
+            return None;
+        }
+        Some(program_id)
+    }
+
+    /// Save the binary of a program linked with `gl::PROGRAM_BINARY_RETRIEVABLE_HINT`.
+    pub(crate) fn save(gl: &gl::Gl, key: &str, program_id: gl::types::GLuint) {
+        let mut len: gl::types::GLint = 0;
+        unsafe {
+            gl.GetProgramiv(program_id, gl::PROGRAM_BINARY_LENGTH, &mut len);
+        }
+        if len <= 0 {
+            return;
+        }
+
+        let mut binary = vec![0u8; len as usize];
+        let mut written: gl::types::GLsizei = 0;
+        let mut format: gl::types::GLenum = 0;
+        unsafe {
+            gl.GetProgramBinary(
+                program_id,
+                len,
+                &mut written,
+                &mut format,
+                binary.as_mut_ptr() as *mut gl::types::GLvoid,
+            );
+        }
+        binary.truncate(written.max(0) as usize);
+        if binary.is_empty() {
+            return;
+        }
+
+        CACHE.with(|cache| {
+            if let Some(installed) = cache.borrow().as_ref() {
+                let driver = installed.driver.as_bytes();
+                let mut bytes = Vec::with_capacity(12 + driver.len() + binary.len());
+                bytes.extend_from_slice(MAGIC);
+                bytes.extend_from_slice(&u32_bytes(driver.len() as u32));
+                bytes.extend_from_slice(driver);
+                bytes.extend_from_slice(&u32_bytes(format));
+                bytes.extend_from_slice(&binary);
+                if let Err(e) = installed.res.write_bytes(key, &bytes) {
+                    println!("Failed to save program binary {}: {}", key, e);
+                }
+            }
+        });
+    }
+}
+
+/// Delete a saved binary, it is saved again once the program is compiled from source.
+fn remove(key: &str) {
+    CACHE.with(|cache| {
+        if let Some(installed) = cache.borrow().as_ref() {
+            let _ = installed.res.remove(key);
+        }
+    });
+}
+
+/// Driver string, binary format and binary of a saved file, `None` if it is malformed.
+fn parse_header(bytes: &[u8]) -> Option<(&[u8], u32, &[u8])> {
+    if bytes.len() < 8 || &bytes[..4] != MAGIC {
+        return None;
+    }
+    let driver_end = 8 + read_u32(&bytes[4..8]) as usize;
+    if bytes.len() <= driver_end + 4 {
+        return None;
+    }
+    let format = read_u32(&bytes[driver_end..driver_end + 4]);
+    Some((&bytes[8..driver_end], format, &bytes[driver_end + 4..]))
+}
+
+fn u32_bytes(value: u32) -> [u8; 4] {
+    [
+        value as u8,
+        (value >> 8) as u8,
+        (value >> 16) as u8,
+        (value >> 24) as u8,
+    ]
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    u32::from(bytes[0])
+        | u32::from(bytes[1]) << 8
+        | u32::from(bytes[2]) << 16
+        | u32::from(bytes[3]) << 24
+}
+
+/// 64-bit FNV-1a, written out so that keys stay the same across compiler releases.
+struct Fnv1a(u64);
+
+impl Fnv1a {
+    fn new() -> Fnv1a {
+        Fnv1a(0xcbf2_9ce4_8422_2325)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 ^= u64::from(byte);
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+/// GL 4.1 or `GL_ARB_get_program_binary`, with at least one binary format.
+fn binaries_supported(gl: &gl::Gl) -> bool {
+    if render_gl::gl_version(gl) < (4, 1)
+        && !render_gl::extension_supported(gl, "GL_ARB_get_program_binary")
+    {
+        return false;
+    }
+    let mut formats: gl::types::GLint = 0;
+    unsafe {
+        gl.GetIntegerv(gl::NUM_PROGRAM_BINARY_FORMATS, &mut formats);
+    }
+    formats > 0
+}
+
+fn driver_string(gl: &gl::Gl) -> String {
+    [gl::VENDOR, gl::RENDERER, gl::VERSION]
+        .iter()
+        .map(|&name| {
+            let value = unsafe { gl.GetString(name) };
+            if value.is_null() {
+                String::new()
+            } else {
+                unsafe { CStr::from_ptr(value as *const _) }
+                    .to_string_lossy()
+                    .into_owned()
+            }
+        }).collect::<Vec<_>>()
+        .join(" / ")
+}
@@ -0,0 +1,160 @@
+use gl;
+use crate::render_gl::{Error, Program, Shader};
+use crate::resources::{Cache, Resources};
+use std::ffi::CString;
+use std::rc::Rc;
+
+thread_local! {
+    static PROGRAMS: Cache<Program> = Cache::new();
+}
+
+/// Programs linked once for each set of shader files and shared while in use.
+///
+/// There is one cache per thread, as the lessons use a single context on the main thread.
+/// Programs are keyed by resource names only, changed shader files are not picked up
+/// until all users of the program are dropped.
+pub struct ProgramCache;
+
+impl ProgramCache {
+    /// Shared `Program::from_res`.
+    pub fn from_res(gl: &gl::Gl, res: &Resources, name: &str) -> Result<Rc<Program>, Error> {
+        PROGRAMS.with(|programs| programs.get_or_load(name, || Program::from_res(gl, res, name)))
+    }
+
+    /// Shared `Program::from_res_with_defines`, each set of defines is a separate program.
+    pub fn from_res_with_defines(
+        gl: &gl::Gl,
+        res: &Resources,
+        name: &str,
+        defines: &[(&str, &str)],
+    ) -> Result<Rc<Program>, Error> {
+        let key = defines_key(name, defines);
+        PROGRAMS.with(|programs| {
+            programs.get_or_load(&key, || {
+                Program::from_res_with_defines(gl, res, name, defines)
+            })
+        })
+    }
+
+    /// Shared `Program::from_res_files`.
+    pub fn from_res_files(
+        gl: &gl::Gl,
+        res: &Resources,
+        resource_names: &[&str],
+    ) -> Result<Rc<Program>, Error> {
+        PROGRAMS.with(|programs| {
+            programs.get_or_load(&resource_names.join(", "), || {
+                Program::from_res_files(gl, res, resource_names)
+            })
+        })
+    }
+
+    /// Shared `Program::from_res_files_with_defines`.
+    pub fn from_res_files_with_defines(
+        gl: &gl::Gl,
+        res: &Resources,
+        resource_names: &[&str],
+        defines: &[(&str, &str)],
+    ) -> Result<Rc<Program>, Error> {
+        let key = defines_key(&resource_names.join(", "), defines);
+        PROGRAMS.with(|programs| {
+            programs.get_or_load(&key, || {
+                Program::from_res_files_with_defines(gl, res, resource_names, defines)
+            })
+        })
+    }
+
+    /// Solid magenta program drawn in place of programs or models that failed to load.
+    ///
+    /// Compiled from source in the executable, so that it works without any resources.
+    /// Takes positions at location 0, the `Camera` block and the `Model` uniform, or model
+    /// matrices at locations 4 to 7 if `instanced`. Writes the deferred geometry targets too.
+    pub fn error_program(gl: &gl::Gl, instanced: bool) -> Result<Rc<Program>, Error> {
+        let name = if instanced {
+            "error program, instanced"
+        } else {
+            "error program"
+        };
+        PROGRAMS.with(|programs| {
+            programs.get_or_load(name, || {
+                let defines = if instanced { "#define INSTANCED\n" } else { "" };
+                let compile = |kind, body: &str| {
+                    let source = CString::new(format!("#version 330 core\n{}{}", defines, body))
+                        .expect("expected error program source to have no nul bytes");
+                    Shader::from_source(gl, &source, kind).map_err(|message| {
+                        Error::CompileError {
+                            name: name.into(),
+                            message,
+                        }
+                    })
+                };
+                let shaders = [
+                    compile(gl::VERTEX_SHADER, ERROR_VERT)?,
+                    compile(gl::FRAGMENT_SHADER, ERROR_FRAG)?,
+                ];
+                let program = Program::from_shaders(gl, &shaders).map_err(|message| {
+                    Error::LinkError {
+                        name: name.into(),
+                        message,
+                    }
+                })?;
+                program.set_label(name);
+                Ok(program)
+            })
+        })
+    }
+
+    /// Number of programs in use.
+    pub fn len() -> usize {
+        PROGRAMS.with(|programs| programs.len())
+    }
+}
+
+fn defines_key(name: &str, defines: &[(&str, &str)]) -> String {
+    defines
+        .iter()
+        .fold(name.to_string(), |key, (define, value)| {
+            format!("{} {}={}", key, define, value)
+        })
+}
+
+const ERROR_VERT: &str = r#"
+layout (location = 0) in vec3 Position;
+#ifdef INSTANCED
+layout (location = 4) in mat4 Model;
+#else
+uniform mat4 Model;
+#endif
+
+layout (std140) uniform Camera {
+    mat4 ViewProjection;
+    vec3 CameraPos;
+    int DebugView;
+    vec4 ClipPlane;
+};
+
+out vec3 WorldPosition;
+
+void main()
+{
+    vec4 world = Model * vec4(Position, 1.0);
+    gl_Position = ViewProjection * world;
+    gl_ClipDistance[0] = dot(world, ClipPlane);
+    WorldPosition = world.xyz;
+}
+"#;
+
+const ERROR_FRAG: &str = r#"
+in vec3 WorldPosition;
+
+layout (location = 0) out vec4 Color;
+layout (location = 1) out vec4 Normal;
+layout (location = 2) out vec4 Position;
+
+void main()
+{
+    Color = vec4(1.0, 0.0, 1.0, 1.0);
+    Normal = vec4(0.0, 0.0, 1.0, 0.0);
+    Position = vec4(WorldPosition, 1.0);
+}
+"#;
@@ -0,0 +1,87 @@
+use gl;
+use crate::render_gl::{DepthMode, StateCache};
+
+/// Faces left out by rasterization.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CullMode {
+    /// Draw both sides, i.e. for foliage and other double-sided materials.
+    None,
+    Back,
+    Front,
+}
+
+/// Winding of front faces as seen by the camera.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum FrontFace {
+    CounterClockwise,
+    Clockwise,
+}
+
+/// Culling, winding and depth state of a material, applied through `StateCache` when the
+/// material is bound.
+///
+/// The default culls back faces of counter-clockwise triangles and keeps the depth state
+/// of the pass, i.e. no depth writes in the blended pass.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct RasterState {
+    pub cull: CullMode,
+    pub front_face: FrontFace,
+    /// Depth test and writes, the state of the pass if not set.
+    pub depth: Option<DepthMode>,
+}
+
+impl Default for RasterState {
+    fn default() -> Self {
+        RasterState {
+            cull: CullMode::Back,
+            front_face: FrontFace::CounterClockwise,
+            depth: None,
+        }
+    }
+}
+
+impl RasterState {
+    /// Both sides drawn, for materials of thin surfaces.
+    pub fn double_sided() -> RasterState {
+        RasterState::default().with_cull(CullMode::None)
+    }
+
+    pub fn with_cull(mut self, cull: CullMode) -> Self {
+        self.cull = cull;
+        self
+    }
+
+    pub fn with_front_face(mut self, front_face: FrontFace) -> Self {
+        self.front_face = front_face;
+        self
+    }
+
+    pub fn with_depth(mut self, depth: DepthMode) -> Self {
+        self.depth = Some(depth);
+        self
+    }
+
+    pub fn apply(&self, gl: &gl::Gl) {
+        match self.cull {
+            CullMode::None => StateCache::set_cull_face(gl, false),
+            CullMode::Back => {
+                StateCache::set_cull_face(gl, true);
+                StateCache::set_cull_mode(gl, gl::BACK);
+            }
+            CullMode::Front => {
+                StateCache::set_cull_face(gl, true);
+                StateCache::set_cull_mode(gl, gl::FRONT);
+            }
+        }
+        StateCache::set_front_face(
+            gl,
+            match self.front_face {
+                FrontFace::CounterClockwise => gl::CCW,
+                FrontFace::Clockwise => gl::CW,
+            },
+        );
+        if let Some(depth) = self.depth {
+            depth.apply(gl);
+        }
+    }
+}
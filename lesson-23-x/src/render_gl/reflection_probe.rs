@@ -0,0 +1,129 @@
+use failure;
+use gl;
+use nalgebra as na;
+use crate::render_gl::texture_cube::mip_levels;
+use crate::render_gl::{pbr, Framebuffer, FramebufferError, StateCache, TextureCube};
+use crate::resources::Resources;
+use std::f32;
+
+/// View direction and up vector of each cube map face, in GL face order.
+const FACES: [([f32; 3], [f32; 3]); 6] = [
+    ([1.0, 0.0, 0.0], [0.0, -1.0, 0.0]),
+    ([-1.0, 0.0, 0.0], [0.0, -1.0, 0.0]),
+    ([0.0, 1.0, 0.0], [0.0, 0.0, 1.0]),
+    ([0.0, -1.0, 0.0], [0.0, 0.0, -1.0]),
+    ([0.0, 0.0, 1.0], [0.0, -1.0, 0.0]),
+    ([0.0, 0.0, -1.0], [0.0, -1.0, 0.0]),
+];
+
+/// Camera of a single probe face, passed to the draw callback.
+pub struct ProbeFace {
+    /// Face index, 0 to 5 in GL order.
+    pub index: u32,
+    pub position: na::Point3<f32>,
+    pub view_projection: na::Matrix4<f32>,
+}
+
+/// Cube map of the scene as seen from a point, for reflections of the surroundings.
+///
+/// Every `render` draws the scene six times, once per face. Lower mipmap levels are
+/// box-filtered for blurry reflections, unless the probe is prefiltered for PBR materials.
+pub struct ReflectionProbe {
+    position: na::Point3<f32>,
+    near: f32,
+    far: f32,
+    cube: TextureCube,
+    target: Framebuffer,
+    environment: Option<pbr::Environment>,
+}
+
+impl ReflectionProbe {
+    pub fn new(
+        gl: &gl::Gl,
+        face_size: i32,
+        position: na::Point3<f32>,
+    ) -> Result<ReflectionProbe, FramebufferError> {
+        let target = Framebuffer::builder(face_size, face_size)
+            .with_depth()
+            .build(gl)?;
+
+        Ok(ReflectionProbe {
+            position,
+            near: 0.05,
+            far: 200.0,
+            cube: TextureCube::new_empty(gl, face_size, gl::RGB16F, mip_levels(face_size)),
+            target,
+            environment: None,
+        })
+    }
+
+    /// Prefilter irradiance and specular lighting after each render, so that the probe
+    /// can replace the sky environment of PBR materials. Costly for every frame updates.
+    pub fn enable_prefiltering(
+        &mut self,
+        gl: &gl::Gl,
+        res: &Resources,
+    ) -> Result<(), failure::Error> {
+        if self.environment.is_none() {
+            self.environment = Some(pbr::Environment::new(gl, res, &self.cube)?);
+        }
+        Ok(())
+    }
+
+    pub fn disable_prefiltering(&mut self) {
+        self.environment = None;
+    }
+
+    pub fn position(&self) -> na::Point3<f32> {
+        self.position
+    }
+
+    pub fn set_position(&mut self, position: na::Point3<f32>) {
+        self.position = position;
+    }
+
+    /// 90 degree projection shared by all faces.
+    pub fn projection(&self) -> na::Matrix4<f32> {
+        na::Matrix4::new_perspective(1.0, f32::consts::FRAC_PI_2, self.near, self.far)
+    }
+
+    pub fn texture(&self) -> &TextureCube {
+        &self.cube
+    }
+
+    /// Prefiltered lighting, if prefiltering is enabled.
+    pub fn environment(&self) -> Option<&pbr::Environment> {
+        self.environment.as_ref()
+    }
+
+    /// Call `draw` once for each face with its camera bound to a cleared target.
+    ///
+    /// The callback must not sample the probe texture, it is being rendered into.
+    pub fn render<F: FnMut(&ProbeFace)>(&self, gl: &gl::Gl, mut draw: F) {
+        let projection = self.projection();
+
+        for (index, &(direction, up)) in FACES.iter().enumerate() {
+            let target = self.position + na::Vector3::from(direction);
+            let view = na::Matrix4::look_at_rh(&self.position, &target, &up.into());
+            let face = ProbeFace {
+                index: index as u32,
+                position: self.position,
+                view_projection: projection * view,
+            };
+
+            self.target.attach_cube_face(&self.cube, face.index, 0);
+            let _binding = self.target.bind();
+            StateCache::set_depth_test(gl, true);
+            StateCache::set_depth_mask(gl, true);
+            unsafe {
+                gl.Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
+            }
+            draw(&face);
+        }
+
+        self.cube.generate_mipmaps();
+        if let Some(ref environment) = self.environment {
+            environment.update(&self.cube);
+        }
+    }
+}
@@ -0,0 +1,227 @@
+use gl;
+use nalgebra as na;
+use crate::render_gl::debug::{self, GroupScope};
+use crate::render_gl::{Framebuffer, FramebufferBinding, RasterState, StateCache};
+
+/// Depth test and writes of a pass.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DepthMode {
+    Disabled,
+    /// Test against the depth of earlier passes without writing it, i.e. for overlays.
+    Test,
+    TestWrite,
+}
+
+impl DepthMode {
+    pub fn apply(self, gl: &gl::Gl) {
+        match self {
+            DepthMode::Disabled => {
+                StateCache::set_depth_test(gl, false);
+                StateCache::set_depth_mask(gl, false);
+            }
+            DepthMode::Test => {
+                StateCache::set_depth_test(gl, true);
+                StateCache::set_depth_mask(gl, false);
+            }
+            DepthMode::TestWrite => {
+                StateCache::set_depth_test(gl, true);
+                StateCache::set_depth_mask(gl, true);
+            }
+        }
+    }
+}
+
+/// Blending of a pass, `Alpha` is the `ColorBuffer` default blend function.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BlendMode {
+    Opaque,
+    Alpha,
+    Additive,
+}
+
+/// Target, clears and fixed-function state of a pass, started with `begin_pass`.
+///
+/// Without a target the pass draws into the currently bound framebuffer, i.e. the window.
+/// Nothing is cleared unless asked for.
+#[derive(Clone)]
+pub struct RenderPass<'a> {
+    pub name: &'a str,
+    pub target: Option<&'a Framebuffer>,
+    pub clear_color: Option<na::Vector4<f32>>,
+    pub clear_depth: Option<f32>,
+    pub clear_stencil: Option<i32>,
+    /// `x, y, width, height` in pixels, the whole target if not set.
+    pub viewport: Option<[i32; 4]>,
+    pub depth: DepthMode,
+    pub blend: BlendMode,
+}
+
+impl<'a> RenderPass<'a> {
+    /// Opaque pass with depth test and writes, named in captures and debug output.
+    pub fn new(name: &'a str) -> RenderPass<'a> {
+        RenderPass {
+            name,
+            target: None,
+            clear_color: None,
+            clear_depth: None,
+            clear_stencil: None,
+            viewport: None,
+            depth: DepthMode::TestWrite,
+            blend: BlendMode::Opaque,
+        }
+    }
+
+    pub fn with_target(mut self, target: &'a Framebuffer) -> Self {
+        self.target = Some(target);
+        self
+    }
+
+    pub fn with_clear_color(mut self, color: na::Vector4<f32>) -> Self {
+        self.clear_color = Some(color);
+        self
+    }
+
+    pub fn with_clear_depth(mut self, depth: f32) -> Self {
+        self.clear_depth = Some(depth);
+        self
+    }
+
+    pub fn with_clear_stencil(mut self, stencil: i32) -> Self {
+        self.clear_stencil = Some(stencil);
+        self
+    }
+
+    pub fn with_viewport(mut self, x: i32, y: i32, width: i32, height: i32) -> Self {
+        self.viewport = Some([x, y, width, height]);
+        self
+    }
+
+    pub fn with_depth(mut self, depth: DepthMode) -> Self {
+        self.depth = depth;
+        self
+    }
+
+    pub fn with_blend(mut self, blend: BlendMode) -> Self {
+        self.blend = blend;
+        self
+    }
+}
+
+/// Bind the target of the pass, set its state and clear it.
+///
+/// Draw while the returned value is alive, the pass ends with `end_pass` or when it is
+/// dropped.
+pub fn begin_pass<'a>(gl: &'a gl::Gl, pass: &RenderPass<'a>) -> ActivePass<'a> {
+    let group = debug::group(gl, pass.name);
+
+    let binding = pass.target.map(|target| target.bind());
+    let mut previous_viewport = None;
+    if let Some([x, y, width, height]) = pass.viewport {
+        if binding.is_none() {
+            let mut viewport: [gl::types::GLint; 4] = [0; 4];
+            unsafe {
+                gl.GetIntegerv(gl::VIEWPORT, viewport.as_mut_ptr());
+            }
+            previous_viewport = Some(viewport);
+        }
+        unsafe {
+            gl.Viewport(x, y, width, height);
+        }
+    }
+
+    clear(gl, pass);
+
+    pass.depth.apply(gl);
+    match pass.blend {
+        BlendMode::Opaque => StateCache::set_blend(gl, false),
+        BlendMode::Alpha => {
+            StateCache::set_blend(gl, true);
+            StateCache::set_blend_func(gl, gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+        }
+        BlendMode::Additive => {
+            StateCache::set_blend(gl, true);
+            StateCache::set_blend_func(gl, gl::ONE, gl::ONE);
+        }
+    }
+
+    ActivePass {
+        gl,
+        previous_viewport,
+        _binding: binding,
+        _group: group,
+    }
+}
+
+/// Clear what the pass asks for, keeping the clear values of other code.
+fn clear(gl: &gl::Gl, pass: &RenderPass) {
+    let mut mask = 0;
+    unsafe {
+        let mut previous_color: [f32; 4] = [0.0; 4];
+        if let Some(color) = pass.clear_color {
+            gl.GetFloatv(gl::COLOR_CLEAR_VALUE, previous_color.as_mut_ptr());
+            gl.ClearColor(color.x, color.y, color.z, color.w);
+            mask |= gl::COLOR_BUFFER_BIT;
+        }
+        let mut previous_depth: gl::types::GLfloat = 1.0;
+        if let Some(depth) = pass.clear_depth {
+            gl.GetFloatv(gl::DEPTH_CLEAR_VALUE, &mut previous_depth);
+            gl.ClearDepth(f64::from(depth));
+            // masked depth writes also mask clears
+            StateCache::set_depth_mask(gl, true);
+            mask |= gl::DEPTH_BUFFER_BIT;
+        }
+        let mut previous_stencil: gl::types::GLint = 0;
+        if let Some(stencil) = pass.clear_stencil {
+            gl.GetIntegerv(gl::STENCIL_CLEAR_VALUE, &mut previous_stencil);
+            gl.ClearStencil(stencil);
+            mask |= gl::STENCIL_BUFFER_BIT;
+        }
+        if mask == 0 {
+            return;
+        }
+
+        gl.Clear(mask);
+
+        if pass.clear_color.is_some() {
+            let [r, g, b, a] = previous_color;
+            gl.ClearColor(r, g, b, a);
+        }
+        if pass.clear_depth.is_some() {
+            gl.ClearDepth(f64::from(previous_depth));
+        }
+        if pass.clear_stencil.is_some() {
+            gl.ClearStencil(previous_stencil);
+        }
+    }
+}
+
+/// Pass started by `begin_pass`, active while this value is alive.
+///
+/// When it ends the previous framebuffer and viewport are bound again, and depth test,
+/// depth writes, blending and culling go back to the defaults other passes expect: depth on,
+/// blending off, and the `RasterState` default left by materials.
+pub struct ActivePass<'a> {
+    gl: &'a gl::Gl,
+    previous_viewport: Option<[gl::types::GLint; 4]>,
+    _binding: Option<FramebufferBinding<'a>>,
+    _group: GroupScope,
+}
+
+impl<'a> ActivePass<'a> {
+    /// End the pass before the end of the scope.
+    pub fn end_pass(self) {}
+}
+
+impl<'a> Drop for ActivePass<'a> {
+    fn drop(&mut self) {
+        StateCache::set_depth_test(self.gl, true);
+        StateCache::set_depth_mask(self.gl, true);
+        StateCache::set_blend(self.gl, false);
+        RasterState::default().apply(self.gl);
+        if let Some([x, y, width, height]) = self.previous_viewport {
+            unsafe {
+                self.gl.Viewport(x, y, width, height);
+            }
+        }
+    }
+}
@@ -0,0 +1,207 @@
+use gl;
+use crate::render_gl::{Capabilities, StateCache};
+
+// anisotropic filtering is core only since GL 4.6, before that it is an extension
+const TEXTURE_MAX_ANISOTROPY: gl::types::GLenum = 0x84FE;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Filter {
+    Nearest,
+    Linear,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Wrap {
+    Repeat,
+    MirroredRepeat,
+    ClampToEdge,
+}
+
+impl Wrap {
+    fn gl_enum(self) -> gl::types::GLenum {
+        match self {
+            Wrap::Repeat => gl::REPEAT,
+            Wrap::MirroredRepeat => gl::MIRRORED_REPEAT,
+            Wrap::ClampToEdge => gl::CLAMP_TO_EDGE,
+        }
+    }
+}
+
+pub struct SamplerBuilder {
+    min_filter: Filter,
+    mag_filter: Filter,
+    mipmap_filter: Option<Filter>,
+    wrap: Wrap,
+    anisotropy: f32,
+    lod_bias: f32,
+}
+
+impl SamplerBuilder {
+    pub fn with_filter(mut self, min_filter: Filter, mag_filter: Filter) -> Self {
+        self.min_filter = min_filter;
+        self.mag_filter = mag_filter;
+        self
+    }
+
+    /// Filter between mipmap levels, `None` samples only the base level.
+    pub fn with_mipmap_filter(mut self, mipmap_filter: Option<Filter>) -> Self {
+        self.mipmap_filter = mipmap_filter;
+        self
+    }
+
+    pub fn with_wrap(mut self, wrap: Wrap) -> Self {
+        self.wrap = wrap;
+        self
+    }
+
+    /// Maximum anisotropy, clamped to what the driver supports. 1.0 disables it.
+    pub fn with_anisotropy(mut self, anisotropy: f32) -> Self {
+        self.anisotropy = anisotropy;
+        self
+    }
+
+    /// Added to the mipmap level, positive values blur.
+    pub fn with_lod_bias(mut self, lod_bias: f32) -> Self {
+        self.lod_bias = lod_bias;
+        self
+    }
+
+    pub fn build(self, gl: &gl::Gl) -> Sampler {
+        let mut id: gl::types::GLuint = 0;
+        unsafe {
+            gl.GenSamplers(1, &mut id);
+        }
+
+        let min_filter = match (self.min_filter, self.mipmap_filter) {
+            (Filter::Nearest, None) => gl::NEAREST,
+            (Filter::Linear, None) => gl::LINEAR,
+            (Filter::Nearest, Some(Filter::Nearest)) => gl::NEAREST_MIPMAP_NEAREST,
+            (Filter::Nearest, Some(Filter::Linear)) => gl::NEAREST_MIPMAP_LINEAR,
+            (Filter::Linear, Some(Filter::Nearest)) => gl::LINEAR_MIPMAP_NEAREST,
+            (Filter::Linear, Some(Filter::Linear)) => gl::LINEAR_MIPMAP_LINEAR,
+        };
+        let mag_filter = match self.mag_filter {
+            Filter::Nearest => gl::NEAREST,
+            Filter::Linear => gl::LINEAR,
+        };
+        let wrap = self.wrap.gl_enum() as gl::types::GLint;
+
+        unsafe {
+            gl.SamplerParameteri(id, gl::TEXTURE_MIN_FILTER, min_filter as gl::types::GLint);
+            gl.SamplerParameteri(id, gl::TEXTURE_MAG_FILTER, mag_filter as gl::types::GLint);
+            gl.SamplerParameteri(id, gl::TEXTURE_WRAP_S, wrap);
+            gl.SamplerParameteri(id, gl::TEXTURE_WRAP_T, wrap);
+            gl.SamplerParameteri(id, gl::TEXTURE_WRAP_R, wrap);
+            gl.SamplerParameterf(id, gl::TEXTURE_LOD_BIAS, self.lod_bias);
+        }
+
+        let anisotropy = self.anisotropy.min(max_anisotropy(gl));
+        if anisotropy > 1.0 {
+            unsafe {
+                gl.SamplerParameterf(id, TEXTURE_MAX_ANISOTROPY, anisotropy);
+            }
+        }
+
+        Sampler {
+            gl: gl.clone(),
+            id,
+            anisotropy: anisotropy.max(1.0),
+        }
+    }
+}
+
+/// Filtering and wrapping state bound to a texture unit, overriding the parameters
+/// of textures bound to the same unit.
+pub struct Sampler {
+    gl: gl::Gl,
+    id: gl::types::GLuint,
+    anisotropy: f32,
+}
+
+impl Sampler {
+    /// Trilinear filtering with repeat wrapping.
+    pub fn builder() -> SamplerBuilder {
+        SamplerBuilder {
+            min_filter: Filter::Linear,
+            mag_filter: Filter::Linear,
+            mipmap_filter: Some(Filter::Linear),
+            wrap: Wrap::Repeat,
+            anisotropy: 1.0,
+            lod_bias: 0.0,
+        }
+    }
+
+    pub fn id(&self) -> gl::types::GLuint {
+        self.id
+    }
+
+    /// Anisotropy actually used, after clamping to the driver limit.
+    pub fn anisotropy(&self) -> f32 {
+        self.anisotropy
+    }
+
+    pub fn bind_at(&self, unit: u32) {
+        StateCache::bind_sampler(&self.gl, unit, self.id);
+    }
+
+    /// Go back to sampling with texture parameters on the unit.
+    pub fn unbind_at(gl: &gl::Gl, unit: u32) {
+        StateCache::bind_sampler(gl, unit, 0);
+    }
+}
+
+impl Drop for Sampler {
+    fn drop(&mut self) {
+        StateCache::sampler_deleted(self.id);
+        unsafe {
+            self.gl.DeleteSamplers(1, &self.id);
+        }
+    }
+}
+
+/// Sampler settings to compare at runtime.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SamplerPreset {
+    /// No sampler, texture parameters are used.
+    Texture,
+    Nearest,
+    Bilinear,
+    Trilinear,
+    Anisotropic,
+}
+
+impl SamplerPreset {
+    pub fn next(self) -> SamplerPreset {
+        match self {
+            SamplerPreset::Texture => SamplerPreset::Nearest,
+            SamplerPreset::Nearest => SamplerPreset::Bilinear,
+            SamplerPreset::Bilinear => SamplerPreset::Trilinear,
+            SamplerPreset::Trilinear => SamplerPreset::Anisotropic,
+            SamplerPreset::Anisotropic => SamplerPreset::Texture,
+        }
+    }
+
+    pub fn build(self, gl: &gl::Gl) -> Option<Sampler> {
+        self.build_with_anisotropy(gl, 16.0)
+    }
+
+    /// Same as `build`, with anisotropy of the `Anisotropic` preset.
+    pub fn build_with_anisotropy(self, gl: &gl::Gl, anisotropy: f32) -> Option<Sampler> {
+        let builder = Sampler::builder();
+        let builder = match self {
+            SamplerPreset::Texture => return None,
+            SamplerPreset::Nearest => builder
+                .with_filter(Filter::Nearest, Filter::Nearest)
+                .with_mipmap_filter(None),
+            SamplerPreset::Bilinear => builder.with_mipmap_filter(Some(Filter::Nearest)),
+            SamplerPreset::Trilinear => builder,
+            SamplerPreset::Anisotropic => builder.with_anisotropy(anisotropy),
+        };
+        Some(builder.build(gl))
+    }
+}
+
+/// Anisotropy limit of the driver, 1.0 if anisotropic filtering is not supported.
+pub fn max_anisotropy(gl: &gl::Gl) -> f32 {
+    Capabilities::get(gl).max_anisotropy
+}
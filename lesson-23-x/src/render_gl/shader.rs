@@ -1,5 +1,7 @@
 use gl;
 use nalgebra as na;
+use crate::render_gl::shader_source::ShaderSource;
+use crate::render_gl::{self, debug, ProgramBinaryCache, StateCache, Uniform, UniformValue};
 use crate::resources::{self, Resources};
 use std;
 use std::ffi::{CStr, CString};
@@ -17,10 +19,19 @@ pub enum Error {
         name
     )]
     CanNotDetermineShaderTypeForResource { name: String },
+    #[fail(display = "Invalid #include in {} on line {}", name, line)]
+    InvalidInclude { name: String, line: usize },
+    #[fail(display = "Include cycle in shader {}: {}", name, chain)]
+    IncludeCycle { name: String, chain: String },
     #[fail(display = "Failed to compile shader {}: {}", name, message)]
     CompileError { name: String, message: String },
     #[fail(display = "Failed to link program {}: {}", name, message)]
     LinkError { name: String, message: String },
+    #[fail(
+        display = "Compute shader {} needs GL 4.3, context is {}.{}",
+        name, major, minor
+    )]
+    ComputeNotSupported { name: String, major: i32, minor: i32 },
 }
 
 pub struct Program {
@@ -28,27 +39,134 @@ pub struct Program {
     id: gl::types::GLuint,
 }
 
+/// Uniform reported as active by the linked program.
+#[derive(Clone, Debug)]
+pub struct ActiveUniform {
+    /// Name without the `[0]` suffix of arrays.
+    pub name: String,
+    /// GL type, such as `gl::FLOAT_MAT4` or `gl::SAMPLER_2D`.
+    pub kind: gl::types::GLenum,
+    /// Array length, 1 for non-array uniforms.
+    pub size: i32,
+    pub location: i32,
+}
+
 impl Program {
+    /// Link program from `name.vert` and `name.frag`, together with `name.geom`, `name.tesc`
+    /// and `name.tese` if they exist.
     pub fn from_res(gl: &gl::Gl, res: &Resources, name: &str) -> Result<Program, Error> {
-        const POSSIBLE_EXT: [&str; 2] = [".vert", ".frag"];
+        Program::from_res_with_defines(gl, res, name, &[])
+    }
 
-        let resource_names = POSSIBLE_EXT
+    /// `from_res` with `#define NAME VALUE` added to every stage, i.e. for feature toggles.
+    pub fn from_res_with_defines(
+        gl: &gl::Gl,
+        res: &Resources,
+        name: &str,
+        defines: &[(&str, &str)],
+    ) -> Result<Program, Error> {
+        const REQUIRED_EXT: [&str; 2] = [".vert", ".frag"];
+        const OPTIONAL_EXT: [&str; 3] = [".geom", ".tesc", ".tese"];
+
+        let mut stages = REQUIRED_EXT
             .iter()
             .map(|file_extension| format!("{}{}", name, file_extension))
-            .collect::<Vec<String>>();
+            .map(|resource_name| Stage::load(res, &resource_name, defines))
+            .collect::<Result<Vec<Stage>, Error>>()?;
+
+        for file_extension in &OPTIONAL_EXT {
+            let resource_name = format!("{}{}", name, file_extension);
+            match Stage::load(res, &resource_name, defines) {
+                Ok(stage) => stages.push(stage),
+                // a missing include is an error, only the stage itself is optional
+                Err(Error::ResourceLoad {
+                    name: ref missing,
+                    ref inner,
+                }) if inner.is_not_found() && *missing == resource_name => (),
+                Err(e) => return Err(e),
+            }
+        }
 
-        let shaders = resource_names
+        Program::from_stages(gl, name, &stages)
+    }
+
+    /// Link program from explicitly listed shader resources, i.e. to share a fragment shader.
+    pub fn from_res_files(
+        gl: &gl::Gl,
+        res: &Resources,
+        resource_names: &[&str],
+    ) -> Result<Program, Error> {
+        Program::from_res_files_with_defines(gl, res, resource_names, &[])
+    }
+
+    /// `from_res_files` with `#define NAME VALUE` added to every stage.
+    pub fn from_res_files_with_defines(
+        gl: &gl::Gl,
+        res: &Resources,
+        resource_names: &[&str],
+        defines: &[(&str, &str)],
+    ) -> Result<Program, Error> {
+        let stages = resource_names
             .iter()
-            .map(|resource_name| Shader::from_res(gl, res, resource_name))
-            .collect::<Result<Vec<Shader>, Error>>()?;
+            .map(|resource_name| Stage::load(res, resource_name, defines))
+            .collect::<Result<Vec<Stage>, Error>>()?;
 
-        Program::from_shaders(gl, &shaders[..]).map_err(|message| Error::LinkError {
-            name: name.into(),
-            message,
-        })
+        Program::from_stages(gl, &resource_names.join(", "), &stages)
+    }
+
+    /// Link compute program from `name.comp`. Fails on contexts older than GL 4.3.
+    pub fn from_res_compute(gl: &gl::Gl, res: &Resources, name: &str) -> Result<Program, Error> {
+        if !render_gl::compute_supported(gl) {
+            let (major, minor) = render_gl::gl_version(gl);
+            return Err(Error::ComputeNotSupported {
+                name: name.into(),
+                major,
+                minor,
+            });
+        }
+
+        let stage = Stage::load(res, &format!("{}.comp", name), &[])?;
+        Program::from_stages(gl, name, &[stage])
+    }
+
+    /// Link loaded stages, or load the program from `ProgramBinaryCache` if it has them.
+    fn from_stages(gl: &gl::Gl, name: &str, stages: &[Stage]) -> Result<Program, Error> {
+        let key = ProgramBinaryCache::key(
+            &stages
+                .iter()
+                .map(|stage| (stage.kind, stage.source.source.as_c_str()))
+                .collect::<Vec<_>>(),
+        );
+
+        if let Some(id) = key.as_ref().and_then(|key| ProgramBinaryCache::load(gl, key)) {
+            let program = Program { gl: gl.clone(), id };
+            program.set_label(name);
+            return Ok(program);
+        }
+
+        let shaders = stages
+            .iter()
+            .map(|stage| stage.compile(gl))
+            .collect::<Result<Vec<Shader>, Error>>()?;
+        let program = Program::link(gl, &shaders[..], key.is_some()).map_err(|message| {
+            Error::LinkError {
+                name: name.into(),
+                message,
+            }
+        })?;
+        if let Some(key) = key {
+            ProgramBinaryCache::save(gl, &key, program.id);
+        }
+        program.set_label(name);
+        Ok(program)
     }
 
     pub fn from_shaders(gl: &gl::Gl, shaders: &[Shader]) -> Result<Program, String> {
+        Program::link(gl, shaders, false)
+    }
+
+    /// Link the shaders, keeping the binary retrievable for `ProgramBinaryCache` if asked.
+    fn link(gl: &gl::Gl, shaders: &[Shader], retrievable: bool) -> Result<Program, String> {
         let program_id = unsafe { gl.CreateProgram() };
 
         for shader in shaders {
@@ -58,6 +176,13 @@ impl Program {
         }
 
         unsafe {
+            if retrievable {
+                gl.ProgramParameteri(
+                    program_id,
+                    gl::PROGRAM_BINARY_RETRIEVABLE_HINT,
+                    gl::TRUE as gl::types::GLint,
+                );
+            }
             gl.LinkProgram(program_id);
         }
 
@@ -102,10 +227,17 @@ impl Program {
         self.id
     }
 
+    pub fn gl(&self) -> &gl::Gl {
+        &self.gl
+    }
+
     pub fn set_used(&self) {
-        unsafe {
-            self.gl.UseProgram(self.id);
-        }
+        StateCache::use_program(&self.gl, self.id);
+    }
+
+    /// Name shown in debug messages and captures, set to the resource name by `from_res`.
+    pub fn set_label(&self, label: &str) {
+        debug::object_label(&self.gl, gl::PROGRAM, self.id, label);
     }
 
     pub fn get_uniform_location(&self, name: &str) -> Option<i32> {
@@ -123,6 +255,156 @@ impl Program {
         Some(location)
     }
 
+    /// List uniforms used by the program. Uniforms in blocks have no location and are left out.
+    /// Typed handle of the uniform, checked against its reflected GLSL type.
+    ///
+    /// A type mismatch panics in debug builds. Release builds log it and return an inactive
+    /// handle, so that the wrong value is never written.
+    pub fn uniform<T: UniformValue>(&self, name: &str) -> Uniform<T> {
+        let info = match self.active_uniforms().into_iter().find(|u| u.name == name) {
+            Some(info) => info,
+            None => return Uniform::inactive(),
+        };
+
+        if !T::accepts(info.kind) {
+            let message = format!(
+                "uniform {} of GL type 0x{:x} can not be set from {}",
+                name,
+                info.kind,
+                T::TYPE_NAME
+            );
+            if cfg!(debug_assertions) {
+                panic!("{}", message);
+            }
+            println!("{}", message);
+            return Uniform::inactive();
+        }
+
+        Uniform::new(Some(info.location))
+    }
+
+    pub fn active_uniforms(&self) -> Vec<ActiveUniform> {
+        let mut count: gl::types::GLint = 0;
+        let mut max_name_len: gl::types::GLint = 0;
+        unsafe {
+            self.gl
+                .GetProgramiv(self.id, gl::ACTIVE_UNIFORMS, &mut count);
+            self.gl
+                .GetProgramiv(self.id, gl::ACTIVE_UNIFORM_MAX_LENGTH, &mut max_name_len);
+        }
+
+        let mut uniforms = Vec::with_capacity(count as usize);
+        let mut name_buffer = vec![0u8; max_name_len.max(1) as usize];
+
+        for index in 0..count as gl::types::GLuint {
+            let mut name_len: gl::types::GLsizei = 0;
+            let mut size: gl::types::GLint = 0;
+            let mut kind: gl::types::GLenum = 0;
+            unsafe {
+                self.gl.GetActiveUniform(
+                    self.id,
+                    index,
+                    name_buffer.len() as gl::types::GLsizei,
+                    &mut name_len,
+                    &mut size,
+                    &mut kind,
+                    name_buffer.as_mut_ptr() as *mut gl::types::GLchar,
+                );
+            }
+
+            let name = String::from_utf8_lossy(&name_buffer[..name_len as usize]);
+            let name = name.trim_end_matches("[0]");
+            if let Some(location) = self.get_uniform_location(name) {
+                uniforms.push(ActiveUniform {
+                    name: name.into(),
+                    kind,
+                    size,
+                    location,
+                });
+            }
+        }
+
+        uniforms
+    }
+
+    /// Assign uniform block to the binding point. Returns false if the block is not used.
+    pub fn bind_uniform_block(&self, name: &str, binding_point: u32) -> bool {
+        let cname = CString::new(name).expect("expected uniform block name to have no nul bytes");
+
+        let index = unsafe {
+            self.gl
+                .GetUniformBlockIndex(self.id, cname.as_bytes_with_nul().as_ptr() as *const i8)
+        };
+
+        if index == gl::INVALID_INDEX {
+            return false;
+        }
+
+        unsafe {
+            self.gl.UniformBlockBinding(self.id, index, binding_point);
+        }
+
+        true
+    }
+
+    /// Assign shader storage block to the binding point. Returns false if the block is not used.
+    pub fn bind_shader_storage_block(&self, name: &str, binding_point: u32) -> bool {
+        let cname = CString::new(name).expect("expected storage block name to have no nul bytes");
+
+        let index = unsafe {
+            self.gl.GetProgramResourceIndex(
+                self.id,
+                gl::SHADER_STORAGE_BLOCK,
+                cname.as_bytes_with_nul().as_ptr() as *const i8,
+            )
+        };
+
+        if index == gl::INVALID_INDEX {
+            return false;
+        }
+
+        unsafe {
+            self.gl
+                .ShaderStorageBlockBinding(self.id, index, binding_point);
+        }
+
+        true
+    }
+
+    /// `local_size` declared by the compute shader.
+    pub fn compute_work_group_size(&self) -> [i32; 3] {
+        let mut size: [gl::types::GLint; 3] = [0; 3];
+        unsafe {
+            self.gl
+                .GetProgramiv(self.id, gl::COMPUTE_WORK_GROUP_SIZE, size.as_mut_ptr());
+        }
+        size
+    }
+
+    /// Run the compute program over the given number of work groups.
+    pub fn dispatch_compute(&self, groups_x: u32, groups_y: u32, groups_z: u32) {
+        self.set_used();
+        unsafe {
+            self.gl.DispatchCompute(groups_x, groups_y, groups_z);
+        }
+    }
+
+    /// Run enough work groups along x to cover `count` invocations.
+    pub fn dispatch_compute_1d(&self, count: u32) {
+        let group_size = self.compute_work_group_size()[0].max(1) as u32;
+        self.dispatch_compute((count + group_size - 1) / group_size, 1, 1);
+    }
+
+    /// Run the compute program with group counts read from the bound
+    /// `gl::DISPATCH_INDIRECT_BUFFER` at byte `offset`.
+    pub fn dispatch_compute_indirect(&self, offset: usize) {
+        self.set_used();
+        unsafe {
+            self.gl
+                .DispatchComputeIndirect(offset as gl::types::GLintptr);
+        }
+    }
+
     pub fn set_uniform_matrix_4fv(&self, location: i32, value: &na::Matrix4<f32>) {
         unsafe {
             self.gl.UniformMatrix4fv(
@@ -134,21 +416,55 @@ impl Program {
         }
     }
 
+    pub fn set_uniform_2f(&self, location: i32, value: &na::Vector2<f32>) {
+        unsafe {
+            self.gl.Uniform2f(location, value.x, value.y);
+        }
+    }
+
     pub fn set_uniform_3f(&self, location: i32, value: &na::Vector3<f32>) {
         unsafe {
             self.gl.Uniform3f(location, value.x, value.y, value.z);
         }
     }
 
+    pub fn set_uniform_4f(&self, location: i32, value: &na::Vector4<f32>) {
+        unsafe {
+            self.gl
+                .Uniform4f(location, value.x, value.y, value.z, value.w);
+        }
+    }
+
     pub fn set_uniform_1i(&self, location: i32, index: i32) {
         unsafe {
             self.gl.Uniform1i(location, index);
         }
     }
+
+    pub fn set_uniform_1ui(&self, location: i32, value: u32) {
+        unsafe {
+            self.gl.Uniform1ui(location, value);
+        }
+    }
+
+    pub fn set_uniform_1f(&self, location: i32, value: f32) {
+        unsafe {
+            self.gl.Uniform1f(location, value);
+        }
+    }
+
+    /// Set a float array uniform from its first element.
+    pub fn set_uniform_1fv(&self, location: i32, values: &[f32]) {
+        unsafe {
+            self.gl
+                .Uniform1fv(location, values.len() as gl::types::GLsizei, values.as_ptr());
+        }
+    }
 }
 
 impl Drop for Program {
     fn drop(&mut self) {
+        StateCache::program_deleted(self.id);
         unsafe {
             self.gl.DeleteProgram(self.id);
         }
@@ -161,25 +477,21 @@ pub struct Shader {
 }
 
 impl Shader {
+    /// Compile the shader resource, expanding `#include "file"` lines.
+    ///
+    /// Compile errors name the file the failing line comes from.
     pub fn from_res(gl: &gl::Gl, res: &Resources, name: &str) -> Result<Shader, Error> {
-        const POSSIBLE_EXT: [(&str, gl::types::GLenum); 2] =
-            [(".vert", gl::VERTEX_SHADER), (".frag", gl::FRAGMENT_SHADER)];
-
-        let shader_kind = POSSIBLE_EXT
-            .iter()
-            .find(|&&(file_extension, _)| name.ends_with(file_extension))
-            .map(|&(_, kind)| kind)
-            .ok_or_else(|| Error::CanNotDetermineShaderTypeForResource { name: name.into() })?;
-
-        let source = res.load_cstring(name).map_err(|e| Error::ResourceLoad {
-            name: name.into(),
-            inner: e,
-        })?;
+        Shader::from_res_with_defines(gl, res, name, &[])
+    }
 
-        Shader::from_source(gl, &source, shader_kind).map_err(|message| Error::CompileError {
-            name: name.into(),
-            message,
-        })
+    /// `from_res` with `#define NAME VALUE` lines added after `#version`.
+    pub fn from_res_with_defines(
+        gl: &gl::Gl,
+        res: &Resources,
+        name: &str,
+        defines: &[(&str, &str)],
+    ) -> Result<Shader, Error> {
+        Stage::load(res, name, defines)?.compile(gl)
     }
 
     pub fn from_source(
@@ -199,6 +511,22 @@ impl Shader {
         Shader::from_source(gl, source, gl::FRAGMENT_SHADER)
     }
 
+    pub fn from_geom_source(gl: &gl::Gl, source: &CStr) -> Result<Shader, String> {
+        Shader::from_source(gl, source, gl::GEOMETRY_SHADER)
+    }
+
+    pub fn from_tesc_source(gl: &gl::Gl, source: &CStr) -> Result<Shader, String> {
+        Shader::from_source(gl, source, gl::TESS_CONTROL_SHADER)
+    }
+
+    pub fn from_tese_source(gl: &gl::Gl, source: &CStr) -> Result<Shader, String> {
+        Shader::from_source(gl, source, gl::TESS_EVALUATION_SHADER)
+    }
+
+    pub fn from_comp_source(gl: &gl::Gl, source: &CStr) -> Result<Shader, String> {
+        Shader::from_source(gl, source, gl::COMPUTE_SHADER)
+    }
+
     pub fn id(&self) -> gl::types::GLuint {
         self.id
     }
@@ -212,6 +540,47 @@ impl Drop for Shader {
     }
 }
 
+/// Shader resource loaded and expanded, ready to compile.
+struct Stage {
+    name: String,
+    kind: gl::types::GLenum,
+    source: ShaderSource,
+}
+
+impl Stage {
+    fn load(res: &Resources, name: &str, defines: &[(&str, &str)]) -> Result<Stage, Error> {
+        const POSSIBLE_EXT: [(&str, gl::types::GLenum); 6] = [
+            (".vert", gl::VERTEX_SHADER),
+            (".frag", gl::FRAGMENT_SHADER),
+            (".geom", gl::GEOMETRY_SHADER),
+            (".tesc", gl::TESS_CONTROL_SHADER),
+            (".tese", gl::TESS_EVALUATION_SHADER),
+            (".comp", gl::COMPUTE_SHADER),
+        ];
+
+        let kind = POSSIBLE_EXT
+            .iter()
+            .find(|&&(file_extension, _)| name.ends_with(file_extension))
+            .map(|&(_, kind)| kind)
+            .ok_or_else(|| Error::CanNotDetermineShaderTypeForResource { name: name.into() })?;
+
+        Ok(Stage {
+            name: name.into(),
+            kind,
+            source: ShaderSource::load(res, name, defines)?,
+        })
+    }
+
+    fn compile(&self, gl: &gl::Gl) -> Result<Shader, Error> {
+        Shader::from_source(gl, &self.source.source, self.kind).map_err(|message| {
+            Error::CompileError {
+                name: self.name.clone(),
+                message: self.source.remap_log(&message),
+            }
+        })
+    }
+}
+
 fn shader_from_source(
     gl: &gl::Gl,
     source: &CStr,
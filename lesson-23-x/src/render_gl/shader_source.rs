@@ -0,0 +1,185 @@
+use crate::render_gl::Error;
+use crate::resources::{ResourcePath, Resources};
+use std::ffi::CString;
+
+/// Includes not found next to the including file are looked up from here.
+const INCLUDE_ROOT: &str = "shaders";
+
+/// Shader source with `#include "file"` lines expanded and defines injected.
+///
+/// Each file is included once, later includes of it are skipped. `#line` directives keep
+/// line numbers of every file, with the file index as GLSL source string number, so that
+/// `remap_log` can name the file of a compile error.
+pub struct ShaderSource {
+    pub source: CString,
+    /// Resource names by source string number, the shader itself is 0.
+    pub files: Vec<String>,
+}
+
+impl ShaderSource {
+    /// Load the shader and its includes. Defines are added as `#define NAME VALUE` right
+    /// after `#version`.
+    pub fn load(
+        res: &Resources,
+        name: &str,
+        defines: &[(&str, &str)],
+    ) -> Result<ShaderSource, Error> {
+        let mut expander = Expander {
+            res,
+            defines,
+            files: Vec::new(),
+            stack: Vec::new(),
+            output: String::new(),
+        };
+        expander.expand(name)?;
+
+        let source = CString::new(expander.output).map_err(|_| Error::ResourceLoad {
+            name: name.into(),
+            inner: crate::resources::Error::FileContainsNil,
+        })?;
+        Ok(ShaderSource {
+            source,
+            files: expander.files,
+        })
+    }
+
+    /// Compile log with source string numbers replaced by file names.
+    ///
+    /// Understands `0(12) : error` and `ERROR: 0:12:` styles of driver messages, other
+    /// lines are kept as they are.
+    pub fn remap_log(&self, log: &str) -> String {
+        log.lines()
+            .map(|line| {
+                let (prefix, rest) = ["ERROR: ", "WARNING: "]
+                    .iter()
+                    .find(|prefix| line.starts_with(*prefix))
+                    .map(|prefix| line.split_at(prefix.len()))
+                    .unwrap_or(("", line));
+                let digits = rest.bytes().take_while(u8::is_ascii_digit).count();
+                let file = rest[..digits]
+                    .parse::<usize>()
+                    .ok()
+                    .and_then(|index| self.files.get(index));
+                match (file, rest[digits..].chars().next()) {
+                    (Some(file), Some(':')) | (Some(file), Some('(')) => {
+                        format!("{}{}{}", prefix, file, &rest[digits..])
+                    }
+                    _ => line.to_string(),
+                }
+            }).collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+struct Expander<'a> {
+    res: &'a Resources,
+    defines: &'a [(&'a str, &'a str)],
+    files: Vec<String>,
+    /// Files being expanded, to report include cycles.
+    stack: Vec<String>,
+    output: String,
+}
+
+impl<'a> Expander<'a> {
+    fn expand(&mut self, name: &str) -> Result<(), Error> {
+        let bytes = self.res.load_bytes(name).map_err(|e| Error::ResourceLoad {
+            name: name.into(),
+            inner: e,
+        })?;
+        let text = String::from_utf8_lossy(&bytes).into_owned();
+
+        let file_index = self.files.len();
+        self.files.push(name.to_string());
+        self.stack.push(name.to_string());
+
+        // defines go after `#version`, which must come first
+        let is_main = file_index == 0;
+        let has_version = is_main
+            && text
+                .lines()
+                .next()
+                .map_or(false, |line| line.trim_start().starts_with("#version"));
+        if is_main && !has_version {
+            self.inject_defines();
+        }
+        if !has_version {
+            self.line_directive(1, file_index);
+        }
+
+        for (index, line) in text.lines().enumerate() {
+            let directive = line.trim_start();
+            if has_version && index == 0 {
+                self.output.push_str(line);
+                self.output.push('\n');
+                self.inject_defines();
+                self.line_directive(2, file_index);
+            } else if directive.starts_with("#include") {
+                let path = include_path(directive).ok_or_else(|| Error::InvalidInclude {
+                    name: name.into(),
+                    line: index + 1,
+                })?;
+                let resolved = self.resolve(name, path)?;
+                if self.stack.contains(&resolved) {
+                    let mut chain = self.stack.clone();
+                    chain.push(resolved);
+                    return Err(Error::IncludeCycle {
+                        name: self.files[0].clone(),
+                        chain: chain.join(" -> "),
+                    });
+                }
+                if !self.files.contains(&resolved) {
+                    self.expand(&resolved)?;
+                }
+                self.line_directive(index + 2, file_index);
+            } else {
+                self.output.push_str(line);
+                self.output.push('\n');
+            }
+        }
+
+        self.stack.pop();
+        Ok(())
+    }
+
+    /// Path next to the including file if it exists, otherwise under the include root.
+    fn resolve(&self, including: &str, path: &str) -> Result<String, Error> {
+        let including: &ResourcePath = including.as_ref();
+        let relative = match including.parent() {
+            Some(dir) => dir.join(path).to_string(),
+            None => path.to_string(),
+        };
+        match self.res.load_bytes(&relative) {
+            Ok(_) => Ok(relative),
+            Err(ref e) if e.is_not_found() => {
+                let root: &ResourcePath = INCLUDE_ROOT.as_ref();
+                Ok(root.join(path).to_string())
+            }
+            Err(e) => Err(Error::ResourceLoad {
+                name: relative,
+                inner: e,
+            }),
+        }
+    }
+
+    fn inject_defines(&mut self) {
+        for &(name, value) in self.defines {
+            self.output
+                .push_str(&format!("#define {} {}\n", name, value));
+        }
+    }
+
+    fn line_directive(&mut self, line: usize, file_index: usize) {
+        self.output
+            .push_str(&format!("#line {} {}\n", line, file_index));
+    }
+}
+
+/// Path in quotes after `#include`.
+fn include_path(directive: &str) -> Option<&str> {
+    let rest = directive["#include".len()..].trim();
+    if rest.len() >= 2 && rest.starts_with('"') && rest.ends_with('"') {
+        Some(&rest[1..rest.len() - 1])
+    } else {
+        None
+    }
+}
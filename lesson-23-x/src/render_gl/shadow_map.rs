@@ -0,0 +1,465 @@
+use failure;
+use gl;
+use nalgebra as na;
+use crate::render_gl::{
+    FramebufferBinding, FramebufferError, Material, Program, ProgramPermutations, StateCache,
+    Uniform, DEFORM_FEATURES,
+};
+use crate::resources::Resources;
+use std::rc::Rc;
+
+/// Length of the `LightSpace` array in lit shaders.
+pub const MAX_SHADOW_CASCADES: usize = 4;
+
+/// Weight of logarithmic over uniform split distances, higher gives more detail nearby.
+const SPLIT_LAMBDA: f32 = 0.75;
+
+/// Extra depth behind each cascade, so that casters outside the view still cast into it.
+const CASTER_DISTANCE: f32 = 40.0;
+
+/// Depth texture array with one layer per cascade.
+struct DepthArray {
+    gl: gl::Gl,
+    obj: gl::types::GLuint,
+}
+
+impl DepthArray {
+    fn new(gl: &gl::Gl, size: i32, layers: usize) -> DepthArray {
+        let mut obj: gl::types::GLuint = 0;
+        unsafe {
+            gl.GenTextures(1, &mut obj);
+            StateCache::bind_texture(gl, gl::TEXTURE_2D_ARRAY, obj);
+            gl.TexParameteri(gl::TEXTURE_2D_ARRAY, gl::TEXTURE_MIN_FILTER, gl::LINEAR as i32);
+            gl.TexParameteri(gl::TEXTURE_2D_ARRAY, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
+            gl.TexParameteri(
+                gl::TEXTURE_2D_ARRAY,
+                gl::TEXTURE_WRAP_S,
+                gl::CLAMP_TO_EDGE as i32,
+            );
+            gl.TexParameteri(
+                gl::TEXTURE_2D_ARRAY,
+                gl::TEXTURE_WRAP_T,
+                gl::CLAMP_TO_EDGE as i32,
+            );
+            gl.TexParameteri(gl::TEXTURE_2D_ARRAY, gl::TEXTURE_MAX_LEVEL, 0);
+            StateCache::bind_texture(gl, gl::TEXTURE_2D_ARRAY, 0);
+        }
+
+        let array = DepthArray { gl: gl.clone(), obj };
+        array.allocate(size, layers);
+        array
+    }
+
+    /// Reallocate storage, discarding the contents.
+    fn allocate(&self, size: i32, layers: usize) {
+        unsafe {
+            StateCache::bind_texture(&self.gl, gl::TEXTURE_2D_ARRAY, self.obj);
+            self.gl.TexImage3D(
+                gl::TEXTURE_2D_ARRAY,
+                0,
+                gl::DEPTH_COMPONENT24 as gl::types::GLint,
+                size,
+                size,
+                layers as i32,
+                0,
+                gl::DEPTH_COMPONENT,
+                gl::FLOAT,
+                ::std::ptr::null(),
+            );
+            StateCache::bind_texture(&self.gl, gl::TEXTURE_2D_ARRAY, 0);
+        }
+    }
+
+    fn bind_at(&self, unit: u32) {
+        StateCache::bind_texture_at(&self.gl, unit, gl::TEXTURE_2D_ARRAY, self.obj);
+    }
+}
+
+impl Drop for DepthArray {
+    fn drop(&mut self) {
+        StateCache::texture_deleted(self.obj);
+        unsafe { self.gl.DeleteTextures(1, &self.obj) };
+    }
+}
+
+/// Shadow uniforms of a lit program, set with `ShadowMap::bind`.
+#[derive(Copy, Clone)]
+pub struct ShadowUniforms {
+    shadow_map: Uniform<i32>,
+    light_space: Uniform<na::Matrix4<f32>>,
+    cascade_count: Uniform<i32>,
+    show_cascades: Uniform<bool>,
+}
+
+impl ShadowUniforms {
+    pub fn new(program: &Program) -> ShadowUniforms {
+        ShadowUniforms {
+            shadow_map: program.uniform("ShadowMap"),
+            light_space: program.uniform("LightSpace"),
+            cascade_count: program.uniform("CascadeCount"),
+            show_cascades: program.uniform("ShowCascades"),
+        }
+    }
+}
+
+/// Cascaded depth target for a single directional light.
+///
+/// Each cascade covers a slice of the camera view, nearest first, and is rendered into its
+/// own layer of a depth texture array. Render shadow casters once per cascade between `begin`
+/// and the drop of returned `ShadowPass`. Lit shaders pick the first cascade containing the
+/// fragment and blend into the next one near its edge.
+pub struct ShadowMap {
+    gl: gl::Gl,
+    fbo: gl::types::GLuint,
+    depth: DepthArray,
+    size: i32,
+    program: Program,
+    program_light_space_location: Option<i32>,
+    program_model_location: Option<i32>,
+    /// Depth program variants for meshes moved in the vertex shader.
+    deformed_programs: ProgramPermutations,
+    instanced_program: Program,
+    instanced_program_light_space_location: Option<i32>,
+    light_direction: na::Vector3<f32>,
+    light_space_matrices: Vec<na::Matrix4<f32>>,
+    /// Tint lit surfaces by cascade.
+    pub show_cascades: bool,
+}
+
+impl ShadowMap {
+    /// Square `size` texels per cascade, `cascades` up to `MAX_SHADOW_CASCADES`.
+    pub fn new(
+        gl: &gl::Gl,
+        res: &Resources,
+        size: i32,
+        cascades: usize,
+    ) -> Result<ShadowMap, failure::Error> {
+        let cascades = cascades.max(1).min(MAX_SHADOW_CASCADES);
+        if size <= 0 {
+            return Err(FramebufferError::InvalidSize {
+                width: size,
+                height: size,
+            }.into());
+        }
+
+        let program = Program::from_res(gl, res, "shaders/render_gl/shadow_depth")?;
+        let program_light_space_location = program.get_uniform_location("LightSpace");
+        let program_model_location = program.get_uniform_location("Model");
+        let deformed_programs =
+            ProgramPermutations::deformed(gl, res, "shaders/render_gl/shadow_depth");
+        // errors show on creation rather than on the first skinned draw
+        deformed_programs.variant(&DEFORM_FEATURES)?;
+
+        let instanced_program = Program::from_res_files(
+            gl,
+            res,
+            &[
+                "shaders/render_gl/shadow_depth_instanced.vert",
+                "shaders/render_gl/shadow_depth.frag",
+            ],
+        )?;
+        let instanced_program_light_space_location =
+            instanced_program.get_uniform_location("LightSpace");
+
+        let mut fbo: gl::types::GLuint = 0;
+        unsafe {
+            gl.GenFramebuffers(1, &mut fbo);
+        }
+
+        Ok(ShadowMap {
+            gl: gl.clone(),
+            fbo,
+            depth: DepthArray::new(gl, size, cascades),
+            size,
+            program,
+            program_light_space_location,
+            program_model_location,
+            deformed_programs,
+            instanced_program,
+            instanced_program_light_space_location,
+            light_direction: -na::Vector3::z(),
+            light_space_matrices: vec![na::Matrix4::identity(); cascades],
+            show_cascades: false,
+        })
+    }
+
+    /// Width and height of a cascade in texels.
+    pub fn size(&self) -> i32 {
+        self.size
+    }
+
+    /// Reallocate the depth target, i.e. when shadow quality changes.
+    pub fn set_size(&mut self, size: i32) -> Result<(), FramebufferError> {
+        if size <= 0 {
+            return Err(FramebufferError::InvalidSize {
+                width: size,
+                height: size,
+            });
+        }
+        if size != self.size {
+            self.size = size;
+            self.depth.allocate(size, self.cascade_count());
+        }
+        Ok(())
+    }
+
+    pub fn cascade_count(&self) -> usize {
+        self.light_space_matrices.len()
+    }
+
+    /// Point directional light at the target, all cascades covering a cube of `radius`
+    /// around it. Call `fit_to_view` afterwards to split the view between cascades.
+    pub fn set_directional_light(
+        &mut self,
+        direction: &na::Vector3<f32>,
+        target: &na::Point3<f32>,
+        radius: f32,
+    ) {
+        let direction = direction.normalize();
+        self.light_direction = direction;
+
+        let eye = target - direction * radius * 2.0;
+        let view = na::Matrix4::look_at_rh(&eye, target, &up(&direction));
+        let projection =
+            na::Matrix4::new_orthographic(-radius, radius, -radius, radius, radius, radius * 3.0);
+
+        for matrix in &mut self.light_space_matrices {
+            *matrix = projection * view;
+        }
+    }
+
+    /// Split the camera view up to `distance` into cascades and fit a light box around each.
+    ///
+    /// Boxes are sized by the bounding sphere of the slice and snapped to whole texels,
+    /// so that shadow edges do not shimmer when the camera moves or turns.
+    pub fn fit_to_view(
+        &mut self,
+        view: &na::Matrix4<f32>,
+        projection: &na::Matrix4<f32>,
+        distance: f32,
+    ) {
+        let (inverse_view, inverse_projection) =
+            match (view.try_inverse(), projection.try_inverse()) {
+                (Some(inverse_view), Some(inverse_projection)) => {
+                    (inverse_view, inverse_projection)
+                }
+                _ => return,
+            };
+
+        // view space corners of the near plane, scaled along their rays for other distances
+        let near_corners = [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)]
+            .iter()
+            .map(|&(x, y)| inverse_projection.transform_point(&na::Point3::new(x, y, -1.0)))
+            .collect::<Vec<_>>();
+        let near = -near_corners[0].z;
+        let far = (projection[(2, 3)] / (projection[(2, 2)] + 1.0)).min(distance);
+        if near <= 0.0 || far <= near {
+            return;
+        }
+
+        let count = self.cascade_count();
+        let mut slice_near = near;
+        for cascade in 0..count {
+            let t = (cascade + 1) as f32 / count as f32;
+            let slice_far = SPLIT_LAMBDA * near * (far / near).powf(t)
+                + (1.0 - SPLIT_LAMBDA) * (near + (far - near) * t);
+
+            let corners = [slice_near, slice_far]
+                .iter()
+                .flat_map(|&d| near_corners.iter().map(move |c| c.coords * (d / near)))
+                .map(|v| inverse_view.transform_point(&na::Point3::from(v)))
+                .collect::<Vec<_>>();
+            self.light_space_matrices[cascade] = self.fit_cascade(&corners);
+
+            slice_near = slice_far;
+        }
+    }
+
+    fn fit_cascade(&self, corners: &[na::Point3<f32>]) -> na::Matrix4<f32> {
+        let center = corners
+            .iter()
+            .fold(na::Vector3::zeros(), |sum, corner| sum + corner.coords)
+            / corners.len() as f32;
+        let center = na::Point3::from(center);
+        let radius = corners
+            .iter()
+            .map(|corner| na::distance(corner, &center))
+            .fold(0.0f32, f32::max);
+        let radius = (radius * 16.0).ceil() / 16.0;
+
+        let direction = self.light_direction;
+        let eye = center - direction * (radius + CASTER_DISTANCE);
+        let view = na::Matrix4::look_at_rh(&eye, &center, &up(&direction));
+        let mut projection = na::Matrix4::new_orthographic(
+            -radius,
+            radius,
+            -radius,
+            radius,
+            0.0,
+            radius * 2.0 + CASTER_DISTANCE,
+        );
+
+        // move the box in whole texels
+        let half_size = self.size as f32 * 0.5;
+        let origin = (projection * view).transform_point(&na::Point3::origin()) * half_size;
+        projection[(0, 3)] += (origin.x.round() - origin.x) / half_size;
+        projection[(1, 3)] += (origin.y.round() - origin.y) / half_size;
+
+        projection * view
+    }
+
+    /// Normalized direction the light travels in.
+    pub fn light_direction(&self) -> &na::Vector3<f32> {
+        &self.light_direction
+    }
+
+    /// View-projection of each cascade, nearest first.
+    pub fn light_space_matrices(&self) -> &[na::Matrix4<f32>] {
+        &self.light_space_matrices
+    }
+
+    /// Bind depth array and set shadow uniforms, the program must be in use.
+    pub fn bind(&self, program: &Program, uniforms: &ShadowUniforms, unit: u32) {
+        if uniforms.shadow_map.is_active() {
+            self.depth.bind_at(unit);
+            uniforms.shadow_map.set(program, &(unit as i32));
+        }
+        uniforms
+            .light_space
+            .set_array(program, &self.light_space_matrices);
+        uniforms
+            .cascade_count
+            .set(program, &(self.cascade_count() as i32));
+        uniforms.show_cascades.set(program, &self.show_cascades);
+    }
+
+    /// Same as `bind`, for a material in use.
+    pub fn bind_material(&self, material: &Material) {
+        if let Some(unit) = material.texture_unit("ShadowMap") {
+            self.depth.bind_at(unit);
+            material.set("ShadowMap", &(unit as i32));
+        }
+        material.set_array("LightSpace", &self.light_space_matrices);
+        material.set("CascadeCount", &(self.cascade_count() as i32));
+        material.set("ShowCascades", &self.show_cascades);
+    }
+
+    /// Bind depth framebuffer with the cascade layer attached and clear it.
+    pub fn begin(&self, cascade: usize) -> ShadowPass {
+        let binding = FramebufferBinding::new(&self.gl, self.fbo, self.size, self.size);
+
+        unsafe {
+            self.gl.FramebufferTextureLayer(
+                gl::FRAMEBUFFER,
+                gl::DEPTH_ATTACHMENT,
+                self.depth.obj,
+                0,
+                cascade as i32,
+            );
+            self.gl.DrawBuffer(gl::NONE);
+            self.gl.ReadBuffer(gl::NONE);
+            self.gl.Clear(gl::DEPTH_BUFFER_BIT);
+            self.gl.Enable(gl::POLYGON_OFFSET_FILL);
+            self.gl.PolygonOffset(2.0, 4.0);
+        }
+
+        ShadowPass {
+            shadow_map: self,
+            light_space_matrix: self.light_space_matrices[cascade],
+            _binding: binding,
+        }
+    }
+}
+
+impl Drop for ShadowMap {
+    fn drop(&mut self) {
+        unsafe {
+            self.gl.DeleteFramebuffers(1, &self.fbo);
+        }
+    }
+}
+
+fn up(direction: &na::Vector3<f32>) -> na::Vector3<f32> {
+    if direction.z.abs() > 0.99 {
+        na::Vector3::y()
+    } else {
+        na::Vector3::z()
+    }
+}
+
+/// Depth-only pass into one cascade of the shadow map, active while this value is alive.
+pub struct ShadowPass<'a> {
+    shadow_map: &'a ShadowMap,
+    light_space_matrix: na::Matrix4<f32>,
+    _binding: FramebufferBinding<'a>,
+}
+
+impl<'a> ShadowPass<'a> {
+    /// View-projection of the cascade, for entities drawing depth with their own program.
+    pub fn light_space_matrix(&self) -> &na::Matrix4<f32> {
+        &self.light_space_matrix
+    }
+
+    /// Use depth program for the mesh with the model matrix.
+    pub fn prepare_model(&self, model_matrix: &na::Matrix4<f32>) {
+        let shadow_map = self.shadow_map;
+        shadow_map.program.set_used();
+
+        if let Some(loc) = shadow_map.program_light_space_location {
+            shadow_map
+                .program
+                .set_uniform_matrix_4fv(loc, &self.light_space_matrix);
+        }
+        if let Some(loc) = shadow_map.program_model_location {
+            shadow_map.program.set_uniform_matrix_4fv(loc, model_matrix);
+        }
+    }
+
+    /// Use the depth program variant for a mesh moved in the vertex shader, i.e. `SKINNED`
+    /// with the bone matrices of its pose bound. Morph targets are bound to the returned
+    /// variant. Without features, or if the variant failed to link, the mesh is drawn as
+    /// modelled and `None` is returned.
+    pub fn prepare_deformed_model(
+        &self,
+        model_matrix: &na::Matrix4<f32>,
+        features: &[&str],
+    ) -> Option<Rc<Material>> {
+        let variant = if features.is_empty() {
+            None
+        } else {
+            let programs = &self.shadow_map.deformed_programs;
+            programs.variant(features).ok().and_then(|variant| variant)
+        };
+        let variant = match variant {
+            Some(variant) => variant,
+            None => {
+                self.prepare_model(model_matrix);
+                return None;
+            }
+        };
+        variant.set_used();
+        variant.set("LightSpace", &self.light_space_matrix);
+        variant.set("Model", model_matrix);
+        Some(variant)
+    }
+
+    /// Use depth program for meshes with per-instance model matrix at locations 4-7.
+    pub fn prepare_instanced(&self) {
+        let shadow_map = self.shadow_map;
+        shadow_map.instanced_program.set_used();
+
+        if let Some(loc) = shadow_map.instanced_program_light_space_location {
+            shadow_map
+                .instanced_program
+                .set_uniform_matrix_4fv(loc, &self.light_space_matrix);
+        }
+    }
+}
+
+impl<'a> Drop for ShadowPass<'a> {
+    fn drop(&mut self) {
+        unsafe {
+            self.shadow_map.gl.Disable(gl::POLYGON_OFFSET_FILL);
+        }
+    }
+}
@@ -0,0 +1,204 @@
+use failure;
+use gl;
+use image;
+use nalgebra as na;
+use crate::render;
+use crate::render_gl::{
+    ColorFormat, ColorSpace, Filter, Framebuffer, FramebufferError, FullscreenTriangle, Program,
+    Sampler, StateCache, Texture, Uniform,
+};
+use crate::resources::Resources;
+
+/// Must match the `Kernel` array length in `ssao.frag`.
+const KERNEL_SIZE: usize = 16;
+
+/// Noise texture is tiled over the screen, blur removes the pattern of the same size.
+const NOISE_SIZE: u32 = 4;
+
+/// Screen-space ambient occlusion from G-buffer normals and world positions.
+///
+/// Samples in a hemisphere around each normal are projected back to the screen and
+/// compared with the surface seen there. The result is blurred into `ao_texture`,
+/// where 1 is unoccluded.
+pub struct Ssao {
+    gl: gl::Gl,
+    ao_target: Framebuffer,
+    blur_target: Framebuffer,
+    program: Program,
+    program_normal: Uniform<i32>,
+    program_position: Uniform<i32>,
+    program_noise: Uniform<i32>,
+    program_noise_scale: Uniform<na::Vector2<f32>>,
+    program_radius: Uniform<f32>,
+    program_bias: Uniform<f32>,
+    blur_program: Program,
+    blur_input: Uniform<i32>,
+    blur_intensity: Uniform<f32>,
+    noise: Texture,
+    noise_sampler: Sampler,
+    fullscreen: FullscreenTriangle,
+    pub enabled: bool,
+    /// Sample hemisphere radius in world units.
+    pub radius: f32,
+    /// Depth difference ignored, against self-occlusion of flat surfaces.
+    pub bias: f32,
+    /// Exponent applied to the result, higher darkens occluded parts more.
+    pub intensity: f32,
+}
+
+impl Ssao {
+    pub fn new(
+        gl: &gl::Gl,
+        res: &Resources,
+        width: i32,
+        height: i32,
+    ) -> Result<Ssao, failure::Error> {
+        let load = |frag: &str| {
+            Program::from_res_files(gl, res, &["shaders/render_gl/fullscreen.vert", frag])
+        };
+        let program = load("shaders/render_gl/ssao.frag")?;
+        program.bind_uniform_block("Camera", render::CAMERA_UNIFORMS_BINDING);
+        program.set_used();
+        program
+            .uniform::<na::Vector3<f32>>("Kernel")
+            .set_array(&program, &kernel());
+        let blur_program = load("shaders/render_gl/ssao_blur.frag")?;
+
+        let noise = Texture::from_rgb_image(gl, &noise_image(), ColorSpace::Linear, false);
+        noise.set_label("ssao noise");
+
+        Ok(Ssao {
+            gl: gl.clone(),
+            ao_target: Ssao::target(gl, width, height)?,
+            blur_target: Ssao::target(gl, width, height)?,
+            program_normal: program.uniform("Normal"),
+            program_position: program.uniform("Position"),
+            program_noise: program.uniform("Noise"),
+            program_noise_scale: program.uniform("NoiseScale"),
+            program_radius: program.uniform("Radius"),
+            program_bias: program.uniform("Bias"),
+            program,
+            blur_input: blur_program.uniform("Input"),
+            blur_intensity: blur_program.uniform("Intensity"),
+            blur_program,
+            noise,
+            noise_sampler: Sampler::builder()
+                .with_filter(Filter::Nearest, Filter::Nearest)
+                .with_mipmap_filter(None)
+                .build(gl),
+            fullscreen: FullscreenTriangle::new(gl),
+            enabled: true,
+            radius: 0.5,
+            bias: 0.025,
+            intensity: 1.5,
+        })
+    }
+
+    pub fn toggle(&mut self) {
+        self.enabled = !self.enabled;
+    }
+
+    pub fn resize(&mut self, width: i32, height: i32) -> Result<(), FramebufferError> {
+        self.ao_target.resize(width, height)?;
+        self.blur_target.resize(width, height)
+    }
+
+    /// Blurred occlusion, valid after `render`.
+    pub fn ao_texture(&self) -> &Texture {
+        self.blur_target
+            .color_texture(0)
+            .expect("ssao blur target has a color attachment")
+    }
+
+    /// Compute occlusion for the G-buffer, using the camera from the uniform buffer.
+    pub fn render(&self, normal: &Texture, position: &Texture) {
+        let gl = &self.gl;
+        StateCache::set_depth_test(gl, false);
+
+        {
+            let _binding = self.ao_target.bind();
+            let program = &self.program;
+            program.set_used();
+            normal.bind_at(0);
+            self.program_normal.set(program, &0);
+            position.bind_at(1);
+            self.program_position.set(program, &1);
+            self.noise.bind_at(2);
+            self.noise_sampler.bind_at(2);
+            self.program_noise.set(program, &2);
+            self.program_noise_scale.set(
+                program,
+                &na::Vector2::new(
+                    self.ao_target.width() as f32 / NOISE_SIZE as f32,
+                    self.ao_target.height() as f32 / NOISE_SIZE as f32,
+                ),
+            );
+            self.program_radius.set(program, &self.radius);
+            self.program_bias.set(program, &self.bias);
+            self.fullscreen.draw(gl);
+            Sampler::unbind_at(gl, 2);
+        }
+
+        {
+            let _binding = self.blur_target.bind();
+            self.blur_program.set_used();
+            self.ao_target
+                .color_texture(0)
+                .expect("ssao target has a color attachment")
+                .bind_at(0);
+            self.blur_input.set(&self.blur_program, &0);
+            self.blur_intensity
+                .set(&self.blur_program, &self.intensity);
+            self.fullscreen.draw(gl);
+        }
+
+        StateCache::set_depth_test(gl, true);
+    }
+
+    fn target(gl: &gl::Gl, width: i32, height: i32) -> Result<Framebuffer, FramebufferError> {
+        Framebuffer::builder(width, height)
+            .with_color(ColorFormat::R8)
+            .build(gl)
+    }
+}
+
+/// Small deterministic generator, the kernel only needs to look random.
+struct XorShift(u32);
+
+impl XorShift {
+    /// Next value from 0 to 1.
+    fn next(&mut self) -> f32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 17;
+        self.0 ^= self.0 << 5;
+        (self.0 % 10_000) as f32 / 10_000.0
+    }
+}
+
+/// Points in the hemisphere around +Z, more of them close to the center.
+fn kernel() -> Vec<na::Vector3<f32>> {
+    let mut random = XorShift(0x2545_f491);
+    (0..KERNEL_SIZE)
+        .map(|index| {
+            let direction = na::Vector3::new(
+                random.next() * 2.0 - 1.0,
+                random.next() * 2.0 - 1.0,
+                random.next(),
+            ).try_normalize(::std::f32::EPSILON)
+                .unwrap_or_else(na::Vector3::z);
+            let t = index as f32 / KERNEL_SIZE as f32;
+            direction * random.next() * (0.1 + 0.9 * t * t)
+        }).collect()
+}
+
+/// Random rotations around the normal, XY from 0 to 1.
+fn noise_image() -> image::RgbImage {
+    let mut random = XorShift(0x9e37_79b9);
+    image::RgbImage::from_fn(NOISE_SIZE, NOISE_SIZE, |_, _| {
+        image::Rgb([
+            (random.next() * 255.0) as u8,
+            (random.next() * 255.0) as u8,
+            0,
+        ])
+    })
+}
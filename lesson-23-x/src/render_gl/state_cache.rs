@@ -0,0 +1,271 @@
+use gl;
+use crate::render_gl::FrameStats;
+use std::cell::RefCell;
+
+/// Texture units tracked by the cache, binds on higher units always reach the driver.
+const MAX_TEXTURE_UNITS: usize = 32;
+
+/// Last GL state set through the wrappers, used to skip binds and toggles that would
+/// not change anything.
+///
+/// There is one cache per thread, as the lessons use a single context on the main thread.
+/// Code that changes the tracked state with raw GL calls must call `invalidate` afterwards.
+#[derive(Default)]
+pub struct StateCache {
+    program: Option<gl::types::GLuint>,
+    vertex_array: Option<gl::types::GLuint>,
+    active_texture: Option<u32>,
+    textures: [Option<(gl::types::GLenum, gl::types::GLuint)>; MAX_TEXTURE_UNITS],
+    samplers: [Option<gl::types::GLuint>; MAX_TEXTURE_UNITS],
+    blend: Option<bool>,
+    blend_func: Option<(gl::types::GLenum, gl::types::GLenum)>,
+    depth_test: Option<bool>,
+    depth_mask: Option<bool>,
+    cull_face: Option<bool>,
+    cull_mode: Option<gl::types::GLenum>,
+    /// Winding passed to `glFrontFace`, after flipping for `mirrored`.
+    front_face: Option<gl::types::GLenum>,
+    /// Winding last asked for with `set_front_face`, `None` for the GL default.
+    requested_front_face: Option<gl::types::GLenum>,
+    /// Drawing through a mirror matrix, which turns the winding of all triangles around.
+    mirrored: bool,
+    elided: usize,
+}
+
+thread_local! {
+    static STATE_CACHE: RefCell<StateCache> = RefCell::new(StateCache::default());
+}
+
+/// Update the cached value, returns true if it changed and GL has to be called.
+fn replace<T: PartialEq>(cached: &mut Option<T>, value: T, elided: &mut usize) -> bool {
+    if cached.as_ref() == Some(&value) {
+        *elided += 1;
+        false
+    } else {
+        *cached = Some(value);
+        true
+    }
+}
+
+impl StateCache {
+    fn with<R, F: FnOnce(&mut StateCache) -> R>(f: F) -> R {
+        STATE_CACHE.with(|cache| f(&mut cache.borrow_mut()))
+    }
+
+    /// Forget all state, i.e. after raw GL calls or when the context was recreated.
+    pub fn invalidate() {
+        StateCache::with(|cache| {
+            let elided = cache.elided;
+            let mirrored = cache.mirrored;
+            *cache = StateCache::default();
+            cache.elided = elided;
+            cache.mirrored = mirrored;
+        });
+    }
+
+    /// Number of skipped GL calls since the last call, for profiling.
+    pub fn take_elided_count() -> usize {
+        StateCache::with(|cache| ::std::mem::replace(&mut cache.elided, 0))
+    }
+
+    pub fn use_program(gl: &gl::Gl, program: gl::types::GLuint) {
+        if StateCache::with(|cache| replace(&mut cache.program, program, &mut cache.elided)) {
+            unsafe {
+                gl.UseProgram(program);
+            }
+        }
+    }
+
+    pub fn bind_vertex_array(gl: &gl::Gl, vertex_array: gl::types::GLuint) {
+        let changed = StateCache::with(|cache| {
+            replace(&mut cache.vertex_array, vertex_array, &mut cache.elided)
+        });
+        if changed {
+            unsafe {
+                gl.BindVertexArray(vertex_array);
+            }
+        }
+    }
+
+    /// Bind texture to the given unit, switching the active unit if needed.
+    pub fn bind_texture_at(
+        gl: &gl::Gl,
+        unit: u32,
+        target: gl::types::GLenum,
+        texture: gl::types::GLuint,
+    ) {
+        StateCache::set_active_texture(gl, unit);
+        StateCache::bind_texture(gl, target, texture);
+    }
+
+    /// Bind texture to the active unit, i.e. to upload its contents.
+    pub fn bind_texture(gl: &gl::Gl, target: gl::types::GLenum, texture: gl::types::GLuint) {
+        let changed = StateCache::with(|cache| match cache.active_texture {
+            Some(unit) if (unit as usize) < MAX_TEXTURE_UNITS => {
+                let elided = &mut cache.elided;
+                replace(&mut cache.textures[unit as usize], (target, texture), elided)
+            }
+            _ => true,
+        });
+        if changed {
+            unsafe {
+                gl.BindTexture(target, texture);
+            }
+            FrameStats::record_texture_bind();
+        }
+    }
+
+    /// Bind sampler object to the unit, zero goes back to texture parameters.
+    pub fn bind_sampler(gl: &gl::Gl, unit: u32, sampler: gl::types::GLuint) {
+        let changed = StateCache::with(|cache| match cache.samplers.get_mut(unit as usize) {
+            Some(cached) => replace(cached, sampler, &mut cache.elided),
+            None => true,
+        });
+        if changed {
+            unsafe {
+                gl.BindSampler(unit, sampler);
+            }
+        }
+    }
+
+    pub fn set_active_texture(gl: &gl::Gl, unit: u32) {
+        if StateCache::with(|cache| replace(&mut cache.active_texture, unit, &mut cache.elided)) {
+            unsafe {
+                gl.ActiveTexture(gl::TEXTURE0 + unit);
+            }
+        }
+    }
+
+    pub fn set_blend(gl: &gl::Gl, enabled: bool) {
+        if StateCache::with(|cache| replace(&mut cache.blend, enabled, &mut cache.elided)) {
+            set_capability(gl, gl::BLEND, enabled);
+        }
+    }
+
+    pub fn set_blend_func(gl: &gl::Gl, src: gl::types::GLenum, dst: gl::types::GLenum) {
+        let changed =
+            StateCache::with(|cache| replace(&mut cache.blend_func, (src, dst), &mut cache.elided));
+        if changed {
+            unsafe {
+                gl.BlendFunc(src, dst);
+            }
+        }
+    }
+
+    pub fn set_depth_test(gl: &gl::Gl, enabled: bool) {
+        if StateCache::with(|cache| replace(&mut cache.depth_test, enabled, &mut cache.elided)) {
+            set_capability(gl, gl::DEPTH_TEST, enabled);
+        }
+    }
+
+    pub fn set_depth_mask(gl: &gl::Gl, enabled: bool) {
+        if StateCache::with(|cache| replace(&mut cache.depth_mask, enabled, &mut cache.elided)) {
+            unsafe {
+                gl.DepthMask(if enabled { gl::TRUE } else { gl::FALSE });
+            }
+        }
+    }
+
+    pub fn set_cull_face(gl: &gl::Gl, enabled: bool) {
+        if StateCache::with(|cache| replace(&mut cache.cull_face, enabled, &mut cache.elided)) {
+            set_capability(gl, gl::CULL_FACE, enabled);
+        }
+    }
+
+    /// Faces culled while culling is enabled, `gl::BACK` or `gl::FRONT`.
+    pub fn set_cull_mode(gl: &gl::Gl, face: gl::types::GLenum) {
+        if StateCache::with(|cache| replace(&mut cache.cull_mode, face, &mut cache.elided)) {
+            unsafe {
+                gl.CullFace(face);
+            }
+        }
+    }
+
+    /// Winding of front faces, `gl::CCW` or `gl::CW`, turned around while `mirrored`.
+    pub fn set_front_face(gl: &gl::Gl, winding: gl::types::GLenum) {
+        let winding = StateCache::with(|cache| {
+            cache.requested_front_face = Some(winding);
+            cache.mirrored_winding(winding)
+        });
+        StateCache::apply_front_face(gl, winding);
+    }
+
+    /// Flip the winding set with `set_front_face` while drawing through a mirror matrix,
+    /// i.e. planar reflections, so that materials keep culling the same side.
+    pub fn set_mirrored(gl: &gl::Gl, mirrored: bool) {
+        let winding = StateCache::with(|cache| {
+            cache.mirrored = mirrored;
+            cache.mirrored_winding(cache.requested_front_face.unwrap_or(gl::CCW))
+        });
+        StateCache::apply_front_face(gl, winding);
+    }
+
+    fn mirrored_winding(&self, winding: gl::types::GLenum) -> gl::types::GLenum {
+        match (self.mirrored, winding) {
+            (true, gl::CCW) => gl::CW,
+            (true, _) => gl::CCW,
+            (false, winding) => winding,
+        }
+    }
+
+    fn apply_front_face(gl: &gl::Gl, winding: gl::types::GLenum) {
+        if StateCache::with(|cache| replace(&mut cache.front_face, winding, &mut cache.elided)) {
+            unsafe {
+                gl.FrontFace(winding);
+            }
+        }
+    }
+
+    /// Deleting a program keeps it in use until another one is used,
+    /// but its name may be reused by a new program.
+    pub fn program_deleted(program: gl::types::GLuint) {
+        StateCache::with(|cache| {
+            if cache.program == Some(program) {
+                cache.program = None;
+            }
+        });
+    }
+
+    /// Deleting a bound vertex array binds zero.
+    pub fn vertex_array_deleted(vertex_array: gl::types::GLuint) {
+        StateCache::with(|cache| {
+            if cache.vertex_array == Some(vertex_array) {
+                cache.vertex_array = Some(0);
+            }
+        });
+    }
+
+    /// Deleting a sampler unbinds it from all units.
+    pub fn sampler_deleted(sampler: gl::types::GLuint) {
+        StateCache::with(|cache| {
+            for binding in cache.samplers.iter_mut() {
+                if *binding == Some(sampler) {
+                    *binding = Some(0);
+                }
+            }
+        });
+    }
+
+    /// Deleting a texture unbinds it from all units.
+    pub fn texture_deleted(texture: gl::types::GLuint) {
+        StateCache::with(|cache| {
+            for binding in cache.textures.iter_mut() {
+                if let Some((target, bound)) = *binding {
+                    if bound == texture {
+                        *binding = Some((target, 0));
+                    }
+                }
+            }
+        });
+    }
+}
+
+fn set_capability(gl: &gl::Gl, capability: gl::types::GLenum, enabled: bool) {
+    unsafe {
+        if enabled {
+            gl.Enable(capability);
+        } else {
+            gl.Disable(capability);
+        }
+    }
+}
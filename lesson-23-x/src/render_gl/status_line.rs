@@ -0,0 +1,74 @@
+use nalgebra as na;
+use crate::render_gl::DebugText;
+
+/// Seconds a message stays on screen.
+const SHOW_SECONDS: f32 = 4.0;
+
+/// Single line text overlay with the outcome of the last action, i.e. a saved scene.
+///
+/// A message replaces the one before it and disappears after a few seconds. Errors are
+/// drawn in magenta, as in `ErrorConsole`.
+pub struct StatusLine {
+    message: Option<Message>,
+}
+
+struct Message {
+    text: String,
+    error: bool,
+    /// Seconds left on screen.
+    remaining: f32,
+}
+
+impl StatusLine {
+    pub fn new() -> StatusLine {
+        StatusLine { message: None }
+    }
+
+    pub fn show<S: Into<String>>(&mut self, text: S) {
+        self.set(text.into(), false);
+    }
+
+    pub fn show_error<S: Into<String>>(&mut self, text: S) {
+        self.set(text.into(), true);
+    }
+
+    fn set(&mut self, text: String, error: bool) {
+        self.message = Some(Message {
+            text,
+            error,
+            remaining: SHOW_SECONDS,
+        });
+    }
+
+    /// Count down the time the message has left, `delta` in seconds.
+    pub fn update(&mut self, delta: f32) {
+        let expired = match self.message {
+            Some(ref mut message) => {
+                message.remaining -= delta;
+                message.remaining <= 0.0
+            }
+            None => false,
+        };
+        if expired {
+            self.message = None;
+        }
+    }
+
+    /// Queue the message with top left corner at (x, y).
+    pub fn draw(&self, text: &DebugText, x: f32, y: f32) {
+        if let Some(ref message) = self.message {
+            let color = if message.error {
+                na::Vector4::new(1.0, 0.0, 1.0, 1.0)
+            } else {
+                na::Vector4::new(1.0, 1.0, 1.0, 1.0)
+            };
+            text.draw_2d_colored(x, y, &message.text, color);
+        }
+    }
+}
+
+impl Default for StatusLine {
+    fn default() -> StatusLine {
+        StatusLine::new()
+    }
+}
@@ -0,0 +1,188 @@
+use gl;
+use crate::render_gl::buffer::Buffer;
+use crate::render_gl::{Capabilities, FrameStats};
+use std::marker::PhantomData;
+
+/// Regions written in turn, so that the CPU fills one while the GPU may still read the others.
+const REGIONS: usize = 3;
+
+/// Longest single wait for the GPU to release a region, in nanoseconds.
+const FENCE_TIMEOUT: u64 = 1_000_000_000;
+
+enum Mode<T> {
+    /// Buffer storage mapped once for the lifetime of the buffer.
+    Persistent { data: *mut T },
+    /// Storage reallocated with `glBufferData` before every write.
+    Orphaning,
+}
+
+/// Array buffer for vertex data rewritten often, i.e. every frame or on every change.
+///
+/// On GL 4.4 or with `GL_ARB_buffer_storage` the buffer is mapped once with
+/// `GL_MAP_PERSISTENT_BIT` and split into three regions written in turn. A fence after the
+/// draws of each region makes the next write to it wait only if the GPU is still reading it.
+/// Other contexts orphan the whole buffer before each write instead, and always write at
+/// the start.
+///
+/// Vertex attributes point at the start of the buffer, draws add `write` result to
+/// their first vertex.
+pub struct StreamBuffer<T: Copy> {
+    gl: gl::Gl,
+    buffer: Buffer,
+    capacity: usize,
+    mode: Mode<T>,
+    region: usize,
+    fences: [gl::types::GLsync; REGIONS],
+    _data: PhantomData<T>,
+}
+
+impl<T: Copy> StreamBuffer<T> {
+    /// Room for `capacity` elements in each write.
+    pub fn new(gl: &gl::Gl, capacity: usize) -> StreamBuffer<T> {
+        let capacity = capacity.max(1);
+        let buffer = Buffer::new_array(gl);
+        buffer.set_label("stream buffer");
+
+        let mode = if Capabilities::get(gl).persistent_mapping {
+            let size = (capacity * REGIONS * ::std::mem::size_of::<T>()) as gl::types::GLsizeiptr;
+            let flags = gl::MAP_WRITE_BIT | gl::MAP_PERSISTENT_BIT | gl::MAP_COHERENT_BIT;
+            buffer.bind();
+            let data = unsafe {
+                gl.BufferStorage(gl::ARRAY_BUFFER, size, ::std::ptr::null(), flags);
+                gl.MapBufferRange(gl::ARRAY_BUFFER, 0, size, flags) as *mut T
+            };
+            buffer.unbind();
+            if data.is_null() {
+                Mode::Orphaning
+            } else {
+                Mode::Persistent { data }
+            }
+        } else {
+            Mode::Orphaning
+        };
+
+        if let Mode::Orphaning = mode {
+            buffer.bind();
+            buffer.stream_draw_data_null::<T>(capacity);
+            buffer.unbind();
+        }
+
+        StreamBuffer {
+            gl: gl.clone(),
+            buffer,
+            capacity,
+            mode,
+            region: 0,
+            fences: [::std::ptr::null(); REGIONS],
+            _data: PhantomData,
+        }
+    }
+
+    /// Elements that fit into one write.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// True if the buffer is persistently mapped, false on the orphaning fallback.
+    pub fn is_persistent(&self) -> bool {
+        match self.mode {
+            Mode::Persistent { .. } => true,
+            Mode::Orphaning => false,
+        }
+    }
+
+    /// Buffer to set vertex attribute pointers with.
+    pub fn buffer(&self) -> &Buffer {
+        &self.buffer
+    }
+
+    /// Write items up to the capacity into the next region.
+    ///
+    /// Returns the index of the first written element, to be added to the first vertex
+    /// of draws reading them.
+    pub fn write(&mut self, items: impl Iterator<Item = T>) -> usize {
+        match self.mode {
+            Mode::Persistent { data } => {
+                self.region = (self.region + 1) % REGIONS;
+                self.wait_for_region();
+
+                let first = self.region * self.capacity;
+                let mut written = 0;
+                for (index, item) in items.enumerate().take(self.capacity) {
+                    unsafe {
+                        *data.add(first + index) = item;
+                    }
+                    written += 1;
+                }
+                FrameStats::record_upload(written * ::std::mem::size_of::<T>());
+                first
+            }
+            Mode::Orphaning => {
+                self.buffer.bind();
+                self.buffer.stream_draw_data_null::<T>(self.capacity);
+                if let Some(mut mapped) = unsafe {
+                    self.buffer
+                        .map_buffer_range_write_invalidate::<T>(0, self.capacity)
+                } {
+                    let mut written = 0;
+                    for item in items.take(self.capacity) {
+                        mapped.push(item);
+                        written += 1;
+                    }
+                    FrameStats::record_upload(written * ::std::mem::size_of::<T>());
+                }
+                self.buffer.unbind();
+                0
+            }
+        }
+    }
+
+    /// Mark the end of draws reading the last written region.
+    pub fn fence(&mut self) {
+        if let Mode::Orphaning = self.mode {
+            return;
+        }
+        unsafe {
+            if !self.fences[self.region].is_null() {
+                self.gl.DeleteSync(self.fences[self.region]);
+            }
+            self.fences[self.region] = self.gl.FenceSync(gl::SYNC_GPU_COMMANDS_COMPLETE, 0);
+        }
+    }
+
+    fn wait_for_region(&mut self) {
+        let fence = self.fences[self.region];
+        if fence.is_null() {
+            return;
+        }
+        unsafe {
+            loop {
+                let result =
+                    self.gl
+                        .ClientWaitSync(fence, gl::SYNC_FLUSH_COMMANDS_BIT, FENCE_TIMEOUT);
+                if result != gl::TIMEOUT_EXPIRED {
+                    break;
+                }
+            }
+            self.gl.DeleteSync(fence);
+        }
+        self.fences[self.region] = ::std::ptr::null();
+    }
+}
+
+impl<T: Copy> Drop for StreamBuffer<T> {
+    fn drop(&mut self) {
+        for fence in self.fences.iter().filter(|fence| !fence.is_null()) {
+            unsafe {
+                self.gl.DeleteSync(*fence);
+            }
+        }
+        if let Mode::Persistent { .. } = self.mode {
+            self.buffer.bind();
+            unsafe {
+                self.gl.UnmapBuffer(gl::ARRAY_BUFFER);
+            }
+            self.buffer.unbind();
+        }
+    }
+}
@@ -1,11 +1,53 @@
 use failure;
 use gl;
-use crate::resources::{ResourcePath, Resources};
+use image;
+use crate::render_gl::{debug, Capabilities, StateCache};
+use crate::resources::{CompressedFormat, CompressedImage, ResourcePath, Resources};
+use std::cell::RefCell;
 use std::os::raw;
 
+// S3TC formats are not part of core GL bindings
+const COMPRESSED_RGB_S3TC_DXT1: gl::types::GLenum = 0x83F0;
+const COMPRESSED_RGBA_S3TC_DXT1: gl::types::GLenum = 0x83F1;
+const COMPRESSED_RGBA_S3TC_DXT3: gl::types::GLenum = 0x83F2;
+const COMPRESSED_RGBA_S3TC_DXT5: gl::types::GLenum = 0x83F3;
+const COMPRESSED_SRGB_S3TC_DXT1: gl::types::GLenum = 0x8C4C;
+const COMPRESSED_SRGB_ALPHA_S3TC_DXT1: gl::types::GLenum = 0x8C4D;
+const COMPRESSED_SRGB_ALPHA_S3TC_DXT3: gl::types::GLenum = 0x8C4E;
+const COMPRESSED_SRGB_ALPHA_S3TC_DXT5: gl::types::GLenum = 0x8C4F;
+
+/// How texel values are stored. Color maps authored in image editors are sRGB and
+/// are converted to linear when sampled, data maps such as normals are linear.
+///
+/// Output gamma is applied by `ToneMapping` at the end of the post process chain.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ColorSpace {
+    Linear,
+    Srgb,
+}
+
+impl ColorSpace {
+    fn rgb_internal_format(self) -> gl::types::GLenum {
+        match self {
+            ColorSpace::Linear => gl::RGB8,
+            ColorSpace::Srgb => gl::SRGB8,
+        }
+    }
+
+    fn rgba_internal_format(self) -> gl::types::GLenum {
+        match self {
+            ColorSpace::Linear => gl::RGBA8,
+            ColorSpace::Srgb => gl::SRGB8_ALPHA8,
+        }
+    }
+}
+
 pub struct TextureLoadOptions<'a> {
     resource_name: &'a ResourcePath,
     format: gl::types::GLenum,
+    pub color_space: ColorSpace,
+    /// DDS or KTX2 version of the same image, used if GPU supports its format.
+    compressed_resource_name: Option<&'a ResourcePath>,
     pub gen_mipmaps: bool,
 }
 
@@ -14,17 +56,35 @@ impl<'a> TextureLoadOptions<'a> {
         TextureLoadOptions {
             resource_name,
             format: gl::RGB,
+            color_space: ColorSpace::Linear,
+            compressed_resource_name: None,
             gen_mipmaps: false,
         }
     }
 
+    pub fn from_res_srgb(resource_name: &ResourcePath) -> TextureLoadOptions {
+        TextureLoadOptions {
+            color_space: ColorSpace::Srgb,
+            ..TextureLoadOptions::from_res_rgb(resource_name)
+        }
+    }
+
     pub fn from_res_rgba(resource_name: &ResourcePath) -> TextureLoadOptions {
         TextureLoadOptions {
             resource_name,
             format: gl::RGBA,
+            color_space: ColorSpace::Linear,
+            compressed_resource_name: None,
             gen_mipmaps: false,
         }
     }
+
+    pub fn from_res_srgba(resource_name: &ResourcePath) -> TextureLoadOptions {
+        TextureLoadOptions {
+            color_space: ColorSpace::Srgb,
+            ..TextureLoadOptions::from_res_rgba(resource_name)
+        }
+    }
 }
 
 pub struct TextureLoadBuilder<'a> {
@@ -40,6 +100,27 @@ impl<'a> TextureLoadBuilder<'a> {
         self.options.gen_mipmaps = true;
         self
     }
+
+    pub fn with_color_space(mut self, color_space: ColorSpace) -> Self {
+        self.options.color_space = color_space;
+        self
+    }
+
+    /// Prefer DDS or KTX2 file, falling back to the decoded image if the file
+    /// can not be loaded or its format is not supported.
+    pub fn with_compressed(mut self, resource_name: &'a ResourcePath) -> Self {
+        self.options.compressed_resource_name = Some(resource_name);
+        self
+    }
+}
+
+thread_local! {
+    /// Fallbacks taken by texture loads since the last `Texture::take_notices`.
+    static NOTICES: RefCell<Vec<String>> = RefCell::new(Vec::new());
+}
+
+fn notice(text: String) {
+    NOTICES.with(|notices| notices.borrow_mut().push(text));
 }
 
 pub struct Texture {
@@ -49,11 +130,18 @@ pub struct Texture {
 
 impl Drop for Texture {
     fn drop(&mut self) {
+        StateCache::texture_deleted(self.obj);
         unsafe { self.gl.DeleteTextures(1, &mut self.obj) };
     }
 }
 
 impl Texture {
+    /// Fallbacks taken by loads on this thread since the last call, i.e. a compressed
+    /// format the driver lacks, shown by `StatsHud`.
+    pub fn take_notices() -> Vec<String> {
+        NOTICES.with(|notices| notices.replace(Vec::new()))
+    }
+
     pub fn from_res_rgb(resource_name: &ResourcePath) -> TextureLoadBuilder {
         TextureLoadBuilder {
             options: TextureLoadOptions::from_res_rgb(resource_name),
@@ -66,14 +154,105 @@ impl Texture {
         }
     }
 
+    /// Color texture stored as sRGB, sampled as linear.
+    pub fn from_res_srgb(resource_name: &ResourcePath) -> TextureLoadBuilder {
+        TextureLoadBuilder {
+            options: TextureLoadOptions::from_res_srgb(resource_name),
+        }
+    }
+
+    /// Color texture with linear alpha stored as sRGB, sampled as linear.
+    pub fn from_res_srgba(resource_name: &ResourcePath) -> TextureLoadBuilder {
+        TextureLoadBuilder {
+            options: TextureLoadOptions::from_res_srgba(resource_name),
+        }
+    }
+
     pub fn from_res<'a>(
         options: TextureLoadOptions<'a>,
         gl: &gl::Gl,
         res: &Resources,
     ) -> Result<Texture, failure::Error> {
+        if let Some(compressed_name) = options.compressed_resource_name {
+            match res.load_compressed_image(compressed_name) {
+                Ok(ref img) if compressed_format_supported(gl, img.format, img.srgb) => {
+                    let texture = Texture::from_compressed_image(gl, img);
+                    texture.set_label(&compressed_name.to_string());
+                    return Ok(texture);
+                }
+                Ok(img) => notice(format!(
+                    "Compressed format {:?} of {} is not supported, using {}",
+                    img.format, compressed_name, options.resource_name
+                )),
+                Err(e) => notice(format!(
+                    "Error loading {}: {}, using {}",
+                    compressed_name, e, options.resource_name
+                )),
+            }
+        }
+
+        let texture = Texture::new_unallocated(gl);
+        texture.set_label(&options.resource_name.to_string());
+        texture.update(options, res)?;
+
+        Ok(texture)
+    }
+
+    /// Upload block-compressed mipmap chain without decompressing it.
+    ///
+    /// Check `compressed_format_supported` first.
+    pub fn from_compressed_image(gl: &gl::Gl, img: &CompressedImage) -> Texture {
+        let texture = Texture::new_unallocated(gl);
+        let internal_format = compressed_internal_format(img.format, img.srgb);
+
+        unsafe {
+            StateCache::bind_texture(gl, gl::TEXTURE_2D, texture.obj);
+            gl.TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_BASE_LEVEL, 0);
+            gl.TexParameteri(
+                gl::TEXTURE_2D,
+                gl::TEXTURE_MAX_LEVEL,
+                img.levels.len() as i32 - 1,
+            );
+
+            for (index, level) in img.levels.iter().enumerate() {
+                gl.CompressedTexImage2D(
+                    gl::TEXTURE_2D,
+                    index as i32,
+                    internal_format,
+                    level.width as i32,
+                    level.height as i32,
+                    0,
+                    level.data.len() as i32,
+                    level.data.as_ptr() as *const raw::c_void,
+                );
+            }
+
+            StateCache::bind_texture(gl, gl::TEXTURE_2D, 0);
+        }
+
+        texture
+    }
+
+    /// Create texture with uninitialized storage, i.e. for a framebuffer attachment.
+    pub fn new_empty(
+        gl: &gl::Gl,
+        width: i32,
+        height: i32,
+        internal_format: gl::types::GLenum,
+        format: gl::types::GLenum,
+        data_type: gl::types::GLenum,
+    ) -> Texture {
         let mut obj: gl::types::GLuint = 0;
         unsafe {
             gl.GenTextures(1, &mut obj);
+            StateCache::bind_texture(gl, gl::TEXTURE_2D, obj);
+            gl.TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR as i32);
+            gl.TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
+            gl.TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32);
+            gl.TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32);
+            gl.TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_BASE_LEVEL, 0);
+            gl.TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAX_LEVEL, 0);
+            StateCache::bind_texture(gl, gl::TEXTURE_2D, 0);
         }
 
         let texture = Texture {
@@ -81,9 +260,44 @@ impl Texture {
             obj,
         };
 
-        texture.update(options, res)?;
+        texture.allocate_empty(width, height, internal_format, format, data_type);
 
-        Ok(texture)
+        texture
+    }
+
+    /// Reallocate texture storage with new size, discarding the contents.
+    pub fn allocate_empty(
+        &self,
+        width: i32,
+        height: i32,
+        internal_format: gl::types::GLenum,
+        format: gl::types::GLenum,
+        data_type: gl::types::GLenum,
+    ) {
+        unsafe {
+            StateCache::bind_texture(&self.gl, gl::TEXTURE_2D, self.obj);
+            self.gl.TexImage2D(
+                gl::TEXTURE_2D,
+                0,
+                internal_format as gl::types::GLint,
+                width,
+                height,
+                0,
+                format,
+                data_type,
+                ::std::ptr::null(),
+            );
+            StateCache::bind_texture(&self.gl, gl::TEXTURE_2D, 0);
+        }
+    }
+
+    pub fn id(&self) -> gl::types::GLuint {
+        self.obj
+    }
+
+    /// Name shown in debug messages and captures, set to the resource name by `from_res`.
+    pub fn set_label(&self, label: &str) {
+        debug::object_label(&self.gl, gl::TEXTURE, self.obj, label);
     }
 
     pub fn update<'a>(
@@ -91,113 +305,212 @@ impl Texture {
         options: TextureLoadOptions<'a>,
         res: &Resources,
     ) -> Result<(), failure::Error> {
-        let gl = &self.gl;
-
-        unsafe {
-            gl.BindTexture(gl::TEXTURE_2D, self.obj);
-        }
-
         // https://www.khronos.org/opengl/wiki/Common_Mistakes
 
         match options.format {
             gl::RGB => {
                 let img = res.load_rgb_image(options.resource_name)?;
-
-                if options.gen_mipmaps {
-                    unsafe {
-                        gl.TexImage2D(
-                            gl::TEXTURE_2D,
-                            0,
-                            gl::RGB8 as gl::types::GLint,
-                            img.width() as i32,
-                            img.height() as i32,
-                            0,
-                            gl::RGB,
-                            gl::UNSIGNED_BYTE,
-                            img.as_ptr() as *const raw::c_void,
-                        );
-                        gl.GenerateMipmap(gl::TEXTURE_2D);
-                    }
-                } else {
-                    unsafe {
-                        gl.TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_BASE_LEVEL, 0);
-                        gl.TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAX_LEVEL, 0);
-                        gl.TexImage2D(
-                            gl::TEXTURE_2D,
-                            0,
-                            gl::RGB8 as gl::types::GLint,
-                            img.width() as i32,
-                            img.height() as i32,
-                            0,
-                            gl::RGB,
-                            gl::UNSIGNED_BYTE,
-                            img.as_ptr() as *const raw::c_void,
-                        );
-                    }
-                }
+                self.update_rgb_image(&img, options.color_space, options.gen_mipmaps);
             }
             gl::RGBA => {
                 let img = res.load_rgba_image(options.resource_name)?;
-
-                if options.gen_mipmaps {
-                    unsafe {
-                        gl.TexImage2D(
-                            gl::TEXTURE_2D,
-                            0,
-                            gl::RGBA8 as gl::types::GLint,
-                            img.width() as i32,
-                            img.height() as i32,
-                            0,
-                            gl::RGBA,
-                            gl::UNSIGNED_BYTE,
-                            img.as_ptr() as *const raw::c_void,
-                        );
-                        gl.GenerateMipmap(gl::TEXTURE_2D);
-                    }
-                } else {
-                    unsafe {
-                        gl.TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_BASE_LEVEL, 0);
-                        gl.TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAX_LEVEL, 0);
-                        gl.TexImage2D(
-                            gl::TEXTURE_2D,
-                            0,
-                            gl::RGBA8 as gl::types::GLint,
-                            img.width() as i32,
-                            img.height() as i32,
-                            0,
-                            gl::RGBA,
-                            gl::UNSIGNED_BYTE,
-                            img.as_ptr() as *const raw::c_void,
-                        );
-                    }
-                }
+                self.update_rgba_image(&img, options.color_space, options.gen_mipmaps);
             }
             _ => unreachable!("Only RGB or RGBA images can be constructed"),
         }
 
+        Ok(())
+    }
+
+    /// Create texture from an already decoded image, i.e. one loaded in background.
+    pub fn from_rgb_image(
+        gl: &gl::Gl,
+        img: &image::RgbImage,
+        color_space: ColorSpace,
+        gen_mipmaps: bool,
+    ) -> Texture {
+        let texture = Texture::new_unallocated(gl);
+        texture.update_rgb_image(img, color_space, gen_mipmaps);
+        texture
+    }
+
+    /// Create texture from an already decoded image, i.e. one loaded in background.
+    pub fn from_rgba_image(
+        gl: &gl::Gl,
+        img: &image::RgbaImage,
+        color_space: ColorSpace,
+        gen_mipmaps: bool,
+    ) -> Texture {
+        let texture = Texture::new_unallocated(gl);
+        texture.update_rgba_image(img, color_space, gen_mipmaps);
+        texture
+    }
+
+    /// Images larger than the context supports are scaled down to fit, keeping their aspect.
+    pub fn update_rgb_image(
+        &self,
+        img: &image::RgbImage,
+        color_space: ColorSpace,
+        gen_mipmaps: bool,
+    ) {
+        let resized = self.fit_max_size(img);
+        let img = resized.as_ref().unwrap_or(img);
+        self.upload(
+            color_space.rgb_internal_format(),
+            gl::RGB,
+            img.width() as i32,
+            img.height() as i32,
+            img.as_ptr() as *const raw::c_void,
+            gen_mipmaps,
+        );
+    }
+
+    /// Images larger than the context supports are scaled down to fit, keeping their aspect.
+    pub fn update_rgba_image(
+        &self,
+        img: &image::RgbaImage,
+        color_space: ColorSpace,
+        gen_mipmaps: bool,
+    ) {
+        let resized = self.fit_max_size(img);
+        let img = resized.as_ref().unwrap_or(img);
+        self.upload(
+            color_space.rgba_internal_format(),
+            gl::RGBA,
+            img.width() as i32,
+            img.height() as i32,
+            img.as_ptr() as *const raw::c_void,
+            gen_mipmaps,
+        );
+    }
+
+    /// Copy scaled down to `GL_MAX_TEXTURE_SIZE`, `None` if the image fits.
+    fn fit_max_size<P>(
+        &self,
+        img: &image::ImageBuffer<P, Vec<u8>>,
+    ) -> Option<image::ImageBuffer<P, Vec<u8>>>
+    where
+        P: image::Pixel<Subpixel = u8> + 'static,
+    {
+        let max_size = Capabilities::get(&self.gl).max_texture_size.max(1) as u32;
+        let (width, height) = img.dimensions();
+        if width <= max_size && height <= max_size {
+            return None;
+        }
+        let scale = max_size as f32 / width.max(height) as f32;
+        let fitted_width = ((width as f32 * scale) as u32).max(1).min(max_size);
+        let fitted_height = ((height as f32 * scale) as u32).max(1).min(max_size);
+        notice(format!(
+            "Texture of {}x{} is larger than supported, scaled to {}x{}",
+            width, height, fitted_width, fitted_height
+        ));
+        Some(image::imageops::resize(
+            img,
+            fitted_width,
+            fitted_height,
+            image::FilterType::Triangle,
+        ))
+    }
+
+    fn new_unallocated(gl: &gl::Gl) -> Texture {
+        let mut obj: gl::types::GLuint = 0;
         unsafe {
-            gl.BindTexture(gl::TEXTURE_2D, 0);
+            gl.GenTextures(1, &mut obj);
         }
 
-        Ok(())
+        Texture {
+            gl: gl.clone(),
+            obj,
+        }
     }
 
-    pub fn bind(&self) {
+    fn upload(
+        &self,
+        internal_format: gl::types::GLenum,
+        format: gl::types::GLenum,
+        width: i32,
+        height: i32,
+        pixels: *const raw::c_void,
+        gen_mipmaps: bool,
+    ) {
+        let gl = &self.gl;
+
         unsafe {
-            self.gl.BindTexture(gl::TEXTURE_2D, self.obj);
+            StateCache::bind_texture(gl, gl::TEXTURE_2D, self.obj);
+
+            if !gen_mipmaps {
+                gl.TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_BASE_LEVEL, 0);
+                gl.TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAX_LEVEL, 0);
+            }
+
+            gl.TexImage2D(
+                gl::TEXTURE_2D,
+                0,
+                internal_format as gl::types::GLint,
+                width,
+                height,
+                0,
+                format,
+                gl::UNSIGNED_BYTE,
+                pixels,
+            );
+
+            if gen_mipmaps {
+                gl.GenerateMipmap(gl::TEXTURE_2D);
+            }
+
+            StateCache::bind_texture(gl, gl::TEXTURE_2D, 0);
         }
     }
 
+    pub fn bind(&self) {
+        StateCache::bind_texture(&self.gl, gl::TEXTURE_2D, self.obj);
+    }
+
     pub fn unbind(&self) {
-        unsafe {
-            self.gl.BindTexture(gl::TEXTURE_2D, 0);
-        }
+        StateCache::bind_texture(&self.gl, gl::TEXTURE_2D, 0);
     }
 
     pub fn bind_at(&self, index: u32) {
+        StateCache::bind_texture_at(&self.gl, index, gl::TEXTURE_2D, self.obj);
+    }
+}
+
+/// Check if the compressed format is in the list of formats supported by the driver.
+pub fn compressed_format_supported(gl: &gl::Gl, format: CompressedFormat, srgb: bool) -> bool {
+    let internal_format = compressed_internal_format(format, srgb) as gl::types::GLint;
+
+    let mut count: gl::types::GLint = 0;
+    unsafe {
+        gl.GetIntegerv(gl::NUM_COMPRESSED_TEXTURE_FORMATS, &mut count);
+    }
+    let mut formats: Vec<gl::types::GLint> = vec![0; count as usize];
+    if count > 0 {
         unsafe {
-            self.gl.ActiveTexture(gl::TEXTURE0 + index);
+            gl.GetIntegerv(gl::COMPRESSED_TEXTURE_FORMATS, formats.as_mut_ptr());
         }
-        self.bind();
+    }
+
+    formats.contains(&internal_format)
+}
+
+fn compressed_internal_format(format: CompressedFormat, srgb: bool) -> gl::types::GLenum {
+    match (format, srgb) {
+        (CompressedFormat::Bc1Rgb, false) => COMPRESSED_RGB_S3TC_DXT1,
+        (CompressedFormat::Bc1Rgb, true) => COMPRESSED_SRGB_S3TC_DXT1,
+        (CompressedFormat::Bc1Rgba, false) => COMPRESSED_RGBA_S3TC_DXT1,
+        (CompressedFormat::Bc1Rgba, true) => COMPRESSED_SRGB_ALPHA_S3TC_DXT1,
+        (CompressedFormat::Bc2, false) => COMPRESSED_RGBA_S3TC_DXT3,
+        (CompressedFormat::Bc2, true) => COMPRESSED_SRGB_ALPHA_S3TC_DXT3,
+        (CompressedFormat::Bc3, false) => COMPRESSED_RGBA_S3TC_DXT5,
+        (CompressedFormat::Bc3, true) => COMPRESSED_SRGB_ALPHA_S3TC_DXT5,
+        (CompressedFormat::Bc4, _) => gl::COMPRESSED_RED_RGTC1,
+        (CompressedFormat::Bc5, _) => gl::COMPRESSED_RG_RGTC2,
+        (CompressedFormat::Bc7, false) => gl::COMPRESSED_RGBA_BPTC_UNORM,
+        (CompressedFormat::Bc7, true) => gl::COMPRESSED_SRGB_ALPHA_BPTC_UNORM,
+        (CompressedFormat::Etc2Rgb8, false) => gl::COMPRESSED_RGB8_ETC2,
+        (CompressedFormat::Etc2Rgb8, true) => gl::COMPRESSED_SRGB8_ETC2,
+        (CompressedFormat::Etc2Rgba8, false) => gl::COMPRESSED_RGBA8_ETC2_EAC,
+        (CompressedFormat::Etc2Rgba8, true) => gl::COMPRESSED_SRGB8_ALPHA8_ETC2_EAC,
     }
 }
@@ -0,0 +1,203 @@
+use gl;
+use image;
+use crate::render_gl::texture_cube::mip_levels;
+use crate::render_gl::{debug, ColorSpace, StateCache};
+use crate::resources::{self, ResourcePath, Resources};
+use std::os::raw;
+
+#[derive(Debug, Fail)]
+pub enum Error {
+    #[fail(display = "Texture array needs at least one layer")]
+    NoLayers,
+    #[fail(display = "Failed to load texture array layer {}", name)]
+    ResourceLoad {
+        name: String,
+        #[cause]
+        inner: resources::Error,
+    },
+    #[fail(
+        display = "Texture array layer {} is {}x{}, the first layer is {}x{}",
+        name, width, height, expected_width, expected_height
+    )]
+    SizeMismatch {
+        name: String,
+        width: u32,
+        height: u32,
+        expected_width: u32,
+        expected_height: u32,
+    },
+}
+
+/// How layers of a size other than the first one are handled.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum LayerSize {
+    /// Fail with `Error::SizeMismatch`.
+    Validate,
+    /// Scale to the size of the first layer.
+    Resize,
+}
+
+/// Layers of RGBA images with the same size and mipmaps, sampled with `sampler2DArray`.
+///
+/// Lets instances with different textures share one draw call, each instance picks its
+/// layer, i.e. dice with different skins in `dices::DiceInstances`.
+pub struct Texture2DArray {
+    gl: gl::Gl,
+    obj: gl::types::GLuint,
+    width: i32,
+    height: i32,
+    layers: i32,
+}
+
+impl Drop for Texture2DArray {
+    fn drop(&mut self) {
+        StateCache::texture_deleted(self.obj);
+        unsafe { self.gl.DeleteTextures(1, &self.obj) };
+    }
+}
+
+impl Texture2DArray {
+    /// Load one layer from each resource, in order.
+    pub fn from_res(
+        gl: &gl::Gl,
+        res: &Resources,
+        layers: &[&ResourcePath],
+        color_space: ColorSpace,
+        size: LayerSize,
+    ) -> Result<Texture2DArray, Error> {
+        let mut images = Vec::with_capacity(layers.len());
+        for &name in layers {
+            let img = res
+                .load_rgba_image(name)
+                .map_err(|inner| Error::ResourceLoad {
+                    name: name.to_string(),
+                    inner,
+                })?;
+            images.push(img);
+        }
+        let (width, height) = match images.first() {
+            Some(first) => first.dimensions(),
+            None => return Err(Error::NoLayers),
+        };
+
+        for (img, name) in images.iter_mut().zip(layers) {
+            if img.dimensions() == (width, height) {
+                continue;
+            }
+            match size {
+                LayerSize::Validate => {
+                    return Err(Error::SizeMismatch {
+                        name: name.to_string(),
+                        width: img.width(),
+                        height: img.height(),
+                        expected_width: width,
+                        expected_height: height,
+                    })
+                }
+                LayerSize::Resize => {
+                    *img = image::imageops::resize(img, width, height, image::FilterType::Triangle)
+                }
+            }
+        }
+
+        let texture = Texture2DArray::from_rgba_images(gl, &images, color_space);
+        let names = layers.iter().map(|name| name.to_string()).collect::<Vec<_>>();
+        texture.set_label(&names.join(", "));
+        Ok(texture)
+    }
+
+    /// Create texture from decoded images of the same size, with mipmaps.
+    pub fn from_rgba_images(
+        gl: &gl::Gl,
+        images: &[image::RgbaImage],
+        color_space: ColorSpace,
+    ) -> Texture2DArray {
+        let (width, height) = images.first().map_or((1, 1), |img| img.dimensions());
+        let (width, height) = (width as i32, height as i32);
+        let layers = images.len() as i32;
+        let internal_format = match color_space {
+            ColorSpace::Linear => gl::RGBA8,
+            ColorSpace::Srgb => gl::SRGB8_ALPHA8,
+        };
+
+        let mut obj: gl::types::GLuint = 0;
+        unsafe {
+            gl.GenTextures(1, &mut obj);
+            StateCache::bind_texture(gl, gl::TEXTURE_2D_ARRAY, obj);
+            gl.TexParameteri(
+                gl::TEXTURE_2D_ARRAY,
+                gl::TEXTURE_MIN_FILTER,
+                gl::LINEAR_MIPMAP_LINEAR as i32,
+            );
+            gl.TexParameteri(gl::TEXTURE_2D_ARRAY, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
+            gl.TexParameteri(gl::TEXTURE_2D_ARRAY, gl::TEXTURE_BASE_LEVEL, 0);
+            gl.TexParameteri(
+                gl::TEXTURE_2D_ARRAY,
+                gl::TEXTURE_MAX_LEVEL,
+                mip_levels(width.max(height)) - 1,
+            );
+            gl.TexImage3D(
+                gl::TEXTURE_2D_ARRAY,
+                0,
+                internal_format as gl::types::GLint,
+                width,
+                height,
+                layers.max(1),
+                0,
+                gl::RGBA,
+                gl::UNSIGNED_BYTE,
+                ::std::ptr::null(),
+            );
+            for (layer, img) in images.iter().enumerate() {
+                gl.TexSubImage3D(
+                    gl::TEXTURE_2D_ARRAY,
+                    0,
+                    0,
+                    0,
+                    layer as i32,
+                    width,
+                    height,
+                    1,
+                    gl::RGBA,
+                    gl::UNSIGNED_BYTE,
+                    img.as_ptr() as *const raw::c_void,
+                );
+            }
+            gl.GenerateMipmap(gl::TEXTURE_2D_ARRAY);
+            StateCache::bind_texture(gl, gl::TEXTURE_2D_ARRAY, 0);
+        }
+
+        Texture2DArray {
+            gl: gl.clone(),
+            obj,
+            width,
+            height,
+            layers,
+        }
+    }
+
+    pub fn id(&self) -> gl::types::GLuint {
+        self.obj
+    }
+
+    pub fn width(&self) -> i32 {
+        self.width
+    }
+
+    pub fn height(&self) -> i32 {
+        self.height
+    }
+
+    pub fn layers(&self) -> i32 {
+        self.layers
+    }
+
+    /// Name shown in debug messages and captures, set to the resource names by `from_res`.
+    pub fn set_label(&self, label: &str) {
+        debug::object_label(&self.gl, gl::TEXTURE, self.obj, label);
+    }
+
+    pub fn bind_at(&self, index: u32) {
+        StateCache::bind_texture_at(&self.gl, index, gl::TEXTURE_2D_ARRAY, self.obj);
+    }
+}
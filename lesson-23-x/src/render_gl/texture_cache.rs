@@ -0,0 +1,166 @@
+use failure;
+use gl;
+use image;
+use crate::render_gl::{compressed_format_supported, ColorSpace, Texture};
+use crate::resources::{CompressedImage, ResourcePath, Resources};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+struct TextureKey {
+    path: String,
+    color_space: ColorSpace,
+    gen_mipmaps: bool,
+}
+
+struct CachedTexture {
+    texture: Rc<Texture>,
+    /// Estimated size of all levels in video memory.
+    bytes: usize,
+}
+
+thread_local! {
+    static TEXTURES: RefCell<HashMap<TextureKey, CachedTexture>> = RefCell::new(HashMap::new());
+}
+
+/// Textures uploaded once for each resource path and options, shared by all users.
+///
+/// Unlike `ProgramCache`, the cache keeps its own reference, so a texture stays on the GPU
+/// after its last user is dropped until `evict_unused` is called. There is one cache per
+/// thread, as the lessons use a single context on the main thread.
+pub struct TextureCache;
+
+impl TextureCache {
+    /// Shared texture of the RGB image at the path, loaded if it is not in the cache.
+    pub fn from_res_rgb(
+        gl: &gl::Gl,
+        res: &Resources,
+        path: &ResourcePath,
+        color_space: ColorSpace,
+        gen_mipmaps: bool,
+    ) -> Result<Rc<Texture>, failure::Error> {
+        let key = TextureCache::key(path, color_space, gen_mipmaps);
+        if let Some(texture) = TextureCache::get(&key) {
+            return Ok(texture);
+        }
+        let img = res.load_rgb_image(path)?;
+        Ok(TextureCache::upload(gl, key, &img))
+    }
+
+    /// Shared texture of an image decoded from the path, i.e. in background.
+    ///
+    /// The image is uploaded only if the path is not in the cache yet.
+    pub fn from_rgb_image(
+        gl: &gl::Gl,
+        path: &ResourcePath,
+        img: &image::RgbImage,
+        color_space: ColorSpace,
+        gen_mipmaps: bool,
+    ) -> Rc<Texture> {
+        let key = TextureCache::key(path, color_space, gen_mipmaps);
+        match TextureCache::get(&key) {
+            Some(texture) => texture,
+            None => TextureCache::upload(gl, key, img),
+        }
+    }
+
+    /// Shared texture of a DDS or KTX2 image parsed from the path, uploaded with its own
+    /// mipmap chain.
+    ///
+    /// `None` if the GL implementation does not support the block format.
+    pub fn from_compressed_image(
+        gl: &gl::Gl,
+        path: &ResourcePath,
+        img: &CompressedImage,
+    ) -> Option<Rc<Texture>> {
+        let color_space = if img.srgb {
+            ColorSpace::Srgb
+        } else {
+            ColorSpace::Linear
+        };
+        let key = TextureCache::key(path, color_space, false);
+        if let Some(texture) = TextureCache::get(&key) {
+            return Some(texture);
+        }
+        if !compressed_format_supported(gl, img.format, img.srgb) {
+            return None;
+        }
+        let texture = Texture::from_compressed_image(gl, img);
+        texture.set_label(&key.path);
+        let bytes = img.levels.iter().map(|level| level.data.len()).sum();
+        Some(TextureCache::insert(key, texture, bytes))
+    }
+
+    /// Forget textures of the path with any options, i.e. when the file has changed.
+    ///
+    /// Current users keep their textures, the next request uploads the path again.
+    pub fn remove(path: &ResourcePath) {
+        TEXTURES.with(|textures| {
+            textures
+                .borrow_mut()
+                .retain(|key, _| key.path != path.as_clean_str())
+        });
+    }
+
+    /// Drop textures used by nothing but the cache, returns how many were dropped.
+    pub fn evict_unused() -> usize {
+        TEXTURES.with(|textures| {
+            let mut textures = textures.borrow_mut();
+            let before = textures.len();
+            textures.retain(|_, cached| Rc::strong_count(&cached.texture) > 1);
+            before - textures.len()
+        })
+    }
+
+    /// Number of cached textures, including unused ones.
+    pub fn len() -> usize {
+        TEXTURES.with(|textures| textures.borrow().len())
+    }
+
+    /// Estimated video memory of cached textures in bytes.
+    pub fn memory_usage() -> usize {
+        TEXTURES.with(|textures| textures.borrow().values().map(|cached| cached.bytes).sum())
+    }
+
+    fn key(path: &ResourcePath, color_space: ColorSpace, gen_mipmaps: bool) -> TextureKey {
+        TextureKey {
+            path: path.as_clean_str().to_string(),
+            color_space,
+            gen_mipmaps,
+        }
+    }
+
+    fn get(key: &TextureKey) -> Option<Rc<Texture>> {
+        TEXTURES.with(|textures| {
+            textures
+                .borrow()
+                .get(key)
+                .map(|cached| cached.texture.clone())
+        })
+    }
+
+    fn upload(gl: &gl::Gl, key: TextureKey, img: &image::RgbImage) -> Rc<Texture> {
+        let texture = Texture::from_rgb_image(gl, img, key.color_space, key.gen_mipmaps);
+        texture.set_label(&key.path);
+
+        // drivers pad RGB texels to 4 bytes, a mipmap chain adds a third
+        let base = img.width() as usize * img.height() as usize * 4;
+        let bytes = if key.gen_mipmaps { base * 4 / 3 } else { base };
+        TextureCache::insert(key, texture, bytes)
+    }
+
+    fn insert(key: TextureKey, texture: Texture, bytes: usize) -> Rc<Texture> {
+        let texture = Rc::new(texture);
+        TEXTURES.with(|textures| {
+            textures.borrow_mut().insert(
+                key,
+                CachedTexture {
+                    texture: texture.clone(),
+                    bytes,
+                },
+            )
+        });
+        texture
+    }
+}
@@ -0,0 +1,357 @@
+use failure;
+use gl;
+use nalgebra as na;
+use crate::render_gl::buffer::VertexArray;
+use crate::render_gl::{FrameStats, Program, StateCache, Texture};
+use crate::resources::{ResourcePath, Resources};
+use std::os::raw;
+
+pub enum CubeTextureSource<'a> {
+    /// Six images in order +X, -X, +Y, -Y, +Z, -Z.
+    Faces([&'a ResourcePath; 6]),
+    /// Single equirectangular HDR image with Z up, converted to faces on the GPU.
+    Equirectangular {
+        resource_name: &'a ResourcePath,
+        face_size: i32,
+    },
+}
+
+pub struct TextureCube {
+    gl: gl::Gl,
+    obj: gl::types::GLuint,
+    face_size: i32,
+}
+
+impl Drop for TextureCube {
+    fn drop(&mut self) {
+        StateCache::texture_deleted(self.obj);
+        unsafe { self.gl.DeleteTextures(1, &self.obj) };
+    }
+}
+
+impl TextureCube {
+    pub fn from_res(
+        gl: &gl::Gl,
+        res: &Resources,
+        source: CubeTextureSource,
+    ) -> Result<TextureCube, failure::Error> {
+        match source {
+            CubeTextureSource::Faces(faces) => TextureCube::from_res_faces(gl, res, &faces),
+            CubeTextureSource::Equirectangular {
+                resource_name,
+                face_size,
+            } => TextureCube::from_res_equirectangular(gl, res, resource_name, face_size),
+        }
+    }
+
+    /// Create floating point cube map with uninitialized storage for `levels` mipmaps,
+    /// to be filled with `render_faces`.
+    pub fn new_empty(
+        gl: &gl::Gl,
+        face_size: i32,
+        internal_format: gl::types::GLenum,
+        levels: i32,
+    ) -> TextureCube {
+        let mut texture = TextureCube::new_unallocated(gl);
+        texture.face_size = face_size;
+
+        unsafe {
+            StateCache::bind_texture(gl, gl::TEXTURE_CUBE_MAP, texture.obj);
+            gl.TexParameteri(gl::TEXTURE_CUBE_MAP, gl::TEXTURE_BASE_LEVEL, 0);
+            gl.TexParameteri(gl::TEXTURE_CUBE_MAP, gl::TEXTURE_MAX_LEVEL, levels - 1);
+            for level in 0..levels {
+                let size = ::std::cmp::max(1, face_size >> level);
+                for face in 0..6 {
+                    gl.TexImage2D(
+                        gl::TEXTURE_CUBE_MAP_POSITIVE_X + face,
+                        level,
+                        internal_format as gl::types::GLint,
+                        size,
+                        size,
+                        0,
+                        gl::RGB,
+                        gl::FLOAT,
+                        ::std::ptr::null(),
+                    );
+                }
+            }
+            StateCache::bind_texture(gl, gl::TEXTURE_CUBE_MAP, 0);
+        }
+
+        texture
+    }
+
+    /// Create floating point cube map with the color of each texel direction, with mipmaps.
+    ///
+    /// Directions are not normalized, they follow `shaders/render_gl/cube_face.vert`.
+    pub fn from_directions<F>(gl: &gl::Gl, face_size: i32, color: F) -> TextureCube
+    where
+        F: Fn(na::Vector3<f32>) -> na::Vector3<f32>,
+    {
+        let texture = TextureCube::new_empty(gl, face_size, gl::RGB16F, mip_levels(face_size));
+
+        let texel = |index: i32| (index as f32 + 0.5) / face_size as f32 * 2.0 - 1.0;
+        let mut pixels = Vec::with_capacity((face_size * face_size * 3) as usize);
+        StateCache::bind_texture(gl, gl::TEXTURE_CUBE_MAP, texture.obj);
+        for face in 0..6 {
+            pixels.clear();
+            for y in 0..face_size {
+                for x in 0..face_size {
+                    let rgb = color(face_direction(face, texel(x), texel(y)));
+                    pixels.extend_from_slice(&[rgb.x, rgb.y, rgb.z]);
+                }
+            }
+            unsafe {
+                gl.TexSubImage2D(
+                    gl::TEXTURE_CUBE_MAP_POSITIVE_X + face,
+                    0,
+                    0,
+                    0,
+                    face_size,
+                    face_size,
+                    gl::RGB,
+                    gl::FLOAT,
+                    pixels.as_ptr() as *const raw::c_void,
+                );
+            }
+        }
+        unsafe {
+            gl.GenerateMipmap(gl::TEXTURE_CUBE_MAP);
+        }
+        StateCache::bind_texture(gl, gl::TEXTURE_CUBE_MAP, 0);
+
+        texture
+    }
+
+    fn from_res_faces(
+        gl: &gl::Gl,
+        res: &Resources,
+        faces: &[&ResourcePath; 6],
+    ) -> Result<TextureCube, failure::Error> {
+        let mut texture = TextureCube::new_unallocated(gl);
+
+        StateCache::bind_texture(gl, gl::TEXTURE_CUBE_MAP, texture.obj);
+
+        for (index, face) in faces.iter().enumerate() {
+            let img = res.load_rgb_image(face)?;
+            texture.face_size = img.width() as i32;
+
+            unsafe {
+                gl.TexImage2D(
+                    gl::TEXTURE_CUBE_MAP_POSITIVE_X + index as u32,
+                    0,
+                    gl::RGB8 as gl::types::GLint,
+                    img.width() as i32,
+                    img.height() as i32,
+                    0,
+                    gl::RGB,
+                    gl::UNSIGNED_BYTE,
+                    img.as_ptr() as *const raw::c_void,
+                );
+            }
+        }
+
+        unsafe {
+            gl.GenerateMipmap(gl::TEXTURE_CUBE_MAP);
+            StateCache::bind_texture(gl, gl::TEXTURE_CUBE_MAP, 0);
+        }
+
+        Ok(texture)
+    }
+
+    fn from_res_equirectangular(
+        gl: &gl::Gl,
+        res: &Resources,
+        resource_name: &ResourcePath,
+        face_size: i32,
+    ) -> Result<TextureCube, failure::Error> {
+        let img = res.load_hdr_image(resource_name)?;
+
+        let equirectangular = Texture::new_empty(
+            gl,
+            img.width() as i32,
+            img.height() as i32,
+            gl::RGB16F,
+            gl::RGB,
+            gl::FLOAT,
+        );
+        unsafe {
+            StateCache::bind_texture(gl, gl::TEXTURE_2D, equirectangular.id());
+            // longitude wraps around
+            gl.TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::REPEAT as i32);
+            gl.TexSubImage2D(
+                gl::TEXTURE_2D,
+                0,
+                0,
+                0,
+                img.width() as i32,
+                img.height() as i32,
+                gl::RGB,
+                gl::FLOAT,
+                img.as_ptr() as *const raw::c_void,
+            );
+            StateCache::bind_texture(gl, gl::TEXTURE_2D, 0);
+        }
+
+        let program = Program::from_res_files(
+            gl,
+            res,
+            &[
+                "shaders/render_gl/cube_face.vert",
+                "shaders/render_gl/equirect_to_cube.frag",
+            ],
+        )?;
+        if let Some(loc) = program.get_uniform_location("Equirectangular") {
+            program.set_used();
+            equirectangular.bind_at(0);
+            program.set_uniform_1i(loc, 0);
+        }
+
+        let levels = mip_levels(face_size);
+        let texture = TextureCube::new_empty(gl, face_size, gl::RGB16F, levels);
+        texture.render_faces(&program, 0);
+
+        unsafe {
+            StateCache::bind_texture(gl, gl::TEXTURE_CUBE_MAP, texture.obj);
+            gl.GenerateMipmap(gl::TEXTURE_CUBE_MAP);
+            StateCache::bind_texture(gl, gl::TEXTURE_CUBE_MAP, 0);
+        }
+
+        Ok(texture)
+    }
+
+    /// Render all six faces of a mipmap level with a fullscreen triangle.
+    ///
+    /// Program textures and uniforms should already be set. The vertex shader
+    /// receives face index in `Face` uniform, see `shaders/render_gl/cube_face.vert`.
+    pub fn render_faces(&self, program: &Program, level: i32) {
+        let gl = &self.gl;
+        let size = ::std::cmp::max(1, self.face_size >> level);
+
+        let mut fbo: gl::types::GLuint = 0;
+        let mut previous_fbo: gl::types::GLint = 0;
+        let mut previous_viewport: [gl::types::GLint; 4] = [0; 4];
+
+        unsafe {
+            gl.GetIntegerv(gl::DRAW_FRAMEBUFFER_BINDING, &mut previous_fbo);
+            gl.GetIntegerv(gl::VIEWPORT, previous_viewport.as_mut_ptr());
+
+            gl.GenFramebuffers(1, &mut fbo);
+            gl.BindFramebuffer(gl::DRAW_FRAMEBUFFER, fbo);
+            gl.Viewport(0, 0, size, size);
+            StateCache::set_depth_test(gl, false);
+        }
+
+        program.set_used();
+        let face_location = program.get_uniform_location("Face");
+
+        let vao = VertexArray::new(gl);
+        vao.bind();
+        for face in 0..6 {
+            unsafe {
+                gl.FramebufferTexture2D(
+                    gl::DRAW_FRAMEBUFFER,
+                    gl::COLOR_ATTACHMENT0,
+                    gl::TEXTURE_CUBE_MAP_POSITIVE_X + face,
+                    self.obj,
+                    level,
+                );
+            }
+            if let Some(loc) = face_location {
+                program.set_uniform_1i(loc, face as i32);
+            }
+            // fullscreen triangle is generated from vertex id in the shader
+            unsafe {
+                gl.DrawArrays(gl::TRIANGLES, 0, 3);
+            }
+            FrameStats::record_draw(gl::TRIANGLES, 3, 1);
+        }
+        vao.unbind();
+
+        unsafe {
+            StateCache::set_depth_test(gl, true);
+            gl.BindFramebuffer(gl::DRAW_FRAMEBUFFER, previous_fbo as gl::types::GLuint);
+            gl.Viewport(
+                previous_viewport[0],
+                previous_viewport[1],
+                previous_viewport[2],
+                previous_viewport[3],
+            );
+            gl.DeleteFramebuffers(1, &fbo);
+        }
+    }
+
+    fn new_unallocated(gl: &gl::Gl) -> TextureCube {
+        let mut obj: gl::types::GLuint = 0;
+        unsafe {
+            gl.GenTextures(1, &mut obj);
+            StateCache::bind_texture(gl, gl::TEXTURE_CUBE_MAP, obj);
+            gl.TexParameteri(
+                gl::TEXTURE_CUBE_MAP,
+                gl::TEXTURE_MIN_FILTER,
+                gl::LINEAR_MIPMAP_LINEAR as i32,
+            );
+            gl.TexParameteri(gl::TEXTURE_CUBE_MAP, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
+            gl.TexParameteri(gl::TEXTURE_CUBE_MAP, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32);
+            gl.TexParameteri(gl::TEXTURE_CUBE_MAP, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32);
+            gl.TexParameteri(gl::TEXTURE_CUBE_MAP, gl::TEXTURE_WRAP_R, gl::CLAMP_TO_EDGE as i32);
+            StateCache::bind_texture(gl, gl::TEXTURE_CUBE_MAP, 0);
+        }
+
+        TextureCube {
+            gl: gl.clone(),
+            obj,
+            face_size: 0,
+        }
+    }
+
+    pub fn id(&self) -> gl::types::GLuint {
+        self.obj
+    }
+
+    pub fn face_size(&self) -> i32 {
+        self.face_size
+    }
+
+    pub fn bind(&self) {
+        StateCache::bind_texture(&self.gl, gl::TEXTURE_CUBE_MAP, self.obj);
+    }
+
+    pub fn unbind(&self) {
+        StateCache::bind_texture(&self.gl, gl::TEXTURE_CUBE_MAP, 0);
+    }
+
+    pub fn bind_at(&self, index: u32) {
+        StateCache::bind_texture_at(&self.gl, index, gl::TEXTURE_CUBE_MAP, self.obj);
+    }
+
+    /// Fill lower mipmap levels from the base level, i.e. after rendering into it.
+    pub fn generate_mipmaps(&self) {
+        StateCache::bind_texture(&self.gl, gl::TEXTURE_CUBE_MAP, self.obj);
+        unsafe {
+            self.gl.GenerateMipmap(gl::TEXTURE_CUBE_MAP);
+        }
+        StateCache::bind_texture(&self.gl, gl::TEXTURE_CUBE_MAP, 0);
+    }
+}
+
+/// Direction at face coordinates in [-1, 1], in GL cube map face order.
+fn face_direction(face: u32, s: f32, t: f32) -> na::Vector3<f32> {
+    match face {
+        0 => na::Vector3::new(1.0, -t, -s),
+        1 => na::Vector3::new(-1.0, -t, s),
+        2 => na::Vector3::new(s, 1.0, t),
+        3 => na::Vector3::new(s, -1.0, -t),
+        4 => na::Vector3::new(s, -t, 1.0),
+        _ => na::Vector3::new(-s, -t, -1.0),
+    }
+}
+
+/// Number of mipmap levels down to 1x1.
+pub(crate) fn mip_levels(size: i32) -> i32 {
+    let mut levels = 1;
+    while size >> levels > 0 {
+        levels += 1;
+    }
+    levels
+}
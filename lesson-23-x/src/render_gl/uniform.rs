@@ -0,0 +1,56 @@
+use crate::render_gl::{Program, UniformValue};
+use std::marker::PhantomData;
+
+/// Uniform location checked against the GLSL type of `T`, obtained with `Program::uniform`.
+///
+/// Uniforms optimized out of the program give an inactive handle that ignores writes.
+/// Array uniforms are set through their first element.
+pub struct Uniform<T: UniformValue> {
+    location: Option<i32>,
+    _value: PhantomData<T>,
+}
+
+impl<T: UniformValue> Clone for Uniform<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T: UniformValue> Copy for Uniform<T> {}
+
+impl<T: UniformValue> Uniform<T> {
+    pub(crate) fn new(location: Option<i32>) -> Uniform<T> {
+        Uniform {
+            location,
+            _value: PhantomData,
+        }
+    }
+
+    pub fn inactive() -> Uniform<T> {
+        Uniform::new(None)
+    }
+
+    pub fn location(&self) -> Option<i32> {
+        self.location
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.location.is_some()
+    }
+
+    /// Set the value, the program the handle came from must be in use.
+    pub fn set(&self, program: &Program, value: &T) {
+        if let Some(location) = self.location {
+            value.set(program, location);
+        }
+    }
+
+    /// Set consecutive elements of an array uniform, starting with the first one.
+    pub fn set_array(&self, program: &Program, values: &[T]) {
+        if let Some(location) = self.location {
+            for (index, value) in values.iter().enumerate() {
+                value.set(program, location + index as i32);
+            }
+        }
+    }
+}
@@ -0,0 +1,244 @@
+use gl;
+use nalgebra as na;
+use std::marker::PhantomData;
+
+/// Value that can be written into uniform block memory using std140 layout rules.
+///
+/// Implement it for uniform block structs with `#[derive(Std140)]`.
+pub trait Std140 {
+    /// Base alignment of the value in bytes.
+    fn std140_align() -> usize;
+    /// Size of the value in bytes, without trailing padding to the next member.
+    fn std140_size() -> usize;
+    /// Write value bytes into the slice of exactly `std140_size` length.
+    fn write_std140(&self, out: &mut [u8]);
+}
+
+/// Round offset up to the next multiple of the alignment.
+pub fn std140_align_offset(offset: usize, align: usize) -> usize {
+    (offset + align - 1) / align * align
+}
+
+/// Bytes of the value in native order, the way GL reads buffer memory.
+fn u32_bytes(value: u32) -> [u8; 4] {
+    let bytes = [value as u8, (value >> 8) as u8, (value >> 16) as u8, (value >> 24) as u8];
+    if cfg!(target_endian = "big") {
+        [bytes[3], bytes[2], bytes[1], bytes[0]]
+    } else {
+        bytes
+    }
+}
+
+fn write_f32s(values: &[f32], out: &mut [u8]) {
+    for (value, bytes) in values.iter().zip(out.chunks_mut(4)) {
+        bytes.copy_from_slice(&u32_bytes(value.to_bits()));
+    }
+}
+
+impl Std140 for f32 {
+    fn std140_align() -> usize {
+        4
+    }
+
+    fn std140_size() -> usize {
+        4
+    }
+
+    fn write_std140(&self, out: &mut [u8]) {
+        out.copy_from_slice(&u32_bytes(self.to_bits()));
+    }
+}
+
+impl Std140 for i32 {
+    fn std140_align() -> usize {
+        4
+    }
+
+    fn std140_size() -> usize {
+        4
+    }
+
+    fn write_std140(&self, out: &mut [u8]) {
+        out.copy_from_slice(&u32_bytes(*self as u32));
+    }
+}
+
+impl Std140 for u32 {
+    fn std140_align() -> usize {
+        4
+    }
+
+    fn std140_size() -> usize {
+        4
+    }
+
+    fn write_std140(&self, out: &mut [u8]) {
+        out.copy_from_slice(&u32_bytes(*self));
+    }
+}
+
+impl Std140 for na::Vector2<f32> {
+    fn std140_align() -> usize {
+        8
+    }
+
+    fn std140_size() -> usize {
+        8
+    }
+
+    fn write_std140(&self, out: &mut [u8]) {
+        write_f32s(self.as_slice(), out);
+    }
+}
+
+impl Std140 for na::Vector3<f32> {
+    fn std140_align() -> usize {
+        16
+    }
+
+    fn std140_size() -> usize {
+        12
+    }
+
+    fn write_std140(&self, out: &mut [u8]) {
+        write_f32s(self.as_slice(), out);
+    }
+}
+
+impl Std140 for na::Vector4<f32> {
+    fn std140_align() -> usize {
+        16
+    }
+
+    fn std140_size() -> usize {
+        16
+    }
+
+    fn write_std140(&self, out: &mut [u8]) {
+        write_f32s(self.as_slice(), out);
+    }
+}
+
+impl Std140 for na::Matrix3<f32> {
+    fn std140_align() -> usize {
+        16
+    }
+
+    fn std140_size() -> usize {
+        48
+    }
+
+    fn write_std140(&self, out: &mut [u8]) {
+        // every column is padded to vec4
+        for (column, bytes) in self.as_slice().chunks(3).zip(out.chunks_mut(16)) {
+            write_f32s(column, bytes);
+        }
+    }
+}
+
+impl Std140 for na::Matrix4<f32> {
+    fn std140_align() -> usize {
+        16
+    }
+
+    fn std140_size() -> usize {
+        64
+    }
+
+    fn write_std140(&self, out: &mut [u8]) {
+        write_f32s(self.as_slice(), out);
+    }
+}
+
+macro_rules! impl_std140_array {
+    ($($len:expr),*) => {
+        $(
+            impl<T: Std140> Std140 for [T; $len] {
+                fn std140_align() -> usize {
+                    16
+                }
+
+                fn std140_size() -> usize {
+                    std140_align_offset(T::std140_size(), 16) * $len
+                }
+
+                fn write_std140(&self, out: &mut [u8]) {
+                    let stride = std140_align_offset(T::std140_size(), 16);
+                    for (item, bytes) in self.iter().zip(out.chunks_mut(stride)) {
+                        item.write_std140(&mut bytes[..T::std140_size()]);
+                    }
+                }
+            }
+        )*
+    };
+}
+
+impl_std140_array!(1, 2, 3, 4, 5, 6, 7, 8, 16, 32, 64);
+
+/// Uniform buffer object holding a single std140 value of type `T`.
+///
+/// The value is uploaded once with `update` and becomes visible to every program
+/// whose uniform block is bound to the same binding point.
+pub struct UniformBuffer<T: Std140> {
+    gl: gl::Gl,
+    ubo: gl::types::GLuint,
+    data: Vec<u8>,
+    _marker: PhantomData<T>,
+}
+
+impl<T: Std140> UniformBuffer<T> {
+    pub fn new(gl: &gl::Gl) -> UniformBuffer<T> {
+        let size = T::std140_size();
+
+        let mut ubo: gl::types::GLuint = 0;
+        unsafe {
+            gl.GenBuffers(1, &mut ubo);
+            gl.BindBuffer(gl::UNIFORM_BUFFER, ubo);
+            gl.BufferData(
+                gl::UNIFORM_BUFFER,
+                size as gl::types::GLsizeiptr,
+                ::std::ptr::null() as *const gl::types::GLvoid,
+                gl::DYNAMIC_DRAW,
+            );
+            gl.BindBuffer(gl::UNIFORM_BUFFER, 0);
+        }
+
+        UniformBuffer {
+            gl: gl.clone(),
+            ubo,
+            data: vec![0; size],
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn update(&mut self, value: &T) {
+        value.write_std140(&mut self.data[..]);
+
+        unsafe {
+            self.gl.BindBuffer(gl::UNIFORM_BUFFER, self.ubo);
+            self.gl.BufferSubData(
+                gl::UNIFORM_BUFFER,
+                0,
+                self.data.len() as gl::types::GLsizeiptr,
+                self.data.as_ptr() as *const gl::types::GLvoid,
+            );
+            self.gl.BindBuffer(gl::UNIFORM_BUFFER, 0);
+        }
+    }
+
+    /// Bind buffer to uniform block binding point.
+    pub fn bind_base(&self, binding_point: u32) {
+        unsafe {
+            self.gl
+                .BindBufferBase(gl::UNIFORM_BUFFER, binding_point, self.ubo);
+        }
+    }
+}
+
+impl<T: Std140> Drop for UniformBuffer<T> {
+    fn drop(&mut self) {
+        unsafe {
+            self.gl.DeleteBuffers(1, &self.ubo);
+        }
+    }
+}
@@ -0,0 +1,33 @@
+use std::cell::Cell;
+
+/// Limits how many finished background loads are uploaded to the GPU in a single frame,
+/// so that many resources arriving at once do not stall the frame.
+pub struct UploadQueue {
+    uploads_per_frame: usize,
+    remaining: Cell<usize>,
+}
+
+impl UploadQueue {
+    pub fn new(uploads_per_frame: usize) -> UploadQueue {
+        UploadQueue {
+            uploads_per_frame,
+            remaining: Cell::new(uploads_per_frame),
+        }
+    }
+
+    /// Reset the budget, call once at the start of the frame.
+    pub fn begin_frame(&self) {
+        self.remaining.set(self.uploads_per_frame);
+    }
+
+    /// Take one upload from this frame budget. If it returns false, keep
+    /// loaded data around and try again next frame.
+    pub fn try_acquire(&self) -> bool {
+        let remaining = self.remaining.get();
+        if remaining == 0 {
+            return false;
+        }
+        self.remaining.set(remaining - 1);
+        true
+    }
+}
@@ -1,15 +1,47 @@
 use gl;
+use crate::platform::Event;
+use crate::render::WindowSize;
 
+/// Drawable area of the window in pixels, follows resize events.
+///
+/// On high DPI displays the drawable has more pixels than the window size that mouse events
+/// are reported in, `to_pixels` converts between the two.
 pub struct Viewport {
     pub x: i32,
     pub y: i32,
     pub w: i32,
     pub h: i32,
+    logical_w: i32,
+    logical_h: i32,
+}
+
+/// New drawable size, returned by `Viewport::handle_event` so that projections and render
+/// targets can follow it.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ViewportResize {
+    pub width: i32,
+    pub height: i32,
+    pub aspect: f32,
 }
 
 impl Viewport {
     pub fn for_window(w: i32, h: i32) -> Viewport {
-        Viewport { x: 0, y: 0, w, h }
+        Viewport {
+            x: 0,
+            y: 0,
+            w,
+            h,
+            logical_w: w,
+            logical_h: h,
+        }
+    }
+
+    /// Drawable size in pixels, window size for conversion of mouse positions.
+    pub fn for_window_size(size: &WindowSize) -> Viewport {
+        let mut viewport = Viewport::for_window(size.highdpi_width, size.highdpi_height);
+        viewport.logical_w = size.width;
+        viewport.logical_h = size.height;
+        viewport
     }
 
     pub fn update_size(&mut self, w: i32, h: i32) {
@@ -17,6 +49,53 @@ impl Viewport {
         self.h = h;
     }
 
+    /// Width over height of the drawable, for projections.
+    pub fn aspect(&self) -> f32 {
+        self.w as f32 / self.h.max(1) as f32
+    }
+
+    /// Drawable pixels per window unit, larger than 1 on high DPI displays.
+    pub fn scale(&self) -> f32 {
+        self.w as f32 / self.logical_w.max(1) as f32
+    }
+
+    /// Convert mouse position in window coordinates to drawable pixels.
+    pub fn to_pixels(&self, x: i32, y: i32) -> (i32, i32) {
+        (
+            x * self.w / self.logical_w.max(1),
+            y * self.h / self.logical_h.max(1),
+        )
+    }
+
+    /// Follow window resizes and make the new size used.
+    ///
+    /// Returns the new size if it changed. Minimized windows report zero size, which is
+    /// skipped so that projections never get a degenerate aspect.
+    pub fn handle_event(&mut self, gl: &gl::Gl, event: &Event) -> Option<ViewportResize> {
+        let size = match *event {
+            Event::Resized(size) => size,
+            _ => return None,
+        };
+        if size.highdpi_width <= 0 || size.highdpi_height <= 0 {
+            return None;
+        }
+
+        self.logical_w = size.width;
+        self.logical_h = size.height;
+        if (self.w, self.h) == (size.highdpi_width, size.highdpi_height) {
+            return None;
+        }
+
+        self.update_size(size.highdpi_width, size.highdpi_height);
+        self.set_used(gl);
+
+        Some(ViewportResize {
+            width: self.w,
+            height: self.h,
+            aspect: self.aspect(),
+        })
+    }
+
     pub fn set_used(&self, gl: &gl::Gl) {
         unsafe {
             gl.Viewport(self.x, self.y, self.w, self.h);
@@ -0,0 +1,67 @@
+use failure;
+use crate::jobs;
+use std::sync::mpsc;
+
+use super::Error;
+
+/// Run the load on the shared job pool.
+pub fn spawn<T, F>(load: F) -> LoadHandle<T>
+where
+    T: Send + 'static,
+    F: FnOnce() -> Result<T, failure::Error> + Send + 'static,
+{
+    let (sender, receiver) = mpsc::channel();
+
+    jobs::spawn(move || {
+        // receiver may be gone if the handle was dropped
+        let _ = sender.send(load());
+    });
+
+    LoadHandle {
+        receiver,
+        result: None,
+    }
+}
+
+/// Result of a background load, polled once per frame from the main thread.
+pub struct LoadHandle<T> {
+    receiver: mpsc::Receiver<Result<T, failure::Error>>,
+    result: Option<Result<T, failure::Error>>,
+}
+
+impl<T> LoadHandle<T> {
+    /// Check if the load has finished, without blocking.
+    pub fn is_ready(&mut self) -> bool {
+        if self.result.is_none() {
+            match self.receiver.try_recv() {
+                Ok(result) => self.result = Some(result),
+                Err(mpsc::TryRecvError::Empty) => (),
+                Err(mpsc::TryRecvError::Disconnected) => {
+                    self.result = Some(Err(Error::LoaderStopped.into()))
+                }
+            }
+        }
+
+        self.result.is_some()
+    }
+
+    /// Take the finished result, or `None` while the load is still in progress.
+    pub fn take(&mut self) -> Option<Result<T, failure::Error>> {
+        if self.is_ready() {
+            self.result.take()
+        } else {
+            None
+        }
+    }
+
+    /// Block until the load finishes.
+    pub fn wait(mut self) -> Result<T, failure::Error> {
+        if let Some(result) = self.result.take() {
+            return result;
+        }
+
+        self.receiver
+            .recv()
+            .unwrap_or_else(|_| Err(Error::LoaderStopped.into()))
+    }
+}
@@ -0,0 +1,243 @@
+use crate::resources::ResourcePath;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Source of resource file contents.
+pub trait Backend: Send + Sync {
+    fn read(&self, path: &ResourcePath) -> io::Result<Vec<u8>>;
+
+    /// Last modification time, for backends that can change while running.
+    fn modified(&self, _path: &ResourcePath) -> Option<SystemTime> {
+        None
+    }
+
+    /// Replace file contents, only loose files can be written.
+    fn write(&self, path: &ResourcePath, _contents: &[u8]) -> io::Result<()> {
+        Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("Resource {} is read-only", path),
+        ))
+    }
+
+    /// Delete a file, only loose files can be removed.
+    fn remove(&self, path: &ResourcePath) -> io::Result<()> {
+        Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("Resource {} is read-only", path),
+        ))
+    }
+}
+
+/// Loose files in a directory.
+pub struct FileSystem {
+    root_path: PathBuf,
+}
+
+impl FileSystem {
+    pub fn new<P: Into<PathBuf>>(root_path: P) -> FileSystem {
+        FileSystem {
+            root_path: root_path.into(),
+        }
+    }
+
+    pub fn root_path(&self) -> &Path {
+        &self.root_path
+    }
+}
+
+impl Backend for FileSystem {
+    fn read(&self, path: &ResourcePath) -> io::Result<Vec<u8>> {
+        fs::read(super::resource_name_to_path(&self.root_path, path))
+    }
+
+    fn modified(&self, path: &ResourcePath) -> Option<SystemTime> {
+        fs::metadata(super::resource_name_to_path(&self.root_path, path))
+            .and_then(|metadata| metadata.modified())
+            .ok()
+    }
+
+    fn write(&self, path: &ResourcePath, contents: &[u8]) -> io::Result<()> {
+        let path = super::resource_name_to_path(&self.root_path, path);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, contents)
+    }
+
+    fn remove(&self, path: &ResourcePath) -> io::Result<()> {
+        fs::remove_file(super::resource_name_to_path(&self.root_path, path))
+    }
+}
+
+/// Files compiled into the executable, usually with `include_bytes!`.
+#[derive(Default)]
+pub struct Embedded {
+    files: HashMap<String, &'static [u8]>,
+}
+
+impl Embedded {
+    pub fn new() -> Embedded {
+        Embedded::default()
+    }
+
+    pub fn with_file<P: AsRef<ResourcePath>>(mut self, path: P, contents: &'static [u8]) -> Self {
+        self.files
+            .insert(path.as_ref().as_clean_str().into(), contents);
+        self
+    }
+}
+
+impl Backend for Embedded {
+    fn read(&self, path: &ResourcePath) -> io::Result<Vec<u8>> {
+        self.files
+            .get(path.as_clean_str())
+            .map(|contents| contents.to_vec())
+            .ok_or_else(|| not_found(path))
+    }
+}
+
+/// Uncompressed tar archive, loaded into memory at once.
+///
+/// Only regular files are read, entries with pax or GNU long names are skipped.
+pub struct TarPack {
+    files: HashMap<String, Vec<u8>>,
+}
+
+const TAR_BLOCK: usize = 512;
+
+impl TarPack {
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<TarPack> {
+        TarPack::from_bytes(&fs::read(path)?)
+    }
+
+    pub fn from_bytes(data: &[u8]) -> io::Result<TarPack> {
+        let mut files = HashMap::new();
+        let mut offset = 0;
+
+        while offset + TAR_BLOCK <= data.len() {
+            let header = &data[offset..offset + TAR_BLOCK];
+            // archive ends with zero blocks
+            if header.iter().all(|&b| b == 0) {
+                break;
+            }
+
+            let size = parse_octal(&header[124..136]).ok_or_else(|| invalid_tar(offset))?;
+            let start = offset + TAR_BLOCK;
+            let end = start + size;
+            if end > data.len() {
+                return Err(invalid_tar(offset));
+            }
+
+            let is_file = header[156] == b'0' || header[156] == 0;
+            if is_file {
+                // only POSIX ustar has a prefix, old GNU headers keep other fields there
+                let mut name = if &header[257..263] == b"ustar\0" {
+                    tar_str(&header[345..500])
+                } else {
+                    String::new()
+                };
+                if !name.is_empty() {
+                    name.push('/');
+                }
+                name.push_str(&tar_str(&header[0..100]));
+                let name = name.trim_start_matches("./").to_string();
+                files.insert(name, data[start..end].to_vec());
+            }
+
+            // contents are padded to whole blocks
+            offset = start + (size + TAR_BLOCK - 1) / TAR_BLOCK * TAR_BLOCK;
+        }
+
+        Ok(TarPack { files })
+    }
+}
+
+impl Backend for TarPack {
+    fn read(&self, path: &ResourcePath) -> io::Result<Vec<u8>> {
+        self.files
+            .get(path.as_clean_str())
+            .cloned()
+            .ok_or_else(|| not_found(path))
+    }
+}
+
+fn tar_str(field: &[u8]) -> String {
+    let len = field.iter().position(|&b| b == 0).unwrap_or(field.len());
+    String::from_utf8_lossy(&field[..len]).into_owned()
+}
+
+fn parse_octal(field: &[u8]) -> Option<usize> {
+    let text = tar_str(field);
+    let text = text.trim();
+    if text.is_empty() {
+        return Some(0);
+    }
+    usize::from_str_radix(text, 8).ok()
+}
+
+fn invalid_tar(offset: usize) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("Invalid tar header at offset {}", offset),
+    )
+}
+
+fn not_found(path: &ResourcePath) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::NotFound,
+        format!("Resource {} is not in the pack", path),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Header and contents of a regular file, padded to whole blocks.
+    fn tar_entry(magic: &[u8], prefix: &[u8], name: &str, contents: &[u8]) -> Vec<u8> {
+        let mut entry = vec![0; TAR_BLOCK];
+        entry[..name.len()].copy_from_slice(name.as_bytes());
+        let size = format!("{:011o}", contents.len());
+        entry[124..135].copy_from_slice(size.as_bytes());
+        entry[156] = b'0';
+        entry[257..257 + magic.len()].copy_from_slice(magic);
+        entry[345..345 + prefix.len()].copy_from_slice(prefix);
+        entry.extend_from_slice(contents);
+        let padding = (TAR_BLOCK - entry.len() % TAR_BLOCK) % TAR_BLOCK;
+        entry.extend(vec![0; padding]);
+        entry
+    }
+
+    fn read(pack: &TarPack, path: &str) -> io::Result<Vec<u8>> {
+        let path: &ResourcePath = path.as_ref();
+        pack.read(path)
+    }
+
+    #[test]
+    fn reads_files_of_tar() {
+        let mut data = tar_entry(b"ustar\x0000", b"", "./shaders/a.vert", b"void main() {}");
+        data.extend(tar_entry(b"ustar\x0000", b"textures", "b.png", &[1; 600]));
+        // old GNU headers keep access and change times where ustar has the prefix
+        data.extend(tar_entry(b"ustar  \0", b"13356121233\0", "c.txt", b"c"));
+        data.extend(vec![0; TAR_BLOCK * 2]);
+
+        let pack = TarPack::from_bytes(&data).unwrap();
+        assert_eq!(read(&pack, "shaders/a.vert").unwrap(), b"void main() {}");
+        assert_eq!(read(&pack, "textures/b.png").unwrap(), vec![1; 600]);
+        assert_eq!(read(&pack, "c.txt").unwrap(), b"c");
+        assert_eq!(
+            read(&pack, "missing.txt").unwrap_err().kind(),
+            io::ErrorKind::NotFound
+        );
+    }
+
+    #[test]
+    fn rejects_truncated_tar() {
+        let mut data = tar_entry(b"ustar\x0000", b"", "a.txt", &[1; 600]);
+        data.truncate(TAR_BLOCK + 100);
+        assert!(TarPack::from_bytes(&data).is_err());
+    }
+}
@@ -0,0 +1,75 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::{Rc, Weak};
+
+/// Values loaded once per key, usually a resource path, and shared while anything holds them.
+///
+/// Entries are weak, so a value is dropped with its last `Rc` and loaded again on the next
+/// request. Clones share the entries.
+pub struct Cache<T> {
+    entries: Rc<RefCell<HashMap<String, Weak<T>>>>,
+}
+
+impl<T> Clone for Cache<T> {
+    fn clone(&self) -> Self {
+        Cache {
+            entries: self.entries.clone(),
+        }
+    }
+}
+
+impl<T> Default for Cache<T> {
+    fn default() -> Self {
+        Cache::new()
+    }
+}
+
+impl<T> Cache<T> {
+    pub fn new() -> Cache<T> {
+        Cache {
+            entries: Rc::new(RefCell::new(HashMap::new())),
+        }
+    }
+
+    /// Value for the key, if anything still holds it.
+    pub fn get(&self, key: &str) -> Option<Rc<T>> {
+        self.entries
+            .borrow()
+            .get(key)
+            .and_then(|entry| entry.upgrade())
+    }
+
+    /// Share the value under the key, replacing the previous one for later requests.
+    pub fn insert(&self, key: &str, value: T) -> Rc<T> {
+        let value = Rc::new(value);
+        let mut entries = self.entries.borrow_mut();
+        // keys of dropped values would otherwise pile up
+        entries.retain(|_, entry| entry.strong_count() > 0);
+        entries.insert(key.to_string(), Rc::downgrade(&value));
+        value
+    }
+
+    /// Value for the key, loaded with `load` if nothing holds it.
+    pub fn get_or_load<E, F>(&self, key: &str, load: F) -> Result<Rc<T>, E>
+    where
+        F: FnOnce() -> Result<T, E>,
+    {
+        match self.get(key) {
+            Some(value) => Ok(value),
+            None => Ok(self.insert(key, load()?)),
+        }
+    }
+
+    /// Number of values still held.
+    pub fn len(&self) -> usize {
+        self.entries
+            .borrow()
+            .values()
+            .filter(|entry| entry.strong_count() > 0)
+            .count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
@@ -0,0 +1,244 @@
+#[derive(Debug, Fail)]
+pub enum Error {
+    #[fail(display = "Unknown compressed image container")]
+    UnknownContainer,
+    #[fail(display = "Compressed image file is truncated")]
+    Truncated,
+    #[fail(display = "Unsupported compressed image format {}", _0)]
+    UnsupportedFormat(String),
+    #[fail(display = "Only 2D images without layers or faces are supported")]
+    UnsupportedLayout,
+    #[fail(display = "KTX2 supercompression is not supported")]
+    UnsupportedSupercompression,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CompressedFormat {
+    Bc1Rgb,
+    Bc1Rgba,
+    Bc2,
+    Bc3,
+    Bc4,
+    Bc5,
+    Bc7,
+    Etc2Rgb8,
+    Etc2Rgba8,
+}
+
+impl CompressedFormat {
+    /// Bytes in a single 4x4 block.
+    pub fn block_size(&self) -> usize {
+        match *self {
+            CompressedFormat::Bc1Rgb
+            | CompressedFormat::Bc1Rgba
+            | CompressedFormat::Bc4
+            | CompressedFormat::Etc2Rgb8 => 8,
+            _ => 16,
+        }
+    }
+
+    /// Size of compressed level data with given pixel dimensions.
+    pub fn level_size(&self, width: u32, height: u32) -> usize {
+        let blocks_x = ::std::cmp::max(1, (width as usize + 3) / 4);
+        let blocks_y = ::std::cmp::max(1, (height as usize + 3) / 4);
+        blocks_x * blocks_y * self.block_size()
+    }
+}
+
+pub struct CompressedLevel {
+    pub width: u32,
+    pub height: u32,
+    pub data: Vec<u8>,
+}
+
+/// Block-compressed image from DDS or KTX2 container, uploaded to GPU as-is.
+pub struct CompressedImage {
+    pub format: CompressedFormat,
+    pub srgb: bool,
+    /// Mipmap chain, starting with the full size image.
+    pub levels: Vec<CompressedLevel>,
+}
+
+impl CompressedImage {
+    pub fn width(&self) -> u32 {
+        self.levels.first().map(|l| l.width).unwrap_or(0)
+    }
+
+    pub fn height(&self) -> u32 {
+        self.levels.first().map(|l| l.height).unwrap_or(0)
+    }
+
+    /// Parse DDS or KTX2 file contents, detected by the file magic.
+    pub fn parse(bytes: &[u8]) -> Result<CompressedImage, Error> {
+        if bytes.starts_with(DDS_MAGIC) {
+            parse_dds(bytes)
+        } else if bytes.starts_with(KTX2_IDENTIFIER) {
+            parse_ktx2(bytes)
+        } else {
+            Err(Error::UnknownContainer)
+        }
+    }
+}
+
+const DDS_MAGIC: &[u8] = b"DDS ";
+const DDS_HEADER_END: usize = 128;
+const DDS_DX10_HEADER_END: usize = DDS_HEADER_END + 20;
+const DDSD_MIPMAPCOUNT: u32 = 0x2_0000;
+
+fn parse_dds(bytes: &[u8]) -> Result<CompressedImage, Error> {
+    let flags = read_u32(bytes, 8)?;
+    let height = read_u32(bytes, 12)?;
+    let width = read_u32(bytes, 16)?;
+    // the count is only valid with the flag, writers leave garbage there otherwise
+    let mip_count = if flags & DDSD_MIPMAPCOUNT != 0 {
+        read_u32(bytes, 28)?
+    } else {
+        1
+    };
+    let four_cc = bytes.get(84..88).ok_or(Error::Truncated)?;
+
+    let (format, srgb, data_start) = if four_cc == b"DX10" {
+        let dxgi_format = read_u32(bytes, DDS_HEADER_END)?;
+        let array_size = read_u32(bytes, DDS_HEADER_END + 12)?;
+        if array_size > 1 {
+            return Err(Error::UnsupportedLayout);
+        }
+        let (format, srgb) = match dxgi_format {
+            71 => (CompressedFormat::Bc1Rgba, false),
+            72 => (CompressedFormat::Bc1Rgba, true),
+            74 => (CompressedFormat::Bc2, false),
+            75 => (CompressedFormat::Bc2, true),
+            77 => (CompressedFormat::Bc3, false),
+            78 => (CompressedFormat::Bc3, true),
+            80 => (CompressedFormat::Bc4, false),
+            83 => (CompressedFormat::Bc5, false),
+            98 => (CompressedFormat::Bc7, false),
+            99 => (CompressedFormat::Bc7, true),
+            other => return Err(Error::UnsupportedFormat(format!("DXGI {}", other))),
+        };
+        (format, srgb, DDS_DX10_HEADER_END)
+    } else {
+        let format = match four_cc {
+            b"DXT1" => CompressedFormat::Bc1Rgba,
+            b"DXT3" => CompressedFormat::Bc2,
+            b"DXT5" => CompressedFormat::Bc3,
+            b"ATI1" | b"BC4U" => CompressedFormat::Bc4,
+            b"ATI2" | b"BC5U" => CompressedFormat::Bc5,
+            other => {
+                return Err(Error::UnsupportedFormat(
+                    String::from_utf8_lossy(other).into_owned(),
+                ))
+            }
+        };
+        (format, false, DDS_HEADER_END)
+    };
+
+    let level_count = clamp_level_count(mip_count, width, height);
+    let mut levels = Vec::with_capacity(level_count as usize);
+    let mut offset = data_start;
+
+    for level in 0..level_count {
+        let level_width = ::std::cmp::max(1, width >> level);
+        let level_height = ::std::cmp::max(1, height >> level);
+        let size = format.level_size(level_width, level_height);
+
+        levels.push(CompressedLevel {
+            width: level_width,
+            height: level_height,
+            data: read_bytes(bytes, offset, size)?.to_vec(),
+        });
+        offset += size;
+    }
+
+    Ok(CompressedImage {
+        format,
+        srgb,
+        levels,
+    })
+}
+
+const KTX2_IDENTIFIER: &[u8] = &[
+    0xAB, 0x4B, 0x54, 0x58, 0x20, 0x32, 0x30, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A,
+];
+const KTX2_LEVEL_INDEX_START: usize = 80;
+
+fn parse_ktx2(bytes: &[u8]) -> Result<CompressedImage, Error> {
+    let vk_format = read_u32(bytes, 12)?;
+    let width = read_u32(bytes, 20)?;
+    let height = read_u32(bytes, 24)?;
+    let depth = read_u32(bytes, 28)?;
+    let layer_count = read_u32(bytes, 32)?;
+    let face_count = read_u32(bytes, 36)?;
+    let level_count = clamp_level_count(read_u32(bytes, 40)?, width, height);
+    let supercompression = read_u32(bytes, 44)?;
+
+    if depth > 0 || layer_count > 0 || face_count != 1 {
+        return Err(Error::UnsupportedLayout);
+    }
+    if supercompression != 0 {
+        return Err(Error::UnsupportedSupercompression);
+    }
+
+    let (format, srgb) = match vk_format {
+        131 => (CompressedFormat::Bc1Rgb, false),
+        132 => (CompressedFormat::Bc1Rgb, true),
+        133 => (CompressedFormat::Bc1Rgba, false),
+        134 => (CompressedFormat::Bc1Rgba, true),
+        135 => (CompressedFormat::Bc2, false),
+        136 => (CompressedFormat::Bc2, true),
+        137 => (CompressedFormat::Bc3, false),
+        138 => (CompressedFormat::Bc3, true),
+        139 => (CompressedFormat::Bc4, false),
+        141 => (CompressedFormat::Bc5, false),
+        145 => (CompressedFormat::Bc7, false),
+        146 => (CompressedFormat::Bc7, true),
+        147 => (CompressedFormat::Etc2Rgb8, false),
+        148 => (CompressedFormat::Etc2Rgb8, true),
+        151 => (CompressedFormat::Etc2Rgba8, false),
+        152 => (CompressedFormat::Etc2Rgba8, true),
+        other => return Err(Error::UnsupportedFormat(format!("VkFormat {}", other))),
+    };
+
+    let mut levels = Vec::with_capacity(level_count as usize);
+
+    for level in 0..level_count {
+        let index = KTX2_LEVEL_INDEX_START + level as usize * 24;
+        let offset = read_u64(bytes, index)? as usize;
+        let length = read_u64(bytes, index + 8)? as usize;
+
+        levels.push(CompressedLevel {
+            width: ::std::cmp::max(1, width >> level),
+            height: ::std::cmp::max(1, height >> level),
+            data: read_bytes(bytes, offset, length)?.to_vec(),
+        });
+    }
+
+    Ok(CompressedImage {
+        format,
+        srgb,
+        levels,
+    })
+}
+
+/// Level count from a header, at least one and at most a full mipmap chain down to 1x1.
+fn clamp_level_count(count: u32, width: u32, height: u32) -> u32 {
+    let full_chain = 32 - ::std::cmp::max(1, ::std::cmp::max(width, height)).leading_zeros();
+    ::std::cmp::min(::std::cmp::max(1, count), full_chain)
+}
+
+/// Bytes of a level, `Error::Truncated` if it is not in the file or the range overflows.
+fn read_bytes(bytes: &[u8], offset: usize, length: usize) -> Result<&[u8], Error> {
+    let end = offset.checked_add(length).ok_or(Error::Truncated)?;
+    bytes.get(offset..end).ok_or(Error::Truncated)
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Result<u32, Error> {
+    let b = bytes.get(offset..offset + 4).ok_or(Error::Truncated)?;
+    Ok(u32::from(b[0]) | u32::from(b[1]) << 8 | u32::from(b[2]) << 16 | u32::from(b[3]) << 24)
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> Result<u64, Error> {
+    let low = read_u32(bytes, offset)?;
+    let high = read_u32(bytes, offset + 4)?;
+    Ok(u64::from(low) | u64::from(high) << 32)
+}
@@ -1,3 +1,5 @@
+use super::compressed_image;
+use super::gltf;
 use super::obj;
 use image;
 use std::io;
@@ -24,6 +26,30 @@ pub enum Error {
         #[cause]
         inner: obj::Error,
     },
+    #[fail(display = "Failed to load {} gltf file", name)]
+    FailedToLoadGltf {
+        name: String,
+        #[cause]
+        inner: gltf::Error,
+    },
+    #[fail(display = "Failed to load compressed image {}", name)]
+    FailedToLoadCompressedImage {
+        name: String,
+        #[cause]
+        inner: compressed_image::Error,
+    },
+    #[fail(display = "Resource load stopped without a result")]
+    LoaderStopped,
+}
+
+impl Error {
+    /// True if the resource does not exist, as opposed to failing to read or parse it.
+    pub fn is_not_found(&self) -> bool {
+        match *self {
+            Error::Io(ref e) => e.kind() == io::ErrorKind::NotFound,
+            _ => false,
+        }
+    }
 }
 
 impl From<io::Error> for Error {
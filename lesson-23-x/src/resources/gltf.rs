@@ -0,0 +1,649 @@
+use crate::mesh;
+use crate::resources::{Backend, ResourcePath, ResourcePathBuf};
+use nalgebra as na;
+use std::io;
+
+#[derive(Debug, Fail)]
+pub enum Error {
+    #[fail(display = "glTF or glb parse error")]
+    ParseError(#[cause] ::gltf::Error),
+    #[fail(display = "Failed to read glTF file")]
+    ReadError(#[cause] io::Error),
+    #[fail(display = "Failed to read buffer {}", uri)]
+    BufferReadError {
+        uri: String,
+        #[cause]
+        inner: io::Error,
+    },
+    #[fail(
+        display = "Buffer {} is embedded as data uri, which is not supported",
+        index
+    )]
+    DataUriNotSupported { index: usize },
+    #[fail(display = "Buffer {} refers to missing glb binary chunk", index)]
+    MissingBinaryChunk { index: usize },
+    #[fail(display = "Primitive {} of mesh {} has no positions", primitive, mesh)]
+    MissingPositions { mesh: usize, primitive: usize },
+    #[fail(
+        display = "Primitive {} of mesh {} has {} {} values for {} positions",
+        primitive, mesh, count, attribute, positions
+    )]
+    AttributeCountMismatch {
+        mesh: usize,
+        primitive: usize,
+        attribute: &'static str,
+        count: usize,
+        positions: usize,
+    },
+    #[fail(
+        display = "Primitive {} of mesh {} refers to vertex {} of {}",
+        primitive, mesh, index, vertices
+    )]
+    IndexOutOfRange {
+        mesh: usize,
+        primitive: usize,
+        index: u32,
+        vertices: usize,
+    },
+    #[fail(display = "Resource path must not be empty")]
+    ResourcePathMustNotBeEmpty,
+}
+
+impl From<::gltf::Error> for Error {
+    fn from(other: ::gltf::Error) -> Self {
+        Error::ParseError(other)
+    }
+}
+
+pub struct GltfScene;
+
+impl GltfScene {
+    /// Load .gltf or .glb file into a mesh set.
+    ///
+    /// Every primitive becomes a separate mesh, with node transforms of the default scene
+    /// baked into vertex positions, normals and tangents. Skinned meshes are kept in bind
+    /// space, and their skins and animations are loaded as skeletons and animation clips.
+    ///
+    /// Triangle strips and fans are converted to triangles, point and line primitives are
+    /// listed in `MeshSet::skipped`.
+    pub fn load(
+        backend: &dyn Backend,
+        resource_path: &ResourcePath,
+    ) -> Result<mesh::MeshSet, Error> {
+        let resource_dir = resource_path
+            .parent()
+            .ok_or(Error::ResourcePathMustNotBeEmpty)?;
+
+        let contents = backend.read(resource_path).map_err(Error::ReadError)?;
+        let ::gltf::Gltf { document, blob } = ::gltf::Gltf::from_slice(&contents)?;
+
+        let mut buffers = Vec::with_capacity(document.buffers().len());
+        for buffer in document.buffers() {
+            let data = match buffer.source() {
+                ::gltf::buffer::Source::Bin => blob.clone().ok_or(Error::MissingBinaryChunk {
+                    index: buffer.index(),
+                })?,
+                ::gltf::buffer::Source::Uri(uri) => {
+                    if uri.starts_with("data:") {
+                        return Err(Error::DataUriNotSupported {
+                            index: buffer.index(),
+                        });
+                    }
+                    let buffer_path = resource_dir.join(uri_to_rel_resource_path(uri));
+                    backend
+                        .read(&buffer_path)
+                        .map_err(|e| Error::BufferReadError {
+                            uri: uri.into(),
+                            inner: e,
+                        })?
+                }
+            };
+            buffers.push(data);
+        }
+
+        let mapped_materials = document
+            .materials()
+            .map(|m| mesh::Material {
+                name: m.name().map(|n| n.into()),
+                diffuse_map: m
+                    .pbr_metallic_roughness()
+                    .base_color_texture()
+                    .and_then(|info| image_resource_path(resource_dir, &info.texture())),
+                bump_map: m
+                    .normal_texture()
+                    .and_then(|info| image_resource_path(resource_dir, &info.texture())),
+                metallic_map: m
+                    .pbr_metallic_roughness()
+                    .metallic_roughness_texture()
+                    .and_then(|info| image_resource_path(resource_dir, &info.texture())),
+                roughness_map: m
+                    .pbr_metallic_roughness()
+                    .metallic_roughness_texture()
+                    .and_then(|info| image_resource_path(resource_dir, &info.texture())),
+                occlusion_map: m
+                    .occlusion_texture()
+                    .filter(|info| info.tex_coord() == 0)
+                    .and_then(|info| image_resource_path(resource_dir, &info.texture())),
+                height_map: None,
+                // occlusion baked for the second coordinate set is used as a lightmap
+                lightmap: m
+                    .occlusion_texture()
+                    .filter(|info| info.tex_coord() == 1)
+                    .and_then(|info| image_resource_path(resource_dir, &info.texture())),
+                // vertex colors multiply the base color in glTF
+                vertex_colors: true,
+                double_sided: m.double_sided(),
+                metallic: Some(m.pbr_metallic_roughness().metallic_factor()),
+                roughness: Some(m.pbr_metallic_roughness().roughness_factor()),
+            })
+            .collect::<Vec<_>>();
+
+        let mut mapped_meshes = Vec::new();
+        let mut skipped = Vec::new();
+
+        let nodes = document.nodes().collect::<Vec<_>>();
+        let skins = document
+            .skins()
+            .map(|skin| map_skin(&skin, &nodes, &buffers))
+            .collect::<Vec<_>>();
+        let mapped_animations = document
+            .animations()
+            .flat_map(|animation| map_animation(&animation, &skins, &buffers))
+            .collect::<Vec<_>>();
+        let root_nodes = match document
+            .default_scene()
+            .or_else(|| document.scenes().next())
+        {
+            Some(scene) => scene.nodes().map(|node| node.index()).collect::<Vec<_>>(),
+            None => Vec::new(),
+        };
+
+        let mut stack = root_nodes
+            .into_iter()
+            .map(|index| (index, na::Matrix4::<f32>::identity()))
+            .collect::<Vec<_>>();
+
+        while let Some((node_index, parent_transform)) = stack.pop() {
+            let node = &nodes[node_index];
+            let world_transform = parent_transform * node_matrix(node);
+
+            if let Some(gltf_mesh) = node.mesh() {
+                let skin = node.skin().map(|skin| (skin.index(), &skins[skin.index()]));
+                // skinned vertices stay in bind space, the skeleton moves them
+                let mesh_transform = match skin {
+                    Some(_) => na::Matrix4::identity(),
+                    None => world_transform,
+                };
+
+                for primitive in gltf_mesh.primitives() {
+                    let mapped = map_primitive_to_mesh(
+                        &gltf_mesh,
+                        &primitive,
+                        &buffers,
+                        &mesh_transform,
+                        &mapped_materials,
+                        skin,
+                    )?;
+                    match mapped {
+                        Some(mesh) => mapped_meshes.push(mesh),
+                        None => skipped.push(format!(
+                            "primitive {} of mesh {} with mode {:?}",
+                            primitive.index(),
+                            gltf_mesh.index(),
+                            primitive.mode()
+                        )),
+                    }
+                }
+            }
+
+            for child in node.children() {
+                stack.push((child.index(), world_transform));
+            }
+        }
+
+        Ok(mesh::MeshSet {
+            materials: mapped_materials,
+            meshes: mapped_meshes,
+            skeletons: skins.into_iter().map(|s| s.skeleton).collect(),
+            animations: mapped_animations,
+            skipped,
+            optimize_stats: Vec::new(),
+        })
+    }
+}
+
+struct MappedSkin {
+    skeleton: mesh::Skeleton,
+    /// Joint index in the mapped skeleton for every joint in glTF skin order.
+    joint_remap: Vec<u16>,
+    /// Node index for every joint in the mapped skeleton.
+    joint_nodes: Vec<usize>,
+}
+
+fn map_skin(skin: &::gltf::Skin, nodes: &[::gltf::Node], buffers: &[Vec<u8>]) -> MappedSkin {
+    let mut node_parents = vec![None; nodes.len()];
+    for node in nodes {
+        for child in node.children() {
+            node_parents[child.index()] = Some(node.index());
+        }
+    }
+
+    let depth = |mut index: usize| {
+        let mut depth = 0;
+        while let Some(parent) = node_parents[index] {
+            depth += 1;
+            index = parent;
+        }
+        depth
+    };
+
+    let skin_joint_nodes = skin.joints().map(|j| j.index()).collect::<Vec<_>>();
+    let inverse_binds = skin
+        .reader(|buffer| buffers.get(buffer.index()).map(|b| &b[..]))
+        .read_inverse_bind_matrices()
+        .map(|m| m.collect::<Vec<_>>())
+        .unwrap_or_default();
+
+    // order joints so that parents come before children
+    let mut order = (0..skin_joint_nodes.len()).collect::<Vec<_>>();
+    order.sort_by_key(|&joint| depth(skin_joint_nodes[joint]));
+
+    let mut joint_remap = vec![0; skin_joint_nodes.len()];
+    for (mapped_index, &joint) in order.iter().enumerate() {
+        joint_remap[joint] = mapped_index as u16;
+    }
+
+    let joint_nodes = order
+        .iter()
+        .map(|&joint| skin_joint_nodes[joint])
+        .collect::<Vec<_>>();
+
+    let joints = order
+        .iter()
+        .map(|&joint| {
+            let node = &nodes[skin_joint_nodes[joint]];
+
+            // nearest ancestor which is a joint of this skin, other nodes on the way still
+            // move the joint
+            let mut parent = None;
+            let mut offset = na::Matrix4::identity();
+            let mut ancestor = node_parents[node.index()];
+            while let Some(ancestor_index) = ancestor {
+                if let Some(parent_joint) =
+                    skin_joint_nodes.iter().position(|n| *n == ancestor_index)
+                {
+                    parent = Some(joint_remap[parent_joint] as usize);
+                    break;
+                }
+                offset = node_matrix(&nodes[ancestor_index]) * offset;
+                ancestor = node_parents[ancestor_index];
+            }
+
+            let (t, r, s) = node.transform().decomposed();
+
+            mesh::Joint {
+                name: node.name().map(|n| n.into()),
+                parent,
+                inverse_bind: inverse_binds
+                    .get(joint)
+                    .map(gltf_matrix)
+                    .unwrap_or_else(na::Matrix4::identity),
+                rest: mesh::JointTransform {
+                    translation: na::Vector3::new(t[0], t[1], t[2]),
+                    rotation: gltf_rotation(r),
+                    scale: na::Vector3::new(s[0], s[1], s[2]),
+                },
+                offset,
+            }
+        }).collect::<Vec<_>>();
+
+    MappedSkin {
+        skeleton: mesh::Skeleton {
+            name: skin.name().map(|n| n.into()),
+            joints,
+        },
+        joint_remap,
+        joint_nodes,
+    }
+}
+
+/// Split animation into clips, one for every skin it animates.
+fn map_animation(
+    animation: &::gltf::Animation,
+    skins: &[MappedSkin],
+    buffers: &[Vec<u8>],
+) -> Vec<mesh::AnimationClip> {
+    let mut clips = Vec::new();
+
+    for (skeleton_index, skin) in skins.iter().enumerate() {
+        let mut duration: f32 = 0.0;
+        let mut channels = Vec::new();
+
+        for channel in animation.channels() {
+            let target_node = channel.target().node().index();
+            let joint = match skin.joint_nodes.iter().position(|n| *n == target_node) {
+                Some(joint) => joint,
+                None => continue,
+            };
+
+            let reader = channel.reader(|buffer| buffers.get(buffer.index()).map(|b| &b[..]));
+            let times = match reader.read_inputs() {
+                Some(inputs) => inputs.collect::<Vec<_>>(),
+                None => continue,
+            };
+            let interpolation = match channel.sampler().interpolation() {
+                ::gltf::animation::Interpolation::Step => mesh::Interpolation::Step,
+                ::gltf::animation::Interpolation::CubicSpline => mesh::Interpolation::CubicSpline,
+                // Catmull-Rom is not part of glTF 2.0, keyframes hold plain values
+                _ => mesh::Interpolation::Linear,
+            };
+            let cubic = interpolation == mesh::Interpolation::CubicSpline;
+
+            let vector = |v: [f32; 3]| na::Vector4::new(v[0], v[1], v[2], 0.0);
+            let (values, tangents) = match reader.read_outputs() {
+                Some(::gltf::animation::util::ReadOutputs::Translations(values)) => {
+                    let (values, tangents) = keyframe_values(values, cubic, vector);
+                    let values = values.iter().map(|v| na::Vector3::new(v[0], v[1], v[2]));
+                    (mesh::ChannelValues::Translation(values.collect()), tangents)
+                }
+                Some(::gltf::animation::util::ReadOutputs::Rotations(values)) => {
+                    let (values, tangents) =
+                        keyframe_values(values.into_f32(), cubic, |r| {
+                            na::Vector4::new(r[0], r[1], r[2], r[3])
+                        });
+                    let values = values.into_iter().map(gltf_rotation);
+                    (mesh::ChannelValues::Rotation(values.collect()), tangents)
+                }
+                Some(::gltf::animation::util::ReadOutputs::Scales(values)) => {
+                    let (values, tangents) = keyframe_values(values, cubic, vector);
+                    let values = values.iter().map(|v| na::Vector3::new(v[0], v[1], v[2]));
+                    (mesh::ChannelValues::Scale(values.collect()), tangents)
+                }
+                _ => continue,
+            };
+
+            if let Some(last) = times.last() {
+                duration = duration.max(*last);
+            }
+
+            channels.push(mesh::Channel {
+                joint,
+                times,
+                interpolation,
+                values,
+                tangents,
+            });
+        }
+
+        if !channels.is_empty() {
+            clips.push(mesh::AnimationClip {
+                name: animation.name().map(|n| n.into()),
+                skeleton_index,
+                duration,
+                channels,
+            });
+        }
+    }
+
+    clips
+}
+
+/// Keyframe values, and the in and out tangents of each keyframe for cubic splines, which
+/// store in-tangent, value and out-tangent.
+fn keyframe_values<T, I, F>(
+    values: I,
+    cubic: bool,
+    tangent: F,
+) -> (Vec<T>, Vec<mesh::KeyframeTangents>)
+where
+    I: Iterator<Item = T>,
+    F: Fn(T) -> na::Vector4<f32>,
+{
+    if !cubic {
+        return (values.collect(), Vec::new());
+    }
+    let mut keyframes = Vec::new();
+    let mut tangents = Vec::new();
+    let mut values = values;
+    while let (Some(in_tangent), Some(value), Some(out_tangent)) =
+        (values.next(), values.next(), values.next())
+    {
+        keyframes.push(value);
+        tangents.push((tangent(in_tangent), tangent(out_tangent)));
+    }
+    (keyframes, tangents)
+}
+
+fn gltf_rotation(r: [f32; 4]) -> na::UnitQuaternion<f32> {
+    // glTF stores quaternion as x, y, z, w
+    na::UnitQuaternion::from_quaternion(na::Quaternion::new(r[3], r[0], r[1], r[2]))
+}
+
+fn gltf_matrix(m: &[[f32; 4]; 4]) -> na::Matrix4<f32> {
+    // glTF matrices are column-major, same as the nalgebra storage
+    na::Matrix4::new(
+        m[0][0], m[1][0], m[2][0], m[3][0], m[0][1], m[1][1], m[2][1], m[3][1], m[0][2], m[1][2],
+        m[2][2], m[3][2], m[0][3], m[1][3], m[2][3], m[3][3],
+    )
+}
+
+fn node_matrix(node: &::gltf::Node) -> na::Matrix4<f32> {
+    gltf_matrix(&node.transform().matrix())
+}
+
+/// Mesh of a triangle primitive, `None` for points and lines.
+fn map_primitive_to_mesh(
+    gltf_mesh: &::gltf::Mesh,
+    primitive: &::gltf::Primitive,
+    buffers: &[Vec<u8>],
+    transform: &na::Matrix4<f32>,
+    mapped_materials: &[mesh::Material],
+    skin: Option<(usize, &MappedSkin)>,
+) -> Result<Option<mesh::Mesh>, Error> {
+    match primitive.mode() {
+        ::gltf::mesh::Mode::Triangles
+        | ::gltf::mesh::Mode::TriangleStrip
+        | ::gltf::mesh::Mode::TriangleFan => (),
+        _ => return Ok(None),
+    }
+
+    let reader = primitive.reader(|buffer| buffers.get(buffer.index()).map(|b| &b[..]));
+
+    let positions = reader
+        .read_positions()
+        .ok_or(Error::MissingPositions {
+            mesh: gltf_mesh.index(),
+            primitive: primitive.index(),
+        })?
+        .collect::<Vec<_>>();
+    let normals = reader.read_normals().map(|n| n.collect::<Vec<_>>());
+    let tangents = reader.read_tangents().map(|t| t.collect::<Vec<_>>());
+    let texcoords = reader
+        .read_tex_coords(0)
+        .map(|t| t.into_f32().collect::<Vec<_>>());
+    let texcoords2 = reader
+        .read_tex_coords(1)
+        .map(|t| t.into_f32().collect::<Vec<_>>());
+    let colors = reader
+        .read_colors(0)
+        .map(|c| c.into_rgba_f32().collect::<Vec<_>>());
+    let joints = reader.read_joints(0).map(|j| j.into_u16().collect::<Vec<_>>());
+    let weights = reader
+        .read_weights(0)
+        .map(|w| w.into_f32().collect::<Vec<_>>());
+
+    // vertex attributes are indexed by position below
+    let check_count = |attribute: &'static str, count: Option<usize>| match count {
+        Some(count) if count != positions.len() => Err(Error::AttributeCountMismatch {
+            mesh: gltf_mesh.index(),
+            primitive: primitive.index(),
+            attribute,
+            count,
+            positions: positions.len(),
+        }),
+        _ => Ok(()),
+    };
+    check_count("normal", normals.as_ref().map(Vec::len))?;
+    check_count("tangent", tangents.as_ref().map(Vec::len))?;
+    check_count("texcoord", texcoords.as_ref().map(Vec::len))?;
+    check_count("second texcoord", texcoords2.as_ref().map(Vec::len))?;
+    check_count("color", colors.as_ref().map(Vec::len))?;
+    check_count("joint", joints.as_ref().map(Vec::len))?;
+    check_count("weight", weights.as_ref().map(Vec::len))?;
+
+    let normal_transform = transform
+        .fixed_slice::<na::U3, na::U3>(0, 0)
+        .try_inverse()
+        .map(|m| m.transpose())
+        .unwrap_or_else(na::Matrix3::identity);
+    let tangent_transform = transform.fixed_slice::<na::U3, na::U3>(0, 0).into_owned();
+
+    let mut vertices = Vec::with_capacity(positions.len());
+
+    for (index, p) in positions.iter().enumerate() {
+        let normal = normals.as_ref().map(|n| {
+            (normal_transform * na::Vector3::new(n[index][0], n[index][1], n[index][2])).normalize()
+        });
+
+        vertices.push(mesh::Vertex {
+            pos: transform.transform_point(&na::Point3::new(p[0], p[1], p[2])),
+            normal,
+            tangents: match (normal, tangents.as_ref()) {
+                (Some(normal), Some(t)) => {
+                    let tangent = (tangent_transform
+                        * na::Vector3::new(t[index][0], t[index][1], t[index][2]))
+                    .normalize();
+                    Some(mesh::Tangents {
+                        tangent,
+                        bitangent: normal.cross(&tangent) * t[index][3],
+                    })
+                }
+                _ => None,
+            },
+            // flip v to match the obj texture coordinate convention
+            uv: texcoords
+                .as_ref()
+                .map(|t| [t[index][0], 1.0 - t[index][1]].into()),
+            uv2: texcoords2
+                .as_ref()
+                .map(|t| [t[index][0], 1.0 - t[index][1]].into()),
+            color: colors.as_ref().map(|c| c[index].into()),
+            bones: match (skin, joints.as_ref(), weights.as_ref()) {
+                (Some((_, skin)), Some(j), Some(w)) => {
+                    let remap = |joint: u16| {
+                        skin.joint_remap
+                            .get(joint as usize)
+                            .cloned()
+                            .unwrap_or(0)
+                    };
+                    Some(mesh::BoneWeights {
+                        joints: [
+                            remap(j[index][0]),
+                            remap(j[index][1]),
+                            remap(j[index][2]),
+                            remap(j[index][3]),
+                        ],
+                        weights: w[index],
+                    })
+                }
+                _ => None,
+            },
+        });
+    }
+
+    let default_weights = gltf_mesh.weights().unwrap_or(&[]);
+    let morph_targets = reader
+        .read_morph_targets()
+        .enumerate()
+        .map(|(index, (positions, normals, _))| {
+            // deltas are directions, so they only take the linear part of the transform
+            mesh::MorphTarget {
+                positions: positions.map_or_else(Vec::new, |p| {
+                    p.map(|p| tangent_transform * na::Vector3::new(p[0], p[1], p[2]))
+                        .collect()
+                }),
+                normals: normals.map_or_else(Vec::new, |n| {
+                    n.map(|n| normal_transform * na::Vector3::new(n[0], n[1], n[2]))
+                        .collect()
+                }),
+                default_weight: default_weights.get(index).cloned().unwrap_or(0.0),
+            }
+        }).collect::<Vec<_>>();
+    for target in &morph_targets {
+        check_count("morph target position", Some(target.positions.len()).filter(|&n| n > 0))?;
+        check_count("morph target normal", Some(target.normals.len()).filter(|&n| n > 0))?;
+    }
+
+    let indices = match reader.read_indices() {
+        Some(indices) => indices.into_u32().collect::<Vec<_>>(),
+        None => (0..vertices.len() as u32).collect::<Vec<_>>(),
+    };
+    if let Some(&index) = indices.iter().find(|&&index| index as usize >= vertices.len()) {
+        return Err(Error::IndexOutOfRange {
+            mesh: gltf_mesh.index(),
+            primitive: primitive.index(),
+            index,
+            vertices: vertices.len(),
+        });
+    }
+
+    let primitives = triangles(primitive.mode(), &indices);
+
+    let mut mesh = mesh::Mesh {
+        name: gltf_mesh.name().map(|n| n.into()),
+        skeleton_index: skin.map(|(index, _)| index),
+        vertices,
+        primitives,
+        submeshes: Vec::new(),
+        morph_targets,
+        material_index: primitive
+            .material()
+            .index()
+            .filter(|&id| id < mapped_materials.len()),
+    };
+
+    if tangents.is_none() {
+        mesh.calculate_tangents();
+    }
+
+    Ok(Some(mesh))
+}
+
+/// Triangles of a triangle list, strip or fan.
+fn triangles(mode: ::gltf::mesh::Mode, indices: &[u32]) -> Vec<mesh::Primitive> {
+    match mode {
+        ::gltf::mesh::Mode::TriangleStrip => (2..indices.len())
+            .map(|i| {
+                // every other triangle of a strip is flipped to keep the winding
+                if i % 2 == 0 {
+                    mesh::Primitive::Triangle(indices[i - 2], indices[i - 1], indices[i])
+                } else {
+                    mesh::Primitive::Triangle(indices[i - 1], indices[i - 2], indices[i])
+                }
+            }).collect(),
+        ::gltf::mesh::Mode::TriangleFan => (2..indices.len())
+            .map(|i| mesh::Primitive::Triangle(indices[0], indices[i - 1], indices[i]))
+            .collect(),
+        _ => indices
+            .chunks(3)
+            .filter(|c| c.len() == 3)
+            .map(|c| mesh::Primitive::Triangle(c[0], c[1], c[2]))
+            .collect(),
+    }
+}
+
+fn image_resource_path(
+    resource_dir: &ResourcePath,
+    texture: &::gltf::texture::Texture,
+) -> Option<ResourcePathBuf> {
+    match texture.source().source() {
+        ::gltf::image::Source::Uri { uri, .. } if !uri.starts_with("data:") => {
+            Some(resource_dir.join(uri_to_rel_resource_path(uri)))
+        }
+        _ => None,
+    }
+}
+
+fn uri_to_rel_resource_path(value: &str) -> ResourcePathBuf {
+    value.replace("%20", " ").replace('\\', "/").into()
+}
@@ -0,0 +1,53 @@
+use std::cell::RefCell;
+use std::fmt;
+
+thread_local! {
+    static LOAD_ERRORS: RefCell<Vec<LoadError>> = RefCell::new(Vec::new());
+}
+
+/// Resource that failed to load, with the error message.
+#[derive(Clone, Debug)]
+pub struct LoadError {
+    pub path: String,
+    pub message: String,
+}
+
+/// Resources that failed to load on this thread and have not loaded since, listed by the
+/// error console.
+///
+/// Loaders report failures where they fall back to a placeholder, and resolve them once
+/// a retry succeeds.
+pub struct LoadErrors;
+
+impl LoadErrors {
+    /// Record the failure, replacing an earlier one for the same path.
+    pub fn report<E: fmt::Display>(path: &str, error: E) {
+        let message = error.to_string();
+        LOAD_ERRORS.with(|errors| {
+            let mut errors = errors.borrow_mut();
+            match errors.iter_mut().find(|e| e.path == path) {
+                Some(ref existing) if existing.message == message => return,
+                Some(existing) => existing.message = message.clone(),
+                None => errors.push(LoadError {
+                    path: path.into(),
+                    message: message.clone(),
+                }),
+            }
+            println!("Error loading {}: {}", path, message);
+        });
+    }
+
+    /// Forget the failure after the resource has loaded.
+    pub fn resolve(path: &str) {
+        LOAD_ERRORS.with(|errors| errors.borrow_mut().retain(|e| e.path != path));
+    }
+
+    /// Failures in the order they were first reported.
+    pub fn list() -> Vec<LoadError> {
+        LOAD_ERRORS.with(|errors| errors.borrow().clone())
+    }
+
+    pub fn is_empty() -> bool {
+        LOAD_ERRORS.with(|errors| errors.borrow().is_empty())
+    }
+}
@@ -1,16 +1,30 @@
+use failure;
 use image;
 use crate::mesh;
 use std::ffi;
-use std::fs;
-use std::io::Read;
+use std::io;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
+mod async_load;
+pub mod backend;
+mod cache;
+pub mod compressed_image;
 mod error;
+pub mod gltf;
+mod load_errors;
 pub mod obj;
 mod path;
+mod watch;
 
+pub use self::async_load::LoadHandle;
+pub use self::backend::{Backend, Embedded, FileSystem, TarPack};
+pub use self::cache::Cache;
+pub use self::compressed_image::{CompressedFormat, CompressedImage};
 pub use self::error::Error;
+pub use self::load_errors::{LoadError, LoadErrors};
 pub use self::path::{ResourcePath, ResourcePathBuf};
+pub use self::watch::Watch;
 
 pub struct ImportedModels {
     pub imported_from_resource_path: ResourcePathBuf,
@@ -18,31 +32,68 @@ pub struct ImportedModels {
     pub materials: Vec<::tobj::Material>,
 }
 
+#[derive(Clone)]
 pub struct Resources {
-    root_path: PathBuf,
+    backend: Arc<dyn Backend>,
 }
 
 impl Resources {
     pub fn from_relative_exe_path<P: AsRef<ResourcePath>>(rel_path: P) -> Result<Resources, Error> {
-        let exe_file_name = ::std::env::current_exe().map_err(|_| Error::FailedToGetExePath)?;
-
-        let exe_path = exe_file_name.parent().ok_or(Error::FailedToGetExePath)?;
-
-        Ok(Resources {
-            root_path: resource_name_to_path(&exe_path, rel_path.as_ref()),
-        })
+        Ok(Resources::from_backend(FileSystem::new(resource_name_to_path(
+            &exe_dir()?,
+            rel_path.as_ref(),
+        ))))
     }
 
     pub fn from_exe_path() -> Result<Resources, Error> {
         Resources::from_relative_exe_path("")
     }
 
-    pub fn load_cstring<P: AsRef<ResourcePath>>(&self, rel_path: P) -> Result<ffi::CString, Error> {
-        let mut file = fs::File::open(resource_name_to_path(&self.root_path, rel_path.as_ref()))?;
+    /// Serve resources from a tar archive next to the executable.
+    pub fn from_relative_exe_pack<P: AsRef<ResourcePath>>(rel_path: P) -> Result<Resources, Error> {
+        let pack = TarPack::open(resource_name_to_path(&exe_dir()?, rel_path.as_ref()))?;
+        Ok(Resources::from_backend(pack))
+    }
+
+    /// Serve resources compiled into the executable.
+    pub fn from_embedded(files: Embedded) -> Resources {
+        Resources::from_backend(files)
+    }
+
+    pub fn from_backend<B: Backend + 'static>(backend: B) -> Resources {
+        Resources {
+            backend: Arc::new(backend),
+        }
+    }
 
-        // allocate buffer of the same size as file
-        let mut buffer: Vec<u8> = Vec::with_capacity(file.metadata()?.len() as usize + 1);
-        file.read_to_end(&mut buffer)?;
+    /// Start watching the file for changes, more files can be added to the returned watch.
+    pub fn watch<P: AsRef<ResourcePath>>(&self, rel_path: P) -> Watch {
+        Watch::new(self.backend.clone()).with(rel_path)
+    }
+
+    /// Read whole resource file.
+    pub fn load_bytes<P: AsRef<ResourcePath>>(&self, rel_path: P) -> Result<Vec<u8>, Error> {
+        Ok(self.backend.read(rel_path.as_ref())?)
+    }
+
+    /// Write whole resource file, i.e. settings saved by the lesson.
+    pub fn write_bytes<P: AsRef<ResourcePath>>(
+        &self,
+        rel_path: P,
+        contents: &[u8],
+    ) -> Result<(), Error> {
+        Ok(self.backend.write(rel_path.as_ref(), contents)?)
+    }
+
+    /// Delete a resource file written with `write_bytes`.
+    pub fn remove<P: AsRef<ResourcePath>>(&self, rel_path: P) -> Result<(), Error> {
+        Ok(self.backend.remove(rel_path.as_ref())?)
+    }
+
+    pub fn load_cstring<P: AsRef<ResourcePath>>(&self, rel_path: P) -> Result<ffi::CString, Error> {
+        let mut buffer = self.load_bytes(rel_path)?;
+        // room for the nul terminator
+        buffer.reserve_exact(1);
 
         // check for nul byte
         if buffer.iter().find(|i| **i == 0).is_some() {
@@ -56,12 +107,7 @@ impl Resources {
         &self,
         rel_path: P,
     ) -> Result<image::RgbImage, Error> {
-        let img = image::open(resource_name_to_path(&self.root_path, rel_path.as_ref())).map_err(
-            |e| Error::FailedToLoadImage {
-                name: rel_path.as_ref().to_string(),
-                inner: e,
-            },
-        )?;
+        let img = self.load_image(rel_path.as_ref())?;
 
         Ok(img.to_rgb())
     }
@@ -70,12 +116,7 @@ impl Resources {
         &self,
         rel_path: P,
     ) -> Result<image::RgbaImage, Error> {
-        let img = image::open(resource_name_to_path(&self.root_path, rel_path.as_ref())).map_err(
-            |e| Error::FailedToLoadImage {
-                name: rel_path.as_ref().to_string(),
-                inner: e,
-            },
-        )?;
+        let img = self.load_image(rel_path.as_ref())?;
 
         if let image::ColorType::RGBA(_) = img.color() {
             Ok(img.to_rgba())
@@ -86,14 +127,125 @@ impl Resources {
         }
     }
 
+    /// Load image with a single 8-bit channel, i.e. a heightmap.
+    pub fn load_luma_image<P: AsRef<ResourcePath>>(
+        &self,
+        rel_path: P,
+    ) -> Result<image::GrayImage, Error> {
+        let img = self.load_image(rel_path.as_ref())?;
+
+        Ok(img.to_luma())
+    }
+
+    /// Load Radiance HDR image with linear floating point color.
+    pub fn load_hdr_image<P: AsRef<ResourcePath>>(
+        &self,
+        rel_path: P,
+    ) -> Result<image::ImageBuffer<image::Rgb<f32>, Vec<f32>>, Error> {
+        let file = self.load_bytes(rel_path.as_ref())?;
+
+        let image_error = |e| Error::FailedToLoadImage {
+            name: rel_path.as_ref().to_string(),
+            inner: e,
+        };
+
+        let decoder = image::hdr::HDRDecoder::new(io::Cursor::new(file)).map_err(image_error)?;
+        let metadata = decoder.metadata();
+        let pixels = decoder.read_image_hdr().map_err(image_error)?;
+
+        Ok(image::ImageBuffer::from_raw(
+            metadata.width,
+            metadata.height,
+            pixels.iter().flat_map(|p| p.data.iter().cloned()).collect(),
+        ).expect("decoded HDR image should match its dimensions"))
+    }
+
+    /// Load DDS or KTX2 image without decompressing it.
+    pub fn load_compressed_image<P: AsRef<ResourcePath>>(
+        &self,
+        rel_path: P,
+    ) -> Result<CompressedImage, Error> {
+        let buffer = self.load_bytes(rel_path.as_ref())?;
+
+        CompressedImage::parse(&buffer).map_err(|e| Error::FailedToLoadCompressedImage {
+            name: rel_path.as_ref().to_string(),
+            inner: e,
+        })
+    }
+
+    pub fn load_gltf<P: AsRef<ResourcePath>>(&self, rel_path: P) -> Result<mesh::MeshSet, Error> {
+        gltf::GltfScene::load(&*self.backend, rel_path.as_ref()).map_err(|e| {
+            Error::FailedToLoadGltf {
+                name: rel_path.as_ref().to_string(),
+                inner: e,
+            }
+        })
+    }
+
     pub fn load_obj<P: AsRef<ResourcePath>>(&self, rel_path: P) -> Result<mesh::MeshSet, Error> {
-        obj::ModelsWithMaterials::load(&self.root_path, rel_path.as_ref()).map_err(|e| {
+        obj::ModelsWithMaterials::load(&*self.backend, rel_path.as_ref()).map_err(|e| {
             Error::FailedToLoadObj {
                 name: rel_path.as_ref().to_string(),
                 inner: e,
             }
         })
     }
+
+    /// Load .gltf and .glb files with `load_gltf`, anything else with `load_obj`.
+    pub fn load_model<P: AsRef<ResourcePath>>(&self, rel_path: P) -> Result<mesh::MeshSet, Error> {
+        let path = rel_path.as_ref();
+        let name = path.as_clean_str().to_lowercase();
+        if name.ends_with(".gltf") || name.ends_with(".glb") {
+            self.load_gltf(path)
+        } else {
+            self.load_obj(path)
+        }
+    }
+
+    /// Run custom load on background loader thread, with a copy of these resources.
+    pub fn load_async<T, F>(&self, load: F) -> LoadHandle<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&Resources) -> Result<T, failure::Error> + Send + 'static,
+    {
+        let res = self.clone();
+        async_load::spawn(move || load(&res))
+    }
+
+    pub fn load_obj_async<P: AsRef<ResourcePath>>(&self, rel_path: P) -> LoadHandle<mesh::MeshSet> {
+        let rel_path = ResourcePathBuf::from(rel_path.as_ref());
+        self.load_async(move |res| Ok(res.load_obj(&rel_path)?))
+    }
+
+    pub fn load_rgb_image_async<P: AsRef<ResourcePath>>(
+        &self,
+        rel_path: P,
+    ) -> LoadHandle<image::RgbImage> {
+        let rel_path = ResourcePathBuf::from(rel_path.as_ref());
+        self.load_async(move |res| Ok(res.load_rgb_image(&rel_path)?))
+    }
+
+    pub fn load_rgba_image_async<P: AsRef<ResourcePath>>(
+        &self,
+        rel_path: P,
+    ) -> LoadHandle<image::RgbaImage> {
+        let rel_path = ResourcePathBuf::from(rel_path.as_ref());
+        self.load_async(move |res| Ok(res.load_rgba_image(&rel_path)?))
+    }
+
+    fn load_image(&self, rel_path: &ResourcePath) -> Result<image::DynamicImage, Error> {
+        let bytes = self.load_bytes(rel_path)?;
+        image::load_from_memory(&bytes).map_err(|e| Error::FailedToLoadImage {
+            name: rel_path.to_string(),
+            inner: e,
+        })
+    }
+}
+
+fn exe_dir() -> Result<PathBuf, Error> {
+    let exe_file_name = ::std::env::current_exe().map_err(|_| Error::FailedToGetExePath)?;
+    let exe_path = exe_file_name.parent().ok_or(Error::FailedToGetExePath)?;
+    Ok(exe_path.into())
 }
 
 fn resource_name_to_path(root_dir: &Path, location: &ResourcePath) -> PathBuf {
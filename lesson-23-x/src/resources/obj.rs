@@ -1,5 +1,7 @@
 use crate::mesh;
-use crate::resources::{ResourcePath, ResourcePathBuf};
+use crate::resources::{Backend, ResourcePath, ResourcePathBuf};
+use std::collections::HashMap;
+use std::io;
 use std::path::Path;
 use tobj;
 
@@ -7,6 +9,14 @@ use tobj;
 pub enum Error {
     #[fail(display = "Obj or Mtl load error")]
     LoadError(#[cause] tobj::LoadError),
+    #[fail(display = "Failed to read obj file")]
+    ReadError(#[cause] io::Error),
+    #[fail(display = "Failed to read mtl file {}", path)]
+    MtlReadError {
+        path: ResourcePathBuf,
+        #[cause]
+        inner: io::Error,
+    },
     #[fail(display = "Resource path must not be empty")]
     ResourcePathMustNotBeEmpty,
 }
@@ -23,50 +33,64 @@ pub struct ModelsWithMaterials {
 }
 
 impl ModelsWithMaterials {
-    pub fn load(root_path: &Path, resource_path: &ResourcePath) -> Result<mesh::MeshSet, Error> {
-        let fs_path = super::resource_name_to_path(root_path, resource_path);
+    pub fn load(
+        backend: &dyn Backend,
+        resource_path: &ResourcePath,
+    ) -> Result<mesh::MeshSet, Error> {
         let resource_dir = resource_path
             .parent()
             .ok_or(Error::ResourcePathMustNotBeEmpty)?;
 
-        let (models, materials) = tobj::load_obj(&fs_path)?;
+        let obj = backend.read(resource_path).map_err(Error::ReadError)?;
+        let obj = String::from_utf8_lossy(&obj);
+        let PreparedObj {
+            obj,
+            materials: mapped_materials,
+            libraries,
+        } = prepare_obj(backend, resource_dir, &obj)?;
 
-        let mapped_materials = materials
-            .into_iter()
-            .map(|m| mesh::Material {
-                name: Some(m.name),
-                diffuse_map: if &m.diffuse_texture == "" {
-                    None
-                } else {
-                    Some(resource_dir.join(&platform_path_to_rel_resource_path(&m.diffuse_texture)))
-                },
-                bump_map: match m
-                    .unknown_param
+        // materials were parsed while preparing, tobj only needs their names
+        let (models, _) =
+            tobj::load_obj_buf(&mut io::Cursor::new(obj), |library: &Path| {
+                let range = library
+                    .to_str()
+                    .and_then(|index| index.parse::<usize>().ok())
+                    .and_then(|index| libraries.get(index))
+                    .ok_or(tobj::LoadError::OpenFileFailed)?;
+                let materials = range
+                    .clone()
+                    .map(|index| tobj::Material {
+                        name: placeholder_material_name(index),
+                        ..tobj::Material::empty()
+                    }).collect::<Vec<_>>();
+                let names = materials
                     .iter()
-                    .filter(|(k, _)| k.to_lowercase() == "map_bump" || k.to_lowercase() == "bump")
-                    .map(|(_, v)| v)
-                    .next()
-                {
-                    Some(ref name) => {
-                        Some(resource_dir.join(&platform_path_to_rel_resource_path(name)))
-                    }
-                    None => None,
-                },
-            }).collect::<Vec<_>>();
+                    .enumerate()
+                    .map(|(index, m)| (m.name.clone(), index))
+                    .collect();
+                Ok((materials, names))
+            })?;
 
-        let mapped_meshes = models
+        let (mapped_meshes, optimize_stats) = models
             .into_iter()
             .map(|m| map_model_to_mesh(m, &mapped_materials))
-            .collect::<Vec<_>>();
+            .unzip();
 
         Ok(mesh::MeshSet {
             materials: mapped_materials,
             meshes: mapped_meshes,
+            skeletons: Vec::new(),
+            animations: Vec::new(),
+            skipped: Vec::new(),
+            optimize_stats,
         })
     }
 }
 
-fn map_model_to_mesh(model: tobj::Model, mapped_materials: &[mesh::Material]) -> mesh::Mesh {
+fn map_model_to_mesh(
+    model: tobj::Model,
+    mapped_materials: &[mesh::Material],
+) -> (mesh::Mesh, mesh::OptimizeStats) {
     let normals = if model.mesh.normals.len() == 0 {
         None
     } else {
@@ -91,6 +115,10 @@ fn map_model_to_mesh(model: tobj::Model, mapped_materials: &[mesh::Material]) ->
                 let index = index * 2;
                 [t[index + 0], t[index + 1]].into()
             }),
+            // the format has a single set of coordinates, and colors are dropped by the parser
+            uv2: None,
+            color: None,
+            bones: None,
         });
     }
 
@@ -108,8 +136,11 @@ fn map_model_to_mesh(model: tobj::Model, mapped_materials: &[mesh::Material]) ->
 
     let mut mesh = mesh::Mesh {
         name: Some(model.name),
+        skeleton_index: None,
         vertices,
         primitives,
+        submeshes: Vec::new(),
+        morph_targets: Vec::new(),
         material_index: match model.mesh.material_id {
             Some(id) => if id >= mapped_materials.len() {
                 None
@@ -120,11 +151,228 @@ fn map_model_to_mesh(model: tobj::Model, mapped_materials: &[mesh::Material]) ->
         },
     };
 
+    // tobj does not share vertices between faces
+    let stats = mesh.optimize();
+
     mesh.calculate_tangents();
 
-    mesh
+    (mesh, stats)
 }
 
 fn platform_path_to_rel_resource_path(value: &str) -> ResourcePathBuf {
     value.replace('\\', "/").into()
 }
+
+/// Obj text rewritten into statements tobj can parse, with materials of its libraries.
+struct PreparedObj {
+    obj: String,
+    materials: Vec<mesh::Material>,
+    /// Materials of each `mtllib`, the library path in the rewritten text is the index here.
+    libraries: Vec<::std::ops::Range<usize>>,
+}
+
+/// Load material libraries and rewrite the statements tobj reads only the first word of.
+///
+/// Library paths and material names may contain spaces, and are replaced by indices.
+/// Unnamed groups get a default name.
+fn prepare_obj(
+    backend: &dyn Backend,
+    resource_dir: &ResourcePath,
+    obj: &str,
+) -> Result<PreparedObj, Error> {
+    let mut prepared = PreparedObj {
+        obj: String::with_capacity(obj.len()),
+        materials: Vec::new(),
+        libraries: Vec::new(),
+    };
+    let mut material_names = HashMap::new();
+
+    for line in obj.lines() {
+        let line = line.trim();
+        let (keyword, rest) = match line.find(char::is_whitespace) {
+            Some(end) => (&line[..end], line[end..].trim()),
+            None => (line, ""),
+        };
+
+        match keyword {
+            "mtllib" => for path in split_mtllib(rest) {
+                let mtl_path = resource_dir.join(platform_path_to_rel_resource_path(&path));
+                let mtl = backend
+                    .read(&mtl_path)
+                    .map_err(|inner| Error::MtlReadError {
+                        path: mtl_path.clone(),
+                        inner,
+                    })?;
+
+                let first = prepared.materials.len();
+                for material in parse_mtl(resource_dir, &String::from_utf8_lossy(&mtl)) {
+                    if let Some(ref name) = material.name {
+                        material_names
+                            .entry(name.clone())
+                            .or_insert_with(|| prepared.materials.len());
+                    }
+                    prepared.materials.push(material);
+                }
+                prepared
+                    .obj
+                    .push_str(&format!("mtllib {}\n", prepared.libraries.len()));
+                prepared.libraries.push(first..prepared.materials.len());
+            },
+            "usemtl" => match material_names.get(unquote(rest)) {
+                Some(&index) => prepared
+                    .obj
+                    .push_str(&format!("usemtl {}\n", placeholder_material_name(index))),
+                None => {
+                    prepared.obj.push_str(line);
+                    prepared.obj.push('\n');
+                }
+            },
+            "g" | "o" if rest.is_empty() => prepared.obj.push_str("g default\n"),
+            _ => {
+                prepared.obj.push_str(line);
+                prepared.obj.push('\n');
+            }
+        }
+    }
+
+    Ok(prepared)
+}
+
+fn placeholder_material_name(index: usize) -> String {
+    format!("material_{}", index)
+}
+
+/// Paths of a `mtllib` statement, either quoted or each ending with `.mtl`.
+fn split_mtllib(value: &str) -> Vec<String> {
+    if value.contains('"') {
+        return value
+            .split('"')
+            .skip(1)
+            .step_by(2)
+            .filter(|path| !path.is_empty())
+            .map(|path| path.to_string())
+            .collect();
+    }
+
+    let mut paths = Vec::new();
+    let mut current = Vec::new();
+    for word in value.split_whitespace() {
+        current.push(word);
+        if word.to_lowercase().ends_with(".mtl") {
+            paths.push(current.join(" "));
+            current.clear();
+        }
+    }
+    if !current.is_empty() {
+        paths.push(current.join(" "));
+    }
+    paths
+}
+
+fn unquote(value: &str) -> &str {
+    value.trim_matches('"')
+}
+
+/// Parse materials of a library, ignoring statements that are not used for rendering.
+fn parse_mtl(resource_dir: &ResourcePath, mtl: &str) -> Vec<mesh::Material> {
+    let mut materials = Vec::new();
+
+    for line in mtl.lines() {
+        let line = line.trim();
+        let (keyword, rest) = match line.find(char::is_whitespace) {
+            Some(end) => (&line[..end], line[end..].trim()),
+            None => (line, ""),
+        };
+
+        if keyword == "newmtl" {
+            materials.push(mesh::Material {
+                name: Some(unquote(rest).to_string()),
+                diffuse_map: None,
+                bump_map: None,
+                metallic_map: None,
+                roughness_map: None,
+                occlusion_map: None,
+                height_map: None,
+                lightmap: None,
+                vertex_colors: false,
+                double_sided: false,
+                metallic: None,
+                roughness: None,
+            });
+            continue;
+        }
+
+        let material = match materials.last_mut() {
+            Some(material) => material,
+            None => continue,
+        };
+        let map = || {
+            texture_map_path(rest)
+                .map(|path| resource_dir.join(platform_path_to_rel_resource_path(path)))
+        };
+        let value = || rest.parse::<f32>().ok();
+
+        // Pm, Pr and their maps are the PBR extension of the format, map_lightmap is our own
+        match keyword.to_lowercase().as_str() {
+            "map_kd" => material.diffuse_map = map(),
+            "map_bump" | "bump" => material.bump_map = map(),
+            "map_pm" => material.metallic_map = map(),
+            "map_pr" => material.roughness_map = map(),
+            "map_ao" => material.occlusion_map = map(),
+            "disp" | "map_disp" => material.height_map = map(),
+            "map_lightmap" => material.lightmap = map(),
+            "pm" => material.metallic = value(),
+            "pr" => material.roughness = value(),
+            _ => (),
+        }
+    }
+
+    materials
+}
+
+/// Number of values following each texture map option.
+fn texture_option_arity(option: &str) -> usize {
+    match option {
+        "-mm" => 2,
+        "-o" | "-s" | "-t" => 3,
+        _ => 1,
+    }
+}
+
+/// File name of a texture map statement, after options such as `-bm 0.5` or `-clamp on`.
+///
+/// The file name may contain spaces. Options with up to three values, such as `-o`,
+/// take only those that are numbers.
+fn texture_map_path(value: &str) -> Option<&str> {
+    let mut rest = value.trim();
+
+    while rest.starts_with('-') {
+        let mut words = rest.split_whitespace();
+        let option = words.next()?;
+        let mut consumed = option.len();
+        let mut remaining = &rest[consumed..];
+
+        for index in 0..texture_option_arity(option) {
+            let trimmed = remaining.trim_start();
+            let word = match trimmed.split_whitespace().next() {
+                Some(word) => word,
+                None => break,
+            };
+            // only the first value is required, others are optional numbers
+            if index > 0 && word.parse::<f32>().is_err() {
+                break;
+            }
+            consumed += remaining.len() - trimmed.len() + word.len();
+            remaining = &rest[consumed..];
+        }
+
+        rest = rest[consumed..].trim_start();
+    }
+
+    let path = unquote(rest.trim());
+    if path.is_empty() {
+        None
+    } else {
+        Some(path)
+    }
+}
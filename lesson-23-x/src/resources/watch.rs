@@ -0,0 +1,57 @@
+use crate::resources::{Backend, ResourcePath, ResourcePathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+
+/// Files are checked at most this often, because polling is done every frame.
+fn poll_interval() -> Duration {
+    Duration::from_millis(500)
+}
+
+/// Change notification for a set of resource files, polled by modification time.
+///
+/// Resources served from packs never change.
+pub struct Watch {
+    backend: Arc<dyn Backend>,
+    files: Vec<(ResourcePathBuf, Option<SystemTime>)>,
+    last_poll: Instant,
+}
+
+impl Watch {
+    pub(super) fn new(backend: Arc<dyn Backend>) -> Watch {
+        Watch {
+            backend,
+            files: Vec::new(),
+            last_poll: Instant::now(),
+        }
+    }
+
+    /// Also watch this file, current version is not reported as changed.
+    pub fn add<P: AsRef<ResourcePath>>(&mut self, rel_path: P) {
+        let rel_path = ResourcePathBuf::from(rel_path.as_ref());
+        let modified = self.backend.modified(&rel_path);
+        self.files.push((rel_path, modified));
+    }
+
+    pub fn with<P: AsRef<ResourcePath>>(mut self, rel_path: P) -> Self {
+        self.add(rel_path);
+        self
+    }
+
+    /// Returns true once after any of the files was modified.
+    pub fn changed(&mut self) -> bool {
+        if self.last_poll.elapsed() < poll_interval() {
+            return false;
+        }
+        self.last_poll = Instant::now();
+
+        let mut changed = false;
+        for (rel_path, last_modified) in &mut self.files {
+            let modified = self.backend.modified(rel_path);
+            if modified != *last_modified {
+                *last_modified = modified;
+                changed = true;
+            }
+        }
+        changed
+    }
+}
@@ -0,0 +1,61 @@
+/// Coarser levels are not switched back to until the distance drops this much below the limit.
+const HYSTERESIS: f32 = 0.9;
+
+/// Levels of detail from the most detailed, each used up to its maximum camera distance.
+/// The last level is used at any larger distance.
+pub struct Lod<T> {
+    levels: Vec<(f32, T)>,
+    current: usize,
+}
+
+impl<T> Lod<T> {
+    /// Create from levels and their maximum distances, which are sorted if needed.
+    pub fn new(mut levels: Vec<(f32, T)>) -> Lod<T> {
+        assert!(!levels.is_empty(), "expected at least one level of detail");
+        levels.sort_by(|a, b| a.0.partial_cmp(&b.0).expect("expected LOD distance not NaN"));
+        Lod { levels, current: 0 }
+    }
+
+    pub fn single(value: T) -> Lod<T> {
+        Lod::new(vec![(::std::f32::INFINITY, value)])
+    }
+
+    /// Switch level for the camera distance and return it.
+    pub fn select(&mut self, distance: f32) -> usize {
+        let last = self.levels.len() - 1;
+        while self.current < last && distance > self.levels[self.current].0 {
+            self.current += 1;
+        }
+        while self.current > 0 && distance < self.levels[self.current - 1].0 * HYSTERESIS {
+            self.current -= 1;
+        }
+        self.current
+    }
+
+    pub fn current_level(&self) -> usize {
+        self.current
+    }
+
+    pub fn current(&self) -> &T {
+        &self.levels[self.current].1
+    }
+
+    pub fn len(&self) -> usize {
+        self.levels.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.levels.is_empty()
+    }
+
+    pub fn map<U, F: FnMut(T) -> U>(self, mut f: F) -> Lod<U> {
+        Lod {
+            levels: self
+                .levels
+                .into_iter()
+                .map(|(distance, value)| (distance, f(value)))
+                .collect(),
+            current: self.current,
+        }
+    }
+}
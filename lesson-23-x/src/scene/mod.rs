@@ -0,0 +1,105 @@
+use nalgebra as na;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+mod lod;
+pub mod serde;
+mod shared_scene;
+pub use self::lod::Lod;
+pub use self::shared_scene::NodeHandle;
+use self::shared_scene::SharedScene;
+
+/// Transform node in the scene tree. The node is removed from the tree when dropped.
+///
+/// World transform is the product of all parent local transforms and is recalculated
+/// lazily after any of them change.
+pub struct Node {
+    shared: Rc<RefCell<SharedScene>>,
+    handle: NodeHandle,
+}
+
+impl Node {
+    pub fn handle(&self) -> NodeHandle {
+        self.handle
+    }
+
+    pub fn parent(&self) -> Option<NodeHandle> {
+        self.shared.borrow().parent(self.handle)
+    }
+
+    /// Attach to the parent so that the node keeps its current world position.
+    pub fn set_parent(&self, parent: Option<&Node>) {
+        self.shared
+            .borrow_mut()
+            .set_parent_keep_world(self.handle, parent.map(|p| p.handle));
+    }
+
+    pub fn local_transform(&self) -> na::Isometry3<f32> {
+        self.shared
+            .borrow()
+            .local_transform(self.handle)
+            .unwrap_or_else(na::Isometry3::identity)
+    }
+
+    pub fn set_local_transform(&self, isometry: na::Isometry3<f32>) {
+        self.shared
+            .borrow_mut()
+            .set_local_transform(self.handle, isometry);
+    }
+
+    pub fn world_transform(&self) -> na::Isometry3<f32> {
+        self.shared
+            .borrow_mut()
+            .world_transform(self.handle)
+            .unwrap_or_else(na::Isometry3::identity)
+    }
+
+    pub fn set_world_transform(&self, isometry: na::Isometry3<f32>) {
+        self.shared
+            .borrow_mut()
+            .set_world_transform(self.handle, isometry);
+    }
+
+    /// Returns new world transform if this node or any of its parents moved since the last call.
+    pub fn take_changed_world_transform(&self) -> Option<na::Isometry3<f32>> {
+        self.shared
+            .borrow_mut()
+            .take_changed_world_transform(self.handle)
+    }
+}
+
+impl Drop for Node {
+    fn drop(&mut self) {
+        self.shared.borrow_mut().remove_node(self.handle);
+    }
+}
+
+pub struct Scene {
+    shared: Rc<RefCell<SharedScene>>,
+}
+
+impl Scene {
+    pub fn new() -> Scene {
+        Scene {
+            shared: Rc::new(RefCell::new(SharedScene::new())),
+        }
+    }
+
+    pub fn node(&self, local: na::Isometry3<f32>, parent: Option<&Node>) -> Node {
+        let handle = self
+            .shared
+            .borrow_mut()
+            .new_node(local, parent.map(|p| p.handle));
+
+        Node {
+            shared: self.shared.clone(),
+            handle,
+        }
+    }
+}
+
+impl Default for Scene {
+    fn default() -> Scene {
+        Scene::new()
+    }
+}
@@ -0,0 +1,161 @@
+use failure;
+use nalgebra as na;
+use crate::entity::{Entities, Entity, EntityHandle};
+use crate::resources::ResourcePath;
+use serde_json;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+#[derive(Debug, Fail)]
+pub enum Error {
+    #[fail(display = "Failed to read or write scene file")]
+    Io(#[cause] io::Error),
+    #[fail(display = "Failed to parse scene file")]
+    Json(#[cause] serde_json::Error),
+    #[fail(display = "No entity type {} in registry", kind)]
+    UnknownKind { kind: String },
+    #[fail(display = "Failed to create entity {}: {}", kind, inner)]
+    CreateFailed { kind: String, inner: failure::Error },
+}
+
+impl From<io::Error> for Error {
+    fn from(other: io::Error) -> Self {
+        Error::Io(other)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(other: serde_json::Error) -> Self {
+        Error::Json(other)
+    }
+}
+
+/// Saved state of a single entity.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct EntityRecord {
+    /// Entity type name in the `Registry`.
+    pub kind: String,
+    /// Model or other resource the entity is created from.
+    pub resource: Option<String>,
+    pub translation: [f32; 3],
+    /// Rotation quaternion as (i, j, k, w).
+    pub rotation: [f32; 4],
+    #[serde(default)]
+    pub scale: Option<[f32; 3]>,
+}
+
+impl EntityRecord {
+    pub fn new(
+        kind: &str,
+        resource: Option<&ResourcePath>,
+        isometry: &na::Isometry3<f32>,
+    ) -> EntityRecord {
+        let translation = isometry.translation.vector;
+        let rotation = isometry.rotation.coords;
+        EntityRecord {
+            kind: kind.into(),
+            resource: resource.map(|path| path.to_string()),
+            translation: [translation.x, translation.y, translation.z],
+            rotation: [rotation.x, rotation.y, rotation.z, rotation.w],
+            scale: None,
+        }
+    }
+
+    pub fn with_scale(mut self, scale: na::Vector3<f32>) -> EntityRecord {
+        self.scale = Some([scale.x, scale.y, scale.z]);
+        self
+    }
+
+    pub fn isometry(&self) -> na::Isometry3<f32> {
+        let [x, y, z] = self.translation;
+        let [i, j, k, w] = self.rotation;
+        na::Isometry3::from_parts(
+            na::Translation3::new(x, y, z),
+            na::UnitQuaternion::from_quaternion(na::Quaternion::new(w, i, j, k)),
+        )
+    }
+}
+
+/// Entities that can be saved, written as JSON.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct SceneFile {
+    pub entities: Vec<EntityRecord>,
+}
+
+impl SceneFile {
+    /// Collect records of entities that support saving.
+    pub fn from_entities(entities: &Entities) -> SceneFile {
+        SceneFile {
+            entities: entities.iter().filter_map(|entity| entity.record()).collect(),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        let file = fs::File::create(path)?;
+        serde_json::to_writer_pretty(io::BufWriter::new(file), self)?;
+        Ok(())
+    }
+
+    pub fn load(path: &Path) -> Result<SceneFile, Error> {
+        let file = fs::File::open(path)?;
+        Ok(serde_json::from_reader(io::BufReader::new(file))?)
+    }
+}
+
+type Factory<'a> = Box<dyn Fn(&EntityRecord) -> Result<Box<dyn Entity>, failure::Error> + 'a>;
+
+/// Entity constructors by type name, used to recreate saved entities.
+#[derive(Default)]
+pub struct Registry<'a> {
+    factories: HashMap<String, Factory<'a>>,
+}
+
+impl<'a> Registry<'a> {
+    pub fn new() -> Registry<'a> {
+        Registry::default()
+    }
+
+    pub fn with_kind<E, F>(mut self, kind: &str, create: F) -> Registry<'a>
+    where
+        E: Entity + 'static,
+        F: Fn(&EntityRecord) -> Result<E, failure::Error> + 'a,
+    {
+        self.factories.insert(
+            kind.into(),
+            Box::new(move |record| Ok(Box::new(create(record)?) as Box<dyn Entity>)),
+        );
+        self
+    }
+
+    /// Replace saved entities with the ones in the file. Entities that are not saved,
+    /// such as the floor, are kept. Nothing is replaced if any entity fails to load.
+    pub fn load_into(
+        &self,
+        file: &SceneFile,
+        entities: &mut Entities,
+    ) -> Result<Vec<EntityHandle>, Error> {
+        let created = file
+            .entities
+            .iter()
+            .map(|record| {
+                let create = self
+                    .factories
+                    .get(&record.kind)
+                    .ok_or_else(|| Error::UnknownKind {
+                        kind: record.kind.clone(),
+                    })?;
+                create(record).map_err(|inner| Error::CreateFailed {
+                    kind: record.kind.clone(),
+                    inner,
+                })
+            }).collect::<Result<Vec<_>, Error>>()?;
+
+        entities.retain(|entity| entity.record().is_none());
+        Ok(created
+            .into_iter()
+            .map(|entity| entities.add_boxed(entity))
+            .collect())
+    }
+}
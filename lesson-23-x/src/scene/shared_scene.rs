@@ -0,0 +1,174 @@
+use nalgebra as na;
+use slab::Slab;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct NodeHandle(usize);
+
+struct NodeContainer {
+    parent: Option<NodeHandle>,
+    children: Vec<NodeHandle>,
+    local: na::Isometry3<f32>,
+    world: na::Isometry3<f32>,
+    dirty: bool,
+    changed: bool,
+}
+
+pub struct SharedScene {
+    nodes: Slab<NodeContainer>,
+}
+
+impl SharedScene {
+    pub fn new() -> SharedScene {
+        SharedScene { nodes: Slab::new() }
+    }
+
+    pub fn new_node(
+        &mut self,
+        local: na::Isometry3<f32>,
+        parent: Option<NodeHandle>,
+    ) -> NodeHandle {
+        let handle = NodeHandle(self.nodes.insert(NodeContainer {
+            parent: None,
+            children: Vec::new(),
+            local,
+            world: local,
+            dirty: true,
+            changed: true,
+        }));
+        self.set_parent(handle, parent);
+        handle
+    }
+
+    /// Detach node from the tree. Children become roots and keep their world transforms.
+    pub fn remove_node(&mut self, handle: NodeHandle) {
+        let children = match self.nodes.get(handle.0) {
+            Some(node) => node.children.clone(),
+            None => return,
+        };
+
+        for child in children {
+            self.set_parent_keep_world(child, None);
+        }
+        self.set_parent(handle, None);
+        self.nodes.remove(handle.0);
+    }
+
+    /// Attach node to another parent, keeping the local transform.
+    pub fn set_parent(&mut self, handle: NodeHandle, parent: Option<NodeHandle>) {
+        if parent.map(|p| self.is_self_or_ancestor(handle, p)) == Some(true) {
+            return;
+        }
+
+        let old_parent = match self.nodes.get(handle.0) {
+            Some(node) => node.parent,
+            None => return,
+        };
+
+        if let Some(old_parent) = old_parent {
+            if let Some(old_parent_node) = self.nodes.get_mut(old_parent.0) {
+                old_parent_node.children.retain(|c| *c != handle);
+            }
+        }
+
+        let parent = parent.filter(|p| self.nodes.contains(p.0));
+        if let Some(parent) = parent {
+            self.nodes[parent.0].children.push(handle);
+        }
+
+        self.nodes[handle.0].parent = parent;
+        self.mark_dirty(handle);
+    }
+
+    /// Attach node to another parent, adjusting local transform to keep it in place.
+    pub fn set_parent_keep_world(&mut self, handle: NodeHandle, parent: Option<NodeHandle>) {
+        let world = match self.world_transform(handle) {
+            Some(world) => world,
+            None => return,
+        };
+        self.set_parent(handle, parent);
+        self.set_world_transform(handle, world);
+    }
+
+    pub fn parent(&self, handle: NodeHandle) -> Option<NodeHandle> {
+        self.nodes.get(handle.0).and_then(|n| n.parent)
+    }
+
+    pub fn local_transform(&self, handle: NodeHandle) -> Option<na::Isometry3<f32>> {
+        self.nodes.get(handle.0).map(|n| n.local)
+    }
+
+    pub fn set_local_transform(&mut self, handle: NodeHandle, local: na::Isometry3<f32>) {
+        if let Some(node) = self.nodes.get_mut(handle.0) {
+            node.local = local;
+        } else {
+            return;
+        }
+        self.mark_dirty(handle);
+    }
+
+    pub fn set_world_transform(&mut self, handle: NodeHandle, world: na::Isometry3<f32>) {
+        let parent_world = self
+            .parent(handle)
+            .and_then(|p| self.world_transform(p))
+            .unwrap_or_else(na::Isometry3::identity);
+        self.set_local_transform(handle, parent_world.inverse() * world);
+    }
+
+    pub fn world_transform(&mut self, handle: NodeHandle) -> Option<na::Isometry3<f32>> {
+        let (dirty, parent, local, world) = match self.nodes.get(handle.0) {
+            Some(node) => (node.dirty, node.parent, node.local, node.world),
+            None => return None,
+        };
+
+        if !dirty {
+            return Some(world);
+        }
+
+        let world = match parent.and_then(|p| self.world_transform(p)) {
+            Some(parent_world) => parent_world * local,
+            None => local,
+        };
+
+        let node = &mut self.nodes[handle.0];
+        node.world = world;
+        node.dirty = false;
+
+        Some(world)
+    }
+
+    /// Returns new world transform if it changed since the last call.
+    pub fn take_changed_world_transform(
+        &mut self,
+        handle: NodeHandle,
+    ) -> Option<na::Isometry3<f32>> {
+        let changed = self.nodes.get(handle.0).map(|n| n.changed) == Some(true);
+        if !changed {
+            return None;
+        }
+        self.nodes[handle.0].changed = false;
+        self.world_transform(handle)
+    }
+
+    fn mark_dirty(&mut self, handle: NodeHandle) {
+        let mut stack = vec![handle];
+        while let Some(handle) = stack.pop() {
+            if let Some(node) = self.nodes.get_mut(handle.0) {
+                node.dirty = true;
+                node.changed = true;
+                stack.extend(node.children.iter().cloned());
+            }
+        }
+    }
+
+    fn is_self_or_ancestor(&self, handle: NodeHandle, mut other: NodeHandle) -> bool {
+        loop {
+            if other == handle {
+                return true;
+            }
+            match self.parent(other) {
+                Some(parent) => other = parent,
+                None => return false,
+            }
+        }
+    }
+}
@@ -0,0 +1,124 @@
+use nalgebra as na;
+use ncollide3d::bounding_volume::aabb::AABB;
+use ncollide3d::bounding_volume::BoundingVolume;
+use ncollide3d::partitioning::{DBVTLeaf, DBVTLeafId, VisitStatus, Visitor, BVH, DBVT};
+use ncollide3d::query::visitors::RayInterferencesCollector;
+use ncollide3d::query::Ray;
+use super::shared_selectables::ContainerHandle;
+use std::collections::HashMap;
+
+/// Leaves are enlarged by this much, so that small moves do not restructure the tree.
+const MARGIN: f32 = 0.2;
+
+/// Dynamic tree over world-space bounds of selectables.
+///
+/// A leaf is reinserted only when the object leaves its enlarged bounds.
+pub struct Bvh {
+    tree: DBVT<f32, ContainerHandle, AABB<f32>>,
+    leaves: HashMap<ContainerHandle, DBVTLeafId>,
+}
+
+impl Bvh {
+    pub fn new() -> Bvh {
+        Bvh {
+            tree: DBVT::new(),
+            leaves: HashMap::new(),
+        }
+    }
+
+    /// Insert the object or move it to new bounds.
+    pub fn update(&mut self, handle: ContainerHandle, world_aabb: &AABB<f32>) {
+        if let Some(&leaf) = self.leaves.get(&handle) {
+            if self.tree[leaf].bounding_volume.contains(world_aabb) {
+                return;
+            }
+            self.tree.remove(leaf);
+        }
+        let leaf = self
+            .tree
+            .insert(DBVTLeaf::new(world_aabb.loosened(MARGIN), handle));
+        self.leaves.insert(handle, leaf);
+    }
+
+    pub fn remove(&mut self, handle: ContainerHandle) {
+        if let Some(leaf) = self.leaves.remove(&handle) {
+            self.tree.remove(leaf);
+        }
+    }
+
+    /// Objects whose enlarged bounds are hit by the ray.
+    pub fn cast_ray(&self, ray: &Ray<f32>) -> Vec<ContainerHandle> {
+        let mut handles = Vec::new();
+        self.tree
+            .visit(&mut RayInterferencesCollector::new(ray, &mut handles));
+        handles
+    }
+
+    /// Objects that may overlap the rectangle in normalized device coordinates, between
+    /// the near and far planes.
+    pub fn in_frustum(
+        &self,
+        view_projection: &na::Matrix4<f32>,
+        min: &na::Point2<f32>,
+        max: &na::Point2<f32>,
+    ) -> Vec<ContainerHandle> {
+        let row = |index: usize| view_projection.row(index).transpose();
+        let (x, y, z, w) = (row(0), row(1), row(2), row(3));
+        let mut handles = Vec::new();
+        self.tree.visit(&mut FrustumCollector {
+            planes: [
+                x - w * min.x,
+                w * max.x - x,
+                y - w * min.y,
+                w * max.y - y,
+                z + w,
+                w - z,
+            ],
+            collector: &mut handles,
+        });
+        handles
+    }
+
+    /// Bounds of all tree nodes with their depth, the root is at depth 0.
+    pub fn nodes(&self) -> Vec<(AABB<f32>, usize)> {
+        let mut nodes = Vec::new();
+        let mut stack = self.tree.root().into_iter().map(|root| (root, 0)).collect::<Vec<_>>();
+        while let Some((node, depth)) = stack.pop() {
+            nodes.push((self.tree.content(node).0.clone(), depth));
+            for index in 0..self.tree.num_children(node) {
+                stack.push((self.tree.child(index, node), depth + 1));
+            }
+        }
+        nodes
+    }
+}
+
+/// Collects leaves not fully outside any of the planes, given as `(a, b, c, d)` with
+/// `ax + by + cz + d >= 0` inside.
+struct FrustumCollector<'a> {
+    planes: [na::Vector4<f32>; 6],
+    collector: &'a mut Vec<ContainerHandle>,
+}
+
+impl<'a> Visitor<ContainerHandle, AABB<f32>> for FrustumCollector<'a> {
+    fn visit(&mut self, aabb: &AABB<f32>, handle: Option<&ContainerHandle>) -> VisitStatus {
+        let (mins, maxs) = (aabb.mins(), aabb.maxs());
+        let outside = self.planes.iter().any(|plane| {
+            // corner furthest along the plane normal
+            let corner = na::Vector4::new(
+                if plane.x >= 0.0 { maxs.x } else { mins.x },
+                if plane.y >= 0.0 { maxs.y } else { mins.y },
+                if plane.z >= 0.0 { maxs.z } else { mins.z },
+                1.0,
+            );
+            plane.dot(&corner) < 0.0
+        });
+        if outside {
+            return VisitStatus::Stop;
+        }
+        if let Some(&handle) = handle {
+            self.collector.push(handle);
+        }
+        VisitStatus::Continue
+    }
+}
@@ -0,0 +1,215 @@
+use nalgebra as na;
+use ncollide3d::query::Ray;
+
+/// Fraction of the camera distance used as gizmo size, keeps it constant on screen.
+const SCREEN_SIZE: f32 = 0.15;
+/// Pick tolerance relative to gizmo size.
+const PICK_TOLERANCE: f32 = 0.08;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum GizmoMode {
+    Translate,
+    Rotate,
+    Scale,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum GizmoAxis {
+    X,
+    Y,
+    Z,
+}
+
+impl GizmoAxis {
+    pub fn all() -> [GizmoAxis; 3] {
+        [GizmoAxis::X, GizmoAxis::Y, GizmoAxis::Z]
+    }
+
+    /// Unit axis in the object local space.
+    pub fn local_direction(&self) -> na::Vector3<f32> {
+        match *self {
+            GizmoAxis::X => na::Vector3::x(),
+            GizmoAxis::Y => na::Vector3::y(),
+            GizmoAxis::Z => na::Vector3::z(),
+        }
+    }
+
+    pub fn index(&self) -> usize {
+        match *self {
+            GizmoAxis::X => 0,
+            GizmoAxis::Y => 1,
+            GizmoAxis::Z => 2,
+        }
+    }
+
+    pub fn color(&self) -> na::Vector4<f32> {
+        match *self {
+            GizmoAxis::X => na::Vector4::new(1.0, 0.2, 0.2, 1.0),
+            GizmoAxis::Y => na::Vector4::new(0.2, 1.0, 0.2, 1.0),
+            GizmoAxis::Z => na::Vector4::new(0.2, 0.4, 1.0, 1.0),
+        }
+    }
+}
+
+/// Gizmo attached to the selected object, as it should be drawn this frame.
+#[derive(Copy, Clone, Debug)]
+pub struct Gizmo {
+    pub mode: GizmoMode,
+    /// Axes follow the object rotation.
+    pub isometry: na::Isometry3<f32>,
+    /// Length of the handles in world units.
+    pub size: f32,
+    pub hover_axis: Option<GizmoAxis>,
+    pub active_axis: Option<GizmoAxis>,
+}
+
+impl Gizmo {
+    pub fn axis_color(&self, axis: GizmoAxis) -> na::Vector4<f32> {
+        let highlighted = match self.active_axis {
+            Some(active_axis) => active_axis == axis,
+            None => self.hover_axis == Some(axis),
+        };
+        if highlighted {
+            na::Vector4::new(1.0, 1.0, 0.2, 1.0)
+        } else {
+            axis.color()
+        }
+    }
+}
+
+pub fn size_for_camera(isometry: &na::Isometry3<f32>, ray: &Ray<f32>) -> f32 {
+    na::distance(&ray.origin, &center(isometry)) * SCREEN_SIZE
+}
+
+pub fn center(isometry: &na::Isometry3<f32>) -> na::Point3<f32> {
+    na::Point3::from(isometry.translation.vector)
+}
+
+pub fn world_direction(isometry: &na::Isometry3<f32>, axis: GizmoAxis) -> na::Vector3<f32> {
+    isometry.rotation * axis.local_direction()
+}
+
+/// Find the handle closest to the camera under the ray.
+pub fn pick_axis(
+    mode: GizmoMode,
+    isometry: &na::Isometry3<f32>,
+    size: f32,
+    ray: &Ray<f32>,
+) -> Option<GizmoAxis> {
+    let tolerance = size * PICK_TOLERANCE;
+    let mut closest: Option<(GizmoAxis, f32)> = None;
+
+    for &axis in GizmoAxis::all().iter() {
+        let ray_toi = match mode {
+            GizmoMode::Translate | GizmoMode::Scale => {
+                let direction = world_direction(isometry, axis);
+                closest_on_axis(&center(isometry), &direction, ray).and_then(
+                    |(axis_param, ray_toi, distance)| {
+                        if axis_param >= 0.0 && axis_param <= size * 1.1 && distance < tolerance {
+                            Some(ray_toi)
+                        } else {
+                            None
+                        }
+                    },
+                )
+            }
+            GizmoMode::Rotate => {
+                let normal = world_direction(isometry, axis);
+                plane_hit(&center(isometry), &normal, ray).and_then(|(point, ray_toi)| {
+                    let radius = na::distance(&point, &center(isometry));
+                    if (radius - size).abs() < tolerance {
+                        Some(ray_toi)
+                    } else {
+                        None
+                    }
+                })
+            }
+        };
+
+        if let Some(toi) = ray_toi {
+            let is_closer = match closest {
+                Some((_, closest_toi)) => toi < closest_toi,
+                None => true,
+            };
+            if is_closer {
+                closest = Some((axis, toi));
+            }
+        }
+    }
+
+    closest.map(|(axis, _)| axis)
+}
+
+/// Closest points between the axis line and the ray.
+///
+/// Returns position along the axis, ray time of impact, and distance between the points.
+pub fn closest_on_axis(
+    origin: &na::Point3<f32>,
+    direction: &na::Vector3<f32>,
+    ray: &Ray<f32>,
+) -> Option<(f32, f32, f32)> {
+    let w = origin - ray.origin;
+    let a = direction.dot(direction);
+    let b = direction.dot(&ray.dir);
+    let c = ray.dir.dot(&ray.dir);
+    let d = direction.dot(&w);
+    let e = ray.dir.dot(&w);
+
+    let denominator = a * c - b * b;
+    if denominator.abs() < ::std::f32::EPSILON {
+        // looking along the axis
+        return None;
+    }
+
+    let axis_param = (b * e - c * d) / denominator;
+    let ray_toi = (a * e - b * d) / denominator;
+    if ray_toi < 0.0 {
+        return None;
+    }
+
+    let axis_point = origin + direction * axis_param;
+    let ray_point = ray.origin + ray.dir * ray_toi;
+
+    Some((axis_param, ray_toi, na::distance(&axis_point, &ray_point)))
+}
+
+/// Angle of the ray hit around the axis, measured in the plane perpendicular to it.
+pub fn angle_around_axis(
+    origin: &na::Point3<f32>,
+    axis: &na::Vector3<f32>,
+    ray: &Ray<f32>,
+) -> Option<f32> {
+    let (point, _) = plane_hit(origin, axis, ray)?;
+    let (u, v) = plane_basis(axis);
+    let offset = point - origin;
+
+    Some(offset.dot(&v).atan2(offset.dot(&u)))
+}
+
+/// Two unit vectors perpendicular to the axis and each other.
+pub fn plane_basis(axis: &na::Vector3<f32>) -> (na::Vector3<f32>, na::Vector3<f32>) {
+    let helper = if axis.x.abs() < 0.9 {
+        na::Vector3::x()
+    } else {
+        na::Vector3::y()
+    };
+    let u = axis.cross(&helper).normalize();
+    let v = axis.cross(&u).normalize();
+    (u, v)
+}
+
+fn plane_hit(
+    origin: &na::Point3<f32>,
+    normal: &na::Vector3<f32>,
+    ray: &Ray<f32>,
+) -> Option<(na::Point3<f32>, f32)> {
+    let denominator = normal.dot(&ray.dir);
+    if denominator.abs() < ::std::f32::EPSILON {
+        return None;
+    }
+    let toi = normal.dot(&(origin - ray.origin)) / denominator;
+    if toi < 0.0 {
+        return None;
+    }
+    Some((ray.origin + ray.dir * toi, toi))
+}
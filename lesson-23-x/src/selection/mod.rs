@@ -1,16 +1,109 @@
 use nalgebra as na;
 use ncollide3d::bounding_volume::aabb::AABB;
 use ncollide3d::query::Ray;
+use ncollide3d::shape::TriMesh;
 use std::cell::RefCell;
 use std::rc::Rc;
 
+mod bvh;
+mod gizmo;
 mod shared_selectables;
-use self::shared_selectables::{Container, ContainerHandle, SharedSelectables};
+pub use self::gizmo::{Gizmo, GizmoAxis, GizmoMode};
+pub use self::shared_selectables::{Container, ContainerHandle};
+use self::shared_selectables::SharedSelectables;
+
+/// World-space point where the cursor ray hit a selectable.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Hit {
+    pub point: na::Point3<f32>,
+    pub normal: na::Vector3<f32>,
+    /// Index of the hit triangle, if the selectable has a mesh collider.
+    pub triangle: Option<usize>,
+}
+
+/// How dragged objects follow the cursor.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum DragMode {
+    /// Move in the plane facing the camera.
+    ViewPlane,
+    /// Place on the surface hit by the cursor ray: another selectable or the ground plane.
+    Surface,
+}
+
+/// Surface that objects can be dragged onto in `DragMode::Surface` without being a
+/// selectable itself, i.e. terrain.
+pub trait DragSurface {
+    fn cast_ray(&self, ray: &Ray<f32>) -> Option<Hit>;
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct DragSettings {
+    pub mode: DragMode,
+    /// Ground plane used in `Surface` mode when no selectable is hit.
+    pub ground_point: na::Point3<f32>,
+    pub ground_normal: na::Unit<na::Vector3<f32>>,
+    /// Round dragged position to this grid step.
+    pub grid: Option<f32>,
+}
+
+impl Default for DragSettings {
+    fn default() -> DragSettings {
+        DragSettings {
+            mode: DragMode::ViewPlane,
+            ground_point: na::Point3::origin(),
+            ground_normal: na::Vector3::z_axis(),
+            grid: None,
+        }
+    }
+}
 
 #[derive(Copy, Clone, PartialEq)]
 pub enum Action {
-    Click,
+    Click { hit: Hit },
     Drag { new_isometry: na::Isometry3<f32> },
+    /// Gizmo changed object scale, applied before its isometry.
+    Scale { new_scale: na::Vector3<f32> },
+}
+
+/// Transform of an object before and after a drag, reported once the drag is released.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct TransformChange {
+    pub handle: ContainerHandle,
+    pub old_isometry: na::Isometry3<f32>,
+    pub old_scale: na::Vector3<f32>,
+    pub new_isometry: na::Isometry3<f32>,
+    pub new_scale: na::Vector3<f32>,
+}
+
+/// Selected objects, handed back to the app. The last one is primary and carries the gizmo.
+#[derive(Clone)]
+pub struct Selection {
+    handles: Vec<ContainerHandle>,
+}
+
+impl Selection {
+    pub fn contains(&self, selectable: &SelectableAABB) -> bool {
+        self.handles.iter().any(|h| selectable.has_handle(*h))
+    }
+
+    pub fn is_primary(&self, selectable: &SelectableAABB) -> bool {
+        self.handles
+            .last()
+            .map(|h| selectable.has_handle(*h))
+            .unwrap_or(false)
+    }
+
+    pub fn len(&self) -> usize {
+        self.handles.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.handles.is_empty()
+    }
+
+    pub fn handles(&self) -> &[ContainerHandle] {
+        &self.handles
+    }
 }
 
 pub struct SelectableAABB {
@@ -23,27 +116,60 @@ impl SelectableAABB {
         self.handle == handle
     }
 
+    /// Id to draw into the id buffer for GPU picking.
+    pub fn pick_id(&self) -> u32 {
+        self.handle.pick_id()
+    }
+
     pub fn update_aabb(&self, aabb: AABB<f32>) {
-        let mut shared_ref = self.shared.borrow_mut();
-        if let Some(container_ref) = shared_ref.get_container_mut(self.handle) {
-            container_ref.aabb = aabb;
-        }
+        self.shared
+            .borrow_mut()
+            .update_container(self.handle, |container| container.aabb = aabb);
+    }
+
+    /// Hit-test the bounds instead of triangles, i.e. for a mesh deformed every frame.
+    pub fn clear_mesh(&self) {
+        self.shared
+            .borrow_mut()
+            .update_container(self.handle, |container| container.mesh = None);
+    }
+
+    /// Replace the triangles used for hit-testing, for example after the model was reloaded.
+    pub fn update_mesh(&self, vertices: Vec<na::Point3<f32>>, indices: &[u32]) {
+        let mesh = Rc::new(tri_mesh(vertices, indices));
+        self.shared
+            .borrow_mut()
+            .update_container(self.handle, |container| container.mesh = Some(mesh));
     }
 
     pub fn update_isometry(&self, isometry: na::Isometry3<f32>) {
-        let mut shared_ref = self.shared.borrow_mut();
-        if let Some(container_ref) = shared_ref.get_container_mut(self.handle) {
-            container_ref.isometry = isometry;
-        }
+        self.shared
+            .borrow_mut()
+            .update_container(self.handle, |container| container.isometry = isometry);
+    }
+
+    pub fn update_scale(&self, scale: na::Vector3<f32>) {
+        self.shared
+            .borrow_mut()
+            .update_container(self.handle, |container| container.scale = scale);
     }
 
     pub fn drain_pending_action(&self) -> Option<Action> {
         self.shared.borrow_mut().drain_pending_action(self.handle)
     }
 
+    /// Add to selection as the primary object.
     pub fn select(&self) {
         self.shared.borrow_mut().select(self.handle)
     }
+
+    pub fn deselect(&self) {
+        self.shared.borrow_mut().deselect(self.handle)
+    }
+
+    pub fn is_selected(&self) -> bool {
+        self.shared.borrow().selected().contains(&self.handle)
+    }
 }
 
 impl Drop for SelectableAABB {
@@ -65,7 +191,33 @@ impl Selectables {
     }
 
     pub fn selectable(&self, aabb: AABB<f32>, isometry: na::Isometry3<f32>) -> SelectableAABB {
-        let new_handle = self.shared.borrow_mut().new_container(aabb, isometry);
+        let new_handle = self
+            .shared
+            .borrow_mut()
+            .new_container(aabb, isometry, None);
+
+        SelectableAABB {
+            shared: self.shared.clone(),
+            handle: new_handle,
+        }
+    }
+
+    /// Selectable hit-tested against triangles, so clicks report the exact surface point.
+    ///
+    /// The AABB is still used as a quick early-out and for highlighting.
+    pub fn selectable_with_mesh(
+        &self,
+        aabb: AABB<f32>,
+        isometry: na::Isometry3<f32>,
+        vertices: Vec<na::Point3<f32>>,
+        indices: &[u32],
+    ) -> SelectableAABB {
+        let mesh = tri_mesh(vertices, indices);
+
+        let new_handle =
+            self.shared
+                .borrow_mut()
+                .new_container(aabb, isometry, Some(Rc::new(mesh)));
 
         SelectableAABB {
             shared: self.shared.clone(),
@@ -84,8 +236,28 @@ impl Selectables {
             .cast_cursor(ray, camera_target_pos, camera_dir);
     }
 
-    pub fn send_mouse_down(&self) {
-        self.shared.borrow_mut().send_mouse_down();
+    /// Take the object under cursor from the id buffer instead of testing the ray against
+    /// every selectable. Pass the id read under the cursor, `render_gl::NO_ID` for none.
+    ///
+    /// The ray still places the hit point and drives dragging.
+    pub fn set_picked_id(&self, id: u32) {
+        self.shared.borrow_mut().set_picked_id(id);
+    }
+
+    /// Go back to CPU ray casting against bounds and meshes.
+    pub fn clear_picked_id(&self) {
+        self.shared.borrow_mut().clear_picked_id();
+    }
+
+    /// Queue an action for the owner of the selectable, as if it came from the cursor,
+    /// i.e. to move an object from transform fields.
+    pub fn queue_action(&self, handle: ContainerHandle, action: Action) {
+        self.shared.borrow_mut().push_action(handle, action);
+    }
+
+    /// With `additive`, the object under cursor is toggled in the selection.
+    pub fn send_mouse_down(&self, additive: bool) {
+        self.shared.borrow_mut().send_mouse_down(additive);
     }
 
     pub fn send_mouse_up(&self) {
@@ -96,11 +268,133 @@ impl Selectables {
         self.shared.borrow_mut().cancel_drag();
     }
 
+    /// Objects moved by drags released since the last call, i.e. to record them for undo.
+    ///
+    /// Canceled drags are not reported.
+    pub fn drain_finished_drags(&self) -> Vec<TransformChange> {
+        self.shared.borrow_mut().drain_finished_drags()
+    }
+
+    /// Push objects dragged in the view plane away from the camera, negative `distance`
+    /// pulls them closer. Takes effect on the next `cast_cursor`.
+    pub fn move_drag_depth(&self, distance: f32) {
+        self.shared.borrow_mut().move_drag_depth(distance);
+    }
+
+    /// True while dragging a selection rectangle over empty space.
+    pub fn is_dragging_empty_space(&self) -> bool {
+        self.shared.borrow().is_dragging_empty_space()
+    }
+
+    /// Rubber-band selection, corners are in normalized device coordinates.
+    pub fn select_in_rect(
+        &self,
+        view_projection: &na::Matrix4<f32>,
+        a: na::Point2<f32>,
+        b: na::Point2<f32>,
+        additive: bool,
+    ) {
+        self.shared
+            .borrow_mut()
+            .select_in_rect(view_projection, a, b, additive);
+    }
+
+    pub fn selection(&self) -> Selection {
+        Selection {
+            handles: self.shared.borrow().selected().to_vec(),
+        }
+    }
+
+    pub fn set_gizmo_mode(&self, mode: GizmoMode) {
+        self.shared.borrow_mut().set_gizmo_mode(mode);
+    }
+
+    pub fn gizmo_mode(&self) -> GizmoMode {
+        self.shared.borrow().gizmo_mode()
+    }
+
+    pub fn set_drag_settings(&self, settings: DragSettings) {
+        self.shared.borrow_mut().drag_settings = settings;
+    }
+
+    pub fn drag_settings(&self) -> DragSettings {
+        self.shared.borrow().drag_settings
+    }
+
+    /// Also place objects dragged in `DragMode::Surface` on this surface.
+    pub fn set_drag_surface(&self, surface: Option<Rc<dyn DragSurface>>) {
+        self.shared.borrow_mut().drag_surface = surface;
+    }
+
+    /// Gizmo for the selected object, if any.
+    pub fn get_gizmo(&self) -> Option<Gizmo> {
+        self.shared.borrow().get_gizmo()
+    }
+
     pub fn get_hover_aabb(&self) -> Option<(ContainerHandle, Container)> {
         self.shared.borrow().get_hover_aabb()
     }
 
+    /// Unregister the selectable now, instead of whenever its owner is dropped.
+    pub fn remove(&self, selectable: SelectableAABB) {
+        debug_assert!(
+            Rc::ptr_eq(&self.shared, &selectable.shared),
+            "selectable belongs to other selectables"
+        );
+        drop(selectable);
+    }
+
+    pub fn contains(&self, handle: ContainerHandle) -> bool {
+        self.shared.borrow().get_container(handle).is_some()
+    }
+
+    pub fn get_container(&self, handle: ContainerHandle) -> Option<Container> {
+        self.shared.borrow().get_container(handle).cloned()
+    }
+
+    /// Selectables that may be visible with the view projection, found in the tree.
+    pub fn in_frustum(&self, view_projection: &na::Matrix4<f32>) -> Vec<ContainerHandle> {
+        self.shared.borrow().in_frustum(view_projection)
+    }
+
+    /// World bounds of the tree nodes with their depth, i.e. to draw them with debug lines.
+    pub fn bvh_nodes(&self) -> Vec<(AABB<f32>, usize)> {
+        self.shared.borrow().bvh_nodes()
+    }
+
+    /// Selectable under the cursor and the point on it, as of the last `cast_cursor`.
+    pub fn cursor_hit(&self) -> Option<(ContainerHandle, Hit)> {
+        self.shared.borrow().cursor_hit()
+    }
+
+    /// Ray of the last `cast_cursor`, i.e. to intersect with surfaces that are not selectable.
+    pub fn cursor_ray(&self) -> Option<Ray<f32>> {
+        self.shared.borrow().last_ray()
+    }
+
+    /// Transform and scale of the object once its queued actions are applied.
+    pub fn target_transform(
+        &self,
+        handle: ContainerHandle,
+    ) -> Option<(na::Isometry3<f32>, na::Vector3<f32>)> {
+        self.shared.borrow().target_transform(handle)
+    }
+
+    /// Primary selection.
     pub fn get_selected_aabb(&self) -> Option<(ContainerHandle, Container)> {
         self.shared.borrow().get_selected_aabb()
     }
+
+    pub fn get_selected_aabbs(&self) -> Vec<(ContainerHandle, Container)> {
+        self.shared.borrow().get_selected_aabbs()
+    }
+}
+
+fn tri_mesh(vertices: Vec<na::Point3<f32>>, indices: &[u32]) -> TriMesh<f32> {
+    let triangles = indices
+        .chunks(3)
+        .filter(|t| t.len() == 3)
+        .map(|t| na::Point3::new(t[0] as usize, t[1] as usize, t[2] as usize))
+        .collect();
+    TriMesh::new(vertices, triangles, None)
 }
@@ -1,9 +1,14 @@
-use super::Action;
+use super::bvh::Bvh;
+use super::gizmo::{self, Gizmo, GizmoAxis, GizmoMode};
+use crate::camera::projection;
+use crate::mesh;
+use super::{Action, DragMode, DragSettings, DragSurface, Hit, TransformChange};
 use nalgebra as na;
 use ncollide3d::bounding_volume::aabb::AABB;
-use ncollide3d::query::{Ray, RayCast};
-use ncollide3d::shape::Plane;
+use ncollide3d::query::{Ray, RayCast, RayIntersection};
+use ncollide3d::shape::{FeatureId, TriMesh};
 use slab::Slab;
+use std::rc::Rc;
 
 #[derive(Copy, Clone)]
 struct PendingAction {
@@ -11,37 +16,80 @@ struct PendingAction {
     action: Action,
 }
 
+/// Transform of a selected object when the group drag started.
+#[derive(Copy, Clone)]
+struct GroupMember {
+    handle: ContainerHandle,
+    isometry: na::Isometry3<f32>,
+    scale: na::Vector3<f32>,
+}
+
 #[derive(Clone)]
 enum DragState {
     NoObject,
     ViewPlane {
         handle: ContainerHandle,
-        initial_isometry: na::Isometry3<f32>,
+        group: Vec<GroupMember>,
         drag_start_point: na::Point3<f32>,
         drag_start_camera_target_pos: na::Point3<f32>,
+        /// Distance the plane was pushed away from the camera.
+        depth: f32,
+    },
+    Gizmo {
+        handle: ContainerHandle,
+        group: Vec<GroupMember>,
+        axis: GizmoAxis,
+        /// Position along the axis, or angle around it when rotating.
+        start: f32,
     },
 }
 
 pub struct SharedSelectables {
     containers: Slab<Container>,
+    /// World bounds of all containers, kept in sync by `update_container`.
+    bvh: Bvh,
     under_cursor: Option<ContainerHandle>,
-    selected: Option<ContainerHandle>,
-    query: Option<PendingAction>,
+    under_cursor_hit: Option<Hit>,
+    /// Last item is the primary selection that carries the gizmo.
+    selected: Vec<ContainerHandle>,
+    query: Vec<PendingAction>,
+    /// Transforms changed by drags released since the last `drain_finished_drags`.
+    finished_drags: Vec<TransformChange>,
 
     mouse_down: bool,
     drag_state: Option<DragState>,
+
+    last_ray: Option<Ray<f32>>,
+    /// Object found in the id buffer, replaces ray tests against all containers when set.
+    picked: Option<Option<ContainerHandle>>,
+    gizmo_mode: GizmoMode,
+    gizmo_size: f32,
+    gizmo_hover: Option<GizmoAxis>,
+    pub drag_settings: DragSettings,
+    pub drag_surface: Option<Rc<dyn DragSurface>>,
 }
 
 impl SharedSelectables {
     pub fn new() -> SharedSelectables {
         SharedSelectables {
             containers: Slab::new(),
+            bvh: Bvh::new(),
             under_cursor: None,
-            selected: None,
-            query: None,
+            under_cursor_hit: None,
+            selected: Vec::new(),
+            query: Vec::new(),
+            finished_drags: Vec::new(),
 
             mouse_down: false,
             drag_state: None,
+
+            last_ray: None,
+            picked: None,
+            gizmo_mode: GizmoMode::Translate,
+            gizmo_size: 1.0,
+            gizmo_hover: None,
+            drag_settings: DragSettings::default(),
+            drag_surface: None,
         }
     }
 
@@ -49,31 +97,102 @@ impl SharedSelectables {
         &mut self,
         aabb: AABB<f32>,
         isometry: na::Isometry3<f32>,
+        mesh: Option<Rc<TriMesh<f32>>>,
     ) -> ContainerHandle {
-        ContainerHandle(self.containers.insert(Container { aabb, isometry }))
+        let container = Container {
+            aabb,
+            isometry,
+            scale: na::Vector3::new(1.0, 1.0, 1.0),
+            mesh,
+        };
+        let world_aabb = container.world_aabb();
+        let handle = ContainerHandle(self.containers.insert(container));
+        self.bvh.update(handle, &world_aabb);
+        handle
     }
 
     pub fn remove_container(&mut self, handle: ContainerHandle) {
         if self.under_cursor == Some(handle) {
             self.under_cursor = None;
+            self.under_cursor_hit = None;
         }
-        if self.selected == Some(handle) {
-            self.selected = None;
+        if self.picked == Some(Some(handle)) {
+            self.picked = Some(None);
         }
-        if let Some(DragState::ViewPlane {
-            handle: drag_handle,
-            ..
-        }) = self.drag_state
-        {
-            if handle == drag_handle {
-                self.drag_state = None;
+        if self.primary_selected() == Some(handle) {
+            self.gizmo_hover = None;
+        }
+        self.selected.retain(|h| *h != handle);
+        self.query.retain(|p| p.handle != handle);
+        self.finished_drags.retain(|c| c.handle != handle);
+        match self.drag_state {
+            Some(DragState::ViewPlane {
+                handle: drag_handle,
+                ..
+            })
+            | Some(DragState::Gizmo {
+                handle: drag_handle,
+                ..
+            }) if handle == drag_handle => self.drag_state = None,
+            Some(DragState::ViewPlane { ref mut group, .. })
+            | Some(DragState::Gizmo { ref mut group, .. }) => {
+                group.retain(|m| m.handle != handle)
             }
+            _ => (),
         }
+        self.bvh.remove(handle);
         self.containers.remove(handle.0);
     }
 
-    pub fn get_container_mut(&mut self, handle: ContainerHandle) -> Option<&mut Container> {
-        self.containers.get_mut(handle.0)
+    pub fn get_container(&self, handle: ContainerHandle) -> Option<&Container> {
+        self.containers.get(handle.0)
+    }
+
+    /// Change the container and move it in the tree.
+    pub fn update_container(
+        &mut self,
+        handle: ContainerHandle,
+        update: impl FnOnce(&mut Container),
+    ) {
+        if let Some(container) = self.containers.get_mut(handle.0) {
+            update(container);
+            self.bvh.update(handle, &container.world_aabb());
+        }
+    }
+
+    /// Containers whose bounds may be inside the view frustum.
+    pub fn in_frustum(&self, view_projection: &na::Matrix4<f32>) -> Vec<ContainerHandle> {
+        self.bvh.in_frustum(
+            view_projection,
+            &na::Point2::new(-1.0, -1.0),
+            &na::Point2::new(1.0, 1.0),
+        )
+    }
+
+    pub fn bvh_nodes(&self) -> Vec<(AABB<f32>, usize)> {
+        self.bvh.nodes()
+    }
+
+    pub fn cursor_hit(&self) -> Option<(ContainerHandle, Hit)> {
+        match (self.under_cursor, self.under_cursor_hit) {
+            (Some(handle), Some(hit)) => Some((handle, hit)),
+            _ => None,
+        }
+    }
+
+    pub fn last_ray(&self) -> Option<Ray<f32>> {
+        self.last_ray
+    }
+
+    /// Use object id read from the id buffer under the cursor, `NO_ID` for empty space.
+    pub fn set_picked_id(&mut self, id: u32) {
+        let handle = ContainerHandle::from_pick_id(id).filter(|h| self.containers.contains(h.0));
+        self.picked = Some(handle);
+    }
+
+    /// Go back to ray tests against container bounds and meshes.
+    pub fn clear_picked_id(&mut self) {
+        self.picked = None;
     }
 
     pub fn cast_cursor(
@@ -82,84 +201,96 @@ impl SharedSelectables {
         camera_target_pos: &na::Point3<f32>,
         camera_dir: &na::Vector3<f32>,
     ) {
-        let mut closest = None;
-        let mut impact_point = None;
-        let mut impact_obj_isometry = None;
-        let mut closest_distance2 = None;
+        self.last_ray = Some(*ray);
 
-        for (handle, c) in &self.containers {
-            if let Some(toi) = c.aabb.toi_with_ray(&c.isometry, ray, true) {
-                let point = ray.origin + ray.dir * toi;
-                let distance2 = na::distance_squared(&point, &ray.origin);
-                let new_closest = match closest_distance2 {
-                    None => true,
-                    Some(ref cd) => if distance2 < *cd {
-                        true
-                    } else {
-                        false
-                    },
-                };
+        if let Some(DragState::Gizmo { .. }) = self.drag_state {
+            self.drag_gizmo(ray);
+            return;
+        }
 
-                if new_closest {
-                    closest_distance2 = Some(distance2);
-                    impact_point = Some(point);
-                    impact_obj_isometry = Some(c.isometry);
-                    closest = Some(handle);
-                }
+        self.gizmo_hover = None;
+        if let Some(isometry) = self.selected_isometry() {
+            self.gizmo_size = gizmo::size_for_camera(&isometry, ray);
+            if self.drag_state.is_none() {
+                self.gizmo_hover =
+                    gizmo::pick_axis(self.gizmo_mode, &isometry, self.gizmo_size, ray);
             }
         }
 
-        self.under_cursor = closest.map(ContainerHandle);
+        let (closest, closest_hit) = match self.picked {
+            Some(picked) => (picked, picked.and_then(|handle| self.picked_hit(handle, ray))),
+            None => match self.closest_hit(ray) {
+                Some((handle, hit)) => (Some(handle), Some(hit)),
+                None => (None, None),
+            },
+        };
+        let impact_point = closest_hit.map(|hit| hit.point);
+
+        self.under_cursor = closest;
+        self.under_cursor_hit = closest_hit;
 
         const DRAG_SNAP_DISTANCE: f32 = 0.1;
 
-        match self.drag_state {
+        match self.drag_state.clone() {
             None => if self.mouse_down {
-                match (self.under_cursor, impact_point, impact_obj_isometry) {
-                    (Some(under_cursor_obj), Some(start_point), Some(impact_obj_isometry)) => {
+                match (self.under_cursor, impact_point) {
+                    (Some(under_cursor_obj), Some(start_point)) => {
+                        // whole selection moves together if the grabbed object is part of it
+                        let group = if self.selected.contains(&under_cursor_obj) {
+                            self.group_members(&self.selected)
+                        } else {
+                            self.group_members(&[under_cursor_obj])
+                        };
                         self.drag_state = Some(DragState::ViewPlane {
                             handle: under_cursor_obj,
-                            initial_isometry: impact_obj_isometry,
+                            group,
                             drag_start_point: start_point,
                             drag_start_camera_target_pos: *camera_target_pos,
+                            depth: 0.0,
                         })
                     }
-                    (None, _, _) => self.drag_state = Some(DragState::NoObject), // dragging empty space until mouse up
+                    (None, _) => self.drag_state = Some(DragState::NoObject), // dragging empty space until mouse up
                     _ => (),
                 }
             },
+            Some(DragState::ViewPlane { handle, ref group, .. })
+                if self.drag_settings.mode == DragMode::Surface =>
+            {
+                self.drag_to_surface(handle, group, ray);
+            }
             Some(DragState::ViewPlane {
-                handle,
-                initial_isometry,
+                group,
                 drag_start_point,
                 drag_start_camera_target_pos,
+                depth,
+                ..
             }) => {
-                let plane = Plane::new(na::Unit::new_normalize(-camera_dir));
                 let movement_difference = camera_target_pos - drag_start_camera_target_pos;
-                let plane_isometry = na::Isometry3::from_parts(
-                    na::Translation3::from(drag_start_point.coords + movement_difference),
-                    na::UnitQuaternion::identity(),
-                );
-                if let Some(toi) = plane.toi_with_ray(&plane_isometry, ray, true) {
+                let plane_point = drag_start_point + movement_difference + camera_dir * depth;
+                let plane_normal = na::Unit::new_normalize(-camera_dir);
+                if let Some(toi) = projection::plane_toi(ray, &plane_point, &plane_normal) {
                     let dragged_to_point_on_plane = ray.origin + ray.dir * toi;
-                    let drag_vector = dragged_to_point_on_plane - drag_start_point;
-                    if na::norm_squared(&drag_vector) > DRAG_SNAP_DISTANCE * DRAG_SNAP_DISTANCE {
-                        self.query = Some(PendingAction {
-                            handle,
-                            action: Action::Drag {
-                                new_isometry: na::Isometry3::from_parts(
-                                    na::Translation3::from(drag_vector),
-                                    na::UnitQuaternion::identity(),
-                                ) * initial_isometry,
-                            },
-                        });
+                    let mut drag_vector = dragged_to_point_on_plane - drag_start_point;
+                    if let Some(step) = self.drag_settings.grid {
+                        drag_vector = drag_vector.map(|v| snap_to_grid(v, step));
+                    }
+                    let delta = if na::norm_squared(&drag_vector)
+                        > DRAG_SNAP_DISTANCE * DRAG_SNAP_DISTANCE
+                    {
+                        na::Isometry3::from_parts(
+                            na::Translation3::from(drag_vector),
+                            na::UnitQuaternion::identity(),
+                        )
                     } else {
-                        self.query = Some(PendingAction {
-                            handle,
-                            action: Action::Drag {
-                                new_isometry: initial_isometry,
+                        na::Isometry3::identity()
+                    };
+                    for member in &group {
+                        self.push_action(
+                            member.handle,
+                            Action::Drag {
+                                new_isometry: delta * member.isometry,
                             },
-                        });
+                        );
                     }
                 }
             }
@@ -167,44 +298,202 @@ impl SharedSelectables {
         };
     }
 
-    pub fn send_mouse_down(&mut self) {
+    /// Closest container hit by the ray, testing only those whose tree leaf it hits.
+    fn closest_hit(&self, ray: &Ray<f32>) -> Option<(ContainerHandle, Hit)> {
+        let mut closest = None;
+        let mut closest_distance2 = None;
+
+        for handle in self.bvh.cast_ray(ray) {
+            let c = match self.containers.get(handle.0) {
+                Some(c) => c,
+                None => continue,
+            };
+            if let Some(hit) = c.cast_ray(ray) {
+                let distance2 = na::distance_squared(&hit.point, &ray.origin);
+                let new_closest = match closest_distance2 {
+                    None => true,
+                    Some(ref cd) => distance2 < *cd,
+                };
+
+                if new_closest {
+                    closest_distance2 = Some(distance2);
+                    closest = Some((handle, hit));
+                }
+            }
+        }
+
+        closest
+    }
+
+    /// Surface point of the picked container. The id buffer is a few frames old and
+    /// rasterized triangles do not match the collider exactly, so the bounds are the fallback.
+    fn picked_hit(&self, handle: ContainerHandle, ray: &Ray<f32>) -> Option<Hit> {
+        let container = self.containers.get(handle.0)?;
+        container
+            .cast_ray(ray)
+            .or_else(|| container.cast_ray_aabb(ray))
+    }
+
+    /// Press without modifiers replaces the selection, unless clicking an already selected
+    /// object, so that the group can be dragged. With `additive`, the object is toggled.
+    pub fn send_mouse_down(&mut self, additive: bool) {
         self.mouse_down = true;
-        if self.selected.is_some() && self.under_cursor.is_none() {
-            self.selected = None;
+        if !additive && self.start_gizmo_drag() {
+            return;
         }
-        match (self.selected.is_some(), self.under_cursor) {
-            (true, None) => self.selected = None,
-            (_, Some(handle)) => self.selected = Some(handle),
-            _ => (),
+
+        match self.under_cursor {
+            None => if !additive {
+                self.selected.clear();
+            },
+            Some(handle) => {
+                let was_selected = self.selected.contains(&handle);
+                if additive && was_selected {
+                    self.selected.retain(|h| *h != handle);
+                    // deselecting is not a click on the object
+                    return;
+                }
+                if !additive && !was_selected {
+                    self.selected.clear();
+                }
+                self.select(handle);
+            }
+        }
+
+        if let (Some(handle), Some(hit)) = (self.under_cursor, self.under_cursor_hit) {
+            self.query.push(PendingAction {
+                handle,
+                action: Action::Click { hit },
+            });
         }
     }
 
     pub fn send_mouse_up(&mut self) {
         self.mouse_down = false;
-        self.drag_state = None;
+        let group = match self.drag_state.take() {
+            Some(DragState::Gizmo { group, .. }) | Some(DragState::ViewPlane { group, .. }) => {
+                group
+            }
+            _ => return,
+        };
+        for member in group {
+            let (new_isometry, new_scale) = match self.target_transform(member.handle) {
+                Some(transform) => transform,
+                None => continue,
+            };
+            if new_isometry != member.isometry || new_scale != member.scale {
+                self.finished_drags.push(TransformChange {
+                    handle: member.handle,
+                    old_isometry: member.isometry,
+                    old_scale: member.scale,
+                    new_isometry,
+                    new_scale,
+                });
+            }
+        }
+    }
+
+    pub fn drain_finished_drags(&mut self) -> Vec<TransformChange> {
+        self.finished_drags.drain(..).collect()
+    }
+
+    /// Transform the container ends up with once its queued actions are applied.
+    pub fn target_transform(
+        &self,
+        handle: ContainerHandle,
+    ) -> Option<(na::Isometry3<f32>, na::Vector3<f32>)> {
+        let container = self.containers.get(handle.0)?;
+        let (mut isometry, mut scale) = (container.isometry, container.scale);
+        for pending in self.query.iter().filter(|p| p.handle == handle) {
+            match pending.action {
+                Action::Drag { new_isometry } => isometry = new_isometry,
+                Action::Scale { new_scale } => scale = new_scale,
+                Action::Click { .. } => (),
+            }
+        }
+        Some((isometry, scale))
+    }
+
+    pub fn move_drag_depth(&mut self, distance: f32) {
+        if let Some(DragState::ViewPlane { ref mut depth, .. }) = self.drag_state {
+            *depth += distance;
+        }
     }
 
     pub fn cancel_drag(&mut self) {
+        let group = match self.drag_state {
+            Some(DragState::Gizmo { ref group, .. })
+            | Some(DragState::ViewPlane { ref group, .. }) => group.clone(),
+            _ => return,
+        };
+        let restore_scale = match self.drag_state {
+            Some(DragState::Gizmo { .. }) => self.gizmo_mode == GizmoMode::Scale,
+            _ => false,
+        };
+
+        self.drag_state = Some(DragState::NoObject);
+        for member in group {
+            let action = if restore_scale {
+                Action::Scale {
+                    new_scale: member.scale,
+                }
+            } else {
+                Action::Drag {
+                    new_isometry: member.isometry,
+                }
+            };
+            self.push_action(member.handle, action);
+        }
+    }
+
+    /// True while the mouse is held down after pressing on empty space.
+    pub fn is_dragging_empty_space(&self) -> bool {
         match self.drag_state {
-            Some(DragState::ViewPlane {
-                handle,
-                initial_isometry,
-                ..
-            }) => {
-                self.drag_state = Some(DragState::NoObject);
-                self.query = Some(PendingAction {
-                    handle,
-                    action: Action::Drag {
-                        new_isometry: initial_isometry,
-                    },
-                });
-            }
-            _ => (),
+            Some(DragState::NoObject) => self.mouse_down,
+            _ => false,
+        }
+    }
+
+    /// Select all objects whose projected AABB overlaps the rectangle in normalized device
+    /// coordinates.
+    pub fn select_in_rect(
+        &mut self,
+        view_projection: &na::Matrix4<f32>,
+        a: na::Point2<f32>,
+        b: na::Point2<f32>,
+        additive: bool,
+    ) {
+        let min = na::Point2::new(a.x.min(b.x), a.y.min(b.y));
+        let max = na::Point2::new(a.x.max(b.x), a.y.max(b.y));
+
+        if !additive {
+            self.selected.clear();
+        }
+
+        let inside: Vec<_> = self
+            .bvh
+            .in_frustum(view_projection, &min, &max)
+            .into_iter()
+            .filter(|handle| match self.containers.get(handle.0) {
+                Some(c) => match c.projected_rect(view_projection) {
+                    Some((c_min, c_max)) => {
+                        c_min.x <= max.x
+                            && c_max.x >= min.x
+                            && c_min.y <= max.y
+                            && c_max.y >= min.y
+                    }
+                    None => false,
+                },
+                None => false,
+            }).collect();
+
+        for handle in inside {
+            self.select(handle);
         }
     }
 
     pub fn drain_pending_action(&mut self, consumer_handle: ContainerHandle) -> Option<Action> {
-        if let Some(_) = self
+        if let Some(index) = self
             .query
             .iter()
             .enumerate()
@@ -213,13 +502,255 @@ impl SharedSelectables {
                 _ => None,
             }).next()
         {
-            return self.query.take().map(|p| p.action);
+            return Some(self.query.remove(index).action);
         }
         None
     }
 
+    /// Add to selection and make it primary.
     pub fn select(&mut self, handle: ContainerHandle) {
-        self.selected = Some(handle);
+        self.selected.retain(|h| *h != handle);
+        self.selected.push(handle);
+    }
+
+    pub fn deselect(&mut self, handle: ContainerHandle) {
+        self.selected.retain(|h| *h != handle);
+    }
+
+    pub fn selected(&self) -> &[ContainerHandle] {
+        &self.selected
+    }
+
+    pub fn set_gizmo_mode(&mut self, mode: GizmoMode) {
+        if self.drag_state.is_none() {
+            self.gizmo_mode = mode;
+        }
+    }
+
+    pub fn gizmo_mode(&self) -> GizmoMode {
+        self.gizmo_mode
+    }
+
+    pub fn get_gizmo(&self) -> Option<Gizmo> {
+        let active_axis = match self.drag_state {
+            Some(DragState::Gizmo { axis, .. }) => Some(axis),
+            _ => None,
+        };
+
+        self.selected_isometry().map(|isometry| Gizmo {
+            mode: self.gizmo_mode,
+            isometry,
+            size: self.gizmo_size,
+            hover_axis: self.gizmo_hover,
+            active_axis,
+        })
+    }
+
+    fn primary_selected(&self) -> Option<ContainerHandle> {
+        self.selected.last().cloned()
+    }
+
+    fn selected_isometry(&self) -> Option<na::Isometry3<f32>> {
+        self.primary_selected()
+            .and_then(|handle| self.containers.get(handle.0))
+            .map(|c| c.isometry)
+    }
+
+    fn group_members(&self, handles: &[ContainerHandle]) -> Vec<GroupMember> {
+        handles
+            .iter()
+            .filter_map(|&handle| {
+                self.containers.get(handle.0).map(|c| GroupMember {
+                    handle,
+                    isometry: c.isometry,
+                    scale: c.scale,
+                })
+            }).collect()
+    }
+
+    /// Queue an action, replacing a not yet drained action of the same kind.
+    pub fn push_action(&mut self, handle: ContainerHandle, action: Action) {
+        let existing = self.query.iter_mut().find(|p| {
+            p.handle == handle
+                && ::std::mem::discriminant(&p.action) == ::std::mem::discriminant(&action)
+        });
+        match existing {
+            Some(pending) => pending.action = action,
+            None => self.query.push(PendingAction { handle, action }),
+        }
+    }
+
+    /// Place the primary object on the closest surface under the cursor, other objects of the
+    /// group keep their offsets from it.
+    fn drag_to_surface(&mut self, handle: ContainerHandle, group: &[GroupMember], ray: &Ray<f32>) {
+        let primary = match group.iter().find(|m| m.handle == handle) {
+            Some(member) => *member,
+            None => return,
+        };
+        let container = match self.containers.get(handle.0) {
+            Some(container) => container,
+            None => return,
+        };
+
+        // dragged objects would be hit first, so they are skipped
+        let surface_hit = self
+            .containers
+            .iter()
+            .filter(|&(h, _)| !group.iter().any(|m| m.handle.0 == h))
+            .filter_map(|(_, c)| c.cast_ray(ray))
+            .chain(self.drag_surface.as_ref().and_then(|s| s.cast_ray(ray)))
+            .map(|hit| (na::distance_squared(&hit.point, &ray.origin), hit.point, hit.normal))
+            .min_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(::std::cmp::Ordering::Equal));
+
+        let drag = &self.drag_settings;
+        let ground_toi = projection::plane_toi(ray, &drag.ground_point, &drag.ground_normal);
+        let ground_hit = ground_toi.map(|toi| {
+            let point = ray.origin + ray.dir * toi;
+            let normal = drag.ground_normal.into_inner();
+            (na::distance_squared(&point, &ray.origin), point, normal)
+        });
+
+        let (point, normal) = match (surface_hit, ground_hit) {
+            (Some(surface), Some(ground)) if ground.0 < surface.0 => (ground.1, ground.2),
+            (Some(surface), _) => (surface.1, surface.2),
+            (None, Some(ground)) => (ground.1, ground.2),
+            (None, None) => return,
+        };
+
+        let point = match self.drag_settings.grid {
+            // snap along the surface, keeping the point on it
+            Some(step) => {
+                let snapped = na::Point3::from(point.coords.map(|v| snap_to_grid(v, step)));
+                snapped - normal * (snapped - point).dot(&normal)
+            }
+            None => point,
+        };
+
+        // lift the object so that its lowest corner along the normal touches the surface
+        let aabb = mesh::scale_aabb(&container.aabb, &primary.scale);
+        let (a, b) = (aabb.mins(), aabb.maxs());
+        let lift = (0..8)
+            .map(|index| {
+                let corner = na::Vector3::new(
+                    if index & 1 == 0 { a.x } else { b.x },
+                    if index & 2 == 0 { a.y } else { b.y },
+                    if index & 4 == 0 { a.z } else { b.z },
+                );
+                -(primary.isometry.rotation * corner).dot(&normal)
+            }).fold(::std::f32::MIN, f32::max);
+
+        let translation = point + normal * lift - primary.isometry.translation.vector;
+        let delta = na::Isometry3::from_parts(
+            na::Translation3::from(translation.coords),
+            na::UnitQuaternion::identity(),
+        );
+        for member in group {
+            self.push_action(
+                member.handle,
+                Action::Drag {
+                    new_isometry: delta * member.isometry,
+                },
+            );
+        }
+    }
+
+    fn start_gizmo_drag(&mut self) -> bool {
+        let (handle, axis, ray) =
+            match (self.primary_selected(), self.gizmo_hover, self.last_ray) {
+                (Some(handle), Some(axis), Some(ray)) => (handle, axis, ray),
+                _ => return false,
+            };
+        let isometry = match self.containers.get(handle.0) {
+            Some(c) => c.isometry,
+            None => return false,
+        };
+
+        let start = match self.gizmo_value(&isometry, axis, &ray) {
+            Some(start) => start,
+            None => return false,
+        };
+
+        self.drag_state = Some(DragState::Gizmo {
+            handle,
+            group: self.group_members(&self.selected),
+            axis,
+            start,
+        });
+        true
+    }
+
+    fn drag_gizmo(&mut self, ray: &Ray<f32>) {
+        let (handle, group, axis, start) = match self.drag_state {
+            Some(DragState::Gizmo {
+                handle,
+                ref group,
+                axis,
+                start,
+            }) => (handle, group.clone(), axis, start),
+            _ => return,
+        };
+        let primary_isometry = match group.iter().find(|m| m.handle == handle) {
+            Some(member) => member.isometry,
+            None => return,
+        };
+
+        let value = match self.gizmo_value(&primary_isometry, axis, ray) {
+            Some(value) => value,
+            None => return,
+        };
+        let direction = gizmo::world_direction(&primary_isometry, axis);
+
+        // other selected objects move relative to the primary one
+        let delta = match self.gizmo_mode {
+            GizmoMode::Translate => na::Isometry3::from_parts(
+                na::Translation3::from(direction * (value - start)),
+                na::UnitQuaternion::identity(),
+            ),
+            GizmoMode::Rotate => na::Isometry3::rotation_wrt_point(
+                na::UnitQuaternion::from_axis_angle(
+                    &na::Unit::new_normalize(direction),
+                    value - start,
+                ),
+                gizmo::center(&primary_isometry),
+            ),
+            GizmoMode::Scale => na::Isometry3::identity(),
+        };
+
+        for member in &group {
+            let action = match self.gizmo_mode {
+                GizmoMode::Scale => {
+                    const MIN_SCALE: f32 = 0.01;
+                    let mut new_scale = member.scale;
+                    new_scale[axis.index()] =
+                        (member.scale[axis.index()] * value / start).max(MIN_SCALE);
+                    Action::Scale { new_scale }
+                }
+                _ => Action::Drag {
+                    new_isometry: delta * member.isometry,
+                },
+            };
+            self.push_action(member.handle, action);
+        }
+    }
+
+    fn gizmo_value(
+        &self,
+        isometry: &na::Isometry3<f32>,
+        axis: GizmoAxis,
+        ray: &Ray<f32>,
+    ) -> Option<f32> {
+        let center = gizmo::center(isometry);
+        let direction = gizmo::world_direction(isometry, axis);
+
+        match self.gizmo_mode {
+            GizmoMode::Translate => {
+                gizmo::closest_on_axis(&center, &direction, ray).map(|(param, _, _)| param)
+            }
+            // avoid division by zero when grabbing the handle right at the center
+            GizmoMode::Scale => gizmo::closest_on_axis(&center, &direction, ray)
+                .map(|(param, _, _)| param.max(self.gizmo_size * 0.1)),
+            GizmoMode::Rotate => gizmo::angle_around_axis(&center, &direction, ray),
+        }
     }
 
     pub fn get_hover_aabb(&self) -> Option<(ContainerHandle, Container)> {
@@ -229,19 +760,164 @@ impl SharedSelectables {
         }
     }
 
+    /// Primary selection.
     pub fn get_selected_aabb(&self) -> Option<(ContainerHandle, Container)> {
-        match self.selected {
+        match self.primary_selected() {
             Some(handle) => self.containers.get(handle.0).map(|c| (handle, c.clone())),
             None => None,
         }
     }
+
+    pub fn get_selected_aabbs(&self) -> Vec<(ContainerHandle, Container)> {
+        self.selected
+            .iter()
+            .filter_map(|&handle| self.containers.get(handle.0).map(|c| (handle, c.clone())))
+            .collect()
+    }
 }
 
-#[derive(Copy, Clone, Eq, PartialEq)]
+fn snap_to_grid(value: f32, step: f32) -> f32 {
+    (value / step).round() * step
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
 pub struct ContainerHandle(usize);
 
+impl ContainerHandle {
+    /// Non-zero id to render into the id buffer, zero is left for empty space.
+    pub fn pick_id(self) -> u32 {
+        self.0 as u32 + 1
+    }
+
+    pub fn from_pick_id(id: u32) -> Option<ContainerHandle> {
+        match id {
+            0 => None,
+            id => Some(ContainerHandle(id as usize - 1)),
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct Container {
     pub aabb: AABB<f32>,
     pub isometry: na::Isometry3<f32>,
+    /// Non-uniform scale applied before the isometry.
+    pub scale: na::Vector3<f32>,
+    /// Optional precise collider in the same local space as the AABB.
+    pub mesh: Option<Rc<TriMesh<f32>>>,
+}
+
+impl Container {
+    /// Triangles of the mesh collider in world space, none without one.
+    pub fn world_triangles(&self) -> Vec<[na::Point3<f32>; 3]> {
+        let mesh = match self.mesh {
+            Some(ref mesh) => mesh,
+            None => return Vec::new(),
+        };
+        let to_world = |p: &na::Point3<f32>| {
+            self.isometry * na::Point3::from(p.coords.component_mul(&self.scale))
+        };
+        (0..mesh.faces().len())
+            .map(|index| {
+                let triangle = mesh.triangle_at(index);
+                [
+                    to_world(triangle.a()),
+                    to_world(triangle.b()),
+                    to_world(triangle.c()),
+                ]
+            }).collect()
+    }
+
+    /// Bounds of the scaled AABB in world space.
+    pub fn world_aabb(&self) -> AABB<f32> {
+        self.scaled_aabb().transform_by(&self.isometry)
+    }
+
+    /// AABB with scale applied, to be drawn with the isometry.
+    pub fn scaled_aabb(&self) -> AABB<f32> {
+        mesh::scale_aabb(&self.aabb, &self.scale)
+    }
+
+    /// Screen rectangle in normalized device coordinates covered by the AABB.
+    ///
+    /// Returns `None` if any corner is behind the camera.
+    pub fn projected_rect(
+        &self,
+        view_projection: &na::Matrix4<f32>,
+    ) -> Option<(na::Point2<f32>, na::Point2<f32>)> {
+        let aabb = self.scaled_aabb();
+        let (a, b) = (aabb.mins(), aabb.maxs());
+        let mut min = na::Point2::new(::std::f32::MAX, ::std::f32::MAX);
+        let mut max = na::Point2::new(::std::f32::MIN, ::std::f32::MIN);
+
+        for index in 0..8 {
+            let corner = na::Point3::new(
+                if index & 1 == 0 { a.x } else { b.x },
+                if index & 2 == 0 { a.y } else { b.y },
+                if index & 4 == 0 { a.z } else { b.z },
+            );
+            let ndc = projection::world_to_ndc(&(self.isometry * corner), view_projection)?;
+            min = na::Point2::new(min.x.min(ndc.x), min.y.min(ndc.y));
+            max = na::Point2::new(max.x.max(ndc.x), max.y.max(ndc.y));
+        }
+
+        Some((min, max))
+    }
+
+    /// Test the AABB first, then the mesh if there is one.
+    fn cast_ray(&self, ray: &Ray<f32>) -> Option<Hit> {
+        let local_ray = self.local_ray(ray);
+        let identity = na::Isometry3::identity();
+
+        let aabb_intersection = self
+            .aabb
+            .toi_and_normal_with_ray(&identity, &local_ray, true)?;
+
+        match self.mesh {
+            None => Some(self.hit(ray, &aabb_intersection, None)),
+            Some(ref mesh) => {
+                let intersection = mesh.toi_and_normal_with_ray(&identity, &local_ray, true)?;
+                // back faces are reported after all front faces
+                let triangle = match intersection.feature {
+                    FeatureId::Face(index) => Some(index % mesh.faces().len()),
+                    _ => None,
+                };
+                Some(self.hit(ray, &intersection, triangle))
+            }
+        }
+    }
+
+    /// Test the AABB only, ignoring the mesh.
+    fn cast_ray_aabb(&self, ray: &Ray<f32>) -> Option<Hit> {
+        let intersection = self.aabb.toi_and_normal_with_ray(
+            &na::Isometry3::identity(),
+            &self.local_ray(ray),
+            true,
+        )?;
+        Some(self.hit(ray, &intersection, None))
+    }
+
+    // unscaled local space keeps the same time of impact
+    fn local_ray(&self, ray: &Ray<f32>) -> Ray<f32> {
+        let local_ray = ray.inverse_transform_by(&self.isometry);
+        Ray::new(
+            na::Point3::from(local_ray.origin.coords.component_div(&self.scale)),
+            local_ray.dir.component_div(&self.scale),
+        )
+    }
+
+    fn hit(
+        &self,
+        ray: &Ray<f32>,
+        local_intersection: &RayIntersection<f32>,
+        triangle: Option<usize>,
+    ) -> Hit {
+        let normal = self.isometry.rotation * local_intersection.normal.component_div(&self.scale);
+
+        Hit {
+            point: ray.origin + ray.dir * local_intersection.toi,
+            normal: normal.try_normalize(::std::f32::EPSILON).unwrap_or(normal),
+            triangle,
+        }
+    }
 }
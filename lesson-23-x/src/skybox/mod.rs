@@ -0,0 +1,122 @@
+use failure;
+use gl;
+use crate::render;
+use crate::render_gl::buffer::{Buffer, VertexArray};
+use crate::render_gl::{self, data, CubeTextureSource, TextureCube};
+use crate::resources::Resources;
+
+mod sky;
+
+pub use self::sky::SkyModel;
+
+/// Face size of cube maps rendered from a `SkyModel`.
+const SKY_FACE_SIZE: i32 = 128;
+
+#[derive(VertexAttribPointers, Copy, Clone, Debug)]
+#[repr(C, packed)]
+struct SkyboxVertex {
+    #[location = "0"]
+    pos: data::f32_f32_f32,
+}
+
+/// Environment cube drawn around the camera behind all other geometry.
+pub struct Skybox {
+    program: render_gl::Program,
+    program_environment_location: Option<i32>,
+    texture: TextureCube,
+    /// Drawn instead of `texture` when set.
+    sky: Option<TextureCube>,
+    _vbo: Buffer,
+    vao: VertexArray,
+}
+
+impl Skybox {
+    pub fn new(
+        res: &Resources,
+        gl: &gl::Gl,
+        source: CubeTextureSource,
+    ) -> Result<Skybox, failure::Error> {
+        let program = render_gl::Program::from_res(gl, res, "shaders/skybox")?;
+        program.bind_uniform_block("Camera", render::CAMERA_UNIFORMS_BINDING);
+
+        let texture = TextureCube::from_res(gl, res, source)?;
+
+        // two triangles for each of the cube sides
+        let sides: [[(f32, f32, f32); 4]; 6] = [
+            [(1.0, -1.0, -1.0), (1.0, 1.0, -1.0), (1.0, 1.0, 1.0), (1.0, -1.0, 1.0)],
+            [(-1.0, 1.0, -1.0), (-1.0, -1.0, -1.0), (-1.0, -1.0, 1.0), (-1.0, 1.0, 1.0)],
+            [(1.0, 1.0, -1.0), (-1.0, 1.0, -1.0), (-1.0, 1.0, 1.0), (1.0, 1.0, 1.0)],
+            [(-1.0, -1.0, -1.0), (1.0, -1.0, -1.0), (1.0, -1.0, 1.0), (-1.0, -1.0, 1.0)],
+            [(-1.0, -1.0, 1.0), (1.0, -1.0, 1.0), (1.0, 1.0, 1.0), (-1.0, 1.0, 1.0)],
+            [(-1.0, 1.0, -1.0), (1.0, 1.0, -1.0), (1.0, -1.0, -1.0), (-1.0, -1.0, -1.0)],
+        ];
+        let vertices = sides
+            .iter()
+            .flat_map(|side| [side[0], side[1], side[2], side[0], side[2], side[3]].to_vec())
+            .map(|pos| SkyboxVertex { pos: pos.into() })
+            .collect::<Vec<_>>();
+
+        let vbo = Buffer::new_array(gl);
+        vbo.bind();
+        vbo.static_draw_data(&vertices);
+        vbo.unbind();
+
+        let vao = VertexArray::new(gl);
+        vao.bind();
+        vbo.bind();
+        SkyboxVertex::vertex_attrib_pointers(gl);
+        vao.unbind();
+        vbo.unbind();
+
+        Ok(Skybox {
+            program_environment_location: program.get_uniform_location("Environment"),
+            program,
+            texture,
+            sky: None,
+            _vbo: vbo,
+            vao,
+        })
+    }
+
+    /// Environment cube map, i.e. for reflections.
+    pub fn texture(&self) -> &TextureCube {
+        self.sky.as_ref().unwrap_or(&self.texture)
+    }
+
+    /// Show the sky model instead of the loaded cube map, or go back to it with `None`.
+    pub fn set_sky(&mut self, gl: &gl::Gl, sky: Option<&SkyModel>) {
+        self.sky = sky.map(|sky| sky.texture(gl, SKY_FACE_SIZE));
+    }
+
+    /// Render after opaque geometry, so that only pixels at far plane are covered.
+    pub fn render(&self, gl: &gl::Gl) {
+        self.program.set_used();
+
+        if let Some(loc) = self.program_environment_location {
+            self.texture().bind_at(0);
+            self.program.set_uniform_1i(loc, 0);
+        }
+
+        // camera is inside the box, and the box may poke through near plane
+        render_gl::StateCache::set_cull_face(gl, false);
+        unsafe {
+            gl.Enable(gl::DEPTH_CLAMP);
+            gl.DepthFunc(gl::LEQUAL);
+            render_gl::StateCache::set_depth_mask(gl, false);
+        }
+
+        self.vao.bind();
+        unsafe {
+            gl.DrawArrays(gl::TRIANGLES, 0, 36);
+        }
+        render_gl::FrameStats::record_draw(gl::TRIANGLES, 36, 1);
+        self.vao.unbind();
+
+        unsafe {
+            render_gl::StateCache::set_depth_mask(gl, true);
+            gl.DepthFunc(gl::LESS);
+            gl.Disable(gl::DEPTH_CLAMP);
+        }
+        render_gl::StateCache::set_cull_face(gl, true);
+    }
+}
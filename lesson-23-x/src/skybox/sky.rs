@@ -0,0 +1,148 @@
+use gl;
+use nalgebra as na;
+use crate::render_gl::TextureCube;
+use std::f32::consts::{FRAC_PI_2, PI};
+
+/// Largest sun zenith angle, the model is not defined for a set sun.
+const MAX_SUN_ZENITH: f32 = FRAC_PI_2 - 0.02;
+
+/// Cosine of the angular radius of the sun disc.
+const SUN_DISC_COS: f32 = 0.9997;
+
+/// Daylight sky of the Preetham model, for `Skybox::set_sky`.
+///
+/// Colors are linear, luminance of the model in kcd/m² is scaled by `exposure`. Below the
+/// horizon the sky keeps the horizon color.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SkyModel {
+    /// Direction towards the sun, Z is up.
+    pub sun_direction: na::Vector3<f32>,
+    /// Haziness, from 2 for a clear sky to about 10 for a hazy one.
+    pub turbidity: f32,
+    pub exposure: f32,
+    /// Added to the sky color inside the sun disc.
+    pub sun_intensity: f32,
+}
+
+impl Default for SkyModel {
+    fn default() -> Self {
+        SkyModel {
+            sun_direction: na::Vector3::new(0.4, -0.6, 1.0).normalize(),
+            turbidity: 3.0,
+            exposure: 0.1,
+            sun_intensity: 20.0,
+        }
+    }
+}
+
+impl SkyModel {
+    /// Sky lit by a directional light traveling in `light_direction`.
+    pub fn with_light_direction(mut self, light_direction: &na::Vector3<f32>) -> Self {
+        self.sun_direction = -light_direction.normalize();
+        self
+    }
+
+    /// Cube map of the sky, with mipmaps.
+    pub fn texture(&self, gl: &gl::Gl, face_size: i32) -> TextureCube {
+        let perez = Perez::new(self);
+        TextureCube::from_directions(gl, face_size, |direction| {
+            let direction = direction.normalize();
+            let mut color = perez.color(&direction);
+            if direction.dot(&perez.sun_direction) > SUN_DISC_COS {
+                color += na::Vector3::repeat(self.sun_intensity);
+            }
+            color
+        })
+    }
+
+    /// Average sky color around the horizon, i.e. for fog.
+    pub fn horizon_color(&self) -> na::Vector3<f32> {
+        let perez = Perez::new(self);
+        let samples = 16;
+        (0..samples)
+            .map(|index| {
+                let angle = index as f32 / samples as f32 * 2.0 * PI;
+                perez.color(&na::Vector3::new(angle.cos(), angle.sin(), 0.0))
+            }).fold(na::Vector3::zeros(), |sum, color| sum + color)
+            / samples as f32
+    }
+}
+
+/// Model coefficients for a sun position and turbidity.
+struct Perez {
+    sun_direction: na::Vector3<f32>,
+    /// Distribution coefficients A to E of luminance Y and chromaticity x, y.
+    coefficients: [na::Vector3<f32>; 5],
+    /// Zenith Y, x, y divided by the distribution at the zenith.
+    zenith: na::Vector3<f32>,
+    exposure: f32,
+}
+
+impl Perez {
+    fn new(sky: &SkyModel) -> Perez {
+        let t = sky.turbidity;
+        let sun = sky.sun_direction.normalize();
+        let theta = sun.z.max(-1.0).min(1.0).acos().min(MAX_SUN_ZENITH);
+        let sun_direction = na::Vector3::new(sun.x, sun.y, 0.0)
+            .try_normalize(1.0e-6)
+            .unwrap_or_else(na::Vector3::x)
+            * theta.sin()
+            + na::Vector3::z() * theta.cos();
+
+        let coefficients = [
+            na::Vector3::new(0.1787 * t - 1.4630, -0.0193 * t - 0.2592, -0.0167 * t - 0.2608),
+            na::Vector3::new(-0.3554 * t + 0.4275, -0.0665 * t + 0.0008, -0.0950 * t + 0.0092),
+            na::Vector3::new(-0.0227 * t + 5.3251, -0.0004 * t + 0.2125, -0.0079 * t + 0.2102),
+            na::Vector3::new(0.1206 * t - 2.5771, -0.0641 * t - 0.8989, -0.0441 * t - 1.6537),
+            na::Vector3::new(-0.0670 * t + 0.3703, -0.0033 * t + 0.0452, -0.0109 * t + 0.0529),
+        ];
+
+        let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta);
+        let (theta2, theta3) = (theta * theta, theta * theta * theta);
+        let zenith = na::Vector3::new(
+            (4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192,
+            t * t * (0.00166 * theta3 - 0.00375 * theta2 + 0.00209 * theta)
+                + t * (-0.02903 * theta3 + 0.06377 * theta2 - 0.03202 * theta + 0.00394)
+                + (0.11693 * theta3 - 0.21196 * theta2 + 0.06052 * theta + 0.25886),
+            t * t * (0.00275 * theta3 - 0.00610 * theta2 + 0.00317 * theta)
+                + t * (-0.04214 * theta3 + 0.08970 * theta2 - 0.04153 * theta + 0.00516)
+                + (0.15346 * theta3 - 0.26756 * theta2 + 0.06670 * theta + 0.26688),
+        );
+
+        let mut perez = Perez {
+            sun_direction,
+            coefficients,
+            zenith,
+            exposure: sky.exposure,
+        };
+        let at_zenith = perez.distribution(1.0, theta.cos());
+        perez.zenith = zenith.component_div(&at_zenith);
+        perez
+    }
+
+    /// Relative Y, x, y for the cosines of the view zenith angle and the angle to the sun.
+    fn distribution(&self, cos_theta: f32, cos_gamma: f32) -> na::Vector3<f32> {
+        let gamma = cos_gamma.max(-1.0).min(1.0).acos();
+        let [a, b, c, d, e] = self.coefficients;
+        na::Vector3::from_fn(|i, _| {
+            (1.0 + a[i] * (b[i] / cos_theta).exp())
+                * (1.0 + c[i] * (d[i] * gamma).exp() + e[i] * cos_gamma * cos_gamma)
+        })
+    }
+
+    /// Linear sRGB color of the sky in the normalized direction.
+    fn color(&self, direction: &na::Vector3<f32>) -> na::Vector3<f32> {
+        let cos_theta = direction.z.max(0.01);
+        let cos_gamma = direction.dot(&self.sun_direction);
+        let yxy = self.zenith.component_mul(&self.distribution(cos_theta, cos_gamma));
+
+        let (luminance, x, y) = (yxy.x * self.exposure, yxy.y, yxy.z.max(1.0e-4));
+        let xyz = na::Vector3::new(x / y * luminance, luminance, (1.0 - x - y) / y * luminance);
+        let rgb = na::Matrix3::new(
+            3.2406, -1.5372, -0.4986,
+            -0.9689, 1.8758, 0.0415,
+            0.0557, -0.2040, 1.0570,
+        ) * xyz;
+        rgb.map(|channel| channel.max(0.0))
+    }
+}
@@ -0,0 +1,50 @@
+use failure;
+use failure::err_msg;
+use gl;
+use crate::render_gl::Capabilities;
+use sdl2;
+use std::os::raw;
+
+/// GL context of a hidden window, for rendering without showing anything on screen,
+/// i.e. in tests. Draw into a `Framebuffer`, the window's own one may not be backed.
+pub struct HeadlessContext {
+    pub gl: gl::Gl,
+    _gl_context: sdl2::video::GLContext,
+    _window: sdl2::video::Window,
+    _video_subsystem: sdl2::VideoSubsystem,
+    _sdl: sdl2::Sdl,
+}
+
+impl HeadlessContext {
+    /// Same context version and profile as the lesson window.
+    pub fn new() -> Result<HeadlessContext, failure::Error> {
+        let sdl = sdl2::init().map_err(err_msg)?;
+        let video_subsystem = sdl.video().map_err(err_msg)?;
+
+        let gl_attr = video_subsystem.gl_attr();
+        gl_attr.set_context_profile(sdl2::video::GLProfile::Core);
+        gl_attr.set_context_version(4, 1);
+        gl_attr.set_depth_size(24);
+        gl_attr.set_stencil_size(8);
+
+        let window = video_subsystem
+            .window("Headless", 1, 1)
+            .opengl()
+            .hidden()
+            .build()?;
+
+        let gl_context = window.gl_create_context().map_err(err_msg)?;
+        let gl = gl::Gl::load_with(|s| {
+            video_subsystem.gl_get_proc_address(s) as *const raw::c_void
+        });
+        Capabilities::install(&gl);
+
+        Ok(HeadlessContext {
+            gl,
+            _gl_context: gl_context,
+            _window: window,
+            _video_subsystem: video_subsystem,
+            _sdl: sdl,
+        })
+    }
+}
@@ -1,81 +1,145 @@
-use crate::camera::TargetCamera;
+use crate::camera::{projection, Camera};
+use crate::input::InputMap;
 use nalgebra as na;
 use ncollide3d;
+use crate::platform::{Event, MouseButton};
 use crate::render::WindowSize;
-use sdl2::event::Event;
-use sdl2::keyboard::Scancode;
-use sdl2::mouse::MouseButton;
-use crate::selection::Selectables;
+use crate::selection::{DragMode, GizmoMode, Selectables};
+
+/// Rectangle selection starts after the cursor moves this far, in device coordinates.
+const RECT_SELECT_THRESHOLD: f32 = 0.01;
+/// Grid step toggled for dragging, in world units.
+const DRAG_GRID_STEP: f32 = 1.0;
+/// Fully deflected `drag_depth` axis to world units per second.
+const DRAG_DEPTH_SPEED: f32 = 4.0;
 
 pub struct SelectablesInput {
     previous_device_ray: Option<na::Vector3<f32>>,
+    device_cursor: Option<na::Point2<f32>>,
+    rect_start: Option<na::Point2<f32>>,
 }
 
 impl SelectablesInput {
     pub fn new() -> SelectablesInput {
         SelectablesInput {
             previous_device_ray: None,
+            device_cursor: None,
+            rect_start: None,
+        }
+    }
+
+    /// Rectangle being dragged in normalized device coordinates.
+    pub fn selection_rect(
+        &self,
+        selectables: &Selectables,
+    ) -> Option<(na::Point2<f32>, na::Point2<f32>)> {
+        match (self.rect_start, self.device_cursor) {
+            (Some(start), Some(cursor)) if selectables.is_dragging_empty_space() => {
+                if na::distance(&start, &cursor) > RECT_SELECT_THRESHOLD {
+                    Some((start, cursor))
+                } else {
+                    None
+                }
+            }
+            _ => None,
         }
     }
 
-    pub fn handle_selectable_events(
+    /// Mouse events select and drag, holding `select_additive` adds to the selection.
+    pub fn handle_selectable_events<C: Camera>(
         &mut self,
         event: &Event,
+        input: &InputMap,
         window_size: &WindowSize,
-        camera: &TargetCamera,
+        camera: &C,
         selectables: &Selectables,
     ) {
+        let additive = input.pressed("select_additive");
         match event {
             Event::MouseButtonDown {
-                mouse_btn: MouseButton::Left,
+                button: MouseButton::Left,
                 ..
             } => {
-                selectables.send_mouse_down();
+                selectables.send_mouse_down(additive);
+                self.rect_start = self.device_cursor;
             }
             Event::MouseButtonUp {
-                mouse_btn: MouseButton::Left,
+                button: MouseButton::Left,
                 ..
             } => {
+                if let Some((a, b)) = self.selection_rect(selectables) {
+                    selectables.select_in_rect(&camera.get_vp_matrix(), a, b, additive);
+                }
+                self.rect_start = None;
                 selectables.send_mouse_up();
             }
             Event::MouseMotion { x, y, .. } => {
-                let device_cursor = na::Vector4::new(
-                    *x as f32 / window_size.width as f32 * 2.0 - 1.0,
-                    (1.0 - (*y as f32 / window_size.height as f32)) * 2.0 - 1.0,
-                    -1.0,
-                    1.0,
-                );
+                let screen = na::Point2::new(*x as f32, *y as f32);
+                let size = na::Vector2::new(window_size.width as f32, window_size.height as f32);
+                self.device_cursor = Some(projection::screen_to_ndc(&screen, &size));
 
-                let inverse_view_matrix = camera.get_inverse_view_matrix();
-
-                let device_ray_pos = (camera.get_inverse_p_matrix() * device_cursor)
-                    .fixed_resize::<na::U2, na::U1>(0.0);
-                let device_ray = inverse_view_matrix
-                    * na::Vector4::new(device_ray_pos.x, device_ray_pos.y, -1.0, 0.0);
-                let device_ray = device_ray.fixed_resize::<na::U3, na::U1>(0.0).normalize();
-                self.previous_device_ray = Some(device_ray);
-                Self::cast_ray_for_camera(&device_ray, camera, selectables);
-            }
-            Event::KeyDown {
-                scancode: Some(Scancode::Escape),
-                ..
-            } => {
-                selectables.cancel_drag();
+                let vp_matrix = camera.get_vp_matrix();
+                if let Some(ray) = projection::unproject(&screen, -1.0, &size, &vp_matrix) {
+                    self.previous_device_ray = Some(ray.dir);
+                    Self::cast_ray_for_camera(&ray.dir, camera, selectables);
+                }
             }
             _ => (),
         };
     }
 
-    fn cast_ray_for_camera(
+    /// Gizmo and drag actions, once per frame. The `drag_depth` axis moves objects dragged
+    /// in the view plane closer or further.
+    pub fn handle_selectable_input(
+        &mut self,
+        input: &InputMap,
+        selectables: &Selectables,
+        delta: f32,
+    ) {
+        let depth = input.axis("drag_depth");
+        if depth != 0.0 {
+            selectables.move_drag_depth(depth * DRAG_DEPTH_SPEED * delta);
+        }
+        if input.just_pressed("cancel_drag") {
+            selectables.cancel_drag();
+        }
+        if input.just_pressed("gizmo_translate") {
+            selectables.set_gizmo_mode(GizmoMode::Translate);
+        }
+        if input.just_pressed("gizmo_rotate") {
+            selectables.set_gizmo_mode(GizmoMode::Rotate);
+        }
+        if input.just_pressed("gizmo_scale") {
+            selectables.set_gizmo_mode(GizmoMode::Scale);
+        }
+        if input.just_pressed("toggle_drag_mode") {
+            let mut settings = selectables.drag_settings();
+            settings.mode = match settings.mode {
+                DragMode::ViewPlane => DragMode::Surface,
+                DragMode::Surface => DragMode::ViewPlane,
+            };
+            selectables.set_drag_settings(settings);
+        }
+        if input.just_pressed("toggle_drag_grid") {
+            let mut settings = selectables.drag_settings();
+            settings.grid = match settings.grid {
+                Some(_) => None,
+                None => Some(DRAG_GRID_STEP),
+            };
+            selectables.set_drag_settings(settings);
+        }
+    }
+
+    fn cast_ray_for_camera<C: Camera>(
         device_ray: &na::Vector3<f32>,
-        camera: &TargetCamera,
+        camera: &C,
         selectables: &Selectables,
     ) {
         let ray = ncollide3d::query::Ray::new(camera.project_pos(), *device_ray);
-        selectables.cast_cursor(&ray, &camera.target, &camera.direction());
+        selectables.cast_cursor(&ray, &camera.target(), &camera.direction());
     }
 
-    pub fn update(&mut self, camera: &TargetCamera, selectables: &Selectables) {
+    pub fn update<C: Camera>(&mut self, camera: &C, selectables: &Selectables) {
         if let Some(ref device_ray) = self.previous_device_ray {
             Self::cast_ray_for_camera(device_ray, camera, selectables);
         }
@@ -1,40 +1,32 @@
-use crate::camera::TargetCamera;
+use crate::camera::Camera;
 use gl;
+use crate::platform::Event;
 use crate::render::WindowSize;
-use crate::render_gl::Viewport;
-use sdl2::event::{Event, WindowEvent};
-use sdl2::video::Window;
+use crate::render_gl::{Viewport, ViewportResize};
 
 #[derive(PartialEq)]
 pub enum HandleResult {
     Quit,
+    /// Drawable size changed, render targets of the window size need to follow it.
+    Resized(ViewportResize),
     Continue,
 }
 
-pub fn handle_default_window_events(
+pub fn handle_default_window_events<C: Camera>(
     event: &Event,
     gl: &gl::Gl,
-    window: &Window,
     window_size: &mut WindowSize,
     viewport: &mut Viewport,
-    camera: &mut TargetCamera,
+    camera: &mut C,
 ) -> HandleResult {
     match event {
-        Event::Quit { .. } => return HandleResult::Quit,
-        Event::Window {
-            win_event: WindowEvent::Resized(w, h),
-            ..
-        } => {
-            let (hdpi_w, hdpi_h) = window.drawable_size();
-            viewport.update_size(hdpi_w as i32, hdpi_h as i32);
-            viewport.set_used(&gl);
-            *window_size = WindowSize {
-                width: *w as i32,
-                height: *h as i32,
-                highdpi_width: hdpi_w as i32,
-                highdpi_height: hdpi_h as i32,
-            };
-            camera.update_aspect(hdpi_w as f32 / hdpi_h as f32);
+        Event::Quit => return HandleResult::Quit,
+        Event::Resized(size) => {
+            *window_size = *size;
+            if let Some(resize) = viewport.handle_event(gl, event) {
+                camera.update_aspect(resize.aspect);
+                return HandleResult::Resized(resize);
+            }
         }
         _ => (),
     };
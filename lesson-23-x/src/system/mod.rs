@@ -1,3 +1,4 @@
+pub mod headless;
 pub mod input;
 pub mod profiling;
 pub mod render;
@@ -1,54 +1,236 @@
-use crate::render_gl::{AabbMarker, DebugLines};
-use crate::selection::Selectables;
+use nalgebra as na;
+use ncollide3d::bounding_volume::aabb::AABB;
+use crate::camera::projection;
+use crate::render_gl::{AabbMarker, DebugLines, LinesMarker};
+use crate::selection::{ContainerHandle, Gizmo, GizmoAxis, GizmoMode, Selectables};
+
+const RING_SEGMENTS: usize = 48;
 
 pub struct RenderSelectables {
     hover: Option<AabbMarker>,
-    selected: Option<AabbMarker>,
+    selected: Vec<(ContainerHandle, AabbMarker)>,
+    gizmo: Option<LinesMarker>,
+    selection_rect: Option<LinesMarker>,
+    /// Bounds of the selection tree nodes, while shown.
+    bvh: Option<LinesMarker>,
+    show_bvh: bool,
 }
 
 impl RenderSelectables {
     pub fn new() -> RenderSelectables {
         RenderSelectables {
             hover: None,
-            selected: None,
+            selected: Vec::new(),
+            gizmo: None,
+            selection_rect: None,
+            bvh: None,
+            show_bvh: false,
         }
     }
 
-    pub fn update(&mut self, selectables: &Selectables, debug_lines: &DebugLines) {
-        let selected = selectables.get_selected_aabb();
+    /// Show or hide the selection tree, leaves green and the root red.
+    pub fn toggle_bvh(&mut self) {
+        self.show_bvh = !self.show_bvh;
+    }
+
+    /// Draw rubber-band rectangle, given in normalized device coordinates, just behind
+    /// the near plane.
+    pub fn update_selection_rect(
+        &mut self,
+        rect: Option<(na::Point2<f32>, na::Point2<f32>)>,
+        inverse_vp_matrix: &na::Matrix4<f32>,
+        gizmo_lines: &DebugLines,
+    ) {
+        let (a, b) = match rect {
+            Some(rect) => rect,
+            None => {
+                self.selection_rect = None;
+                return;
+            }
+        };
+
+        let unproject = |x: f32, y: f32| {
+            projection::ndc_to_world(&na::Point3::new(x, y, -0.99), inverse_vp_matrix)
+        };
+        let corners = [
+            unproject(a.x, a.y),
+            unproject(b.x, a.y),
+            unproject(b.x, b.y),
+            unproject(a.x, b.y),
+        ];
+        let color = na::Vector4::new(1.0, 1.0, 1.0, 0.8);
+        let segments = (0..4).map(|index| (corners[index], corners[(index + 1) % 4], color));
 
-        self.selected = match (selected.clone(), self.selected.take()) {
-            (Some((_, c)), None) => {
-                Some(debug_lines.aabb_marker(c.isometry, c.aabb, [1.0, 1.0, 1.0, 1.0].into()))
+        match self.selection_rect {
+            Some(ref marker) => marker.update_lines(segments),
+            None => {
+                self.selection_rect =
+                    Some(gizmo_lines.lines_marker(na::Isometry3::identity(), segments))
             }
+        }
+    }
+
+    /// Gizmo goes to separate `gizmo_lines`, so that it can be drawn on top of everything.
+    pub fn update(
+        &mut self,
+        selectables: &Selectables,
+        debug_lines: &DebugLines,
+        gizmo_lines: &DebugLines,
+    ) {
+        let selected = selectables.get_selected_aabbs();
+        let primary = selected.last().map(|&(handle, _)| handle);
+
+        // reuse markers of objects that stay selected
+        let mut previous_markers: Vec<_> = self.selected.drain(..).collect();
+        for &(handle, ref c) in &selected {
+            let color = if Some(handle) == primary {
+                [1.0, 1.0, 1.0, 1.0].into()
+            } else {
+                [1.0, 1.0, 1.0, 0.6].into()
+            };
+            let marker = match previous_markers.iter().position(|&(h, _)| h == handle) {
+                Some(index) => {
+                    let (_, marker) = previous_markers.swap_remove(index);
+                    marker.update_isometry(c.isometry);
+                    marker.update_aabb(c.scaled_aabb(), color);
+                    marker
+                }
+                None => debug_lines.aabb_marker(c.isometry, c.scaled_aabb(), color),
+            };
+            self.selected.push((handle, marker));
+        }
+
+        // do not show hover on selected items
+        let hover = match selectables.get_hover_aabb() {
+            Some((hover_handle, _)) if selected.iter().any(|&(h, _)| h == hover_handle) => None,
+            hover => hover,
+        };
+
+        self.hover = match (hover, self.hover.take()) {
+            (Some((_, c)), None) => Some(debug_lines.aabb_marker(
+                c.isometry,
+                c.scaled_aabb(),
+                [1.0, 1.0, 1.0, 0.3].into(),
+            )),
             (Some((_, c)), Some(item)) => {
                 item.update_isometry(c.isometry);
+                item.update_aabb(c.scaled_aabb(), [1.0, 1.0, 1.0, 0.3].into());
                 Some(item)
             }
             _ => None,
         };
 
-        // do not show hover on selected items
-        let hover = match (selected, selectables.get_hover_aabb()) {
-            (Some((selected_handle, _)), Some((hover_handle, hover_aabb))) => {
-                if selected_handle == hover_handle {
-                    None
-                } else {
-                    Some((hover_handle, hover_aabb))
-                }
+        self.gizmo = match (selectables.get_gizmo(), self.gizmo.take()) {
+            (Some(gizmo), None) => Some(
+                gizmo_lines.lines_marker(gizmo.isometry, gizmo_segments(&gizmo).into_iter()),
+            ),
+            (Some(gizmo), Some(item)) => {
+                item.update_isometry(gizmo.isometry);
+                item.update_lines(gizmo_segments(&gizmo).into_iter());
+                Some(item)
             }
-            (_, hover) => hover,
+            _ => None,
         };
 
-        self.hover = match (hover, self.hover.take()) {
-            (Some((_, c)), None) => {
-                Some(debug_lines.aabb_marker(c.isometry, c.aabb, [1.0, 1.0, 1.0, 0.3].into()))
-            }
-            (Some((_, c)), Some(item)) => {
-                item.update_isometry(c.isometry);
+        self.bvh = match self.bvh.take() {
+            Some(item) if self.show_bvh => {
+                item.update_lines(bvh_segments(&selectables.bvh_nodes()).into_iter());
                 Some(item)
             }
+            None if self.show_bvh => Some(debug_lines.lines_marker(
+                na::Isometry3::identity(),
+                bvh_segments(&selectables.bvh_nodes()).into_iter(),
+            )),
             _ => None,
         };
     }
 }
+
+/// Box edges of the tree nodes, colored from red at the root to green at the deepest level.
+fn bvh_segments(
+    nodes: &[(AABB<f32>, usize)],
+) -> Vec<(na::Point3<f32>, na::Point3<f32>, na::Vector4<f32>)> {
+    let max_depth = nodes.iter().map(|&(_, depth)| depth).max().unwrap_or(0).max(1);
+    let mut segments = Vec::new();
+    for &(ref aabb, depth) in nodes {
+        let t = depth as f32 / max_depth as f32;
+        let color = na::Vector4::new(1.0 - t, t, 0.2, 0.5);
+        let (a, b) = (aabb.mins(), aabb.maxs());
+        let corner = |index: usize| {
+            na::Point3::new(
+                if index & 1 == 0 { a.x } else { b.x },
+                if index & 2 == 0 { a.y } else { b.y },
+                if index & 4 == 0 { a.z } else { b.z },
+            )
+        };
+        // corners differing in a single bit are joined by an edge
+        for index in 0..8 {
+            for &bit in &[1, 2, 4] {
+                if index & bit == 0 {
+                    segments.push((corner(index), corner(index | bit), color));
+                }
+            }
+        }
+    }
+    segments
+}
+
+/// Gizmo handles in its local space.
+fn gizmo_segments(
+    gizmo: &Gizmo,
+) -> Vec<(na::Point3<f32>, na::Point3<f32>, na::Vector4<f32>)> {
+    let mut segments = Vec::new();
+    let origin = na::Point3::origin();
+
+    for &axis in GizmoAxis::all().iter() {
+        let color = gizmo.axis_color(axis);
+        let direction = axis.local_direction();
+        let tip = origin + direction * gizmo.size;
+        let (u, v) = perpendicular(axis);
+
+        match gizmo.mode {
+            GizmoMode::Translate => {
+                segments.push((origin, tip, color));
+                // arrow head
+                let head = gizmo.size * 0.1;
+                let base = tip - direction * head * 2.0;
+                for side in [u, -u, v, -v].iter() {
+                    segments.push((tip, base + side * head, color));
+                }
+            }
+            GizmoMode::Rotate => {
+                let point_at = |index: usize| {
+                    let angle = index as f32 / RING_SEGMENTS as f32 * 2.0 * ::std::f32::consts::PI;
+                    origin + (u * angle.cos() + v * angle.sin()) * gizmo.size
+                };
+                for index in 0..RING_SEGMENTS {
+                    segments.push((point_at(index), point_at(index + 1), color));
+                }
+            }
+            GizmoMode::Scale => {
+                segments.push((origin, tip, color));
+                // square handle at the tip
+                let half = gizmo.size * 0.06;
+                let corners = [
+                    tip + (u + v) * half,
+                    tip + (u - v) * half,
+                    tip + (-u - v) * half,
+                    tip + (-u + v) * half,
+                ];
+                for index in 0..4 {
+                    segments.push((corners[index], corners[(index + 1) % 4], color));
+                }
+            }
+        }
+    }
+
+    segments
+}
+
+fn perpendicular(axis: GizmoAxis) -> (na::Vector3<f32>, na::Vector3<f32>) {
+    match axis {
+        GizmoAxis::X => (na::Vector3::y(), na::Vector3::z()),
+        GizmoAxis::Y => (na::Vector3::z(), na::Vector3::x()),
+        GizmoAxis::Z => (na::Vector3::x(), na::Vector3::y()),
+    }
+}
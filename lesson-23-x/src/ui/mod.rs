@@ -0,0 +1,409 @@
+use nalgebra as na;
+use crate::platform::{Event, MouseButton};
+use crate::render::WindowSize;
+use crate::render_gl::DebugText;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+mod render;
+
+pub use self::render::UiRenderer;
+
+/// Rectangle in pixels from the top left corner of the window.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Rect {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl Rect {
+    pub fn new(x: f32, y: f32, width: f32, height: f32) -> Rect {
+        Rect {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    pub fn contains(&self, point: na::Point2<f32>) -> bool {
+        point.x >= self.x
+            && point.y >= self.y
+            && point.x < self.x + self.width
+            && point.y < self.y + self.height
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Style {
+    pub panel_color: na::Vector4<f32>,
+    pub widget_color: na::Vector4<f32>,
+    pub hover_color: na::Vector4<f32>,
+    pub active_color: na::Vector4<f32>,
+    pub text_color: na::Vector4<f32>,
+    pub padding: f32,
+    /// Width of sliders and value fields.
+    pub field_width: f32,
+}
+
+impl Default for Style {
+    fn default() -> Style {
+        Style {
+            panel_color: na::Vector4::new(0.1, 0.1, 0.12, 0.8),
+            widget_color: na::Vector4::new(0.25, 0.25, 0.3, 1.0),
+            hover_color: na::Vector4::new(0.35, 0.35, 0.45, 1.0),
+            active_color: na::Vector4::new(0.45, 0.55, 0.8, 1.0),
+            text_color: na::Vector4::new(1.0, 1.0, 1.0, 1.0),
+            padding: 4.0,
+            field_width: 140.0,
+        }
+    }
+}
+
+/// Shapes produced by widgets during the frame, drawn by `UiRenderer`.
+#[derive(Default)]
+pub struct DrawList {
+    pub rects: Vec<(Rect, na::Vector4<f32>)>,
+    pub texts: Vec<(f32, f32, String, na::Vector4<f32>)>,
+}
+
+impl DrawList {
+    fn clear(&mut self) {
+        self.rects.clear();
+        self.texts.clear();
+    }
+}
+
+#[derive(Copy, Clone)]
+struct Mouse {
+    position: na::Point2<f32>,
+    down: bool,
+    /// Pressed since the last frame.
+    pressed: bool,
+    /// Horizontal movement since the last frame.
+    delta_x: f32,
+}
+
+/// Layout of the panel being built.
+struct PanelLayout {
+    id: u64,
+    rect: Rect,
+    cursor_y: f32,
+    background: usize,
+}
+
+/// Immediate-mode widgets drawn on top of the scene.
+///
+/// Widgets are declared every frame between `begin_frame` and `end_frame` and return
+/// whether they changed the value. Feed platform events to `handle_event` before other input
+/// handlers and skip those when it returns true.
+pub struct Ui {
+    pub style: Style,
+    mouse: Mouse,
+    /// Pixels per window coordinate.
+    scale: f32,
+    line_height: f32,
+    active: Option<u64>,
+    panel: Option<PanelLayout>,
+    /// Panel rectangles of the previous frame, for deciding whether the UI takes the mouse.
+    panel_rects: Vec<Rect>,
+    current_panel_rects: Vec<Rect>,
+    draw_list: DrawList,
+}
+
+impl Ui {
+    pub fn new() -> Ui {
+        Ui {
+            style: Style::default(),
+            mouse: Mouse {
+                position: na::Point2::origin(),
+                down: false,
+                pressed: false,
+                delta_x: 0.0,
+            },
+            scale: 1.0,
+            line_height: 16.0,
+            active: None,
+            panel: None,
+            panel_rects: Vec::new(),
+            current_panel_rects: Vec::new(),
+            draw_list: DrawList::default(),
+        }
+    }
+
+    /// True while the cursor is over a panel or a widget is being dragged.
+    pub fn wants_mouse(&self) -> bool {
+        self.active.is_some() || self.panel_rects.iter().any(|r| r.contains(self.mouse.position))
+    }
+
+    /// True while a widget is held with the mouse, i.e. a value is being dragged.
+    pub fn is_dragging(&self) -> bool {
+        self.active.is_some()
+    }
+
+    /// Track the mouse and return true if the event is taken by the UI.
+    pub fn handle_event(&mut self, event: &Event, window_size: &WindowSize) -> bool {
+        self.scale = window_size.highdpi_width as f32 / window_size.width.max(1) as f32;
+
+        match event {
+            Event::MouseMotion { x, y, xrel, .. } => {
+                self.mouse.position =
+                    na::Point2::new(*x as f32 * self.scale, *y as f32 * self.scale);
+                self.mouse.delta_x += *xrel as f32 * self.scale;
+                self.active.is_some()
+            }
+            Event::MouseButtonDown {
+                button: MouseButton::Left,
+                ..
+            } => {
+                if !self.wants_mouse() {
+                    return false;
+                }
+                self.mouse.down = true;
+                self.mouse.pressed = true;
+                true
+            }
+            Event::MouseButtonUp {
+                button: MouseButton::Left,
+                ..
+            } => {
+                let taken = self.mouse.down;
+                self.mouse.down = false;
+                self.active = None;
+                taken
+            }
+            Event::MouseWheel { .. } => self.wants_mouse(),
+            _ => false,
+        }
+    }
+
+    /// Start declaring widgets, rows are as high as lines of `text`.
+    pub fn begin_frame(&mut self, text: &DebugText) {
+        self.line_height = text.line_height();
+        self.draw_list.clear();
+        self.current_panel_rects.clear();
+    }
+
+    pub fn end_frame(&mut self) {
+        ::std::mem::swap(&mut self.panel_rects, &mut self.current_panel_rects);
+        self.mouse.pressed = false;
+        self.mouse.delta_x = 0.0;
+    }
+
+    pub fn draw_list(&self) -> &DrawList {
+        &self.draw_list
+    }
+
+    /// Panel with a title, widgets added in `build` are stacked below it.
+    pub fn panel<F: FnOnce(&mut Ui)>(&mut self, title: &str, x: f32, y: f32, build: F) {
+        let padding = self.style.padding;
+        let width = self.style.field_width * 2.0 + padding * 3.0;
+
+        self.draw_list
+            .rects
+            .push((Rect::new(x, y, width, 0.0), self.style.panel_color));
+        self.panel = Some(PanelLayout {
+            id: hash_id(0, title),
+            rect: Rect::new(x, y, width, 0.0),
+            cursor_y: y + padding,
+            background: self.draw_list.rects.len() - 1,
+        });
+
+        self.label(title);
+        build(self);
+
+        if let Some(panel) = self.panel.take() {
+            let rect = Rect::new(x, y, width, panel.cursor_y - y);
+            self.draw_list.rects[panel.background].0 = rect;
+            self.current_panel_rects.push(rect);
+        }
+    }
+
+    pub fn label(&mut self, text: &str) {
+        let (x, y) = self.next_row();
+        let color = self.style.text_color;
+        self.push_text(x, y, text, color);
+    }
+
+    pub fn button(&mut self, label: &str) -> bool {
+        let (x, y) = self.next_row();
+        let rect = Rect::new(x, y, self.style.field_width, self.line_height);
+        let id = self.id(label);
+        let (hovered, pressed) = self.interact(id, rect);
+
+        let color = self.widget_color(id, hovered);
+        self.draw_list.rects.push((rect, color));
+        let text_color = self.style.text_color;
+        self.push_text(x + self.style.padding, y, label, text_color);
+
+        pressed
+    }
+
+    pub fn checkbox(&mut self, label: &str, value: &mut bool) -> bool {
+        let (x, y) = self.next_row();
+        let size = self.line_height;
+        let rect = Rect::new(x, y, size, size);
+        let id = self.id(label);
+        let (hovered, pressed) = self.interact(id, rect);
+
+        if pressed {
+            *value = !*value;
+        }
+
+        let color = self.widget_color(id, hovered);
+        self.draw_list.rects.push((rect, color));
+        if *value {
+            let inset = size * 0.25;
+            self.draw_list.rects.push((
+                Rect::new(x + inset, y + inset, size - inset * 2.0, size - inset * 2.0),
+                self.style.text_color,
+            ));
+        }
+        let text_color = self.style.text_color;
+        self.push_text(x + size + self.style.padding, y, label, text_color);
+
+        pressed
+    }
+
+    /// Horizontal slider, clicking or dragging sets the value under the cursor.
+    pub fn slider(&mut self, label: &str, value: &mut f32, min: f32, max: f32) -> bool {
+        let (x, y) = self.next_row();
+        let rect = Rect::new(x, y, self.style.field_width, self.line_height);
+        let id = self.id(label);
+        let (hovered, _) = self.interact(id, rect);
+
+        let previous = *value;
+        if self.active == Some(id) {
+            let t = ((self.mouse.position.x - rect.x) / rect.width).max(0.0).min(1.0);
+            *value = min + (max - min) * t;
+        }
+
+        let color = self.widget_color(id, hovered);
+        self.draw_list.rects.push((rect, self.style.widget_color));
+        let t = if max > min {
+            ((*value - min) / (max - min)).max(0.0).min(1.0)
+        } else {
+            0.0
+        };
+        self.draw_list
+            .rects
+            .push((Rect::new(x, y, rect.width * t, rect.height), color));
+        self.push_value_and_label(rect, *value, label);
+
+        *value != previous
+    }
+
+    /// Value changed by dragging horizontally, `speed` per pixel.
+    pub fn drag_value(&mut self, label: &str, value: &mut f32, speed: f32) -> bool {
+        let (x, y) = self.next_row();
+        let rect = Rect::new(x, y, self.style.field_width, self.line_height);
+        let changed = self.drag_field(label, rect, value, speed);
+        self.push_value_and_label(rect, *value, label);
+        changed
+    }
+
+    /// Three drag fields in one row, i.e. for a position or scale.
+    pub fn drag_vector3(&mut self, label: &str, value: &mut na::Vector3<f32>, speed: f32) -> bool {
+        self.label(label);
+        let (x, y) = self.next_row();
+        let padding = self.style.padding;
+        let field_width = (self.style.field_width * 2.0 - padding * 2.0) / 3.0;
+
+        let mut changed = false;
+        for index in 0..3 {
+            let rect = Rect::new(
+                x + (field_width + padding) * index as f32,
+                y,
+                field_width,
+                self.line_height,
+            );
+            let field_label = format!("{}#{}", label, index);
+            changed |= self.drag_field(&field_label, rect, &mut value[index], speed);
+            let text_color = self.style.text_color;
+            self.push_text(rect.x + padding, y, &format!("{:.2}", value[index]), text_color);
+        }
+
+        changed
+    }
+
+    fn drag_field(&mut self, label: &str, rect: Rect, value: &mut f32, speed: f32) -> bool {
+        let id = self.id(label);
+        let (hovered, _) = self.interact(id, rect);
+
+        let previous = *value;
+        if self.active == Some(id) {
+            *value += self.mouse.delta_x * speed;
+        }
+
+        let color = self.widget_color(id, hovered);
+        self.draw_list.rects.push((rect, color));
+
+        *value != previous
+    }
+
+    fn push_value_and_label(&mut self, rect: Rect, value: f32, label: &str) {
+        let padding = self.style.padding;
+        let text_color = self.style.text_color;
+        self.push_text(rect.x + padding, rect.y, &format!("{:.2}", value), text_color);
+        self.push_text(rect.x + rect.width + padding, rect.y, label, text_color);
+    }
+
+    /// Hover state, and whether the widget was pressed this frame, which makes it active.
+    fn interact(&mut self, id: u64, rect: Rect) -> (bool, bool) {
+        let hovered = rect.contains(self.mouse.position)
+            && (self.active.is_none() || self.active == Some(id));
+
+        let pressed = hovered && self.mouse.pressed;
+        if pressed {
+            self.active = Some(id);
+        }
+        (hovered, pressed)
+    }
+
+    fn widget_color(&self, id: u64, hovered: bool) -> na::Vector4<f32> {
+        if self.active == Some(id) {
+            self.style.active_color
+        } else if hovered {
+            self.style.hover_color
+        } else {
+            self.style.widget_color
+        }
+    }
+
+    /// Top left corner of the next row in the current panel.
+    fn next_row(&mut self) -> (f32, f32) {
+        let padding = self.style.padding;
+        let line_height = self.line_height;
+        match self.panel {
+            Some(ref mut panel) => {
+                let y = panel.cursor_y;
+                panel.cursor_y += line_height + padding;
+                (panel.rect.x + padding, y)
+            }
+            None => (padding, padding),
+        }
+    }
+
+    fn id(&self, label: &str) -> u64 {
+        hash_id(self.panel.as_ref().map(|p| p.id).unwrap_or(0), label)
+    }
+
+    fn push_text(&mut self, x: f32, y: f32, text: &str, color: na::Vector4<f32>) {
+        self.draw_list.texts.push((x, y, text.into(), color));
+    }
+}
+
+impl Default for Ui {
+    fn default() -> Ui {
+        Ui::new()
+    }
+}
+
+fn hash_id(parent: u64, label: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    parent.hash(&mut hasher);
+    label.hash(&mut hasher);
+    hasher.finish()
+}
@@ -0,0 +1,115 @@
+use failure;
+use gl;
+use nalgebra as na;
+use crate::render_gl::buffer::{Buffer, VertexArray};
+use crate::render_gl::data;
+use crate::render_gl::{ColorBuffer, DebugText, FrameStats, Program, StateCache, Uniform};
+use crate::resources::Resources;
+use crate::ui::Ui;
+
+#[derive(VertexAttribPointers, Copy, Clone, Debug)]
+#[repr(C, packed)]
+struct RectVertex {
+    #[location = "0"]
+    pos: data::f32_f32,
+    #[location = "1"]
+    color: data::f32_f32_f32_f32,
+}
+
+/// Draws `Ui` rectangles, text is queued into `DebugText`.
+pub struct UiRenderer {
+    program: Program,
+    view_projection_uniform: Uniform<na::Matrix4<f32>>,
+    vertices: Vec<RectVertex>,
+    vbo: Buffer,
+    vao: VertexArray,
+}
+
+impl UiRenderer {
+    pub fn new(gl: &gl::Gl, res: &Resources) -> Result<UiRenderer, failure::Error> {
+        let program = Program::from_res(gl, res, "shaders/ui_rect")?;
+
+        let vbo = Buffer::new_array(gl);
+        let vao = VertexArray::new(gl);
+        vao.bind();
+        vbo.bind();
+        RectVertex::vertex_attrib_pointers(gl);
+        vbo.unbind();
+        vao.unbind();
+
+        Ok(UiRenderer {
+            view_projection_uniform: program.uniform("ViewProjection"),
+            program,
+            vertices: Vec::new(),
+            vbo,
+            vao,
+        })
+    }
+
+    /// Draw the frame's widgets. Call before `DebugText::render`, so that labels end up
+    /// on top of them.
+    ///
+    /// `ui_matrix` maps pixels to clip space with origin at the bottom left.
+    pub fn render(
+        &mut self,
+        gl: &gl::Gl,
+        target: &ColorBuffer,
+        ui: &Ui,
+        text: &DebugText,
+        ui_matrix: &na::Matrix4<f32>,
+        view_height_pixels: i32,
+    ) {
+        let draw_list = ui.draw_list();
+        let height = view_height_pixels as f32;
+
+        self.vertices.clear();
+        for &(rect, color) in &draw_list.rects {
+            let color: data::f32_f32_f32_f32 = (color.x, color.y, color.z, color.w).into();
+            let (left, right) = (rect.x, rect.x + rect.width);
+            let (top, bottom) = (height - rect.y, height - rect.y - rect.height);
+
+            let vertex = |x, y| RectVertex {
+                pos: (x, y).into(),
+                color,
+            };
+
+            self.vertices.extend_from_slice(&[
+                vertex(left, top),
+                vertex(left, bottom),
+                vertex(right, bottom),
+                vertex(left, top),
+                vertex(right, bottom),
+                vertex(right, top),
+            ]);
+        }
+
+        for &(x, y, ref label, color) in &draw_list.texts {
+            text.draw_2d_colored(x, y, label, color);
+        }
+
+        if self.vertices.is_empty() {
+            return;
+        }
+
+        self.vbo.bind();
+        self.vbo.stream_draw_data(&self.vertices);
+        self.vbo.unbind();
+
+        self.program.set_used();
+        self.view_projection_uniform.set(&self.program, ui_matrix);
+
+        self.vao.bind();
+        unsafe {
+            target.set_default_blend_func(gl);
+            target.enable_blend(gl);
+            StateCache::set_depth_test(gl, false);
+
+            gl.DrawArrays(gl::TRIANGLES, 0, self.vertices.len() as i32);
+            FrameStats::record_draw(gl::TRIANGLES, self.vertices.len() as i32, 1);
+
+            StateCache::set_depth_test(gl, true);
+            target.disable_blend(gl);
+        }
+        self.vao.unbind();
+    }
+}
@@ -0,0 +1,143 @@
+use crate::selection::{Action, Selectables, TransformChange};
+
+/// Commands kept by `UndoStack` when no limit is given.
+pub const DEFAULT_UNDO_LIMIT: usize = 100;
+
+/// What commands may change, grows with new kinds of commands.
+pub struct UndoContext<'a> {
+    pub selectables: &'a Selectables,
+}
+
+/// Reversible edit recorded in `UndoStack`, i.e. a move, spawn, delete or material change.
+pub trait Command {
+    /// Short description, i.e. for debug text.
+    fn name(&self) -> &str;
+    fn undo(&mut self, ctx: &UndoContext);
+    fn redo(&mut self, ctx: &UndoContext);
+}
+
+/// Objects moved, turned or scaled by one drag.
+///
+/// Transforms are restored through selection actions, so that the owners of the
+/// selectables apply them like any other drag.
+pub struct TransformCommand {
+    changes: Vec<TransformChange>,
+}
+
+impl TransformCommand {
+    /// `None` if nothing has changed.
+    pub fn new(changes: Vec<TransformChange>) -> Option<TransformCommand> {
+        if changes.is_empty() {
+            None
+        } else {
+            Some(TransformCommand { changes })
+        }
+    }
+}
+
+impl Command for TransformCommand {
+    fn name(&self) -> &str {
+        "transform"
+    }
+
+    fn undo(&mut self, ctx: &UndoContext) {
+        for change in &self.changes {
+            ctx.selectables.queue_action(
+                change.handle,
+                Action::Scale {
+                    new_scale: change.old_scale,
+                },
+            );
+            ctx.selectables.queue_action(
+                change.handle,
+                Action::Drag {
+                    new_isometry: change.old_isometry,
+                },
+            );
+        }
+    }
+
+    fn redo(&mut self, ctx: &UndoContext) {
+        for change in &self.changes {
+            ctx.selectables.queue_action(
+                change.handle,
+                Action::Scale {
+                    new_scale: change.new_scale,
+                },
+            );
+            ctx.selectables.queue_action(
+                change.handle,
+                Action::Drag {
+                    new_isometry: change.new_isometry,
+                },
+            );
+        }
+    }
+}
+
+/// Applied commands that can be undone, and undone ones that can be redone.
+///
+/// Pushing a new command drops the redo history. The oldest commands are dropped past
+/// the limit.
+pub struct UndoStack {
+    done: Vec<Box<dyn Command>>,
+    undone: Vec<Box<dyn Command>>,
+    limit: usize,
+}
+
+impl Default for UndoStack {
+    fn default() -> UndoStack {
+        UndoStack::new(DEFAULT_UNDO_LIMIT)
+    }
+}
+
+impl UndoStack {
+    pub fn new(limit: usize) -> UndoStack {
+        UndoStack {
+            done: Vec::new(),
+            undone: Vec::new(),
+            limit: limit.max(1),
+        }
+    }
+
+    /// Record a command that has already been applied.
+    pub fn push(&mut self, command: Box<dyn Command>) {
+        self.undone.clear();
+        self.done.push(command);
+        if self.done.len() > self.limit {
+            let excess = self.done.len() - self.limit;
+            self.done.drain(..excess);
+        }
+    }
+
+    /// Undo the last command, returns its name.
+    pub fn undo(&mut self, ctx: &UndoContext) -> Option<String> {
+        let mut command = self.done.pop()?;
+        command.undo(ctx);
+        let name = command.name().to_string();
+        self.undone.push(command);
+        Some(name)
+    }
+
+    /// Apply the last undone command again, returns its name.
+    pub fn redo(&mut self, ctx: &UndoContext) -> Option<String> {
+        let mut command = self.undone.pop()?;
+        command.redo(ctx);
+        let name = command.name().to_string();
+        self.done.push(command);
+        Some(name)
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.done.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.undone.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.done.clear();
+        self.undone.clear();
+    }
+}
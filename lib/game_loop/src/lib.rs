@@ -0,0 +1,179 @@
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Frames that took longer than this are treated as this long, so that a stall does not
+/// queue up more updates than can be run before the next frame.
+fn max_frame_time() -> Duration {
+    Duration::from_millis(250)
+}
+
+/// Remaining wait for the frame cap is spun instead of slept, sleep is not precise enough.
+fn spin_time() -> Duration {
+    Duration::from_millis(2)
+}
+
+/// How buffer swaps wait for the vertical retrace.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum VSync {
+    Off,
+    On,
+    /// Wait for the retrace, unless the frame is late, then swap immediately and tear.
+    Adaptive,
+}
+
+impl VSync {
+    pub fn next(self) -> VSync {
+        match self {
+            VSync::Off => VSync::On,
+            VSync::On => VSync::Adaptive,
+            VSync::Adaptive => VSync::Off,
+        }
+    }
+}
+
+/// Frame timing with fixed-timestep updates.
+///
+/// Each frame, `begin_frame` returns how many updates of `timestep` seconds to run, so that
+/// simulation does not depend on the frame rate. Time left over is exposed as `alpha`,
+/// for rendering between the previous and the current update.
+///
+/// ```ignore
+/// let mut game_loop = GameLoop::new(60).with_frame_cap(144);
+/// loop {
+///     for _ in 0..game_loop.begin_frame() {
+///         world.update(game_loop.timestep());
+///     }
+///     world.render(game_loop.alpha());
+///     game_loop.end_frame();
+///     window.gl_swap_window();
+/// }
+/// ```
+pub struct GameLoop {
+    timestep: Duration,
+    frame_cap: Option<Duration>,
+    accumulator: Duration,
+    frame_start: Instant,
+    frame_time: Duration,
+}
+
+impl GameLoop {
+    pub fn new(updates_per_second: u32) -> GameLoop {
+        GameLoop {
+            timestep: Duration::from_secs(1) / updates_per_second.max(1),
+            frame_cap: None,
+            accumulator: Duration::from_secs(0),
+            frame_start: Instant::now(),
+            frame_time: Duration::from_secs(0),
+        }
+    }
+
+    /// Wait in `end_frame` so that frames are not shorter than `1 / fps`.
+    pub fn with_frame_cap(mut self, fps: u32) -> Self {
+        self.set_frame_cap(Some(fps));
+        self
+    }
+
+    pub fn set_frame_cap(&mut self, fps: Option<u32>) {
+        self.frame_cap = fps.map(|fps| Duration::from_secs(1) / fps.max(1));
+    }
+
+    pub fn frame_cap(&self) -> Option<u32> {
+        self.frame_cap
+            .map(|cap| (1.0 / secs(cap)).round() as u32)
+    }
+
+    /// Measure time since the previous frame and return the number of updates to run.
+    pub fn begin_frame(&mut self) -> u32 {
+        let now = Instant::now();
+        let frame_time = now - self.frame_start;
+        self.frame_start = now;
+        self.advance(frame_time)
+    }
+
+    /// Add elapsed time and take whole timesteps out of it. `begin_frame` calls this with
+    /// measured time, tools and tests can step with their own.
+    pub fn advance(&mut self, frame_time: Duration) -> u32 {
+        self.frame_time = frame_time.min(max_frame_time());
+        self.accumulator += self.frame_time;
+
+        let mut updates = 0;
+        while self.accumulator >= self.timestep {
+            self.accumulator -= self.timestep;
+            updates += 1;
+        }
+        updates
+    }
+
+    /// Wait for the frame cap, call before swapping buffers.
+    pub fn end_frame(&self) {
+        let cap = match self.frame_cap {
+            Some(cap) => cap,
+            None => return,
+        };
+
+        let elapsed = self.frame_start.elapsed();
+        if elapsed + spin_time() < cap {
+            thread::sleep(cap - elapsed - spin_time());
+        }
+        while self.frame_start.elapsed() < cap {
+            thread::yield_now();
+        }
+    }
+
+    /// Seconds simulated by each update.
+    pub fn timestep(&self) -> f32 {
+        secs(self.timestep)
+    }
+
+    /// Progress towards the next update, from 0 to 1. Blend the previous and the current
+    /// state with it, so that motion is smooth when frames and updates do not line up.
+    pub fn alpha(&self) -> f32 {
+        secs(self.accumulator) / secs(self.timestep)
+    }
+
+    /// Seconds between the last two frames, i.e. for frame rate display.
+    pub fn frame_time(&self) -> f32 {
+        secs(self.frame_time)
+    }
+
+    /// Time between the last two frames as added by `advance`, i.e. to record and replay
+    /// the exact frame times.
+    pub fn frame_duration(&self) -> Duration {
+        self.frame_time
+    }
+}
+
+fn secs(duration: Duration) -> f32 {
+    duration.as_secs() as f32 + duration.subsec_nanos() as f32 * 1e-9
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn whole_timesteps_are_taken_from_accumulated_time() {
+        let mut game_loop = GameLoop::new(100);
+
+        assert_eq!(game_loop.advance(Duration::from_millis(25)), 2);
+        assert!((game_loop.alpha() - 0.5).abs() < 1e-4);
+
+        assert_eq!(game_loop.advance(Duration::from_millis(5)), 1);
+        assert!(game_loop.alpha().abs() < 1e-4);
+    }
+
+    #[test]
+    fn long_frames_are_clamped() {
+        let mut game_loop = GameLoop::new(100);
+
+        assert_eq!(game_loop.advance(Duration::from_secs(10)), 25);
+    }
+
+    #[test]
+    fn frame_cap_is_reported_in_frames_per_second() {
+        let game_loop = GameLoop::new(60).with_frame_cap(144);
+
+        assert_eq!(game_loop.frame_cap(), Some(144));
+    }
+}